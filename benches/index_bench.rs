@@ -0,0 +1,92 @@
+use std::sync::Arc;
+
+use bencher::{benchmark_group, benchmark_main, Bencher};
+use criterion_bencher_compat as bencher;
+use jujutsu_lib::commit::Commit;
+use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::repo::{MutableRepo, ReadonlyRepo};
+use jujutsu_lib::revset;
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestRepo;
+
+/// Creates `count` commits in a single operation. They form a chain with a
+/// side branch that's merged back in every 10 commits.
+fn create_commits(test_repo: &TestRepo, count: usize) -> Arc<ReadonlyRepo> {
+    let settings = testutils::user_settings();
+    let repo = &test_repo.repo;
+    let tree_id = repo.store().empty_tree_id().clone();
+    let mut tx = repo.start_transaction("create commits");
+    let new_commit = |mut_repo: &mut MutableRepo, parents: &[&Commit], i: usize| {
+        CommitBuilder::for_new_commit(
+            &settings,
+            parents.iter().map(|commit| commit.id().clone()).collect(),
+            tree_id.clone(),
+        )
+        .set_description(format!("commit {i}"))
+        .write_to_repo(mut_repo)
+    };
+    let root_commit = repo.store().root_commit();
+    let mut main = new_commit(tx.mut_repo(), &[&root_commit], 0);
+    let mut side = main.clone();
+    for i in 1..count {
+        if i % 10 == 0 {
+            main = new_commit(tx.mut_repo(), &[&main, &side], i);
+            side = main.clone();
+        } else if i % 2 == 0 {
+            side = new_commit(tx.mut_repo(), &[&side], i);
+        } else {
+            main = new_commit(tx.mut_repo(), &[&main], i);
+        }
+    }
+    tx.commit()
+}
+
+fn bench_index_10k_commits(b: &mut Bencher) {
+    let test_repo = TestRepo::init(false);
+    let repo = create_commits(&test_repo, 10_000);
+    b.iter(|| {
+        repo.index_store()
+            .reindex_at_op(repo.operation(), repo.store())
+            .unwrap()
+            .num_commits()
+    });
+}
+
+fn bench_revset(b: &mut Bencher, revset_str: &str) {
+    let test_repo = TestRepo::init(false);
+    let repo = create_commits(&test_repo, 10_000);
+    let expression = revset::parse(revset_str).unwrap();
+    b.iter(|| {
+        expression
+            .evaluate(repo.as_repo_ref(), None)
+            .unwrap()
+            .iter()
+            .count()
+    });
+}
+
+fn bench_revset_ancestors_10k(b: &mut Bencher) {
+    bench_revset(b, ":heads()");
+}
+
+fn bench_revset_merges_10k(b: &mut Bencher) {
+    bench_revset(b, "merges()");
+}
+
+fn bench_revset_range_10k(b: &mut Bencher) {
+    bench_revset(b, "roots(merges())..heads()");
+}
+
+fn bench_revset_description_10k(b: &mut Bencher) {
+    bench_revset(b, "description(\"commit 99\")");
+}
+
+benchmark_group!(
+    benches,
+    bench_index_10k_commits,
+    bench_revset_ancestors_10k,
+    bench_revset_merges_10k,
+    bench_revset_range_10k,
+    bench_revset_description_10k,
+);
+benchmark_main!(benches);
@@ -0,0 +1,62 @@
+use bencher::{benchmark_group, benchmark_main, Bencher};
+use criterion_bencher_compat as bencher;
+use jujutsu_lib::matchers::{FilesMatcher, Matcher, PrefixMatcher};
+use jujutsu_lib::repo_path::RepoPath;
+
+fn repo_paths(count: usize, depth: usize) -> Vec<RepoPath> {
+    let mut paths = vec![];
+    for i in 0..count {
+        let mut components = vec![];
+        for level in 0..depth {
+            components.push(format!("dir{}_{}", level, i % (level + 2)));
+        }
+        components.push(format!("file{}", i));
+        paths.push(RepoPath::from_internal_string(&components.join("/")));
+    }
+    paths
+}
+
+fn bench_prefixmatcher_matches_1k(b: &mut Bencher) {
+    let prefixes = repo_paths(100, 3)
+        .iter()
+        .map(|path| path.parent().unwrap())
+        .collect::<Vec<_>>();
+    let matcher = PrefixMatcher::new(&prefixes);
+    let paths = repo_paths(1000, 6);
+    b.iter(|| paths.iter().filter(|path| matcher.matches(path)).count());
+}
+
+fn bench_prefixmatcher_visit_1k(b: &mut Bencher) {
+    let prefixes = repo_paths(100, 3);
+    let matcher = PrefixMatcher::new(&prefixes);
+    let dirs = repo_paths(1000, 4)
+        .iter()
+        .map(|path| path.parent().unwrap())
+        .collect::<Vec<_>>();
+    b.iter(|| {
+        dirs.iter()
+            .filter(|dir| !matcher.visit(dir).is_nothing())
+            .count()
+    });
+}
+
+fn bench_filesmatcher_visit_1k(b: &mut Bencher) {
+    let matcher = FilesMatcher::new(repo_paths(1000, 4).into_iter().collect());
+    let dirs = repo_paths(1000, 4)
+        .iter()
+        .map(|path| path.parent().unwrap())
+        .collect::<Vec<_>>();
+    b.iter(|| {
+        dirs.iter()
+            .filter(|dir| !matcher.visit(dir).is_nothing())
+            .count()
+    });
+}
+
+benchmark_group!(
+    benches,
+    bench_prefixmatcher_matches_1k,
+    bench_prefixmatcher_visit_1k,
+    bench_filesmatcher_visit_1k,
+);
+benchmark_main!(benches);
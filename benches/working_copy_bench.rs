@@ -0,0 +1,138 @@
+use std::fs;
+use std::path::Path;
+
+use bencher::{benchmark_group, benchmark_main, Bencher};
+use criterion_bencher_compat as bencher;
+use jujutsu_lib::gitignore::GitIgnoreFile;
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestWorkspace;
+use jujutsu_lib::tree::Tree;
+use jujutsu_lib::working_copy::TreeState;
+
+fn file_path(i: usize) -> String {
+    format!("dir{}/sub{}/file{i}", i % 100, i % 10)
+}
+
+/// Writes `count` small files, spread over 100 directories with 10
+/// subdirectories each.
+fn write_files(root: &Path, count: usize) {
+    for i in 0..count {
+        let path = root.join(file_path(i));
+        if i < 1000 {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+        }
+        fs::write(path, format!("contents {i}\n")).unwrap();
+    }
+}
+
+/// Creates a tree with the same files as `write_files()`, except that every
+/// `modulus`th file has different contents.
+fn create_tree(test_workspace: &TestWorkspace, count: usize, modulus: usize) -> Tree {
+    let paths = (0..count)
+        .map(|i| RepoPath::from_internal_string(&file_path(i)))
+        .collect::<Vec<_>>();
+    let contents = (0..count)
+        .map(|i| {
+            if i % modulus == 0 {
+                format!("modified {i}\n")
+            } else {
+                format!("contents {i}\n")
+            }
+        })
+        .collect::<Vec<_>>();
+    let path_contents = paths
+        .iter()
+        .zip(&contents)
+        .map(|(path, contents)| (path, contents.as_str()))
+        .collect::<Vec<_>>();
+    testutils::create_tree(&test_workspace.repo, &path_contents)
+}
+
+fn new_tree_state(test_workspace: &TestWorkspace, state_dir: &Path) -> TreeState {
+    TreeState::init(
+        test_workspace.repo.store().clone(),
+        test_workspace.workspace.workspace_root().clone(),
+        state_dir.to_path_buf(),
+    )
+}
+
+fn bench_snapshot_new_files(b: &mut Bencher, count: usize) {
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let test_workspace = TestWorkspace::init(&settings, false);
+    write_files(test_workspace.workspace.workspace_root(), count);
+    b.iter(|| {
+        // Start from an empty state so every file gets hashed and written
+        let state_dir = testutils::new_temp_dir();
+        let mut tree_state = new_tree_state(&test_workspace, state_dir.path());
+        tree_state.snapshot(GitIgnoreFile::empty()).unwrap();
+        tree_state.current_tree_id().clone()
+    });
+}
+
+fn bench_snapshot_1k_new_files(b: &mut Bencher) {
+    bench_snapshot_new_files(b, 1_000);
+}
+
+fn bench_snapshot_100k_new_files(b: &mut Bencher) {
+    bench_snapshot_new_files(b, 100_000);
+}
+
+fn bench_snapshot_100k_unchanged_files(b: &mut Bencher) {
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let test_workspace = TestWorkspace::init(&settings, false);
+    write_files(test_workspace.workspace.workspace_root(), 100_000);
+    let state_dir = testutils::new_temp_dir();
+    let mut tree_state = new_tree_state(&test_workspace, state_dir.path());
+    tree_state.snapshot(GitIgnoreFile::empty()).unwrap();
+    tree_state.save();
+    b.iter(|| tree_state.snapshot(GitIgnoreFile::empty()).unwrap());
+}
+
+fn bench_checkout_10k_files(b: &mut Bencher) {
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let test_workspace = TestWorkspace::init(&settings, false);
+    let tree = create_tree(&test_workspace, 10_000, usize::MAX);
+    let empty_tree = test_workspace
+        .repo
+        .store()
+        .get_tree(
+            &RepoPath::root(),
+            test_workspace.repo.store().empty_tree_id(),
+        )
+        .unwrap();
+    let state_dir = testutils::new_temp_dir();
+    let mut tree_state = new_tree_state(&test_workspace, state_dir.path());
+    b.iter(|| {
+        tree_state.check_out(&tree).unwrap();
+        tree_state.check_out(&empty_tree).unwrap()
+    });
+}
+
+fn bench_checkout_10k_files_1_percent_modified(b: &mut Bencher) {
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let test_workspace = TestWorkspace::init(&settings, false);
+    let tree1 = create_tree(&test_workspace, 10_000, usize::MAX);
+    let tree2 = create_tree(&test_workspace, 10_000, 100);
+    let state_dir = testutils::new_temp_dir();
+    let mut tree_state = new_tree_state(&test_workspace, state_dir.path());
+    tree_state.check_out(&tree1).unwrap();
+    b.iter(|| {
+        tree_state.check_out(&tree2).unwrap();
+        tree_state.check_out(&tree1).unwrap()
+    });
+}
+
+benchmark_group!(
+    benches,
+    bench_snapshot_1k_new_files,
+    bench_snapshot_100k_new_files,
+    bench_snapshot_100k_unchanged_files,
+    bench_checkout_10k_files,
+    bench_checkout_10k_files_1_percent_modified,
+);
+benchmark_main!(benches);
@@ -0,0 +1,68 @@
+/*
+ * Copyright 2022 Google LLC
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ * https://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+/*
+ * C bindings for read-only access to a Jujutsu repo.
+ *
+ * Strings passed in must be NUL-terminated UTF-8. Returned strings and buffers
+ * are owned by the caller and must be freed with jj_string_free() and
+ * jj_bytes_free(). Functions that can fail return NULL (or -1) on failure and,
+ * if `error` is not NULL, store a message there that must be freed with
+ * jj_string_free(). Internal errors (Rust panics) are reported the same way;
+ * the functions without an `error` argument return NULL (or INT64_MIN) then.
+ */
+
+#ifndef JUJUTSU_H
+#define JUJUTSU_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct JjRepo JjRepo;
+typedef struct JjCommit JjCommit;
+
+JjRepo *jj_repo_open(const char *path, char **error);
+int jj_repo_reload(JjRepo *repo, char **error);
+void jj_repo_free(JjRepo *repo);
+
+/* Returns full hex commit IDs, one per line. */
+char *jj_revset_evaluate(const JjRepo *repo, const char *revset, char **error);
+
+JjCommit *jj_commit_get(const JjRepo *repo, const char *revision, char **error);
+void jj_commit_free(JjCommit *commit);
+char *jj_commit_id(const JjCommit *commit);
+char *jj_commit_change_id(const JjCommit *commit);
+char *jj_commit_description(const JjCommit *commit);
+char *jj_commit_author(const JjCommit *commit);
+int64_t jj_commit_author_timestamp(const JjCommit *commit);
+/* Returns full hex commit IDs, one per line. */
+char *jj_commit_parents(const JjCommit *commit);
+
+int jj_file_read(const JjCommit *commit, const char *path, uint8_t **data,
+                 size_t *len, char **error);
+
+void jj_string_free(char *string);
+void jj_bytes_free(uint8_t *data, size_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* JUJUTSU_H */
@@ -0,0 +1,456 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! C bindings for read-only access to a Jujutsu repo. See
+//! `include/jujutsu.h` for the C declarations.
+//!
+//! Strings passed in must be NUL-terminated UTF-8. Strings and byte buffers
+//! returned are owned by the caller and must be freed with `jj_string_free()`
+//! and `jj_bytes_free()` respectively. Functions that can fail take a
+//! `char **error` argument; on failure, they return NULL (or -1) and, if
+//! `error` is not NULL, store an error message there that the caller must free
+//! with `jj_string_free()`.
+//!
+//! Panics must not unwind into C, so each entry point catches them. Functions
+//! with an `error` argument report them as errors; the others return NULL (or
+//! `INT64_MIN`).
+
+#![deny(unsafe_op_in_unsafe_fn)]
+
+use std::ffi::{CStr, CString};
+use std::io::Read;
+use std::os::raw::{c_char, c_int};
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+use std::sync::Arc;
+
+use jujutsu_lib::backend::TreeValue;
+use jujutsu_lib::commit::Commit;
+use jujutsu_lib::repo::{BackendFactories, ReadonlyRepo};
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::revset;
+use jujutsu_lib::settings::UserSettings;
+use jujutsu_lib::workspace::{Workspace, WorkspaceLoadError};
+
+/// An open workspace and the repo loaded at its latest operation.
+pub struct JjRepo {
+    workspace: Workspace,
+    repo: Arc<ReadonlyRepo>,
+}
+
+/// A commit in a `JjRepo`.
+pub struct JjCommit {
+    commit: Commit,
+}
+
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, String> {
+    if ptr.is_null() {
+        return Err(format!("{name} must not be NULL"));
+    }
+    // SAFETY: The caller guarantees that non-NULL pointers point to
+    // NUL-terminated strings.
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map_err(|_| format!("{name} is not valid UTF-8"))
+}
+
+fn to_c_string(value: impl Into<Vec<u8>>) -> *mut c_char {
+    let mut bytes = value.into();
+    // C strings can't contain NULs, so truncate at the first one.
+    if let Some(nul_pos) = bytes.iter().position(|b| *b == 0) {
+        bytes.truncate(nul_pos);
+    }
+    CString::new(bytes).unwrap().into_raw()
+}
+
+unsafe fn set_error(error: *mut *mut c_char, message: String) {
+    if !error.is_null() {
+        // SAFETY: The caller guarantees that `error` is valid for writes.
+        unsafe { *error = to_c_string(message) };
+    }
+}
+
+/// Runs `f`, turning a panic into an error so it doesn't unwind across the C
+/// ABI.
+fn catch_panic<T>(f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    std::panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err(format!("Internal error: {message}"))
+    })
+}
+
+fn resolve_revset(jj_repo: &JjRepo, revset_str: &str) -> Result<Vec<Commit>, String> {
+    let expression = revset::parse(revset_str).map_err(|err| err.to_string())?;
+    let revset = expression
+        .evaluate(
+            jj_repo.repo.as_repo_ref(),
+            Some(&jj_repo.workspace.workspace_id()),
+        )
+        .map_err(|err| err.to_string())?;
+    let commits: Result<Vec<_>, _> = revset.iter().commits(jj_repo.repo.store()).collect();
+    commits.map_err(|err| err.to_string())
+}
+
+fn open_repo(path: &str) -> Result<JjRepo, String> {
+    let settings = UserSettings::default();
+    let workspace = Workspace::load(&settings, Path::new(path), &BackendFactories::default())
+        .map_err(|err| match err {
+            WorkspaceLoadError::NoWorkspaceHere(path) => {
+                format!("There is no jj repo in \"{}\"", path.display())
+            }
+            WorkspaceLoadError::RepoDoesNotExist(path) => {
+                format!("The repository directory at {} is missing", path.display())
+            }
+            err @ WorkspaceLoadError::MigrationFailed(_) => err.to_string(),
+        })?;
+    let repo = workspace
+        .repo_loader()
+        .load_at_head_readonly()
+        .map_err(|err| err.to_string())?;
+    Ok(JjRepo { workspace, repo })
+}
+
+/// Opens the workspace at `path` (or in one of its ancestors). The repo is
+/// loaded at its latest operation without taking any locks.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string and `error` must be NULL or valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn jj_repo_open(path: *const c_char, error: *mut *mut c_char) -> *mut JjRepo {
+    let result = catch_panic(|| unsafe { str_arg(path, "path") }.and_then(open_repo));
+    match result {
+        Ok(jj_repo) => Box::into_raw(Box::new(jj_repo)),
+        Err(message) => {
+            unsafe { set_error(error, message) };
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Reloads the repo at its latest operation.
+///
+/// # Safety
+///
+/// `repo` must have been returned by `jj_repo_open()` and not freed.
+#[no_mangle]
+pub unsafe extern "C" fn jj_repo_reload(repo: *mut JjRepo, error: *mut *mut c_char) -> c_int {
+    // SAFETY: The caller guarantees that `repo` is valid.
+    let jj_repo = unsafe { &mut *repo };
+    let result = catch_panic(|| {
+        jj_repo
+            .workspace
+            .repo_loader()
+            .load_at_head_readonly()
+            .map_err(|err| err.to_string())
+    });
+    match result {
+        Ok(repo) => {
+            jj_repo.repo = repo;
+            0
+        }
+        Err(message) => {
+            unsafe { set_error(error, message) };
+            -1
+        }
+    }
+}
+
+/// Frees a repo returned by `jj_repo_open()`. Does nothing if `repo` is NULL.
+///
+/// # Safety
+///
+/// `repo` must be NULL or have been returned by `jj_repo_open()` and not
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn jj_repo_free(repo: *mut JjRepo) {
+    if !repo.is_null() {
+        let jj_repo = unsafe { Box::from_raw(repo) };
+        std::panic::catch_unwind(AssertUnwindSafe(|| drop(jj_repo))).ok();
+    }
+}
+
+/// Evaluates `revset` and returns the full hex commit IDs, one per line, in
+/// the order the revset produces them (children before parents).
+///
+/// # Safety
+///
+/// `repo` must be a valid repo, `revset` a NUL-terminated string, and `error`
+/// NULL or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn jj_revset_evaluate(
+    repo: *const JjRepo,
+    revset: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut c_char {
+    let jj_repo = unsafe { &*repo };
+    let result = catch_panic(|| {
+        let revset_str = unsafe { str_arg(revset, "revset") }?;
+        let mut output = String::new();
+        for commit in resolve_revset(jj_repo, revset_str)? {
+            output.push_str(&commit.id().hex());
+            output.push('\n');
+        }
+        Ok(output)
+    });
+    match result {
+        Ok(output) => to_c_string(output),
+        Err(message) => {
+            unsafe { set_error(error, message) };
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Looks up a commit by its full hex ID or by any revset that resolves to a
+/// single commit.
+///
+/// # Safety
+///
+/// `repo` must be a valid repo, `revision` a NUL-terminated string, and
+/// `error` NULL or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn jj_commit_get(
+    repo: *const JjRepo,
+    revision: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut JjCommit {
+    let jj_repo = unsafe { &*repo };
+    let result = catch_panic(|| {
+        let revision_str = unsafe { str_arg(revision, "revision") }?;
+        let mut commits = resolve_revset(jj_repo, revision_str)?;
+        if commits.len() != 1 {
+            return Err(format!(
+                "Revset \"{revision_str}\" didn't resolve to exactly one revision"
+            ));
+        }
+        Ok(commits.pop().unwrap())
+    });
+    match result {
+        Ok(commit) => Box::into_raw(Box::new(JjCommit { commit })),
+        Err(message) => {
+            unsafe { set_error(error, message) };
+            std::ptr::null_mut()
+        }
+    }
+}
+
+/// Frees a commit returned by `jj_commit_get()`. Does nothing if `commit` is
+/// NULL.
+///
+/// # Safety
+///
+/// `commit` must be NULL or a valid commit that hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn jj_commit_free(commit: *mut JjCommit) {
+    if !commit.is_null() {
+        let jj_commit = unsafe { Box::from_raw(commit) };
+        std::panic::catch_unwind(AssertUnwindSafe(|| drop(jj_commit))).ok();
+    }
+}
+
+/// Returns `f()` as a C string, or NULL if it panics.
+fn string_or_null(f: impl FnOnce() -> String) -> *mut c_char {
+    match catch_panic(|| Ok(f())) {
+        Ok(string) => to_c_string(string),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Returns the commit's full hex commit ID.
+///
+/// # Safety
+///
+/// `commit` must be a valid commit.
+#[no_mangle]
+pub unsafe extern "C" fn jj_commit_id(commit: *const JjCommit) -> *mut c_char {
+    string_or_null(|| unsafe { &*commit }.commit.id().hex())
+}
+
+/// Returns the commit's full hex change ID.
+///
+/// # Safety
+///
+/// `commit` must be a valid commit.
+#[no_mangle]
+pub unsafe extern "C" fn jj_commit_change_id(commit: *const JjCommit) -> *mut c_char {
+    string_or_null(|| unsafe { &*commit }.commit.change_id().hex())
+}
+
+/// Returns the commit's description.
+///
+/// # Safety
+///
+/// `commit` must be a valid commit.
+#[no_mangle]
+pub unsafe extern "C" fn jj_commit_description(commit: *const JjCommit) -> *mut c_char {
+    string_or_null(|| unsafe { &*commit }.commit.description().to_string())
+}
+
+/// Returns the commit's author as "Name <email>".
+///
+/// # Safety
+///
+/// `commit` must be a valid commit.
+#[no_mangle]
+pub unsafe extern "C" fn jj_commit_author(commit: *const JjCommit) -> *mut c_char {
+    string_or_null(|| {
+        let author = unsafe { &*commit }.commit.author();
+        format!("{} <{}>", author.name, author.email)
+    })
+}
+
+/// Returns the author timestamp in milliseconds since the epoch, or
+/// `INT64_MIN` if it can't be read.
+///
+/// # Safety
+///
+/// `commit` must be a valid commit.
+#[no_mangle]
+pub unsafe extern "C" fn jj_commit_author_timestamp(commit: *const JjCommit) -> i64 {
+    catch_panic(|| Ok(unsafe { &*commit }.commit.author().timestamp.timestamp.0))
+        .unwrap_or(i64::MIN)
+}
+
+/// Returns the full hex IDs of the commit's parents, one per line.
+///
+/// # Safety
+///
+/// `commit` must be a valid commit.
+#[no_mangle]
+pub unsafe extern "C" fn jj_commit_parents(commit: *const JjCommit) -> *mut c_char {
+    string_or_null(|| {
+        let mut output = String::new();
+        for parent_id in unsafe { &*commit }.commit.parent_ids() {
+            output.push_str(&parent_id.hex());
+            output.push('\n');
+        }
+        output
+    })
+}
+
+fn read_file(commit: &Commit, path_str: &str) -> Result<Vec<u8>, String> {
+    let path = RepoPath::from_internal_string(path_str);
+    let store = commit.store();
+    match commit.tree().path_value(&path) {
+        Some(TreeValue::Normal { id, .. }) => {
+            let mut content = vec![];
+            store
+                .read_file(&path, &id)
+                .map_err(|err| err.to_string())?
+                .read_to_end(&mut content)
+                .map_err(|err| err.to_string())?;
+            Ok(content)
+        }
+        Some(TreeValue::Symlink(id)) => store
+            .read_symlink(&path, &id)
+            .map(String::into_bytes)
+            .map_err(|err| err.to_string()),
+        Some(TreeValue::Conflict(_)) => Err(format!("Path \"{path_str}\" is conflicted")),
+        Some(_) | None => Err(format!(
+            "No such file \"{path_str}\" in commit {}",
+            commit.id().hex()
+        )),
+    }
+}
+
+/// Reads the contents of the file at `path` (a slash-separated path relative
+/// to the workspace root) in `commit`. For symlinks, the target is returned.
+/// On success, stores a buffer the caller must free with `jj_bytes_free()` in
+/// `*data` and its length in `*len`, and returns 0.
+///
+/// # Safety
+///
+/// `commit` must be a valid commit, `path` a NUL-terminated string, `data` and
+/// `len` valid for writes, and `error` NULL or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn jj_file_read(
+    commit: *const JjCommit,
+    path: *const c_char,
+    data: *mut *mut u8,
+    len: *mut usize,
+    error: *mut *mut c_char,
+) -> c_int {
+    let jj_commit = unsafe { &*commit };
+    let result = catch_panic(|| {
+        unsafe { str_arg(path, "path") }.and_then(|path_str| read_file(&jj_commit.commit, path_str))
+    });
+    match result {
+        Ok(content) => {
+            let content = content.into_boxed_slice();
+            unsafe {
+                *len = content.len();
+                *data = Box::into_raw(content) as *mut u8;
+            }
+            0
+        }
+        Err(message) => {
+            unsafe { set_error(error, message) };
+            -1
+        }
+    }
+}
+
+/// Frees a string returned by this library. Does nothing if `string` is NULL.
+///
+/// # Safety
+///
+/// `string` must be NULL or a string returned by this library that hasn't
+/// been freed.
+#[no_mangle]
+pub unsafe extern "C" fn jj_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
+
+/// Frees a buffer returned by `jj_file_read()`.
+///
+/// # Safety
+///
+/// `data` and `len` must be a buffer and its length as returned by
+/// `jj_file_read()`, and the buffer must not have been freed.
+#[no_mangle]
+pub unsafe extern "C" fn jj_bytes_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(data, len)) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catch_panic() {
+        assert_eq!(catch_panic(|| Ok(1)), Ok(1));
+        assert_eq!(
+            catch_panic::<()>(|| Err("error".to_string())),
+            Err("error".to_string())
+        );
+        assert_eq!(
+            catch_panic::<()>(|| panic!("corrupt {}", "index")),
+            Err("Internal error: corrupt index".to_string())
+        );
+        assert_eq!(
+            catch_panic::<()>(|| panic!("corrupt")),
+            Err("Internal error: corrupt".to_string())
+        );
+    }
+}
@@ -0,0 +1,113 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+
+use jujutsu_ffi::*;
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestWorkspace;
+
+unsafe fn take_string(ptr: *mut c_char) -> String {
+    assert!(!ptr.is_null());
+    let string = CStr::from_ptr(ptr).to_str().unwrap().to_string();
+    jj_string_free(ptr);
+    string
+}
+
+#[test]
+fn test_ffi_read_commit() {
+    let settings = testutils::user_settings();
+    let test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root();
+
+    let file_path = RepoPath::from_internal_string("dir/file");
+    let tree = testutils::create_tree(repo, &[(&file_path, "contents")]);
+    let mut tx = repo.start_transaction("test");
+    let commit = testutils::create_random_commit(&settings, repo)
+        .set_tree(tree.id().clone())
+        .set_description("description".to_string())
+        .write_to_repo(tx.mut_repo());
+    tx.commit();
+
+    unsafe {
+        let path = CString::new(workspace_root.to_str().unwrap()).unwrap();
+        let mut error = std::ptr::null_mut();
+        let jj_repo = jj_repo_open(path.as_ptr(), &mut error);
+        assert!(!jj_repo.is_null());
+
+        let revset = CString::new(commit.id().hex()).unwrap();
+        let ids = take_string(jj_revset_evaluate(jj_repo, revset.as_ptr(), &mut error));
+        assert_eq!(ids, format!("{}\n", commit.id().hex()));
+
+        let jj_commit = jj_commit_get(jj_repo, revset.as_ptr(), &mut error);
+        assert!(!jj_commit.is_null());
+        assert_eq!(take_string(jj_commit_id(jj_commit)), commit.id().hex());
+        assert_eq!(
+            take_string(jj_commit_change_id(jj_commit)),
+            commit.change_id().hex()
+        );
+        assert_eq!(take_string(jj_commit_description(jj_commit)), "description");
+        assert_eq!(
+            take_string(jj_commit_author(jj_commit)),
+            "Test User <test.user@example.com>"
+        );
+
+        let file_path = CString::new("dir/file").unwrap();
+        let mut data = std::ptr::null_mut();
+        let mut len = 0;
+        assert_eq!(
+            jj_file_read(
+                jj_commit,
+                file_path.as_ptr(),
+                &mut data,
+                &mut len,
+                &mut error
+            ),
+            0
+        );
+        assert_eq!(std::slice::from_raw_parts(data, len), b"contents");
+        jj_bytes_free(data, len);
+
+        let missing_path = CString::new("missing").unwrap();
+        assert_eq!(
+            jj_file_read(
+                jj_commit,
+                missing_path.as_ptr(),
+                &mut data,
+                &mut len,
+                &mut error
+            ),
+            -1
+        );
+        assert!(take_string(error).starts_with("No such file \"missing\""));
+
+        jj_commit_free(jj_commit);
+        jj_repo_free(jj_repo);
+    }
+}
+
+#[test]
+fn test_ffi_open_error() {
+    let temp_dir = testutils::new_temp_dir();
+    let path = CString::new(temp_dir.path().to_str().unwrap()).unwrap();
+    unsafe {
+        let mut error = std::ptr::null_mut();
+        let jj_repo = jj_repo_open(path.as_ptr(), &mut error);
+        assert!(jj_repo.is_null());
+        assert!(take_string(error).starts_with("There is no jj repo in"));
+    }
+}
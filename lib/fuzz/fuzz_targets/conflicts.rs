@@ -0,0 +1,43 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use jujutsu_lib::conflicts::{materialize_merge_result, parse_conflict};
+use jujutsu_lib::files::{merge, MergeHunk, MergeResult};
+use libfuzzer_sys::fuzz_target;
+
+// The input is parsed as a materialized conflict, and also split into the base
+// and two sides of a merge at NUL bytes
+fuzz_target!(|data: &[u8]| {
+    if let Some(hunks) = parse_conflict(data, 1, 2) {
+        for hunk in hunks {
+            if let MergeHunk::Conflict { removes, adds } = hunk {
+                assert_eq!(removes.len(), 1);
+                assert_eq!(adds.len(), 2);
+            }
+        }
+    }
+
+    let parts: Vec<&[u8]> = data.splitn(3, |b| *b == 0).collect();
+    if let [base, left, right] = parts[..] {
+        assert_eq!(
+            merge(&[base], &[base, right]),
+            MergeResult::Resolved(right.to_vec())
+        );
+        let merge_result = merge(&[base], &[left, right]);
+        let mut materialized = vec![];
+        materialize_merge_result(merge_result, &mut materialized).unwrap();
+    }
+});
@@ -0,0 +1,39 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use jujutsu_lib::diff::{diff, DiffHunk};
+use libfuzzer_sys::fuzz_target;
+
+fn diff_side(hunks: &[DiffHunk], side: usize) -> Vec<u8> {
+    hunks
+        .iter()
+        .flat_map(|hunk| match hunk {
+            DiffHunk::Matching(content) => content.to_vec(),
+            DiffHunk::Different(contents) => contents[side].to_vec(),
+        })
+        .collect()
+}
+
+// The input is split into the two sides at the first NUL byte
+fuzz_target!(|data: &[u8]| {
+    let (left, right) = match data.iter().position(|b| *b == 0) {
+        Some(pos) => (&data[..pos], &data[pos + 1..]),
+        None => (data, &b""[..]),
+    };
+    let hunks = diff(left, right);
+    assert_eq!(diff_side(&hunks, 0), left);
+    assert_eq!(diff_side(&hunks, 1), right);
+});
@@ -0,0 +1,31 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use jujutsu_lib::gitignore::GitIgnoreFile;
+use libfuzzer_sys::fuzz_target;
+
+// The lines before the first empty line are the patterns and the rest are
+// paths to match
+fuzz_target!(|data: &str| {
+    let (patterns, paths) = data.split_once("\n\n").unwrap_or((data, ""));
+    let ignore = GitIgnoreFile::empty().chain("", patterns.as_bytes());
+    for path in paths.lines() {
+        ignore.matches_file(path);
+        if path.ends_with('/') {
+            ignore.matches_all_files_in(path);
+        }
+    }
+});
@@ -257,7 +257,23 @@ pub struct Signature {
     pub timestamp: Timestamp,
 }
 
-#[derive(Debug, Clone)]
+/// Records that the file at `target` in a commit was copied from `source` in
+/// its parent.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
+pub struct CopyRecord {
+    pub source: RepoPath,
+    pub target: RepoPath,
+}
+
+/// Someone who wrote a commit together with its author. Stored in Git commits
+/// as `Co-authored-by:` trailers.
+#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+pub struct CoAuthor {
+    pub name: String,
+    pub email: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Commit {
     pub parents: Vec<CommitId>,
     pub predecessors: Vec<CommitId>,
@@ -267,6 +283,9 @@ pub struct Commit {
     pub author: Signature,
     pub committer: Signature,
     pub is_open: bool,
+    /// Copies (and renames) made in this commit, if they were recorded.
+    pub copies: Vec<CopyRecord>,
+    pub co_authors: Vec<CoAuthor>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -286,10 +305,54 @@ pub struct Conflict {
     pub adds: Vec<ConflictPart>,
 }
 
+/// The kinds of objects a backend stores.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
+pub enum ObjectKind {
+    Commit,
+    Tree,
+    File,
+    Symlink,
+    Conflict,
+}
+
+impl ObjectKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ObjectKind::Commit => "commit",
+            ObjectKind::Tree => "tree",
+            ObjectKind::File => "file",
+            ObjectKind::Symlink => "symlink",
+            ObjectKind::Conflict => "conflict",
+        }
+    }
+}
+
+/// How much storage a backend saves by storing large files as chunks that are
+/// shared between files (see `Backend::chunk_stats()`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChunkStats {
+    /// Number of files stored as chunks
+    pub chunked_files: usize,
+    /// Total size of the contents of those files
+    pub total_bytes: u64,
+    /// Number of distinct chunks they consist of
+    pub unique_chunks: usize,
+    /// Total size of the contents of the distinct chunks
+    pub unique_bytes: u64,
+    /// Total size of the distinct chunks as stored (compressed)
+    pub stored_bytes: u64,
+}
+
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum BackendError {
     #[error("Object not found")]
     NotFound,
+    #[error("Corrupt {} object {hash}: its contents hash to {actual_hash}", .kind.name())]
+    HashMismatch {
+        kind: ObjectKind,
+        hash: String,
+        actual_hash: String,
+    },
     #[error("Error: {0}")]
     Other(String),
 }
@@ -394,6 +457,8 @@ pub fn make_root_commit(empty_tree_id: TreeId) -> Commit {
         author: signature.clone(),
         committer: signature,
         is_open: false,
+        copies: vec![],
+        co_authors: vec![],
     }
 }
 
@@ -429,4 +494,26 @@ pub trait Backend: Send + Sync + Debug {
     fn read_commit(&self, id: &CommitId) -> BackendResult<Commit>;
 
     fn write_commit(&self, contents: &Commit) -> BackendResult<CommitId>;
+
+    /// Re-hashes the stored contents of an object and returns
+    /// `BackendError::HashMismatch` if they don't hash to `id`. Backends that
+    /// can't do that don't check anything.
+    fn verify_object(&self, kind: ObjectKind, id: &[u8]) -> BackendResult<()> {
+        let _ = (kind, id);
+        Ok(())
+    }
+
+    /// Consolidates the objects stored one per file into packs, if there are
+    /// at least `min_count` of them and the backend stores objects that way.
+    /// Returns the number of objects packed.
+    fn pack_loose_objects(&self, min_count: usize) -> BackendResult<usize> {
+        let _ = min_count;
+        Ok(0)
+    }
+
+    /// Returns statistics about the files stored as chunks, or `None` if the
+    /// backend doesn't store files that way.
+    fn chunk_stats(&self) -> BackendResult<Option<ChunkStats>> {
+        Ok(None)
+    }
 }
@@ -0,0 +1,461 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Copying a repo's objects to another backend. Backends hash objects
+//! differently, so the commits get new ids, and the operation log is
+//! rewritten to refer to them.
+//!
+//! The new store is written next to the current one. The migration takes
+//! effect when a `PendingMigration` is committed, which is a single atomic
+//! file write. Swapping the stores and op heads afterwards can be interrupted
+//! and is completed the next time the workspace is loaded.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::backend::{
+    Backend, BackendError, BackendResult, CommitId, Conflict, ConflictId, ConflictPart, FileId,
+    SymlinkId, Tree, TreeId, TreeValue,
+};
+use crate::index_store::IndexStore;
+use crate::op_heads_store::OpHeadsStore;
+use crate::op_store::{
+    BranchTarget, OpStore, OpStoreError, Operation, OperationId, RefTarget, View,
+};
+use crate::repo_path::{RepoPath, RepoPathJoin};
+
+#[derive(Debug, Error)]
+pub enum BackendMigrationError {
+    #[error("Failed to copy objects: {0}")]
+    Backend(#[from] BackendError),
+    #[error("Failed to rewrite the operation log: {0}")]
+    OpStore(#[from] OpStoreError),
+}
+
+/// Copies objects from one backend to another, remembering the ids they got
+/// in the destination so each object is only copied once.
+pub struct ObjectCopier<'a> {
+    source: &'a dyn Backend,
+    destination: &'a dyn Backend,
+    commit_ids: HashMap<CommitId, CommitId>,
+    tree_ids: HashMap<TreeId, TreeId>,
+    file_ids: HashMap<FileId, FileId>,
+    symlink_ids: HashMap<SymlinkId, SymlinkId>,
+    conflict_ids: HashMap<ConflictId, ConflictId>,
+}
+
+impl<'a> ObjectCopier<'a> {
+    pub fn new(source: &'a dyn Backend, destination: &'a dyn Backend) -> Self {
+        let mut commit_ids = HashMap::new();
+        commit_ids.insert(
+            source.root_commit_id().clone(),
+            destination.root_commit_id().clone(),
+        );
+        let mut tree_ids = HashMap::new();
+        tree_ids.insert(
+            source.empty_tree_id().clone(),
+            destination.empty_tree_id().clone(),
+        );
+        ObjectCopier {
+            source,
+            destination,
+            commit_ids,
+            tree_ids,
+            file_ids: HashMap::new(),
+            symlink_ids: HashMap::new(),
+            conflict_ids: HashMap::new(),
+        }
+    }
+
+    /// The ids of the commits copied so far (including the root commit), keyed
+    /// by their id in the source backend.
+    pub fn commit_ids(&self) -> &HashMap<CommitId, CommitId> {
+        &self.commit_ids
+    }
+
+    /// Copies a commit along with its ancestors, predecessors and trees.
+    /// Returns its id in the destination backend.
+    pub fn copy_commit(&mut self, id: &CommitId) -> BackendResult<CommitId> {
+        // Parents and predecessors have to be copied first since the copy refers
+        // to their new ids. Histories can be long, so don't recurse.
+        let mut stack = vec![id.clone()];
+        while let Some(id) = stack.last().cloned() {
+            if self.commit_ids.contains_key(&id) {
+                stack.pop();
+                continue;
+            }
+            let mut commit = self.source.read_commit(&id)?;
+            let missing_ids = commit
+                .parents
+                .iter()
+                .chain(&commit.predecessors)
+                .filter(|id| !self.commit_ids.contains_key(id))
+                .cloned()
+                .collect::<Vec<_>>();
+            if !missing_ids.is_empty() {
+                stack.extend(missing_ids);
+                continue;
+            }
+            commit.parents = commit
+                .parents
+                .iter()
+                .map(|id| self.commit_ids[id].clone())
+                .collect();
+            commit.predecessors = commit
+                .predecessors
+                .iter()
+                .map(|id| self.commit_ids[id].clone())
+                .collect();
+            commit.root_tree = self.copy_tree(&RepoPath::root(), &commit.root_tree)?;
+            let new_id = self.destination.write_commit(&commit)?;
+            self.commit_ids.insert(id, new_id);
+            stack.pop();
+        }
+        Ok(self.commit_ids[id].clone())
+    }
+
+    fn copy_tree(&mut self, path: &RepoPath, id: &TreeId) -> BackendResult<TreeId> {
+        if let Some(new_id) = self.tree_ids.get(id) {
+            return Ok(new_id.clone());
+        }
+        let tree = self.source.read_tree(path, id)?;
+        let mut new_tree = Tree::default();
+        for entry in tree.entries() {
+            let value = self.copy_value(&path.join(entry.name()), entry.value())?;
+            new_tree.set(entry.name().clone(), value);
+        }
+        let new_id = self.destination.write_tree(path, &new_tree)?;
+        self.tree_ids.insert(id.clone(), new_id.clone());
+        Ok(new_id)
+    }
+
+    fn copy_value(&mut self, path: &RepoPath, value: &TreeValue) -> BackendResult<TreeValue> {
+        let new_value = match value {
+            TreeValue::Normal { id, executable } => TreeValue::Normal {
+                id: self.copy_file(path, id)?,
+                executable: *executable,
+            },
+            TreeValue::Symlink(id) => TreeValue::Symlink(self.copy_symlink(path, id)?),
+            TreeValue::Tree(id) => TreeValue::Tree(self.copy_tree(path, id)?),
+            TreeValue::Conflict(id) => TreeValue::Conflict(self.copy_conflict(path, id)?),
+            TreeValue::GitSubmodule(id) => {
+                if self.destination.git_repo().is_none() {
+                    return Err(BackendError::Other(format!(
+                        "The {} backend can't store the Git submodule at {}",
+                        self.destination.name(),
+                        path.to_internal_file_string()
+                    )));
+                }
+                TreeValue::GitSubmodule(id.clone())
+            }
+        };
+        Ok(new_value)
+    }
+
+    fn copy_file(&mut self, path: &RepoPath, id: &FileId) -> BackendResult<FileId> {
+        if let Some(new_id) = self.file_ids.get(id) {
+            return Ok(new_id.clone());
+        }
+        let mut contents = self.source.read_file(path, id)?;
+        let new_id = self.destination.write_file(path, &mut contents)?;
+        self.file_ids.insert(id.clone(), new_id.clone());
+        Ok(new_id)
+    }
+
+    fn copy_symlink(&mut self, path: &RepoPath, id: &SymlinkId) -> BackendResult<SymlinkId> {
+        if let Some(new_id) = self.symlink_ids.get(id) {
+            return Ok(new_id.clone());
+        }
+        let target = self.source.read_symlink(path, id)?;
+        let new_id = self.destination.write_symlink(path, &target)?;
+        self.symlink_ids.insert(id.clone(), new_id.clone());
+        Ok(new_id)
+    }
+
+    fn copy_conflict(&mut self, path: &RepoPath, id: &ConflictId) -> BackendResult<ConflictId> {
+        if let Some(new_id) = self.conflict_ids.get(id) {
+            return Ok(new_id.clone());
+        }
+        let conflict = self.source.read_conflict(path, id)?;
+        let mut copy_parts = |parts: &[ConflictPart]| -> BackendResult<Vec<ConflictPart>> {
+            parts
+                .iter()
+                .map(|part| {
+                    Ok(ConflictPart {
+                        value: self.copy_value(path, &part.value)?,
+                    })
+                })
+                .collect()
+        };
+        let new_conflict = Conflict {
+            removes: copy_parts(&conflict.removes)?,
+            adds: copy_parts(&conflict.adds)?,
+        };
+        let new_id = self.destination.write_conflict(path, &new_conflict)?;
+        self.conflict_ids.insert(id.clone(), new_id.clone());
+        Ok(new_id)
+    }
+}
+
+/// Returns all commit ids a view refers to.
+pub fn view_commit_ids(view: &View) -> Vec<CommitId> {
+    let mut ids = vec![];
+    ids.extend(view.head_ids.iter().cloned());
+    ids.extend(view.public_head_ids.iter().cloned());
+    ids.extend(view.wc_commit_ids.values().cloned());
+    ids.extend(view.git_head.iter().cloned());
+    let mut add_target =
+        |target: &RefTarget| ids.extend(target.adds().into_iter().chain(target.removes()));
+    for branch_target in view.branches.values() {
+        branch_target.local_target.iter().for_each(&mut add_target);
+        branch_target
+            .remote_targets
+            .values()
+            .for_each(&mut add_target);
+    }
+    view.tags.values().for_each(&mut add_target);
+    view.git_refs.values().for_each(&mut add_target);
+    ids
+}
+
+fn map_ref_target(target: &RefTarget, commit_ids: &HashMap<CommitId, CommitId>) -> RefTarget {
+    match target {
+        RefTarget::Normal(id) => RefTarget::Normal(commit_ids[id].clone()),
+        RefTarget::Conflict { removes, adds } => RefTarget::Conflict {
+            removes: removes.iter().map(|id| commit_ids[id].clone()).collect(),
+            adds: adds.iter().map(|id| commit_ids[id].clone()).collect(),
+        },
+    }
+}
+
+fn map_view(view: &View, commit_ids: &HashMap<CommitId, CommitId>) -> View {
+    let map_id = |id: &CommitId| commit_ids[id].clone();
+    let map_targets = |targets: &BTreeMap<String, RefTarget>| {
+        targets
+            .iter()
+            .map(|(name, target)| (name.clone(), map_ref_target(target, commit_ids)))
+            .collect()
+    };
+    View {
+        head_ids: view.head_ids.iter().map(map_id).collect(),
+        public_head_ids: view.public_head_ids.iter().map(map_id).collect(),
+        branches: view
+            .branches
+            .iter()
+            .map(|(name, target)| {
+                let target = BranchTarget {
+                    local_target: target
+                        .local_target
+                        .as_ref()
+                        .map(|target| map_ref_target(target, commit_ids)),
+                    remote_targets: map_targets(&target.remote_targets),
+                };
+                (name.clone(), target)
+            })
+            .collect(),
+        tags: map_targets(&view.tags),
+        git_refs: map_targets(&view.git_refs),
+        git_head: view.git_head.as_ref().map(map_id),
+        wc_commit_ids: view
+            .wc_commit_ids
+            .iter()
+            .map(|(workspace_id, id)| (workspace_id.clone(), map_id(id)))
+            .collect(),
+        change_names: view.change_names.clone(),
+        change_topics: view.change_topics.clone(),
+    }
+}
+
+/// The result of `migrate_backend()`.
+pub struct BackendMigration {
+    /// Commit ids in the destination backend, keyed by their id in the source
+    /// backend. Includes every commit the operation log refers to.
+    pub commit_ids: HashMap<CommitId, CommitId>,
+    /// Ids of the rewritten operations, keyed by the original ones.
+    pub operation_ids: HashMap<OperationId, OperationId>,
+}
+
+/// Copies every commit the operations before `head_op_ids` refer to (with
+/// their ancestors and predecessors) from `source` to `destination`. Then
+/// writes copies of the operations that refer to the new commit ids to
+/// `op_store`. The original operations are left as they are.
+pub fn migrate_backend(
+    op_store: &Arc<dyn OpStore>,
+    head_op_ids: &[OperationId],
+    source: &dyn Backend,
+    destination: &dyn Backend,
+) -> Result<BackendMigration, BackendMigrationError> {
+    let mut copier = ObjectCopier::new(source, destination);
+    let mut operation_ids: HashMap<OperationId, OperationId> = HashMap::new();
+    let mut stack = head_op_ids.to_vec();
+    while let Some(op_id) = stack.last().cloned() {
+        if operation_ids.contains_key(&op_id) {
+            stack.pop();
+            continue;
+        }
+        let operation = op_store.read_operation(&op_id)?;
+        let missing_ids = operation
+            .parents
+            .iter()
+            .filter(|id| !operation_ids.contains_key(*id))
+            .cloned()
+            .collect::<Vec<_>>();
+        if !missing_ids.is_empty() {
+            stack.extend(missing_ids);
+            continue;
+        }
+        let view = op_store.read_view(&operation.view_id)?;
+        for id in view_commit_ids(&view) {
+            copier.copy_commit(&id)?;
+        }
+        let new_view_id = op_store.write_view(&map_view(&view, copier.commit_ids()))?;
+        let new_operation = Operation {
+            view_id: new_view_id,
+            parents: operation
+                .parents
+                .iter()
+                .map(|id| operation_ids[id].clone())
+                .collect(),
+            metadata: operation.metadata.clone(),
+        };
+        operation_ids.insert(op_id, op_store.write_operation(&new_operation)?);
+        stack.pop();
+    }
+    Ok(BackendMigration {
+        commit_ids: copier.commit_ids,
+        operation_ids,
+    })
+}
+
+/// A migration whose new store (in `store.new`) and operations have been
+/// written, but that hasn't replaced the repo's store and op heads yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingMigration {
+    repo_path: PathBuf,
+    old_head_op_ids: Vec<OperationId>,
+    new_head_op_ids: Vec<OperationId>,
+}
+
+impl PendingMigration {
+    fn state_path(repo_path: &Path) -> PathBuf {
+        repo_path.join("pending_migration")
+    }
+
+    pub fn new_store_path(repo_path: &Path) -> PathBuf {
+        repo_path.join("store.new")
+    }
+
+    pub fn old_store_path(repo_path: &Path) -> PathBuf {
+        repo_path.join("store.old")
+    }
+
+    /// Records that the store in `store.new` and the operations in
+    /// `new_head_op_ids` replace the current store and `old_head_op_ids`. From
+    /// then on, the migration is completed even if it's interrupted. The op
+    /// heads should be locked.
+    pub fn commit(
+        repo_path: &Path,
+        old_head_op_ids: &[OperationId],
+        new_head_op_ids: &[OperationId],
+    ) -> io::Result<Self> {
+        let mut temp_file = tempfile::NamedTempFile::new_in(repo_path)?;
+        for id in old_head_op_ids {
+            writeln!(temp_file, "old {}", id.hex())?;
+        }
+        for id in new_head_op_ids {
+            writeln!(temp_file, "new {}", id.hex())?;
+        }
+        temp_file.as_file().sync_all()?;
+        temp_file
+            .persist(Self::state_path(repo_path))
+            .map_err(|err| err.error)?;
+        Ok(PendingMigration {
+            repo_path: repo_path.to_path_buf(),
+            old_head_op_ids: old_head_op_ids.to_vec(),
+            new_head_op_ids: new_head_op_ids.to_vec(),
+        })
+    }
+
+    /// Returns the migration of the repo at `repo_path` that was committed but
+    /// not finished, if any.
+    pub fn load(repo_path: &Path) -> io::Result<Option<Self>> {
+        let contents = match fs::read_to_string(Self::state_path(repo_path)) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let mut old_head_op_ids = vec![];
+        let mut new_head_op_ids = vec![];
+        for line in contents.lines() {
+            match line.split_once(' ') {
+                Some(("old", hex)) => old_head_op_ids.push(OperationId::from_hex(hex)),
+                Some(("new", hex)) => new_head_op_ids.push(OperationId::from_hex(hex)),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid line in pending_migration: {line}"),
+                    ))
+                }
+            }
+        }
+        Ok(Some(PendingMigration {
+            repo_path: repo_path.to_path_buf(),
+            old_head_op_ids,
+            new_head_op_ids,
+        }))
+    }
+
+    /// Replaces the op heads and moves the new store into place. Can be
+    /// called again if it was interrupted.
+    pub fn swap_store(&self) -> io::Result<()> {
+        let op_heads_store = Arc::new(OpHeadsStore::load(self.repo_path.join("op_heads")));
+        let locked_op_heads = op_heads_store.lock();
+        if !Self::state_path(&self.repo_path).exists() {
+            // Another process finished the migration while we waited for the lock
+            return Ok(());
+        }
+        locked_op_heads.replace(&self.old_head_op_ids, &self.new_head_op_ids);
+        let store_path = self.repo_path.join("store");
+        let new_store_path = Self::new_store_path(&self.repo_path);
+        if new_store_path.exists() {
+            if store_path.exists() {
+                fs::rename(&store_path, Self::old_store_path(&self.repo_path))?;
+            }
+            fs::rename(&new_store_path, &store_path)?;
+        }
+        // The index refers to commits by their position, which has changed, and
+        // may use another hash length.
+        IndexStore::load(self.repo_path.join("index")).reinit();
+        Ok(())
+    }
+
+    /// Deletes the old store. Must only be called after `swap_store()`, once
+    /// nothing refers to the old store anymore.
+    pub fn finish(self) -> io::Result<()> {
+        let old_store_path = Self::old_store_path(&self.repo_path);
+        if old_store_path.exists() {
+            fs::remove_dir_all(&old_store_path)?;
+        }
+        match fs::remove_file(Self::state_path(&self.repo_path)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
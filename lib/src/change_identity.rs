@@ -0,0 +1,215 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Backend-independent identities of commits. Commit ids depend on how the
+//! backend hashes commits, so the same commit gets a different id in the Git
+//! backend than in the local backend. A commit's identity is instead a hash of
+//! the metadata that every backend preserves (the author, committer and
+//! description) and of its parents' identities. It's used for deriving change
+//! ids for imported commits that don't come with one, and for checking that
+//! change ids survived a migration or re-import.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use blake2::{Blake2b512, Digest};
+
+use crate::backend::{BackendResult, ChangeId, Commit, CommitId, Signature};
+use crate::store::Store;
+
+/// Changing the serialization changes every identity, so it's versioned.
+const IDENTITY_VERSION: &[u8] = b"jj-commit-identity-v1\0";
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CommitIdentity(Vec<u8>);
+
+impl Debug for CommitIdentity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.debug_tuple("CommitIdentity").field(&self.hex()).finish()
+    }
+}
+
+impl CommitIdentity {
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        hex::decode(hex).ok().map(CommitIdentity)
+    }
+
+    pub fn hex(&self) -> String {
+        hex::encode(&self.0)
+    }
+
+    /// Computes the identity of a commit whose parents have the given
+    /// identities. The commit's change id, tree and any metadata that only
+    /// some backends store (predecessors, copies, the open flag) don't affect
+    /// it.
+    pub fn compute(commit: &Commit, parent_identities: &[CommitIdentity]) -> Self {
+        let mut hasher = Blake2b512::new();
+        hasher.update(IDENTITY_VERSION);
+        hash_u64(&mut hasher, parent_identities.len() as u64);
+        for parent_identity in parent_identities {
+            hash_bytes(&mut hasher, &parent_identity.0);
+        }
+        hash_signature(&mut hasher, &commit.author);
+        hash_signature(&mut hasher, &commit.committer);
+        hash_bytes(&mut hasher, commit.description.as_bytes());
+        hash_u64(&mut hasher, commit.co_authors.len() as u64);
+        for co_author in &commit.co_authors {
+            hash_bytes(&mut hasher, co_author.name.as_bytes());
+            hash_bytes(&mut hasher, co_author.email.as_bytes());
+        }
+        CommitIdentity(hasher.finalize()[..32].to_vec())
+    }
+
+    /// The change id for a commit with this identity, for when there's no
+    /// other source for it. Commits with the same identity get the same change
+    /// id, so they'll appear as divergent.
+    pub fn derived_change_id(&self) -> ChangeId {
+        let mut hasher = Blake2b512::new();
+        hasher.update(b"change-id\0");
+        hasher.update(&self.0);
+        ChangeId::from_bytes(&hasher.finalize()[..16])
+    }
+}
+
+fn hash_u64(hasher: &mut Blake2b512, value: u64) {
+    hasher.update(value.to_be_bytes());
+}
+
+fn hash_bytes(hasher: &mut Blake2b512, bytes: &[u8]) {
+    hash_u64(hasher, bytes.len() as u64);
+    hasher.update(bytes);
+}
+
+fn hash_signature(hasher: &mut Blake2b512, signature: &Signature) {
+    hash_bytes(hasher, signature.name.as_bytes());
+    hash_bytes(hasher, signature.email.as_bytes());
+    // The Git backend only stores whole seconds
+    let seconds = signature.timestamp.timestamp.0.div_euclid(1000);
+    hasher.update(seconds.to_be_bytes());
+    hasher.update(signature.timestamp.tz_offset.to_be_bytes());
+}
+
+/// Computes and remembers the identities of commits in a store.
+#[derive(Debug, Default)]
+pub struct CommitIdentities {
+    identities: HashMap<CommitId, CommitIdentity>,
+}
+
+impl CommitIdentities {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the identity of a commit, computing the identities of its
+    /// ancestors first if necessary.
+    pub fn get(&mut self, store: &Arc<Store>, id: &CommitId) -> BackendResult<CommitIdentity> {
+        // Histories can be long, so don't recurse
+        let mut stack = vec![id.clone()];
+        while let Some(id) = stack.last().cloned() {
+            if self.identities.contains_key(&id) {
+                stack.pop();
+                continue;
+            }
+            let commit = store.get_commit(&id)?;
+            let missing_ids = commit
+                .parent_ids()
+                .iter()
+                .filter(|id| !self.identities.contains_key(id))
+                .cloned()
+                .collect::<Vec<_>>();
+            if !missing_ids.is_empty() {
+                stack.extend(missing_ids);
+                continue;
+            }
+            let parent_identities = commit
+                .parent_ids()
+                .iter()
+                .map(|id| self.identities[id].clone())
+                .collect::<Vec<_>>();
+            let identity = CommitIdentity::compute(commit.store_commit(), &parent_identities);
+            self.identities.insert(id, identity);
+            stack.pop();
+        }
+        Ok(self.identities[id].clone())
+    }
+
+    /// The identities computed so far, keyed by commit id.
+    pub fn identities(&self) -> &HashMap<CommitId, CommitIdentity> {
+        &self.identities
+    }
+}
+
+/// A commit whose change id isn't among the ones recorded for its identity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeIdDrift {
+    pub commit_id: CommitId,
+    pub identity: CommitIdentity,
+    pub expected_change_ids: BTreeSet<ChangeId>,
+    pub actual_change_id: ChangeId,
+}
+
+/// Returns the change ids of the given commits and their ancestors, keyed by
+/// the commits' identities. Commits with the same metadata and parents (e.g.
+/// empty siblings created in the same second) have the same identity, so an
+/// identity can have several change ids.
+pub fn change_ids_by_identity(
+    store: &Arc<Store>,
+    head_ids: &[CommitId],
+) -> BackendResult<BTreeMap<CommitIdentity, BTreeSet<ChangeId>>> {
+    let mut identities = CommitIdentities::new();
+    for id in head_ids {
+        identities.get(store, id)?;
+    }
+    let mut change_ids: BTreeMap<CommitIdentity, BTreeSet<ChangeId>> = BTreeMap::new();
+    for (id, identity) in identities.identities() {
+        let commit = store.get_commit(id)?;
+        change_ids
+            .entry(identity.clone())
+            .or_default()
+            .insert(commit.change_id().clone());
+    }
+    Ok(change_ids)
+}
+
+/// Finds the commits among the given commits and their ancestors whose change
+/// ids aren't among the ones in `expected` (as returned by
+/// `change_ids_by_identity()`, possibly for another repo). Commits whose
+/// identity isn't in `expected` are ignored.
+pub fn find_change_id_drift(
+    store: &Arc<Store>,
+    head_ids: &[CommitId],
+    expected: &BTreeMap<CommitIdentity, BTreeSet<ChangeId>>,
+) -> BackendResult<Vec<ChangeIdDrift>> {
+    let mut identities = CommitIdentities::new();
+    for id in head_ids {
+        identities.get(store, id)?;
+    }
+    let mut drift = vec![];
+    for (id, identity) in identities.identities() {
+        if let Some(expected_change_ids) = expected.get(identity) {
+            let commit = store.get_commit(id)?;
+            if !expected_change_ids.contains(commit.change_id()) {
+                drift.push(ChangeIdDrift {
+                    commit_id: id.clone(),
+                    identity: identity.clone(),
+                    expected_change_ids: expected_change_ids.clone(),
+                    actual_change_id: commit.change_id().clone(),
+                });
+            }
+        }
+    }
+    drift.sort_by(|a, b| a.identity.cmp(&b.identity));
+    Ok(drift)
+}
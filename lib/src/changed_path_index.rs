@@ -0,0 +1,196 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bloom filters of the paths each commit changed compared to its parents,
+//! like Git's changed-path filters. A filter can tell for certain that a
+//! commit didn't touch a path, so path-limited queries only need to diff the
+//! commits whose filter says they might have.
+//!
+//! The filters are computed the first time a commit is diffed for such a
+//! query and are stored in a table next to the index.
+
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use blake2::{Blake2b512, Digest};
+
+use crate::backend::CommitId;
+use crate::repo_path::RepoPath;
+use crate::stacked_table::{TableSegment, TableStore, TableStoreResult};
+
+// The same parameters as Git uses: 10 bits per path and 7 hash functions give
+// a false positive rate of about 1%.
+const BITS_PER_PATH: usize = 10;
+const NUM_HASHES: u64 = 7;
+// Commits that changed more paths than this (such as large imports) get an
+// empty filter, which matches every path.
+const MAX_PATHS: usize = 512;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangedPathFilter {
+    // Empty if the commit changed too many paths to be worth filtering.
+    bits: Vec<u8>,
+}
+
+impl ChangedPathFilter {
+    /// Creates a filter from the files a commit changed. The directories
+    /// containing them are added too, so a filter can be queried for
+    /// directories.
+    pub fn from_changed_files<'a>(files: impl IntoIterator<Item = &'a RepoPath>) -> Self {
+        let mut paths = vec![];
+        for file in files {
+            let mut path = Some(file.clone());
+            while let Some(current) = path {
+                if current.is_root() {
+                    break;
+                }
+                path = current.parent();
+                paths.push(current);
+            }
+        }
+        paths.sort();
+        paths.dedup();
+        if paths.len() > MAX_PATHS {
+            return ChangedPathFilter { bits: vec![] };
+        }
+        let num_bytes = (paths.len() * BITS_PER_PATH).max(64) / 8;
+        let mut filter = ChangedPathFilter {
+            bits: vec![0; num_bytes],
+        };
+        for path in &paths {
+            for bit in filter.bit_positions(path) {
+                filter.bits[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        filter
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        ChangedPathFilter {
+            bits: bytes.to_vec(),
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+
+    fn bit_positions(&self, path: &RepoPath) -> impl Iterator<Item = usize> {
+        // Double hashing: the i:th hash is h1 + i * h2
+        let digest = Blake2b512::digest(path.to_internal_file_string().as_bytes());
+        let h1 = u64::from_le_bytes(digest[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(digest[8..16].try_into().unwrap());
+        let num_bits = self.bits.len() as u64 * 8;
+        (0..NUM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits) as usize)
+    }
+
+    /// Returns false if the commit definitely didn't change the path (or
+    /// anything under it, if it's a directory).
+    pub fn might_contain(&self, path: &RepoPath) -> bool {
+        if self.bits.is_empty() || path.is_root() {
+            return true;
+        }
+        self.bit_positions(path)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+}
+
+/// The stored filters, plus the ones computed since the index was loaded.
+/// Those are written when the `ChangedPathIndex` is dropped.
+pub struct ChangedPathIndex {
+    table_store: TableStore,
+    new_filters: Mutex<Vec<(CommitId, ChangedPathFilter)>>,
+}
+
+impl ChangedPathIndex {
+    /// Loads the filters from `dir`, which is created if it doesn't exist.
+    /// `key_size` is the length of the commit ids.
+    pub fn load(dir: PathBuf, key_size: usize) -> TableStoreResult<Self> {
+        let table_store = if dir.join("heads").is_dir() {
+            TableStore::load(dir, key_size)
+        } else {
+            std::fs::create_dir_all(&dir)?;
+            TableStore::init(dir, key_size)
+        };
+        Ok(ChangedPathIndex {
+            table_store,
+            new_filters: Mutex::new(vec![]),
+        })
+    }
+
+    pub fn get(&self, commit_id: &CommitId) -> Option<ChangedPathFilter> {
+        let table = self.table_store.get_head().ok()?;
+        let bytes = table.get_value(commit_id.as_bytes())?;
+        Some(ChangedPathFilter::from_bytes(bytes))
+    }
+
+    /// Records the filter for the commit. It will be written when `self` is
+    /// dropped.
+    pub fn add(&self, commit_id: CommitId, filter: ChangedPathFilter) {
+        self.new_filters.lock().unwrap().push((commit_id, filter));
+    }
+
+    fn flush(&self) -> TableStoreResult<()> {
+        let new_filters = std::mem::take(&mut *self.new_filters.lock().unwrap());
+        if new_filters.is_empty() {
+            return Ok(());
+        }
+        let mut mut_table = self.table_store.get_head()?.start_mutation();
+        for (commit_id, filter) in new_filters {
+            mut_table.add_entry(commit_id.to_bytes(), filter.as_bytes().to_vec());
+        }
+        self.table_store.save_table(mut_table)?;
+        Ok(())
+    }
+}
+
+impl Drop for ChangedPathIndex {
+    fn drop(&mut self) {
+        // The filters are only a cache, so it's fine if they can't be written
+        // (e.g. because the repo is read-only). They'll be computed again.
+        self.flush().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_path_filter() {
+        let file = RepoPath::from_internal_string("dir/sub/file");
+        let other_file = RepoPath::from_internal_string("other");
+        let filter = ChangedPathFilter::from_changed_files([&file, &other_file]);
+        assert!(filter.might_contain(&file));
+        assert!(filter.might_contain(&other_file));
+        // The parent directories are included
+        assert!(filter.might_contain(&RepoPath::from_internal_string("dir")));
+        assert!(filter.might_contain(&RepoPath::from_internal_string("dir/sub")));
+        assert!(filter.might_contain(&RepoPath::root()));
+        // These could be false positives, but aren't with this hash function
+        assert!(!filter.might_contain(&RepoPath::from_internal_string("dir/file")));
+        assert!(!filter.might_contain(&RepoPath::from_internal_string("sub")));
+
+        // A commit that changed nothing contains nothing
+        let empty_filter = ChangedPathFilter::from_changed_files([]);
+        assert!(!empty_filter.might_contain(&file));
+
+        // A commit that changed very many files might contain anything
+        let files = (0..1000)
+            .map(|i| RepoPath::from_internal_string(&format!("file{i}")))
+            .collect::<Vec<_>>();
+        let large_filter = ChangedPathFilter::from_changed_files(&files);
+        assert!(large_filter.might_contain(&file));
+    }
+}
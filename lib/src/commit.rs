@@ -18,7 +18,7 @@ use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
 use crate::backend;
-use crate::backend::{ChangeId, CommitId, Signature, TreeId};
+use crate::backend::{ChangeId, CoAuthor, CommitId, CopyRecord, Signature, TreeId};
 use crate::repo_path::RepoPath;
 use crate::store::Store;
 use crate::tree::Tree;
@@ -131,6 +131,16 @@ impl Commit {
         &self.data.description
     }
 
+    pub fn co_authors(&self) -> &[CoAuthor] {
+        &self.data.co_authors
+    }
+
+    /// The copies recorded in this commit. See `copies::resolve_copies()` for
+    /// finding the ones that apply to its diff.
+    pub fn copies(&self) -> &[CopyRecord] {
+        &self.data.copies
+    }
+
     pub fn author(&self) -> &Signature {
         &self.data.author
     }
@@ -15,9 +15,11 @@
 use uuid::Uuid;
 
 use crate::backend;
-use crate::backend::{ChangeId, CommitId, Signature, TreeId};
+use crate::backend::{ChangeId, CoAuthor, CommitId, CopyRecord, Signature, TreeId};
+use crate::change_identity::CommitIdentity;
 use crate::commit::Commit;
 use crate::repo::MutableRepo;
+use crate::repo_path::RepoPath;
 use crate::settings::UserSettings;
 
 #[derive(Debug)]
@@ -36,7 +38,7 @@ impl CommitBuilder {
         parents: Vec<CommitId>,
         tree_id: TreeId,
     ) -> CommitBuilder {
-        let signature = settings.signature();
+        let signature = settings.mailmap().resolve(&settings.signature());
         assert!(!parents.is_empty());
         let commit = backend::Commit {
             parents,
@@ -47,6 +49,8 @@ impl CommitBuilder {
             author: signature.clone(),
             committer: signature,
             is_open: false,
+            copies: vec![],
+            co_authors: vec![],
         };
         CommitBuilder {
             commit,
@@ -57,7 +61,11 @@ impl CommitBuilder {
     pub fn for_rewrite_from(settings: &UserSettings, predecessor: &Commit) -> CommitBuilder {
         let mut commit = predecessor.store_commit().clone();
         commit.predecessors = vec![predecessor.id().clone()];
-        commit.committer = settings.signature();
+        let mailmap = settings.mailmap();
+        commit.committer = mailmap.resolve(&settings.signature());
+        // Rewritten commits get the canonical identity of their author, so old
+        // identities eventually disappear from the history.
+        commit.author = mailmap.resolve(&commit.author);
         // If the user had not configured a name and email before but now they have,
         // update the author fields with the new information.
         if commit.author.name == UserSettings::user_name_placeholder() {
@@ -77,7 +85,7 @@ impl CommitBuilder {
         parent_id: CommitId,
         tree_id: TreeId,
     ) -> CommitBuilder {
-        let signature = settings.signature();
+        let signature = settings.mailmap().resolve(&settings.signature());
         let commit = backend::Commit {
             parents: vec![parent_id],
             predecessors: vec![],
@@ -87,6 +95,8 @@ impl CommitBuilder {
             author: signature.clone(),
             committer: signature,
             is_open: true,
+            copies: vec![],
+            co_authors: vec![],
         };
         CommitBuilder {
             commit,
@@ -120,6 +130,15 @@ impl CommitBuilder {
         self
     }
 
+    /// Sets the change id to the one derived from the commit's identity (see
+    /// `CommitIdentity`), so importing the same commit into another repo gives
+    /// it the same change id. Call it after setting the other metadata.
+    pub fn derive_change_id(mut self, parent_identities: &[CommitIdentity]) -> Self {
+        self.commit.change_id =
+            CommitIdentity::compute(&self.commit, parent_identities).derived_change_id();
+        self
+    }
+
     pub fn set_description(mut self, description: String) -> Self {
         self.commit.description = description;
         self
@@ -140,6 +159,24 @@ impl CommitBuilder {
         self
     }
 
+    pub fn set_co_authors(mut self, co_authors: Vec<CoAuthor>) -> Self {
+        self.commit.co_authors = co_authors;
+        self
+    }
+
+    pub fn set_copies(mut self, copies: Vec<CopyRecord>) -> Self {
+        self.commit.copies = copies;
+        self
+    }
+
+    /// Records that `target` was copied from `source` in the parent. Replaces
+    /// any earlier record for `target`.
+    pub fn add_copy(mut self, source: RepoPath, target: RepoPath) -> Self {
+        self.commit.copies.retain(|copy| copy.target != target);
+        self.commit.copies.push(CopyRecord { source, target });
+        self
+    }
+
     pub fn write_to_repo(self, repo: &mut MutableRepo) -> Commit {
         let mut rewrite_source_id = None;
         if let Some(rewrite_source) = self.rewrite_source {
@@ -0,0 +1,100 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A table of the metadata of commits (parents, change id, author, committer,
+//! description, etc.) that have been read from the backend. Reading the
+//! metadata from the table is much cheaper than reading the commit from some
+//! backends, which matters when rendering many commits, such as in `jj log`.
+//!
+//! Commits are immutable, so the entries never need to be invalidated. New
+//! entries are written when the `CommitMetadataCache` is dropped.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use protobuf::Message;
+
+use crate::backend::{BackendResult, CommitId};
+use crate::commit::Commit;
+use crate::local_backend::{commit_from_proto, commit_to_proto};
+use crate::stacked_table::{TableSegment, TableStore, TableStoreResult};
+use crate::store::Store;
+
+pub struct CommitMetadataCache {
+    table_store: TableStore,
+    new_entries: Mutex<Vec<(CommitId, Vec<u8>)>>,
+}
+
+impl CommitMetadataCache {
+    /// Loads the cache from `dir`, which is created if it doesn't exist.
+    /// `key_size` is the length of the commit ids.
+    pub fn load(dir: PathBuf, key_size: usize) -> TableStoreResult<Self> {
+        let table_store = if dir.join("heads").is_dir() {
+            TableStore::load(dir, key_size)
+        } else {
+            std::fs::create_dir_all(&dir)?;
+            TableStore::init(dir, key_size)
+        };
+        Ok(CommitMetadataCache {
+            table_store,
+            new_entries: Mutex::new(vec![]),
+        })
+    }
+
+    /// Reads the commit from the cache if it's there, and otherwise from the
+    /// store, in which case it's added to the cache.
+    pub fn get_commit(&self, store: &Arc<Store>, id: &CommitId) -> BackendResult<Commit> {
+        let cached = self
+            .table_store
+            .get_head()
+            .ok()
+            .and_then(|table| table.get_value(id.as_bytes()).map(|value| value.to_vec()));
+        if let Some(proto) =
+            cached.and_then(|value| crate::protos::store::Commit::parse_from_bytes(&value).ok())
+        {
+            return Ok(Commit::new(
+                store.clone(),
+                id.clone(),
+                Arc::new(commit_from_proto(&proto)),
+            ));
+        }
+        let commit = store.get_commit(id)?;
+        let value = commit_to_proto(commit.store_commit())
+            .write_to_bytes()
+            .unwrap();
+        self.new_entries.lock().unwrap().push((id.clone(), value));
+        Ok(commit)
+    }
+
+    fn flush(&self) -> TableStoreResult<()> {
+        let new_entries = std::mem::take(&mut *self.new_entries.lock().unwrap());
+        if new_entries.is_empty() {
+            return Ok(());
+        }
+        let mut mut_table = self.table_store.get_head()?.start_mutation();
+        for (commit_id, value) in new_entries {
+            mut_table.add_entry(commit_id.to_bytes(), value);
+        }
+        self.table_store.save_table(mut_table)?;
+        Ok(())
+    }
+}
+
+impl Drop for CommitMetadataCache {
+    fn drop(&mut self) {
+        // The entries are only a cache, so it's fine if they can't be written
+        // (e.g. because the repo is read-only). They'll be read again.
+        self.flush().ok();
+    }
+}
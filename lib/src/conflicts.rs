@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::{Cursor, Write};
+use std::io::{Cursor, Read, Write};
 
 use itertools::Itertools;
 
@@ -153,6 +153,80 @@ pub fn materialize_conflict(
     let added_slices = added_content.iter().map(Vec::as_slice).collect_vec();
 
     let merge_result = files::merge(&removed_slices, &added_slices);
+    materialize_merge_result(merge_result, output)
+}
+
+/// Writes each side and base of a conflict in full (in the order side 1, base
+/// 1, side 2, ...) instead of merging them like `materialize_conflict()` does.
+/// File contents and symlink targets follow a header line describing the part.
+pub fn write_conflict_sides(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Conflict,
+    output: &mut dyn Write,
+) -> std::io::Result<()> {
+    let to_io_error = |err| std::io::Error::new(std::io::ErrorKind::Other, err);
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    writeln!(
+        output,
+        "Conflict with {} side{} and {} base{}:",
+        conflict.adds.len(),
+        plural(conflict.adds.len()),
+        conflict.removes.len(),
+        plural(conflict.removes.len())
+    )?;
+    let adds = conflict
+        .adds
+        .iter()
+        .enumerate()
+        .map(|(i, part)| (true, i, part));
+    let removes = conflict
+        .removes
+        .iter()
+        .enumerate()
+        .map(|(i, part)| (false, i, part));
+    for (is_add, i, part) in adds.interleave(removes) {
+        let (marker, name) = if is_add {
+            (CONFLICT_PLUS_LINE, "side")
+        } else {
+            (CONFLICT_MINUS_LINE, "base")
+        };
+        output.write_all(&marker[..marker.len() - 1])?;
+        writeln!(
+            output,
+            " {name} #{}: {}",
+            i + 1,
+            describe_conflict_part(part)
+        )?;
+        let mut content = match &part.value {
+            TreeValue::Normal { id, .. } => {
+                let mut content = vec![];
+                store
+                    .read_file(path, id)
+                    .map_err(to_io_error)?
+                    .read_to_end(&mut content)?;
+                content
+            }
+            TreeValue::Symlink(id) => store
+                .read_symlink(path, id)
+                .map_err(to_io_error)?
+                .into_bytes(),
+            _ => continue,
+        };
+        if !content.is_empty() && !content.ends_with(b"\n") {
+            content.push(b'\n');
+        }
+        output.write_all(&content)?;
+    }
+    Ok(())
+}
+
+/// Writes the result of a merge, with conflict markers around the conflicting
+/// hunks. `parse_conflict()` parses the output back into hunks.
+pub fn materialize_merge_result(
+    merge_result: MergeResult,
+    output: &mut dyn Write,
+) -> std::io::Result<()> {
     match merge_result {
         MergeResult::Resolved(content) => {
             output.write_all(&content)?;
@@ -0,0 +1,185 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A cache of file contents on local disk, keyed by file id, that files in the
+//! working copy can be cloned from.
+//!
+//! On filesystems that support reflinks (btrfs, XFS, APFS, ...), cloning a file
+//! only copies metadata and shares the data blocks copy-on-write, so checking
+//! out a file that's already in the cache doesn't need to read it from the
+//! backend or write its contents again. For the same reason, the cache takes
+//! little extra space as long as the working-copy files aren't modified. Once
+//! they are, old entries keep their own copies of the data, so the cache is
+//! pruned to a maximum size after each checkout.
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tempfile::NamedTempFile;
+use thiserror::Error;
+
+use crate::backend::{BackendError, BackendResult, FileId};
+use crate::repo_path::RepoPath;
+use crate::store::Store;
+
+#[derive(Debug, Error)]
+pub enum ContentCacheError {
+    #[error(transparent)]
+    BackendError(#[from] BackendError),
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+}
+
+#[derive(Debug)]
+pub struct ContentCache {
+    dir: PathBuf,
+    max_size: u64,
+    /// Cleared once cloning fails because the filesystem doesn't support it,
+    /// so we don't keep populating the cache for nothing.
+    supported: AtomicBool,
+}
+
+impl ContentCache {
+    /// Creates a cache in `dir` that `prune()` keeps below `max_size` bytes.
+    /// The directory is created if it doesn't exist.
+    pub fn new(dir: PathBuf, max_size: u64) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(ContentCache {
+            dir,
+            max_size,
+            supported: AtomicBool::new(true),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether cloning from the cache is still believed to work.
+    pub fn is_supported(&self) -> bool {
+        self.supported.load(Ordering::Relaxed)
+    }
+
+    fn entry_path(&self, id: &FileId) -> PathBuf {
+        self.dir.join(id.hex())
+    }
+
+    /// Returns the path of the cache file for `id`, writing it from the store
+    /// first if it's not already cached.
+    fn get_or_insert(&self, store: &Store, path: &RepoPath, id: &FileId) -> BackendResult<PathBuf> {
+        let entry_path = self.entry_path(id);
+        if entry_path.is_file() {
+            return Ok(entry_path);
+        }
+        let mut contents = store.read_file(path, id)?;
+        let mut write_entry = || -> io::Result<()> {
+            let mut temp_file = NamedTempFile::new_in(&self.dir)?;
+            io::copy(&mut contents, temp_file.as_file_mut())?;
+            temp_file.persist(&entry_path).map_err(|err| err.error)?;
+            Ok(())
+        };
+        if write_entry().is_err() {
+            // We can always fall back to writing the file directly.
+            self.supported.store(false, Ordering::Relaxed);
+        }
+        Ok(entry_path)
+    }
+
+    /// Creates a file at `disk_path` (which must not exist) with the contents
+    /// of `id` by cloning the cached copy. Returns the created file, or `None`
+    /// if cloning isn't supported, in which case nothing was created.
+    pub fn clone_to(
+        &self,
+        store: &Store,
+        path: &RepoPath,
+        id: &FileId,
+        disk_path: &Path,
+    ) -> Result<Option<File>, ContentCacheError> {
+        if !self.is_supported() {
+            return Ok(None);
+        }
+        let entry_path = self.get_or_insert(store, path, id)?;
+        if !self.is_supported() {
+            return Ok(None);
+        }
+        let maybe_file = clone_file(&entry_path, disk_path)?;
+        if maybe_file.is_none() {
+            self.supported.store(false, Ordering::Relaxed);
+        }
+        Ok(maybe_file)
+    }
+
+    /// Removes the oldest entries until the cache is no larger than its
+    /// maximum size.
+    pub fn prune(&self) -> io::Result<()> {
+        let mut entries = vec![];
+        let mut total_size = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            total_size += metadata.len();
+            entries.push((metadata.modified()?, metadata.len(), entry.path()));
+        }
+        entries.sort();
+        for (_modified, size, path) in entries {
+            if total_size <= self.max_size {
+                break;
+            }
+            fs::remove_file(path)?;
+            total_size -= size;
+        }
+        Ok(())
+    }
+}
+
+/// Creates `dst` as a reflink of `src`. Returns `Ok(None)` without leaving
+/// anything behind if the platform or filesystem doesn't support reflinks.
+/// Fails if `dst` already exists.
+#[cfg(target_os = "linux")]
+pub fn clone_file(src: &Path, dst: &Path) -> io::Result<Option<File>> {
+    use std::os::unix::io::AsRawFd;
+
+    // _IOW(0x94, 9, int) from <linux/fs.h>
+    const FICLONE: u32 = 0x4004_9409;
+
+    let src_file = File::open(src)?;
+    let dst_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dst)?;
+    // SAFETY: Both file descriptors are valid for the duration of the call.
+    let result = unsafe { libc::ioctl(dst_file.as_raw_fd(), FICLONE as _, src_file.as_raw_fd()) };
+    if result == 0 {
+        return Ok(Some(dst_file));
+    }
+    let err = io::Error::last_os_error();
+    drop(dst_file);
+    fs::remove_file(dst)?;
+    match err.raw_os_error() {
+        // Not supported by the filesystem, or the files are on different
+        // filesystems
+        Some(libc::EOPNOTSUPP | libc::EXDEV | libc::EINVAL | libc::ENOTTY) => Ok(None),
+        _ => Err(err),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn clone_file(_src: &Path, _dst: &Path) -> io::Result<Option<File>> {
+    // TODO: Use clonefile() on macOS and FSCTL_DUPLICATE_EXTENTS_TO_FILE on
+    // Windows.
+    Ok(None)
+}
@@ -0,0 +1,257 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Filters that transform file contents between the working copy and the
+//! store, like Git's `filter.<driver>.clean` and `.smudge` commands.
+//!
+//! The "clean" command runs when a file is snapshotted and the "smudge" command
+//! runs when it's checked out. Both receive the contents on stdin and write the
+//! transformed contents to stdout. This can be used e.g. for keyword expansion,
+//! decrypting secrets in the working copy, or replacing large files by pointers
+//! in the store.
+
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::matchers::FilePattern;
+use crate::repo_path::RepoPath;
+use crate::settings::UserSettings;
+
+#[derive(Debug, Error)]
+pub enum ContentFilterError {
+    #[error("Invalid filter config: {0}")]
+    InvalidConfig(String),
+    #[error("Filter '{name}' failed to {direction} {path}{}", format_stderr(.stderr))]
+    Failed {
+        name: String,
+        direction: &'static str,
+        path: String,
+        stderr: String,
+    },
+    #[error("Failed to run the {direction} command of filter '{name}': {err}")]
+    IoError {
+        name: String,
+        direction: &'static str,
+        #[source]
+        err: io::Error,
+    },
+}
+
+fn format_stderr(stderr: &str) -> String {
+    let stderr = stderr.trim_end();
+    if stderr.is_empty() {
+        String::new()
+    } else {
+        format!(":\n{stderr}")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ContentFilter {
+    pub name: String,
+    pub patterns: Vec<FilePattern>,
+    /// Program and arguments run on snapshot. `$path` is replaced by the path
+    /// of the file.
+    pub clean: Option<Vec<String>>,
+    /// Program and arguments run on checkout. `$path` is replaced by the path
+    /// of the file.
+    pub smudge: Option<Vec<String>>,
+}
+
+/// The filters configured for a working copy. The first filter (ordered by
+/// name) whose patterns match a path is the one that applies to it.
+#[derive(Clone, Debug)]
+pub struct ContentFilters {
+    working_copy_path: PathBuf,
+    filters: Vec<ContentFilter>,
+}
+
+impl ContentFilters {
+    pub fn new(working_copy_path: PathBuf, filters: Vec<ContentFilter>) -> Self {
+        ContentFilters {
+            working_copy_path,
+            filters,
+        }
+    }
+
+    /// Reads the filters configured in `[filters.<name>]` tables. Each of them
+    /// has a list of glob `patterns` and optional `clean` and `smudge`
+    /// commands (lists of strings). Returns `None` if there are none.
+    pub fn from_settings(
+        settings: &UserSettings,
+        working_copy_path: &Path,
+    ) -> Result<Option<ContentFilters>, ContentFilterError> {
+        let table = match settings.config().get_table("filters") {
+            Ok(table) => table,
+            Err(config::ConfigError::NotFound(_)) => return Ok(None),
+            Err(err) => return Err(ContentFilterError::InvalidConfig(err.to_string())),
+        };
+        let mut filters = vec![];
+        for (name, value) in table.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+            let key = format!("filters.{name}");
+            let invalid = |err: config::ConfigError| {
+                ContentFilterError::InvalidConfig(format!("{key}: {err}"))
+            };
+            let string_list = |value: config::Value| {
+                value
+                    .into_array()
+                    .map_err(invalid)?
+                    .into_iter()
+                    .map(|item| item.into_string())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(invalid)
+            };
+            let mut table = value.into_table().map_err(invalid)?;
+            let patterns = string_list(table.remove("patterns").ok_or_else(|| {
+                ContentFilterError::InvalidConfig(format!("{key}: missing `patterns`"))
+            })?)?;
+            let mut command = |direction: &str| -> Result<Option<Vec<String>>, ContentFilterError> {
+                match table.remove(direction) {
+                    None => Ok(None),
+                    Some(value) => {
+                        let command = string_list(value)?;
+                        if command.is_empty() {
+                            return Err(ContentFilterError::InvalidConfig(format!(
+                                "{key}: `{direction}` must not be empty"
+                            )));
+                        }
+                        Ok(Some(command))
+                    }
+                }
+            };
+            let clean = command("clean")?;
+            let smudge = command("smudge")?;
+            filters.push(ContentFilter {
+                name,
+                patterns: patterns.iter().map(|glob| FilePattern::new(glob)).collect(),
+                clean,
+                smudge,
+            });
+        }
+        if filters.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(ContentFilters::new(
+                working_copy_path.to_path_buf(),
+                filters,
+            )))
+        }
+    }
+
+    fn filter_for(&self, path: &RepoPath) -> Option<&ContentFilter> {
+        self.filters
+            .iter()
+            .find(|filter| filter.patterns.iter().any(|pattern| pattern.matches(path)))
+    }
+
+    /// Whether snapshotting `path` runs a clean command.
+    pub fn cleans(&self, path: &RepoPath) -> bool {
+        self.filter_for(path)
+            .map_or(false, |filter| filter.clean.is_some())
+    }
+
+    /// Whether checking out `path` runs a smudge command.
+    pub fn smudges(&self, path: &RepoPath) -> bool {
+        self.filter_for(path)
+            .map_or(false, |filter| filter.smudge.is_some())
+    }
+
+    /// Transforms the working-copy contents of `path` into the contents to
+    /// store. Returns the contents unchanged if no clean command applies.
+    pub fn clean(&self, path: &RepoPath, contents: Vec<u8>) -> Result<Vec<u8>, ContentFilterError> {
+        match self.filter_for(path) {
+            Some(ContentFilter {
+                name,
+                clean: Some(command),
+                ..
+            }) => self.run(name, "clean", command, path, contents),
+            _ => Ok(contents),
+        }
+    }
+
+    /// Transforms the stored contents of `path` into the contents to write to
+    /// the working copy. Returns the contents unchanged if no smudge command
+    /// applies.
+    pub fn smudge(
+        &self,
+        path: &RepoPath,
+        contents: Vec<u8>,
+    ) -> Result<Vec<u8>, ContentFilterError> {
+        match self.filter_for(path) {
+            Some(ContentFilter {
+                name,
+                smudge: Some(command),
+                ..
+            }) => self.run(name, "smudge", command, path, contents),
+            _ => Ok(contents),
+        }
+    }
+
+    fn run(
+        &self,
+        name: &str,
+        direction: &'static str,
+        command: &[String],
+        path: &RepoPath,
+        contents: Vec<u8>,
+    ) -> Result<Vec<u8>, ContentFilterError> {
+        let path_str = path.to_internal_file_string();
+        let io_error = |err| ContentFilterError::IoError {
+            name: name.to_string(),
+            direction,
+            err,
+        };
+        let args = command
+            .iter()
+            .map(|arg| arg.replace("$path", &path_str))
+            .collect_vec();
+        let mut child = Command::new(&args[0])
+            .args(&args[1..])
+            .current_dir(&self.working_copy_path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(io_error)?;
+        // Write on a separate thread so a command that writes its output before
+        // having read all of its input doesn't deadlock with us.
+        let mut stdin = child.stdin.take().unwrap();
+        let stdin_thread = thread::spawn(move || stdin.write_all(&contents).ok());
+        let mut stderr = child.stderr.take().unwrap();
+        let stderr_thread = thread::spawn(move || {
+            let mut buf = vec![];
+            stderr.read_to_end(&mut buf).ok();
+            buf
+        });
+        let output = child.wait_with_output().map_err(io_error)?;
+        stdin_thread.join().ok();
+        let stderr = stderr_thread.join().unwrap_or_default();
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(ContentFilterError::Failed {
+                name: name.to_string(),
+                direction,
+                path: path_str,
+                stderr: String::from_utf8_lossy(&stderr).into_owned(),
+            })
+        }
+    }
+}
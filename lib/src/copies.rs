@@ -0,0 +1,70 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Copy and rename tracking based on the copies recorded in commits (instead of
+//! on guessing from the similarity of file contents).
+
+use crate::backend::{CopyRecord, TreeValue};
+use crate::repo_path::RepoPath;
+use crate::tree::Tree;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CopyOperation {
+    /// The source still exists after the copy.
+    Copy,
+    /// The source was removed.
+    Rename,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CopiedPath {
+    pub source: RepoPath,
+    pub target: RepoPath,
+    pub operation: CopyOperation,
+}
+
+fn is_file_value(value: &TreeValue) -> bool {
+    matches!(value, TreeValue::Normal { .. } | TreeValue::Symlink(_))
+}
+
+/// Finds the recorded `copies` that apply to the diff from `from_tree` to
+/// `to_tree`. A record applies if its target was added in the diff and its
+/// source is a file in `from_tree`. Records that don't apply (e.g. because
+/// the commit was rebased onto a tree without the source) are ignored.
+pub fn resolve_copies(from_tree: &Tree, to_tree: &Tree, copies: &[CopyRecord]) -> Vec<CopiedPath> {
+    let mut resolved = vec![];
+    for copy in copies {
+        let target_added = from_tree.path_value(&copy.target).is_none()
+            && to_tree
+                .path_value(&copy.target)
+                .map_or(false, |value| is_file_value(&value));
+        let source_existed = from_tree
+            .path_value(&copy.source)
+            .map_or(false, |value| is_file_value(&value));
+        if !target_added || !source_existed {
+            continue;
+        }
+        let operation = if to_tree.path_value(&copy.source).is_some() {
+            CopyOperation::Copy
+        } else {
+            CopyOperation::Rename
+        };
+        resolved.push(CopiedPath {
+            source: copy.source.clone(),
+            target: copy.target.clone(),
+            operation,
+        });
+    }
+    resolved
+}
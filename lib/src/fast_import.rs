@@ -0,0 +1,659 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for the stream format used by `git fast-export` and
+//! `git fast-import`, which many version control tools can read and write.
+//!
+//! Only the parts of the format that are needed for plain history are
+//! supported: blobs, commits with file modifications and deletions, resets
+//! and lightweight tags.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::io::{BufRead, Read, Write};
+
+use thiserror::Error;
+
+use crate::backend::{BackendError, CommitId, MillisSinceEpoch, Signature, Timestamp, TreeValue};
+use crate::change_identity::CommitIdentities;
+use crate::commit::Commit;
+use crate::commit_builder::CommitBuilder;
+use crate::conflicts::materialize_conflict;
+use crate::matchers::EverythingMatcher;
+use crate::op_store::RefTarget;
+use crate::repo::{MutableRepo, RepoRef};
+use crate::repo_path::RepoPath;
+use crate::settings::UserSettings;
+use crate::tree::Diff;
+use crate::tree_builder::TreeBuilder;
+
+const NO_GC_REF_NAMESPACE: &str = "refs/jj/keep/";
+
+/// Writes the commits as a fast-import stream. Commits are emitted parents
+/// first. Parents that are not among the commits are referred to by their
+/// commit ID (except for the root commit, which is omitted), so the stream can
+/// only be imported into a repo that has them.
+///
+/// Each commit is written to the first branch pointing to it or to one of its
+/// descendants among the commits, or to `refs/jj/keep/<commit id>` if there
+/// is no such branch. Branches pointing to exported commits are reset to them
+/// at the end of the stream.
+pub fn export_commits(
+    repo: RepoRef,
+    commits: &[Commit],
+    output: &mut dyn Write,
+) -> Result<(), FastExportError> {
+    let store = repo.store();
+    let index = repo.index();
+    let mut commits = commits.to_vec();
+    commits.retain(|commit| commit.id() != store.root_commit_id());
+    commits.sort_by_key(|commit| index.commit_id_to_pos(commit.id()));
+    commits.dedup_by(|a, b| a.id() == b.id());
+
+    let mut branches_by_commit: HashMap<&CommitId, Vec<&str>> = HashMap::new();
+    for (name, target) in repo.view().branches() {
+        if let Some(RefTarget::Normal(id)) = &target.local_target {
+            branches_by_commit.entry(id).or_default().push(name);
+        }
+    }
+    let commit_ids: HashSet<_> = commits.iter().map(|commit| commit.id().clone()).collect();
+    let mut ref_names: HashMap<CommitId, String> = HashMap::new();
+    for commit in commits.iter().rev() {
+        let ref_name = match branches_by_commit.get(commit.id()) {
+            Some(branches) => format!("refs/heads/{}", branches[0]),
+            None => ref_names
+                .get(commit.id())
+                .cloned()
+                .unwrap_or_else(|| format!("{}{}", NO_GC_REF_NAMESPACE, commit.id().hex())),
+        };
+        for parent_id in commit.parent_ids() {
+            if commit_ids.contains(parent_id) && !ref_names.contains_key(parent_id) {
+                ref_names.insert(parent_id.clone(), ref_name.clone());
+            }
+        }
+        ref_names.insert(commit.id().clone(), ref_name);
+    }
+
+    let mut marks: HashMap<CommitId, usize> = HashMap::new();
+    for commit in &commits {
+        let mark = marks.len() + 1;
+        let mut header = vec![];
+        writeln!(header, "commit {}", ref_names[commit.id()]).unwrap();
+        writeln!(header, "mark :{mark}").unwrap();
+        writeln!(header, "author {}", format_signature(commit.author())).unwrap();
+        writeln!(header, "committer {}", format_signature(commit.committer())).unwrap();
+        writeln!(header, "data {}", commit.description().len()).unwrap();
+        header.extend_from_slice(commit.description().as_bytes());
+        header.push(b'\n');
+        for (i, parent_id) in commit.parent_ids().iter().enumerate() {
+            if parent_id == store.root_commit_id() {
+                continue;
+            }
+            let command = if i == 0 { "from" } else { "merge" };
+            match marks.get(parent_id) {
+                Some(parent_mark) => writeln!(header, "{command} :{parent_mark}").unwrap(),
+                None => writeln!(header, "{command} {}", parent_id.hex()).unwrap(),
+            }
+        }
+        output.write_all(&header)?;
+
+        let parent_tree = match commit.parent_ids().first() {
+            Some(parent_id) if parent_id != store.root_commit_id() => {
+                store.get_commit(parent_id)?.tree()
+            }
+            _ => store.get_tree(&RepoPath::root(), store.empty_tree_id())?,
+        };
+        for (path, diff) in parent_tree.diff(&commit.tree(), &EverythingMatcher) {
+            let quoted_path = quote_path(&path.to_internal_file_string());
+            let value = match diff {
+                Diff::Removed(_) => {
+                    writeln!(output, "D {quoted_path}")?;
+                    continue;
+                }
+                Diff::Added(value) | Diff::Modified(_, value) => value,
+            };
+            let (mode, contents) = match value {
+                TreeValue::Normal { id, executable } => {
+                    let mut contents = vec![];
+                    store.read_file(&path, &id)?.read_to_end(&mut contents)?;
+                    (if executable { "100755" } else { "100644" }, contents)
+                }
+                TreeValue::Symlink(id) => ("120000", store.read_symlink(&path, &id)?.into_bytes()),
+                TreeValue::Conflict(id) => {
+                    let conflict = store.read_conflict(&path, &id)?;
+                    let mut contents = vec![];
+                    materialize_conflict(store, &path, &conflict, &mut contents)?;
+                    ("100644", contents)
+                }
+                TreeValue::GitSubmodule(id) => {
+                    writeln!(output, "M 160000 {} {quoted_path}", id.hex())?;
+                    continue;
+                }
+                TreeValue::Tree(_) => continue,
+            };
+            writeln!(output, "M {mode} inline {quoted_path}")?;
+            writeln!(output, "data {}", contents.len())?;
+            output.write_all(&contents)?;
+            writeln!(output)?;
+        }
+        writeln!(output)?;
+        marks.insert(commit.id().clone(), mark);
+    }
+
+    for (name, target) in repo.view().branches() {
+        if let Some(RefTarget::Normal(id)) = &target.local_target {
+            let ref_name = format!("refs/heads/{name}");
+            if let Some(mark) = marks.get(id) {
+                if ref_names[id] != ref_name {
+                    writeln!(output, "reset {ref_name}\nfrom :{mark}\n")?;
+                }
+            }
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
+
+fn format_signature(signature: &Signature) -> String {
+    let offset = signature.timestamp.tz_offset;
+    let sign = if offset < 0 { '-' } else { '+' };
+    format!(
+        "{} <{}> {} {}{:02}{:02}",
+        signature.name,
+        signature.email,
+        signature.timestamp.timestamp.0.div_euclid(1000),
+        sign,
+        offset.abs() / 60,
+        offset.abs() % 60
+    )
+}
+
+fn quote_path(path: &str) -> String {
+    if !path.starts_with('"') && !path.contains(['\n', '\\']) {
+        return path.to_string();
+    }
+    let mut quoted = String::from('"');
+    for c in path.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[derive(Debug, Error)]
+pub enum FastExportError {
+    #[error("Failed to write the stream: {0}")]
+    IoError(#[from] io::Error),
+    #[error("Unexpected backend error: {0}")]
+    BackendError(#[from] BackendError),
+}
+
+#[derive(Debug, Error)]
+pub enum FastImportError {
+    #[error("Line {line}: {message}")]
+    InvalidStream { line: usize, message: String },
+    #[error("Line {line}: Unsupported command: {command}")]
+    UnsupportedCommand { line: usize, command: String },
+    #[error("Failed to read the stream: {0}")]
+    IoError(#[from] io::Error),
+    #[error("Unexpected backend error: {0}")]
+    BackendError(#[from] BackendError),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FastImportStats {
+    pub imported_commits: usize,
+    pub updated_branches: usize,
+    pub updated_tags: usize,
+}
+
+/// Creates commits from a fast-import stream. Refs under `refs/heads/` become
+/// branches and refs under `refs/tags/` become tags. Other refs are ignored,
+/// but the commits written to them are still added to the repo.
+pub fn import_stream(
+    settings: &UserSettings,
+    mut_repo: &mut MutableRepo,
+    input: &mut dyn BufRead,
+) -> Result<FastImportStats, FastImportError> {
+    let mut importer = Importer {
+        reader: StreamReader {
+            input,
+            peeked: None,
+            line_number: 0,
+        },
+        blobs: HashMap::new(),
+        commit_marks: HashMap::new(),
+        ref_tips: BTreeMap::new(),
+        tags: BTreeMap::new(),
+        identities: CommitIdentities::new(),
+        stats: FastImportStats::default(),
+    };
+    while let Some(line) = importer.reader.next_line()? {
+        let (command, arg) = line.split_once(' ').unwrap_or((&line, ""));
+        match command {
+            "" => {}
+            "blob" => importer.read_blob()?,
+            "commit" => importer.read_commit(settings, mut_repo, arg)?,
+            "reset" => importer.read_reset(mut_repo, arg)?,
+            "tag" => importer.read_tag(mut_repo, arg)?,
+            "feature" | "option" | "progress" | "checkpoint" => {}
+            "done" => break,
+            _ => {
+                return Err(FastImportError::UnsupportedCommand {
+                    line: importer.reader.line_number,
+                    command: line,
+                })
+            }
+        }
+    }
+    let mut stats = importer.stats;
+    for (ref_name, commit_id) in importer.ref_tips {
+        if let Some(branch) = ref_name.strip_prefix("refs/heads/") {
+            mut_repo.set_local_branch(branch.to_string(), RefTarget::Normal(commit_id));
+            stats.updated_branches += 1;
+        } else if let Some(tag) = ref_name.strip_prefix("refs/tags/") {
+            importer.tags.insert(tag.to_string(), commit_id);
+        }
+    }
+    for (tag, commit_id) in importer.tags {
+        mut_repo.set_tag(tag, RefTarget::Normal(commit_id));
+        stats.updated_tags += 1;
+    }
+    Ok(stats)
+}
+
+struct StreamReader<'a> {
+    input: &'a mut dyn BufRead,
+    peeked: Option<String>,
+    line_number: usize,
+}
+
+impl StreamReader<'_> {
+    fn next_line(&mut self) -> io::Result<Option<String>> {
+        if let Some(line) = self.peeked.take() {
+            return Ok(Some(line));
+        }
+        let mut buf = vec![];
+        if self.input.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(None);
+        }
+        self.line_number += 1;
+        if buf.last() == Some(&b'\n') {
+            buf.pop();
+        }
+        let line = String::from_utf8_lossy(&buf).into_owned();
+        // Comments can appear anywhere a command can
+        if line.starts_with('#') {
+            return self.next_line();
+        }
+        Ok(Some(line))
+    }
+
+    /// Returns the next line if it starts with `prefix`, leaving it to be read
+    /// again otherwise.
+    fn next_line_if(&mut self, prefix: &str) -> io::Result<Option<String>> {
+        match self.next_line()? {
+            Some(line) if line.starts_with(prefix) => Ok(Some(line)),
+            line => {
+                self.peeked = line;
+                Ok(None)
+            }
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> FastImportError {
+        FastImportError::InvalidStream {
+            line: self.line_number,
+            message: message.into(),
+        }
+    }
+
+    /// Reads a `data` command in either the exact byte count format or the
+    /// delimited format.
+    fn read_data(&mut self) -> Result<Vec<u8>, FastImportError> {
+        let line = self
+            .next_line()?
+            .ok_or_else(|| self.error("Expected data"))?;
+        let arg = line
+            .strip_prefix("data ")
+            .ok_or_else(|| self.error(format!("Expected data, found: {line}")))?;
+        if let Some(delimiter) = arg.strip_prefix("<<") {
+            let mut data = vec![];
+            loop {
+                let mut buf = vec![];
+                if self.input.read_until(b'\n', &mut buf)? == 0 {
+                    return Err(self.error(format!("Missing data delimiter {delimiter}")));
+                }
+                self.line_number += 1;
+                if buf.strip_suffix(b"\n").unwrap_or(&buf) == delimiter.as_bytes() {
+                    return Ok(data);
+                }
+                data.extend(buf);
+            }
+        }
+        let len: usize = arg
+            .parse()
+            .map_err(|_| self.error(format!("Invalid data length: {arg}")))?;
+        let mut data = vec![0; len];
+        self.input.read_exact(&mut data)?;
+        self.line_number += data.iter().filter(|b| **b == b'\n').count();
+        // The data may be followed by an optional newline
+        if self.input.fill_buf()?.first() == Some(&b'\n') {
+            self.input.consume(1);
+            self.line_number += 1;
+        }
+        Ok(data)
+    }
+}
+
+struct Importer<'a> {
+    reader: StreamReader<'a>,
+    blobs: HashMap<String, Vec<u8>>,
+    commit_marks: HashMap<String, CommitId>,
+    ref_tips: BTreeMap<String, CommitId>,
+    tags: BTreeMap<String, CommitId>,
+    identities: CommitIdentities,
+    stats: FastImportStats,
+}
+
+impl Importer<'_> {
+    fn read_mark(&mut self) -> Result<Option<String>, FastImportError> {
+        Ok(self
+            .reader
+            .next_line_if("mark ")?
+            .map(|line| line["mark ".len()..].to_string()))
+    }
+
+    fn read_blob(&mut self) -> Result<(), FastImportError> {
+        let mark = self.read_mark()?;
+        self.reader.next_line_if("original-oid ")?;
+        let data = self.reader.read_data()?;
+        if let Some(mark) = mark {
+            self.blobs.insert(mark, data);
+        }
+        Ok(())
+    }
+
+    fn resolve_commit(
+        &self,
+        mut_repo: &MutableRepo,
+        commit_ish: &str,
+    ) -> Result<CommitId, FastImportError> {
+        if commit_ish.starts_with(':') {
+            return self
+                .commit_marks
+                .get(commit_ish)
+                .cloned()
+                .ok_or_else(|| self.reader.error(format!("Unknown mark {commit_ish}")));
+        }
+        if let Some(commit_id) = self.ref_tips.get(commit_ish) {
+            return Ok(commit_id.clone());
+        }
+        if commit_ish.len() % 2 == 0 && hex::decode(commit_ish).is_ok() {
+            let commit_id = CommitId::from_hex(commit_ish);
+            if mut_repo.index().has_id(&commit_id) {
+                return Ok(commit_id);
+            }
+        }
+        Err(self.reader.error(format!("Unknown commit {commit_ish}")))
+    }
+
+    fn read_commit(
+        &mut self,
+        settings: &UserSettings,
+        mut_repo: &mut MutableRepo,
+        ref_name: &str,
+    ) -> Result<(), FastImportError> {
+        let mark = self.read_mark()?;
+        self.reader.next_line_if("original-oid ")?;
+        let author = match self.reader.next_line_if("author ")? {
+            Some(line) => Some(self.parse_signature(&line["author ".len()..])?),
+            None => None,
+        };
+        let committer = match self.reader.next_line_if("committer ")? {
+            Some(line) => self.parse_signature(&line["committer ".len()..])?,
+            None => return Err(self.reader.error("Expected committer")),
+        };
+        self.reader.next_line_if("encoding ")?;
+        let description = String::from_utf8_lossy(&self.reader.read_data()?).into_owned();
+
+        let mut parent_ids = vec![];
+        if let Some(line) = self.reader.next_line_if("from ")? {
+            parent_ids.push(self.resolve_commit(mut_repo, &line["from ".len()..])?);
+        } else if let Some(commit_id) = self.ref_tips.get(ref_name) {
+            // Like in Git, a commit without a `from` continues the ref's history
+            parent_ids.push(commit_id.clone());
+        }
+        while let Some(line) = self.reader.next_line_if("merge ")? {
+            if parent_ids.is_empty() {
+                return Err(self.reader.error("merge without from"));
+            }
+            parent_ids.push(self.resolve_commit(mut_repo, &line["merge ".len()..])?);
+        }
+
+        let store = mut_repo.store().clone();
+        let base_tree_id = match parent_ids.first() {
+            Some(parent_id) => store.get_commit(parent_id)?.tree_id().clone(),
+            None => {
+                parent_ids.push(store.root_commit_id().clone());
+                store.empty_tree_id().clone()
+            }
+        };
+        let mut tree_builder = TreeBuilder::new(store.clone(), base_tree_id);
+        while let Some(line) = self.reader.next_line()? {
+            if line.is_empty() {
+                break;
+            }
+            let (command, arg) = line.split_once(' ').unwrap_or((&line, ""));
+            match command {
+                "M" => {
+                    let mut parts = arg.splitn(3, ' ');
+                    let (mode, data_ref, path) = match (parts.next(), parts.next(), parts.next()) {
+                        (Some(mode), Some(data_ref), Some(path)) => (mode, data_ref, path),
+                        _ => return Err(self.reader.error(format!("Invalid filemodify: {line}"))),
+                    };
+                    let path = RepoPath::from_internal_string(&self.unquote_path(path)?);
+                    if mode == "160000" {
+                        tree_builder
+                            .set(path, TreeValue::GitSubmodule(CommitId::from_hex(data_ref)));
+                        continue;
+                    }
+                    let contents = if data_ref == "inline" {
+                        self.reader.read_data()?
+                    } else {
+                        self.blobs
+                            .get(data_ref)
+                            .cloned()
+                            .ok_or_else(|| self.reader.error(format!("Unknown blob {data_ref}")))?
+                    };
+                    let value = match mode {
+                        "100644" | "644" | "100755" | "755" => TreeValue::Normal {
+                            id: store.write_file(&path, &mut contents.as_slice())?,
+                            executable: mode.ends_with("755"),
+                        },
+                        "120000" => TreeValue::Symlink(
+                            store.write_symlink(&path, &String::from_utf8_lossy(&contents))?,
+                        ),
+                        _ => return Err(self.reader.error(format!("Unsupported mode {mode}"))),
+                    };
+                    tree_builder.set(path, value);
+                }
+                "D" => {
+                    let path = RepoPath::from_internal_string(&self.unquote_path(arg)?);
+                    tree_builder.remove(path);
+                }
+                "deleteall" => {
+                    tree_builder = TreeBuilder::new(store.clone(), store.empty_tree_id().clone());
+                }
+                _ => {
+                    self.reader.peeked = Some(line);
+                    break;
+                }
+            }
+        }
+        let tree_id = tree_builder.write_tree();
+
+        let mailmap = settings.mailmap();
+        let committer = mailmap.resolve(&committer);
+        let author = author.map_or_else(|| committer.clone(), |author| mailmap.resolve(&author));
+        // Derive the change id from the commit's identity so importing the same
+        // stream again results in the same change ids
+        let parent_identities = parent_ids
+            .iter()
+            .map(|id| self.identities.get(&store, id))
+            .collect::<Result<Vec<_>, _>>()?;
+        let commit = CommitBuilder::for_new_commit(settings, parent_ids, tree_id)
+            .set_description(description)
+            .set_author(author)
+            .set_committer(committer)
+            .derive_change_id(&parent_identities)
+            .write_to_repo(mut_repo);
+        if let Some(mark) = mark {
+            self.commit_marks.insert(mark, commit.id().clone());
+        }
+        self.ref_tips
+            .insert(ref_name.to_string(), commit.id().clone());
+        self.stats.imported_commits += 1;
+        Ok(())
+    }
+
+    fn read_reset(
+        &mut self,
+        mut_repo: &MutableRepo,
+        ref_name: &str,
+    ) -> Result<(), FastImportError> {
+        match self.reader.next_line_if("from ")? {
+            Some(line) => {
+                let commit_id = self.resolve_commit(mut_repo, &line["from ".len()..])?;
+                self.ref_tips.insert(ref_name.to_string(), commit_id);
+            }
+            None => {
+                self.ref_tips.remove(ref_name);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_tag(&mut self, mut_repo: &MutableRepo, name: &str) -> Result<(), FastImportError> {
+        self.read_mark()?;
+        let line = self
+            .reader
+            .next_line_if("from ")?
+            .ok_or_else(|| self.reader.error("Expected from"))?;
+        let commit_id = self.resolve_commit(mut_repo, &line["from ".len()..])?;
+        self.reader.next_line_if("original-oid ")?;
+        self.reader.next_line_if("tagger ")?;
+        // Tag messages can't be represented, so the tag becomes a lightweight one
+        self.reader.read_data()?;
+        self.tags.insert(name.to_string(), commit_id);
+        Ok(())
+    }
+
+    /// Parses a signature in the "raw" date format: `Name <email> 1234 +0100`.
+    fn parse_signature(&self, value: &str) -> Result<Signature, FastImportError> {
+        let invalid = || self.reader.error(format!("Invalid signature: {value}"));
+        let (name, rest) = value.split_once('<').ok_or_else(invalid)?;
+        let (email, date) = rest.split_once('>').ok_or_else(invalid)?;
+        let (seconds, offset) = date.trim().split_once(' ').ok_or_else(invalid)?;
+        let seconds: i64 = seconds.parse().map_err(|_| invalid())?;
+        let (sign, offset) = match offset.split_at(1) {
+            ("+", offset) => (1, offset),
+            ("-", offset) => (-1, offset),
+            _ => return Err(invalid()),
+        };
+        if offset.len() != 4 {
+            return Err(invalid());
+        }
+        let hours: i32 = offset[..2].parse().map_err(|_| invalid())?;
+        let minutes: i32 = offset[2..].parse().map_err(|_| invalid())?;
+        Ok(Signature {
+            name: name.trim().to_string(),
+            email: email.to_string(),
+            timestamp: Timestamp {
+                timestamp: MillisSinceEpoch(seconds * 1000),
+                tz_offset: sign * (hours * 60 + minutes),
+            },
+        })
+    }
+
+    fn unquote_path(&self, path: &str) -> Result<String, FastImportError> {
+        let quoted = match path
+            .strip_prefix('"')
+            .and_then(|path| path.strip_suffix('"'))
+        {
+            Some(quoted) => quoted,
+            None => return Ok(path.to_string()),
+        };
+        let mut bytes = vec![];
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                let mut buf = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                continue;
+            }
+            match chars.next() {
+                Some('n') => bytes.push(b'\n'),
+                Some('t') => bytes.push(b'\t'),
+                Some('"') => bytes.push(b'"'),
+                Some('\\') => bytes.push(b'\\'),
+                Some(c) if c.is_digit(8) => {
+                    // Octal escapes are used for bytes of non-ASCII characters
+                    let digits: String = [Some(c), chars.next(), chars.next()]
+                        .into_iter()
+                        .flatten()
+                        .collect();
+                    let byte = u8::from_str_radix(&digits, 8)
+                        .map_err(|_| self.reader.error(format!("Invalid path: {path}")))?;
+                    bytes.push(byte);
+                }
+                _ => return Err(self.reader.error(format!("Invalid path: {path}"))),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| self.reader.error(format!("Invalid path: {path}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_signature() {
+        let signature = Signature {
+            name: "Some One".to_string(),
+            email: "some.one@example.com".to_string(),
+            timestamp: Timestamp {
+                timestamp: MillisSinceEpoch(1_000_000_500),
+                tz_offset: -90,
+            },
+        };
+        assert_eq!(
+            format_signature(&signature),
+            "Some One <some.one@example.com> 1000000 -0130"
+        );
+    }
+
+    #[test]
+    fn test_quote_path() {
+        assert_eq!(quote_path("dir/file"), "dir/file");
+        assert_eq!(quote_path("a\nb"), "\"a\\nb\"");
+        assert_eq!(quote_path("\"file"), "\"\\\"file\"");
+    }
+}
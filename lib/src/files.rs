@@ -204,11 +204,71 @@ struct SyncRegion {
     right: Range<usize>,
 }
 
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct MergeOptions {
+    /// Resolve conflicts where a side only changed indentation, trailing
+    /// whitespace, the amount of whitespace between words, or line endings by
+    /// taking the other side's change.
+    pub ignore_whitespace: bool,
+    /// Resolve conflicts by keeping the lines from all sides, in order.
+    pub union: bool,
+}
+
+/// Trims leading and trailing whitespace (including `\r`) from each line and
+/// collapses the remaining whitespace runs to a single space, so parts that
+/// only differ in indentation, spacing or line endings compare equal. Words
+/// that were joined or split still compare different.
+fn normalize_whitespace(part: &[u8]) -> Vec<u8> {
+    part.split(|b| *b == b'\n')
+        .map(|line| {
+            line.split(|b| b.is_ascii_whitespace())
+                .filter(|word| !word.is_empty())
+                .collect_vec()
+                .join(&b' ')
+        })
+        .collect_vec()
+        .join(&b'\n')
+}
+
+/// Resolves a conflicting hunk of a 3-way merge if one of the sides only
+/// changed whitespace, by taking the other side's change. If both sides only
+/// changed whitespace, takes the first one.
+fn resolve_whitespace_conflict<'a>(
+    removed_parts: &[&[u8]],
+    added_parts: &[&'a [u8]],
+) -> Option<&'a [u8]> {
+    if removed_parts.len() != 1 || added_parts.len() != 2 {
+        return None;
+    }
+    let normalized_base = normalize_whitespace(removed_parts[0]);
+    let changed_parts = added_parts
+        .iter()
+        .filter(|part| normalize_whitespace(part) != normalized_base)
+        .collect_vec();
+    match changed_parts.as_slice() {
+        [] => Some(added_parts[0]),
+        [first, rest @ ..] => {
+            let normalized_first = normalize_whitespace(first);
+            rest.iter()
+                .all(|part| normalize_whitespace(part) == normalized_first)
+                .then(|| **first)
+        }
+    }
+}
+
 // TODO: Should we require `add.len() == removes.len() + 1`? If that condition
 // is false, it effectively means that we should pretend that there are empty
 // strings in `removes` or `adds` to make it true. Maybe we should have to
 // caller make it explicitly that way.
 pub fn merge(removes: &[&[u8]], adds: &[&[u8]]) -> MergeResult {
+    merge_with_options(removes, adds, &MergeOptions::default())
+}
+
+pub fn merge_with_options(
+    removes: &[&[u8]],
+    adds: &[&[u8]],
+    options: &MergeOptions,
+) -> MergeResult {
     let num_removes = removes.len();
     // TODO: Using the first remove as base (first in the inputs) is how it's
     // usually done for 3-way conflicts. Are there better heuristics when there are
@@ -260,6 +320,16 @@ pub fn merge(removes: &[&[u8]], adds: &[&[u8]]) -> MergeResult {
                     // All sides made the same change, and there's a matching extra base to apply it
                     // to
                     resolved_hunk.extend(added_parts[0]);
+                } else if let Some(resolved) = options
+                    .ignore_whitespace
+                    .then(|| resolve_whitespace_conflict(&removed_parts, &added_parts))
+                    .flatten()
+                {
+                    resolved_hunk.extend(resolved);
+                } else if options.union {
+                    for added_part in added_parts {
+                        resolved_hunk.extend(added_part);
+                    }
                 } else {
                     if !resolved_hunk.is_empty() {
                         merge_hunks.push(MergeHunk::Resolved(resolved_hunk));
@@ -441,4 +511,74 @@ mod tests {
             }])
         );
     }
+
+    #[test]
+    fn test_merge_ignore_whitespace() {
+        let options = MergeOptions {
+            ignore_whitespace: true,
+            ..Default::default()
+        };
+        // One side reindented, the other side modified
+        assert_eq!(
+            merge_with_options(&[b"a\nb\n"], &[b"  a\n  b\n", b"a\nc\n"], &options),
+            MergeResult::Resolved(b"a\nc\n".to_vec())
+        );
+        // One side changed line endings, the other side modified
+        assert_eq!(
+            merge_with_options(&[b"a\nb\n"], &[b"a\nc\n", b"a\r\nb\r\n"], &options),
+            MergeResult::Resolved(b"a\nc\n".to_vec())
+        );
+        // Both sides only changed whitespace: the first side wins
+        assert_eq!(
+            merge_with_options(&[b"a\n"], &[b" a\n", b"a \n"], &options),
+            MergeResult::Resolved(b" a\n".to_vec())
+        );
+        // One side changed the spacing between words, the other side modified
+        assert_eq!(
+            merge_with_options(&[b"a b\nc\n"], &[b"a    b\nc\n", b"a b\nd\n"], &options),
+            MergeResult::Resolved(b"a b\nd\n".to_vec())
+        );
+        // Removing whitespace between words is a real change
+        assert_eq!(
+            merge_with_options(&[b"int x\n"], &[b"intx\n", b"int y\n"], &options),
+            MergeResult::Conflict(vec![MergeHunk::Conflict {
+                removes: vec![b"int x\n".to_vec()],
+                adds: vec![b"intx\n".to_vec(), b"int y\n".to_vec()]
+            }])
+        );
+        // Both sides modified
+        assert_eq!(
+            merge_with_options(&[b"a\n"], &[b" b\n", b"c\n"], &options),
+            MergeResult::Conflict(vec![MergeHunk::Conflict {
+                removes: vec![b"a\n".to_vec()],
+                adds: vec![b" b\n".to_vec(), b"c\n".to_vec()]
+            }])
+        );
+        // Without the option, whitespace changes conflict
+        assert_eq!(
+            merge(&[b"a\n"], &[b" a\n", b"c\n"]),
+            MergeResult::Conflict(vec![MergeHunk::Conflict {
+                removes: vec![b"a\n".to_vec()],
+                adds: vec![b" a\n".to_vec(), b"c\n".to_vec()]
+            }])
+        );
+    }
+
+    #[test]
+    fn test_merge_union() {
+        let options = MergeOptions {
+            union: true,
+            ..Default::default()
+        };
+        // Both sides added lines at the end
+        assert_eq!(
+            merge_with_options(&[b"a\n"], &[b"a\nb\n", b"a\nc\n"], &options),
+            MergeResult::Resolved(b"a\nb\nc\n".to_vec())
+        );
+        // Both sides modified the same line
+        assert_eq!(
+            merge_with_options(&[b"a\nb\n"], &[b"a\nc\n", b"a\nd\n"], &options),
+            MergeResult::Resolved(b"a\nc\nd\n".to_vec())
+        );
+    }
 }
@@ -0,0 +1,179 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Support for uploading changes for review to Gerrit.
+//!
+//! Gerrit identifies a change across patch sets by the `Change-Id:` trailer in
+//! the commit message. We derive it from the jj change id, so rewriting a
+//! commit in jj keeps it associated with the same Gerrit change. Which commit
+//! was last uploaded for each Change-Id is recorded in the backing Git repo
+//! under `refs/jj/gerrit/`.
+
+use std::collections::BTreeMap;
+
+use crate::backend::{ChangeId, CommitId};
+use crate::trailers::trailer_lines;
+
+/// The name of the trailer Gerrit uses to identify changes.
+pub const CHANGE_ID_TRAILER: &str = "Change-Id";
+
+/// Where the commit last uploaded for each Change-Id is recorded.
+pub const UPLOADED_REF_PREFIX: &str = "refs/jj/gerrit/";
+
+/// Returns the Gerrit Change-Id for a jj change id. Gerrit expects "I"
+/// followed by 40 hex digits; jj change ids are only 32 hex digits long, so
+/// they're prefixed by the hex encoding of "jjid".
+pub fn change_id_for(change_id: &ChangeId) -> String {
+    format!("I6a6a6964{}", change_id.hex())
+}
+
+/// Returns the value of the `Change-Id:` trailer in `description`, if there is
+/// one.
+pub fn find_change_id(description: &str) -> Option<&str> {
+    trailer_lines(description).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key == CHANGE_ID_TRAILER).then(|| value.trim())
+    })
+}
+
+/// Appends a `Change-Id:` trailer to `description` unless it already has one.
+/// The trailer is added to the trailer block at the end of the description if
+/// there is one, otherwise in a new paragraph.
+pub fn add_change_id_trailer(description: &str, change_id: &ChangeId) -> String {
+    if find_change_id(description).is_some() {
+        return description.to_string();
+    }
+    let trailer = format!("{CHANGE_ID_TRAILER}: {}\n", change_id_for(change_id));
+    let mut result = description.trim_end().to_string();
+    if result.is_empty() {
+        return trailer;
+    }
+    if trailer_lines(description).next().is_some() {
+        result.push('\n');
+    } else {
+        result.push_str("\n\n");
+    }
+    result.push_str(&trailer);
+    result
+}
+
+/// Returns the commits last uploaded to Gerrit, keyed by Change-Id.
+pub fn get_uploaded(
+    git_repo: &git2::Repository,
+) -> Result<BTreeMap<String, CommitId>, git2::Error> {
+    let mut uploaded = BTreeMap::new();
+    for git_ref in git_repo.references_glob(&format!("{UPLOADED_REF_PREFIX}*"))? {
+        let git_ref = git_ref?;
+        if let (Some(name), Some(oid)) = (git_ref.name(), git_ref.target()) {
+            let change_id = name.strip_prefix(UPLOADED_REF_PREFIX).unwrap();
+            uploaded.insert(change_id.to_string(), CommitId::from_bytes(oid.as_bytes()));
+        }
+    }
+    Ok(uploaded)
+}
+
+/// Records that `commit_id` was uploaded as the latest patch set of the change
+/// with `gerrit_change_id`.
+pub fn record_uploaded(
+    git_repo: &git2::Repository,
+    gerrit_change_id: &str,
+    commit_id: &CommitId,
+) -> Result<(), git2::Error> {
+    let oid = git2::Oid::from_bytes(commit_id.as_bytes())?;
+    git_repo.reference(
+        &format!("{UPLOADED_REF_PREFIX}{gerrit_change_id}"),
+        oid,
+        true,
+        "uploaded to gerrit",
+    )?;
+    Ok(())
+}
+
+/// The review state of a commit, as far as it's known locally.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReviewState {
+    /// The commit has no Change-Id, or its change was never uploaded.
+    NotUploaded,
+    /// The commit is the latest upload of its change.
+    Uploaded,
+    /// The change was uploaded, but the commit has been rewritten since.
+    NeedsUpload,
+}
+
+impl ReviewState {
+    pub fn for_commit(
+        uploaded: &BTreeMap<String, CommitId>,
+        commit_id: &CommitId,
+        description: &str,
+    ) -> Self {
+        let uploaded_id = find_change_id(description).and_then(|change_id| uploaded.get(change_id));
+        match uploaded_id {
+            None => ReviewState::NotUploaded,
+            Some(uploaded_id) if uploaded_id == commit_id => ReviewState::Uploaded,
+            Some(_) => ReviewState::NeedsUpload,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReviewState::NotUploaded => "",
+            ReviewState::Uploaded => "uploaded",
+            ReviewState::NeedsUpload => "needs-upload",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_change_id_trailer() {
+        let change_id = ChangeId::from_hex("0123456789abcdef0123456789abcdef");
+        let trailer = "Change-Id: I6a6a69640123456789abcdef0123456789abcdef\n";
+        assert_eq!(add_change_id_trailer("", &change_id), trailer);
+        assert_eq!(
+            add_change_id_trailer("subject\n", &change_id),
+            format!("subject\n\n{trailer}")
+        );
+        assert_eq!(
+            add_change_id_trailer("subject\n\nbody\n", &change_id),
+            format!("subject\n\nbody\n\n{trailer}")
+        );
+        // Added to an existing trailer block
+        assert_eq!(
+            add_change_id_trailer("subject\n\nBug: 123\n", &change_id),
+            format!("subject\n\nBug: 123\n{trailer}")
+        );
+        // A paragraph with other lines isn't a trailer block
+        assert_eq!(
+            add_change_id_trailer("subject\n\nNote: this\nand that\n", &change_id),
+            format!("subject\n\nNote: this\nand that\n\n{trailer}")
+        );
+        // An existing Change-Id is kept
+        let description = "subject\n\nChange-Id: Iabc\n";
+        assert_eq!(add_change_id_trailer(description, &change_id), description);
+    }
+
+    #[test]
+    fn test_find_change_id() {
+        assert_eq!(find_change_id(""), None);
+        assert_eq!(find_change_id("Change-Id: Iabc\n"), None);
+        assert_eq!(
+            find_change_id("subject\n\nbody\n\nBug: 1\nChange-Id: Iabc\n"),
+            Some("Iabc")
+        );
+        assert_eq!(find_change_id("subject\n\nChange-Id: Iabc\n\nbody\n"), None);
+    }
+}
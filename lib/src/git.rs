@@ -12,17 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cell::Cell;
 use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fs::OpenOptions;
 use std::io::{Read, Write};
+use std::process::Command;
 use std::sync::Arc;
 
-use git2::{Oid, RemoteCallbacks};
+use git2::Oid;
 use itertools::Itertools;
 use thiserror::Error;
 
-use crate::backend::CommitId;
+use crate::backend::{CommitId, Signature};
 use crate::commit::Commit;
+use crate::git_backend::signature_to_git;
 use crate::op_store::{OperationId, RefTarget};
 use crate::operation::Operation;
 use crate::repo::{MutableRepo, ReadonlyRepo, RepoRef};
@@ -111,8 +114,17 @@ pub fn import_refs(
             // If a git remote-tracking branch changed, apply the change to the local branch
             // as well
             if let RefName::RemoteBranch { branch, remote: _ } = ref_name {
+                let local_ref_name = RefName::LocalBranch(branch);
+                // If the branch was deleted on the remote but moved locally, keep the local
+                // branch instead of making it conflicted. The remote-tracking branch is
+                // still deleted, so pushing the branch will create it again.
+                if new_git_target.is_none()
+                    && mut_repo.view().get_ref(&local_ref_name) != old_git_target
+                {
+                    continue;
+                }
                 mut_repo.merge_single_ref(
-                    &RefName::LocalBranch(branch),
+                    &local_ref_name,
                     old_git_target.as_ref(),
                     new_git_target.as_ref(),
                 );
@@ -122,7 +134,16 @@ pub fn import_refs(
 
     // Find commits that are no longer referenced in the git repo and abandon them
     // in jj as well.
-    let new_git_heads = new_git_heads.into_iter().collect_vec();
+    let mut new_git_heads = new_git_heads.into_iter().collect_vec();
+    // Keep commits that local branches still point to, e.g. because they were
+    // moved locally after being deleted on the remote. Also never abandon the
+    // root commit, which is an ancestor of the old heads if no refs are left.
+    for branch_target in mut_repo.view().branches().values() {
+        if let Some(local_target) = &branch_target.local_target {
+            new_git_heads.extend(local_target.adds());
+        }
+    }
+    new_git_heads.push(store.root_commit_id().clone());
     // We could use mut_repo.record_rewrites() here but we know we only need to care
     // about abandoned commits for now. We may want to change this if we ever
     // add a way of preserving change IDs across rewrites by `git` (e.g. by
@@ -258,19 +279,152 @@ pub fn export_refs(
     Ok(())
 }
 
+#[derive(Error, Debug, PartialEq)]
+pub enum GitRemoteManagementError {
+    #[error("No git remote named '{0}'")]
+    NoSuchRemote(String),
+    #[error("Git remote named '{0}' already exists")]
+    RemoteAlreadyExists(String),
+    #[error("Unexpected git error when managing remotes: {0}")]
+    InternalGitError(#[from] git2::Error),
+}
+
+/// Renames the remote in the Git repo, and its remote-tracking branches in
+/// both the Git repo and the Jujutsu repo.
+pub fn rename_remote(
+    mut_repo: &mut MutableRepo,
+    git_repo: &git2::Repository,
+    old_remote_name: &str,
+    new_remote_name: &str,
+) -> Result<(), GitRemoteManagementError> {
+    if git_repo.find_remote(old_remote_name).is_err() {
+        return Err(GitRemoteManagementError::NoSuchRemote(
+            old_remote_name.to_string(),
+        ));
+    }
+    if git_repo.find_remote(new_remote_name).is_ok() {
+        return Err(GitRemoteManagementError::RemoteAlreadyExists(
+            new_remote_name.to_string(),
+        ));
+    }
+    // Refspecs that aren't the default can't be renamed automatically. They're
+    // left as they are, like `git remote rename` does.
+    git_repo.remote_rename(old_remote_name, new_remote_name)?;
+
+    let branches = mut_repo.view().branches().clone();
+    for (branch, target) in branches {
+        if let Some(remote_target) = target.remote_targets.get(old_remote_name) {
+            mut_repo.set_remote_branch(
+                branch.clone(),
+                new_remote_name.to_string(),
+                remote_target.clone(),
+            );
+            mut_repo.remove_remote_branch(&branch, old_remote_name);
+        }
+    }
+    let old_prefix = format!("refs/remotes/{old_remote_name}/");
+    let git_refs = mut_repo.view().git_refs().clone();
+    for (full_name, target) in git_refs {
+        if let Some(branch) = full_name.strip_prefix(&old_prefix) {
+            mut_repo.set_git_ref(format!("refs/remotes/{new_remote_name}/{branch}"), target);
+            mut_repo.remove_git_ref(&full_name);
+        }
+    }
+    Ok(())
+}
+
+/// The ref where Git stores notes by default.
+pub const DEFAULT_NOTES_REF: &str = "refs/notes/commits";
+
+/// Returns the note attached to the commit in `notes_ref`, if any.
+pub fn get_note(
+    git_repo: &git2::Repository,
+    notes_ref: &str,
+    commit_id: &CommitId,
+) -> Result<Option<String>, git2::Error> {
+    let oid = Oid::from_bytes(commit_id.as_bytes()).unwrap();
+    match git_repo.find_note(Some(notes_ref), oid) {
+        Ok(note) => Ok(Some(
+            String::from_utf8_lossy(note.message_bytes()).into_owned(),
+        )),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns all notes in `notes_ref`, keyed by the commit they're attached to.
+pub fn get_notes(
+    git_repo: &git2::Repository,
+    notes_ref: &str,
+) -> Result<BTreeMap<CommitId, String>, git2::Error> {
+    let mut notes = BTreeMap::new();
+    let iter = match git_repo.notes(Some(notes_ref)) {
+        Ok(iter) => iter,
+        Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(notes),
+        Err(err) => return Err(err),
+    };
+    for entry in iter {
+        let (_note_id, annotated_id) = entry?;
+        let commit_id = CommitId::from_bytes(annotated_id.as_bytes());
+        if let Some(note) = get_note(git_repo, notes_ref, &commit_id)? {
+            notes.insert(commit_id, note);
+        }
+    }
+    Ok(notes)
+}
+
+/// Attaches a note to the commit in `notes_ref`. Fails if the commit already
+/// has a note unless `force` is set.
+pub fn set_note(
+    git_repo: &git2::Repository,
+    notes_ref: &str,
+    commit_id: &CommitId,
+    message: &str,
+    signature: &Signature,
+    force: bool,
+) -> Result<(), git2::Error> {
+    let oid = Oid::from_bytes(commit_id.as_bytes()).unwrap();
+    let signature = signature_to_git(signature);
+    git_repo.note(&signature, &signature, Some(notes_ref), oid, message, force)?;
+    Ok(())
+}
+
+/// Removes the note attached to the commit in `notes_ref`. Returns `false` if
+/// there was no note.
+pub fn remove_note(
+    git_repo: &git2::Repository,
+    notes_ref: &str,
+    commit_id: &CommitId,
+    signature: &Signature,
+) -> Result<bool, git2::Error> {
+    let oid = Oid::from_bytes(commit_id.as_bytes()).unwrap();
+    let signature = signature_to_git(signature);
+    match git_repo.note_delete(oid, Some(notes_ref), &signature, &signature) {
+        Ok(()) => Ok(true),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
 #[derive(Error, Debug, PartialEq)]
 pub enum GitFetchError {
     #[error("No git remote named '{0}'")]
     NoSuchRemote(String),
+    #[error("The fetch would download {total} objects, more than the limit of {limit}")]
+    TooManyObjects { total: usize, limit: usize },
     // TODO: I'm sure there are other errors possible, such as transport-level errors.
     #[error("Unexpected git error when fetching: {0}")]
     InternalGitError(#[from] git2::Error),
 }
 
+/// Fetches from the remote and imports the refs. If `prune` is set,
+/// remote-tracking branches that no longer exist on the remote are deleted.
 pub fn fetch(
     mut_repo: &mut MutableRepo,
     git_repo: &git2::Repository,
     remote_name: &str,
+    prune: bool,
+    callbacks: RemoteCallbacks<'_>,
 ) -> Result<Option<String>, GitFetchError> {
     let mut remote =
         git_repo
@@ -288,12 +442,41 @@ pub fn fetch(
     let mut proxy_options = git2::ProxyOptions::new();
     proxy_options.auto();
     fetch_options.proxy_options(proxy_options);
-    let callbacks = create_remote_callbacks();
-    fetch_options.remote_callbacks(callbacks);
+    let mut git_callbacks = create_remote_callbacks();
+    let max_objects = callbacks.max_objects;
+    let too_many_objects = Cell::new(None);
+    if callbacks.progress.is_some() || max_objects.is_some() {
+        let mut progress_cb = callbacks.progress;
+        let too_many_objects = &too_many_objects;
+        git_callbacks.transfer_progress(move |progress| {
+            if let Some(limit) = max_objects {
+                if progress.total_objects() > limit {
+                    too_many_objects.set(Some(progress.total_objects()));
+                    return false;
+                }
+            }
+            if let Some(progress_cb) = &mut progress_cb {
+                progress_cb(&Progress {
+                    objects: progress.received_objects(),
+                    total_objects: progress.total_objects(),
+                    bytes: progress.received_bytes(),
+                });
+            }
+            true
+        });
+    }
+    fetch_options.remote_callbacks(git_callbacks);
     let refspec: &[&str] = &[];
-    remote.download(refspec, Some(&mut fetch_options))?;
+    let download_result = remote.download(refspec, Some(&mut fetch_options));
+    drop(fetch_options);
+    if let (Some(total), Some(limit)) = (too_many_objects.get(), max_objects) {
+        return Err(GitFetchError::TooManyObjects { total, limit });
+    }
+    download_result?;
     remote.update_tips(None, false, git2::AutotagOption::Unspecified, None)?;
-    remote.prune(None)?;
+    if prune {
+        remote.prune(None)?;
+    }
     // TODO: We could make it optional to get the default branch since we only care
     // about it on clone.
     let mut default_branch = None;
@@ -321,6 +504,8 @@ pub enum GitPushError {
     NotFastForward,
     #[error("Remote rejected the update of some refs (do you have permission to push to {0:?}?)")]
     RefUpdateRejected(Vec<String>),
+    #[error("Failed to run `git push`: {0}")]
+    GitCommandFailed(String),
     // TODO: I'm sure there are other errors possible, such as transport-level errors,
     // and errors caused by the remote rejecting the push.
     #[error("Unexpected git error when pushing: {0}")]
@@ -345,6 +530,7 @@ pub fn push_commit(
             force,
             new_target: Some(target.id().clone()),
         }],
+        RemoteCallbacks::default(),
     )
 }
 
@@ -361,6 +547,7 @@ pub fn push_updates(
     git_repo: &git2::Repository,
     remote_name: &str,
     updates: &[GitRefUpdate],
+    callbacks: RemoteCallbacks<'_>,
 ) -> Result<(), GitPushError> {
     let mut temp_refs = vec![];
     let mut qualified_remote_refs = vec![];
@@ -386,7 +573,13 @@ pub fn push_updates(
             refspecs.push(format!(":{}", update.qualified_name));
         }
     }
-    let result = push_refs(git_repo, remote_name, &qualified_remote_refs, &refspecs);
+    let result = push_refs(
+        git_repo,
+        remote_name,
+        &qualified_remote_refs,
+        &refspecs,
+        callbacks,
+    );
     for mut temp_ref in temp_refs {
         // TODO: Figure out how to do the equivalent of absl::Cleanup for
         // temp_ref.delete().
@@ -402,11 +595,87 @@ pub fn push_updates(
     result
 }
 
+/// Whether to send a push certificate when pushing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignedPush {
+    /// Always send one, failing if the remote doesn't support them.
+    Yes,
+    /// Send one only if the remote supports them.
+    IfAsked,
+}
+
+/// Like `push_updates()`, but sends a signed push certificate, which the remote
+/// can record for auditing. libgit2 doesn't support push certificates, so this
+/// runs `git push --signed`, which signs them using Git's signing configuration
+/// (`user.signingKey`, `gpg.format`, etc.).
+pub fn push_updates_signed(
+    git_repo: &git2::Repository,
+    remote_name: &str,
+    updates: &[GitRefUpdate],
+    signed: SignedPush,
+) -> Result<(), GitPushError> {
+    if let Err(err) = git_repo.find_remote(remote_name) {
+        return Err(match err.code() {
+            git2::ErrorCode::NotFound | git2::ErrorCode::InvalidSpec => {
+                GitPushError::NoSuchRemote(remote_name.to_string())
+            }
+            _ => GitPushError::InternalGitError(err),
+        });
+    }
+    let mut command = Command::new("git");
+    command
+        .arg("--git-dir")
+        .arg(git_repo.path())
+        .arg("push")
+        .arg("--porcelain")
+        .arg(match signed {
+            SignedPush::Yes => "--signed=yes",
+            SignedPush::IfAsked => "--signed=if-asked",
+        })
+        .arg(remote_name);
+    for update in updates {
+        match &update.new_target {
+            Some(new_target) => command.arg(format!(
+                "{}{}:{}",
+                (if update.force { "+" } else { "" }),
+                new_target.hex(),
+                update.qualified_name
+            )),
+            None => command.arg(format!(":{}", update.qualified_name)),
+        };
+    }
+    let output = command
+        .output()
+        .map_err(|err| GitPushError::GitCommandFailed(err.to_string()))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    // Rejected refs are reported on lines like "!<TAB><src>:<dst><TAB><reason>"
+    let rejected_refs = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let refspec = line.strip_prefix("!\t")?.split('\t').next()?;
+            Some(refspec.rsplit(':').next()?.to_string())
+        })
+        .sorted()
+        .collect_vec();
+    if rejected_refs.is_empty() {
+        Err(GitPushError::GitCommandFailed(
+            String::from_utf8_lossy(&output.stderr)
+                .trim_end()
+                .to_string(),
+        ))
+    } else {
+        Err(GitPushError::RefUpdateRejected(rejected_refs))
+    }
+}
+
 fn push_refs(
     git_repo: &git2::Repository,
     remote_name: &str,
     qualified_remote_refs: &[&str],
     refspecs: &[String],
+    callbacks: RemoteCallbacks<'_>,
 ) -> Result<(), GitPushError> {
     let mut remote =
         git_repo
@@ -425,15 +694,24 @@ fn push_refs(
     let mut proxy_options = git2::ProxyOptions::new();
     proxy_options.auto();
     push_options.proxy_options(proxy_options);
-    let mut callbacks = create_remote_callbacks();
-    callbacks.push_update_reference(|refname, status| {
+    let mut git_callbacks = create_remote_callbacks();
+    if let Some(progress_cb) = callbacks.progress {
+        git_callbacks.push_transfer_progress(move |objects, total_objects, bytes| {
+            progress_cb(&Progress {
+                objects,
+                total_objects,
+                bytes,
+            });
+        });
+    }
+    git_callbacks.push_update_reference(|refname, status| {
         // The status is Some if the ref update was rejected
         if status.is_none() {
             remaining_remote_refs.remove(refname);
         }
         Ok(())
     });
-    push_options.remote_callbacks(callbacks);
+    push_options.remote_callbacks(git_callbacks);
     remote
         .push(refspecs, Some(&mut push_options))
         .map_err(|err| match (err.class(), err.code()) {
@@ -456,7 +734,27 @@ fn push_refs(
     }
 }
 
-fn create_remote_callbacks() -> RemoteCallbacks<'static> {
+/// Progress of the transfer of objects during a fetch or push.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    /// The number of objects transferred so far.
+    pub objects: usize,
+    /// The number of objects to transfer in total.
+    pub total_objects: usize,
+    /// The number of bytes transferred so far.
+    pub bytes: usize,
+}
+
+/// Callbacks for reporting on the progress of a fetch or push.
+#[derive(Default)]
+pub struct RemoteCallbacks<'a> {
+    pub progress: Option<&'a mut dyn FnMut(&Progress)>,
+    /// Makes a fetch fail with `GitFetchError::TooManyObjects` as soon as the
+    /// remote says it's going to send more objects than this.
+    pub max_objects: Option<usize>,
+}
+
+fn create_remote_callbacks<'a>() -> git2::RemoteCallbacks<'a> {
     let mut callbacks = git2::RemoteCallbacks::new();
     // TODO: We should expose the callbacks to the caller instead -- the library
     // crate shouldn't look in $HOME etc.
@@ -476,3 +774,55 @@ fn create_remote_callbacks() -> RemoteCallbacks<'static> {
     });
     callbacks
 }
+
+/// Whether a commit is signed, and whether the signature could be verified.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommitSignatureStatus {
+    Unsigned,
+    Good,
+    /// The signature couldn't be verified, with the reason reported by Git.
+    Bad(String),
+}
+
+#[derive(Error, Debug)]
+pub enum GitVerifyError {
+    #[error("Failed to run `git verify-commit`: {0}")]
+    GitCommandFailed(String),
+    #[error("Unexpected git error when verifying a signature: {0}")]
+    InternalGitError(#[from] git2::Error),
+}
+
+/// Checks the signature of a commit in the Git repo. libgit2 can't verify
+/// signatures, so this runs `git verify-commit`, which uses Git's configuration
+/// for the keys to trust (`gpg.program`, `gpg.ssh.allowedSignersFile`, etc.).
+pub fn verify_commit_signature(
+    git_repo: &git2::Repository,
+    commit_id: &CommitId,
+) -> Result<CommitSignatureStatus, GitVerifyError> {
+    let oid = Oid::from_bytes(commit_id.as_bytes())?;
+    match git_repo.extract_signature(&oid, None) {
+        Ok(_) => {}
+        Err(err) if err.code() == git2::ErrorCode::NotFound => {
+            return Ok(CommitSignatureStatus::Unsigned);
+        }
+        Err(err) => return Err(err.into()),
+    }
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(git_repo.path())
+        .arg("verify-commit")
+        .arg(commit_id.hex())
+        .output()
+        .map_err(|err| GitVerifyError::GitCommandFailed(err.to_string()))?;
+    if output.status.success() {
+        Ok(CommitSignatureStatus::Good)
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr
+            .lines()
+            .map(str::trim)
+            .rfind(|line| !line.is_empty())
+            .unwrap_or("verification failed");
+        Ok(CommitSignatureStatus::Bad(reason.to_string()))
+    }
+}
@@ -25,11 +25,13 @@ use uuid::Uuid;
 
 use crate::backend::{
     make_root_commit, Backend, BackendError, BackendResult, ChangeId, Commit, CommitId, Conflict,
-    ConflictId, ConflictPart, FileId, MillisSinceEpoch, Signature, SymlinkId, Timestamp, Tree,
-    TreeId, TreeValue,
+    ConflictId, ConflictPart, FileId, MillisSinceEpoch, ObjectKind, Signature, SymlinkId,
+    Timestamp, Tree, TreeId, TreeValue,
 };
+use crate::local_backend::{copy_record_from_proto, copy_record_to_proto};
 use crate::repo_path::{RepoPath, RepoPathComponent};
 use crate::stacked_table::{TableSegment, TableStore};
+use crate::trailers::{add_co_author_trailers, split_co_author_trailers};
 
 const HASH_LENGTH: usize = 20;
 /// Ref namespace used only for preventing GC.
@@ -113,7 +115,7 @@ fn signature_from_git(signature: git2::Signature) -> Signature {
     }
 }
 
-fn signature_to_git(signature: &Signature) -> git2::Signature {
+pub(crate) fn signature_to_git(signature: &Signature) -> git2::Signature {
     let name = &signature.name;
     let email = &signature.email;
     let time = git2::Time::new(
@@ -130,6 +132,7 @@ fn serialize_extras(commit: &Commit) -> Vec<u8> {
     for predecessor in &commit.predecessors {
         proto.predecessors.push(predecessor.to_bytes());
     }
+    proto.copies = commit.copies.iter().map(copy_record_to_proto).collect();
     proto.write_to_bytes().unwrap()
 }
 
@@ -141,6 +144,7 @@ fn deserialize_extras(commit: &mut Commit, bytes: &[u8]) {
     for predecessor in &proto.predecessors {
         commit.predecessors.push(CommitId::from_bytes(predecessor));
     }
+    commit.copies = proto.copies.iter().map(copy_record_from_proto).collect();
 }
 
 /// Creates a random ref in refs/jj/. Used for preventing GC of commits we
@@ -378,7 +382,8 @@ impl Backend for GitBackend {
             parents.push(self.root_commit_id.clone());
         };
         let tree_id = TreeId::from_bytes(commit.tree_id().as_bytes());
-        let description = commit.message().unwrap_or("<no message>").to_owned();
+        let (description, co_authors) =
+            split_co_author_trailers(commit.message().unwrap_or("<no message>"));
         let author = signature_from_git(commit.author());
         let committer = signature_from_git(commit.committer());
 
@@ -391,6 +396,8 @@ impl Backend for GitBackend {
             author,
             committer,
             is_open: false,
+            copies: vec![],
+            co_authors,
         };
 
         let table = self.extra_metadata_store.get_head().map_err(|err| {
@@ -406,10 +413,16 @@ impl Backend for GitBackend {
 
     fn write_commit(&self, contents: &Commit) -> BackendResult<CommitId> {
         let locked_repo = self.repo.lock().unwrap();
+        if contents.root_tree == self.empty_tree_id {
+            // libgit2 can find the empty tree without it being in the object database,
+            // but `git fsck` considers commits pointing to it broken unless it's
+            // actually written.
+            locked_repo.treebuilder(None)?.write()?;
+        }
         let git_tree = locked_repo.find_tree(Oid::from_bytes(contents.root_tree.as_bytes())?)?;
         let author = signature_to_git(&contents.author);
         let committer = signature_to_git(&contents.committer);
-        let message = &contents.description;
+        let message = add_co_author_trailers(&contents.description, &contents.co_authors);
 
         let mut parents = vec![];
         for parent_id in &contents.parents {
@@ -430,7 +443,7 @@ impl Backend for GitBackend {
             Some(&create_no_gc_ref()),
             &author,
             &committer,
-            message,
+            &message,
             &git_tree,
             &parent_refs,
         )?;
@@ -457,6 +470,25 @@ impl Backend for GitBackend {
             })?;
         Ok(id)
     }
+
+    fn verify_object(&self, kind: ObjectKind, id: &[u8]) -> BackendResult<()> {
+        if kind == ObjectKind::Commit && id == self.root_commit_id.as_bytes() {
+            return Ok(());
+        }
+        let locked_repo = self.repo.lock().unwrap();
+        let odb = locked_repo.odb()?;
+        let object = odb.read(Oid::from_bytes(id).unwrap())?;
+        let actual_hash = Oid::hash_object(object.kind(), object.data())?;
+        if actual_hash.as_bytes() == id {
+            Ok(())
+        } else {
+            Err(BackendError::HashMismatch {
+                kind,
+                hash: hex::encode(id),
+                actual_hash: actual_hash.to_string(),
+            })
+        }
+    }
 }
 
 fn conflict_part_list_to_json(parts: &[ConflictPart]) -> serde_json::Value {
@@ -669,6 +701,8 @@ mod tests {
             author: signature.clone(),
             committer: signature,
             is_open: false,
+            copies: vec![],
+            co_authors: vec![],
         };
         let commit_id = store.write_commit(&commit).unwrap();
         let git_refs = store
@@ -705,6 +739,8 @@ mod tests {
             author: signature.clone(),
             committer: signature,
             is_open: false,
+            copies: vec![],
+            co_authors: vec![],
         };
         let commit_id1 = store.write_commit(&commit1).unwrap();
         let mut commit2 = commit1;
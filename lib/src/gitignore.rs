@@ -23,6 +23,7 @@ use regex::{escape as regex_escape, Regex};
 #[derive(Debug)]
 struct GitIgnoreLine {
     is_negative: bool,
+    matches_only_directory: bool,
     regex: Regex,
 }
 
@@ -104,6 +105,10 @@ impl GitIgnoreLine {
                 for c in component.chars() {
                     if in_escape {
                         in_escape = false;
+                        if let Some(characters) = &mut character_class {
+                            characters.push_str(&regex_escape(&c.to_string()));
+                            continue;
+                        }
                         if !matches!(c, ' ' | '#' | '!' | '?' | '\\' | '*') {
                             regex.push_str(&regex_escape("\\"));
                         }
@@ -111,11 +116,25 @@ impl GitIgnoreLine {
                     } else if c == '\\' {
                         in_escape = true;
                     } else if let Some(characters) = &mut character_class {
-                        if c == ']' {
-                            regex.push('[');
-                            regex.push_str(characters);
+                        if c == ']' && !characters.is_empty() && characters != "^" {
+                            // Like other wildcards, negated classes don't match "/"
+                            if let Some(negated) = characters.strip_prefix('^') {
+                                regex.push_str("[^/");
+                                regex.push_str(negated);
+                            } else {
+                                regex.push('[');
+                                regex.push_str(characters);
+                            }
                             regex.push(']');
                             character_class = None;
+                        } else if (c == '!' || c == '^') && characters.is_empty() {
+                            // "[!a]" negates the class like "[^a]" does
+                            characters.push('^');
+                        } else if matches!(c, '[' | ']' | '&' | '~' | '-' | '\\')
+                            && !(c == '-' && !characters.is_empty() && characters != "^")
+                        {
+                            characters.push('\\');
+                            characters.push(c);
                         } else {
                             characters.push(c);
                         }
@@ -140,18 +159,20 @@ impl GitIgnoreLine {
                 }
             }
         }
-        if matches_only_directory {
-            regex.push_str("/.*");
-        } else {
-            regex.push_str("(/.*|$)");
-        }
+        regex.push('$');
         let regex = Regex::new(&regex).unwrap();
 
-        Some(GitIgnoreLine { is_negative, regex })
+        Some(GitIgnoreLine {
+            is_negative,
+            matches_only_directory,
+            regex,
+        })
     }
 
-    fn matches(&self, path: &str) -> bool {
-        self.regex.is_match(path)
+    /// Whether the pattern matches `path` itself (not counting its parent
+    /// directories).
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        (is_dir || !self.matches_only_directory) && self.regex.is_match(path)
     }
 }
 
@@ -209,34 +230,39 @@ impl GitIgnoreFile {
         }
     }
 
-    pub fn matches_file(&self, path: &str) -> bool {
+    /// Whether the last pattern matching `path` itself (if any) excludes it.
+    fn matches_path(&self, path: &str, is_dir: bool) -> bool {
         // Later lines take precedence, so check them in reverse
         for line in self.all_lines_reversed() {
-            if line.matches(path) {
+            if line.matches(path, is_dir) {
                 return !line.is_negative;
             }
         }
         false
     }
 
+    /// Whether any of the directories `dir` and its parents is excluded. Like
+    /// in Git, files in an excluded directory can't be re-included.
+    fn matches_dir_or_parent(&self, dir: &str) -> bool {
+        dir.match_indices('/')
+            .map(|(i, _)| &dir[..i])
+            .chain(std::iter::once(dir))
+            .any(|dir| self.matches_path(dir, true))
+    }
+
+    pub fn matches_file(&self, path: &str) -> bool {
+        match path.rsplit_once('/') {
+            Some((dir, _)) if self.matches_dir_or_parent(dir) => true,
+            _ => self.matches_path(path, false),
+        }
+    }
+
     pub fn matches_all_files_in(&self, dir: &str) -> bool {
-        // Later lines take precedence, so check them in reverse
         assert!(dir.is_empty() || dir.ends_with('/'));
-        for line in self.all_lines_reversed() {
-            // Let's say there's a "/target/" pattern and then a "!interesting" pattern
-            // after it, then we can't say for sure that all files in target/ match.
-            // TODO: This can be smarter. For example, if there's a pattern "/foo/" followed
-            // by "!/bar/", then we can answer "true" for "foo/". A more complex
-            // case is if a pattern "/foo/" is followed "!/foo/bar/", then we
-            // can say "false" for "foo/" and "true" for "foo/baz/".
-            if line.is_negative {
-                return false;
-            }
-            if line.matches(dir) {
-                return true;
-            }
+        match dir.strip_suffix('/') {
+            Some(dir) => self.matches_dir_or_parent(dir),
+            None => false,
         }
-        false
     }
 }
 
@@ -436,26 +462,69 @@ mod tests {
 
     #[test]
     fn test_gitignore_line_ordering() {
+        assert!(matches_file(b"foo/*\n!foo/bar\n", "foo/baz"));
+        assert!(!matches_file(b"foo/*\n!foo/bar\n", "foo/bar"));
+        assert!(!matches_file(b"foo/*\n!foo/bar\n", "foo"));
+        assert!(matches_file(b"foo/*\n!foo/bar\nfoo/bar/baz", "foo/bar/baz"));
+        assert!(!matches_file(
+            b"foo/*\n!foo/bar\nfoo/bar/baz",
+            "foo/bar/quux"
+        ));
+        assert!(!matches_file(b"!foo\nfoo\n!foo\n", "foo"));
+        assert!(matches_file(b"!foo\nfoo\n", "foo"));
+    }
+
+    #[test]
+    fn test_gitignore_reinclude_in_excluded_dir() {
+        // Files can't be re-included if their parent directory is excluded
         assert!(matches_file(b"foo\n!foo/bar\n", "foo"));
-        assert!(!matches_file(b"foo\n!foo/bar\n", "foo/bar"));
-        assert!(matches_file(b"foo\n!foo/bar\n", "foo/baz"));
-        assert!(matches_file(b"foo\n!foo/bar\nfoo/bar/baz", "foo"));
-        assert!(!matches_file(b"foo\n!foo/bar\nfoo/bar/baz", "foo/bar"));
-        assert!(matches_file(b"foo\n!foo/bar\nfoo/bar/baz", "foo/bar/baz"));
-        assert!(!matches_file(b"foo\n!foo/bar\nfoo/bar/baz", "foo/bar/quux"));
+        assert!(matches_file(b"foo\n!foo/bar\n", "foo/bar"));
+        assert!(matches_file(b"foo/\n!foo/bar\n", "foo/bar"));
+        assert!(matches_file(b"foo/\n!bar\n", "foo/bar"));
+        // But the directory itself can be re-included
+        assert!(!matches_file(b"foo\n!foo/\n", "foo/bar"));
+        assert!(!matches_file(b"/*\n!/foo/\n", "foo/bar"));
+        assert!(matches_file(b"/*\n!/foo/\n", "bar/foo"));
+    }
+
+    #[test]
+    fn test_gitignore_negated_range() {
+        assert!(!matches_file(b"foo.[!a]\n", "foo.a"));
+        assert!(matches_file(b"foo.[!a]\n", "foo.b"));
+        assert!(!matches_file(b"foo.[^a]\n", "foo.a"));
+        assert!(matches_file(b"foo.[^a]\n", "foo.b"));
+        assert!(!matches_file(b"foo[!a]bar\n", "foo/bar"));
+        assert!(matches_file(b"foo.[]]\n", "foo.]"));
+        assert!(matches_file(b"foo.[!]]\n", "foo.a"));
+        assert!(!matches_file(b"foo.[!]]\n", "foo.]"));
+        assert!(matches_file(b"foo.[-a]\n", "foo.-"));
+    }
+
+    #[test]
+    fn test_gitignore_escaped_leading_symbols() {
+        // "\#" and "\!" start a pattern with a literal "#" or "!"
+        assert!(!matches_file(b"#foo\n", "#foo"));
+        assert!(matches_file(b"\\#foo\n", "#foo"));
+        assert!(matches_file(b"\\!foo\n", "!foo"));
+        assert!(!matches_file(b"\\!foo\n", "foo"));
+        assert!(!matches_file(b"*foo\n!\\!foo\n", "!foo"));
     }
 
     #[test]
     fn test_gitignore_file_ordering() {
-        let file1 = GitIgnoreFile::empty().chain("", b"foo\n");
+        let file1 = GitIgnoreFile::empty().chain("", b"foo/*\n");
         let file2 = file1.chain("foo/", b"!bar");
         let file3 = file2.chain("foo/bar/", b"baz");
-        assert!(file1.matches_file("foo"));
         assert!(file1.matches_file("foo/bar"));
         assert!(!file2.matches_file("foo/bar"));
         assert!(file2.matches_file("foo/baz"));
         assert!(file3.matches_file("foo/bar/baz"));
         assert!(!file3.matches_file("foo/bar/qux"));
+        // The "!bar" in the nested file can't re-include "foo/bar" if "foo" itself
+        // is excluded
+        let file1 = GitIgnoreFile::empty().chain("", b"foo\n");
+        let file2 = file1.chain("foo/", b"!bar");
+        assert!(file2.matches_file("foo/bar"));
     }
 
     #[test]
@@ -463,8 +532,13 @@ mod tests {
         assert!(matches_all_files_in(b"foo\n", "foo/"));
         assert!(matches_all_files_in(b"foo\nbar\n", "foo/"));
         assert!(matches_all_files_in(b"!foo\nbar\n", "bar/"));
-        assert!(!matches_all_files_in(b"foo\n!bar\n", "foo/"));
-        // This one could return true, but it doesn't currently
-        assert!(!matches_all_files_in(b"foo\n!/bar\n", "foo/"));
+        assert!(!matches_all_files_in(b"foo\n!foo\n", "foo/"));
+        // Files in excluded directories can't be re-included
+        assert!(matches_all_files_in(b"foo\n!bar\n", "foo/"));
+        assert!(matches_all_files_in(b"foo\n!foo/bar\n", "foo/bar/"));
+        assert!(!matches_all_files_in(b"foo/*\n!foo/bar\n", "foo/"));
+        assert!(!matches_all_files_in(b"foo/*\n!foo/bar\n", "foo/bar/"));
+        assert!(matches_all_files_in(b"foo/*\n!foo/bar\n", "foo/baz/"));
+        assert!(!matches_all_files_in(b"foo\n", ""));
     }
 }
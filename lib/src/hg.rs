@@ -0,0 +1,519 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Import of Mercurial repositories.
+//!
+//! The changesets are read by running the `hg` command, so Mercurial needs to
+//! be installed. Imported changesets are recorded in the repo's `hg_import`
+//! file so importing from the same repo again only imports the new ones.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use thiserror::Error;
+
+use crate::backend::{
+    BackendError, ChangeId, CommitId, MillisSinceEpoch, Signature, Timestamp, TreeValue,
+};
+use crate::commit_builder::CommitBuilder;
+use crate::op_store::RefTarget;
+use crate::repo::MutableRepo;
+use crate::repo_path::RepoPath;
+use crate::settings::UserSettings;
+use crate::tree_builder::TreeBuilder;
+
+const NULL_NODE: &str = "0000000000000000000000000000000000000000";
+
+#[derive(Debug, Error)]
+pub enum HgImportError {
+    #[error("{} is not a Mercurial repository", .0.display())]
+    NotAnHgRepo(PathBuf),
+    #[error("Failed to run `{command}`: {err}")]
+    FailedToRun {
+        command: String,
+        #[source]
+        err: io::Error,
+    },
+    #[error("`{command}` failed:\n{stderr}")]
+    CommandFailed { command: String, stderr: String },
+    #[error("Unexpected output from `{command}`: {message}")]
+    InvalidOutput { command: String, message: String },
+    #[error("Changeset {node} has a parent {parent} that hasn't been imported")]
+    MissingParent { node: String, parent: String },
+    #[error("Failed to read or write the import state: {0}")]
+    StateError(#[from] io::Error),
+    #[error("Unexpected backend error: {0}")]
+    BackendError(#[from] BackendError),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HgFile {
+    Normal { contents: Vec<u8>, executable: bool },
+    Symlink(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HgFileChange {
+    pub path: RepoPath,
+    /// The new file, or `None` if the file was removed.
+    pub file: Option<HgFile>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HgChangeset {
+    /// The changeset's node ID in hex.
+    pub node: String,
+    pub parents: Vec<String>,
+    pub author: Signature,
+    pub description: String,
+    /// The changeset's named branch.
+    pub branch: String,
+    pub bookmarks: Vec<String>,
+    /// The files changed compared to the first parent, including the ones a
+    /// merge took from its other parents. Only needs to be populated for
+    /// changesets that haven't been imported before.
+    pub files: Vec<HgFileChange>,
+}
+
+/// Remembers which commit each Mercurial changeset was imported as.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HgImportState {
+    commits: BTreeMap<String, CommitId>,
+}
+
+impl HgImportState {
+    fn state_path(repo_path: &Path) -> PathBuf {
+        repo_path.join("hg_import")
+    }
+
+    /// Reads the state from the repo at `repo_path`. Returns an empty state if
+    /// nothing has been imported yet.
+    pub fn load(repo_path: &Path) -> io::Result<Self> {
+        let contents = match fs::read_to_string(Self::state_path(repo_path)) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err),
+        };
+        let mut commits = BTreeMap::new();
+        for line in contents.lines() {
+            let (node, commit_hex) = line.split_once(' ').ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid line in hg_import: {line}"),
+                )
+            })?;
+            commits.insert(node.to_string(), CommitId::from_hex(commit_hex));
+        }
+        Ok(HgImportState { commits })
+    }
+
+    pub fn save(&self, repo_path: &Path) -> io::Result<()> {
+        let mut temp_file = tempfile::NamedTempFile::new_in(repo_path)?;
+        for (node, commit_id) in &self.commits {
+            writeln!(temp_file, "{} {}", node, commit_id.hex())?;
+        }
+        temp_file
+            .persist(Self::state_path(repo_path))
+            .map_err(|err| err.error)?;
+        Ok(())
+    }
+
+    pub fn commit_id(&self, node: &str) -> Option<&CommitId> {
+        self.commits.get(node)
+    }
+
+    pub fn len(&self) -> usize {
+        self.commits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commits.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HgImportStats {
+    pub imported_changesets: usize,
+    pub updated_branches: usize,
+}
+
+/// Imports the changesets that are not yet in `state` and points branches at
+/// the imported commits. The changesets must be sorted so parents come before
+/// their children.
+///
+/// Bookmarks become branches of the same name. So do the heads of named
+/// branches other than "default", unless there's a bookmark with the same
+/// name.
+pub fn import_changesets(
+    settings: &UserSettings,
+    mut_repo: &mut MutableRepo,
+    state: &mut HgImportState,
+    changesets: &[HgChangeset],
+) -> Result<HgImportStats, HgImportError> {
+    let mut stats = HgImportStats::default();
+    let store = mut_repo.store().clone();
+    let mailmap = settings.mailmap();
+    for changeset in changesets {
+        if state.commits.contains_key(&changeset.node) {
+            continue;
+        }
+        let mut parent_ids = vec![];
+        for parent in &changeset.parents {
+            let parent_id =
+                state
+                    .commits
+                    .get(parent)
+                    .ok_or_else(|| HgImportError::MissingParent {
+                        node: changeset.node.clone(),
+                        parent: parent.clone(),
+                    })?;
+            parent_ids.push(parent_id.clone());
+        }
+        let base_tree_id = match parent_ids.first() {
+            Some(parent_id) => store.get_commit(parent_id)?.tree_id().clone(),
+            None => {
+                parent_ids.push(store.root_commit_id().clone());
+                store.empty_tree_id().clone()
+            }
+        };
+        let mut tree_builder = TreeBuilder::new(store.clone(), base_tree_id);
+        for change in &changeset.files {
+            match &change.file {
+                None => tree_builder.remove(change.path.clone()),
+                Some(HgFile::Normal {
+                    contents,
+                    executable,
+                }) => {
+                    let id = store.write_file(&change.path, &mut contents.as_slice())?;
+                    tree_builder.set(
+                        change.path.clone(),
+                        TreeValue::Normal {
+                            id,
+                            executable: *executable,
+                        },
+                    );
+                }
+                Some(HgFile::Symlink(target)) => {
+                    let id = store.write_symlink(&change.path, target)?;
+                    tree_builder.set(change.path.clone(), TreeValue::Symlink(id));
+                }
+            }
+        }
+        let tree_id = tree_builder.write_tree();
+        let author = mailmap.resolve(&changeset.author);
+        let commit = CommitBuilder::for_new_commit(settings, parent_ids, tree_id)
+            .set_change_id(change_id_for_node(&changeset.node))
+            .set_description(changeset.description.clone())
+            .set_author(author.clone())
+            .set_committer(author)
+            .write_to_repo(mut_repo);
+        state
+            .commits
+            .insert(changeset.node.clone(), commit.id().clone());
+        stats.imported_changesets += 1;
+    }
+
+    // Named branches are listed in revision order, so the last changeset on a
+    // branch is its head
+    let mut branches = HashMap::new();
+    let mut bookmarks = HashMap::new();
+    for changeset in changesets {
+        let commit_id = match state.commits.get(&changeset.node) {
+            Some(commit_id) => commit_id,
+            None => continue,
+        };
+        if changeset.branch != "default" {
+            branches.insert(changeset.branch.clone(), commit_id.clone());
+        }
+        for bookmark in &changeset.bookmarks {
+            bookmarks.insert(bookmark.clone(), commit_id.clone());
+        }
+    }
+    branches.extend(bookmarks);
+    let mut branches = branches.into_iter().collect::<Vec<_>>();
+    branches.sort();
+    for (name, commit_id) in branches {
+        let target = RefTarget::Normal(commit_id);
+        if mut_repo.get_local_branch(&name).as_ref() != Some(&target) {
+            mut_repo.set_local_branch(name, target);
+            stats.updated_branches += 1;
+        }
+    }
+    Ok(stats)
+}
+
+/// Derives the change ID from the changeset's node ID so importing the same
+/// repo into different jj repos results in the same change IDs.
+fn change_id_for_node(node: &str) -> ChangeId {
+    let bytes = hex::decode(node).unwrap_or_default();
+    if bytes.len() < 16 {
+        return crate::commit_builder::new_change_id();
+    }
+    ChangeId::new(bytes[..16].to_vec())
+}
+
+/// A Mercurial repository that is read with the `hg` command.
+pub struct HgRepo {
+    path: PathBuf,
+}
+
+impl HgRepo {
+    pub fn open(path: &Path) -> Result<Self, HgImportError> {
+        if !path.join(".hg").is_dir() {
+            return Err(HgImportError::NotAnHgRepo(path.to_path_buf()));
+        }
+        Ok(HgRepo {
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn run<S: AsRef<OsStr>>(&self, args: &[S]) -> Result<Vec<u8>, HgImportError> {
+        let command = format_command(args);
+        let output = Command::new("hg")
+            .args(args)
+            .current_dir(&self.path)
+            // Make the output independent of the user's config
+            .env("HGPLAIN", "1")
+            .output()
+            .map_err(|err| HgImportError::FailedToRun {
+                command: command.clone(),
+                err,
+            })?;
+        if !output.status.success() {
+            return Err(HgImportError::CommandFailed {
+                command,
+                stderr: String::from_utf8_lossy(&output.stderr)
+                    .trim_end()
+                    .to_string(),
+            });
+        }
+        Ok(output.stdout)
+    }
+
+    fn run_json(&self, args: &[&str]) -> Result<serde_json::Value, HgImportError> {
+        let output = self.run(args)?;
+        serde_json::from_slice(&output).map_err(|err| HgImportError::InvalidOutput {
+            command: format_command(args),
+            message: err.to_string(),
+        })
+    }
+
+    /// Reads all changesets in revision order. The files are only read for
+    /// the changesets that are not in `state`.
+    pub fn changesets(&self, state: &HgImportState) -> Result<Vec<HgChangeset>, HgImportError> {
+        let args = ["log", "-r", "all()", "-T", "json", "-v"];
+        let invalid = |message: &str| HgImportError::InvalidOutput {
+            command: format!("hg {}", args.join(" ")),
+            message: message.to_string(),
+        };
+        let log = self.run_json(&args)?;
+        let entries = log.as_array().ok_or_else(|| invalid("expected a list"))?;
+        let mut changesets = vec![];
+        for entry in entries {
+            let string_list = |key: &str| -> Vec<String> {
+                entry[key]
+                    .as_array()
+                    .map(|values| {
+                        values
+                            .iter()
+                            .filter_map(|value| value.as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default()
+            };
+            let node = entry["node"]
+                .as_str()
+                .ok_or_else(|| invalid("missing node"))?
+                .to_string();
+            let parents = string_list("parents")
+                .into_iter()
+                .filter(|parent| parent != NULL_NODE)
+                .collect();
+            let (name, email) = parse_user(entry["user"].as_str().unwrap_or_default());
+            let seconds = entry["date"][0].as_f64().unwrap_or_default();
+            // Mercurial stores the offset in seconds west of UTC
+            let offset_west = entry["date"][1].as_i64().unwrap_or_default();
+            let author = Signature {
+                name,
+                email,
+                timestamp: Timestamp {
+                    timestamp: MillisSinceEpoch((seconds * 1000.0) as i64),
+                    tz_offset: (-offset_west / 60) as i32,
+                },
+            };
+            let files = if state.commits.contains_key(&node) {
+                vec![]
+            } else {
+                self.read_file_changes(&node)?
+            };
+            changesets.push(HgChangeset {
+                node,
+                parents,
+                author,
+                description: entry["desc"].as_str().unwrap_or_default().to_string(),
+                branch: entry["branch"].as_str().unwrap_or("default").to_string(),
+                bookmarks: string_list("bookmarks"),
+                files,
+            });
+        }
+        Ok(changesets)
+    }
+
+    /// Reads the files changed compared to the first parent. They're read with
+    /// `hg status` because the changeset's own list of files leaves out the
+    /// files a merge took unchanged from its second parent.
+    fn read_file_changes(&self, node: &str) -> Result<Vec<HgFileChange>, HgImportError> {
+        let status = self.run_json(&["status", "--change", node, "-T", "json"])?;
+        let mut changed = vec![];
+        let mut removed = vec![];
+        for entry in status.as_array().into_iter().flatten() {
+            let path = match entry["path"].as_str() {
+                Some(path) => path.replace('\\', "/"),
+                None => continue,
+            };
+            match entry["status"].as_str() {
+                Some("R") => removed.push(path),
+                Some("M" | "A") => changed.push(path),
+                _ => {}
+            }
+        }
+        let mut changes = removed
+            .into_iter()
+            .map(|file| HgFileChange {
+                path: RepoPath::from_internal_string(&file),
+                file: None,
+            })
+            .collect::<Vec<_>>();
+        if changed.is_empty() {
+            return Ok(changes);
+        }
+        let manifest = self.run_json(&["files", "-r", node, "-T", "json", "-v"])?;
+        let mut flags = HashMap::new();
+        for entry in manifest.as_array().into_iter().flatten() {
+            if let Some(path) = entry["path"].as_str() {
+                flags.insert(
+                    path.replace('\\', "/"),
+                    entry["flags"].as_str().unwrap_or_default().to_string(),
+                );
+            }
+        }
+        let mut contents = self.cat_files(node, &changed)?;
+        for file in changed {
+            let path = RepoPath::from_internal_string(&file);
+            let contents = contents.remove(&file).unwrap_or_default();
+            let file_flags = flags.get(&file).map_or("", String::as_str);
+            let file = if file_flags.contains('l') {
+                HgFile::Symlink(String::from_utf8_lossy(&contents).into_owned())
+            } else {
+                HgFile::Normal {
+                    contents,
+                    executable: file_flags.contains('x'),
+                }
+            };
+            changes.push(HgFileChange {
+                path,
+                file: Some(file),
+            });
+        }
+        Ok(changes)
+    }
+
+    /// Reads the contents of `files` at `node` with a single `hg cat`, which
+    /// writes them to a temporary directory.
+    fn cat_files(
+        &self,
+        node: &str,
+        files: &[String],
+    ) -> Result<HashMap<String, Vec<u8>>, HgImportError> {
+        let temp_dir = tempfile::tempdir()?;
+        let list_path = temp_dir.path().join("files");
+        let output_dir = temp_dir.path().join("out");
+        let mut list_arg = OsString::from("listfile0:");
+        list_arg.push(&list_path);
+        let args: Vec<OsString> = vec![
+            "cat".into(),
+            "-r".into(),
+            node.into(),
+            "--output".into(),
+            output_dir.join("%p").into(),
+            list_arg,
+        ];
+        let failed_to_run = |err| HgImportError::FailedToRun {
+            command: format_command(&args),
+            err,
+        };
+        let mut list = vec![];
+        for file in files {
+            list.extend_from_slice(b"path:");
+            list.extend_from_slice(file.as_bytes());
+            list.push(0);
+        }
+        fs::write(&list_path, list).map_err(failed_to_run)?;
+        self.run(&args)?;
+        files
+            .iter()
+            .map(|file| {
+                let contents = fs::read(output_dir.join(file)).map_err(failed_to_run)?;
+                Ok((file.clone(), contents))
+            })
+            .collect()
+    }
+}
+
+fn format_command<S: AsRef<OsStr>>(args: &[S]) -> String {
+    let mut command = "hg".to_string();
+    for arg in args {
+        command.push(' ');
+        command.push_str(&arg.as_ref().to_string_lossy());
+    }
+    command
+}
+
+/// Splits a Mercurial user string like "Name <email>" into name and email.
+fn parse_user(user: &str) -> (String, String) {
+    match user.split_once('<') {
+        Some((name, rest)) => {
+            let email = rest.split_once('>').map_or(rest, |(email, _)| email);
+            (name.trim().to_string(), email.trim().to_string())
+        }
+        None if user.contains('@') => (String::new(), user.trim().to_string()),
+        None => (user.trim().to_string(), String::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_user() {
+        assert_eq!(
+            parse_user("Some One <some.one@example.com>"),
+            ("Some One".to_string(), "some.one@example.com".to_string())
+        );
+        assert_eq!(
+            parse_user("some.one@example.com"),
+            (String::new(), "some.one@example.com".to_string())
+        );
+        assert_eq!(
+            parse_user("someone"),
+            ("someone".to_string(), String::new())
+        );
+    }
+}
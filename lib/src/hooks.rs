@@ -0,0 +1,311 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hooks that inspect the commits a transaction is about to add and can reject
+//! the transaction, e.g. to enforce a commit message format or a limit on file
+//! sizes.
+//!
+//! A hook is a command that receives a JSON description of the commits on its
+//! stdin. If it exits with an error (or doesn't finish within its timeout), the
+//! transaction is rejected and whatever the hook printed to stderr is reported.
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::backend::{CommitId, TreeValue};
+use crate::commit::Commit;
+use crate::matchers::EverythingMatcher;
+use crate::repo::MutableRepo;
+use crate::rewrite::merge_commit_trees;
+use crate::settings::UserSettings;
+use crate::tree::Diff;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookKind {
+    /// Runs for all commits added by the transaction.
+    PreCommit,
+    /// Runs for the commits added by the transaction that rewrite other
+    /// commits.
+    PreRewrite,
+}
+
+impl HookKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookKind::PreCommit => "pre-commit",
+            HookKind::PreRewrite => "pre-rewrite",
+        }
+    }
+}
+
+impl Display for HookKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hook {
+    pub kind: HookKind,
+    pub command: Vec<String>,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Error)]
+pub enum HookError {
+    #[error("Invalid hook config: {0}")]
+    InvalidConfig(String),
+    #[error("The {kind} hook `{command}` rejected the change{}", format_output(.output))]
+    Rejected {
+        kind: HookKind,
+        command: String,
+        output: String,
+    },
+    #[error("The {kind} hook `{command}` did not finish within {} seconds", .timeout.as_secs())]
+    TimedOut {
+        kind: HookKind,
+        command: String,
+        timeout: Duration,
+    },
+    #[error("Failed to run the {kind} hook `{command}`: {err}")]
+    IoError {
+        kind: HookKind,
+        command: String,
+        #[source]
+        err: io::Error,
+    },
+}
+
+fn format_output(output: &str) -> String {
+    let output = output.trim_end();
+    if output.is_empty() {
+        String::new()
+    } else {
+        format!(":\n{output}")
+    }
+}
+
+impl Hook {
+    /// Reads the hooks configured in `hooks.pre-commit` and
+    /// `hooks.pre-rewrite`. Each of them is a list of tables with a `command`
+    /// (a list of strings) and an optional `timeout` in seconds.
+    pub fn all_from_settings(settings: &UserSettings) -> Result<Vec<Hook>, HookError> {
+        let mut hooks = vec![];
+        for kind in [HookKind::PreCommit, HookKind::PreRewrite] {
+            let key = format!("hooks.{}", kind.name());
+            let entries = match settings.config().get_array(&key) {
+                Ok(entries) => entries,
+                Err(config::ConfigError::NotFound(_)) => continue,
+                Err(err) => return Err(HookError::InvalidConfig(err.to_string())),
+            };
+            for entry in entries {
+                let invalid =
+                    |err: config::ConfigError| HookError::InvalidConfig(format!("{key}: {err}"));
+                let mut table = entry.into_table().map_err(invalid)?;
+                let command = table
+                    .remove("command")
+                    .ok_or_else(|| HookError::InvalidConfig(format!("{key}: missing `command`")))?
+                    .into_array()
+                    .map_err(invalid)?
+                    .into_iter()
+                    .map(|arg| arg.into_string())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(invalid)?;
+                if command.is_empty() {
+                    return Err(HookError::InvalidConfig(format!(
+                        "{key}: `command` must not be empty"
+                    )));
+                }
+                let timeout = match table.remove("timeout") {
+                    None => DEFAULT_TIMEOUT,
+                    Some(value) => {
+                        let seconds = value.into_int().map_err(invalid)?;
+                        Duration::from_secs(seconds.try_into().map_err(|_| {
+                            HookError::InvalidConfig(format!("{key}: invalid timeout {seconds}"))
+                        })?)
+                    }
+                };
+                hooks.push(Hook {
+                    kind,
+                    command,
+                    timeout,
+                });
+            }
+        }
+        Ok(hooks)
+    }
+
+    /// Runs the hook with `input` on its stdin.
+    pub fn run(&self, input: &[u8]) -> Result<(), HookError> {
+        let command_str = self.command.join(" ");
+        let io_error = |err| HookError::IoError {
+            kind: self.kind,
+            command: command_str.clone(),
+            err,
+        };
+        let mut child = Command::new(&self.command[0])
+            .args(&self.command[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(io_error)?;
+        // Write the input and read the output on separate threads so a hook that
+        // produces lots of output doesn't block us (or the other way around).
+        let mut stdin = child.stdin.take().unwrap();
+        let input = input.to_vec();
+        // The hook may exit without reading its input, so ignore errors here.
+        let stdin_thread = thread::spawn(move || stdin.write_all(&input).ok());
+        let mut stdout = child.stdout.take().unwrap();
+        let stdout_thread = thread::spawn(move || {
+            let mut buf = vec![];
+            stdout.read_to_end(&mut buf).ok();
+            buf
+        });
+        let mut stderr = child.stderr.take().unwrap();
+        let stderr_thread = thread::spawn(move || {
+            let mut buf = vec![];
+            stderr.read_to_end(&mut buf).ok();
+            buf
+        });
+        let start = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait().map_err(io_error)? {
+                break status;
+            }
+            if start.elapsed() > self.timeout {
+                child.kill().ok();
+                child.wait().ok();
+                return Err(HookError::TimedOut {
+                    kind: self.kind,
+                    command: command_str,
+                    timeout: self.timeout,
+                });
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        stdin_thread.join().ok();
+        let stdout = stdout_thread.join().unwrap_or_default();
+        let stderr = stderr_thread.join().unwrap_or_default();
+        if status.success() {
+            Ok(())
+        } else {
+            let mut output = String::from_utf8_lossy(&stderr).into_owned();
+            if output.trim().is_empty() {
+                output = String::from_utf8_lossy(&stdout).into_owned();
+            }
+            Err(HookError::Rejected {
+                kind: self.kind,
+                command: command_str,
+                output,
+            })
+        }
+    }
+}
+
+/// Runs the hooks on the visible commits that were added in `mut_repo`
+/// compared to its base repo. Stops at the first hook that rejects them.
+pub fn run_hooks(hooks: &[Hook], mut_repo: &MutableRepo) -> Result<(), HookError> {
+    if hooks.is_empty() {
+        return Ok(());
+    }
+    let new_commits = new_visible_commits(mut_repo);
+    for hook in hooks {
+        let commits = new_commits
+            .iter()
+            .filter(|commit| match hook.kind {
+                HookKind::PreCommit => true,
+                HookKind::PreRewrite => !commit.predecessor_ids().is_empty(),
+            })
+            .collect::<Vec<_>>();
+        if commits.is_empty() {
+            continue;
+        }
+        let input = json!({
+            "hook": hook.kind.name(),
+            "commits": commits
+                .iter()
+                .map(|commit| commit_to_json(mut_repo, commit))
+                .collect::<Vec<_>>(),
+        });
+        hook.run(format!("{input}\n").as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Returns the commits that are visible in `mut_repo` but didn't exist in its
+/// base repo, parents before children.
+fn new_visible_commits(mut_repo: &MutableRepo) -> Vec<Commit> {
+    let base_index = mut_repo.base_repo().index();
+    let store = mut_repo.store();
+    let mut commits = vec![];
+    let mut seen = HashSet::new();
+    let mut work: Vec<CommitId> = mut_repo.view().heads().iter().cloned().collect();
+    while let Some(id) = work.pop() {
+        if base_index.has_id(&id) || !seen.insert(id.clone()) {
+            continue;
+        }
+        let commit = store.get_commit(&id).unwrap();
+        work.extend(commit.parent_ids().iter().cloned());
+        commits.push(commit);
+    }
+    let index = mut_repo.index();
+    commits.sort_by_key(|commit| index.commit_id_to_pos(commit.id()));
+    commits
+}
+
+fn commit_to_json(mut_repo: &MutableRepo, commit: &Commit) -> Value {
+    let ids_to_json = |ids: &[CommitId]| ids.iter().map(|id| id.hex()).collect::<Vec<_>>();
+    let parent_tree = merge_commit_trees(mut_repo.as_repo_ref(), &commit.parents());
+    let store = mut_repo.store();
+    let mut files = vec![];
+    for (path, diff) in parent_tree.diff(&commit.tree(), &EverythingMatcher) {
+        let (status, value) = match &diff {
+            Diff::Added(value) => ("added", Some(value)),
+            Diff::Modified(_, value) => ("modified", Some(value)),
+            Diff::Removed(_) => ("removed", None),
+        };
+        let mut file = json!({"path": path.to_internal_file_string(), "status": status});
+        if let Some(TreeValue::Normal { id, .. }) = value {
+            if let Ok(mut reader) = store.read_file(&path, id) {
+                if let Ok(size) = io::copy(&mut reader, &mut io::sink()) {
+                    file["size"] = json!(size);
+                }
+            }
+        }
+        files.push(file);
+    }
+    json!({
+        "commit_id": commit.id().hex(),
+        "change_id": commit.change_id().hex(),
+        "description": commit.description(),
+        "author": {
+            "name": commit.author().name,
+            "email": commit.author().email,
+        },
+        "parents": ids_to_json(commit.parent_ids()),
+        "predecessors": ids_to_json(commit.predecessor_ids()),
+        "files": files,
+    })
+}
@@ -13,7 +13,8 @@
 // limitations under the License.
 
 use std::cmp::{max, min, Ordering};
-use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashSet};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
 use std::fs::File;
 use std::hash::{Hash, Hasher};
@@ -103,6 +104,15 @@ impl<'a> IndexRef<'a> {
         }
     }
 
+    /// All commits in the index, including hidden ones, in reverse topological
+    /// order.
+    pub fn entries(&self) -> impl Iterator<Item = IndexEntry<'a>> + 'a {
+        let index = *self;
+        (0..self.num_commits())
+            .rev()
+            .map(move |pos| index.entry_by_pos(IndexPosition(pos)))
+    }
+
     pub fn has_id(&self, commit_id: &CommitId) -> bool {
         match self {
             IndexRef::Readonly(index) => index.has_id(commit_id),
@@ -124,6 +134,35 @@ impl<'a> IndexRef<'a> {
         }
     }
 
+    /// The name of the saved index file that contains `pos`. Since the name
+    /// identifies the file's ancestor files too, it identifies the positions
+    /// of the commit and all its ancestors. `None` if the commit was added
+    /// to the index in the current transaction.
+    pub fn segment_name_containing(&self, pos: IndexPosition) -> Option<String> {
+        match self {
+            IndexRef::Readonly(index) => CompositeIndex(*index).segment_name_containing(pos),
+            IndexRef::Mutable(index) => CompositeIndex(*index).segment_name_containing(pos),
+        }
+    }
+
+    /// The positions of `pos` and all its ancestors. `known_ancestors` can
+    /// return the precomputed set for an ancestor, in which case that
+    /// ancestor's ancestors aren't walked.
+    pub fn reachable_positions(
+        &self,
+        pos: IndexPosition,
+        known_ancestors: &dyn Fn(IndexPosition) -> Option<Arc<IndexPositionSet>>,
+    ) -> IndexPositionSet {
+        match self {
+            IndexRef::Readonly(index) => {
+                CompositeIndex(*index).reachable_positions(pos, known_ancestors)
+            }
+            IndexRef::Mutable(index) => {
+                CompositeIndex(*index).reachable_positions(pos, known_ancestors)
+            }
+        }
+    }
+
     pub fn walk_revs(&self, wanted: &[CommitId], unwanted: &[CommitId]) -> RevWalk<'a> {
         match self {
             IndexRef::Readonly(index) => index.walk_revs(wanted, unwanted),
@@ -208,6 +247,13 @@ impl CommitLookupEntry<'_> {
     }
 }
 
+/// The name of an index file with the given contents.
+fn content_hash(buf: &[u8]) -> String {
+    let mut hasher = Blake2b512::new();
+    hasher.update(buf);
+    hex::encode(&hasher.finalize())
+}
+
 #[derive(Error, Debug)]
 pub enum IndexLoadError {
     #[error("Index file '{0}' is corrupt.")]
@@ -216,6 +262,13 @@ pub enum IndexLoadError {
     IoError(#[from] io::Error),
 }
 
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("Index file '{file}' is corrupt: {message}")]
+pub struct IndexVerifyError {
+    pub file: String,
+    pub message: String,
+}
+
 // File format:
 // u32: number of entries
 // u32: number of parent overflow entries
@@ -235,6 +288,7 @@ pub enum IndexLoadError {
 pub struct ReadonlyIndex {
     parent_file: Option<Arc<ReadonlyIndex>>,
     num_parent_commits: u32,
+    dir: PathBuf,
     name: String,
     hash_length: usize,
     commit_graph_entry_size: usize,
@@ -551,9 +605,7 @@ impl MutableIndex {
         let hash_length = self.hash_length;
 
         let buf = self.maybe_squash_with_ancestors().serialize();
-        let mut hasher = Blake2b512::new();
-        hasher.update(&buf);
-        let index_file_id_hex = hex::encode(&hasher.finalize());
+        let index_file_id_hex = content_hash(&buf);
         let index_file_path = dir.join(&index_file_id_hex);
 
         let mut temp_file = NamedTempFile::new_in(&dir)?;
@@ -661,6 +713,42 @@ impl<'a> CompositeIndex<'a> {
         self.0.segment_num_parent_commits() + self.0.segment_num_commits()
     }
 
+    fn segment_name_containing(&self, pos: IndexPosition) -> Option<String> {
+        if pos.0 >= self.0.segment_num_parent_commits() {
+            self.0.segment_name()
+        } else {
+            let parent_file = self.0.segment_parent_file().unwrap();
+            CompositeIndex(parent_file.as_ref()).segment_name_containing(pos)
+        }
+    }
+
+    fn reachable_positions(
+        &self,
+        pos: IndexPosition,
+        known_ancestors: &dyn Fn(IndexPosition) -> Option<Arc<IndexPositionSet>>,
+    ) -> IndexPositionSet {
+        let mut reachable = IndexPositionSet::new();
+        // Visiting descendants before ancestors means that ancestors that are
+        // covered by a known set are skipped
+        let mut work = BinaryHeap::from([pos]);
+        while let Some(pos) = work.pop() {
+            if reachable.contains(pos) {
+                continue;
+            }
+            if let Some(known) = known_ancestors(pos) {
+                reachable.union_with(&known);
+                continue;
+            }
+            reachable.insert(pos);
+            for parent_pos in self.entry_by_pos(pos).parent_positions() {
+                if !reachable.contains(parent_pos) {
+                    work.push(parent_pos);
+                }
+            }
+        }
+        reachable
+    }
+
     pub fn stats(&self) -> IndexStats {
         let num_commits = self.num_commits();
         let mut num_merges = 0;
@@ -842,7 +930,7 @@ impl<'a> CompositeIndex<'a> {
     pub fn walk_revs(&self, wanted: &[CommitId], unwanted: &[CommitId]) -> RevWalk<'a> {
         let mut rev_walk = RevWalk::new(self.clone());
         for pos in wanted.iter().map(|id| self.commit_id_to_pos(id).unwrap()) {
-            rev_walk.add_wanted(pos);
+            rev_walk.add_wanted(pos, 0);
         }
         for pos in unwanted.iter().map(|id| self.commit_id_to_pos(id).unwrap()) {
             rev_walk.add_unwanted(pos);
@@ -974,8 +1062,11 @@ struct RevWalkWorkItem<'a> {
 pub struct RevWalk<'a> {
     index: CompositeIndex<'a>,
     items: BinaryHeap<RevWalkWorkItem<'a>>,
-    wanted_boundary_set: HashSet<IndexPosition>,
+    // The wanted entries in `items`, with the smallest number of generations
+    // they are from the entries the walk started from
+    wanted_boundary_set: HashMap<IndexPosition, u32>,
     unwanted_boundary_set: HashSet<IndexPosition>,
+    max_generation: u32,
 }
 
 impl<'a> RevWalk<'a> {
@@ -983,14 +1074,31 @@ impl<'a> RevWalk<'a> {
         Self {
             index,
             items: BinaryHeap::new(),
-            wanted_boundary_set: HashSet::new(),
+            wanted_boundary_set: HashMap::new(),
             unwanted_boundary_set: HashSet::new(),
+            max_generation: u32::MAX,
         }
     }
 
-    fn add_wanted(&mut self, pos: IndexPosition) {
-        if !self.wanted_boundary_set.insert(pos) {
-            return;
+    /// Stops the walk at entries that are more than `max_generation`
+    /// generations away from the entries it started from. The walk doesn't
+    /// visit any ancestors of such entries (unless they're reachable within
+    /// the limit through another path).
+    pub fn max_generation(mut self, max_generation: u32) -> Self {
+        self.max_generation = max_generation;
+        self
+    }
+
+    fn add_wanted(&mut self, pos: IndexPosition, generation: u32) {
+        match self.wanted_boundary_set.entry(pos) {
+            Entry::Occupied(mut entry) => {
+                let known_generation = entry.get_mut();
+                *known_generation = min(*known_generation, generation);
+                return;
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(generation);
+            }
         }
         self.items.push(RevWalkWorkItem {
             entry: IndexEntryByPosition(self.index.entry_by_pos(pos)),
@@ -1015,12 +1123,16 @@ impl<'a> Iterator for RevWalk<'a> {
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(item) = self.items.pop() {
             if item.wanted {
-                self.wanted_boundary_set.remove(&item.entry.0.pos);
+                let generation = self.wanted_boundary_set.remove(&item.entry.0.pos).unwrap();
                 if self.unwanted_boundary_set.contains(&item.entry.0.pos) {
                     continue;
                 }
-                for parent_pos in item.entry.0.parent_positions() {
-                    self.add_wanted(parent_pos);
+                // All children of an entry are visited before the entry itself, so its
+                // generation is final by now
+                if generation < self.max_generation {
+                    for parent_pos in item.entry.0.parent_positions() {
+                        self.add_wanted(parent_pos, generation + 1);
+                    }
                 }
                 return Some(item.entry.0);
             } else {
@@ -1299,25 +1411,46 @@ impl ReadonlyIndex {
         name: String,
         hash_length: usize,
     ) -> Result<Arc<ReadonlyIndex>, IndexLoadError> {
-        let parent_filename_len = file.read_u32::<LittleEndian>()?;
+        let mut buf = vec![];
+        file.read_to_end(&mut buf)?;
+        // The files are named by the hash of their contents, which lets us detect
+        // corruption (including truncation)
+        if content_hash(&buf) != name {
+            return Err(IndexLoadError::IndexCorrupt(name));
+        }
+        let corrupt = |_: io::Error| IndexLoadError::IndexCorrupt(name.clone());
+        let mut file = Cursor::new(buf);
+        let parent_filename_len = file.read_u32::<LittleEndian>().map_err(corrupt)?;
         let num_parent_commits;
         let maybe_parent_file;
         if parent_filename_len > 0 {
             let mut parent_filename_bytes = vec![0; parent_filename_len as usize];
-            file.read_exact(&mut parent_filename_bytes)?;
-            let parent_filename = String::from_utf8(parent_filename_bytes).unwrap();
+            file.read_exact(&mut parent_filename_bytes)
+                .map_err(corrupt)?;
+            let parent_filename = String::from_utf8(parent_filename_bytes)
+                .map_err(|_| IndexLoadError::IndexCorrupt(name.clone()))?;
             let parent_file_path = dir.join(&parent_filename);
-            let mut index_file = File::open(&parent_file_path).unwrap();
-            let parent_file =
-                ReadonlyIndex::load_from(&mut index_file, dir, parent_filename, hash_length)?;
+            let mut index_file = match File::open(&parent_file_path) {
+                Ok(file) => file,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    return Err(IndexLoadError::IndexCorrupt(parent_filename));
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let parent_file = ReadonlyIndex::load_from(
+                &mut index_file,
+                dir.clone(),
+                parent_filename,
+                hash_length,
+            )?;
             num_parent_commits = parent_file.num_parent_commits + parent_file.num_local_commits;
             maybe_parent_file = Some(parent_file);
         } else {
             num_parent_commits = 0;
             maybe_parent_file = None;
         };
-        let num_commits = file.read_u32::<LittleEndian>()?;
-        let num_parent_overflow_entries = file.read_u32::<LittleEndian>()?;
+        let num_commits = file.read_u32::<LittleEndian>().map_err(corrupt)?;
+        let num_parent_overflow_entries = file.read_u32::<LittleEndian>().map_err(corrupt)?;
         let mut data = vec![];
         file.read_to_end(&mut data)?;
         let commit_graph_entry_size = CommitGraphEntry::size(hash_length);
@@ -1335,6 +1468,7 @@ impl ReadonlyIndex {
         Ok(Arc::new(ReadonlyIndex {
             parent_file: maybe_parent_file,
             num_parent_commits,
+            dir,
             name,
             hash_length,
             commit_graph_entry_size,
@@ -1346,6 +1480,85 @@ impl ReadonlyIndex {
         }))
     }
 
+    /// Checks that the index files on disk still match their checksums, and
+    /// that the entries are consistent: parents come before their children,
+    /// generation numbers are correct, and the lookup table is sorted and
+    /// points to the right entries.
+    pub fn verify(&self) -> Result<(), IndexVerifyError> {
+        if let Some(parent_file) = &self.parent_file {
+            parent_file.verify()?;
+        }
+        let error = |message: String| IndexVerifyError {
+            file: self.name.clone(),
+            message,
+        };
+        match std::fs::read(self.dir.join(&self.name)) {
+            Ok(buf) if content_hash(&buf) == self.name => {}
+            Ok(_) => return Err(error("checksum mismatch".to_string())),
+            Err(err) => return Err(error(format!("failed to read file: {err}"))),
+        }
+        let num_overflow_parents = (self.overflow_parent.len() / 4) as u32;
+        let composite = CompositeIndex(self);
+        for local_pos in 0..self.num_local_commits {
+            let pos = IndexPosition(self.num_parent_commits + local_pos);
+            let graph_entry = self.graph_entry(local_pos);
+            let num_parents = graph_entry.num_parents();
+            if num_parents >= 2
+                && graph_entry.parent2_overflow_pos() as u64 + num_parents as u64 - 1
+                    > num_overflow_parents as u64
+            {
+                return Err(error(format!(
+                    "parents of entry {} are out of bounds",
+                    pos.0
+                )));
+            }
+            let mut expected_generation = 0;
+            for parent_pos in self.segment_parent_positions(local_pos) {
+                if parent_pos >= pos {
+                    return Err(error(format!(
+                        "entry {} has parent {} that comes after it",
+                        pos.0, parent_pos.0
+                    )));
+                }
+                expected_generation = max(
+                    expected_generation,
+                    composite.entry_by_pos(parent_pos).generation_number() + 1,
+                );
+            }
+            if graph_entry.generation_number() != expected_generation {
+                return Err(error(format!(
+                    "entry {} has generation number {}, expected {}",
+                    pos.0,
+                    graph_entry.generation_number(),
+                    expected_generation
+                )));
+            }
+        }
+        let mut previous_commit_id = None;
+        for lookup_pos in 0..self.num_local_commits {
+            let lookup_entry = self.lookup_entry(lookup_pos);
+            let commit_id = lookup_entry.commit_id();
+            if previous_commit_id.as_ref() >= Some(&commit_id) {
+                return Err(error("lookup table isn't sorted".to_string()));
+            }
+            let pos = lookup_entry.pos();
+            if pos.0 < self.num_parent_commits
+                || pos.0 >= self.num_parent_commits + self.num_local_commits
+                || self
+                    .graph_entry(pos.0 - self.num_parent_commits)
+                    .commit_id()
+                    != commit_id
+            {
+                return Err(error(format!(
+                    "lookup entry for commit {} points to the wrong entry",
+                    commit_id.hex()
+                )));
+            }
+            previous_commit_id = Some(commit_id);
+        }
+        Ok(())
+    }
+
     pub fn as_index_ref(self: &ReadonlyIndex) -> IndexRef {
         IndexRef::Readonly(self)
     }
@@ -1460,8 +1673,127 @@ impl ReadonlyIndex {
     }
 }
 
+/// A set of index positions stored as a bitmap. Unions, intersections, and
+/// differences of large sets are much cheaper on this representation than by
+/// merging sorted lists of entries.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IndexPositionSet {
+    words: Vec<u64>,
+}
+
+impl IndexPositionSet {
+    const WORD_BITS: u32 = u64::BITS;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn word_and_mask(pos: IndexPosition) -> (usize, u64) {
+        (
+            (pos.0 / Self::WORD_BITS) as usize,
+            1 << (pos.0 % Self::WORD_BITS),
+        )
+    }
+
+    pub fn insert(&mut self, pos: IndexPosition) {
+        let (word, mask) = Self::word_and_mask(pos);
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= mask;
+    }
+
+    pub fn contains(&self, pos: IndexPosition) -> bool {
+        let (word, mask) = Self::word_and_mask(pos);
+        self.words.get(word).map_or(false, |bits| bits & mask != 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|bits| bits.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|bits| *bits == 0)
+    }
+
+    pub fn union_with(&mut self, other: &IndexPositionSet) {
+        if other.words.len() > self.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (bits, other_bits) in self.words.iter_mut().zip(&other.words) {
+            *bits |= other_bits;
+        }
+    }
+
+    pub fn intersect_with(&mut self, other: &IndexPositionSet) {
+        self.words.truncate(other.words.len());
+        for (bits, other_bits) in self.words.iter_mut().zip(&other.words) {
+            *bits &= other_bits;
+        }
+    }
+
+    pub fn difference_with(&mut self, other: &IndexPositionSet) {
+        for (bits, other_bits) in self.words.iter_mut().zip(&other.words) {
+            *bits &= !other_bits;
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.words.len() * 8);
+        for bits in &self.words {
+            buf.write_u64::<LittleEndian>(*bits).unwrap();
+        }
+        buf
+    }
+
+    /// Reads a set written by `to_bytes()`. Trailing bytes that don't make up
+    /// a full word are ignored.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let words = bytes
+            .chunks_exact(8)
+            .map(|mut chunk| chunk.read_u64::<LittleEndian>().unwrap())
+            .collect();
+        IndexPositionSet { words }
+    }
+
+    /// Iterates over the positions from the highest to the lowest, which is
+    /// the order revsets are iterated in.
+    pub fn iter_descending(&self) -> impl Iterator<Item = IndexPosition> + '_ {
+        self.words
+            .iter()
+            .enumerate()
+            .rev()
+            .flat_map(|(word, bits)| {
+                let base = word as u32 * Self::WORD_BITS;
+                let mut bits = *bits;
+                std::iter::from_fn(move || {
+                    if bits == 0 {
+                        return None;
+                    }
+                    let bit = Self::WORD_BITS - 1 - bits.leading_zeros();
+                    bits &= !(1 << bit);
+                    Some(IndexPosition(base + bit))
+                })
+            })
+    }
+}
+
+impl FromIterator<IndexPosition> for IndexPositionSet {
+    fn from_iter<T: IntoIterator<Item = IndexPosition>>(iter: T) -> Self {
+        let mut set = IndexPositionSet::new();
+        for pos in iter {
+            set.insert(pos);
+        }
+        set
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use assert_matches::assert_matches;
     use test_case::test_case;
 
     use super::*;
@@ -2058,4 +2390,138 @@ mod tests {
         // Merge commit and other commit
         assert_eq!(index.heads(&[id_5.clone(), id_3.clone()]), vec![id_3, id_5]);
     }
+
+    #[test]
+    fn test_verify_and_detect_corruption() {
+        let temp_dir = testutils::new_temp_dir();
+        let mut index = MutableIndex::full(3);
+        let id_0 = CommitId::from_hex("000000");
+        let id_1 = CommitId::from_hex("111111");
+        let id_2 = CommitId::from_hex("222222");
+        index.add_commit_data(id_0.clone(), new_change_id(), &[]);
+        index.add_commit_data(id_1.clone(), new_change_id(), &[id_0.clone()]);
+        index.add_commit_data(id_2, new_change_id(), &[id_0, id_1]);
+        let index = index.save_in(temp_dir.path().to_owned()).unwrap();
+        assert_eq!(index.verify(), Ok(()));
+
+        let index_path = temp_dir.path().join(index.name());
+        let buf = std::fs::read(&index_path).unwrap();
+        let load = |buf: &[u8]| {
+            ReadonlyIndex::load_from(
+                &mut &buf[..],
+                temp_dir.path().to_owned(),
+                index.name().to_string(),
+                3,
+            )
+        };
+        assert!(load(&buf).is_ok());
+        // Truncated file
+        assert_matches!(
+            load(&buf[..buf.len() - 1]),
+            Err(IndexLoadError::IndexCorrupt(name)) if name == index.name()
+        );
+        // Flipped bit
+        let mut modified_buf = buf.clone();
+        modified_buf[20] ^= 1;
+        assert_matches!(
+            load(&modified_buf),
+            Err(IndexLoadError::IndexCorrupt(name)) if name == index.name()
+        );
+
+        // A file that was damaged after it was loaded
+        std::fs::write(&index_path, &modified_buf).unwrap();
+        assert_eq!(
+            index.verify(),
+            Err(IndexVerifyError {
+                file: index.name().to_string(),
+                message: "checksum mismatch".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_reachable_positions() {
+        let mut index = MutableIndex::full(3);
+        // 4
+        // |\
+        // 2 3
+        // |/
+        // 1
+        // |
+        // 0
+        let id_0 = CommitId::from_hex("000000");
+        let id_1 = CommitId::from_hex("111111");
+        let id_2 = CommitId::from_hex("222222");
+        let id_3 = CommitId::from_hex("333333");
+        let id_4 = CommitId::from_hex("444444");
+        index.add_commit_data(id_0.clone(), new_change_id(), &[]);
+        index.add_commit_data(id_1.clone(), new_change_id(), &[id_0]);
+        index.add_commit_data(id_2.clone(), new_change_id(), &[id_1.clone()]);
+        index.add_commit_data(id_3.clone(), new_change_id(), &[id_1]);
+        index.add_commit_data(id_4, new_change_id(), &[id_2, id_3]);
+        let index = IndexRef::Mutable(&index);
+
+        let reachable = index.reachable_positions(IndexPosition(4), &|_| None);
+        assert_eq!(
+            reachable.iter_descending().collect_vec(),
+            [4, 3, 2, 1, 0].map(IndexPosition).to_vec()
+        );
+        let reachable = index.reachable_positions(IndexPosition(3), &|_| None);
+        assert_eq!(
+            reachable.iter_descending().collect_vec(),
+            [3, 1, 0].map(IndexPosition).to_vec()
+        );
+
+        // A known set is used instead of walking the ancestors (so a bogus set
+        // shows up in the result)
+        let known: Arc<IndexPositionSet> =
+            Arc::new([2, 1].map(IndexPosition).into_iter().collect());
+        let reachable = index.reachable_positions(IndexPosition(4), &|pos| {
+            (pos == IndexPosition(2)).then(|| known.clone())
+        });
+        assert_eq!(
+            reachable.iter_descending().collect_vec(),
+            [4, 3, 2, 1].map(IndexPosition).to_vec()
+        );
+        // The mutable index isn't stored, so its commits have no segment name
+        assert_eq!(index.segment_name_containing(IndexPosition(0)), None);
+    }
+
+    #[test]
+    fn test_index_position_set() {
+        let positions = [0, 1, 63, 64, 130].map(IndexPosition);
+        let set: IndexPositionSet = positions.iter().copied().collect();
+        assert_eq!(set.len(), 5);
+        assert!(!set.is_empty());
+        assert!(set.contains(IndexPosition(63)));
+        assert!(!set.contains(IndexPosition(62)));
+        assert!(!set.contains(IndexPosition(1000)));
+        assert_eq!(
+            set.iter_descending().collect_vec(),
+            positions.iter().rev().copied().collect_vec()
+        );
+
+        let other: IndexPositionSet = [1, 64, 65].map(IndexPosition).into_iter().collect();
+        let mut union = set.clone();
+        union.union_with(&other);
+        assert_eq!(
+            union.iter_descending().collect_vec(),
+            [130, 65, 64, 63, 1, 0].map(IndexPosition).to_vec()
+        );
+        let mut intersection = set.clone();
+        intersection.intersect_with(&other);
+        assert_eq!(
+            intersection.iter_descending().collect_vec(),
+            [64, 1].map(IndexPosition).to_vec()
+        );
+        let mut difference = set.clone();
+        difference.difference_with(&other);
+        assert_eq!(
+            difference.iter_descending().collect_vec(),
+            [130, 63, 0].map(IndexPosition).to_vec()
+        );
+        assert_eq!(IndexPositionSet::from_bytes(&union.to_bytes()), union);
+        assert!(IndexPositionSet::new().is_empty());
+        assert_eq!(IndexPositionSet::new().iter_descending().next(), None);
+    }
 }
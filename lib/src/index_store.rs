@@ -20,6 +20,7 @@ use std::path::PathBuf;
 use std::sync::Arc;
 
 use itertools::Itertools;
+use rayon::prelude::*;
 use tempfile::NamedTempFile;
 
 use crate::backend::CommitId;
@@ -42,10 +43,30 @@ impl IndexStore {
     }
 
     pub fn reinit(&self) {
-        std::fs::remove_dir_all(self.dir.join("operations")).unwrap();
+        // May have been removed already by an interrupted reinit
+        std::fs::remove_dir_all(self.dir.join("operations")).ok();
+        // Created on demand
+        std::fs::remove_dir_all(self.changed_paths_dir()).ok();
+        std::fs::remove_dir_all(self.commit_metadata_dir()).ok();
+        std::fs::remove_dir_all(self.reachability_dir()).ok();
         IndexStore::init(self.dir.clone());
     }
 
+    /// The directory the `ChangedPathIndex` is stored in.
+    pub fn changed_paths_dir(&self) -> PathBuf {
+        self.dir.join("changed_paths")
+    }
+
+    /// The directory the `CommitMetadataCache` is stored in.
+    pub fn commit_metadata_dir(&self) -> PathBuf {
+        self.dir.join("commit_metadata")
+    }
+
+    /// The directory the `ReachabilityBitmaps` are stored in.
+    pub fn reachability_dir(&self) -> PathBuf {
+        self.dir.join("reachability")
+    }
+
     pub fn load(dir: PathBuf) -> IndexStore {
         IndexStore { dir }
     }
@@ -55,13 +76,18 @@ impl IndexStore {
         let op_id_file = self.dir.join("operations").join(&op_id_hex);
         if op_id_file.exists() {
             match self.load_index_at_operation(store.hash_length(), op.id()) {
-                Err(IndexLoadError::IndexCorrupt(_)) => {
-                    // If the index was corrupt (maybe it was written in a different format),
-                    // we just reindex.
+                Err(IndexLoadError::IndexCorrupt(name)) => {
+                    // If the index was corrupt (maybe it was written in a different format,
+                    // or the file was damaged), we just reindex from the operation log.
                     // TODO: Move this message to a callback or something.
-                    println!("The index was corrupt (maybe the format has changed). Reindexing...");
+                    eprintln!(
+                        "Warning: The index file '{name}' was corrupt (maybe the format has \
+                         changed). Reindexing..."
+                    );
                     std::fs::remove_dir_all(self.dir.join("operations")).unwrap();
                     std::fs::create_dir(self.dir.join("operations")).unwrap();
+                    // Remove the damaged file so it gets written again
+                    std::fs::remove_file(self.dir.join(&name)).ok();
                     self.index_at_operation(store, op).unwrap()
                 }
                 result => result.unwrap(),
@@ -71,6 +97,21 @@ impl IndexStore {
         }
     }
 
+    /// Indexes the operation again, starting from the index of the nearest
+    /// ancestor operation that has one.
+    pub fn reindex_at_op(
+        &self,
+        op: &Operation,
+        store: &Arc<Store>,
+    ) -> io::Result<Arc<ReadonlyIndex>> {
+        let op_id_file = self.dir.join("operations").join(op.id().hex());
+        match std::fs::remove_file(op_id_file) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        self.index_at_operation(store, op)
+    }
+
     pub fn write_index(&self, index: MutableIndex) -> io::Result<Arc<ReadonlyIndex>> {
         index.save_in(self.dir.clone())
     }
@@ -88,7 +129,13 @@ impl IndexStore {
             .unwrap();
         let index_file_id_hex = String::from_utf8(buf).unwrap();
         let index_file_path = self.dir.join(&index_file_id_hex);
-        let mut index_file = File::open(&index_file_path).unwrap();
+        let mut index_file = match File::open(&index_file_path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(IndexLoadError::IndexCorrupt(index_file_id_hex));
+            }
+            Err(err) => return Err(err.into()),
+        };
         ReadonlyIndex::load_from(
             &mut index_file,
             self.dir.clone(),
@@ -140,7 +187,10 @@ impl IndexStore {
 
         let mut heads = new_heads.into_iter().collect_vec();
         heads.sort();
-        let commits = topo_order_earlier_first(store, heads, maybe_parent_file);
+        let parent_file_source = maybe_parent_file.as_ref().map(|file| file.as_ref());
+        let commits = topo_order_earlier_first(store, heads, true, &|id| {
+            parent_file_source.map_or(false, |index| index.has_id(id))
+        });
 
         for commit in &commits {
             data.add_commit(commit);
@@ -170,36 +220,48 @@ impl IndexStore {
     }
 }
 
-// Returns the ancestors of heads with parents and predecessors come before the
-// commit itself
-fn topo_order_earlier_first(
+/// Returns the ancestors of `heads` that `is_indexed` returns false for, with
+/// parents (and predecessors, if `include_predecessors` is set) before the
+/// commit itself. The walk reads the commits from the store in batches, one
+/// generation at a time, with the commits in a batch read in parallel. That
+/// matters when many commits were added at once, such as after a large fetch.
+pub(crate) fn topo_order_earlier_first(
     store: &Arc<Store>,
     heads: Vec<CommitId>,
-    parent_file: Option<Arc<ReadonlyIndex>>,
+    include_predecessors: bool,
+    is_indexed: &dyn Fn(&CommitId) -> bool,
 ) -> Vec<Commit> {
-    // First create a list of all commits in topological order with
-    // children/successors first (reverse of what we want)
-    let mut work = vec![];
-    for head in &heads {
-        work.push(store.get_commit(head).unwrap());
-    }
-    let mut commits = vec![];
-    let mut visited = HashSet::new();
-    let mut in_parent_file = HashSet::new();
-    let parent_file_source = parent_file.as_ref().map(|file| file.as_ref());
-    while let Some(commit) = work.pop() {
-        if parent_file_source.map_or(false, |index| index.has_id(commit.id())) {
-            in_parent_file.insert(commit.id().clone());
-            continue;
-        } else if !visited.insert(commit.id().clone()) {
-            continue;
+    let earlier_ids = |commit: &Commit| -> Vec<CommitId> {
+        let mut ids = commit.parent_ids().to_vec();
+        if include_predecessors {
+            ids.extend(commit.predecessor_ids().iter().cloned());
         }
+        ids
+    };
 
-        work.extend(commit.parents());
-        work.extend(commit.predecessors());
-        commits.push(commit);
+    // First read all the commits that need indexing, children/successors first
+    // (reverse of what we want)
+    let mut visited = HashSet::new();
+    let mut batch = heads
+        .into_iter()
+        .filter(|id| !is_indexed(id) && visited.insert(id.clone()))
+        .collect_vec();
+    let mut commits = vec![];
+    while !batch.is_empty() {
+        let batch_commits: Vec<Commit> = batch
+            .par_iter()
+            .map(|id| store.get_commit(id).unwrap())
+            .collect();
+        batch = vec![];
+        for commit in &batch_commits {
+            for id in earlier_ids(commit) {
+                if !is_indexed(&id) && visited.insert(id.clone()) {
+                    batch.push(id);
+                }
+            }
+        }
+        commits.extend(batch_commits);
     }
-    drop(visited);
 
     // Now create the topological order with earlier commits first. If we run into
     // any commits whose parents/predecessors have not all been indexed, put
@@ -211,13 +273,13 @@ fn topo_order_earlier_first(
     let mut waiting = HashMap::new();
 
     let mut result = vec![];
-    let mut visited = in_parent_file;
+    let mut added = HashSet::new();
     while let Some(commit) = commits.pop() {
         let mut waiting_for_earlier_commit = false;
-        for earlier in commit.parents().iter().chain(commit.predecessors().iter()) {
-            if !visited.contains(earlier.id()) {
+        for earlier_id in earlier_ids(&commit) {
+            if !added.contains(&earlier_id) && !is_indexed(&earlier_id) {
                 waiting
-                    .entry(earlier.id().clone())
+                    .entry(earlier_id)
                     .or_insert_with(Vec::new)
                     .push(commit.clone());
                 waiting_for_earlier_commit = true;
@@ -225,7 +287,7 @@ fn topo_order_earlier_first(
             }
         }
         if !waiting_for_earlier_commit {
-            visited.insert(commit.id().clone());
+            added.insert(commit.id().clone());
             if let Some(dependents) = waiting.remove(commit.id()) {
                 commits.extend(dependents);
             }
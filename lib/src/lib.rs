@@ -12,31 +12,63 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+//! The library behind the `jj` CLI. Tools that build on it should start from
+//! the [`prelude`] module, which re-exports the main types (repos, workspaces,
+//! transactions, revsets and matchers).
+//!
+//! Modules marked as hidden in the documentation are implementation details of
+//! the CLI and the backends. They're public so the CLI can use them, but they
+//! may change in any release.
+
 #![deny(unused_must_use)]
 #![cfg_attr(feature = "map_first_last", feature(map_first_last))]
 
 pub mod backend;
+pub mod backend_migration;
+pub mod change_identity;
+pub mod changed_path_index;
 pub mod commit;
 pub mod commit_builder;
+pub mod commit_metadata_cache;
 pub mod conflicts;
+pub mod content_cache;
+pub mod content_filter;
+pub mod copies;
 pub mod dag_walk;
 pub mod diff;
+pub mod fast_import;
+#[doc(hidden)]
 pub mod file_util;
 pub mod files;
+pub mod gerrit;
 pub mod git;
 pub mod git_backend;
 pub mod gitignore;
+pub mod hg;
+pub mod hooks;
 pub mod index;
 pub mod index_store;
+pub mod line_endings;
 pub mod local_backend;
+#[doc(hidden)]
 pub mod lock;
+#[doc(hidden)]
+pub mod lru_cache;
+pub mod mailmap;
 pub mod matchers;
+pub mod merge_attributes;
+#[doc(hidden)]
 pub mod nightly_shims;
+pub mod object_store;
 pub mod op_heads_store;
 pub mod op_store;
 pub mod operation;
+pub mod prelude;
+#[doc(hidden)]
 pub mod protos;
+pub mod reachability;
 pub mod refs;
+pub mod remote_backend;
 pub mod repo;
 pub mod repo_path;
 pub mod revset;
@@ -44,12 +76,17 @@ pub mod revset_graph_iterator;
 pub mod rewrite;
 pub mod settings;
 pub mod simple_op_store;
+#[doc(hidden)]
 pub mod stacked_table;
 pub mod store;
+#[doc(hidden)]
 pub mod testutils;
+pub mod trailers;
 pub mod transaction;
 pub mod tree;
 pub mod tree_builder;
 pub mod view;
+pub mod windows_paths;
 pub mod working_copy;
 pub mod workspace;
+pub mod workspace_cache;
@@ -0,0 +1,215 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Detection of snapshots that change a file's line endings or byte-order
+//! mark wholesale.
+//!
+//! Such changes are usually caused by a misconfigured editor rather than made
+//! on purpose, and they make every line of the file show up in diffs.
+
+use std::fmt;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// What `snapshot()` does about files whose line endings or byte-order mark
+/// changed wholesale.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LineEndingPolicy {
+    /// Don't look for such changes.
+    Ignore,
+    /// Record the changes and report the files (see
+    /// `LockedWorkingCopy::line_ending_changes()`).
+    Warn,
+    /// Record the files with their previous line endings and byte-order mark
+    /// instead. The files in the working copy are left as they are.
+    Normalize,
+    /// Fail the snapshot with `SnapshotError::LineEndingChanges`.
+    Block,
+}
+
+impl Default for LineEndingPolicy {
+    fn default() -> Self {
+        LineEndingPolicy::Warn
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineEnding::Lf => f.write_str("LF"),
+            LineEnding::Crlf => f.write_str("CRLF"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum LineEndingChange {
+    LineEndings { from: LineEnding, to: LineEnding },
+    BomAdded,
+    BomRemoved,
+}
+
+impl fmt::Display for LineEndingChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineEndingChange::LineEndings { from, to } => {
+                write!(f, "line endings changed from {from} to {to}")
+            }
+            LineEndingChange::BomAdded => f.write_str("byte-order mark added"),
+            LineEndingChange::BomRemoved => f.write_str("byte-order mark removed"),
+        }
+    }
+}
+
+/// Files larger than this aren't checked for line-ending changes, since that
+/// needs the old and new contents in memory. They're streamed to the store
+/// instead.
+pub const MAX_CHECKED_FILE_SIZE: u64 = 1 << 20;
+
+pub fn is_binary(contents: &[u8]) -> bool {
+    contents.contains(&0)
+}
+
+/// The line ending used by all lines of `contents`, or `None` if it has no
+/// line endings or a mix of them.
+fn line_ending(contents: &[u8]) -> Option<LineEnding> {
+    let num_lf = contents.iter().filter(|&&b| b == b'\n').count();
+    let num_crlf = contents.windows(2).filter(|w| w == b"\r\n").count();
+    if num_lf == 0 {
+        None
+    } else if num_crlf == 0 {
+        Some(LineEnding::Lf)
+    } else if num_crlf == num_lf {
+        Some(LineEnding::Crlf)
+    } else {
+        None
+    }
+}
+
+/// Returns the wholesale line-ending and byte-order-mark changes from `old` to
+/// `new`. Files that mix line endings and binary files never have any.
+pub fn detect_changes(old: &[u8], new: &[u8]) -> Vec<LineEndingChange> {
+    if is_binary(old) || is_binary(new) {
+        return vec![];
+    }
+    let mut changes = vec![];
+    if let (Some(from), Some(to)) = (line_ending(old), line_ending(new)) {
+        if from != to {
+            changes.push(LineEndingChange::LineEndings { from, to });
+        }
+    }
+    match (old.starts_with(UTF8_BOM), new.starts_with(UTF8_BOM)) {
+        (false, true) => changes.push(LineEndingChange::BomAdded),
+        (true, false) => changes.push(LineEndingChange::BomRemoved),
+        _ => {}
+    }
+    changes
+}
+
+/// Undoes `changes` (as returned by `detect_changes()`) in `contents`.
+pub fn normalize(contents: &[u8], changes: &[LineEndingChange]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(contents.len());
+    if changes.contains(&LineEndingChange::BomRemoved) {
+        result.extend_from_slice(UTF8_BOM);
+    }
+    let contents = if changes.contains(&LineEndingChange::BomAdded) {
+        &contents[UTF8_BOM.len()..]
+    } else {
+        contents
+    };
+    let restore = changes.iter().find_map(|change| match change {
+        LineEndingChange::LineEndings { from, .. } => Some(*from),
+        _ => None,
+    });
+    match restore {
+        None => result.extend_from_slice(contents),
+        Some(LineEnding::Lf) => {
+            let mut iter = contents.iter().peekable();
+            while let Some(&b) = iter.next() {
+                if !(b == b'\r' && iter.peek() == Some(&&b'\n')) {
+                    result.push(b);
+                }
+            }
+        }
+        Some(LineEnding::Crlf) => {
+            for &b in contents {
+                if b == b'\n' {
+                    result.push(b'\r');
+                }
+                result.push(b);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_changes() {
+        assert_eq!(detect_changes(b"a\nb\n", b"a\nc\n"), vec![]);
+        assert_eq!(
+            detect_changes(b"a\nb\n", b"a\r\nb\r\n"),
+            vec![LineEndingChange::LineEndings {
+                from: LineEnding::Lf,
+                to: LineEnding::Crlf
+            }]
+        );
+        assert_eq!(
+            detect_changes(b"\xEF\xBB\xBFa\r\n", b"a\n"),
+            vec![
+                LineEndingChange::LineEndings {
+                    from: LineEnding::Crlf,
+                    to: LineEnding::Lf
+                },
+                LineEndingChange::BomRemoved
+            ]
+        );
+        assert_eq!(
+            detect_changes(b"a\n", b"\xEF\xBB\xBFa\n"),
+            vec![LineEndingChange::BomAdded]
+        );
+        // Mixed line endings and binary files are left alone
+        assert_eq!(detect_changes(b"a\r\nb\n", b"a\nb\n"), vec![]);
+        assert_eq!(detect_changes(b"a\0\n", b"a\0\r\n"), vec![]);
+    }
+
+    #[test]
+    fn test_normalize() {
+        for (old, new) in [
+            (&b"a\nb\n"[..], &b"a\r\nc\r\n"[..]),
+            (b"a\r\nb\r\n", b"a\nc"),
+            (b"a\n", b"\xEF\xBB\xBFc\n"),
+            (b"\xEF\xBB\xBFa\r\n", b"c\n"),
+        ] {
+            let normalized = normalize(new, &detect_changes(old, new));
+            assert_eq!(detect_changes(old, &normalized), vec![]);
+        }
+        assert_eq!(
+            normalize(
+                b"\xEF\xBB\xBFa\r\nb\r\n",
+                &detect_changes(b"a\n", b"\xEF\xBB\xBFa\r\nb\r\n")
+            ),
+            b"a\nb\n"
+        );
+    }
+}
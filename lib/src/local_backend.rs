@@ -12,20 +12,23 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::fs;
 use std::fs::File;
-use std::io::{ErrorKind, Read, Write};
+use std::io::{BufReader, Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 
 use blake2::{Blake2b512, Digest};
 use protobuf::{Message, MessageField};
 use tempfile::{NamedTempFile, PersistError};
 
 use crate::backend::{
-    make_root_commit, Backend, BackendError, BackendResult, ChangeId, Commit, CommitId, Conflict,
-    ConflictId, ConflictPart, FileId, MillisSinceEpoch, Signature, SymlinkId, Timestamp, Tree,
-    TreeId, TreeValue,
+    make_root_commit, Backend, BackendError, BackendResult, ChangeId, ChunkStats, CoAuthor, Commit,
+    CommitId, Conflict, ConflictId, ConflictPart, CopyRecord, FileId, MillisSinceEpoch, ObjectKind,
+    Signature, SymlinkId, Timestamp, Tree, TreeId, TreeValue,
 };
 use crate::file_util::persist_content_addressed_temp_file;
 use crate::repo_path::{RepoPath, RepoPathComponent};
@@ -48,11 +51,173 @@ impl From<protobuf::Error> for BackendError {
     }
 }
 
+const OBJECT_KINDS: [ObjectKind; 5] = [
+    ObjectKind::Commit,
+    ObjectKind::Tree,
+    ObjectKind::File,
+    ObjectKind::Symlink,
+    ObjectKind::Conflict,
+];
+
+/// Start of every pack index file. The rest is a list of entries.
+const PACK_INDEX_MAGIC: &[u8] = b"JJPACK01";
+/// Length of a pack index entry: kind (1 byte), id (64 bytes), offset and
+/// length in the pack file (8 bytes each, little-endian).
+const PACK_INDEX_ENTRY_LENGTH: usize = 1 + 64 + 8 + 8;
+/// Once there are this many packs, packing merges them all into the new pack,
+/// so reads don't have to look through ever more indexes.
+const MAX_PACKS: usize = 16;
+
+/// Files at least this large are stored as content-defined chunks, so versions
+/// of a large file that differ in small parts share most of their storage.
+const CHUNKING_THRESHOLD: usize = 1 << 20;
+const MIN_CHUNK_SIZE: usize = 16 << 10;
+const AVG_CHUNK_SIZE: usize = 64 << 10;
+const MAX_CHUNK_SIZE: usize = 256 << 10;
+/// Start of a file object that lists the file's chunks instead of holding its
+/// (compressed) contents. The rest is a list of entries.
+const CHUNKED_FILE_MAGIC: &[u8] = b"JJCHUNK1";
+/// Length of a chunk list entry: chunk id (64 bytes) and the chunk's length (8
+/// bytes, little-endian).
+const CHUNK_ENTRY_LENGTH: usize = 64 + 8;
+
+/// Random values for the gear hash used for finding chunk boundaries. They're
+/// generated by splitmix64 so they're fixed without having to be written out.
+const GEAR: [u64; 256] = {
+    let mut table = [0; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+/// Returns the length of the chunk at the start of `data` using FastCDC: the
+/// chunk ends where a rolling gear hash of the preceding 64 bytes has enough
+/// leading zero bits. The condition is stricter before the average chunk size
+/// than after it ("normalized chunking"), so chunk sizes cluster around it.
+/// `data` must extend to the end of the file or be at least `MAX_CHUNK_SIZE`
+/// long.
+fn chunk_length(data: &[u8]) -> usize {
+    const MASK_SMALL: u64 = !0 << (64 - 18);
+    const MASK_LARGE: u64 = !0 << (64 - 14);
+    if data.len() <= MIN_CHUNK_SIZE {
+        return data.len();
+    }
+    let end = data.len().min(MAX_CHUNK_SIZE);
+    let normal = end.min(AVG_CHUNK_SIZE);
+    let mut hash: u64 = 0;
+    for (i, byte) in data.iter().enumerate().take(end).skip(MIN_CHUNK_SIZE) {
+        hash = (hash << 1).wrapping_add(GEAR[usize::from(*byte)]);
+        let mask = if i < normal { MASK_SMALL } else { MASK_LARGE };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+fn parse_chunk_list(buf: &[u8]) -> BackendResult<Vec<(Vec<u8>, u64)>> {
+    if buf.len() % CHUNK_ENTRY_LENGTH != 0 {
+        return Err(BackendError::Other("Invalid chunk list".to_string()));
+    }
+    Ok(buf
+        .chunks_exact(CHUNK_ENTRY_LENGTH)
+        .map(|entry| {
+            let length = u64::from_le_bytes(entry[64..].try_into().unwrap());
+            (entry[..64].to_vec(), length)
+        })
+        .collect())
+}
+
+/// Reads the contents of a chunked file, opening one chunk at a time.
+struct ChunkedFileReader {
+    chunks_dir: PathBuf,
+    chunk_ids: std::vec::IntoIter<Vec<u8>>,
+    current: Option<zstd::Decoder<'static, BufReader<File>>>,
+}
+
+impl Read for ChunkedFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            let decoder = match &mut self.current {
+                Some(decoder) => decoder,
+                None => match self.chunk_ids.next() {
+                    Some(id) => {
+                        let file = File::open(self.chunks_dir.join(hex::encode(id)))?;
+                        self.current.insert(zstd::Decoder::new(file)?)
+                    }
+                    None => return Ok(0),
+                },
+            };
+            let bytes_read = decoder.read(buf)?;
+            if bytes_read > 0 || buf.is_empty() {
+                return Ok(bytes_read);
+            }
+            self.current = None;
+        }
+    }
+}
+
+/// A pack file holds the stored (possibly compressed) bytes of many objects
+/// back to back. Its `.idx` file next to it says where each object is.
+#[derive(Debug)]
+struct Pack {
+    name: String,
+    entries: HashMap<(ObjectKind, Vec<u8>), (u64, u64)>,
+}
+
 #[derive(Debug)]
 pub struct LocalBackend {
     path: PathBuf,
     root_commit_id: CommitId,
     empty_tree_id: TreeId,
+    /// Read when an object is first looked up, so a bad pack is reported by
+    /// the read instead of when the backend is loaded
+    packs: Mutex<Option<Vec<Pack>>>,
+    /// Whether loose objects were written since the backend was loaded
+    wrote_loose_objects: AtomicBool,
+}
+
+fn object_kind_to_byte(kind: ObjectKind) -> u8 {
+    match kind {
+        ObjectKind::Commit => 0,
+        ObjectKind::Tree => 1,
+        ObjectKind::File => 2,
+        ObjectKind::Symlink => 3,
+        ObjectKind::Conflict => 4,
+    }
+}
+
+fn object_kind_from_byte(byte: u8) -> Option<ObjectKind> {
+    OBJECT_KINDS.get(usize::from(byte)).copied()
+}
+
+fn read_pack_index(name: &str, index_path: &Path) -> BackendResult<Pack> {
+    let invalid = || BackendError::Other(format!("Invalid pack index {}", index_path.display()));
+    let buf = fs::read(index_path)?;
+    let entries_buf = buf.strip_prefix(PACK_INDEX_MAGIC).ok_or_else(invalid)?;
+    if entries_buf.len() % PACK_INDEX_ENTRY_LENGTH != 0 {
+        return Err(invalid());
+    }
+    let mut entries = HashMap::new();
+    for entry in entries_buf.chunks_exact(PACK_INDEX_ENTRY_LENGTH) {
+        let kind = object_kind_from_byte(entry[0]).ok_or_else(invalid)?;
+        let id = entry[1..65].to_vec();
+        let offset = u64::from_le_bytes(entry[65..73].try_into().unwrap());
+        let length = u64::from_le_bytes(entry[73..81].try_into().unwrap());
+        entries.insert((kind, id), (offset, length));
+    }
+    Ok(Pack {
+        name: name.to_string(),
+        entries,
+    })
 }
 
 impl LocalBackend {
@@ -77,6 +242,8 @@ impl LocalBackend {
             path: store_path.to_path_buf(),
             root_commit_id,
             empty_tree_id,
+            packs: Mutex::new(None),
+            wrote_loose_objects: AtomicBool::new(false),
         }
     }
 
@@ -84,6 +251,12 @@ impl LocalBackend {
         self.path.join("files").join(id.hex())
     }
 
+    /// Chunks are stored loose (compressed) in this directory, which is
+    /// created when the first chunk is written. They're never packed.
+    fn chunks_dir(&self) -> PathBuf {
+        self.path.join("chunks")
+    }
+
     fn symlink_path(&self, id: &SymlinkId) -> PathBuf {
         self.path.join("symlinks").join(id.hex())
     }
@@ -99,63 +272,388 @@ impl LocalBackend {
     fn conflict_path(&self, id: &ConflictId) -> PathBuf {
         self.path.join("conflicts").join(id.hex())
     }
-}
 
-fn not_found_to_backend_error(err: std::io::Error) -> BackendError {
-    if err.kind() == ErrorKind::NotFound {
-        BackendError::NotFound
-    } else {
-        BackendError::from(err)
+    fn object_dir(&self, kind: ObjectKind) -> PathBuf {
+        let dir = match kind {
+            ObjectKind::Commit => "commits",
+            ObjectKind::Tree => "trees",
+            ObjectKind::File => "files",
+            ObjectKind::Symlink => "symlinks",
+            ObjectKind::Conflict => "conflicts",
+        };
+        self.path.join(dir)
     }
-}
 
-impl Backend for LocalBackend {
-    fn name(&self) -> &str {
-        "local"
+    fn object_path(&self, kind: ObjectKind, id: &[u8]) -> PathBuf {
+        self.object_dir(kind).join(hex::encode(id))
     }
 
-    fn hash_length(&self) -> usize {
-        64
+    fn packs_dir(&self) -> PathBuf {
+        self.path.join("packs")
     }
 
-    fn git_repo(&self) -> Option<git2::Repository> {
-        None
+    /// Updates the list of packs to the ones on disk, which other processes
+    /// may have added or merged since we last looked. Only reads the indexes
+    /// of new packs.
+    fn reload_packs(&self) -> BackendResult<()> {
+        let mut packs = self.packs.lock().unwrap();
+        let mut old_packs = packs.take().unwrap_or_default();
+        let entries = match fs::read_dir(self.packs_dir()) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                *packs = Some(vec![]);
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let mut new_packs = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("idx") {
+                continue;
+            }
+            let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+            match old_packs.iter().position(|pack| pack.name == name) {
+                Some(i) => new_packs.push(old_packs.swap_remove(i)),
+                None => new_packs.push(read_pack_index(&name, &path)?),
+            }
+        }
+        *packs = Some(new_packs);
+        Ok(())
     }
 
-    fn read_file(&self, _path: &RepoPath, id: &FileId) -> BackendResult<Box<dyn Read>> {
-        let path = self.file_path(id);
-        let file = File::open(path).map_err(not_found_to_backend_error)?;
-        Ok(Box::new(zstd::Decoder::new(file)?))
+    /// Reads the pack indexes unless they've been read already.
+    fn ensure_packs_loaded(&self) -> BackendResult<()> {
+        if self.packs.lock().unwrap().is_none() {
+            self.reload_packs()?;
+        }
+        Ok(())
+    }
+
+    /// Persists a new loose object.
+    fn persist_loose_object(&self, temp_file: NamedTempFile, path: PathBuf) -> BackendResult<()> {
+        persist_content_addressed_temp_file(temp_file, path)?;
+        self.wrote_loose_objects.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn open_packed_object(
+        &self,
+        kind: ObjectKind,
+        id: &[u8],
+    ) -> BackendResult<Option<Box<dyn Read>>> {
+        self.ensure_packs_loaded()?;
+        let packs = self.packs.lock().unwrap();
+        for pack in packs.iter().flatten() {
+            if let Some((offset, length)) = pack.entries.get(&(kind, id.to_vec())) {
+                let path = self.packs_dir().join(format!("{}.pack", pack.name));
+                let mut file = match File::open(path) {
+                    Ok(file) => file,
+                    // Merged into a new pack by another process
+                    Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+                    Err(err) => return Err(err.into()),
+                };
+                file.seek(SeekFrom::Start(*offset))?;
+                return Ok(Some(Box::new(file.take(*length))));
+            }
+        }
+        Ok(None)
     }
 
-    fn write_file(&self, _path: &RepoPath, contents: &mut dyn Read) -> BackendResult<FileId> {
+    /// Opens the stored bytes of an object (compressed, for files), looking in
+    /// the packs first.
+    fn open_object(&self, kind: ObjectKind, id: &[u8]) -> BackendResult<Box<dyn Read>> {
+        if let Some(reader) = self.open_packed_object(kind, id)? {
+            return Ok(reader);
+        }
+        match File::open(self.object_path(kind, id)) {
+            Ok(file) => Ok(Box::new(file)),
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                // Another process may have packed the object (and removed the
+                // loose file), or merged the packs, since we loaded the packs.
+                self.reload_packs()?;
+                self.open_packed_object(kind, id)?
+                    .ok_or(BackendError::NotFound)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Returns a reader for the contents of a file object, given a reader for
+    /// its stored bytes: either the compressed contents or a list of chunks.
+    fn decode_file(&self, mut stored: Box<dyn Read>) -> BackendResult<Box<dyn Read>> {
+        let mut header = vec![];
+        (&mut stored)
+            .take(CHUNKED_FILE_MAGIC.len() as u64)
+            .read_to_end(&mut header)?;
+        if header != CHUNKED_FILE_MAGIC {
+            let stored = Cursor::new(header).chain(stored);
+            return Ok(Box::new(zstd::Decoder::new(stored)?));
+        }
+        let mut buf = vec![];
+        stored.read_to_end(&mut buf)?;
+        let chunk_ids = parse_chunk_list(&buf)?
+            .into_iter()
+            .map(|(id, _length)| id)
+            .collect::<Vec<_>>();
+        Ok(Box::new(ChunkedFileReader {
+            chunks_dir: self.chunks_dir(),
+            chunk_ids: chunk_ids.into_iter(),
+            current: None,
+        }))
+    }
+
+    /// Writes a file object holding the compressed `contents`.
+    fn write_file_contents(&self, contents: &[u8]) -> BackendResult<FileId> {
         let temp_file = NamedTempFile::new_in(&self.path)?;
         let mut encoder = zstd::Encoder::new(temp_file.as_file(), 0)?;
+        encoder.write_all(contents)?;
+        encoder.finish()?;
+        let id = FileId::new(Blake2b512::digest(contents).to_vec());
+
+        self.persist_loose_object(temp_file, self.file_path(&id))?;
+        Ok(id)
+    }
+
+    /// Writes a file object listing the chunks of the file's contents, which
+    /// are `head` followed by the rest of `contents`. Chunks that are already
+    /// stored (e.g. for another version of the file) are reused.
+    fn write_chunked_file(&self, head: Vec<u8>, contents: &mut dyn Read) -> BackendResult<FileId> {
         let mut hasher = Blake2b512::new();
+        let mut chunk_list = CHUNKED_FILE_MAGIC.to_vec();
+        let mut buf = head;
+        let mut pos = 0;
+        let mut eof = false;
         loop {
-            let mut buff: Vec<u8> = Vec::with_capacity(1 << 14);
-            let bytes_read;
-            unsafe {
-                buff.set_len(1 << 14);
-                bytes_read = contents.read(&mut buff)?;
-                buff.set_len(bytes_read);
+            if !eof && buf.len() - pos < MAX_CHUNK_SIZE {
+                buf.drain(..pos);
+                pos = 0;
+                let bytes_read = contents
+                    .take(CHUNKING_THRESHOLD as u64)
+                    .read_to_end(&mut buf)?;
+                eof = bytes_read < CHUNKING_THRESHOLD;
+                continue;
             }
-            if bytes_read == 0 {
+            if pos == buf.len() {
                 break;
             }
-            encoder.write_all(&buff)?;
-            hasher.update(&buff);
+            let chunk = &buf[pos..pos + chunk_length(&buf[pos..])];
+            hasher.update(chunk);
+            chunk_list.extend_from_slice(&self.write_chunk(chunk)?);
+            chunk_list.extend_from_slice(&(chunk.len() as u64).to_le_bytes());
+            pos += chunk.len();
         }
-        encoder.finish()?;
         let id = FileId::new(hasher.finalize().to_vec());
+        let mut temp_file = NamedTempFile::new_in(&self.path)?;
+        temp_file.write_all(&chunk_list)?;
+        self.persist_loose_object(temp_file, self.file_path(&id))?;
+        Ok(id)
+    }
 
-        persist_content_addressed_temp_file(temp_file, self.file_path(&id))?;
+    /// Stores a chunk unless it's already stored and returns its id.
+    fn write_chunk(&self, chunk: &[u8]) -> BackendResult<Vec<u8>> {
+        let id = Blake2b512::digest(chunk).to_vec();
+        let path = self.chunks_dir().join(hex::encode(&id));
+        if !path.exists() {
+            fs::create_dir_all(self.chunks_dir())?;
+            let temp_file = NamedTempFile::new_in(&self.path)?;
+            let mut encoder = zstd::Encoder::new(temp_file.as_file(), 0)?;
+            encoder.write_all(chunk)?;
+            encoder.finish()?;
+            persist_content_addressed_temp_file(temp_file, path)?;
+        }
         Ok(id)
     }
 
+    fn file_object_ids(&self) -> BackendResult<Vec<Vec<u8>>> {
+        let mut ids = self.loose_object_ids(ObjectKind::File)?;
+        self.reload_packs()?;
+        let packs = self.packs.lock().unwrap();
+        for pack in packs.iter().flatten() {
+            for (kind, id) in pack.entries.keys() {
+                if *kind == ObjectKind::File {
+                    ids.push(id.clone());
+                }
+            }
+        }
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Collects `ChunkStats` by reading the chunk lists of all chunked files.
+    fn collect_chunk_stats(&self) -> BackendResult<ChunkStats> {
+        let mut stats = ChunkStats::default();
+        let mut seen_chunks = HashSet::new();
+        for id in self.file_object_ids()? {
+            let mut stored = self.open_object(ObjectKind::File, &id)?;
+            let mut header = vec![];
+            (&mut stored)
+                .take(CHUNKED_FILE_MAGIC.len() as u64)
+                .read_to_end(&mut header)?;
+            if header != CHUNKED_FILE_MAGIC {
+                continue;
+            }
+            let mut buf = vec![];
+            stored.read_to_end(&mut buf)?;
+            stats.chunked_files += 1;
+            for (chunk_id, length) in parse_chunk_list(&buf)? {
+                stats.total_bytes += length;
+                if seen_chunks.insert(chunk_id.clone()) {
+                    stats.unique_chunks += 1;
+                    stats.unique_bytes += length;
+                    let chunk_path = self.chunks_dir().join(hex::encode(&chunk_id));
+                    stats.stored_bytes += fs::metadata(chunk_path)?.len();
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    fn loose_object_ids(&self, kind: ObjectKind) -> BackendResult<Vec<Vec<u8>>> {
+        let mut ids = vec![];
+        for entry in fs::read_dir(self.object_dir(kind))? {
+            let file_name = entry?.file_name();
+            if let Some(id) = file_name.to_str().and_then(|name| hex::decode(name).ok()) {
+                if id.len() == 64 {
+                    ids.push(id);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Moves the loose objects (one file per object) into a new pack if there
+    /// are at least `min_count` of them. Returns the number of objects packed.
+    /// If there are `MAX_PACKS` packs already, they're merged into the new
+    /// pack too.
+    ///
+    /// With a `min_count` above 1, the loose objects are only counted if this
+    /// backend has written any, since otherwise the count can't have crossed
+    /// the threshold since the process that wrote them checked it.
+    pub fn pack(&self, min_count: usize) -> BackendResult<usize> {
+        if min_count > 1 && !self.wrote_loose_objects.load(Ordering::Relaxed) {
+            return Ok(0);
+        }
+        let mut loose_ids = vec![];
+        for kind in OBJECT_KINDS {
+            for id in self.loose_object_ids(kind)? {
+                loose_ids.push((kind, id));
+            }
+        }
+        if loose_ids.is_empty() || loose_ids.len() < min_count {
+            return Ok(0);
+        }
+        self.wrote_loose_objects.store(false, Ordering::Relaxed);
+        loose_ids.sort();
+        self.reload_packs()?;
+        let packs_to_merge = {
+            let mut packs = self.packs.lock().unwrap();
+            let packs = packs.as_mut().unwrap();
+            if packs.len() >= MAX_PACKS {
+                std::mem::take(packs)
+            } else {
+                vec![]
+            }
+        };
+        fs::create_dir_all(self.packs_dir())?;
+        let mut pack_file = NamedTempFile::new_in(self.packs_dir())?;
+        let mut index = PACK_INDEX_MAGIC.to_vec();
+        let mut offset: u64 = 0;
+        let mut packed_ids = HashSet::new();
+        // Skips objects that are in the new pack already
+        let mut add_object = |kind: ObjectKind, id: &[u8], contents: &mut dyn Read| {
+            if !packed_ids.insert((kind, id.to_vec())) {
+                return Ok(());
+            }
+            let length = std::io::copy(contents, &mut pack_file)?;
+            index.push(object_kind_to_byte(kind));
+            index.extend_from_slice(id);
+            index.extend_from_slice(&offset.to_le_bytes());
+            index.extend_from_slice(&length.to_le_bytes());
+            offset += length;
+            std::io::Result::Ok(())
+        };
+        for pack in &packs_to_merge {
+            let mut file = File::open(self.packs_dir().join(format!("{}.pack", pack.name)))?;
+            let mut entries = pack.entries.iter().collect::<Vec<_>>();
+            entries.sort_by_key(|(_, (offset, _))| *offset);
+            for ((kind, id), (offset, length)) in entries {
+                file.seek(SeekFrom::Start(*offset))?;
+                add_object(*kind, id, &mut (&mut file).take(*length))?;
+            }
+        }
+        let mut packed_paths = vec![];
+        for (kind, id) in &loose_ids {
+            let path = self.object_path(*kind, id);
+            let mut file = match File::open(&path) {
+                Ok(file) => file,
+                // Packed by a concurrent process
+                Err(err) if err.kind() == ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+            add_object(*kind, id, &mut file)?;
+            packed_paths.push(path);
+        }
+        let name = hex::encode(&Blake2b512::digest(&index)[..32]);
+        let mut index_file = NamedTempFile::new_in(self.packs_dir())?;
+        index_file.write_all(&index)?;
+        // The index is written last so readers never see an incomplete pack.
+        persist_content_addressed_temp_file(
+            pack_file,
+            self.packs_dir().join(format!("{name}.pack")),
+        )?;
+        persist_content_addressed_temp_file(
+            index_file,
+            self.packs_dir().join(format!("{name}.idx")),
+        )?;
+        // Indexes first, so readers never see an index without its pack
+        for pack in &packs_to_merge {
+            fs::remove_file(self.packs_dir().join(format!("{}.idx", pack.name))).ok();
+        }
+        for pack in &packs_to_merge {
+            fs::remove_file(self.packs_dir().join(format!("{}.pack", pack.name))).ok();
+        }
+        self.reload_packs()?;
+        for path in &packed_paths {
+            fs::remove_file(path).ok();
+        }
+        Ok(packed_paths.len())
+    }
+}
+
+impl Backend for LocalBackend {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    fn hash_length(&self) -> usize {
+        64
+    }
+
+    fn git_repo(&self) -> Option<git2::Repository> {
+        None
+    }
+
+    fn read_file(&self, _path: &RepoPath, id: &FileId) -> BackendResult<Box<dyn Read>> {
+        let file = self.open_object(ObjectKind::File, id.as_bytes())?;
+        self.decode_file(file)
+    }
+
+    fn write_file(&self, _path: &RepoPath, contents: &mut dyn Read) -> BackendResult<FileId> {
+        let mut head = vec![];
+        contents
+            .take(CHUNKING_THRESHOLD as u64)
+            .read_to_end(&mut head)?;
+        if head.len() < CHUNKING_THRESHOLD {
+            self.write_file_contents(&head)
+        } else {
+            self.write_chunked_file(head, contents)
+        }
+    }
+
     fn read_symlink(&self, _path: &RepoPath, id: &SymlinkId) -> Result<String, BackendError> {
-        let path = self.symlink_path(id);
-        let mut file = File::open(path).map_err(not_found_to_backend_error)?;
+        let mut file = self.open_object(ObjectKind::Symlink, id.as_bytes())?;
         let mut target = String::new();
         file.read_to_string(&mut target).unwrap();
         Ok(target)
@@ -168,7 +666,7 @@ impl Backend for LocalBackend {
         hasher.update(&target.as_bytes());
         let id = SymlinkId::new(hasher.finalize().to_vec());
 
-        persist_content_addressed_temp_file(temp_file, self.symlink_path(&id))?;
+        self.persist_loose_object(temp_file, self.symlink_path(&id))?;
         Ok(id)
     }
 
@@ -181,8 +679,7 @@ impl Backend for LocalBackend {
     }
 
     fn read_tree(&self, _path: &RepoPath, id: &TreeId) -> BackendResult<Tree> {
-        let path = self.tree_path(id);
-        let mut file = File::open(path).map_err(not_found_to_backend_error)?;
+        let mut file = self.open_object(ObjectKind::Tree, id.as_bytes())?;
 
         let proto: crate::protos::store::Tree = Message::parse_from_reader(&mut file)?;
         Ok(tree_from_proto(&proto))
@@ -199,13 +696,12 @@ impl Backend for LocalBackend {
 
         let id = TreeId::new(Blake2b512::digest(&proto_bytes).to_vec());
 
-        persist_content_addressed_temp_file(temp_file, self.tree_path(&id))?;
+        self.persist_loose_object(temp_file, self.tree_path(&id))?;
         Ok(id)
     }
 
     fn read_conflict(&self, _path: &RepoPath, id: &ConflictId) -> BackendResult<Conflict> {
-        let path = self.conflict_path(id);
-        let mut file = File::open(path).map_err(not_found_to_backend_error)?;
+        let mut file = self.open_object(ObjectKind::Conflict, id.as_bytes())?;
 
         let proto: crate::protos::store::Conflict = Message::parse_from_reader(&mut file)?;
         Ok(conflict_from_proto(&proto))
@@ -222,7 +718,7 @@ impl Backend for LocalBackend {
 
         let id = ConflictId::new(Blake2b512::digest(&proto_bytes).to_vec());
 
-        persist_content_addressed_temp_file(temp_file, self.conflict_path(&id))?;
+        self.persist_loose_object(temp_file, self.conflict_path(&id))?;
         Ok(id)
     }
 
@@ -231,8 +727,7 @@ impl Backend for LocalBackend {
             return Ok(make_root_commit(self.empty_tree_id.clone()));
         }
 
-        let path = self.commit_path(id);
-        let mut file = File::open(path).map_err(not_found_to_backend_error)?;
+        let mut file = self.open_object(ObjectKind::Commit, id.as_bytes())?;
 
         let proto: crate::protos::store::Commit = Message::parse_from_reader(&mut file)?;
         Ok(commit_from_proto(&proto))
@@ -249,9 +744,51 @@ impl Backend for LocalBackend {
 
         let id = CommitId::new(Blake2b512::digest(&proto_bytes).to_vec());
 
-        persist_content_addressed_temp_file(temp_file, self.commit_path(&id))?;
+        self.persist_loose_object(temp_file, self.commit_path(&id))?;
         Ok(id)
     }
+
+    fn verify_object(&self, kind: ObjectKind, id: &[u8]) -> BackendResult<()> {
+        if kind == ObjectKind::Commit && id == self.root_commit_id.as_bytes() {
+            return Ok(());
+        }
+        let mut file = self.open_object(kind, id)?;
+        let mut hasher = Blake2b512::new();
+        if kind == ObjectKind::File {
+            // Files are the only objects that are stored compressed (or as chunks).
+            let mut decoder = self.decode_file(file)?;
+            let mut buf = vec![0; 1 << 14];
+            loop {
+                let bytes_read = decoder.read(&mut buf)?;
+                if bytes_read == 0 {
+                    break;
+                }
+                hasher.update(&buf[..bytes_read]);
+            }
+        } else {
+            let mut contents = vec![];
+            file.read_to_end(&mut contents)?;
+            hasher.update(&contents);
+        }
+        let actual_hash = hasher.finalize();
+        if actual_hash.as_slice() == id {
+            Ok(())
+        } else {
+            Err(BackendError::HashMismatch {
+                kind,
+                hash: hex::encode(id),
+                actual_hash: hex::encode(actual_hash),
+            })
+        }
+    }
+
+    fn pack_loose_objects(&self, min_count: usize) -> BackendResult<usize> {
+        self.pack(min_count)
+    }
+
+    fn chunk_stats(&self) -> BackendResult<Option<ChunkStats>> {
+        self.collect_chunk_stats().map(Some)
+    }
 }
 
 pub fn commit_to_proto(commit: &Commit) -> crate::protos::store::Commit {
@@ -268,10 +805,17 @@ pub fn commit_to_proto(commit: &Commit) -> crate::protos::store::Commit {
     proto.author = MessageField::some(signature_to_proto(&commit.author));
     proto.committer = MessageField::some(signature_to_proto(&commit.committer));
     proto.is_open = commit.is_open;
+    proto.copies = commit.copies.iter().map(copy_record_to_proto).collect();
+    for co_author in &commit.co_authors {
+        let mut co_author_proto = crate::protos::store::commit::CoAuthor::new();
+        co_author_proto.name = co_author.name.clone();
+        co_author_proto.email = co_author.email.clone();
+        proto.co_authors.push(co_author_proto);
+    }
     proto
 }
 
-fn commit_from_proto(proto: &crate::protos::store::Commit) -> Commit {
+pub fn commit_from_proto(proto: &crate::protos::store::Commit) -> Commit {
     let commit_id_from_proto = |parent: &Vec<u8>| CommitId::new(parent.clone());
     let parents = proto.parents.iter().map(commit_id_from_proto).collect();
     let predecessors = proto
@@ -290,10 +834,33 @@ fn commit_from_proto(proto: &crate::protos::store::Commit) -> Commit {
         author: signature_from_proto(&proto.author),
         committer: signature_from_proto(&proto.committer),
         is_open: proto.is_open,
+        copies: proto.copies.iter().map(copy_record_from_proto).collect(),
+        co_authors: proto
+            .co_authors
+            .iter()
+            .map(|co_author| CoAuthor {
+                name: co_author.name.clone(),
+                email: co_author.email.clone(),
+            })
+            .collect(),
+    }
+}
+
+pub fn copy_record_to_proto(copy: &CopyRecord) -> crate::protos::store::commit::CopyRecord {
+    let mut proto = crate::protos::store::commit::CopyRecord::new();
+    proto.source = copy.source.to_internal_file_string();
+    proto.target = copy.target.to_internal_file_string();
+    proto
+}
+
+pub fn copy_record_from_proto(proto: &crate::protos::store::commit::CopyRecord) -> CopyRecord {
+    CopyRecord {
+        source: RepoPath::from_internal_string(&proto.source),
+        target: RepoPath::from_internal_string(&proto.target),
     }
 }
 
-fn tree_to_proto(tree: &Tree) -> crate::protos::store::Tree {
+pub fn tree_to_proto(tree: &Tree) -> crate::protos::store::Tree {
     let mut proto = crate::protos::store::Tree::new();
     for entry in tree.entries() {
         let mut proto_entry = crate::protos::store::tree::Entry::new();
@@ -304,7 +871,7 @@ fn tree_to_proto(tree: &Tree) -> crate::protos::store::Tree {
     proto
 }
 
-fn tree_from_proto(proto: &crate::protos::store::Tree) -> Tree {
+pub fn tree_from_proto(proto: &crate::protos::store::Tree) -> Tree {
     let mut tree = Tree::default();
     for proto_entry in &proto.entries {
         let value = tree_value_from_proto(proto_entry.value.as_ref().unwrap());
@@ -381,7 +948,7 @@ fn signature_from_proto(proto: &crate::protos::store::commit::Signature) -> Sign
     }
 }
 
-fn conflict_to_proto(conflict: &Conflict) -> crate::protos::store::Conflict {
+pub fn conflict_to_proto(conflict: &Conflict) -> crate::protos::store::Conflict {
     let mut proto = crate::protos::store::Conflict::new();
     for part in &conflict.adds {
         proto.adds.push(conflict_part_to_proto(part));
@@ -392,7 +959,7 @@ fn conflict_to_proto(conflict: &Conflict) -> crate::protos::store::Conflict {
     proto
 }
 
-fn conflict_from_proto(proto: &crate::protos::store::Conflict) -> Conflict {
+pub fn conflict_from_proto(proto: &crate::protos::store::Conflict) -> Conflict {
     let mut conflict = Conflict::default();
     for part in &proto.removes {
         conflict.removes.push(conflict_part_from_proto(part))
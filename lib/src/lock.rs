@@ -13,11 +13,131 @@
 // limitations under the License.
 
 use std::fs::{File, OpenOptions};
-use std::path::PathBuf;
-use std::time::Duration;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use backoff::{retry, ExponentialBackoff};
 
+use crate::backend::MillisSinceEpoch;
+
+/// Information about the process holding a lock, as recorded in the lock file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub hostname: String,
+    pub since: MillisSinceEpoch,
+}
+
+impl LockHolder {
+    fn current() -> Self {
+        let since = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| MillisSinceEpoch(duration.as_millis() as i64))
+            .unwrap_or(MillisSinceEpoch(0));
+        LockHolder {
+            pid: std::process::id(),
+            hostname: whoami::hostname(),
+            since,
+        }
+    }
+
+    fn serialize(&self) -> String {
+        format!(
+            "pid={}\nhostname={}\nsince={}\n",
+            self.pid, self.hostname, self.since.0
+        )
+    }
+
+    fn parse(content: &str) -> Option<Self> {
+        let mut pid = None;
+        let mut hostname = None;
+        let mut since = None;
+        for line in content.lines() {
+            match line.split_once('=')? {
+                ("pid", value) => pid = value.parse().ok(),
+                ("hostname", value) => hostname = Some(value.to_string()),
+                ("since", value) => since = value.parse().ok().map(MillisSinceEpoch),
+                _ => {}
+            }
+        }
+        Some(LockHolder {
+            pid: pid?,
+            hostname: hostname?,
+            since: since?,
+        })
+    }
+
+    /// Reads the holder information from the lock file at `path`. Returns
+    /// `None` if there's no such file or if it doesn't contain holder
+    /// information (e.g. because it was written by an older version, or
+    /// because the holder hasn't written it yet).
+    pub fn read(path: &Path) -> Option<Self> {
+        let content = std::fs::read_to_string(path).ok()?;
+        LockHolder::parse(&content)
+    }
+
+    /// Whether the holder is known to have exited. That can only be determined
+    /// for processes on this host, and only on some platforms.
+    pub fn is_dead(&self) -> bool {
+        self.hostname == whoami::hostname() && process_is_dead(self.pid)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn process_is_dead(pid: u32) -> bool {
+    !Path::new("/proc").join(pid.to_string()).exists()
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn process_is_dead(pid: u32) -> bool {
+    // `kill -0` only checks whether the process exists.
+    std::process::Command::new("kill")
+        .arg("-0")
+        .arg(pid.to_string())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .map(|status| !status.success())
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn process_is_dead(_pid: u32) -> bool {
+    false
+}
+
+/// Removes the lock file at `path` if it was left behind by a process that has
+/// exited. Returns the dead holder if the lock was removed.
+fn remove_stale_lock(path: &Path) -> Option<LockHolder> {
+    let holder = LockHolder::read(path)?;
+    if !holder.is_dead() {
+        return None;
+    }
+    // Another process may be doing the same thing, and may even have taken the
+    // lock since we read it, so move the file out of the way and check that we
+    // got the one we looked at.
+    let stale_path = path.with_extension(format!("stale-{}", std::process::id()));
+    std::fs::rename(path, &stale_path).ok()?;
+    if LockHolder::read(&stale_path).as_ref() == Some(&holder) {
+        std::fs::remove_file(&stale_path).ok();
+        Some(holder)
+    } else {
+        // Put it back unless someone else has taken the lock in the meantime.
+        if std::fs::hard_link(&stale_path, path).is_ok() {
+            std::fs::remove_file(&stale_path).ok();
+        }
+        None
+    }
+}
+
+/// Removes the lock file at `path` regardless of who holds it. Returns the
+/// holder information that was recorded in it, if any.
+pub fn break_lock(path: &Path) -> std::io::Result<Option<LockHolder>> {
+    let holder = LockHolder::read(path);
+    std::fs::remove_file(path)?;
+    Ok(holder)
+}
+
 pub struct FileLock {
     path: PathBuf,
     _file: File,
@@ -28,12 +148,23 @@ impl FileLock {
         let mut options = OpenOptions::new();
         options.create_new(true);
         options.write(true);
+        let holder = LockHolder::current();
         let try_write_lock_file = || match options.open(&path) {
-            Ok(file) => Ok(FileLock {
-                path: path.clone(),
-                _file: file,
-            }),
+            Ok(mut file) => {
+                file.write_all(holder.serialize().as_bytes()).ok();
+                Ok(FileLock {
+                    path: path.clone(),
+                    _file: file,
+                })
+            }
             Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                if remove_stale_lock(&path).is_some() {
+                    // Retry right away
+                    return Err(backoff::Error::Transient {
+                        err,
+                        retry_after: Some(Duration::ZERO),
+                    });
+                }
                 Err(backoff::Error::Transient {
                     err,
                     retry_after: None,
@@ -53,14 +184,48 @@ impl FileLock {
             ..Default::default()
         };
         match retry(backoff, try_write_lock_file) {
-            Err(err) => panic!(
-                "failed to create lock file {}: {}",
-                path.to_string_lossy(),
-                err
-            ),
+            Err(err) => match LockHolder::read(&path) {
+                Some(holder) => panic!(
+                    "failed to create lock file {}: {} (held by process {} on {}; run `jj debug \
+                     break-lock` if that process is gone)",
+                    path.to_string_lossy(),
+                    err,
+                    holder.pid,
+                    holder.hostname,
+                ),
+                None => panic!(
+                    "failed to create lock file {}: {}",
+                    path.to_string_lossy(),
+                    err
+                ),
+            },
             Ok(file_lock) => file_lock,
         }
     }
+
+    /// Like `lock()`, but returns `None` instead of waiting if the lock is held
+    /// by another process.
+    pub fn try_lock(path: PathBuf) -> std::io::Result<Option<FileLock>> {
+        let mut options = OpenOptions::new();
+        options.create_new(true);
+        options.write(true);
+        for _ in 0..2 {
+            match options.open(&path) {
+                Ok(mut file) => {
+                    file.write_all(LockHolder::current().serialize().as_bytes())
+                        .ok();
+                    return Ok(Some(FileLock { path, _file: file }));
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if remove_stale_lock(&path).is_none() {
+                        return Ok(None);
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(None)
+    }
 }
 
 impl Drop for FileLock {
@@ -91,6 +256,48 @@ mod tests {
         assert!(!lock_path.exists());
     }
 
+    #[test]
+    fn try_lock_held() {
+        let temp_dir = testutils::new_temp_dir();
+        let lock_path = temp_dir.path().join("test.lock");
+        let lock = FileLock::try_lock(lock_path.clone()).unwrap();
+        assert!(lock.is_some());
+        assert!(FileLock::try_lock(lock_path.clone()).unwrap().is_none());
+        drop(lock);
+        assert!(FileLock::try_lock(lock_path).unwrap().is_some());
+    }
+
+    #[test]
+    fn lock_records_holder() {
+        let temp_dir = testutils::new_temp_dir();
+        let lock_path = temp_dir.path().join("test.lock");
+        let _lock = FileLock::lock(lock_path.clone());
+        let holder = LockHolder::read(&lock_path).unwrap();
+        assert_eq!(holder.pid, std::process::id());
+        assert_eq!(holder.hostname, whoami::hostname());
+        assert!(!holder.is_dead());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn lock_takes_over_from_dead_holder() {
+        let temp_dir = testutils::new_temp_dir();
+        let lock_path = temp_dir.path().join("test.lock");
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        let dead_pid = child.id();
+        child.wait().unwrap();
+        let dead_holder = LockHolder {
+            pid: dead_pid,
+            ..LockHolder::current()
+        };
+        std::fs::write(&lock_path, dead_holder.serialize()).unwrap();
+        let _lock = FileLock::lock(lock_path.clone());
+        assert_eq!(
+            LockHolder::read(&lock_path).unwrap().pid,
+            std::process::id()
+        );
+    }
+
     #[test]
     fn lock_concurrent() {
         let temp_dir = testutils::new_temp_dir();
@@ -0,0 +1,249 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Formatter};
+use std::hash::Hash;
+
+/// A map that holds entries up to a total weight of `capacity`. Entries
+/// inserted with `insert()` weigh 1, so the capacity is then simply the maximum
+/// number of entries. When it's full, inserting a new entry evicts the least
+/// recently used ones.
+pub struct LruCache<K, V> {
+    capacity: usize,
+    total_weight: usize,
+    entries: HashMap<K, Entry<V>>,
+    // Keys by the "time" they were last used.
+    recency: BTreeMap<u64, K>,
+    next_tick: u64,
+    stats: CacheStats,
+}
+
+struct Entry<V> {
+    value: V,
+    weight: usize,
+    last_used: u64,
+}
+
+/// Counts how well a cache works.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// The number of entries currently in the cache.
+    pub entries: usize,
+    /// The total weight of the entries currently in the cache.
+    pub weight: usize,
+    pub capacity: usize,
+}
+
+impl CacheStats {
+    /// The fraction of lookups that were hits, or `None` if there were none.
+    pub fn hit_rate(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            None
+        } else {
+            Some(self.hits as f64 / lookups as f64)
+        }
+    }
+}
+
+impl<K, V> Debug for LruCache<K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.debug_struct("LruCache")
+            .field("capacity", &self.capacity)
+            .field("len", &self.entries.len())
+            .field("total_weight", &self.total_weight)
+            .finish()
+    }
+}
+
+impl<K: Clone + Eq + Hash, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            total_weight: 0,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            next_tick: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn total_weight(&self) -> usize {
+        self.total_weight
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.len(),
+            weight: self.total_weight,
+            capacity: self.capacity,
+            ..self.stats.clone()
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        let tick = self.next_tick;
+        self.next_tick += 1;
+        tick
+    }
+
+    /// Looks up `key` and marks it as the most recently used entry. Counts as
+    /// a hit or miss in the stats.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        let tick = self.tick();
+        let entry = match self.entries.get_mut(key) {
+            Some(entry) => entry,
+            None => {
+                self.stats.misses += 1;
+                return None;
+            }
+        };
+        self.stats.hits += 1;
+        let key = self.recency.remove(&entry.last_used).unwrap();
+        entry.last_used = tick;
+        self.recency.insert(tick, key);
+        Some(&entry.value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.insert_with_weight(key, value, 1);
+    }
+
+    /// Inserts an entry that uses `weight` of the capacity. Entries heavier
+    /// than the whole capacity are not inserted (but replace any existing entry
+    /// for the key).
+    pub fn insert_with_weight(&mut self, key: K, value: V, weight: usize) {
+        self.remove(&key);
+        if weight > self.capacity {
+            return;
+        }
+        while self.total_weight + weight > self.capacity {
+            let (_, oldest_key) = self.recency.iter().next().unwrap();
+            let oldest_key = oldest_key.clone();
+            self.remove(&oldest_key);
+            self.stats.evictions += 1;
+        }
+        let tick = self.tick();
+        self.recency.insert(tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                weight,
+                last_used: tick,
+            },
+        );
+        self.total_weight += weight;
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.last_used);
+        self.total_weight -= entry.weight;
+        Some(entry.value)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.total_weight = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_cache_evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        // "b" is now the least recently used entry
+        cache.insert("c", 3);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(&1));
+        assert_eq!(cache.get(&"c"), Some(&3));
+    }
+
+    #[test]
+    fn test_lru_cache_replace() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        cache.insert("a", 3);
+        assert_eq!(cache.len(), 2);
+        cache.insert("c", 4);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(&3));
+        assert_eq!(cache.remove(&"a"), Some(3));
+        assert_eq!(cache.remove(&"a"), None);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_lru_cache_weights() {
+        let mut cache = LruCache::new(10);
+        cache.insert_with_weight("a", 1, 4);
+        cache.insert_with_weight("b", 2, 4);
+        cache.insert_with_weight("c", 3, 2);
+        assert_eq!(cache.total_weight(), 10);
+        // Evicts "a", which is the least recently used entry
+        cache.insert_with_weight("d", 4, 3);
+        assert_eq!(cache.total_weight(), 9);
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"b"), Some(&2));
+        assert_eq!(cache.get(&"c"), Some(&3));
+        // Too heavy to be cached at all
+        cache.insert_with_weight("e", 5, 11);
+        assert_eq!(cache.get(&"e"), None);
+        // Evicts "d" and "b"
+        cache.insert_with_weight("f", 6, 5);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(
+            cache.stats(),
+            CacheStats {
+                hits: 2,
+                misses: 2,
+                evictions: 3,
+                entries: 2,
+                weight: 7,
+                capacity: 10,
+            }
+        );
+        assert_eq!(cache.stats().hit_rate(), Some(0.5));
+    }
+}
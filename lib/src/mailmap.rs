@@ -0,0 +1,180 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Mapping of old identities to canonical ones, similar to Git's `.mailmap`.
+//!
+//! The mapping is configured as a list of `[[mailmap]]` tables, each with the
+//! `old-email` (and optionally `old-name`) to replace and the `name` and/or
+//! `email` to replace it with.
+
+use crate::backend::Signature;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MailmapEntry {
+    old_email: String,
+    old_name: Option<String>,
+    name: Option<String>,
+    email: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Mailmap {
+    entries: Vec<MailmapEntry>,
+}
+
+impl Mailmap {
+    /// Reads the `[[mailmap]]` tables from the config. Entries without an
+    /// `old-email` or without anything to replace it with are ignored.
+    pub fn from_config(config: &config::Config) -> Self {
+        let mut mailmap = Mailmap::default();
+        let entries = config.get_array("mailmap").unwrap_or_default();
+        for entry in entries {
+            let mut table = match entry.into_table() {
+                Ok(table) => table,
+                Err(_) => continue,
+            };
+            let mut take = |key: &str| table.remove(key).and_then(|value| value.into_string().ok());
+            let old_email = take("old-email");
+            let old_name = take("old-name");
+            let name = take("name");
+            let email = take("email");
+            if let Some(old_email) = old_email {
+                if name.is_some() || email.is_some() {
+                    mailmap.add(old_email, old_name, name, email);
+                }
+            }
+        }
+        mailmap
+    }
+
+    /// Adds a mapping from `old_email` (and `old_name` if given) to `name`
+    /// and/or `email`. Entries that match on the name take precedence over
+    /// entries that only match on the email, like in Git's mailmap.
+    pub fn add(
+        &mut self,
+        old_email: String,
+        old_name: Option<String>,
+        name: Option<String>,
+        email: Option<String>,
+    ) {
+        self.entries.push(MailmapEntry {
+            old_email,
+            old_name,
+            name,
+            email,
+        });
+        self.entries.sort_by_key(|entry| entry.old_name.is_none());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the canonical identity for the signature. The timestamp is
+    /// left unchanged.
+    pub fn resolve(&self, signature: &Signature) -> Signature {
+        let entry = self.entries.iter().find(|entry| {
+            entry.old_email.eq_ignore_ascii_case(&signature.email)
+                && entry
+                    .old_name
+                    .as_ref()
+                    .map_or(true, |old_name| *old_name == signature.name)
+        });
+        match entry {
+            None => signature.clone(),
+            Some(entry) => Signature {
+                name: entry.name.clone().unwrap_or_else(|| signature.name.clone()),
+                email: entry
+                    .email
+                    .clone()
+                    .unwrap_or_else(|| signature.email.clone()),
+                timestamp: signature.timestamp.clone(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MillisSinceEpoch, Timestamp};
+
+    fn signature(name: &str, email: &str) -> Signature {
+        Signature {
+            name: name.to_string(),
+            email: email.to_string(),
+            timestamp: Timestamp {
+                timestamp: MillisSinceEpoch(0),
+                tz_offset: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let mut mailmap = Mailmap::default();
+        mailmap.add(
+            "old@example.com".to_string(),
+            None,
+            Some("Some One".to_string()),
+            Some("new@example.com".to_string()),
+        );
+        mailmap.add(
+            "shared@example.com".to_string(),
+            Some("Other".to_string()),
+            None,
+            Some("other@example.com".to_string()),
+        );
+
+        // Emails are matched case-insensitively
+        assert_eq!(
+            mailmap.resolve(&signature("Someone", "OLD@example.com")),
+            signature("Some One", "new@example.com")
+        );
+        // Only the email is replaced if there's no name
+        assert_eq!(
+            mailmap.resolve(&signature("Other", "shared@example.com")),
+            signature("Other", "other@example.com")
+        );
+        // The name has to match if the entry has one
+        assert_eq!(
+            mailmap.resolve(&signature("Someone", "shared@example.com")),
+            signature("Someone", "shared@example.com")
+        );
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = config::Config::builder()
+            .add_source(config::File::from_str(
+                r#"
+                [[mailmap]]
+                old-email = "old@example.com"
+                email = "new@example.com"
+
+                [[mailmap]]
+                old-email = "nothing-to-map@example.com"
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .build()
+            .unwrap();
+        let mailmap = Mailmap::from_config(&config);
+        assert_eq!(mailmap.entries.len(), 1);
+        assert_eq!(
+            mailmap.resolve(&signature("Someone", "old@example.com")),
+            signature("Someone", "new@example.com")
+        );
+    }
+}
@@ -14,8 +14,10 @@
 
 #![allow(dead_code)]
 
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
+use crate::gitignore::GitIgnoreFile;
 use crate::repo_path::{RepoPath, RepoPathComponent};
 
 #[derive(PartialEq, Eq, Debug)]
@@ -64,9 +66,17 @@ pub enum VisitFiles {
     Set(HashSet<RepoPathComponent>),
 }
 
-pub trait Matcher {
+// Matchers are `Sync` so trees can be diffed in parallel.
+pub trait Matcher: Sync {
     fn matches(&self, file: &RepoPath) -> bool;
     fn visit(&self, dir: &RepoPath) -> Visit;
+
+    /// Paths that every matching file is at or under, if the matcher only
+    /// matches such paths. `None` if the matcher can't tell (or matches
+    /// everything).
+    fn path_prefixes(&self) -> Option<Vec<RepoPath>> {
+        None
+    }
 }
 
 #[derive(PartialEq, Eq, Debug)]
@@ -77,6 +87,10 @@ impl Matcher for NothingMatcher {
         false
     }
 
+    fn path_prefixes(&self) -> Option<Vec<RepoPath>> {
+        Some(vec![])
+    }
+
     fn visit(&self, _dir: &RepoPath) -> Visit {
         Visit::Nothing
     }
@@ -99,71 +113,240 @@ impl Matcher for EverythingMatcher {
 pub struct FilesMatcher {
     files: HashSet<RepoPath>,
     dirs: Dirs,
+    case_insensitive: bool,
 }
 
 impl FilesMatcher {
     pub fn new(files: HashSet<RepoPath>) -> Self {
+        Self::with_case_sensitivity(files, false)
+    }
+
+    /// Like `new()`, but compares path components after Unicode case folding,
+    /// so e.g. "README.md" matches "readme.md".
+    pub fn new_case_insensitive(files: HashSet<RepoPath>) -> Self {
+        Self::with_case_sensitivity(files, true)
+    }
+
+    fn with_case_sensitivity(files: HashSet<RepoPath>, case_insensitive: bool) -> Self {
+        let files: HashSet<_> = if case_insensitive {
+            files.iter().map(fold_path_case).collect()
+        } else {
+            files
+        };
         let mut dirs = Dirs::new();
         for f in &files {
             dirs.add_file(f);
         }
-        FilesMatcher { files, dirs }
+        FilesMatcher {
+            files,
+            dirs,
+            case_insensitive,
+        }
     }
 }
 
 impl Matcher for FilesMatcher {
     fn matches(&self, file: &RepoPath) -> bool {
-        self.files.contains(file)
+        if self.case_insensitive {
+            self.files.contains(&fold_path_case(file))
+        } else {
+            self.files.contains(file)
+        }
     }
 
     fn visit(&self, dir: &RepoPath) -> Visit {
-        let dirs = self.dirs.get_dirs(dir);
-        let files = self.dirs.get_files(dir);
-        Visit::sets(dirs, files)
+        if self.case_insensitive {
+            self.dirs.visit_case_insensitive(&fold_path_case(dir))
+        } else {
+            let dirs = self.dirs.get_dirs(dir);
+            let files = self.dirs.get_files(dir);
+            Visit::sets(dirs, files)
+        }
+    }
+
+    fn path_prefixes(&self) -> Option<Vec<RepoPath>> {
+        // The folded paths aren't the paths in the tree
+        (!self.case_insensitive).then(|| self.files.iter().cloned().collect())
     }
 }
 
 pub struct PrefixMatcher {
-    prefixes: BTreeSet<RepoPath>,
+    prefixes: Vec<RepoPath>,
     dirs: Dirs,
+    case_insensitive: bool,
 }
 
 impl PrefixMatcher {
     pub fn new(prefixes: &[RepoPath]) -> Self {
-        let prefixes = prefixes.iter().cloned().collect();
+        Self::with_case_sensitivity(prefixes, false)
+    }
+
+    /// Like `new()`, but compares path components after Unicode case folding.
+    pub fn new_case_insensitive(prefixes: &[RepoPath]) -> Self {
+        Self::with_case_sensitivity(prefixes, true)
+    }
+
+    fn with_case_sensitivity(prefixes: &[RepoPath], case_insensitive: bool) -> Self {
         let mut dirs = Dirs::new();
-        for prefix in &prefixes {
-            dirs.add_dir(prefix);
+        for prefix in prefixes {
+            let prefix = if case_insensitive {
+                fold_path_case(prefix)
+            } else {
+                prefix.clone()
+            };
+            dirs.add_dir(&prefix);
             if !prefix.is_root() {
-                dirs.add_file(prefix);
+                dirs.add_file(&prefix);
             }
         }
-        PrefixMatcher { prefixes, dirs }
+        PrefixMatcher {
+            prefixes: prefixes.to_vec(),
+            dirs,
+            case_insensitive,
+        }
     }
 }
 
 impl Matcher for PrefixMatcher {
     fn matches(&self, file: &RepoPath) -> bool {
-        let components = file.components();
-        // TODO: Make Dirs a trie instead, so this can just walk that trie.
-        for i in 0..components.len() + 1 {
-            let prefix = RepoPath::from_components(components[0..i].to_vec());
-            if self.prefixes.contains(&prefix) {
-                return true;
-            }
+        if self.case_insensitive {
+            self.dirs.has_explicit_ancestor(&fold_path_case(file))
+        } else {
+            self.dirs.has_explicit_ancestor(file)
         }
-        false
     }
 
     fn visit(&self, dir: &RepoPath) -> Visit {
         if self.matches(dir) {
             Visit::AllRecursively
+        } else if self.case_insensitive {
+            self.dirs.visit_case_insensitive(&fold_path_case(dir))
         } else {
             let dirs = self.dirs.get_dirs(dir);
             let files = self.dirs.get_files(dir);
             Visit::sets(dirs, files)
         }
     }
+
+    fn path_prefixes(&self) -> Option<Vec<RepoPath>> {
+        if self.case_insensitive || self.prefixes.iter().any(RepoPath::is_root) {
+            None
+        } else {
+            Some(self.prefixes.clone())
+        }
+    }
+}
+
+/// A glob pattern for file paths. Patterns without a `/` are matched against
+/// the file name only, others against the whole path, where `**/` matches any
+/// number of directories, including none.
+#[derive(Clone, Debug)]
+pub struct FilePattern {
+    matches_name_only: bool,
+    regex: regex::Regex,
+}
+
+impl FilePattern {
+    pub fn new(glob: &str) -> Self {
+        let mut regex = String::from("^");
+        let mut chars = glob.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        regex.push_str("(?:.*/)?");
+                    } else {
+                        regex.push_str(".*");
+                    }
+                }
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+        FilePattern {
+            matches_name_only: !glob.contains('/'),
+            regex: regex::Regex::new(&regex).unwrap(),
+        }
+    }
+
+    pub fn matches(&self, path: &RepoPath) -> bool {
+        let path_str = path.to_internal_file_string();
+        if self.matches_name_only {
+            self.regex.is_match(path_str.rsplit('/').next().unwrap())
+        } else {
+            self.regex.is_match(&path_str)
+        }
+    }
+}
+
+impl PartialEq for FilePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.matches_name_only == other.matches_name_only
+            && self.regex.as_str() == other.regex.as_str()
+    }
+}
+
+impl Eq for FilePattern {}
+
+/// Folds the case of a string for caseless comparison.
+///
+/// The standard library doesn't provide Unicode case folding, but
+/// upper-casing and then lower-casing each character gives the same result
+/// for nearly all inputs (e.g. "ß" and "SS" both become "ss", and final
+/// sigma becomes a regular sigma).
+pub fn fold_case(value: &str) -> String {
+    value
+        .to_uppercase()
+        .chars()
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn fold_path_case(path: &RepoPath) -> RepoPath {
+    RepoPath::from_components(
+        path.components()
+            .iter()
+            .map(|component| RepoPathComponent::from(fold_case(component.as_str()).as_str()))
+            .collect(),
+    )
+}
+
+/// Matches paths that are ignored by a chain of gitignore rules.
+pub struct IgnoreMatcher {
+    git_ignore: Arc<GitIgnoreFile>,
+}
+
+impl IgnoreMatcher {
+    pub fn new(git_ignore: Arc<GitIgnoreFile>) -> Self {
+        IgnoreMatcher { git_ignore }
+    }
+}
+
+impl Matcher for IgnoreMatcher {
+    fn matches(&self, file: &RepoPath) -> bool {
+        self.git_ignore
+            .matches_file(&file.to_internal_file_string())
+    }
+
+    fn visit(&self, dir: &RepoPath) -> Visit {
+        if self
+            .git_ignore
+            .matches_all_files_in(&dir.to_internal_dir_string())
+        {
+            Visit::AllRecursively
+        } else {
+            // A directory can't be skipped just because it's ignored itself, since a
+            // later negative pattern may un-ignore some file in it.
+            Visit::Specific {
+                dirs: VisitDirs::All,
+                files: VisitFiles::All,
+            }
+        }
+    }
 }
 
 /// Matches paths that are matched by the first input matcher but not by the
@@ -266,57 +449,98 @@ impl Matcher for IntersectionMatcher<'_> {
 }
 
 /// Keeps track of which subdirectories and files of each directory need to be
-/// visited.
-#[derive(PartialEq, Eq, Debug)]
+/// visited. This is a trie keyed by path component, so looking up a directory
+/// only walks as many nodes as the directory has components.
+#[derive(PartialEq, Eq, Debug, Default)]
 struct Dirs {
-    dirs: HashMap<RepoPath, HashSet<RepoPathComponent>>,
-    files: HashMap<RepoPath, HashSet<RepoPathComponent>>,
+    dirs: HashMap<RepoPathComponent, Dirs>,
+    files: HashSet<RepoPathComponent>,
+    /// Whether this directory was added by `add_dir()` (as opposed to only
+    /// being the parent of such a directory or file)
+    explicit: bool,
 }
 
 impl Dirs {
     fn new() -> Self {
-        Dirs {
-            dirs: HashMap::new(),
-            files: HashMap::new(),
-        }
+        Dirs::default()
     }
 
     fn add_dir(&mut self, dir: &RepoPath) {
-        let mut dir = dir.clone();
-        let mut maybe_child = None;
-        loop {
-            let was_present = self.dirs.contains_key(&dir);
-            let children = self.dirs.entry(dir.clone()).or_default();
-            if let Some(child) = maybe_child {
-                children.insert(child);
-            }
-            if was_present {
-                break;
-            }
-            match dir.split() {
-                None => break,
-                Some((new_dir, new_child)) => {
-                    maybe_child = Some(new_child.clone());
-                    dir = new_dir;
-                }
-            };
+        let mut node = self;
+        for component in dir.components() {
+            node = node.dirs.entry(component.clone()).or_default();
         }
+        node.explicit = true;
     }
 
     fn add_file(&mut self, file: &RepoPath) {
         let (dir, basename) = file
             .split()
             .unwrap_or_else(|| panic!("got empty filename: {:?}", file));
-        self.add_dir(&dir);
-        self.files.entry(dir).or_default().insert(basename.clone());
+        let mut node = self;
+        for component in dir.components() {
+            node = node.dirs.entry(component.clone()).or_default();
+        }
+        node.files.insert(basename.clone());
+    }
+
+    fn get(&self, dir: &RepoPath) -> Option<&Dirs> {
+        let mut node = self;
+        for component in dir.components() {
+            node = node.dirs.get(component)?;
+        }
+        Some(node)
+    }
+
+    /// Whether `path` or any of its ancestors was added by `add_dir()`.
+    fn has_explicit_ancestor(&self, path: &RepoPath) -> bool {
+        let mut node = self;
+        if node.explicit {
+            return true;
+        }
+        for component in path.components() {
+            match node.dirs.get(component) {
+                None => return false,
+                Some(child) => node = child,
+            }
+            if node.explicit {
+                return true;
+            }
+        }
+        false
     }
 
     fn get_dirs(&self, dir: &RepoPath) -> HashSet<RepoPathComponent> {
-        self.dirs.get(dir).cloned().unwrap_or_default()
+        self.get(dir)
+            .map(|node| node.dirs.keys().cloned().collect())
+            .unwrap_or_default()
     }
 
     fn get_files(&self, dir: &RepoPath) -> HashSet<RepoPathComponent> {
-        self.files.get(dir).cloned().unwrap_or_default()
+        self.get(dir)
+            .map(|node| node.files.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns what to visit in a directory when the entries have been added
+    /// with their case folded. The entries' actual names on disk or in the
+    /// tree are unknown, so we can only tell whether there's anything at all.
+    fn visit_case_insensitive(&self, folded_dir: &RepoPath) -> Visit {
+        match self.get(folded_dir) {
+            Some(node) if !node.dirs.is_empty() || !node.files.is_empty() => Visit::Specific {
+                dirs: if node.dirs.is_empty() {
+                    VisitDirs::Set(HashSet::new())
+                } else {
+                    VisitDirs::All
+                },
+                files: if node.files.is_empty() {
+                    VisitFiles::Set(HashSet::new())
+                } else {
+                    VisitFiles::All
+                },
+            },
+            _ => Visit::Nothing,
+        }
     }
 }
 
@@ -361,6 +585,37 @@ mod tests {
         assert_eq!(dirs.get_files(&RepoPath::root()), hashset! {});
     }
 
+    #[test]
+    fn test_dirs_nested() {
+        let mut dirs = Dirs::new();
+        dirs.add_dir(&RepoPath::from_internal_string("dir1/subdir"));
+        dirs.add_file(&RepoPath::from_internal_string("dir1/file1"));
+        dirs.add_file(&RepoPath::from_internal_string("dir2/file2"));
+        assert_eq!(
+            dirs.get_dirs(&RepoPath::root()),
+            hashset! {RepoPathComponent::from("dir1"), RepoPathComponent::from("dir2")}
+        );
+        assert_eq!(
+            dirs.get_dirs(&RepoPath::from_internal_string("dir1")),
+            hashset! {RepoPathComponent::from("subdir")}
+        );
+        assert_eq!(
+            dirs.get_files(&RepoPath::from_internal_string("dir1")),
+            hashset! {RepoPathComponent::from("file1")}
+        );
+        assert_eq!(
+            dirs.get_files(&RepoPath::from_internal_string("dir2")),
+            hashset! {RepoPathComponent::from("file2")}
+        );
+        assert_eq!(
+            dirs.get_dirs(&RepoPath::from_internal_string("dir3")),
+            hashset! {}
+        );
+        assert!(dirs.has_explicit_ancestor(&RepoPath::from_internal_string("dir1/subdir/file")));
+        assert!(!dirs.has_explicit_ancestor(&RepoPath::from_internal_string("dir1/file1")));
+        assert!(!dirs.has_explicit_ancestor(&RepoPath::from_internal_string("dir2")));
+    }
+
     #[test]
     fn test_nothingmatcher() {
         let m = NothingMatcher;
@@ -517,6 +772,135 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_fold_case() {
+        assert_eq!(fold_case("README.md"), "readme.md");
+        assert_eq!(fold_case("Straße"), "strasse");
+        assert_eq!(fold_case("STRASSE"), "strasse");
+        assert_eq!(fold_case("ΟΔΟΣ"), fold_case("οδος"));
+        assert_eq!(fold_case("οδοσ"), fold_case("οδος"));
+    }
+
+    #[test]
+    fn test_filesmatcher_case_insensitive() {
+        let m = FilesMatcher::new_case_insensitive(hashset! {
+            RepoPath::from_internal_string("Dir1/File1"),
+            RepoPath::from_internal_string("file2"),
+        });
+
+        assert!(m.matches(&RepoPath::from_internal_string("dir1/file1")));
+        assert!(m.matches(&RepoPath::from_internal_string("DIR1/FILE1")));
+        assert!(m.matches(&RepoPath::from_internal_string("File2")));
+        assert!(!m.matches(&RepoPath::from_internal_string("dir1")));
+        assert!(!m.matches(&RepoPath::from_internal_string("dir1/file2")));
+
+        assert_eq!(
+            m.visit(&RepoPath::root()),
+            Visit::Specific {
+                dirs: VisitDirs::All,
+                files: VisitFiles::All,
+            }
+        );
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("DIR1")),
+            Visit::Specific {
+                dirs: VisitDirs::Set(hashset! {}),
+                files: VisitFiles::All,
+            }
+        );
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("dir2")),
+            Visit::Nothing
+        );
+    }
+
+    #[test]
+    fn test_prefixmatcher_case_insensitive() {
+        let m = PrefixMatcher::new_case_insensitive(&[RepoPath::from_internal_string("Foo/Bar")]);
+
+        assert!(m.matches(&RepoPath::from_internal_string("foo/bar")));
+        assert!(m.matches(&RepoPath::from_internal_string("FOO/BAR/baz")));
+        assert!(!m.matches(&RepoPath::from_internal_string("foo")));
+        assert!(!m.matches(&RepoPath::from_internal_string("foo/barbaz")));
+
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("FOO")),
+            Visit::Specific {
+                dirs: VisitDirs::All,
+                files: VisitFiles::All,
+            }
+        );
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("foo/BAR")),
+            Visit::AllRecursively
+        );
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("qux")),
+            Visit::Nothing
+        );
+    }
+
+    #[test]
+    fn test_ignorematcher() {
+        let git_ignore = GitIgnoreFile::empty().chain("", b"*.o\n/target/\n!keep.o\n");
+        let m = IgnoreMatcher::new(git_ignore);
+
+        assert!(m.matches(&RepoPath::from_internal_string("foo.o")));
+        assert!(m.matches(&RepoPath::from_internal_string("dir/foo.o")));
+        assert!(!m.matches(&RepoPath::from_internal_string("keep.o")));
+        assert!(!m.matches(&RepoPath::from_internal_string("foo.c")));
+        assert!(m.matches(&RepoPath::from_internal_string("target/foo.c")));
+
+        assert_eq!(
+            m.visit(&RepoPath::root()),
+            Visit::Specific {
+                dirs: VisitDirs::All,
+                files: VisitFiles::All,
+            }
+        );
+        // The negative pattern for "keep.o" can't re-include files in target/
+        assert!(m.matches(&RepoPath::from_internal_string("target/keep.o")));
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("target")),
+            Visit::AllRecursively
+        );
+        // But it means we can't skip other directories
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("dir")),
+            Visit::Specific {
+                dirs: VisitDirs::All,
+                files: VisitFiles::All,
+            }
+        );
+
+        let git_ignore = GitIgnoreFile::empty().chain("", b"/target/\n");
+        let m = IgnoreMatcher::new(git_ignore);
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("target")),
+            Visit::AllRecursively
+        );
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("target/debug")),
+            Visit::AllRecursively
+        );
+    }
+
+    #[test]
+    fn test_ignorematcher_difference() {
+        // Files under "src" that are not ignored
+        let m1 = PrefixMatcher::new(&[RepoPath::from_internal_string("src")]);
+        let m2 = IgnoreMatcher::new(GitIgnoreFile::empty().chain("", b"/src/generated/\n"));
+        let m = DifferenceMatcher::new(&m1, &m2);
+
+        assert!(m.matches(&RepoPath::from_internal_string("src/lib.rs")));
+        assert!(!m.matches(&RepoPath::from_internal_string("src/generated/lib.rs")));
+        assert!(!m.matches(&RepoPath::from_internal_string("README")));
+        assert_eq!(
+            m.visit(&RepoPath::from_internal_string("src/generated")),
+            Visit::Nothing
+        );
+    }
+
     #[test]
     fn test_differencematcher_remove_subdir() {
         let m1 = PrefixMatcher::new(&[
@@ -683,4 +1067,24 @@ mod tests {
             Visit::AllRecursively
         );
     }
+
+    #[test]
+    fn test_file_pattern() {
+        let matches = |glob: &str, path: &str| {
+            FilePattern::new(glob).matches(&RepoPath::from_internal_string(path))
+        };
+        // Patterns without a slash match the file name in any directory
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", "src/main.rs"));
+        assert!(!matches("*.rs", "main.rs.orig"));
+        // Other patterns match the whole path
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(!matches("src/*.rs", "src/foo/main.rs"));
+        // `**/` matches zero or more directories
+        assert!(matches("src/**/*.rs", "src/main.rs"));
+        assert!(matches("src/**/*.rs", "src/foo/bar/main.rs"));
+        assert!(matches("**/test/*.rs", "test/main.rs"));
+        assert!(matches("**/test/*.rs", "foo/test/main.rs"));
+        assert!(!matches("**/test/*.rs", "footest/main.rs"));
+    }
 }
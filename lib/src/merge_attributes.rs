@@ -0,0 +1,220 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Per-path options for merging file contents, like Git's `merge` attributes.
+//! They're configured in `[merge-attributes.<name>]` tables, each of which has
+//! a list of glob `patterns` and the options for the matching files.
+
+use std::process::{Command, Stdio};
+use std::{fs, io};
+
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::matchers::FilePattern;
+use crate::repo_path::RepoPath;
+
+#[derive(Debug, Error)]
+pub enum MergeAttributesError {
+    #[error("Invalid merge attributes config: {0}")]
+    InvalidConfig(String),
+}
+
+/// A merge driver couldn't be run at all (as opposed to not being able to
+/// merge the contents).
+#[derive(Debug, Error)]
+#[error("Failed to run the merge driver of merge-attributes.{name}: {err}")]
+pub struct MergeDriverError {
+    pub name: String,
+    #[source]
+    pub err: io::Error,
+}
+
+/// Built-in ways of resolving conflicts in a file, like Git's `union`, `ours`
+/// and `theirs` merge options.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Conflicting lines from both sides are kept, the first side's first.
+    Union,
+    /// The first side's version of the file wins (when rebasing, the
+    /// destination's).
+    Ours,
+    /// The second side's version of the file wins (when rebasing, the
+    /// version of the revision being rebased).
+    Theirs,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergeAttribute {
+    pub name: String,
+    pub patterns: Vec<FilePattern>,
+    /// Changes that only add or remove whitespace (including changing line
+    /// endings) don't conflict with other changes to the same lines. The
+    /// other changes win.
+    pub ignore_whitespace: bool,
+    /// Program and arguments of an external merge driver, like Git's
+    /// `merge.<driver>.driver`. It's run instead of the built-in merge.
+    /// `$base`, `$left` and `$right` are replaced by the paths of temporary
+    /// files with the contents to merge, and `$path` by the path of the file in
+    /// the repo. The driver prints the merged contents and exits with 0, or
+    /// exits with an error to leave the file conflicted.
+    pub driver: Option<Vec<String>>,
+    pub strategy: Option<MergeStrategy>,
+}
+
+impl MergeAttribute {
+    /// Runs the merge driver, if any. Returns the merged contents, or `None`
+    /// if there's no driver or it exited with an error. The driver's stderr
+    /// goes to ours, so it can say why it couldn't merge the contents.
+    pub fn run_driver(
+        &self,
+        path: &RepoPath,
+        base: &[u8],
+        left: &[u8],
+        right: &[u8],
+    ) -> Result<Option<Vec<u8>>, MergeDriverError> {
+        let command = match &self.driver {
+            Some(command) => command,
+            None => return Ok(None),
+        };
+        let driver_error = |err| MergeDriverError {
+            name: self.name.clone(),
+            err,
+        };
+        let temp_dir = tempfile::tempdir().map_err(driver_error)?;
+        let mut replacements = vec![("$path", path.to_internal_file_string())];
+        for (placeholder, contents) in [("$base", base), ("$left", left), ("$right", right)] {
+            let file_path = temp_dir.path().join(&placeholder[1..]);
+            fs::write(&file_path, contents).map_err(driver_error)?;
+            let file_path = file_path.to_str().ok_or_else(|| {
+                driver_error(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("temporary path {} isn't valid UTF-8", file_path.display()),
+                ))
+            })?;
+            replacements.push((placeholder, file_path.to_string()));
+        }
+        let args = command
+            .iter()
+            .map(|arg| {
+                replacements
+                    .iter()
+                    .fold(arg.clone(), |arg, (from, to)| arg.replace(from, to))
+            })
+            .collect_vec();
+        let output = Command::new(&args[0])
+            .args(&args[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::inherit())
+            .output()
+            .map_err(driver_error)?;
+        Ok(output.status.success().then(|| output.stdout))
+    }
+}
+
+/// The merge attributes configured for a repo. The first attribute (ordered
+/// by name) whose patterns match a path is the one that applies to it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeAttributes {
+    attributes: Vec<MergeAttribute>,
+}
+
+impl MergeAttributes {
+    pub fn new(attributes: Vec<MergeAttribute>) -> Self {
+        MergeAttributes { attributes }
+    }
+
+    pub fn from_config(config: &config::Config) -> Result<Self, MergeAttributesError> {
+        let table = match config.get_table("merge-attributes") {
+            Ok(table) => table,
+            Err(config::ConfigError::NotFound(_)) => return Ok(Self::default()),
+            Err(err) => return Err(MergeAttributesError::InvalidConfig(err.to_string())),
+        };
+        let mut attributes = vec![];
+        for (name, value) in table.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+            let key = format!("merge-attributes.{name}");
+            let invalid = |err: config::ConfigError| {
+                MergeAttributesError::InvalidConfig(format!("{key}: {err}"))
+            };
+            let string_list = |value: config::Value| {
+                value
+                    .into_array()
+                    .map_err(invalid)?
+                    .into_iter()
+                    .map(|item| item.into_string())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(invalid)
+            };
+            let mut table = value.into_table().map_err(invalid)?;
+            let patterns = string_list(table.remove("patterns").ok_or_else(|| {
+                MergeAttributesError::InvalidConfig(format!("{key}: missing `patterns`"))
+            })?)?;
+            let ignore_whitespace = match table.remove("ignore-whitespace") {
+                None => false,
+                Some(value) => value.into_bool().map_err(invalid)?,
+            };
+            let driver = match table.remove("driver") {
+                None => None,
+                Some(value) => {
+                    let driver = string_list(value)?;
+                    if driver.is_empty() {
+                        return Err(MergeAttributesError::InvalidConfig(format!(
+                            "{key}: `driver` must not be empty"
+                        )));
+                    }
+                    Some(driver)
+                }
+            };
+            let strategy = match table.remove("strategy") {
+                None => None,
+                Some(value) => match value.into_string().map_err(invalid)?.as_str() {
+                    "union" => Some(MergeStrategy::Union),
+                    "ours" => Some(MergeStrategy::Ours),
+                    "theirs" => Some(MergeStrategy::Theirs),
+                    other => {
+                        return Err(MergeAttributesError::InvalidConfig(format!(
+                            "{key}: `strategy` must be \"union\", \"ours\" or \"theirs\", not \"{other}\""
+                        )))
+                    }
+                },
+            };
+            if driver.is_some() && strategy.is_some() {
+                return Err(MergeAttributesError::InvalidConfig(format!(
+                    "{key}: `driver` and `strategy` can't both be set"
+                )));
+            }
+            attributes.push(MergeAttribute {
+                name,
+                patterns: patterns.iter().map(|glob| FilePattern::new(glob)).collect(),
+                ignore_whitespace,
+                driver,
+                strategy,
+            });
+        }
+        Ok(Self::new(attributes))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty()
+    }
+
+    pub fn for_path(&self, path: &RepoPath) -> Option<&MergeAttribute> {
+        self.attributes.iter().find(|attribute| {
+            attribute
+                .patterns
+                .iter()
+                .any(|pattern| pattern.matches(path))
+        })
+    }
+}
@@ -0,0 +1,348 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Key-value stores for the objects of a `RemoteBackend`, such as a directory
+//! on a shared filesystem.
+
+use std::fmt::Debug;
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use backoff::{retry, ExponentialBackoff};
+use rayon::prelude::*;
+use tempfile::NamedTempFile;
+use thiserror::Error;
+
+use crate::file_util::persist_content_addressed_temp_file;
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum ObjectStoreError {
+    /// An error that may go away if the request is retried, such as a network
+    /// error or an overloaded server.
+    #[error("{0}")]
+    Transient(String),
+    #[error("{0}")]
+    Permanent(String),
+}
+
+pub type ObjectStoreResult<T> = Result<T, ObjectStoreError>;
+
+/// A store of immutable objects by key. Keys look like relative paths
+/// (`trees/<hex id>`). Since objects are content-addressed, writing the same
+/// key twice always writes the same data.
+pub trait ObjectStore: Send + Sync + Debug {
+    /// Returns the object's data, or `None` if there's no such object.
+    fn get(&self, key: &str) -> ObjectStoreResult<Option<Vec<u8>>>;
+
+    fn put(&self, key: &str, data: &[u8]) -> ObjectStoreResult<()>;
+
+    /// Returns the data of several objects, in the order of `keys`. Stores
+    /// where requests have high latency should make the requests concurrently.
+    fn get_many(&self, keys: &[String]) -> ObjectStoreResult<Vec<Option<Vec<u8>>>> {
+        keys.iter().map(|key| self.get(key)).collect()
+    }
+}
+
+/// Creates an object store from a `file://` URL (a directory, possibly on a
+/// shared filesystem) or an `http://` URL (a server that accepts plain,
+/// unauthenticated `GET` and `PUT` requests).
+pub fn object_store_from_url(url: &str) -> ObjectStoreResult<Box<dyn ObjectStore>> {
+    if let Some(path) = url.strip_prefix("file://") {
+        Ok(Box::new(DirectoryObjectStore::new(Path::new(path))))
+    } else if url.starts_with("http://") {
+        Ok(Box::new(HttpObjectStore::new(url)?))
+    } else {
+        Err(ObjectStoreError::Permanent(format!(
+            "Unsupported object store URL: {url} (expected a file:// or http:// URL)"
+        )))
+    }
+}
+
+/// Calls `op` until it succeeds or fails with a permanent error, waiting
+/// exponentially longer between attempts. Gives up after about a minute.
+pub fn with_retries<T>(mut op: impl FnMut() -> ObjectStoreResult<T>) -> ObjectStoreResult<T> {
+    let backoff = ExponentialBackoff {
+        initial_interval: Duration::from_millis(50),
+        max_elapsed_time: Some(Duration::from_secs(60)),
+        ..Default::default()
+    };
+    let attempt = || {
+        op().map_err(|err| match err {
+            ObjectStoreError::Transient(_) => backoff::Error::Transient {
+                err,
+                retry_after: None,
+            },
+            ObjectStoreError::Permanent(_) => backoff::Error::Permanent(err),
+        })
+    };
+    retry(backoff, attempt).map_err(|err| match err {
+        backoff::Error::Permanent(err) | backoff::Error::Transient { err, .. } => err,
+    })
+}
+
+/// Stores each object in a file under a directory.
+#[derive(Debug)]
+pub struct DirectoryObjectStore {
+    path: PathBuf,
+}
+
+impl DirectoryObjectStore {
+    pub fn new(path: &Path) -> Self {
+        DirectoryObjectStore {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl ObjectStore for DirectoryObjectStore {
+    fn get(&self, key: &str) -> ObjectStoreResult<Option<Vec<u8>>> {
+        match fs::read(self.path.join(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(io_error(err)),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> ObjectStoreResult<()> {
+        let path = self.path.join(key);
+        let write = || -> std::io::Result<()> {
+            let dir = path.parent().unwrap();
+            fs::create_dir_all(dir)?;
+            let mut temp_file = NamedTempFile::new_in(dir)?;
+            temp_file.write_all(data)?;
+            persist_content_addressed_temp_file(temp_file, &path)?;
+            Ok(())
+        };
+        write().map_err(io_error)
+    }
+}
+
+/// Classifies an error from a (possibly network) filesystem. Only errors that
+/// can go away by themselves are transient; e.g. missing permissions aren't.
+fn io_error(err: std::io::Error) -> ObjectStoreError {
+    match err.kind() {
+        ErrorKind::Interrupted
+        | ErrorKind::TimedOut
+        | ErrorKind::WouldBlock
+        | ErrorKind::ConnectionReset
+        | ErrorKind::ConnectionAborted
+        | ErrorKind::NotConnected => ObjectStoreError::Transient(err.to_string()),
+        _ => ObjectStoreError::Permanent(err.to_string()),
+    }
+}
+
+/// Talks to an HTTP server that stores objects at `<url>/<key>`, answering
+/// `GET` with the object (or 404) and storing the body of `PUT` requests.
+/// Requests are neither authenticated nor encrypted (there's no HTTPS), so
+/// this is only meant for servers on a trusted network.
+#[derive(Debug)]
+pub struct HttpObjectStore {
+    host: String,
+    port: u16,
+    /// The path part of the URL, ending with a slash
+    path_prefix: String,
+}
+
+impl HttpObjectStore {
+    pub fn new(url: &str) -> ObjectStoreResult<Self> {
+        let invalid = || ObjectStoreError::Permanent(format!("Invalid HTTP URL: {url}"));
+        let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => rest.split_at(slash),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        let mut path_prefix = path.to_string();
+        if !path_prefix.ends_with('/') {
+            path_prefix.push('/');
+        }
+        Ok(HttpObjectStore {
+            host: host.to_string(),
+            port,
+            path_prefix,
+        })
+    }
+
+    /// Sends a request and returns the response's status code and body.
+    fn request(&self, method: &str, key: &str, body: &[u8]) -> ObjectStoreResult<(u16, Vec<u8>)> {
+        let transient = |err: std::io::Error| ObjectStoreError::Transient(err.to_string());
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port)).map_err(transient)?;
+        stream
+            .set_read_timeout(Some(Duration::from_secs(60)))
+            .map_err(transient)?;
+        stream
+            .set_write_timeout(Some(Duration::from_secs(60)))
+            .map_err(transient)?;
+        let mut request = format!(
+            "{method} {}{key} HTTP/1.1\r\nHost: {}:{}\r\nConnection: close\r\nContent-Length: \
+             {}\r\n\r\n",
+            self.path_prefix,
+            self.host,
+            self.port,
+            body.len()
+        )
+        .into_bytes();
+        request.extend_from_slice(body);
+        stream.write_all(&request).map_err(transient)?;
+        let mut response = vec![];
+        stream.read_to_end(&mut response).map_err(transient)?;
+        parse_http_response(&response).ok_or_else(|| {
+            ObjectStoreError::Transient(format!(
+                "Malformed HTTP response from {}:{}",
+                self.host, self.port
+            ))
+        })
+    }
+
+    fn status_error(&self, method: &str, key: &str, status: u16) -> ObjectStoreError {
+        let message = format!(
+            "HTTP {method} http://{}:{}{}{key} failed with status {status}",
+            self.host, self.port, self.path_prefix
+        );
+        if status == 408 || status == 429 || status >= 500 {
+            ObjectStoreError::Transient(message)
+        } else {
+            ObjectStoreError::Permanent(message)
+        }
+    }
+}
+
+impl ObjectStore for HttpObjectStore {
+    fn get(&self, key: &str) -> ObjectStoreResult<Option<Vec<u8>>> {
+        match self.request("GET", key, &[])? {
+            (200, body) => Ok(Some(body)),
+            (404, _) => Ok(None),
+            (status, _) => Err(self.status_error("GET", key, status)),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> ObjectStoreResult<()> {
+        match self.request("PUT", key, data)? {
+            (200..=299, _) => Ok(()),
+            (status, _) => Err(self.status_error("PUT", key, status)),
+        }
+    }
+
+    fn get_many(&self, keys: &[String]) -> ObjectStoreResult<Vec<Option<Vec<u8>>>> {
+        keys.par_iter().map(|key| self.get(key)).collect()
+    }
+}
+
+/// Parses a complete HTTP/1.1 response into its status code and body.
+fn parse_http_response(response: &[u8]) -> Option<(u16, Vec<u8>)> {
+    let header_end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")?;
+    let head = std::str::from_utf8(&response[..header_end]).ok()?;
+    let body = &response[header_end + 4..];
+    let mut lines = head.split("\r\n");
+    let status = lines.next()?.split(' ').nth(1)?.parse().ok()?;
+    let mut content_length = None;
+    let mut chunked = false;
+    for line in lines {
+        let (name, value) = line.split_once(':')?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = Some(value.parse::<usize>().ok()?);
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        }
+    }
+    let body = if chunked {
+        decode_chunked_body(body)?
+    } else if let Some(length) = content_length {
+        body.get(..length)?.to_vec()
+    } else {
+        body.to_vec()
+    };
+    Some((status, body))
+}
+
+fn decode_chunked_body(mut encoded: &[u8]) -> Option<Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        let line_end = encoded.windows(2).position(|window| window == b"\r\n")?;
+        let size_str = std::str::from_utf8(&encoded[..line_end]).ok()?;
+        let size_str = size_str.split(';').next().unwrap().trim();
+        let size = usize::from_str_radix(size_str, 16).ok()?;
+        encoded = &encoded[line_end + 2..];
+        if size == 0 {
+            return Some(body);
+        }
+        body.extend_from_slice(encoded.get(..size)?);
+        encoded = encoded.get(size + 2..)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_http_response() {
+        assert_eq!(
+            parse_http_response(b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\nabc"),
+            Some((200, b"abc".to_vec()))
+        );
+        assert_eq!(
+            parse_http_response(b"HTTP/1.1 404 Not Found\r\n\r\n"),
+            Some((404, vec![]))
+        );
+        assert_eq!(
+            parse_http_response(
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n"
+            ),
+            Some((200, b"abcde".to_vec()))
+        );
+        // Truncated
+        assert_eq!(
+            parse_http_response(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nabc"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_io_error() {
+        assert_eq!(
+            io_error(std::io::Error::new(ErrorKind::TimedOut, "timed out")),
+            ObjectStoreError::Transient("timed out".to_string())
+        );
+        assert_eq!(
+            io_error(std::io::Error::new(ErrorKind::PermissionDenied, "denied")),
+            ObjectStoreError::Permanent("denied".to_string())
+        );
+    }
+
+    #[test]
+    fn test_http_object_store_url() {
+        let store = HttpObjectStore::new("http://example.com:8080/bucket").unwrap();
+        assert_eq!(store.host, "example.com");
+        assert_eq!(store.port, 8080);
+        assert_eq!(store.path_prefix, "/bucket/");
+        let store = HttpObjectStore::new("http://example.com").unwrap();
+        assert_eq!(store.port, 80);
+        assert_eq!(store.path_prefix, "/");
+        assert!(HttpObjectStore::new("http://:80/").is_err());
+        assert!(object_store_from_url("s3://bucket").is_err());
+    }
+}
@@ -61,6 +61,17 @@ impl LockedOpHeads {
             self.store.remove_op_head(old_id);
         }
     }
+
+    /// Replaces the op heads by other operations that aren't descendants of
+    /// them, such as rewritten copies of them.
+    pub fn replace(&self, old_ids: &[OperationId], new_ids: &[OperationId]) {
+        for new_id in new_ids {
+            self.store.add_op_head(new_id);
+        }
+        for old_id in old_ids {
+            self.store.remove_op_head(old_id);
+        }
+    }
 }
 
 impl OpHeadsStore {
@@ -113,6 +124,34 @@ impl OpHeadsStore {
         op_heads
     }
 
+    /// Returns the current op heads without taking the lock and without
+    /// writing anything. Heads that are ancestors of other heads are filtered
+    /// out (but not removed from disk). The result is sorted by end time, so
+    /// the most recent operation is last.
+    pub fn get_heads_unlocked(
+        &self,
+        op_store: &Arc<dyn OpStore>,
+    ) -> Result<Vec<Operation>, OpHeadResolutionError> {
+        let op_heads = self
+            .get_op_heads()
+            .into_iter()
+            .map(|op_id| {
+                let data = op_store.read_operation(&op_id).unwrap();
+                Operation::new(op_store.clone(), op_id, data)
+            })
+            .collect_vec();
+        if op_heads.is_empty() {
+            return Err(OpHeadResolutionError::NoHeads);
+        }
+        let mut op_heads = dag_walk::heads(op_heads, &|op: &Operation| op.parents(), &|op| {
+            op.id().clone()
+        })
+        .into_iter()
+        .collect_vec();
+        op_heads.sort_by_key(|op| op.store_operation().metadata.end_time.timestamp.clone());
+        Ok(op_heads)
+    }
+
     pub fn lock(self: &Arc<Self>) -> LockedOpHeads {
         let lock = FileLock::lock(self.dir.join("lock"));
         LockedOpHeads {
@@ -17,7 +17,7 @@ use std::fmt::{Debug, Error, Formatter};
 
 use thiserror::Error;
 
-use crate::backend::{CommitId, Timestamp};
+use crate::backend::{ChangeId, CommitId, Timestamp};
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Hash)]
 pub struct WorkspaceId(String);
@@ -175,6 +175,12 @@ pub struct View {
     // (.jj/working_copy/) has the source of truth about which commit *is* checked out (to be
     // precise: the commit to which we most recently completed an update to).
     pub wc_commit_ids: HashMap<WorkspaceId, CommitId>,
+    /// Human-readable names given to changes. Since they point to change ids,
+    /// they follow the changes when they're rewritten.
+    pub change_names: BTreeMap<String, ChangeId>,
+    /// The topics of changes, for grouping related changes. Like names, they
+    /// follow the changes when they're rewritten.
+    pub change_topics: BTreeMap<ChangeId, String>,
 }
 
 /// Represents an operation (transaction) on the repo view, just like how a
@@ -0,0 +1,35 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The types most tools built on this crate need, for importing with
+//! `use jujutsu_lib::prelude::*`.
+//!
+//! We try to keep the items re-exported here stable. Breaking changes to them
+//! are called out in the changelog. Everything else in the crate may change
+//! whenever the CLI needs it to.
+
+pub use crate::backend::{ChangeId, CommitId, Signature, Timestamp, TreeId, TreeValue};
+pub use crate::commit::Commit;
+pub use crate::commit_builder::CommitBuilder;
+pub use crate::matchers::{EverythingMatcher, FilesMatcher, Matcher, PrefixMatcher};
+pub use crate::op_store::{OperationId, WorkspaceId};
+pub use crate::operation::Operation;
+pub use crate::repo::{MutableRepo, ReadonlyRepo, RepoRef};
+pub use crate::repo_path::RepoPath;
+pub use crate::revset::{Revset, RevsetError, RevsetExpression, RevsetParseError};
+pub use crate::settings::UserSettings;
+pub use crate::store::Store;
+pub use crate::transaction::Transaction;
+pub use crate::tree::Tree;
+pub use crate::workspace::{Workspace, WorkspaceInitError, WorkspaceLoadError};
@@ -0,0 +1,144 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Precomputed sets of the ancestors of branch heads ("reachability
+//! bitmaps"). With them, `:branch` and checking whether a commit is an
+//! ancestor of a branch don't need to walk the history.
+//!
+//! A bitmap is computed the first time it's needed and stored next to the
+//! index. When a branch moves, its new bitmap is computed by walking from the
+//! new head until reaching commits that already have one (typically the
+//! branch's previous head), so keeping them up to date is cheap.
+//!
+//! The bitmaps are sets of index positions, so they are only valid for the
+//! index they were computed with. They are keyed by the name of the index file
+//! that contains the head, which identifies the positions of all its
+//! ancestors.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use blake2::{Blake2b512, Digest};
+
+use crate::backend::CommitId;
+use crate::index::{IndexPosition, IndexPositionSet, IndexRef};
+use crate::stacked_table::{ReadonlyTable, TableSegment, TableStore, TableStoreResult};
+use crate::view::View;
+
+const KEY_SIZE: usize = 32;
+
+pub struct ReachabilityBitmaps {
+    table_store: TableStore,
+    cache: Mutex<HashMap<Vec<u8>, Arc<IndexPositionSet>>>,
+}
+
+impl ReachabilityBitmaps {
+    /// Loads the bitmaps from `dir`, which is created if it doesn't exist.
+    pub fn load(dir: PathBuf) -> TableStoreResult<Self> {
+        let table_store = if dir.join("heads").is_dir() {
+            TableStore::load(dir, KEY_SIZE)
+        } else {
+            std::fs::create_dir_all(&dir)?;
+            TableStore::init(dir, KEY_SIZE)
+        };
+        Ok(ReachabilityBitmaps {
+            table_store,
+            cache: Default::default(),
+        })
+    }
+
+    fn key(index: IndexRef, pos: IndexPosition) -> Option<Vec<u8>> {
+        let segment_name = index.segment_name_containing(pos)?;
+        let mut hasher = Blake2b512::new();
+        hasher.update(segment_name.as_bytes());
+        hasher.update(index.entry_by_pos(pos).commit_id().as_bytes());
+        Some(hasher.finalize()[..KEY_SIZE].to_vec())
+    }
+
+    /// Looks up the bitmap for `pos` in the cache, or else in `table` (the
+    /// head of the table store, which the caller loads once).
+    fn get(
+        &self,
+        table: Option<&ReadonlyTable>,
+        index: IndexRef,
+        pos: IndexPosition,
+    ) -> Option<Arc<IndexPositionSet>> {
+        let key = Self::key(index, pos)?;
+        if let Some(bitmap) = self.cache.lock().unwrap().get(&key) {
+            return Some(bitmap.clone());
+        }
+        let bitmap = Arc::new(IndexPositionSet::from_bytes(
+            &zstd::decode_all(table?.get_value(&key)?).ok()?,
+        ));
+        self.cache.lock().unwrap().insert(key, bitmap.clone());
+        Some(bitmap)
+    }
+
+    fn insert(&self, key: Vec<u8>, bitmap: Arc<IndexPositionSet>) -> TableStoreResult<()> {
+        let value = zstd::encode_all(bitmap.to_bytes().as_slice(), 0)?;
+        let mut mut_table = self.table_store.get_head()?.start_mutation();
+        mut_table.add_entry(key.clone(), value);
+        self.table_store.save_table(mut_table)?;
+        self.cache.lock().unwrap().insert(key, bitmap);
+        Ok(())
+    }
+
+    /// The positions of the commit and all its ancestors. The result is
+    /// stored so it can be reused by later calls, and by the computation for
+    /// descendants of the commit.
+    pub fn ancestors(&self, index: IndexRef, commit_id: &CommitId) -> Arc<IndexPositionSet> {
+        let pos = index.commit_id_to_pos(commit_id).unwrap();
+        let table = self.table_store.get_head().ok();
+        let table = table.as_deref();
+        if let Some(bitmap) = self.get(table, index, pos) {
+            return bitmap;
+        }
+        let bitmap = Arc::new(index.reachable_positions(pos, &|pos| self.get(table, index, pos)));
+        if let Some(key) = Self::key(index, pos) {
+            // The bitmaps are only a cache, so it's fine if they can't be
+            // written (e.g. because the repo is read-only)
+            self.insert(key, bitmap.clone()).ok();
+        }
+        bitmap
+    }
+
+    /// Like `IndexRef::is_ancestor()`, but uses (and computes) the bitmap
+    /// for `descendant_id`. Use it when `descendant_id` is a branch head.
+    pub fn is_ancestor(
+        &self,
+        index: IndexRef,
+        ancestor_id: &CommitId,
+        descendant_id: &CommitId,
+    ) -> bool {
+        let ancestor_pos = index.commit_id_to_pos(ancestor_id).unwrap();
+        self.ancestors(index, descendant_id).contains(ancestor_pos)
+    }
+}
+
+/// The commits local and remote branches point to.
+pub fn branch_heads(view: &View) -> Vec<CommitId> {
+    let mut commit_ids = vec![];
+    for branch_target in view.branches().values() {
+        if let Some(local_target) = &branch_target.local_target {
+            commit_ids.extend(local_target.adds());
+        }
+        for remote_target in branch_target.remote_targets.values() {
+            commit_ids.extend(remote_target.adds());
+        }
+    }
+    commit_ids.sort();
+    commit_ids.dedup();
+    commit_ids
+}
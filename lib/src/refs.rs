@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use regex::Regex;
+
 use crate::backend::CommitId;
 use crate::index::IndexRef;
 use crate::op_store::{BranchTarget, RefTarget};
@@ -108,6 +110,78 @@ fn find_pair_to_remove(
     None
 }
 
+/// How far apart two refs are: `ahead` is the number of commits reachable only
+/// from the first ref and `behind` the number reachable only from the second.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct RefDivergence {
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+impl RefDivergence {
+    pub fn is_diverged(&self) -> bool {
+        self.ahead != 0 && self.behind != 0
+    }
+}
+
+/// Counts the commits that `local` has and `other` doesn't have, and vice
+/// versa. The targets' adds are used, so conflicted refs count the commits
+/// reachable from any side.
+pub fn ref_divergence(index: IndexRef, local: &RefTarget, other: &RefTarget) -> RefDivergence {
+    ref_divergence_bounded(index, local, other, usize::MAX)
+}
+
+/// Like `ref_divergence()`, but stops walking the index after `limit` commits
+/// on each side, so it stays cheap when the refs are far apart. A count equal
+/// to `limit` means "at least `limit`".
+pub fn ref_divergence_bounded(
+    index: IndexRef,
+    local: &RefTarget,
+    other: &RefTarget,
+    limit: usize,
+) -> RefDivergence {
+    let local_heads = local.adds();
+    let other_heads = other.adds();
+    RefDivergence {
+        ahead: index
+            .walk_revs(&local_heads, &other_heads)
+            .take(limit)
+            .count(),
+        behind: index
+            .walk_revs(&other_heads, &local_heads)
+            .take(limit)
+            .count(),
+    }
+}
+
+/// A glob pattern for matching ref names. `*` matches any sequence of
+/// characters (including `/`) and `?` matches a single character.
+#[derive(Debug, Clone)]
+pub struct RefNamePattern {
+    regex: Regex,
+}
+
+impl RefNamePattern {
+    pub fn glob(pattern: &str) -> Self {
+        let mut regex = "^".to_string();
+        for c in pattern.chars() {
+            match c {
+                '*' => regex.push_str(".*"),
+                '?' => regex.push('.'),
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+        RefNamePattern {
+            regex: Regex::new(&regex).unwrap(),
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.regex.is_match(name)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct BranchPushUpdate {
     pub old_target: Option<CommitId>,
@@ -162,6 +236,24 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_ref_name_pattern() {
+        let pattern = RefNamePattern::glob("feature/*");
+        assert!(pattern.matches("feature/foo"));
+        assert!(pattern.matches("feature/foo/bar"));
+        assert!(!pattern.matches("feature"));
+        assert!(!pattern.matches("my-feature/foo"));
+
+        let pattern = RefNamePattern::glob("v1.?");
+        assert!(pattern.matches("v1.2"));
+        assert!(!pattern.matches("v1x2"));
+        assert!(!pattern.matches("v1.23"));
+
+        let pattern = RefNamePattern::glob("main");
+        assert!(pattern.matches("main"));
+        assert!(!pattern.matches("main2"));
+    }
+
     #[test]
     fn test_classify_branch_push_action_unchanged() {
         let commit_id1 = CommitId::from_hex("11");
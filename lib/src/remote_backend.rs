@@ -0,0 +1,316 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs;
+use std::io::{Cursor, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+use blake2::{Blake2b512, Digest};
+use protobuf::Message;
+use tempfile::NamedTempFile;
+
+use crate::backend::{
+    make_root_commit, Backend, BackendError, BackendResult, Commit, CommitId, Conflict, ConflictId,
+    FileId, ObjectKind, SymlinkId, Tree, TreeId, TreeValue,
+};
+use crate::file_util::persist_content_addressed_temp_file;
+use crate::local_backend::{
+    commit_from_proto, commit_to_proto, conflict_from_proto, conflict_to_proto, tree_from_proto,
+    tree_to_proto,
+};
+use crate::object_store::{object_store_from_url, with_retries, ObjectStore, ObjectStoreError};
+use crate::repo_path::RepoPath;
+
+impl From<ObjectStoreError> for BackendError {
+    fn from(err: ObjectStoreError) -> Self {
+        BackendError::Other(err.to_string())
+    }
+}
+
+const OBJECT_KINDS: [ObjectKind; 5] = [
+    ObjectKind::Commit,
+    ObjectKind::Tree,
+    ObjectKind::File,
+    ObjectKind::Symlink,
+    ObjectKind::Conflict,
+];
+
+/// A backend that keeps its objects in an `ObjectStore` (e.g. a directory on a
+/// shared filesystem) so several repos can share them, with a local cache in
+/// `.jj/repo/store/cache/`. Objects are encoded and hashed like in the
+/// `LocalBackend`. Only the objects are shared; each repo has its own
+/// operation log.
+#[derive(Debug)]
+pub struct RemoteBackend {
+    path: PathBuf,
+    object_store: Box<dyn ObjectStore>,
+    root_commit_id: CommitId,
+    empty_tree_id: TreeId,
+}
+
+impl RemoteBackend {
+    /// Creates a backend for the object store at `url` (see
+    /// `object_store_from_url()`), which may already hold objects written by
+    /// other repos. Doesn't talk to the object store.
+    pub fn init(store_path: &Path, url: &str) -> BackendResult<Self> {
+        let object_store = object_store_from_url(url)?;
+        fs::write(store_path.join("remote_url"), url)?;
+        Self::init_with_object_store(store_path, object_store)
+    }
+
+    pub fn init_with_object_store(
+        store_path: &Path,
+        object_store: Box<dyn ObjectStore>,
+    ) -> BackendResult<Self> {
+        Self::load_with_object_store(store_path, object_store)
+    }
+
+    pub fn load(store_path: &Path) -> BackendResult<Self> {
+        let url = fs::read_to_string(store_path.join("remote_url"))?;
+        Self::load_with_object_store(store_path, object_store_from_url(url.trim())?)
+    }
+
+    pub fn load_with_object_store(
+        store_path: &Path,
+        object_store: Box<dyn ObjectStore>,
+    ) -> BackendResult<Self> {
+        for kind in OBJECT_KINDS {
+            fs::create_dir_all(store_path.join("cache").join(object_dir_name(kind)))?;
+        }
+        let root_commit_id = CommitId::from_bytes(&[0; 64]);
+        let empty_tree_id = TreeId::from_hex("786a02f742015903c6c6fd852552d272912f4740e15847618a86e217f71f5419d25e1031afee585313896444934eb04b903a685b1448b755d56f701afe9be2ce");
+        let backend = RemoteBackend {
+            path: store_path.to_path_buf(),
+            object_store,
+            root_commit_id,
+            empty_tree_id,
+        };
+        // The empty tree encodes to no bytes. Caching it means it never has to be
+        // fetched, even if no repo has uploaded it.
+        if !backend
+            .cache_path(ObjectKind::Tree, backend.empty_tree_id.as_bytes())
+            .exists()
+        {
+            backend.write_cache(ObjectKind::Tree, backend.empty_tree_id.as_bytes(), &[])?;
+        }
+        Ok(backend)
+    }
+
+    fn cache_path(&self, kind: ObjectKind, id: &[u8]) -> PathBuf {
+        self.path.join("cache").join(object_key(kind, id))
+    }
+
+    /// Returns the stored bytes of an object, fetching them from the object
+    /// store if they're not in the cache.
+    fn read_object(&self, kind: ObjectKind, id: &[u8]) -> BackendResult<Vec<u8>> {
+        match fs::read(self.cache_path(kind, id)) {
+            Ok(data) => return Ok(data),
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        let key = object_key(kind, id);
+        let data = with_retries(|| self.object_store.get(&key))?.ok_or(BackendError::NotFound)?;
+        check_hash(kind, id, &data)?;
+        self.write_cache(kind, id, &data)?;
+        Ok(data)
+    }
+
+    /// Uploads an object unless it's cached (cached objects have been uploaded
+    /// already) and adds it to the cache.
+    fn write_object(&self, kind: ObjectKind, id: &[u8], data: &[u8]) -> BackendResult<()> {
+        if self.cache_path(kind, id).exists() {
+            return Ok(());
+        }
+        let key = object_key(kind, id);
+        with_retries(|| self.object_store.put(&key, data))?;
+        self.write_cache(kind, id, data)
+    }
+
+    fn write_cache(&self, kind: ObjectKind, id: &[u8], data: &[u8]) -> BackendResult<()> {
+        let mut temp_file = NamedTempFile::new_in(&self.path)?;
+        temp_file.write_all(data)?;
+        persist_content_addressed_temp_file(temp_file, self.cache_path(kind, id))?;
+        Ok(())
+    }
+
+    /// Fetches the uncached subtrees of a tree that was just fetched in one
+    /// batch, since they're likely to be read next.
+    fn prefetch_subtrees(&self, tree: &Tree) -> BackendResult<()> {
+        let ids = tree
+            .entries()
+            .filter_map(|entry| match entry.value() {
+                TreeValue::Tree(id) => Some(id.to_bytes()),
+                _ => None,
+            })
+            .filter(|id| !self.cache_path(ObjectKind::Tree, id).exists())
+            .collect::<Vec<_>>();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let keys = ids
+            .iter()
+            .map(|id| object_key(ObjectKind::Tree, id))
+            .collect::<Vec<_>>();
+        let fetched = with_retries(|| self.object_store.get_many(&keys))?;
+        for (id, data) in ids.iter().zip(fetched) {
+            // Missing subtrees are reported when they're read
+            if let Some(data) = data {
+                check_hash(ObjectKind::Tree, id, &data)?;
+                self.write_cache(ObjectKind::Tree, id, &data)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn object_dir_name(kind: ObjectKind) -> &'static str {
+    match kind {
+        ObjectKind::Commit => "commits",
+        ObjectKind::Tree => "trees",
+        ObjectKind::File => "files",
+        ObjectKind::Symlink => "symlinks",
+        ObjectKind::Conflict => "conflicts",
+    }
+}
+
+fn object_key(kind: ObjectKind, id: &[u8]) -> String {
+    format!("{}/{}", object_dir_name(kind), hex::encode(id))
+}
+
+/// Hashes the stored bytes of an object. Files are stored compressed but
+/// hashed by their contents.
+fn hash_object(kind: ObjectKind, data: &[u8]) -> BackendResult<Vec<u8>> {
+    if kind == ObjectKind::File {
+        let contents = zstd::decode_all(data)?;
+        Ok(Blake2b512::digest(&contents).to_vec())
+    } else {
+        Ok(Blake2b512::digest(data).to_vec())
+    }
+}
+
+/// Makes sure an object we got from the object store is what we asked for.
+fn check_hash(kind: ObjectKind, id: &[u8], data: &[u8]) -> BackendResult<()> {
+    let actual_hash = hash_object(kind, data)?;
+    if actual_hash == id {
+        Ok(())
+    } else {
+        Err(BackendError::HashMismatch {
+            kind,
+            hash: hex::encode(id),
+            actual_hash: hex::encode(actual_hash),
+        })
+    }
+}
+
+impl Backend for RemoteBackend {
+    fn name(&self) -> &str {
+        "remote"
+    }
+
+    fn hash_length(&self) -> usize {
+        64
+    }
+
+    fn git_repo(&self) -> Option<git2::Repository> {
+        None
+    }
+
+    fn read_file(&self, _path: &RepoPath, id: &FileId) -> BackendResult<Box<dyn Read>> {
+        let data = self.read_object(ObjectKind::File, id.as_bytes())?;
+        Ok(Box::new(zstd::Decoder::new(Cursor::new(data))?))
+    }
+
+    fn write_file(&self, _path: &RepoPath, contents: &mut dyn Read) -> BackendResult<FileId> {
+        let mut buf = vec![];
+        contents.read_to_end(&mut buf)?;
+        let id = FileId::new(Blake2b512::digest(&buf).to_vec());
+        let data = zstd::encode_all(buf.as_slice(), 0)?;
+        self.write_object(ObjectKind::File, id.as_bytes(), &data)?;
+        Ok(id)
+    }
+
+    fn read_symlink(&self, _path: &RepoPath, id: &SymlinkId) -> BackendResult<String> {
+        let data = self.read_object(ObjectKind::Symlink, id.as_bytes())?;
+        String::from_utf8(data).map_err(|err| BackendError::Other(err.to_string()))
+    }
+
+    fn write_symlink(&self, _path: &RepoPath, target: &str) -> BackendResult<SymlinkId> {
+        let id = SymlinkId::new(Blake2b512::digest(target.as_bytes()).to_vec());
+        self.write_object(ObjectKind::Symlink, id.as_bytes(), target.as_bytes())?;
+        Ok(id)
+    }
+
+    fn root_commit_id(&self) -> &CommitId {
+        &self.root_commit_id
+    }
+
+    fn empty_tree_id(&self) -> &TreeId {
+        &self.empty_tree_id
+    }
+
+    fn read_tree(&self, _path: &RepoPath, id: &TreeId) -> BackendResult<Tree> {
+        let was_cached = self.cache_path(ObjectKind::Tree, id.as_bytes()).exists();
+        let data = self.read_object(ObjectKind::Tree, id.as_bytes())?;
+        let proto: crate::protos::store::Tree = Message::parse_from_bytes(&data)?;
+        let tree = tree_from_proto(&proto);
+        if !was_cached {
+            self.prefetch_subtrees(&tree)?;
+        }
+        Ok(tree)
+    }
+
+    fn write_tree(&self, _path: &RepoPath, tree: &Tree) -> BackendResult<TreeId> {
+        let data = tree_to_proto(tree).write_to_bytes()?;
+        let id = TreeId::new(Blake2b512::digest(&data).to_vec());
+        self.write_object(ObjectKind::Tree, id.as_bytes(), &data)?;
+        Ok(id)
+    }
+
+    fn read_conflict(&self, _path: &RepoPath, id: &ConflictId) -> BackendResult<Conflict> {
+        let data = self.read_object(ObjectKind::Conflict, id.as_bytes())?;
+        let proto: crate::protos::store::Conflict = Message::parse_from_bytes(&data)?;
+        Ok(conflict_from_proto(&proto))
+    }
+
+    fn write_conflict(&self, _path: &RepoPath, conflict: &Conflict) -> BackendResult<ConflictId> {
+        let data = conflict_to_proto(conflict).write_to_bytes()?;
+        let id = ConflictId::new(Blake2b512::digest(&data).to_vec());
+        self.write_object(ObjectKind::Conflict, id.as_bytes(), &data)?;
+        Ok(id)
+    }
+
+    fn read_commit(&self, id: &CommitId) -> BackendResult<Commit> {
+        if *id == self.root_commit_id {
+            return Ok(make_root_commit(self.empty_tree_id.clone()));
+        }
+        let data = self.read_object(ObjectKind::Commit, id.as_bytes())?;
+        let proto: crate::protos::store::Commit = Message::parse_from_bytes(&data)?;
+        Ok(commit_from_proto(&proto))
+    }
+
+    fn write_commit(&self, commit: &Commit) -> BackendResult<CommitId> {
+        let data = commit_to_proto(commit).write_to_bytes()?;
+        let id = CommitId::new(Blake2b512::digest(&data).to_vec());
+        self.write_object(ObjectKind::Commit, id.as_bytes(), &data)?;
+        Ok(id)
+    }
+
+    fn verify_object(&self, kind: ObjectKind, id: &[u8]) -> BackendResult<()> {
+        if kind == ObjectKind::Commit && id == self.root_commit_id.as_bytes() {
+            return Ok(());
+        }
+        let data = self.read_object(kind, id)?;
+        check_hash(kind, id, &data)
+    }
+}
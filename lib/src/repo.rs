@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
 use std::fs;
 use std::io::ErrorKind;
@@ -27,14 +27,14 @@ use thiserror::Error;
 use crate::backend::{Backend, BackendError, ChangeId, CommitId};
 use crate::commit::Commit;
 use crate::commit_builder::CommitBuilder;
-use crate::dag_walk::topo_order_reverse;
 use crate::git_backend::GitBackend;
 use crate::index::{IndexRef, MutableIndex, ReadonlyIndex};
-use crate::index_store::IndexStore;
+use crate::index_store::{topo_order_earlier_first, IndexStore};
 use crate::local_backend::LocalBackend;
-use crate::op_heads_store::{LockedOpHeads, OpHeads, OpHeadsStore};
+use crate::op_heads_store::{LockedOpHeads, OpHeadResolutionError, OpHeads, OpHeadsStore};
 use crate::op_store::{BranchTarget, OpStore, OperationId, RefTarget, WorkspaceId};
 use crate::operation::Operation;
+use crate::remote_backend::RemoteBackend;
 use crate::rewrite::DescendantRebaser;
 use crate::settings::{RepoSettings, UserSettings};
 use crate::simple_op_store::SimpleOpStore;
@@ -56,6 +56,7 @@ impl From<BackendError> for RepoError {
         match err {
             BackendError::NotFound => RepoError::NotFound,
             BackendError::Other(description) => RepoError::Other(description),
+            other => RepoError::Other(other.to_string()),
         }
     }
 }
@@ -146,8 +147,8 @@ impl ReadonlyRepo {
         let store_path = repo_path.join("store");
         let backend = backend_factory(&store_path);
         fs::write(&store_path.join("backend"), backend.name()).unwrap();
-        let store = Store::new(backend);
         let repo_settings = user_settings.with_repo(&repo_path).unwrap();
+        let store = Store::with_options(backend, repo_settings.store_options());
         let op_store: Arc<dyn OpStore> = Arc::new(SimpleOpStore::init(repo_path.join("op_store")));
         let mut root_view = op_store::View::default();
         root_view.head_ids.insert(store.root_commit_id().clone());
@@ -182,6 +183,17 @@ impl ReadonlyRepo {
             .resolve(user_settings)
     }
 
+    /// Loads the repo at the latest operation without taking any locks and
+    /// without writing to the operation log. See
+    /// `RepoLoader::load_at_head_readonly()`.
+    pub fn load_at_head_readonly(
+        user_settings: &UserSettings,
+        repo_path: &Path,
+        backend_factories: &BackendFactories,
+    ) -> Result<Arc<ReadonlyRepo>, OpHeadResolutionError> {
+        RepoLoader::init(user_settings, repo_path, backend_factories).load_at_head_readonly()
+    }
+
     pub fn loader(&self) -> RepoLoader {
         RepoLoader {
             repo_path: self.repo_path.clone(),
@@ -238,6 +250,17 @@ impl ReadonlyRepo {
         self.index()
     }
 
+    /// Like `reindex()`, but keeps the index of earlier operations and only
+    /// indexes the commits that aren't in it.
+    pub fn reindex_incremental(&mut self) -> &Arc<ReadonlyIndex> {
+        let index = self
+            .index_store
+            .reindex_at_op(&self.operation, &self.store)
+            .unwrap();
+        self.index.lock().unwrap().replace(index);
+        self.index()
+    }
+
     pub fn store(&self) -> &Arc<Store> {
         &self.store
     }
@@ -332,6 +355,10 @@ impl BackendFactories {
             "git",
             Box::new(|store_path| Box::new(GitBackend::load(store_path))),
         );
+        factories.add_backend(
+            "remote",
+            Box::new(|store_path| Box::new(RemoteBackend::load(store_path).unwrap())),
+        );
         factories
     }
 
@@ -377,8 +404,9 @@ impl RepoLoader {
             .factories
             .get(&backend_type)
             .expect("Unexpected backend type");
-        let store = Store::new(backend_factory(&store_path));
         let repo_settings = user_settings.with_repo(repo_path).unwrap();
+        let backend = backend_factory(&store_path);
+        let store = Store::with_options(backend, repo_settings.store_options());
         let op_store: Arc<dyn OpStore> = Arc::new(SimpleOpStore::load(repo_path.join("op_store")));
         let op_heads_store = Arc::new(OpHeadsStore::load(repo_path.join("op_heads")));
         let index_store = Arc::new(IndexStore::load(repo_path.join("index")));
@@ -430,6 +458,25 @@ impl RepoLoader {
         }
     }
 
+    /// Loads the repo at the latest operation without taking any locks and
+    /// without writing to the operation log, so it's safe to call while
+    /// another process is running a command on the repo. This is meant for
+    /// tools that only inspect the repo, such as shell prompts and editor
+    /// integrations.
+    ///
+    /// Unlike `load_at_head()`, this doesn't merge concurrent operations. If
+    /// there are several op heads, the repo is loaded at the most recent one.
+    /// Note that the index for the operation may still get written to disk if
+    /// it's missing; that doesn't require a lock.
+    pub fn load_at_head_readonly(&self) -> Result<Arc<ReadonlyRepo>, OpHeadResolutionError> {
+        let op = self
+            .op_heads_store
+            .get_heads_unlocked(&self.op_store)?
+            .pop()
+            .unwrap();
+        Ok(self.load_at(&op))
+    }
+
     pub fn load_at(&self, op: &Operation) -> Arc<ReadonlyRepo> {
         let view = View::new(op.view().take_store_view());
         self._finish_load(op.clone(), view)
@@ -682,18 +729,14 @@ impl MutableRepo {
                 self.view.get_mut().remove_head(parent_id);
             }
         } else {
-            let missing_commits = topo_order_reverse(
-                vec![head.clone()],
-                Box::new(|commit: &Commit| commit.id().clone()),
-                Box::new(|commit: &Commit| -> Vec<Commit> {
-                    commit
-                        .parents()
-                        .into_iter()
-                        .filter(|parent| !self.index.has_id(parent.id()))
-                        .collect()
-                }),
+            let index = &self.index;
+            let missing_commits = topo_order_earlier_first(
+                self.base_repo.store(),
+                vec![head.id().clone()],
+                false,
+                &|id| index.has_id(id),
             );
-            for missing_commit in missing_commits.iter().rev() {
+            for missing_commit in &missing_commits {
                 self.index.add_commit(missing_commit);
             }
             self.view.get_mut().add_head(head.id());
@@ -780,6 +823,22 @@ impl MutableRepo {
         self.view_mut().clear_git_head();
     }
 
+    pub fn set_change_name(&mut self, name: String, change_id: ChangeId) {
+        self.view_mut().set_change_name(name, change_id);
+    }
+
+    pub fn remove_change_name(&mut self, name: &str) {
+        self.view_mut().remove_change_name(name);
+    }
+
+    pub fn set_change_topic(&mut self, change_id: ChangeId, topic: String) {
+        self.view_mut().set_change_topic(change_id, topic);
+    }
+
+    pub fn remove_change_topic(&mut self, change_id: &ChangeId) {
+        self.view_mut().remove_change_topic(change_id);
+    }
+
     pub fn set_view(&mut self, data: op_store::View) {
         self.view_mut().set_view(data);
         self.view_dirty = true;
@@ -827,6 +886,55 @@ impl MutableRepo {
             }
         }
 
+        // Merge the Git HEAD. It can only point to a single commit, so if both sides
+        // changed it, we keep the self side (like for checkouts).
+        let base_git_head = base.git_head();
+        let other_git_head = other.git_head();
+        if other_git_head != base_git_head && self.view().git_head() == base_git_head {
+            match other_git_head {
+                Some(head_id) => self.view_mut().set_git_head(head_id),
+                None => self.view_mut().clear_git_head(),
+            }
+        }
+
+        // Merge change names. If both sides changed a name, we keep the self side.
+        let change_names = base
+            .change_names()
+            .keys()
+            .chain(other.change_names().keys())
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        for name in change_names {
+            let base_target = base.change_names().get(&name);
+            let other_target = other.change_names().get(&name);
+            if other_target != base_target && self.view().change_names().get(&name) == base_target {
+                match other_target {
+                    Some(change_id) => self.view_mut().set_change_name(name, change_id.clone()),
+                    None => self.view_mut().remove_change_name(&name),
+                }
+            }
+        }
+
+        // Merge change topics the same way
+        let topic_change_ids = base
+            .change_topics()
+            .keys()
+            .chain(other.change_topics().keys())
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        for change_id in topic_change_ids {
+            let base_topic = base.change_topics().get(&change_id);
+            let other_topic = other.change_topics().get(&change_id);
+            if other_topic != base_topic
+                && self.view().change_topics().get(&change_id) == base_topic
+            {
+                match other_topic {
+                    Some(topic) => self.view_mut().set_change_topic(change_id, topic.clone()),
+                    None => self.view_mut().remove_change_topic(&change_id),
+                }
+            }
+        }
+
         for removed_head in base.public_heads().difference(other.public_heads()) {
             self.view_mut().remove_public_head(removed_head);
         }
@@ -13,36 +13,52 @@
 // limitations under the License.
 
 use std::cmp::{Ordering, Reverse};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::iter::Peekable;
 use std::ops::Range;
 use std::rc::Rc;
 use std::sync::Arc;
 
 use itertools::Itertools;
-use pest::iterators::Pairs;
+use pest::iterators::{Pair, Pairs};
 use pest::Parser;
 use pest_derive::Parser;
 use thiserror::Error;
 
-use crate::backend::{BackendError, BackendResult, CommitId};
+use crate::backend::{BackendError, BackendResult, ChangeId, CommitId};
+use crate::changed_path_index::{ChangedPathFilter, ChangedPathIndex};
 use crate::commit::Commit;
-use crate::index::{HexPrefix, IndexEntry, IndexPosition, PrefixResolution, RevWalk};
-use crate::matchers::Matcher;
+use crate::index::{
+    HexPrefix, IndexEntry, IndexPosition, IndexPositionSet, IndexRef, PrefixResolution, RevWalk,
+};
+use crate::matchers::{EverythingMatcher, Matcher};
 use crate::op_store::WorkspaceId;
+use crate::operation::Operation;
+use crate::reachability::{self, ReachabilityBitmaps};
+use crate::refs::RefNamePattern;
 use crate::repo::RepoRef;
+use crate::repo_path::RepoPath;
 use crate::revset_graph_iterator::RevsetGraphIterator;
 use crate::rewrite;
 use crate::store::Store;
 
 #[derive(Debug, Error, PartialEq, Eq)]
 pub enum RevsetError {
-    #[error("Revision \"{0}\" doesn't exist")]
-    NoSuchRevision(String),
+    #[error("Revision \"{name}\" doesn't exist")]
+    NoSuchRevision {
+        name: String,
+        // Similarly named branches, most similar first
+        candidates: Vec<String>,
+    },
     #[error("Commit id prefix \"{0}\" is ambiguous")]
     AmbiguousCommitIdPrefix(String),
     #[error("Change id prefix \"{0}\" is ambiguous")]
     AmbiguousChangeIdPrefix(String),
+    #[error("Operation \"{0}\" doesn't exist")]
+    NoSuchOperation(String),
+    #[error("Operation \"{0}\" resolved to more than one operation")]
+    AmbiguousOperation(String),
     #[error("Unexpected error from store: {0}")]
     StoreError(#[from] BackendError),
 }
@@ -54,7 +70,10 @@ fn resolve_git_ref(repo: RepoRef, symbol: &str) -> Result<Vec<CommitId>, RevsetE
             return Ok(ref_target.adds());
         }
     }
-    Err(RevsetError::NoSuchRevision(symbol.to_owned()))
+    Err(RevsetError::NoSuchRevision {
+        name: symbol.to_owned(),
+        candidates: vec![],
+    })
 }
 
 fn resolve_branch(repo: RepoRef, symbol: &str) -> Result<Vec<CommitId>, RevsetError> {
@@ -72,7 +91,10 @@ fn resolve_branch(repo: RepoRef, symbol: &str) -> Result<Vec<CommitId>, RevsetEr
             }
         }
     }
-    Err(RevsetError::NoSuchRevision(symbol.to_owned()))
+    Err(RevsetError::NoSuchRevision {
+        name: symbol.to_owned(),
+        candidates: vec![],
+    })
 }
 
 fn resolve_commit_id(repo: RepoRef, symbol: &str) -> Result<Vec<CommitId>, RevsetError> {
@@ -89,7 +111,10 @@ fn resolve_commit_id(repo: RepoRef, symbol: &str) -> Result<Vec<CommitId>, Revse
     if let Some(prefix) = HexPrefix::new(symbol.to_owned()) {
         match repo.index().resolve_prefix(&prefix) {
             PrefixResolution::NoMatch => {
-                return Err(RevsetError::NoSuchRevision(symbol.to_owned()))
+                return Err(RevsetError::NoSuchRevision {
+                    name: symbol.to_owned(),
+                    candidates: vec![],
+                })
             }
             PrefixResolution::AmbiguousMatch => {
                 return Err(RevsetError::AmbiguousCommitIdPrefix(symbol.to_owned()))
@@ -98,7 +123,10 @@ fn resolve_commit_id(repo: RepoRef, symbol: &str) -> Result<Vec<CommitId>, Revse
         }
     }
 
-    Err(RevsetError::NoSuchRevision(symbol.to_owned()))
+    Err(RevsetError::NoSuchRevision {
+        name: symbol.to_owned(),
+        candidates: vec![],
+    })
 }
 
 fn resolve_change_id(repo: RepoRef, change_id_prefix: &str) -> Result<Vec<CommitId>, RevsetError> {
@@ -120,14 +148,33 @@ fn resolve_change_id(repo: RepoRef, change_id_prefix: &str) -> Result<Vec<Commit
             }
         }
         if found_change_id.is_none() {
-            return Err(RevsetError::NoSuchRevision(change_id_prefix.to_owned()));
+            return Err(RevsetError::NoSuchRevision {
+                name: change_id_prefix.to_owned(),
+                candidates: vec![],
+            });
         }
         Ok(commit_ids)
     } else {
-        Err(RevsetError::NoSuchRevision(change_id_prefix.to_owned()))
+        Err(RevsetError::NoSuchRevision {
+            name: change_id_prefix.to_owned(),
+            candidates: vec![],
+        })
     }
 }
 
+/// Returns the visible commits of the change a name was given to. That's
+/// usually a single commit, but it's none if the change has been abandoned and
+/// several if it's divergent.
+pub fn resolve_change_name(repo: RepoRef, change_id: &ChangeId) -> Vec<CommitId> {
+    RevsetExpression::all()
+        .evaluate(repo, None)
+        .unwrap()
+        .iter()
+        .filter(|index_entry| &index_entry.change_id() == change_id)
+        .map(|index_entry| index_entry.commit_id())
+        .collect()
+}
+
 pub fn resolve_symbol(
     repo: RepoRef,
     symbol: &str,
@@ -138,7 +185,10 @@ pub fn resolve_symbol(
             if let Some(workspace_id) = workspace_id {
                 workspace_id.clone()
             } else {
-                return Err(RevsetError::NoSuchRevision(symbol.to_owned()));
+                return Err(RevsetError::NoSuchRevision {
+                    name: symbol.to_owned(),
+                    candidates: vec![],
+                });
             }
         } else {
             WorkspaceId::new(symbol.strip_suffix('@').unwrap().to_string())
@@ -146,7 +196,10 @@ pub fn resolve_symbol(
         if let Some(commit_id) = repo.view().get_wc_commit_id(&target_workspace) {
             Ok(vec![commit_id.clone()])
         } else {
-            Err(RevsetError::NoSuchRevision(symbol.to_owned()))
+            Err(RevsetError::NoSuchRevision {
+                name: symbol.to_owned(),
+                candidates: vec![],
+            })
         }
     } else if symbol == "root" {
         Ok(vec![repo.store().root_commit_id().clone()])
@@ -158,30 +211,132 @@ pub fn resolve_symbol(
 
         // Try to resolve as a branch
         let branch_result = resolve_branch(repo, symbol);
-        if !matches!(branch_result, Err(RevsetError::NoSuchRevision(_))) {
+        if !matches!(branch_result, Err(RevsetError::NoSuchRevision { .. })) {
             return branch_result;
         }
 
+        // Try to resolve as a change name
+        if let Some(change_id) = repo.view().change_names().get(symbol) {
+            return Ok(resolve_change_name(repo, change_id));
+        }
+
         // Try to resolve as a git ref
         let git_ref_result = resolve_git_ref(repo, symbol);
-        if !matches!(git_ref_result, Err(RevsetError::NoSuchRevision(_))) {
+        if !matches!(git_ref_result, Err(RevsetError::NoSuchRevision { .. })) {
             return git_ref_result;
         }
 
         // Try to resolve as a commit id.
         let commit_id_result = resolve_commit_id(repo, symbol);
-        if !matches!(commit_id_result, Err(RevsetError::NoSuchRevision(_))) {
+        if !matches!(commit_id_result, Err(RevsetError::NoSuchRevision { .. })) {
             return commit_id_result;
         }
 
         // Try to resolve as a change id.
         let change_id_result = resolve_change_id(repo, symbol);
-        if !matches!(change_id_result, Err(RevsetError::NoSuchRevision(_))) {
+        if !matches!(change_id_result, Err(RevsetError::NoSuchRevision { .. })) {
             return change_id_result;
         }
 
-        Err(RevsetError::NoSuchRevision(symbol.to_owned()))
+        let view = repo.view();
+        let branch_names = view.branches().iter().flat_map(|(name, target)| {
+            let remote_names = target
+                .remote_targets
+                .keys()
+                .map(move |remote_name| format!("{name}@{remote_name}"));
+            target
+                .local_target
+                .as_ref()
+                .map(|_| name.clone())
+                .into_iter()
+                .chain(remote_names)
+        });
+        let change_names = view.change_names().keys().cloned();
+        Err(RevsetError::NoSuchRevision {
+            name: symbol.to_owned(),
+            candidates: collect_similar(symbol, branch_names.chain(change_names)),
+        })
+    }
+}
+
+impl RevsetError {
+    /// Formats the error like `Display` does, but also points at the symbol
+    /// in `revset_str` that it's about, and suggests similar names if there
+    /// are any.
+    pub fn format_with_source(&self, revset_str: &str) -> String {
+        let (symbol, candidates): (_, &[String]) = match self {
+            RevsetError::NoSuchRevision { name, candidates } => (name, candidates),
+            RevsetError::AmbiguousCommitIdPrefix(prefix)
+            | RevsetError::AmbiguousChangeIdPrefix(prefix)
+            | RevsetError::NoSuchOperation(prefix)
+            | RevsetError::AmbiguousOperation(prefix) => (prefix, &[]),
+            RevsetError::StoreError(_) => return self.to_string(),
+        };
+        let mut message = match find_symbol_span(revset_str, symbol) {
+            Some(span) => pest::error::Error::<Rule>::new_from_span(
+                pest::error::ErrorVariant::CustomError {
+                    message: self.to_string(),
+                },
+                span,
+            )
+            .to_string(),
+            None => self.to_string(),
+        };
+        if let Some(hint) = format_similar_candidates_hint(candidates) {
+            message.push('\n');
+            message.push_str(&hint);
+        }
+        message
+    }
+}
+
+/// Finds the first occurrence of `symbol` as a symbol in the parsed
+/// `revset_str`.
+fn find_symbol_span<'a>(revset_str: &'a str, symbol: &str) -> Option<pest::Span<'a>> {
+    let pairs = RevsetParser::parse(Rule::expression, revset_str).ok()?;
+    pairs
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::symbol)
+        .find(|pair| {
+            let pair = pair.clone().into_inner().next().unwrap();
+            match pair.as_rule() {
+                Rule::literal_string => {
+                    pair.as_str().strip_prefix('"').unwrap().strip_suffix('"') == Some(symbol)
+                }
+                _ => pair.as_str() == symbol,
+            }
+        })
+        .map(|pair| pair.as_span())
+}
+
+/// Returns the candidates that look like a misspelling of `name`, most similar
+/// first.
+fn collect_similar(name: &str, candidates: impl IntoIterator<Item = String>) -> Vec<String> {
+    candidates
+        .into_iter()
+        .map(|candidate| (strsim::jaro(name, &candidate), candidate))
+        .filter(|(confidence, candidate)| *confidence > 0.8 && candidate != name)
+        .sorted_by(|(confidence1, candidate1), (confidence2, candidate2)| {
+            // Jaro similarity is never NaN
+            confidence2
+                .partial_cmp(confidence1)
+                .unwrap()
+                .then_with(|| candidate1.cmp(candidate2))
+        })
+        .map(|(_, candidate)| candidate)
+        .dedup()
+        .collect()
+}
+
+fn format_similar_candidates_hint(candidates: &[String]) -> Option<String> {
+    if candidates.is_empty() {
+        return None;
     }
+    let names = candidates
+        .iter()
+        .map(|name| format!("\"{name}\""))
+        .join(", ");
+    Some(format!("Hint: Did you mean {names}?"))
 }
 
 #[derive(Parser)]
@@ -189,15 +344,69 @@ pub fn resolve_symbol(
 pub struct RevsetParser;
 
 #[derive(Debug, Error, PartialEq, Eq)]
-pub enum RevsetParseError {
-    #[error("{0}")]
-    SyntaxError(#[from] pest::error::Error<Rule>),
-    #[error("Revset function \"{0}\" doesn't exist")]
-    NoSuchFunction(String),
+pub enum RevsetParseErrorKind {
+    #[error("Syntax error")]
+    SyntaxError,
+    #[error("Revset function \"{name}\" doesn't exist")]
+    NoSuchFunction {
+        name: String,
+        // Similarly named functions, most similar first
+        candidates: Vec<String>,
+    },
     #[error("Invalid arguments to revset function \"{name}\": {message}")]
     InvalidFunctionArguments { name: String, message: String },
 }
 
+/// An error from parsing a revset expression. Its `Display` implementation
+/// points at the part of the input the error is about.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RevsetParseError {
+    kind: RevsetParseErrorKind,
+    pest_error: Box<pest::error::Error<Rule>>,
+}
+
+impl RevsetParseError {
+    fn new(kind: RevsetParseErrorKind, span: pest::Span<'_>) -> Self {
+        let pest_error = pest::error::Error::new_from_span(
+            pest::error::ErrorVariant::CustomError {
+                message: kind.to_string(),
+            },
+            span,
+        );
+        RevsetParseError {
+            kind,
+            pest_error: Box::new(pest_error),
+        }
+    }
+
+    pub fn kind(&self) -> &RevsetParseErrorKind {
+        &self.kind
+    }
+}
+
+impl From<pest::error::Error<Rule>> for RevsetParseError {
+    fn from(err: pest::error::Error<Rule>) -> Self {
+        RevsetParseError {
+            kind: RevsetParseErrorKind::SyntaxError,
+            pest_error: Box::new(err),
+        }
+    }
+}
+
+impl fmt::Display for RevsetParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.pest_error)?;
+        if let RevsetParseErrorKind::NoSuchFunction { candidates, .. } = &self.kind {
+            if let Some(hint) = format_similar_candidates_hint(candidates) {
+                write!(f, "\n{hint}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RevsetParseError {}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum RevsetExpression {
     None,
@@ -206,6 +415,16 @@ pub enum RevsetExpression {
     Parents(Rc<RevsetExpression>),
     Children(Rc<RevsetExpression>),
     Ancestors(Rc<RevsetExpression>),
+    // Ancestors of "heads" that are at most "depth" generations away from them
+    AncestorsUpTo {
+        heads: Rc<RevsetExpression>,
+        depth: u32,
+    },
+    // Descendants of "roots" that are at most "depth" generations away from them
+    DescendantsUpTo {
+        roots: Rc<RevsetExpression>,
+        depth: u32,
+    },
     // Commits that are ancestors of "heads" but not ancestors of "roots"
     Range {
         roots: Rc<RevsetExpression>,
@@ -220,11 +439,24 @@ pub enum RevsetExpression {
     Roots(Rc<RevsetExpression>),
     VisibleHeads,
     PublicHeads,
+    WorkingCopies,
     Branches,
-    RemoteBranches,
+    RemoteBranches {
+        // Glob pattern for the branch name
+        branch_pattern: Option<String>,
+        remote: Option<String>,
+    },
     Tags,
     GitRefs,
     GitHead,
+    // Visible commits whose change id has other visible commits
+    Divergent,
+    // Commits in the index that aren't visible, e.g. abandoned or rewritten
+    // commits
+    Hidden,
+    // Commits that became visible in an operation (given as "@", an operation
+    // id prefix, or either followed by "-"s)
+    AddedByOp(String),
     ParentCount {
         candidates: Rc<RevsetExpression>,
         parent_count_range: Range<u32>,
@@ -243,6 +475,15 @@ pub enum RevsetExpression {
         needle: String,
         candidates: Rc<RevsetExpression>,
     },
+    // Commits authored by the user, as configured in the repo's settings
+    Mine {
+        candidates: Rc<RevsetExpression>,
+    },
+    // Commits whose change has the topic
+    Topic {
+        topic: String,
+        candidates: Rc<RevsetExpression>,
+    },
     Union(Rc<RevsetExpression>, Rc<RevsetExpression>),
     Intersection(Rc<RevsetExpression>, Rc<RevsetExpression>),
     Difference(Rc<RevsetExpression>, Rc<RevsetExpression>),
@@ -277,12 +518,29 @@ impl RevsetExpression {
         Rc::new(RevsetExpression::PublicHeads)
     }
 
+    pub fn working_copies() -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::WorkingCopies)
+    }
+
     pub fn branches() -> Rc<RevsetExpression> {
         Rc::new(RevsetExpression::Branches)
     }
 
     pub fn remote_branches() -> Rc<RevsetExpression> {
-        Rc::new(RevsetExpression::RemoteBranches)
+        RevsetExpression::remote_branches_matching(None, None)
+    }
+
+    /// Targets of the remote branches whose name matches `branch_pattern` (see
+    /// `RefNamePattern::glob()`) on `remote`. `None` matches any branch or
+    /// remote.
+    pub fn remote_branches_matching(
+        branch_pattern: Option<String>,
+        remote: Option<String>,
+    ) -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::RemoteBranches {
+            branch_pattern,
+            remote,
+        })
     }
 
     pub fn tags() -> Rc<RevsetExpression> {
@@ -297,6 +555,21 @@ impl RevsetExpression {
         Rc::new(RevsetExpression::GitHead)
     }
 
+    pub fn divergent() -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::Divergent)
+    }
+
+    pub fn hidden() -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::Hidden)
+    }
+
+    /// Commits that were visible after the operation `op_str` but not before
+    /// it. `op_str` is `@` (the operation the repo was loaded at) or an
+    /// operation id prefix, optionally followed by `-`s to go to parents.
+    pub fn added_by_op(op_str: String) -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::AddedByOp(op_str))
+    }
+
     /// Commits in `self` that don't have descendants in `self`.
     pub fn heads(self: &Rc<RevsetExpression>) -> Rc<RevsetExpression> {
         Rc::new(RevsetExpression::Heads(self.clone()))
@@ -317,6 +590,15 @@ impl RevsetExpression {
         Rc::new(RevsetExpression::Ancestors(self.clone()))
     }
 
+    /// Ancestors of `self` that are at most `depth` generations away from
+    /// `self`, including `self`.
+    pub fn ancestors_up_to(self: &Rc<RevsetExpression>, depth: u32) -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::AncestorsUpTo {
+            heads: self.clone(),
+            depth,
+        })
+    }
+
     /// Children of `self`.
     pub fn children(self: &Rc<RevsetExpression>) -> Rc<RevsetExpression> {
         Rc::new(RevsetExpression::Children(self.clone()))
@@ -327,6 +609,15 @@ impl RevsetExpression {
         self.dag_range_to(&RevsetExpression::visible_heads())
     }
 
+    /// Descendants of `self` that are at most `depth` generations away from
+    /// `self`, including `self`.
+    pub fn descendants_up_to(self: &Rc<RevsetExpression>, depth: u32) -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::DescendantsUpTo {
+            roots: self.clone(),
+            depth,
+        })
+    }
+
     /// Commits that are descendants of `self` and ancestors of `heads`, both
     /// inclusive.
     pub fn dag_range_to(
@@ -383,6 +674,22 @@ impl RevsetExpression {
         })
     }
 
+    /// Commits in `self` whose author's email is one of the user's emails (see
+    /// `RepoSettings::user_emails()`), after applying the mailmap.
+    pub fn mine(self: &Rc<RevsetExpression>) -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::Mine {
+            candidates: self.clone(),
+        })
+    }
+
+    /// Commits in `self` whose change has the topic `topic`.
+    pub fn with_topic(self: &Rc<RevsetExpression>, topic: String) -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::Topic {
+            candidates: self.clone(),
+            topic,
+        })
+    }
+
     /// Commits in `self` with committer's name or email containing `needle`.
     pub fn with_committer(self: &Rc<RevsetExpression>, needle: String) -> Rc<RevsetExpression> {
         Rc::new(RevsetExpression::Committer {
@@ -517,6 +824,10 @@ fn parse_neighbors_expression_rule(
             Rule::children_op => {
                 expression = expression.children();
             }
+            Rule::ancestors_op => {
+                let depth = parse_depth("~", operator.as_span())?;
+                expression = expression.ancestors_up_to(depth);
+            }
             _ => {
                 panic!(
                     "unxpected revset neighbors operator rule {:?}",
@@ -533,9 +844,8 @@ fn parse_primary_rule(mut pairs: Pairs<Rule>) -> Result<Rc<RevsetExpression>, Re
     match first.as_rule() {
         Rule::expression => parse_expression_rule(first.into_inner()),
         Rule::function_name => {
-            let name = first.as_str().to_owned();
-            let argument_pairs = pairs.next().unwrap().into_inner();
-            parse_function_expression(name, argument_pairs)
+            let arguments_pair = pairs.next().unwrap();
+            parse_function_expression(first, arguments_pair)
         }
         Rule::symbol => parse_symbol_rule(first.into_inner()),
         _ => {
@@ -565,20 +875,71 @@ fn parse_symbol_rule(mut pairs: Pairs<Rule>) -> Result<Rc<RevsetExpression>, Rev
     }
 }
 
+const FUNCTION_NAMES: &[&str] = &[
+    "parents",
+    "children",
+    "ancestors",
+    "descendants",
+    "connected",
+    "none",
+    "all",
+    "heads",
+    "roots",
+    "public_heads",
+    "working_copies",
+    "branches",
+    "remote_branches",
+    "tags",
+    "git_refs",
+    "git_head",
+    "divergent",
+    "builtin_log",
+    "hidden",
+    "added_by_op",
+    "merges",
+    "parents_count",
+    "description",
+    "author",
+    "committer",
+    "mine",
+    "topic",
+];
+
 fn parse_function_expression(
-    name: String,
-    mut argument_pairs: Pairs<Rule>,
+    name_pair: Pair<Rule>,
+    arguments_pair: Pair<Rule>,
 ) -> Result<Rc<RevsetExpression>, RevsetParseError> {
+    let name = name_pair.as_str().to_owned();
+    let arguments_span = arguments_pair.as_span();
+    let mut argument_pairs = arguments_pair.into_inner();
     let arg_count = argument_pairs.clone().count();
+    if name != "remote_branches" {
+        if let Some(keyword_pair) = argument_pairs
+            .clone()
+            .find(|pair| pair.as_rule() == Rule::keyword_argument)
+        {
+            let keyword = keyword_pair.clone().into_inner().next().unwrap().as_str();
+            return Err(RevsetParseError::new(
+                RevsetParseErrorKind::InvalidFunctionArguments {
+                    message: format!("Unexpected keyword argument: {keyword}"),
+                    name,
+                },
+                keyword_pair.as_span(),
+            ));
+        }
+    }
     match name.as_str() {
         "parents" => {
             if arg_count == 1 {
                 Ok(parse_expression_rule(argument_pairs.next().unwrap().into_inner())?.parents())
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 1 argument".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 1 argument".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
         "children" => {
@@ -587,20 +948,31 @@ fn parse_function_expression(
                     parse_expression_rule(argument_pairs.next().unwrap().into_inner())?;
                 Ok(expression.children())
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 1 argument".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 1 argument".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
         "ancestors" => {
             if arg_count == 1 {
                 Ok(parse_expression_rule(argument_pairs.next().unwrap().into_inner())?.ancestors())
+            } else if arg_count == 2 {
+                let expression =
+                    parse_expression_rule(argument_pairs.next().unwrap().into_inner())?;
+                let depth = parse_depth(&name, argument_pairs.next().unwrap().as_span())?;
+                Ok(expression.ancestors_up_to(depth))
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 1 argument".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 1 or 2 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
         "descendants" => {
@@ -608,11 +980,19 @@ fn parse_function_expression(
                 let expression =
                     parse_expression_rule(argument_pairs.next().unwrap().into_inner())?;
                 Ok(expression.descendants())
+            } else if arg_count == 2 {
+                let expression =
+                    parse_expression_rule(argument_pairs.next().unwrap().into_inner())?;
+                let depth = parse_depth(&name, argument_pairs.next().unwrap().as_span())?;
+                Ok(expression.descendants_up_to(depth))
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 1 argument".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 1 or 2 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
         "connected" => {
@@ -621,30 +1001,39 @@ fn parse_function_expression(
                     parse_expression_rule(argument_pairs.next().unwrap().into_inner())?;
                 Ok(candidates.connected())
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 1 argument".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 1 argument".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
         "none" => {
             if arg_count == 0 {
                 Ok(RevsetExpression::none())
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 0 arguments".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
         "all" => {
             if arg_count == 0 {
                 Ok(RevsetExpression::all())
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 0 arguments".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
         "heads" => {
@@ -655,10 +1044,13 @@ fn parse_function_expression(
                     parse_expression_rule(argument_pairs.next().unwrap().into_inner())?;
                 Ok(candidates.heads())
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 0 or 1 arguments".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 or 1 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
         "roots" => {
@@ -667,78 +1059,220 @@ fn parse_function_expression(
                     parse_expression_rule(argument_pairs.next().unwrap().into_inner())?;
                 Ok(candidates.roots())
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 1 argument".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 1 argument".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
         "public_heads" => {
             if arg_count == 0 {
                 Ok(RevsetExpression::public_heads())
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 0 arguments".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
+            }
+        }
+        "working_copies" => {
+            if arg_count == 0 {
+                Ok(RevsetExpression::working_copies())
+            } else {
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
         "branches" => {
             if arg_count == 0 {
                 Ok(RevsetExpression::branches())
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 0 arguments".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
         "remote_branches" => {
-            if arg_count == 0 {
-                Ok(RevsetExpression::remote_branches())
-            } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 0 arguments".to_string(),
-                })
+            let mut branch_pattern = None;
+            let mut remote = None;
+            for argument_pair in argument_pairs {
+                let span = argument_pair.as_span();
+                if argument_pair.as_rule() == Rule::keyword_argument {
+                    let mut pairs = argument_pair.into_inner();
+                    let keyword = pairs.next().unwrap().as_str();
+                    if keyword != "remote" || remote.is_some() {
+                        return Err(RevsetParseError::new(
+                            RevsetParseErrorKind::InvalidFunctionArguments {
+                                name,
+                                message: format!("Unexpected keyword argument: {keyword}"),
+                            },
+                            span,
+                        ));
+                    }
+                    remote = Some(parse_function_argument_to_string(
+                        &name,
+                        pairs.next().unwrap(),
+                    )?);
+                } else if branch_pattern.is_none() && remote.is_none() {
+                    branch_pattern = Some(parse_function_argument_to_string(&name, argument_pair)?);
+                } else {
+                    return Err(RevsetParseError::new(
+                        RevsetParseErrorKind::InvalidFunctionArguments {
+                            name,
+                            message: "Expected at most 1 positional argument, before the \
+                                      keyword arguments"
+                                .to_string(),
+                        },
+                        span,
+                    ));
+                }
             }
+            Ok(RevsetExpression::remote_branches_matching(
+                branch_pattern,
+                remote,
+            ))
         }
         "tags" => {
             if arg_count == 0 {
                 Ok(RevsetExpression::tags())
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 0 arguments".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
         "git_refs" => {
             if arg_count == 0 {
                 Ok(RevsetExpression::git_refs())
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 0 arguments".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
         "git_head" => {
             if arg_count == 0 {
                 Ok(RevsetExpression::git_head())
             } else {
-                Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 0 arguments".to_string(),
-                })
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
             }
         }
+        "divergent" => {
+            if arg_count == 0 {
+                Ok(RevsetExpression::divergent())
+            } else {
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
+            }
+        }
+        "mine" => {
+            if arg_count == 0 {
+                Ok(RevsetExpression::all().mine())
+            } else {
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
+            }
+        }
+        "builtin_log" => {
+            if arg_count == 0 {
+                Ok(parse(BUILTIN_LOG_REVSET).unwrap())
+            } else {
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
+            }
+        }
+        "hidden" => {
+            if arg_count == 0 {
+                Ok(RevsetExpression::hidden())
+            } else {
+                Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 arguments".to_string(),
+                    },
+                    arguments_span,
+                ))
+            }
+        }
+        "added_by_op" => {
+            if arg_count != 1 {
+                return Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 1 argument".to_string(),
+                    },
+                    arguments_span,
+                ));
+            }
+            let op_str = parse_function_argument_to_string(&name, argument_pairs.next().unwrap())?;
+            Ok(RevsetExpression::added_by_op(op_str))
+        }
+        "topic" => {
+            if arg_count != 1 {
+                return Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 1 argument".to_string(),
+                    },
+                    arguments_span,
+                ));
+            }
+            let topic = parse_function_argument_to_string(&name, argument_pairs.next().unwrap())?;
+            Ok(RevsetExpression::all().with_topic(topic))
+        }
         "merges" => {
             if arg_count > 1 {
-                return Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 0 or 1 arguments".to_string(),
-                });
+                return Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 0 or 1 arguments".to_string(),
+                    },
+                    arguments_span,
+                ));
             }
             let candidates = if arg_count == 0 {
                 RevsetExpression::all()
@@ -747,17 +1281,56 @@ fn parse_function_expression(
             };
             Ok(candidates.with_parent_count(2..u32::MAX))
         }
+        "parents_count" => {
+            if !(1..=2).contains(&arg_count) {
+                return Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 1 or 2 arguments".to_string(),
+                    },
+                    arguments_span,
+                ));
+            }
+            let count_span = argument_pairs.next().unwrap().as_span();
+            let count: u32 = count_span.as_str().trim().parse().map_err(|_| {
+                RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name: name.clone(),
+                        message: format!(
+                            "Expected a non-negative integer as parent count, found: {}",
+                            count_span.as_str().trim()
+                        ),
+                    },
+                    count_span,
+                )
+            })?;
+            let end = count.checked_add(1).ok_or_else(|| {
+                RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name: name.clone(),
+                        message: format!("Parent count is too large: {count}"),
+                    },
+                    count_span,
+                )
+            })?;
+            let candidates = if arg_count == 1 {
+                RevsetExpression::all()
+            } else {
+                parse_expression_rule(argument_pairs.next().unwrap().into_inner())?
+            };
+            Ok(candidates.with_parent_count(count..end))
+        }
         "description" | "author" | "committer" => {
             if !(1..=2).contains(&arg_count) {
-                return Err(RevsetParseError::InvalidFunctionArguments {
-                    name,
-                    message: "Expected 1 or 2 arguments".to_string(),
-                });
+                return Err(RevsetParseError::new(
+                    RevsetParseErrorKind::InvalidFunctionArguments {
+                        name,
+                        message: "Expected 1 or 2 arguments".to_string(),
+                    },
+                    arguments_span,
+                ));
             }
-            let needle = parse_function_argument_to_string(
-                &name,
-                argument_pairs.next().unwrap().into_inner(),
-            )?;
+            let needle = parse_function_argument_to_string(&name, argument_pairs.next().unwrap())?;
             let candidates = if arg_count == 1 {
                 RevsetExpression::all()
             } else {
@@ -772,27 +1345,56 @@ fn parse_function_expression(
                 }
             }
         }
-        _ => Err(RevsetParseError::NoSuchFunction(name)),
+        _ => {
+            let candidates =
+                collect_similar(&name, FUNCTION_NAMES.iter().map(|name| name.to_string()));
+            Err(RevsetParseError::new(
+                RevsetParseErrorKind::NoSuchFunction { name, candidates },
+                name_pair.as_span(),
+            ))
+        }
     }
 }
 
 fn parse_function_argument_to_string(
     name: &str,
-    pairs: Pairs<Rule>,
+    pair: Pair<Rule>,
 ) -> Result<String, RevsetParseError> {
-    let expression = parse_expression_rule(pairs.clone())?;
+    let span = pair.as_span();
+    let expression = parse_expression_rule(pair.into_inner())?;
     match expression.as_ref() {
         RevsetExpression::Symbol(symbol) => Ok(symbol.clone()),
-        _ => Err(RevsetParseError::InvalidFunctionArguments {
-            name: name.to_string(),
-            message: format!(
-                "Expected function argument of type string, found: {}",
-                pairs.as_str()
-            ),
-        }),
+        _ => Err(RevsetParseError::new(
+            RevsetParseErrorKind::InvalidFunctionArguments {
+                name: name.to_string(),
+                message: format!(
+                    "Expected function argument of type string, found: {}",
+                    span.as_str()
+                ),
+            },
+            span,
+        )),
     }
 }
 
+fn parse_depth(name: &str, span: pest::Span<'_>) -> Result<u32, RevsetParseError> {
+    let depth_str = span.as_str().trim_start_matches('~').trim();
+    depth_str.parse().map_err(|_| {
+        RevsetParseError::new(
+            RevsetParseErrorKind::InvalidFunctionArguments {
+                name: name.to_string(),
+                message: format!("Expected a non-negative integer as depth, found: {depth_str}"),
+            },
+            span,
+        )
+    })
+}
+
+/// The revisions `jj log` shows by default: the commits that are not on any
+/// remote branch, plus their closest commit on a remote branch for context.
+/// Available as `builtin_log()`, so `ui.default-revset` can extend it.
+pub const BUILTIN_LOG_REVSET: &str = "remote_branches().. | (remote_branches()..)-";
+
 pub fn parse(revset_str: &str) -> Result<Rc<RevsetExpression>, RevsetParseError> {
     let mut pairs = RevsetParser::parse(Rule::expression, revset_str)?;
     let first = pairs.next().unwrap();
@@ -805,7 +1407,7 @@ pub fn parse(revset_str: &str) -> Result<Rc<RevsetExpression>, RevsetParseError>
             },
             pos,
         );
-        return Err(RevsetParseError::SyntaxError(err));
+        return Err(RevsetParseError::from(err));
     }
 
     parse_expression_rule(first.into_inner())
@@ -904,6 +1506,22 @@ impl<'repo> Revset<'repo> for EagerRevset<'repo> {
     }
 }
 
+struct PositionSetRevset<'repo> {
+    index: IndexRef<'repo>,
+    positions: IndexPositionSet,
+}
+
+impl<'repo> Revset<'repo> for PositionSetRevset<'repo> {
+    fn iter<'revset>(&'revset self) -> RevsetIterator<'revset, 'repo> {
+        let index = self.index;
+        RevsetIterator::new(Box::new(
+            self.positions
+                .iter_descending()
+                .map(move |pos| index.entry_by_pos(pos)),
+        ))
+    }
+}
+
 struct RevWalkRevset<'repo> {
     walk: RevWalk<'repo>,
 }
@@ -1135,6 +1753,25 @@ impl<'revset, 'repo> Iterator for DifferenceRevsetIterator<'revset, 'repo> {
     }
 }
 
+/// If `head_ids` are all branch heads, returns their ancestors using the
+/// precomputed reachability bitmaps instead of walking the history.
+fn branch_ancestor_positions(repo: RepoRef, head_ids: &[CommitId]) -> Option<IndexPositionSet> {
+    let branch_heads: HashSet<_> = reachability::branch_heads(repo.view())
+        .into_iter()
+        .collect();
+    if head_ids.is_empty() || !head_ids.iter().all(|id| branch_heads.contains(id)) {
+        return None;
+    }
+    let bitmaps =
+        ReachabilityBitmaps::load(repo.base_repo().index_store().reachability_dir()).ok()?;
+    let index = repo.index();
+    let mut positions = IndexPositionSet::new();
+    for head_id in head_ids {
+        positions.union_with(&bitmaps.ancestors(index, head_id));
+    }
+    Some(positions)
+}
+
 pub fn evaluate_expression<'repo>(
     repo: RepoRef<'repo>,
     expression: &RevsetExpression,
@@ -1171,9 +1808,62 @@ pub fn evaluate_expression<'repo>(
                 candidate_set,
             }))
         }
-        RevsetExpression::Ancestors(base_expression) => RevsetExpression::none()
-            .range(base_expression)
-            .evaluate(repo, workspace_id),
+        RevsetExpression::Ancestors(base_expression) => {
+            let base_set = base_expression.evaluate(repo, workspace_id)?;
+            let head_ids = base_set.iter().commit_ids().collect_vec();
+            if let Some(positions) = branch_ancestor_positions(repo, &head_ids) {
+                return Ok(Box::new(PositionSetRevset {
+                    index: repo.index(),
+                    positions,
+                }));
+            }
+            let walk = repo.index().walk_revs(&head_ids, &[]);
+            Ok(Box::new(RevWalkRevset { walk }))
+        }
+        RevsetExpression::AncestorsUpTo { heads, depth } => {
+            let head_set = heads.evaluate(repo, workspace_id)?;
+            let head_ids = head_set.iter().commit_ids().collect_vec();
+            let walk = repo
+                .index()
+                .walk_revs(&head_ids, &[])
+                .max_generation(*depth);
+            Ok(Box::new(RevWalkRevset { walk }))
+        }
+        #[allow(clippy::needless_collect)]
+        RevsetExpression::DescendantsUpTo { roots, depth } => {
+            let root_set = roots.evaluate(repo, workspace_id)?;
+            let candidate_set = roots.descendants().evaluate(repo, workspace_id)?;
+            // The smallest number of generations from the roots to each commit
+            let mut generations: HashMap<_, u32> =
+                root_set.iter().map(|entry| (entry.position(), 0)).collect();
+            let mut result = vec![];
+            let candidates = candidate_set.iter().collect_vec();
+            for candidate in candidates.into_iter().rev() {
+                let generation = match generations.get(&candidate.position()) {
+                    Some(generation) => *generation,
+                    None => {
+                        let parent_generation = candidate
+                            .parent_positions()
+                            .iter()
+                            .filter_map(|parent_pos| generations.get(parent_pos))
+                            .min()
+                            .copied();
+                        match parent_generation {
+                            Some(parent_generation) => parent_generation.saturating_add(1),
+                            None => continue,
+                        }
+                    }
+                };
+                if generation <= *depth {
+                    generations.insert(candidate.position(), generation);
+                    result.push(candidate);
+                }
+            }
+            result.reverse();
+            Ok(Box::new(EagerRevset {
+                index_entries: result,
+            }))
+        }
         RevsetExpression::Range { roots, heads } => {
             let root_set = roots.evaluate(repo, workspace_id)?;
             let root_ids = root_set.iter().commit_ids().collect_vec();
@@ -1250,6 +1940,10 @@ pub fn evaluate_expression<'repo>(
             repo,
             &repo.view().public_heads().iter().cloned().collect_vec(),
         )),
+        RevsetExpression::WorkingCopies => {
+            let commit_ids = repo.view().wc_commit_ids().values().cloned().collect_vec();
+            Ok(revset_for_commit_ids(repo, &commit_ids))
+        }
         RevsetExpression::Branches => {
             let mut commit_ids = vec![];
             for branch_target in repo.view().branches().values() {
@@ -1259,11 +1953,22 @@ pub fn evaluate_expression<'repo>(
             }
             Ok(revset_for_commit_ids(repo, &commit_ids))
         }
-        RevsetExpression::RemoteBranches => {
+        RevsetExpression::RemoteBranches {
+            branch_pattern,
+            remote,
+        } => {
+            let branch_pattern = branch_pattern.as_deref().map(RefNamePattern::glob);
             let mut commit_ids = vec![];
-            for branch_target in repo.view().branches().values() {
-                for remote_target in branch_target.remote_targets.values() {
-                    commit_ids.extend(remote_target.adds());
+            for (branch_name, branch_target) in repo.view().branches() {
+                if let Some(branch_pattern) = &branch_pattern {
+                    if !branch_pattern.matches(branch_name) {
+                        continue;
+                    }
+                }
+                for (remote_name, remote_target) in &branch_target.remote_targets {
+                    if remote.as_ref().map_or(true, |remote| remote == remote_name) {
+                        commit_ids.extend(remote_target.adds());
+                    }
                 }
             }
             Ok(revset_for_commit_ids(repo, &commit_ids))
@@ -1286,6 +1991,48 @@ pub fn evaluate_expression<'repo>(
             let commit_ids = repo.view().git_head().into_iter().collect_vec();
             Ok(revset_for_commit_ids(repo, &commit_ids))
         }
+        RevsetExpression::Divergent => {
+            // TODO: Create a persistent index from change id to commit ids.
+            let mut entries_by_change: HashMap<ChangeId, Vec<IndexEntry>> = HashMap::new();
+            for entry in RevsetExpression::all().evaluate(repo, workspace_id)?.iter() {
+                entries_by_change
+                    .entry(entry.change_id())
+                    .or_default()
+                    .push(entry);
+            }
+            let mut index_entries = entries_by_change
+                .into_values()
+                .filter(|entries| entries.len() > 1)
+                .flatten()
+                .collect_vec();
+            index_entries.sort_by_key(|entry| Reverse(entry.position()));
+            Ok(Box::new(EagerRevset { index_entries }))
+        }
+        RevsetExpression::Hidden => {
+            let visible: HashSet<_> = RevsetExpression::all()
+                .evaluate(repo, workspace_id)?
+                .iter()
+                .map(|entry| entry.position())
+                .collect();
+            let index_entries = repo
+                .index()
+                .entries()
+                .filter(|entry| !visible.contains(&entry.position()))
+                .collect_vec();
+            Ok(Box::new(EagerRevset { index_entries }))
+        }
+        RevsetExpression::AddedByOp(op_str) => {
+            let operation = resolve_operation(repo, op_str)?;
+            let heads = operation.view().heads().iter().cloned().collect_vec();
+            let parent_heads = operation
+                .parents()
+                .iter()
+                .flat_map(|parent| parent.view().heads().clone())
+                .collect_vec();
+            RevsetExpression::commits(parent_heads)
+                .range(&RevsetExpression::commits(heads))
+                .evaluate(repo, workspace_id)
+        }
         RevsetExpression::Description { needle, candidates } => {
             let candidates = candidates.evaluate(repo, workspace_id)?;
             let repo = repo;
@@ -1317,6 +2064,39 @@ pub fn evaluate_expression<'repo>(
                 }),
             }))
         }
+        RevsetExpression::Mine { candidates } => {
+            let candidates = candidates.evaluate(repo, workspace_id)?;
+            let settings = repo.base_repo().settings();
+            let mailmap = settings.mailmap();
+            let emails = settings
+                .user_emails()
+                .into_iter()
+                .map(|email| email.to_lowercase())
+                .collect::<HashSet<_>>();
+            Ok(Box::new(FilterRevset {
+                candidates,
+                predicate: Box::new(move |entry| {
+                    let commit = repo.store().get_commit(&entry.commit_id()).unwrap();
+                    let author = mailmap.resolve(commit.author());
+                    emails.contains(&commit.author().email.to_lowercase())
+                        || emails.contains(&author.email.to_lowercase())
+                }),
+            }))
+        }
+        RevsetExpression::Topic { topic, candidates } => {
+            let candidates = candidates.evaluate(repo, workspace_id)?;
+            let change_ids: HashSet<_> = repo
+                .view()
+                .change_topics()
+                .iter()
+                .filter(|(_, change_topic)| *change_topic == topic)
+                .map(|(change_id, _)| change_id.clone())
+                .collect();
+            Ok(Box::new(FilterRevset {
+                candidates,
+                predicate: Box::new(move |entry| change_ids.contains(&entry.change_id())),
+            }))
+        }
         RevsetExpression::Committer { needle, candidates } => {
             let candidates = candidates.evaluate(repo, workspace_id)?;
             let repo = repo;
@@ -1330,6 +2110,23 @@ pub fn evaluate_expression<'repo>(
                 }),
             }))
         }
+        RevsetExpression::Union(expression1, expression2)
+        | RevsetExpression::Intersection(expression1, expression2)
+        | RevsetExpression::Difference(expression1, expression2)
+            if is_large_set(expression1) && is_large_set(expression2) =>
+        {
+            let mut positions = evaluate_to_position_set(repo, expression1, workspace_id)?;
+            let other_positions = evaluate_to_position_set(repo, expression2, workspace_id)?;
+            match expression {
+                RevsetExpression::Union(..) => positions.union_with(&other_positions),
+                RevsetExpression::Intersection(..) => positions.intersect_with(&other_positions),
+                _ => positions.difference_with(&other_positions),
+            }
+            Ok(Box::new(PositionSetRevset {
+                index: repo.index(),
+                positions,
+            }))
+        }
         RevsetExpression::Union(expression1, expression2) => {
             let set1 = expression1.evaluate(repo, workspace_id)?;
             let set2 = expression2.evaluate(repo, workspace_id)?;
@@ -1348,6 +2145,82 @@ pub fn evaluate_expression<'repo>(
     }
 }
 
+/// Resolves an operation expression for `added_by_op()`. Operation id
+/// prefixes are looked up among the ancestors of the operation the repo was
+/// loaded at.
+fn resolve_operation(repo: RepoRef, op_str: &str) -> Result<Operation, RevsetError> {
+    let base_str = op_str.trim_end_matches('-');
+    let current_op = repo.base_repo().operation().clone();
+    let mut operation = if base_str == "@" {
+        current_op
+    } else {
+        if base_str.is_empty() || !base_str.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(RevsetError::NoSuchOperation(op_str.to_string()));
+        }
+        let mut visited = HashSet::new();
+        let mut work = vec![current_op];
+        let mut matches: Vec<Operation> = vec![];
+        while let Some(op) = work.pop() {
+            if visited.insert(op.id().clone()) {
+                if op.id().hex().starts_with(base_str) {
+                    matches.push(op.clone());
+                }
+                work.extend(op.parents());
+            }
+        }
+        match matches.len() {
+            0 => return Err(RevsetError::NoSuchOperation(op_str.to_string())),
+            1 => matches.pop().unwrap(),
+            _ => return Err(RevsetError::AmbiguousOperation(op_str.to_string())),
+        }
+    };
+    for _ in 0..(op_str.len() - base_str.len()) {
+        let mut parents = operation.parents();
+        match parents.len() {
+            0 => return Err(RevsetError::NoSuchOperation(op_str.to_string())),
+            1 => operation = parents.pop().unwrap(),
+            _ => return Err(RevsetError::AmbiguousOperation(op_str.to_string())),
+        }
+    }
+    Ok(operation)
+}
+
+/// Guesses whether the expression can evaluate to a large part of the repo.
+/// Set operations on two such expressions are evaluated on bitsets of index
+/// positions instead of by merging lazy iterators, which would walk each
+/// operand once per iteration.
+fn is_large_set(expression: &RevsetExpression) -> bool {
+    match expression {
+        RevsetExpression::Ancestors(_)
+        | RevsetExpression::Range { .. }
+        | RevsetExpression::DagRange { .. } => true,
+        RevsetExpression::ParentCount { candidates, .. }
+        | RevsetExpression::Description { candidates, .. }
+        | RevsetExpression::Author { candidates, .. }
+        | RevsetExpression::Committer { candidates, .. }
+        | RevsetExpression::Mine { candidates }
+        | RevsetExpression::Topic { candidates, .. } => is_large_set(candidates),
+        RevsetExpression::Union(expression1, expression2) => {
+            is_large_set(expression1) || is_large_set(expression2)
+        }
+        RevsetExpression::Intersection(expression1, expression2) => {
+            is_large_set(expression1) && is_large_set(expression2)
+        }
+        RevsetExpression::Difference(expression1, _) => is_large_set(expression1),
+        _ => false,
+    }
+}
+
+fn evaluate_to_position_set(
+    repo: RepoRef,
+    expression: &RevsetExpression,
+    workspace_id: Option<&WorkspaceId>,
+) -> Result<IndexPositionSet, RevsetError> {
+    let revset = evaluate_expression(repo, expression, workspace_id)?;
+    let positions = revset.iter().map(|entry| entry.position()).collect();
+    Ok(positions)
+}
+
 fn revset_for_commit_ids<'revset, 'repo: 'revset>(
     repo: RepoRef<'repo>,
     commit_ids: &[CommitId],
@@ -1380,24 +2253,73 @@ pub fn filter_by_diff<'revset, 'repo: 'revset>(
     matcher: &'repo dyn Matcher,
     candidates: Box<dyn Revset<'repo> + 'revset>,
 ) -> Box<dyn Revset<'repo> + 'revset> {
+    // If the matcher is limited to some paths, the changed-path filters can rule
+    // out most commits without diffing them
+    let path_prefixes = matcher.path_prefixes();
+    let changed_path_index = path_prefixes.as_ref().and_then(|_| {
+        ChangedPathIndex::load(
+            repo.base_repo().index_store().changed_paths_dir(),
+            repo.store().hash_length(),
+        )
+        .ok()
+    });
     Box::new(FilterRevset {
         candidates,
         predicate: Box::new(move |entry| {
-            let commit = repo.store().get_commit(&entry.commit_id()).unwrap();
-            let parents = commit.parents();
-            let from_tree = rewrite::merge_commit_trees(repo, &parents);
-            let to_tree = commit.tree();
-            from_tree.diff(&to_tree, matcher).next().is_some()
+            let commit_id = entry.commit_id();
+            let (path_prefixes, changed_path_index) = match (&path_prefixes, &changed_path_index) {
+                (Some(path_prefixes), Some(changed_path_index)) => {
+                    (path_prefixes, changed_path_index)
+                }
+                _ => return commit_touches_paths(repo, &commit_id, matcher),
+            };
+            match changed_path_index.get(&commit_id) {
+                Some(filter) => {
+                    path_prefixes.iter().any(|path| filter.might_contain(path))
+                        && commit_touches_paths(repo, &commit_id, matcher)
+                }
+                None => {
+                    let changed_files = commit_changed_files(repo, &commit_id);
+                    changed_path_index.add(
+                        commit_id,
+                        ChangedPathFilter::from_changed_files(&changed_files),
+                    );
+                    changed_files.iter().any(|file| matcher.matches(file))
+                }
+            }
         }),
     })
 }
 
+fn commit_touches_paths(repo: RepoRef, commit_id: &CommitId, matcher: &dyn Matcher) -> bool {
+    let commit = repo.store().get_commit(commit_id).unwrap();
+    let parents = commit.parents();
+    let from_tree = rewrite::merge_commit_trees(repo, &parents);
+    let to_tree = commit.tree();
+    from_tree.diff(&to_tree, matcher).next().is_some()
+}
+
+fn commit_changed_files(repo: RepoRef, commit_id: &CommitId) -> Vec<RepoPath> {
+    let commit = repo.store().get_commit(commit_id).unwrap();
+    let parents = commit.parents();
+    let from_tree = rewrite::merge_commit_trees(repo, &parents);
+    let to_tree = commit.tree();
+    from_tree
+        .diff(&to_tree, &EverythingMatcher)
+        .map(|(path, _)| path)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use assert_matches::assert_matches;
 
     use super::*;
 
+    fn parse_into_kind(text: &str) -> Result<Rc<RevsetExpression>, RevsetParseErrorKind> {
+        parse(text).map_err(|err| err.kind)
+    }
+
     #[test]
     fn test_revset_expression_building() {
         let wc_symbol = RevsetExpression::symbol("@".to_string());
@@ -1508,59 +2430,97 @@ mod tests {
         let foo_symbol = RevsetExpression::symbol("foo".to_string());
         let bar_symbol = RevsetExpression::symbol("bar".to_string());
         // Parse a single symbol (specifically the "checkout" symbol)
-        assert_eq!(parse("@"), Ok(wc_symbol.clone()));
+        assert_eq!(parse_into_kind("@"), Ok(wc_symbol.clone()));
         // Parse a single symbol
-        assert_eq!(parse("foo"), Ok(foo_symbol.clone()));
+        assert_eq!(parse_into_kind("foo"), Ok(foo_symbol.clone()));
         // Internal '.', '-', and '+' are allowed
         assert_eq!(
-            parse("foo.bar-v1+7"),
+            parse_into_kind("foo.bar-v1+7"),
             Ok(RevsetExpression::symbol("foo.bar-v1+7".to_string()))
         );
         // '.' is not allowed at the beginning or end
-        assert_matches!(parse(".foo"), Err(RevsetParseError::SyntaxError(_)));
-        assert_matches!(parse("foo."), Err(RevsetParseError::SyntaxError(_)));
+        assert_matches!(
+            parse_into_kind(".foo"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_matches!(
+            parse_into_kind("foo."),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
         // Multiple '.', '-', '+' are not allowed
-        assert_matches!(parse("foo.+bar"), Err(RevsetParseError::SyntaxError(_)));
-        assert_matches!(parse("foo--bar"), Err(RevsetParseError::SyntaxError(_)));
-        assert_matches!(parse("foo+-bar"), Err(RevsetParseError::SyntaxError(_)));
+        assert_matches!(
+            parse_into_kind("foo.+bar"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_matches!(
+            parse_into_kind("foo--bar"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_matches!(
+            parse_into_kind("foo+-bar"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
         // Parse a parenthesized symbol
-        assert_eq!(parse("(foo)"), Ok(foo_symbol.clone()));
+        assert_eq!(parse_into_kind("(foo)"), Ok(foo_symbol.clone()));
         // Parse a quoted symbol
-        assert_eq!(parse("\"foo\""), Ok(foo_symbol.clone()));
+        assert_eq!(parse_into_kind("\"foo\""), Ok(foo_symbol.clone()));
         // Parse the "parents" operator
-        assert_eq!(parse("@-"), Ok(wc_symbol.parents()));
+        assert_eq!(parse_into_kind("@-"), Ok(wc_symbol.parents()));
         // Parse the "children" operator
-        assert_eq!(parse("@+"), Ok(wc_symbol.children()));
+        assert_eq!(parse_into_kind("@+"), Ok(wc_symbol.children()));
         // Parse the "ancestors" operator
-        assert_eq!(parse(":@"), Ok(wc_symbol.ancestors()));
+        assert_eq!(parse_into_kind(":@"), Ok(wc_symbol.ancestors()));
         // Parse the "descendants" operator
-        assert_eq!(parse("@:"), Ok(wc_symbol.descendants()));
+        assert_eq!(parse_into_kind("@:"), Ok(wc_symbol.descendants()));
         // Parse the "dag range" operator
-        assert_eq!(parse("foo:bar"), Ok(foo_symbol.dag_range_to(&bar_symbol)));
+        assert_eq!(
+            parse_into_kind("foo:bar"),
+            Ok(foo_symbol.dag_range_to(&bar_symbol))
+        );
         // Parse the "range" prefix operator
-        assert_eq!(parse("..@"), Ok(wc_symbol.ancestors()));
+        assert_eq!(parse_into_kind("..@"), Ok(wc_symbol.ancestors()));
         assert_eq!(
-            parse("@.."),
+            parse_into_kind("@.."),
             Ok(wc_symbol.range(&RevsetExpression::visible_heads()))
         );
-        assert_eq!(parse("foo..bar"), Ok(foo_symbol.range(&bar_symbol)));
+        assert_eq!(
+            parse_into_kind("foo..bar"),
+            Ok(foo_symbol.range(&bar_symbol))
+        );
         // Parse the "intersection" operator
-        assert_eq!(parse("foo & bar"), Ok(foo_symbol.intersection(&bar_symbol)));
+        assert_eq!(
+            parse_into_kind("foo & bar"),
+            Ok(foo_symbol.intersection(&bar_symbol))
+        );
         // Parse the "union" operator
-        assert_eq!(parse("foo | bar"), Ok(foo_symbol.union(&bar_symbol)));
+        assert_eq!(
+            parse_into_kind("foo | bar"),
+            Ok(foo_symbol.union(&bar_symbol))
+        );
         // Parse the "difference" operator
-        assert_eq!(parse("foo ~ bar"), Ok(foo_symbol.minus(&bar_symbol)));
+        assert_eq!(
+            parse_into_kind("foo ~ bar"),
+            Ok(foo_symbol.minus(&bar_symbol))
+        );
         // Parentheses are allowed before suffix operators
-        assert_eq!(parse("(@)-"), Ok(wc_symbol.parents()));
+        assert_eq!(parse_into_kind("(@)-"), Ok(wc_symbol.parents()));
         // Space is allowed around expressions
-        assert_eq!(parse(" :@ "), Ok(wc_symbol.ancestors()));
+        assert_eq!(parse_into_kind(" :@ "), Ok(wc_symbol.ancestors()));
         // Space is not allowed around prefix operators
-        assert_matches!(parse(" : @ "), Err(RevsetParseError::SyntaxError(_)));
+        assert_matches!(
+            parse_into_kind(" : @ "),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
         // Incomplete parse
-        assert_matches!(parse("foo | -"), Err(RevsetParseError::SyntaxError(_)));
+        assert_matches!(
+            parse_into_kind("foo | -"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
         // Space is allowed around infix operators and function arguments
         assert_eq!(
-            parse("   description(  arg1 ,   arg2 ) ~    parents(   arg1  )  ~ heads(  )  "),
+            parse_into_kind(
+                "   description(  arg1 ,   arg2 ) ~    parents(   arg1  )  ~ heads(  )  "
+            ),
             Ok(RevsetExpression::symbol("arg2".to_string())
                 .with_description("arg1".to_string())
                 .minus(&RevsetExpression::symbol("arg1".to_string()).parents())
@@ -1573,69 +2533,256 @@ mod tests {
         let foo_symbol = RevsetExpression::symbol("foo".to_string());
         // Parse repeated "parents" operator
         assert_eq!(
-            parse("foo---"),
+            parse_into_kind("foo---"),
             Ok(foo_symbol.parents().parents().parents())
         );
         // Parse repeated "children" operator
         assert_eq!(
-            parse("foo+++"),
+            parse_into_kind("foo+++"),
             Ok(foo_symbol.children().children().children())
         );
         // Parse repeated "ancestors"/"descendants"/"dag range"/"range" operators
-        assert_matches!(parse(":foo:"), Err(RevsetParseError::SyntaxError(_)));
-        assert_matches!(parse("::foo"), Err(RevsetParseError::SyntaxError(_)));
-        assert_matches!(parse("foo::"), Err(RevsetParseError::SyntaxError(_)));
-        assert_matches!(parse("foo::bar"), Err(RevsetParseError::SyntaxError(_)));
-        assert_matches!(parse(":foo:bar"), Err(RevsetParseError::SyntaxError(_)));
-        assert_matches!(parse("foo:bar:"), Err(RevsetParseError::SyntaxError(_)));
-        assert_matches!(parse("....foo"), Err(RevsetParseError::SyntaxError(_)));
-        assert_matches!(parse("foo...."), Err(RevsetParseError::SyntaxError(_)));
-        assert_matches!(parse("foo.....bar"), Err(RevsetParseError::SyntaxError(_)));
-        assert_matches!(parse("..foo..bar"), Err(RevsetParseError::SyntaxError(_)));
-        assert_matches!(parse("foo..bar.."), Err(RevsetParseError::SyntaxError(_)));
+        assert_matches!(
+            parse_into_kind(":foo:"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_matches!(
+            parse_into_kind("::foo"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_matches!(
+            parse_into_kind("foo::"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_matches!(
+            parse_into_kind("foo::bar"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_matches!(
+            parse_into_kind(":foo:bar"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_matches!(
+            parse_into_kind("foo:bar:"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_matches!(
+            parse_into_kind("....foo"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_matches!(
+            parse_into_kind("foo...."),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_matches!(
+            parse_into_kind("foo.....bar"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_matches!(
+            parse_into_kind("..foo..bar"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_matches!(
+            parse_into_kind("foo..bar.."),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
         // Parse combinations of "parents"/"children" operators and the range operators.
         // The former bind more strongly.
-        assert_eq!(parse("foo-+"), Ok(foo_symbol.parents().children()));
-        assert_eq!(parse("foo-:"), Ok(foo_symbol.parents().descendants()));
-        assert_eq!(parse(":foo+"), Ok(foo_symbol.children().ancestors()));
+        assert_eq!(
+            parse_into_kind("foo-+"),
+            Ok(foo_symbol.parents().children())
+        );
+        assert_eq!(
+            parse_into_kind("foo-:"),
+            Ok(foo_symbol.parents().descendants())
+        );
+        assert_eq!(
+            parse_into_kind(":foo+"),
+            Ok(foo_symbol.children().ancestors())
+        );
+        // Parse the bounded "ancestors" operator, which binds like "parents". It's
+        // only recognized without whitespace and with a number as the depth.
+        assert_eq!(parse_into_kind("foo~3"), Ok(foo_symbol.ancestors_up_to(3)));
+        assert_eq!(
+            parse_into_kind("foo-~2+"),
+            Ok(foo_symbol.parents().ancestors_up_to(2).children())
+        );
+        assert_eq!(
+            parse_into_kind("foo ~ 3"),
+            Ok(foo_symbol.minus(&RevsetExpression::symbol("3".to_string())))
+        );
+        assert_eq!(
+            parse_into_kind("foo~3a"),
+            Ok(foo_symbol.minus(&RevsetExpression::symbol("3a".to_string())))
+        );
+        assert_eq!(
+            parse_into_kind("foo~99999999999"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "~".to_string(),
+                message: "Expected a non-negative integer as depth, found: 99999999999".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_revset_no_such_function() {
+        assert_eq!(
+            parse_into_kind("ancestor(@)"),
+            Err(RevsetParseErrorKind::NoSuchFunction {
+                name: "ancestor".to_string(),
+                candidates: vec!["ancestors".to_string()],
+            })
+        );
+        assert_eq!(
+            parse_into_kind("foo()"),
+            Err(RevsetParseErrorKind::NoSuchFunction {
+                name: "foo".to_string(),
+                candidates: vec![],
+            })
+        );
+        // The error points at the function name
+        insta::assert_snapshot!(parse("root | ancestor(@)").unwrap_err().to_string(), @r###"
+         --> 1:8
+          |
+        1 | root | ancestor(@)
+          |        ^------^
+          |
+          = Revset function "ancestor" doesn't exist
+        Hint: Did you mean "ancestors"?
+        "###);
     }
 
     #[test]
     fn test_parse_revset_function() {
         let wc_symbol = RevsetExpression::symbol("@".to_string());
-        assert_eq!(parse("parents(@)"), Ok(wc_symbol.parents()));
-        assert_eq!(parse("parents((@))"), Ok(wc_symbol.parents()));
-        assert_eq!(parse("parents(\"@\")"), Ok(wc_symbol.parents()));
+        assert_eq!(parse_into_kind("parents(@)"), Ok(wc_symbol.parents()));
+        assert_eq!(parse_into_kind("parents((@))"), Ok(wc_symbol.parents()));
+        assert_eq!(parse_into_kind("parents(\"@\")"), Ok(wc_symbol.parents()));
         assert_eq!(
-            parse("ancestors(parents(@))"),
+            parse_into_kind("ancestors(parents(@))"),
             Ok(wc_symbol.parents().ancestors())
         );
-        assert_matches!(parse("parents(@"), Err(RevsetParseError::SyntaxError(_)));
+        assert_matches!(
+            parse_into_kind("parents(@"),
+            Err(RevsetParseErrorKind::SyntaxError)
+        );
+        assert_eq!(parse("builtin_log()"), parse(BUILTIN_LOG_REVSET));
+        assert_eq!(
+            parse_into_kind("ancestors(@, 2)"),
+            Ok(wc_symbol.ancestors_up_to(2))
+        );
+        assert_eq!(
+            parse_into_kind("descendants(@,0)"),
+            Ok(wc_symbol.descendants_up_to(0))
+        );
+        assert_eq!(
+            parse_into_kind("ancestors(@, foo)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "ancestors".to_string(),
+                message: "Expected a non-negative integer as depth, found: foo".to_string()
+            })
+        );
         assert_eq!(
-            parse("parents(@,@)"),
-            Err(RevsetParseError::InvalidFunctionArguments {
+            parse_into_kind("parents_count(2)"),
+            Ok(RevsetExpression::all().with_parent_count(2..3))
+        );
+        assert_eq!(
+            parse_into_kind("parents_count(foo, @)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "parents_count".to_string(),
+                message: "Expected a non-negative integer as parent count, found: foo".to_string()
+            })
+        );
+        assert_eq!(
+            parse_into_kind("parents_count(4294967295)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "parents_count".to_string(),
+                message: "Parent count is too large: 4294967295".to_string()
+            })
+        );
+        assert_eq!(
+            parse_into_kind("descendants(@,@,@)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "descendants".to_string(),
+                message: "Expected 1 or 2 arguments".to_string()
+            })
+        );
+        assert_eq!(
+            parse_into_kind("parents(@,@)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
                 name: "parents".to_string(),
                 message: "Expected 1 argument".to_string()
             })
         );
         assert_eq!(
-            parse("description(foo,bar)"),
+            parse_into_kind("description(foo,bar)"),
             Ok(RevsetExpression::symbol("bar".to_string()).with_description("foo".to_string()))
         );
         assert_eq!(
-            parse("description(heads(),bar)"),
-            Err(RevsetParseError::InvalidFunctionArguments {
+            parse_into_kind("description(heads(),bar)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
                 name: "description".to_string(),
                 message: "Expected function argument of type string, found: heads()".to_string()
             })
         );
         assert_eq!(
-            parse("description((foo),bar)"),
+            parse_into_kind("description((foo),bar)"),
             Ok(RevsetExpression::symbol("bar".to_string()).with_description("foo".to_string()))
         );
         assert_eq!(
-            parse("description(\"(foo)\",bar)"),
+            parse_into_kind("description(\"(foo)\",bar)"),
             Ok(RevsetExpression::symbol("bar".to_string()).with_description("(foo)".to_string()))
         );
     }
+
+    #[test]
+    fn test_parse_revset_keyword_arguments() {
+        assert_eq!(
+            parse_into_kind("remote_branches()"),
+            Ok(RevsetExpression::remote_branches())
+        );
+        assert_eq!(
+            parse_into_kind("remote_branches(\"feature/*\")"),
+            Ok(RevsetExpression::remote_branches_matching(
+                Some("feature/*".to_string()),
+                None
+            ))
+        );
+        assert_eq!(
+            parse_into_kind("remote_branches(remote=origin)"),
+            Ok(RevsetExpression::remote_branches_matching(
+                None,
+                Some("origin".to_string())
+            ))
+        );
+        assert_eq!(
+            parse_into_kind("remote_branches( main , remote = \"my-remote\" )"),
+            Ok(RevsetExpression::remote_branches_matching(
+                Some("main".to_string()),
+                Some("my-remote".to_string())
+            ))
+        );
+        assert_eq!(
+            parse_into_kind("remote_branches(remote=origin, main)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "remote_branches".to_string(),
+                message: "Expected at most 1 positional argument, before the keyword arguments"
+                    .to_string()
+            })
+        );
+        assert_eq!(
+            parse_into_kind("remote_branches(main, branch=main)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "remote_branches".to_string(),
+                message: "Unexpected keyword argument: branch".to_string()
+            })
+        );
+        assert_eq!(
+            parse_into_kind("parents(x=@)"),
+            Err(RevsetParseErrorKind::InvalidFunctionArguments {
+                name: "parents".to_string(),
+                message: "Unexpected keyword argument: x".to_string()
+            })
+        );
+    }
 }
@@ -13,13 +13,16 @@
 // limitations under the License.
 
 use std::collections::{HashMap, HashSet};
+use std::io::Read;
 
 use itertools::{process_results, Itertools};
+use regex::bytes::Regex;
 
-use crate::backend::{BackendError, CommitId};
+use crate::backend::{BackendError, BackendResult, CommitId, FileId, TreeId, TreeValue};
 use crate::commit::Commit;
 use crate::commit_builder::CommitBuilder;
 use crate::dag_walk;
+use crate::matchers::{Matcher, Visit};
 use crate::op_store::RefTarget;
 use crate::repo::{MutableRepo, RepoRef};
 use crate::repo_path::RepoPath;
@@ -109,6 +112,82 @@ pub fn back_out_commit(
         .write_to_repo(mut_repo)
 }
 
+/// Returns the id of a tree with the contents of `destination`, except that the
+/// paths matching `matcher` have the contents they have in `source`. When the
+/// result is used to rewrite a commit, the change is merged into the commit's
+/// descendants as they're rebased, like any other rewrite.
+pub fn restore_tree(source: &Tree, destination: &Tree, matcher: &dyn Matcher) -> TreeId {
+    if matcher.visit(&RepoPath::root()) == Visit::AllRecursively {
+        return source.id().clone();
+    }
+    let mut tree_builder = destination.store().tree_builder(destination.id().clone());
+    tree_builder.graft(source, matcher);
+    tree_builder.write_tree()
+}
+
+/// Replaces all matches of a regex in the files of trees. The replacement may
+/// refer to capture groups as described in `regex::Regex::replace_all()`.
+/// Symlinks and conflicted files are left alone.
+pub struct TextReplacer {
+    regex: Regex,
+    replacement: Vec<u8>,
+    // The result of the replacement for each file that has been seen
+    replaced_files: HashMap<FileId, FileId>,
+}
+
+impl TextReplacer {
+    pub fn new(regex: Regex, replacement: &str) -> Self {
+        TextReplacer {
+            regex,
+            replacement: replacement.as_bytes().to_vec(),
+            replaced_files: HashMap::new(),
+        }
+    }
+
+    /// Replaces the matches in the files in `tree` that match `matcher`, and
+    /// returns the id of the resulting tree.
+    pub fn replace_in_tree(&mut self, tree: &Tree, matcher: &dyn Matcher) -> BackendResult<TreeId> {
+        let store = tree.store();
+        let mut tree_builder = store.tree_builder(tree.id().clone());
+        for (path, value) in tree.entries_matching(matcher) {
+            let (id, executable) = match value {
+                TreeValue::Normal { id, executable } => (id, executable),
+                _ => continue,
+            };
+            let new_id = match self.replaced_files.get(&id) {
+                Some(new_id) => new_id.clone(),
+                None => {
+                    let mut contents = vec![];
+                    store
+                        .read_file(&path, &id)?
+                        .read_to_end(&mut contents)
+                        .map_err(|err| BackendError::Other(err.to_string()))?;
+                    let new_contents = self
+                        .regex
+                        .replace_all(&contents, self.replacement.as_slice());
+                    let new_id = if new_contents == contents {
+                        id.clone()
+                    } else {
+                        store.write_file(&path, &mut new_contents.as_ref())?
+                    };
+                    self.replaced_files.insert(id.clone(), new_id.clone());
+                    new_id
+                }
+            };
+            if new_id != id {
+                tree_builder.set(
+                    path,
+                    TreeValue::Normal {
+                        id: new_id,
+                        executable,
+                    },
+                );
+            }
+        }
+        Ok(tree_builder.write_tree())
+    }
+}
+
 /// Rebases descendants of a commit onto a new commit (or several).
 // TODO: Should there be an option to drop empty commits (and/or an option to
 // drop empty commits only if they weren't already empty)? Or maybe that
@@ -12,11 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
 use std::path::Path;
 
 use chrono::DateTime;
 
 use crate::backend::{Signature, Timestamp};
+use crate::git::SignedPush;
+use crate::line_endings::LineEndingPolicy;
+use crate::mailmap::Mailmap;
+use crate::merge_attributes::{MergeAttributes, MergeAttributesError};
+use crate::store::StoreOptions;
+use crate::working_copy::{SnapshotMode, UnicodeNormalization};
 
 #[derive(Debug, Clone, Default)]
 pub struct UserSettings {
@@ -26,21 +33,26 @@ pub struct UserSettings {
 
 #[derive(Debug, Clone)]
 pub struct RepoSettings {
-    _config: config::Config,
+    config: config::Config,
+    mailmap: Mailmap,
 }
 
 impl UserSettings {
     pub fn from_config(config: config::Config) -> Self {
-        let timestamp = match config.get_string("user.timestamp") {
-            Ok(timestamp_str) => match DateTime::parse_from_rfc3339(&timestamp_str) {
-                Ok(datetime) => Some(Timestamp::from_datetime(datetime)),
-                Err(_) => None,
-            },
-            Err(_) => None,
-        };
+        let timestamp = timestamp_override(&config);
         UserSettings { config, timestamp }
     }
 
+    /// Adds settings that apply only where the config doesn't set them, such
+    /// as the ones taken from the Git config of a Git-backed repo.
+    pub fn with_fallback(&self, fallback: config::Config) -> Result<Self, config::ConfigError> {
+        let config = config::Config::builder()
+            .add_source(fallback)
+            .add_source(self.config.clone())
+            .build()?;
+        Ok(Self::from_config(config))
+    }
+
     pub fn with_repo(&self, repo_path: &Path) -> Result<RepoSettings, config::ConfigError> {
         let config = config::Config::builder()
             .add_source(self.config.clone())
@@ -50,7 +62,10 @@ impl UserSettings {
                     .format(config::FileFormat::Toml),
             )
             .build()?;
-        Ok(RepoSettings { _config: config })
+        Ok(RepoSettings {
+            config,
+            mailmap: self.mailmap(),
+        })
     }
 
     pub fn user_name(&self) -> String {
@@ -82,11 +97,17 @@ impl UserSettings {
     pub fn default_revset(&self) -> String {
         self.config
             .get_string("ui.default-revset")
-            .unwrap_or_else(|_| "remote_branches().. | (remote_branches()..)-".to_string())
+            .unwrap_or_else(|_| "builtin_log()".to_string())
+    }
+
+    /// The current time, or the time set by `user.timestamp` (which tests use
+    /// to get reproducible results).
+    pub fn current_timestamp(&self) -> Timestamp {
+        self.timestamp.clone().unwrap_or_else(Timestamp::now)
     }
 
     pub fn signature(&self) -> Signature {
-        let timestamp = self.timestamp.clone().unwrap_or_else(Timestamp::now);
+        let timestamp = self.current_timestamp();
         Signature {
             name: self.user_name(),
             email: self.user_email(),
@@ -94,13 +115,329 @@ impl UserSettings {
         }
     }
 
+    /// The mapping from old identities to canonical ones in the
+    /// `[[mailmap]]` config.
+    pub fn mailmap(&self) -> Mailmap {
+        Mailmap::from_config(&self.config)
+    }
+
+    /// Whether `jj undo` and `jj op restore` show what they would change and
+    /// ask for confirmation when run in a terminal (`ui.confirm-undo`).
+    /// Defaults to true.
+    pub fn confirm_undo(&self) -> bool {
+        self.config.get_bool("ui.confirm-undo").unwrap_or(true)
+    }
+
+    /// Whether a summary of the workspace's state is written to
+    /// `.jj/state-summary.json` after each transaction (`ui.state-summary`).
+    /// Defaults to false.
+    pub fn write_state_summary(&self) -> bool {
+        self.config.get_bool("ui.state-summary").unwrap_or(false)
+    }
+
     pub fn enable_open_commits(&self) -> bool {
         self.config
             .get_bool("ui.enable-open-commits")
             .unwrap_or(false)
     }
 
+    pub fn use_content_cache(&self) -> bool {
+        self.config
+            .get_bool("working-copy.reflink")
+            .unwrap_or(false)
+    }
+
+    /// The size in bytes the reflink content cache is pruned to after each
+    /// checkout (`working-copy.reflink-cache-size`). Defaults to 1 GiB.
+    pub fn content_cache_max_size(&self) -> u64 {
+        self.config
+            .get_int("working-copy.reflink-cache-size")
+            .ok()
+            .and_then(|size| u64::try_from(size).ok())
+            .unwrap_or(1 << 30)
+    }
+
+    /// Whether checkouts skip files whose path can't be used on Windows
+    /// (`working-copy.windows-safe-paths`). On by default on Windows.
+    pub fn windows_safe_paths(&self) -> bool {
+        self.config
+            .get_bool("working-copy.windows-safe-paths")
+            .unwrap_or(cfg!(windows))
+    }
+
+    pub fn lazy_file_threshold(&self) -> Option<u64> {
+        self.config
+            .get_int("working-copy.lazy-threshold")
+            .ok()
+            .and_then(|threshold| u64::try_from(threshold).ok())
+    }
+
+    /// Whether checkouts move untracked files that are in the way of added
+    /// files to `.jj/backup/` (`working-copy.backup-overwritten`) instead of
+    /// failing.
+    pub fn backup_overwritten_files(&self) -> bool {
+        self.config
+            .get_bool("working-copy.backup-overwritten")
+            .unwrap_or(false)
+    }
+
+    /// How far snapshots trust file metadata (`snapshot.mode`, one of
+    /// "default", "paranoid" and "fast"). The paranoid mode's racy window is
+    /// `snapshot.racy-window` milliseconds (2 seconds by default).
+    pub fn snapshot_mode(&self) -> SnapshotMode {
+        match self.config.get_string("snapshot.mode").as_deref() {
+            Ok("paranoid") => SnapshotMode::Paranoid {
+                racy_window_millis: self.config.get_int("snapshot.racy-window").unwrap_or(2000),
+            },
+            Ok("fast") => SnapshotMode::Fast,
+            _ => SnapshotMode::Default,
+        }
+    }
+
+    /// How snapshots handle paths that only differ from tracked paths by their
+    /// Unicode normalization (`snapshot.unicode-normalization`).
+    pub fn unicode_normalization(&self) -> UnicodeNormalization {
+        match self
+            .config
+            .get_string("snapshot.unicode-normalization")
+            .as_deref()
+        {
+            Ok("none") => UnicodeNormalization::None,
+            Ok("nfc") => UnicodeNormalization::Nfc,
+            Ok("nfd") => UnicodeNormalization::Nfd,
+            _ => UnicodeNormalization::default(),
+        }
+    }
+
+    /// The most new or modified files a snapshot may record
+    /// (`limits.snapshot-files`), if limited.
+    pub fn max_snapshot_files(&self) -> Option<usize> {
+        self.limit("limits.snapshot-files")
+    }
+
+    /// The most commits a command may collect from a revset
+    /// (`limits.revset-commits`), if limited.
+    pub fn max_revset_commits(&self) -> Option<usize> {
+        self.limit("limits.revset-commits")
+    }
+
+    /// The most objects a fetch may download (`limits.fetch-objects`), if
+    /// limited.
+    pub fn max_fetch_objects(&self) -> Option<usize> {
+        self.limit("limits.fetch-objects")
+    }
+
+    fn limit(&self, key: &str) -> Option<usize> {
+        self.config
+            .get_int(key)
+            .ok()
+            .and_then(|limit| usize::try_from(limit).ok())
+    }
+
+    /// Whether a changed ctime means that a file has changed
+    /// (`snapshot.trust-ctime`, like Git's `core.trustctime`). Defaults to true.
+    pub fn trust_ctime(&self) -> bool {
+        self.config.get_bool("snapshot.trust-ctime").unwrap_or(true)
+    }
+
+    /// What snapshots do about files whose line endings or byte-order mark
+    /// changed wholesale (`snapshot.line-endings`, one of "warn", "normalize",
+    /// "block" and "ignore"). Defaults to "warn".
+    pub fn line_ending_policy(&self) -> Result<LineEndingPolicy, config::ConfigError> {
+        match self.config.get_string("snapshot.line-endings") {
+            Ok(value) => match value.as_str() {
+                "warn" => Ok(LineEndingPolicy::Warn),
+                "normalize" => Ok(LineEndingPolicy::Normalize),
+                "block" => Ok(LineEndingPolicy::Block),
+                "ignore" => Ok(LineEndingPolicy::Ignore),
+                _ => Err(config::ConfigError::Message(format!(
+                    "snapshot.line-endings: expected \"warn\", \"normalize\", \"block\" or \
+                     \"ignore\", got \"{value}\""
+                ))),
+            },
+            Err(config::ConfigError::NotFound(_)) => Ok(LineEndingPolicy::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Whether snapshots reuse the entries they found in a directory last time
+    /// if its mtime hasn't changed (`snapshot.dir-cache`). Defaults to false.
+    pub fn use_dir_cache(&self) -> bool {
+        self.config.get_bool("snapshot.dir-cache").unwrap_or(false)
+    }
+
+    /// Patterns (in gitignore syntax) of the paths that snapshots never track,
+    /// even if they're not ignored (`snapshot.never-track`).
+    pub fn never_track_patterns(&self) -> Vec<String> {
+        self.config
+            .get_array("snapshot.never-track")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|value| value.into_string().ok())
+            .collect()
+    }
+
+    /// Extra tags from the `[operation.tags]` config to record in the metadata
+    /// of every operation.
+    pub fn operation_tags(&self) -> HashMap<String, String> {
+        self.config
+            .get_table("operation.tags")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value.into_string().ok()?)))
+            .collect()
+    }
+
+    /// Whether commands should snapshot the working copy when they start
+    /// (`snapshot.auto`). Defaults to true.
+    pub fn auto_snapshot(&self) -> bool {
+        self.config.get_bool("snapshot.auto").unwrap_or(true)
+    }
+
     pub fn config(&self) -> &config::Config {
         &self.config
     }
 }
+
+impl RepoSettings {
+    pub fn config(&self) -> &config::Config {
+        &self.config
+    }
+
+    /// Like `UserSettings::current_timestamp()`.
+    pub fn current_timestamp(&self) -> Timestamp {
+        timestamp_override(&self.config).unwrap_or_else(Timestamp::now)
+    }
+
+    /// The same mapping as `UserSettings::mailmap()`. The repo's config isn't
+    /// consulted, since commits are written with the user's mapping.
+    pub fn mailmap(&self) -> Mailmap {
+        self.mailmap.clone()
+    }
+
+    /// The user's email addresses: `user.email` followed by the ones in
+    /// `user.other-emails` (e.g. old or work addresses).
+    pub fn user_emails(&self) -> Vec<String> {
+        let mut emails = vec![];
+        if let Ok(email) = self.config.get_string("user.email") {
+            emails.push(email);
+        }
+        if let Ok(other_emails) = self.config.get_array("user.other-emails") {
+            emails.extend(
+                other_emails
+                    .into_iter()
+                    .filter_map(|value| value.into_string().ok()),
+            );
+        }
+        emails
+    }
+
+    /// How the object store verifies and caches objects (`store.verify-reads`
+    /// and the `store.*-cache-size` limits in bytes), and how it merges files.
+    /// Invalid merge attributes are ignored here; see `merge_attributes()`.
+    pub fn store_options(&self) -> StoreOptions {
+        let defaults = StoreOptions::default();
+        let size = |key: &str, default: usize| {
+            self.config
+                .get_int(key)
+                .ok()
+                .and_then(|size| usize::try_from(size).ok())
+                .unwrap_or(default)
+        };
+        StoreOptions {
+            verify_reads: self.config.get_bool("store.verify-reads").unwrap_or(false),
+            commit_cache_size: size("store.commit-cache-size", defaults.commit_cache_size),
+            tree_cache_size: size("store.tree-cache-size", defaults.tree_cache_size),
+            file_cache_size: size("store.file-cache-size", defaults.file_cache_size),
+            merge_attributes: self.merge_attributes().unwrap_or_default(),
+        }
+    }
+
+    /// The `[merge-attributes.<name>]` tables.
+    pub fn merge_attributes(&self) -> Result<MergeAttributes, MergeAttributesError> {
+        MergeAttributes::from_config(&self.config)
+    }
+
+    /// How many loose objects the local backend accumulates before commands
+    /// pack them (`store.auto-pack-threshold`). 0 disables automatic packing.
+    pub fn auto_pack_threshold(&self) -> usize {
+        self.config
+            .get_int("store.auto-pack-threshold")
+            .ok()
+            .and_then(|threshold| usize::try_from(threshold).ok())
+            .unwrap_or(5000)
+    }
+
+    /// The Git ref to read and write notes in.
+    pub fn git_notes_ref(&self) -> String {
+        self.config
+            .get_string("git.notes-ref")
+            .unwrap_or_else(|_| crate::git::DEFAULT_NOTES_REF.to_string())
+    }
+
+    /// Whether `jj git push` sends signed push certificates (`git.sign-push`):
+    /// `true` to always send one, or `"if-asked"` to send one only if the
+    /// remote supports them.
+    pub fn git_sign_push(&self) -> Option<SignedPush> {
+        match self.config.get_string("git.sign-push").as_deref() {
+            Ok("true") => Some(SignedPush::Yes),
+            Ok("if-asked") => Some(SignedPush::IfAsked),
+            _ => None,
+        }
+    }
+
+    /// The template for links to commits on the forge (`browse.commit-url`),
+    /// e.g. `https://github.com/owner/repo/commit/{commit_id}`.
+    pub fn commit_url_template(&self) -> Option<String> {
+        self.config.get_string("browse.commit-url").ok()
+    }
+
+    /// Patterns of the branches that `jj git push` won't force-push or delete
+    /// unless asked to (`git.protected-branches`), either a list or a single
+    /// pattern.
+    pub fn git_protected_branches(&self) -> Result<Vec<String>, config::ConfigError> {
+        match self.config.get::<config::Value>("git.protected-branches") {
+            Ok(value) => match value.kind {
+                config::ValueKind::String(pattern) => Ok(vec![pattern]),
+                config::ValueKind::Array(values) => values
+                    .into_iter()
+                    .map(|value| value.into_string())
+                    .collect::<Result<_, _>>()
+                    .map_err(|err| {
+                        config::ConfigError::Message(format!("git.protected-branches: {err}"))
+                    }),
+                _ => Err(config::ConfigError::Message(format!(
+                    "git.protected-branches: expected a pattern or a list of patterns, got \
+                     {value}"
+                ))),
+            },
+            Err(config::ConfigError::NotFound(_)) => Ok(vec![]),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Whether `jj git push` may push commits with empty descriptions.
+    pub fn push_allow_empty_description(&self) -> bool {
+        self.config
+            .get_bool("push.allow-empty-description")
+            .unwrap_or(false)
+    }
+
+    /// Regexes for the first lines of descriptions that `jj git push`
+    /// refuses to push, like `^wip\b`.
+    pub fn push_wip_patterns(&self) -> Vec<String> {
+        self.config
+            .get_array("push.wip-patterns")
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|value| value.into_string().ok())
+            .collect()
+    }
+}
+
+/// The time set by `user.timestamp`, if any.
+fn timestamp_override(config: &config::Config) -> Option<Timestamp> {
+    let timestamp_str = config.get_string("user.timestamp").ok()?;
+    let datetime = DateTime::parse_from_rfc3339(&timestamp_str).ok()?;
+    Some(Timestamp::from_datetime(datetime))
+}
@@ -24,7 +24,7 @@ use itertools::Itertools;
 use protobuf::{Message, MessageField};
 use tempfile::{NamedTempFile, PersistError};
 
-use crate::backend::{CommitId, MillisSinceEpoch, Timestamp};
+use crate::backend::{ChangeId, CommitId, MillisSinceEpoch, Timestamp};
 use crate::file_util::persist_content_addressed_temp_file;
 use crate::op_store::{
     BranchTarget, OpStore, OpStoreError, OpStoreResult, Operation, OperationId, OperationMetadata,
@@ -245,6 +245,19 @@ fn view_to_proto(view: &View) -> crate::protos::op_store::View {
         proto.git_head = git_head.to_bytes();
     }
 
+    for (name, change_id) in &view.change_names {
+        proto
+            .change_names
+            .insert(name.clone(), change_id.to_bytes());
+    }
+
+    for (change_id, topic) in &view.change_topics {
+        let mut change_topic_proto = crate::protos::op_store::ChangeTopic::new();
+        change_topic_proto.change_id = change_id.to_bytes();
+        change_topic_proto.topic = topic.clone();
+        proto.change_topics.push(change_topic_proto);
+    }
+
     proto
 }
 
@@ -319,6 +332,18 @@ fn view_from_proto(proto: &crate::protos::op_store::View) -> View {
         view.git_head = Some(CommitId::new(proto.git_head.clone()));
     }
 
+    for (name, change_id) in &proto.change_names {
+        view.change_names
+            .insert(name.clone(), ChangeId::new(change_id.clone()));
+    }
+
+    for change_topic in &proto.change_topics {
+        view.change_topics.insert(
+            ChangeId::new(change_topic.change_id.clone()),
+            change_topic.topic.clone(),
+        );
+    }
+
     view
 }
 
@@ -418,6 +443,12 @@ mod tests {
                 WorkspaceId::default() => default_wc_commit_id,
                 WorkspaceId::new("test".to_string()) => test_wc_commit_id,
             },
+            change_names: btreemap! {
+                "fix-login".to_string() => ChangeId::from_hex("eee111"),
+            },
+            change_topics: btreemap! {
+                ChangeId::from_hex("eee111") => "login".to_string(),
+            },
         };
         let view_id = store.write_view(&view).unwrap();
         let read_view = store.read_view(&view_id).unwrap();
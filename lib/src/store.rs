@@ -12,37 +12,213 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::HashMap;
-use std::io::Read;
-use std::sync::{Arc, RwLock};
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use crate::backend;
 use crate::backend::{
-    Backend, BackendResult, CommitId, Conflict, ConflictId, FileId, SymlinkId, TreeId,
+    Backend, BackendResult, ChunkStats, CommitId, Conflict, ConflictId, FileId, ObjectKind,
+    SymlinkId, TreeId,
 };
 use crate::commit::Commit;
+use crate::lru_cache::{CacheStats, LruCache};
+use crate::merge_attributes::MergeAttributes;
 use crate::repo_path::RepoPath;
 use crate::tree::Tree;
 use crate::tree_builder::TreeBuilder;
 
+type FileCache = LruCache<(RepoPath, FileId), Arc<Vec<u8>>>;
+type VerifiedIds = LruCache<(ObjectKind, Vec<u8>), ()>;
+
 /// Wraps the low-level backend and makes it return more convenient types. Also
 /// adds the root commit and adds caching.
 #[derive(Debug)]
 pub struct Store {
     backend: Box<dyn Backend>,
-    commit_cache: RwLock<HashMap<CommitId, Arc<backend::Commit>>>,
-    tree_cache: RwLock<HashMap<(RepoPath, TreeId), Arc<backend::Tree>>>,
+    commit_cache: Mutex<LruCache<CommitId, Arc<backend::Commit>>>,
+    tree_cache: Mutex<LruCache<(RepoPath, TreeId), Arc<backend::Tree>>>,
+    file_cache: Mutex<FileCache>,
+    /// Ids of objects whose hashes have been verified, if reads are verified.
+    verified_ids: Option<Mutex<VerifiedIds>>,
+    fetch_stats: Mutex<BTreeMap<ObjectKind, FetchStats>>,
+    merge_attributes: MergeAttributes,
+}
+
+/// How many verified object ids are remembered when reads are verified.
+const VERIFIED_IDS_CAPACITY: usize = 100_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreOptions {
+    /// Check that objects hash to their ids when they're read, so corruption
+    /// on disk is detected early (as `BackendError::HashMismatch`).
+    pub verify_reads: bool,
+    /// Approximate number of bytes of commits to keep in memory.
+    pub commit_cache_size: usize,
+    /// Approximate number of bytes of trees to keep in memory.
+    pub tree_cache_size: usize,
+    /// Number of bytes of file contents to keep in memory. Files larger than
+    /// an eighth of it aren't cached, and no files are if it's 0.
+    pub file_cache_size: usize,
+    /// How file contents are merged when trees are merged.
+    pub merge_attributes: MergeAttributes,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        StoreOptions {
+            verify_reads: false,
+            commit_cache_size: 64 << 20,
+            tree_cache_size: 256 << 20,
+            file_cache_size: 64 << 20,
+            merge_attributes: MergeAttributes::default(),
+        }
+    }
+}
+
+/// How well the store's caches have worked so far. The cache weights are in
+/// (approximate) bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreCacheStats {
+    pub commits: CacheStats,
+    pub trees: CacheStats,
+    pub files: CacheStats,
+}
+
+/// Statistics about the objects of one kind that were read from the backend
+/// (i.e. that weren't found in the cache).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FetchStats {
+    pub count: u64,
+    /// The total time spent waiting for the backend. For files, this is the
+    /// time until the backend returned a reader for the contents.
+    pub total_time: Duration,
+    /// The time spent on the slowest fetch.
+    pub max_time: Duration,
+}
+
+impl FetchStats {
+    /// The average time per fetch, or `None` if there were none.
+    pub fn average_time(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(
+                self.total_time.as_secs_f64() / self.count as f64,
+            ))
+        }
+    }
+}
+
+/// How many objects the store has read from its backend so far, and how long
+/// it took, so slow backends (e.g. on network file systems) can be diagnosed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoreFetchStats {
+    /// The name of the backend (see `Backend::name()`).
+    pub backend: String,
+    /// Stats for each kind of object that has been fetched at least once.
+    pub objects: BTreeMap<ObjectKind, FetchStats>,
+}
+
+fn commit_weight(commit: &backend::Commit) -> usize {
+    let signature_weight =
+        |signature: &backend::Signature| signature.name.len() + signature.email.len() + 64;
+    std::mem::size_of::<backend::Commit>()
+        + (commit.parents.len() + commit.predecessors.len() + 2) * 64
+        + commit.description.len()
+        + signature_weight(&commit.author)
+        + signature_weight(&commit.committer)
+}
+
+fn tree_weight(tree: &backend::Tree) -> usize {
+    std::mem::size_of::<backend::Tree>()
+        + tree
+            .entries()
+            .map(|entry| entry.name().as_str().len() + 128)
+            .sum::<usize>()
 }
 
 impl Store {
     pub fn new(backend: Box<dyn Backend>) -> Arc<Self> {
+        Store::with_options(backend, StoreOptions::default())
+    }
+
+    pub fn with_options(backend: Box<dyn Backend>, options: StoreOptions) -> Arc<Self> {
         Arc::new(Store {
             backend,
-            commit_cache: Default::default(),
-            tree_cache: Default::default(),
+            commit_cache: Mutex::new(LruCache::new(options.commit_cache_size)),
+            tree_cache: Mutex::new(LruCache::new(options.tree_cache_size)),
+            file_cache: Mutex::new(LruCache::new(options.file_cache_size)),
+            verified_ids: options
+                .verify_reads
+                .then(|| Mutex::new(LruCache::new(VERIFIED_IDS_CAPACITY))),
+            fetch_stats: Mutex::new(BTreeMap::new()),
+            merge_attributes: options.merge_attributes,
         })
     }
 
+    pub fn merge_attributes(&self) -> &MergeAttributes {
+        &self.merge_attributes
+    }
+
+    pub fn verifies_reads(&self) -> bool {
+        self.verified_ids.is_some()
+    }
+
+    pub fn cache_stats(&self) -> StoreCacheStats {
+        StoreCacheStats {
+            commits: self.commit_cache.lock().unwrap().stats(),
+            trees: self.tree_cache.lock().unwrap().stats(),
+            files: self.file_cache.lock().unwrap().stats(),
+        }
+    }
+
+    pub fn fetch_stats(&self) -> StoreFetchStats {
+        StoreFetchStats {
+            backend: self.backend.name().to_string(),
+            objects: self.fetch_stats.lock().unwrap().clone(),
+        }
+    }
+
+    /// Calls `fetch` to read an object of the given kind from the backend and
+    /// records how long it took.
+    fn fetch<T>(
+        &self,
+        kind: ObjectKind,
+        fetch: impl FnOnce() -> BackendResult<T>,
+    ) -> BackendResult<T> {
+        let start = Instant::now();
+        let result = fetch();
+        let elapsed = start.elapsed();
+        let mut fetch_stats = self.fetch_stats.lock().unwrap();
+        let stats = fetch_stats.entry(kind).or_default();
+        stats.count += 1;
+        stats.total_time += elapsed;
+        stats.max_time = stats.max_time.max(elapsed);
+        result
+    }
+
+    fn verify(&self, kind: ObjectKind, id: &[u8]) -> BackendResult<()> {
+        if let Some(verified_ids) = &self.verified_ids {
+            let key = (kind, id.to_vec());
+            if verified_ids.lock().unwrap().get(&key).is_some() {
+                return Ok(());
+            }
+            self.backend.verify_object(kind, id)?;
+            verified_ids.lock().unwrap().insert(key, ());
+        }
+        Ok(())
+    }
+
+    pub fn backend(&self) -> &dyn Backend {
+        self.backend.as_ref()
+    }
+
+    pub fn backend_name(&self) -> &str {
+        self.backend.name()
+    }
+
     pub fn hash_length(&self) -> usize {
         self.backend.hash_length()
     }
@@ -69,27 +245,30 @@ impl Store {
     }
 
     fn get_backend_commit(&self, id: &CommitId) -> BackendResult<Arc<backend::Commit>> {
-        {
-            let read_locked_cached = self.commit_cache.read().unwrap();
-            if let Some(data) = read_locked_cached.get(id).cloned() {
-                return Ok(data);
-            }
+        if let Some(data) = self.commit_cache.lock().unwrap().get(id).cloned() {
+            return Ok(data);
         }
-        let commit = self.backend.read_commit(id)?;
+        self.verify(ObjectKind::Commit, id.as_bytes())?;
+        let commit = self.fetch(ObjectKind::Commit, || self.backend.read_commit(id))?;
+        let weight = commit_weight(&commit);
         let data = Arc::new(commit);
-        let mut write_locked_cache = self.commit_cache.write().unwrap();
-        write_locked_cache.insert(id.clone(), data.clone());
+        self.commit_cache
+            .lock()
+            .unwrap()
+            .insert_with_weight(id.clone(), data.clone(), weight);
         Ok(data)
     }
 
     pub fn write_commit(self: &Arc<Self>, commit: backend::Commit) -> Commit {
         assert!(!commit.parents.is_empty());
         let commit_id = self.backend.write_commit(&commit).unwrap();
+        let weight = commit_weight(&commit);
         let data = Arc::new(commit);
-        {
-            let mut write_locked_cache = self.commit_cache.write().unwrap();
-            write_locked_cache.insert(commit_id.clone(), data.clone());
-        }
+        self.commit_cache.lock().unwrap().insert_with_weight(
+            commit_id.clone(),
+            data.clone(),
+            weight,
+        );
 
         Commit::new(self.clone(), commit_id, data)
     }
@@ -101,15 +280,17 @@ impl Store {
 
     fn get_backend_tree(&self, dir: &RepoPath, id: &TreeId) -> BackendResult<Arc<backend::Tree>> {
         let key = (dir.clone(), id.clone());
-        {
-            let read_locked_cache = self.tree_cache.read().unwrap();
-            if let Some(data) = read_locked_cache.get(&key).cloned() {
-                return Ok(data);
-            }
+        if let Some(data) = self.tree_cache.lock().unwrap().get(&key).cloned() {
+            return Ok(data);
         }
-        let data = Arc::new(self.backend.read_tree(dir, id)?);
-        let mut write_locked_cache = self.tree_cache.write().unwrap();
-        write_locked_cache.insert(key, data.clone());
+        self.verify(ObjectKind::Tree, id.as_bytes())?;
+        let tree = self.fetch(ObjectKind::Tree, || self.backend.read_tree(dir, id))?;
+        let weight = tree_weight(&tree);
+        let data = Arc::new(tree);
+        self.tree_cache
+            .lock()
+            .unwrap()
+            .insert_with_weight(key, data.clone(), weight);
         Ok(data)
     }
 
@@ -119,7 +300,36 @@ impl Store {
     }
 
     pub fn read_file(&self, path: &RepoPath, id: &FileId) -> BackendResult<Box<dyn Read>> {
-        self.backend.read_file(path, id)
+        let mut file_cache = self.file_cache.lock().unwrap();
+        if file_cache.capacity() == 0 {
+            drop(file_cache);
+            self.verify(ObjectKind::File, id.as_bytes())?;
+            return self.fetch(ObjectKind::File, || self.backend.read_file(path, id));
+        }
+        let key = (path.clone(), id.clone());
+        if let Some(data) = file_cache.get(&key).cloned() {
+            return Ok(Box::new(Cursor::new(SharedBytes(data))));
+        }
+        drop(file_cache);
+        self.verify(ObjectKind::File, id.as_bytes())?;
+        // Large files are streamed instead of being read into memory.
+        let max_cached_size = self.file_cache.lock().unwrap().capacity() / 8;
+        let mut reader = self.fetch(ObjectKind::File, || self.backend.read_file(path, id))?;
+        let mut contents = vec![];
+        reader
+            .by_ref()
+            .take(max_cached_size as u64 + 1)
+            .read_to_end(&mut contents)?;
+        if contents.len() > max_cached_size {
+            return Ok(Box::new(Cursor::new(contents).chain(reader)));
+        }
+        let weight = contents.len();
+        let data = Arc::new(contents);
+        self.file_cache
+            .lock()
+            .unwrap()
+            .insert_with_weight(key, data.clone(), weight);
+        Ok(Box::new(Cursor::new(SharedBytes(data))))
     }
 
     pub fn write_file(&self, path: &RepoPath, contents: &mut dyn Read) -> BackendResult<FileId> {
@@ -127,7 +337,8 @@ impl Store {
     }
 
     pub fn read_symlink(&self, path: &RepoPath, id: &SymlinkId) -> BackendResult<String> {
-        self.backend.read_symlink(path, id)
+        self.verify(ObjectKind::Symlink, id.as_bytes())?;
+        self.fetch(ObjectKind::Symlink, || self.backend.read_symlink(path, id))
     }
 
     pub fn write_symlink(&self, path: &RepoPath, contents: &str) -> BackendResult<SymlinkId> {
@@ -135,7 +346,10 @@ impl Store {
     }
 
     pub fn read_conflict(&self, path: &RepoPath, id: &ConflictId) -> BackendResult<Conflict> {
-        self.backend.read_conflict(path, id)
+        self.verify(ObjectKind::Conflict, id.as_bytes())?;
+        self.fetch(ObjectKind::Conflict, || {
+            self.backend.read_conflict(path, id)
+        })
     }
 
     pub fn write_conflict(
@@ -146,7 +360,26 @@ impl Store {
         self.backend.write_conflict(path, contents)
     }
 
+    /// See `Backend::pack_loose_objects()`.
+    pub fn pack_loose_objects(&self, min_count: usize) -> BackendResult<usize> {
+        self.backend.pack_loose_objects(min_count)
+    }
+
+    /// See `Backend::chunk_stats()`.
+    pub fn chunk_stats(&self) -> BackendResult<Option<ChunkStats>> {
+        self.backend.chunk_stats()
+    }
+
     pub fn tree_builder(self: &Arc<Self>, base_tree_id: TreeId) -> TreeBuilder {
         TreeBuilder::new(self.clone(), base_tree_id)
     }
 }
+
+/// File contents shared with the cache.
+struct SharedBytes(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for SharedBytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
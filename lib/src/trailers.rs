@@ -0,0 +1,191 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parsing of trailers (`Key: value` lines at the end of a commit
+//! description), and conversion between `Co-authored-by:` trailers and
+//! structured co-authors.
+
+use crate::backend::CoAuthor;
+
+/// The name of the trailer Git hosts use for co-authors.
+pub const CO_AUTHORED_BY_TRAILER: &str = "Co-authored-by";
+
+/// Returns the lines of the last paragraph of `description` if it looks like a
+/// block of trailers (`Key: value` lines). The subject line is never a trailer.
+pub fn trailer_lines(description: &str) -> impl Iterator<Item = &str> {
+    let description = description.trim_end();
+    let last_paragraph = match description.rsplit_once("\n\n") {
+        Some((_, paragraph)) => paragraph,
+        None => "",
+    };
+    let lines = if last_paragraph.lines().all(is_trailer) {
+        last_paragraph
+    } else {
+        ""
+    };
+    lines.lines()
+}
+
+fn is_trailer(line: &str) -> bool {
+    match line.split_once(": ") {
+        Some((key, _)) => {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        }
+        None => false,
+    }
+}
+
+/// Parses "Some One <some.one@example.com>".
+pub fn parse_co_author(value: &str) -> Option<CoAuthor> {
+    let (name, rest) = value.trim().split_once(" <")?;
+    let email = rest.strip_suffix('>')?;
+    if name.trim().is_empty() || email.is_empty() || email.contains(['<', '>']) {
+        return None;
+    }
+    Some(CoAuthor {
+        name: name.trim().to_string(),
+        email: email.to_string(),
+    })
+}
+
+/// Appends a `Co-authored-by:` trailer for each co-author to `description`.
+/// The trailers are added to the trailer block at the end of the description
+/// if there is one, otherwise in a new paragraph.
+pub fn add_co_author_trailers(description: &str, co_authors: &[CoAuthor]) -> String {
+    if co_authors.is_empty() {
+        return description.to_string();
+    }
+    let mut result = description.trim_end().to_string();
+    if !result.is_empty() {
+        if trailer_lines(description).next().is_some() {
+            result.push('\n');
+        } else {
+            result.push_str("\n\n");
+        }
+    }
+    for co_author in co_authors {
+        result.push_str(&format!(
+            "{CO_AUTHORED_BY_TRAILER}: {} <{}>\n",
+            co_author.name, co_author.email
+        ));
+    }
+    result
+}
+
+/// Splits the `Co-authored-by:` trailers at the end of `message` from the rest
+/// of it. This is the inverse of `add_co_author_trailers()`: the trailers are
+/// only split off if adding them back results in the same message, so the
+/// message can always be reconstructed exactly.
+pub fn split_co_author_trailers(message: &str) -> (String, Vec<CoAuthor>) {
+    let lines = trailer_lines(message).collect::<Vec<_>>();
+    let mut co_authors = vec![];
+    for line in lines.iter().rev() {
+        let co_author = line
+            .strip_prefix(CO_AUTHORED_BY_TRAILER)
+            .and_then(|rest| rest.strip_prefix(": "))
+            .and_then(parse_co_author);
+        match co_author {
+            Some(co_author) => co_authors.push(co_author),
+            None => break,
+        }
+    }
+    if co_authors.is_empty() {
+        return (message.to_string(), vec![]);
+    }
+    co_authors.reverse();
+    let num_other_lines = lines.len() - co_authors.len();
+    let paragraph_start = message.trim_end().rfind("\n\n").unwrap() + 2;
+    let description = if num_other_lines == 0 {
+        message[..paragraph_start - 1].to_string()
+    } else {
+        let kept = lines[..num_other_lines].join("\n");
+        format!("{}{kept}\n", &message[..paragraph_start])
+    };
+    if add_co_author_trailers(&description, &co_authors) == message {
+        (description, co_authors)
+    } else {
+        (message.to_string(), vec![])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn co_author(name: &str, email: &str) -> CoAuthor {
+        CoAuthor {
+            name: name.to_string(),
+            email: email.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_co_author() {
+        assert_eq!(
+            parse_co_author("Some One <some.one@example.com>"),
+            Some(co_author("Some One", "some.one@example.com"))
+        );
+        assert_eq!(parse_co_author("some.one@example.com"), None);
+        assert_eq!(parse_co_author(" <some.one@example.com>"), None);
+        assert_eq!(parse_co_author("Some One <>"), None);
+    }
+
+    #[test]
+    fn test_add_co_author_trailers() {
+        let alice = co_author("Alice", "alice@example.com");
+        let bob = co_author("Bob", "bob@example.com");
+        assert_eq!(add_co_author_trailers("subject\n", &[]), "subject\n");
+        assert_eq!(
+            add_co_author_trailers("", &[alice.clone()]),
+            "Co-authored-by: Alice <alice@example.com>\n"
+        );
+        assert_eq!(
+            add_co_author_trailers("subject\n\nbody\n", &[alice.clone(), bob]),
+            "subject\n\nbody\n\nCo-authored-by: Alice <alice@example.com>\nCo-authored-by: Bob \
+             <bob@example.com>\n"
+        );
+        assert_eq!(
+            add_co_author_trailers("subject\n\nBug: 123\n", &[alice]),
+            "subject\n\nBug: 123\nCo-authored-by: Alice <alice@example.com>\n"
+        );
+    }
+
+    #[test]
+    fn test_split_co_author_trailers() {
+        let alice = co_author("Alice", "alice@example.com");
+        let bob = co_author("Bob", "bob@example.com");
+        let split = |message: &str| split_co_author_trailers(message);
+        assert_eq!(split("subject\n"), ("subject\n".to_string(), vec![]));
+        assert_eq!(
+            split("subject\n\nCo-authored-by: Alice <alice@example.com>\n"),
+            ("subject\n".to_string(), vec![alice.clone()])
+        );
+        assert_eq!(
+            split(
+                "subject\n\nBug: 123\nCo-authored-by: Alice <alice@example.com>\nCo-authored-by: \
+                 Bob <bob@example.com>\n"
+            ),
+            ("subject\n\nBug: 123\n".to_string(), vec![alice, bob])
+        );
+        // Co-authors before other trailers are left in the description, since
+        // they couldn't be added back in the same place
+        let message = "subject\n\nCo-authored-by: Alice <alice@example.com>\nBug: 123\n";
+        assert_eq!(split(message), (message.to_string(), vec![]));
+        // So are co-authors that wouldn't be written the same way
+        let message = "subject\n\nCo-authored-by: Alice <alice@example.com>";
+        assert_eq!(split(message), (message.to_string(), vec![]));
+        let message = "subject\n\n\nCo-authored-by: Alice <alice@example.com>\n";
+        assert_eq!(split(message), (message.to_string(), vec![]));
+    }
+}
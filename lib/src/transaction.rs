@@ -17,6 +17,8 @@ use std::sync::Arc;
 
 use crate::backend::Timestamp;
 use crate::dag_walk::closest_common_node;
+use crate::hooks;
+use crate::hooks::{Hook, HookError};
 use crate::index::ReadonlyIndex;
 use crate::op_store;
 use crate::op_store::OperationMetadata;
@@ -60,6 +62,12 @@ impl Transaction {
         self.repo.as_mut().unwrap()
     }
 
+    /// Runs `hooks` on the commits added by this transaction. The transaction
+    /// should not be committed if any of them rejects it.
+    pub fn run_hooks(&self, hooks: &[Hook]) -> Result<(), HookError> {
+        hooks::run_hooks(hooks, self.repo.as_ref().unwrap())
+    }
+
     pub fn merge_operation(&mut self, other_op: Operation) {
         let ancestor_op = closest_common_node(
             self.parent_ops.clone(),
@@ -17,17 +17,19 @@ use std::fmt::{Debug, Error, Formatter};
 use std::io::Read;
 use std::iter::Peekable;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use itertools::Itertools;
+use rayon::prelude::*;
 use thiserror::Error;
 
 use crate::backend::{
     BackendError, Conflict, ConflictId, ConflictPart, FileId, TreeEntriesNonRecursiveIterator,
     TreeEntry, TreeId, TreeValue,
 };
-use crate::files::MergeResult;
+use crate::files::{MergeOptions, MergeResult};
 use crate::matchers::{EverythingMatcher, Matcher};
+use crate::merge_attributes::MergeStrategy;
 use crate::repo_path::{RepoPath, RepoPathComponent, RepoPathJoin};
 use crate::store::Store;
 use crate::{backend, files};
@@ -189,17 +191,41 @@ impl Tree {
         recursive_tree_diff(self.clone(), other.clone(), matcher)
     }
 
+    /// Returns the same diffs in the same order as `diff()`, but diffs
+    /// independent subdirectories in parallel. This is faster for large trees
+    /// where the changes are spread over many directories.
+    pub fn diff_parallel(
+        &self,
+        other: &Tree,
+        matcher: &dyn Matcher,
+    ) -> Vec<(RepoPath, Diff<TreeValue>)> {
+        parallel_tree_diff(&RepoPath::root(), self, other, matcher)
+    }
+
+    /// Diffs the trees in parallel like `diff_parallel()`, but calls `callback`
+    /// with each diff as soon as it's found, so the diffs are not sorted by
+    /// path. `callback` may be called from several threads at once.
+    pub fn diff_unordered(
+        &self,
+        other: &Tree,
+        matcher: &dyn Matcher,
+        callback: impl Fn(RepoPath, Diff<TreeValue>) + Sync,
+    ) {
+        unordered_tree_diff(&RepoPath::root(), self, other, matcher, &callback);
+    }
+
     pub fn diff_summary(&self, other: &Tree, matcher: &dyn Matcher) -> DiffSummary {
-        let mut modified = vec![];
-        let mut added = vec![];
-        let mut removed = vec![];
-        for (file, diff) in self.diff(other, matcher) {
+        let summary = Mutex::new((vec![], vec![], vec![]));
+        self.diff_unordered(other, matcher, |file, diff| {
+            let mut summary = summary.lock().unwrap();
+            let (modified, added, removed) = &mut *summary;
             match diff {
-                Diff::Modified(_, _) => modified.push(file.clone()),
-                Diff::Added(_) => added.push(file.clone()),
-                Diff::Removed(_) => removed.push(file.clone()),
+                Diff::Modified(_, _) => modified.push(file),
+                Diff::Added(_) => added.push(file),
+                Diff::Removed(_) => removed.push(file),
             }
-        }
+        });
+        let (mut modified, mut added, mut removed) = summary.into_inner().unwrap();
         modified.sort();
         added.sort();
         removed.sort();
@@ -406,7 +432,7 @@ pub struct TreeDiffIterator<'matcher> {
     // This is used for making sure that when a directory gets replaced by a file, we
     // yield the value for the addition of the file after we yield the values
     // for removing files in the directory.
-    added_file: Option<(RepoPath, TreeValue)>,
+    added_file: Option<(RepoPath, Diff<TreeValue>)>,
     // Iterator over the diffs of a subdirectory, if we're currently visiting one.
     subdir_iterator: Option<Box<TreeDiffIterator<'matcher>>>,
 }
@@ -448,27 +474,21 @@ impl Iterator for TreeDiffIterator<'_> {
                 self.subdir_iterator = None;
             }
 
-            if let Some((name, value)) = self.added_file.take() {
-                return Some((name, Diff::Added(value)));
+            if let Some(diff) = self.added_file.take() {
+                return Some(diff);
             }
 
-            // Note: whenever we say "file" below, it may also be a symlink or a conflict.
             let (name, before, after) = self.entry_iterator.next()?;
-            let tree_before = matches!(before, Some(TreeValue::Tree(_)));
-            let tree_after = matches!(after, Some(TreeValue::Tree(_)));
-            if tree_before || tree_after {
-                let subdir = &name;
-                let subdir_path = self.dir.join(subdir);
-                let before_tree = match before {
-                    Some(TreeValue::Tree(id_before)) => {
-                        self.tree1.known_sub_tree(subdir, id_before)
-                    }
-                    _ => Tree::null(self.tree1.store().clone(), subdir_path.clone()),
-                };
-                let after_tree = match after {
-                    Some(TreeValue::Tree(id_after)) => self.tree2.known_sub_tree(subdir, id_after),
-                    _ => Tree::null(self.tree2.store().clone(), subdir_path.clone()),
-                };
+            let entry_diff = EntryDiff::new(
+                &self.dir,
+                &self.tree1,
+                &self.tree2,
+                &name,
+                before,
+                after,
+                self.matcher,
+            );
+            if let Some((subdir_path, before_tree, after_tree)) = entry_diff.subdir {
                 self.subdir_iterator = Some(Box::new(TreeDiffIterator::new(
                     subdir_path,
                     before_tree,
@@ -476,40 +496,143 @@ impl Iterator for TreeDiffIterator<'_> {
                     self.matcher,
                 )));
             }
-            let file_path = self.dir.join(&name);
-            if self.matcher.matches(&file_path) {
-                if !tree_before && tree_after {
-                    if let Some(file_before) = before {
-                        return Some((file_path, Diff::Removed(file_before.clone())));
-                    }
-                } else if tree_before && !tree_after {
-                    if let Some(file_after) = after {
-                        self.added_file = Some((file_path, file_after.clone()));
+            self.added_file = entry_diff.diff_after_subdir;
+            if let Some(diff) = entry_diff.diff_before_subdir {
+                return Some(diff);
+            }
+        }
+    }
+}
+
+/// The differences caused by a single entry that differs between two trees.
+struct EntryDiff {
+    /// The diff of the file at the entry's path, unless it should be reported
+    /// after the subdirectory's diffs.
+    diff_before_subdir: Option<(RepoPath, Diff<TreeValue>)>,
+    /// The subdirectory to diff recursively, if the entry is a directory on
+    /// either side.
+    subdir: Option<(RepoPath, Tree, Tree)>,
+    /// The diff of a file that replaced a directory. This is reported after
+    /// the removals of the files in the directory.
+    diff_after_subdir: Option<(RepoPath, Diff<TreeValue>)>,
+}
+
+impl EntryDiff {
+    fn new(
+        dir: &RepoPath,
+        tree1: &Tree,
+        tree2: &Tree,
+        name: &RepoPathComponent,
+        before: Option<&TreeValue>,
+        after: Option<&TreeValue>,
+        matcher: &dyn Matcher,
+    ) -> Self {
+        let mut entry_diff = EntryDiff {
+            diff_before_subdir: None,
+            subdir: None,
+            diff_after_subdir: None,
+        };
+        // Note: whenever we say "file" below, it may also be a symlink or a conflict.
+        let tree_before = matches!(before, Some(TreeValue::Tree(_)));
+        let tree_after = matches!(after, Some(TreeValue::Tree(_)));
+        if tree_before || tree_after {
+            let subdir_path = dir.join(name);
+            let before_tree = match before {
+                Some(TreeValue::Tree(id_before)) => tree1.known_sub_tree(name, id_before),
+                _ => Tree::null(tree1.store().clone(), subdir_path.clone()),
+            };
+            let after_tree = match after {
+                Some(TreeValue::Tree(id_after)) => tree2.known_sub_tree(name, id_after),
+                _ => Tree::null(tree2.store().clone(), subdir_path.clone()),
+            };
+            entry_diff.subdir = Some((subdir_path, before_tree, after_tree));
+        }
+        let file_path = dir.join(name);
+        if matcher.matches(&file_path) {
+            if !tree_before && tree_after {
+                if let Some(file_before) = before {
+                    entry_diff.diff_before_subdir =
+                        Some((file_path, Diff::Removed(file_before.clone())));
+                }
+            } else if tree_before && !tree_after {
+                if let Some(file_after) = after {
+                    entry_diff.diff_after_subdir =
+                        Some((file_path, Diff::Added(file_after.clone())));
+                }
+            } else if !tree_before && !tree_after {
+                let diff = match (before, after) {
+                    (Some(file_before), Some(file_after)) => {
+                        Diff::Modified(file_before.clone(), file_after.clone())
                     }
-                } else if !tree_before && !tree_after {
-                    match (before, after) {
-                        (Some(file_before), Some(file_after)) => {
-                            return Some((
-                                file_path,
-                                Diff::Modified(file_before.clone(), file_after.clone()),
-                            ));
-                        }
-                        (None, Some(file_after)) => {
-                            return Some((file_path, Diff::Added(file_after.clone())));
-                        }
-                        (Some(file_before), None) => {
-                            return Some((file_path, Diff::Removed(file_before.clone())));
-                        }
-                        (None, None) => {
-                            panic!("unexpected diff")
-                        }
+                    (None, Some(file_after)) => Diff::Added(file_after.clone()),
+                    (Some(file_before), None) => Diff::Removed(file_before.clone()),
+                    (None, None) => {
+                        panic!("unexpected diff")
                     }
-                }
+                };
+                entry_diff.diff_before_subdir = Some((file_path, diff));
             }
         }
+        entry_diff
     }
 }
 
+/// Like `recursive_tree_diff()`, but diffs the subdirectories of each
+/// directory in parallel (on rayon's thread pool, which is bounded by the
+/// number of CPUs). The result is in the same order as `recursive_tree_diff()`.
+fn parallel_tree_diff(
+    dir: &RepoPath,
+    tree1: &Tree,
+    tree2: &Tree,
+    matcher: &dyn Matcher,
+) -> Vec<(RepoPath, Diff<TreeValue>)> {
+    let entries = diff_entries(tree1, tree2, matcher).collect_vec();
+    let per_entry: Vec<Vec<_>> = entries
+        .par_iter()
+        .map(|(name, before, after)| {
+            let entry_diff = EntryDiff::new(dir, tree1, tree2, name, *before, *after, matcher);
+            let mut diffs = vec![];
+            diffs.extend(entry_diff.diff_before_subdir);
+            if let Some((subdir_path, before_tree, after_tree)) = &entry_diff.subdir {
+                diffs.extend(parallel_tree_diff(
+                    subdir_path,
+                    before_tree,
+                    after_tree,
+                    matcher,
+                ));
+            }
+            diffs.extend(entry_diff.diff_after_subdir);
+            diffs
+        })
+        .collect();
+    per_entry.into_iter().flatten().collect()
+}
+
+/// Like `parallel_tree_diff()`, but passes each diff to `callback` as soon as
+/// it's found instead of collecting them in order.
+fn unordered_tree_diff(
+    dir: &RepoPath,
+    tree1: &Tree,
+    tree2: &Tree,
+    matcher: &dyn Matcher,
+    callback: &(dyn Fn(RepoPath, Diff<TreeValue>) + Sync),
+) {
+    let entries = diff_entries(tree1, tree2, matcher).collect_vec();
+    entries.par_iter().for_each(|(name, before, after)| {
+        let entry_diff = EntryDiff::new(dir, tree1, tree2, name, *before, *after, matcher);
+        for (path, diff) in entry_diff
+            .diff_before_subdir
+            .into_iter()
+            .chain(entry_diff.diff_after_subdir)
+        {
+            callback(path, diff);
+        }
+        if let Some((subdir_path, before_tree, after_tree)) = &entry_diff.subdir {
+            unordered_tree_diff(subdir_path, before_tree, after_tree, matcher, callback);
+        }
+    });
+}
+
 pub fn merge_trees(
     side1_tree: &Tree,
     base_tree: &Tree,
@@ -569,7 +692,7 @@ fn maybe_tree_id<'id>(
     }
 }
 
-fn merge_tree_value(
+pub(crate) fn merge_tree_value(
     store: &Arc<Store>,
     dir: &RepoPath,
     basename: &RepoPathComponent,
@@ -620,28 +743,55 @@ fn merge_tree_value(
                 });
             }
             let filename = dir.join(basename);
-            let conflict = simplify_conflict(store, &filename, &conflict)?;
-            if conflict.adds.is_empty() {
-                // If there are no values to add, then the path doesn't exist
-                return Ok(None);
-            }
-            if conflict.removes.is_empty() && conflict.adds.len() == 1 {
-                // A single add means that the current state is that state.
-                return Ok(Some(conflict.adds[0].value.clone()));
-            }
-            if let Some((merged_content, executable)) =
-                try_resolve_file_conflict(store, &filename, &conflict)?
-            {
-                let id = store.write_file(&filename, &mut merged_content.as_slice())?;
-                Some(TreeValue::Normal { id, executable })
-            } else {
-                let conflict_id = store.write_conflict(&filename, &conflict)?;
-                Some(TreeValue::Conflict(conflict_id))
-            }
+            resolve_conflict(store, &filename, &conflict)?
         }
     })
 }
 
+/// Simplifies a conflict and resolves it if possible. Returns the resulting
+/// value, or `None` if the path doesn't exist after resolving it.
+fn resolve_conflict(
+    store: &Store,
+    filename: &RepoPath,
+    conflict: &Conflict,
+) -> Result<Option<TreeValue>, TreeMergeError> {
+    let conflict = simplify_conflict(store, filename, conflict)?;
+    if conflict.adds.is_empty() {
+        // If there are no values to add, then the path doesn't exist
+        return Ok(None);
+    }
+    if conflict.removes.is_empty() && conflict.adds.len() == 1 {
+        // A single add means that the current state is that state.
+        return Ok(Some(conflict.adds[0].value.clone()));
+    }
+    if let Some((merged_content, executable)) =
+        try_resolve_file_conflict(store, filename, &conflict)?
+    {
+        let id = store.write_file(filename, &mut merged_content.as_slice())?;
+        Ok(Some(TreeValue::Normal { id, executable }))
+    } else {
+        let conflict_id = store.write_conflict(filename, &conflict)?;
+        Ok(Some(TreeValue::Conflict(conflict_id)))
+    }
+}
+
+/// Simplifies the conflicts in a tree, resolving the ones that can be resolved
+/// now, e.g. because all sides became identical or because of the merge
+/// attributes. Returns the id of the resulting tree.
+pub fn simplify_conflicts(tree: &Tree) -> Result<TreeId, TreeMergeError> {
+    let store = tree.store();
+    let mut tree_builder = store.tree_builder(tree.id().clone());
+    for (path, conflict_id) in tree.conflicts() {
+        let conflict = store.read_conflict(&path, &conflict_id)?;
+        match resolve_conflict(store, &path, &conflict)? {
+            None => tree_builder.remove(path),
+            Some(TreeValue::Conflict(new_conflict_id)) if new_conflict_id == conflict_id => {}
+            Some(value) => tree_builder.set(path, value),
+        }
+    }
+    Ok(tree_builder.write_tree())
+}
+
 fn try_resolve_file_conflict(
     store: &Store,
     filename: &RepoPath,
@@ -717,9 +867,53 @@ fn try_resolve_file_conflict(
             })?;
         added_contents.push(content);
     }
-    let merge_result = files::merge(
+    let attribute = store.merge_attributes().for_path(filename);
+    // Merge drivers and the "ours" and "theirs" strategies only do 3-way
+    // merges. A missing base (both sides added the file) is passed to drivers
+    // as an empty file.
+    if let Some(attribute) = attribute {
+        if removed_contents.len() <= 1 && added_contents.len() == 2 {
+            if attribute.driver.is_some() {
+                let base = removed_contents.first().map_or(&[][..], Vec::as_slice);
+                match attribute.run_driver(filename, base, &added_contents[0], &added_contents[1]) {
+                    Ok(merged_content) => {
+                        return Ok(
+                            merged_content.map(|merged_content| (merged_content, executable))
+                        );
+                    }
+                    Err(err) => {
+                        // Rebasing can't fail, so the file is left conflicted.
+                        // TODO: Move this message to a callback or something.
+                        eprintln!(
+                            "Warning: {err}. Leaving {} conflicted.",
+                            filename.to_internal_file_string()
+                        );
+                        return Ok(None);
+                    }
+                }
+            }
+            match attribute.strategy {
+                Some(MergeStrategy::Ours) => {
+                    return Ok(Some((added_contents.swap_remove(0), executable)));
+                }
+                Some(MergeStrategy::Theirs) => {
+                    return Ok(Some((added_contents.swap_remove(1), executable)));
+                }
+                Some(MergeStrategy::Union) | None => {}
+            }
+        }
+    }
+    let merge_options = match attribute {
+        Some(attribute) => MergeOptions {
+            ignore_whitespace: attribute.ignore_whitespace,
+            union: attribute.strategy == Some(MergeStrategy::Union),
+        },
+        None => MergeOptions::default(),
+    };
+    let merge_result = files::merge_with_options(
         &removed_contents.iter().map(Vec::as_slice).collect_vec(),
         &added_contents.iter().map(Vec::as_slice).collect_vec(),
+        &merge_options,
     );
     match merge_result {
         MergeResult::Resolved(merged_content) => Ok(Some((merged_content, executable))),
@@ -825,9 +1019,17 @@ fn simplify_conflict(
         }
     }
 
+    // If all sides made the same change, that's the result. E.g. {+B-A+B}
+    // (both sides changed A to B) becomes just B.
+    if new_adds.len() == new_removes.len() + 1
+        && new_adds.iter().all(|add| add.value == new_adds[0].value)
+    {
+        new_adds.truncate(1);
+        new_removes.clear();
+    }
+
     // TODO: We should probably remove duplicate entries here too. So if we have
-    // {+A+A}, that would become just {+A}. Similarly {+B-A+B} would be just
-    // {+B-A}.
+    // {+A+A}, that would become just {+A}.
 
     Ok(Conflict {
         adds: new_adds,
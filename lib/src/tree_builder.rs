@@ -15,11 +15,15 @@
 use std::collections::{BTreeMap, HashSet};
 use std::sync::Arc;
 
+use itertools::Itertools;
+use rayon::prelude::*;
+
 use crate::backend;
 use crate::backend::{TreeId, TreeValue};
+use crate::matchers::Matcher;
 use crate::repo_path::{RepoPath, RepoPathJoin};
 use crate::store::Store;
-use crate::tree::Tree;
+use crate::tree::{merge_tree_value, Tree, TreeMergeError};
 
 #[derive(Debug)]
 enum Override {
@@ -60,6 +64,97 @@ impl TreeBuilder {
         self.overrides.insert(path, Override::Tombstone);
     }
 
+    fn base_tree(&self) -> Tree {
+        self.store
+            .get_tree(&RepoPath::root(), &self.base_tree_id)
+            .unwrap()
+    }
+
+    /// The value at `path` in the tree that would currently be written.
+    fn current_value(&self, base_tree: &Tree, path: &RepoPath) -> Option<TreeValue> {
+        match self.overrides.get(path) {
+            Some(Override::Replace(value)) => Some(value.clone()),
+            Some(Override::Tombstone) => None,
+            None => base_tree.path_value(path),
+        }
+    }
+
+    /// Removes the files matching `matcher`.
+    pub fn remove_matching(&mut self, matcher: &dyn Matcher) {
+        let overridden_paths = self
+            .overrides
+            .keys()
+            .filter(|path| matcher.matches(path))
+            .cloned()
+            .collect_vec();
+        for path in overridden_paths {
+            self.remove(path);
+        }
+        for (path, _value) in self.base_tree().entries_matching(matcher) {
+            self.remove(path);
+        }
+    }
+
+    /// Makes the files matching `matcher` have the values they have in
+    /// `source`, including any conflicts, and removes the ones that aren't in
+    /// `source`.
+    pub fn graft(&mut self, source: &Tree, matcher: &dyn Matcher) {
+        let overridden_paths = self
+            .overrides
+            .keys()
+            .filter(|path| matcher.matches(path))
+            .cloned()
+            .collect_vec();
+        for path in overridden_paths {
+            match source.path_value(&path) {
+                Some(value) => self.set(path, value),
+                None => self.remove(path),
+            }
+        }
+        for (path, diff) in self.base_tree().diff(source, matcher) {
+            match diff.into_options().1 {
+                Some(value) => self.set(path, value),
+                None => self.remove(path),
+            }
+        }
+    }
+
+    /// Applies the changes from `from` to `to` in the files matching `matcher`.
+    /// Where a file has also been changed in a different way here, the changes
+    /// are merged, which may result in a conflict.
+    pub fn apply_diff(
+        &mut self,
+        from: &Tree,
+        to: &Tree,
+        matcher: &dyn Matcher,
+    ) -> Result<(), TreeMergeError> {
+        let base_tree = self.base_tree();
+        for (path, diff) in from.diff(to, matcher) {
+            let (from_value, to_value) = diff.into_options();
+            let current_value = self.current_value(&base_tree, &path);
+            let new_value = if current_value == from_value {
+                to_value
+            } else if current_value == to_value {
+                continue;
+            } else {
+                let (dir, basename) = path.split().unwrap();
+                merge_tree_value(
+                    &self.store,
+                    &dir,
+                    basename,
+                    from_value.as_ref(),
+                    current_value.as_ref(),
+                    to_value.as_ref(),
+                )?
+            };
+            match new_value {
+                Some(value) => self.set(path, value),
+                None => self.remove(path),
+            }
+        }
+        Ok(())
+    }
+
     pub fn write_tree(mut self) -> TreeId {
         let mut trees_to_write = self.get_base_trees();
         if trees_to_write.is_empty() {
@@ -81,7 +176,9 @@ impl TreeBuilder {
             }
         }
 
-        // Write trees level by level, starting with trees without children.
+        // Write trees level by level, starting with trees without children. The
+        // trees on a level don't depend on each other, so they're written in
+        // parallel.
         let store = self.store.as_ref();
         loop {
             let mut dirs_to_write: HashSet<RepoPath> =
@@ -93,20 +190,30 @@ impl TreeBuilder {
                 }
             }
 
-            for dir in dirs_to_write {
-                let tree = trees_to_write.remove(&dir).unwrap();
+            let root = RepoPath::root();
+            if dirs_to_write.contains(&root) {
+                // We're writing the root tree. Write it even if empty. Return its id.
+                let tree = trees_to_write.remove(&root).unwrap();
+                return store.write_tree(&root, &tree).unwrap();
+            }
 
-                if let Some((parent, basename)) = dir.split() {
-                    let parent_tree = trees_to_write.get_mut(&parent).unwrap();
-                    if tree.is_empty() {
-                        parent_tree.remove(basename);
-                    } else {
-                        let tree_id = store.write_tree(&dir, &tree).unwrap();
-                        parent_tree.set(basename.clone(), TreeValue::Tree(tree_id));
-                    }
-                } else {
-                    // We're writing the root tree. Write it even if empty. Return its id.
-                    return store.write_tree(&dir, &tree).unwrap();
+            let trees = dirs_to_write
+                .into_iter()
+                .map(|dir| {
+                    let tree = trees_to_write.remove(&dir).unwrap();
+                    (dir, tree)
+                })
+                .collect_vec();
+            let tree_ids: Vec<Option<TreeId>> = trees
+                .par_iter()
+                .map(|(dir, tree)| (!tree.is_empty()).then(|| store.write_tree(dir, tree).unwrap()))
+                .collect();
+            for ((dir, _), tree_id) in trees.iter().zip(tree_ids) {
+                let (parent, basename) = dir.split().unwrap();
+                let parent_tree = trees_to_write.get_mut(&parent).unwrap();
+                match tree_id {
+                    Some(tree_id) => parent_tree.set(basename.clone(), TreeValue::Tree(tree_id)),
+                    None => parent_tree.remove(basename),
                 }
             }
         }
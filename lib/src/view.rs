@@ -16,11 +16,11 @@ use std::collections::{BTreeMap, HashMap, HashSet};
 
 use itertools::Itertools;
 
-use crate::backend::CommitId;
+use crate::backend::{ChangeId, CommitId};
 use crate::index::IndexRef;
 use crate::op_store;
 use crate::op_store::{BranchTarget, RefTarget, WorkspaceId};
-use crate::refs::merge_ref_targets;
+use crate::refs::{merge_ref_targets, RefNamePattern};
 
 #[derive(PartialEq, Eq, Clone, Hash, Debug)]
 pub enum RefName {
@@ -76,6 +76,17 @@ impl View {
         &self.data.branches
     }
 
+    /// Iterates over the branches whose names match any of the patterns. All
+    /// branches match if no patterns are given.
+    pub fn branches_matching<'a>(
+        &'a self,
+        patterns: &'a [RefNamePattern],
+    ) -> impl Iterator<Item = (&'a String, &'a BranchTarget)> + 'a {
+        self.data.branches.iter().filter(move |(name, _target)| {
+            patterns.is_empty() || patterns.iter().any(|pattern| pattern.matches(name))
+        })
+    }
+
     pub fn tags(&self) -> &BTreeMap<String, RefTarget> {
         &self.data.tags
     }
@@ -88,6 +99,29 @@ impl View {
         self.data.git_head.clone()
     }
 
+    pub fn change_names(&self) -> &BTreeMap<String, ChangeId> {
+        &self.data.change_names
+    }
+
+    /// Returns the names given to `change_id`, in sorted order.
+    pub fn names_for_change(&self, change_id: &ChangeId) -> Vec<&str> {
+        self.data
+            .change_names
+            .iter()
+            .filter(|(_, target)| *target == change_id)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    pub fn change_topics(&self) -> &BTreeMap<ChangeId, String> {
+        &self.data.change_topics
+    }
+
+    /// Returns the topic of `change_id`, if it has one.
+    pub fn topic_for_change(&self, change_id: &ChangeId) -> Option<&str> {
+        self.data.change_topics.get(change_id).map(String::as_str)
+    }
+
     pub fn set_wc_commit(&mut self, workspace_id: WorkspaceId, commit_id: CommitId) {
         self.data.wc_commit_ids.insert(workspace_id, commit_id);
     }
@@ -240,6 +274,22 @@ impl View {
         self.data.git_head = None;
     }
 
+    pub fn set_change_name(&mut self, name: String, change_id: ChangeId) {
+        self.data.change_names.insert(name, change_id);
+    }
+
+    pub fn remove_change_name(&mut self, name: &str) {
+        self.data.change_names.remove(name);
+    }
+
+    pub fn set_change_topic(&mut self, change_id: ChangeId, topic: String) {
+        self.data.change_topics.insert(change_id, topic);
+    }
+
+    pub fn remove_change_topic(&mut self, change_id: &ChangeId) {
+        self.data.change_topics.remove(change_id);
+    }
+
     pub fn set_view(&mut self, data: op_store::View) {
         self.data = data;
     }
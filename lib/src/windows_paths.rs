@@ -0,0 +1,192 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Paths that Windows can't check out, and paths that are too long for the
+//! Windows APIs unless they're written in their "verbatim" form.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::repo_path::RepoPath;
+
+/// Names of devices that can't be used as file names on Windows, with or
+/// without an extension.
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+const INVALID_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*', '\\'];
+
+/// Why a path can't be checked out on Windows.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum WindowsPathIssue {
+    /// A path component is a reserved device name, like `aux` or `con.txt`.
+    ReservedName(String),
+    /// A path component ends with a dot or a space, which Windows strips.
+    TrailingDotOrSpace(String),
+    /// A path component contains a character that isn't allowed in file
+    /// names.
+    InvalidCharacter(String, char),
+}
+
+impl fmt::Display for WindowsPathIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowsPathIssue::ReservedName(name) => {
+                write!(f, "\"{name}\" is a reserved name on Windows")
+            }
+            WindowsPathIssue::TrailingDotOrSpace(name) => {
+                write!(f, "\"{name}\" ends with a dot or space")
+            }
+            WindowsPathIssue::InvalidCharacter(name, c) => {
+                write!(f, "\"{name}\" contains '{c}', which Windows doesn't allow")
+            }
+        }
+    }
+}
+
+fn check_component(name: &str) -> Option<WindowsPathIssue> {
+    if let Some(c) = name
+        .chars()
+        .find(|c| INVALID_CHARS.contains(c) || c.is_ascii_control())
+    {
+        return Some(WindowsPathIssue::InvalidCharacter(name.to_string(), c));
+    }
+    if name.ends_with('.') || name.ends_with(' ') {
+        return Some(WindowsPathIssue::TrailingDotOrSpace(name.to_string()));
+    }
+    // "aux.txt" and "aux .txt" refer to the device too
+    let stem = name.split('.').next().unwrap().trim_end_matches(' ');
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| stem.eq_ignore_ascii_case(reserved))
+    {
+        return Some(WindowsPathIssue::ReservedName(name.to_string()));
+    }
+    None
+}
+
+/// Returns the first reason why `path` can't be checked out on Windows, if
+/// any.
+pub fn check_path(path: &RepoPath) -> Option<WindowsPathIssue> {
+    path.components()
+        .iter()
+        .find_map(|component| check_component(component.as_str()))
+}
+
+/// Windows' traditional limit on the length of paths (`MAX_PATH`), including
+/// the terminating null character.
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
+/// Returns `path` in its verbatim form (with the `\\?\` prefix) if it's too
+/// long for the Windows APIs otherwise. The path must be absolute and
+/// normalized. On other platforms, the path is returned unchanged.
+#[cfg(windows)]
+pub fn long_path_safe(path: PathBuf) -> PathBuf {
+    use std::ffi::OsString;
+    use std::path::{Component, Prefix};
+
+    if path.as_os_str().len() < MAX_PATH {
+        return path;
+    }
+    let mut components = path.components();
+    let mut verbatim = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) => PathBuf::from(format!(r"\\?\{}:\", letter as char)),
+            Prefix::UNC(server, share) => {
+                let mut prefix = OsString::from(r"\\?\UNC\");
+                prefix.push(server);
+                prefix.push(r"\");
+                prefix.push(share);
+                prefix.push(r"\");
+                PathBuf::from(prefix)
+            }
+            // Already verbatim, or a device path
+            _ => return path,
+        },
+        _ => return path,
+    };
+    for component in components {
+        match component {
+            Component::RootDir | Component::CurDir => {}
+            // Verbatim paths aren't normalized, so ".." would be taken literally
+            Component::ParentDir => return path,
+            component => verbatim.push(component),
+        }
+    }
+    verbatim
+}
+
+/// Returns `path` in its verbatim form (with the `\\?\` prefix) if it's too
+/// long for the Windows APIs otherwise. The path must be absolute and
+/// normalized. On other platforms, the path is returned unchanged.
+#[cfg(not(windows))]
+pub fn long_path_safe(path: PathBuf) -> PathBuf {
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_path() {
+        let check = |path| check_path(&RepoPath::from_internal_string(path));
+        assert_eq!(check("dir/file.txt"), None);
+        assert_eq!(check("auxiliary/console.txt"), None);
+        assert_eq!(check("dir/.hidden"), None);
+        assert_eq!(
+            check("dir/aux/file"),
+            Some(WindowsPathIssue::ReservedName("aux".to_string()))
+        );
+        assert_eq!(
+            check("Con.tar.gz"),
+            Some(WindowsPathIssue::ReservedName("Con.tar.gz".to_string()))
+        );
+        assert_eq!(
+            check("com1 .txt"),
+            Some(WindowsPathIssue::ReservedName("com1 .txt".to_string()))
+        );
+        assert_eq!(
+            check("dir./file"),
+            Some(WindowsPathIssue::TrailingDotOrSpace("dir.".to_string()))
+        );
+        assert_eq!(
+            check("file "),
+            Some(WindowsPathIssue::TrailingDotOrSpace("file ".to_string()))
+        );
+        assert_eq!(
+            check("a:b"),
+            Some(WindowsPathIssue::InvalidCharacter("a:b".to_string(), ':'))
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_long_path_safe() {
+        let short = PathBuf::from(r"C:\repo\file");
+        assert_eq!(long_path_safe(short.clone()), short);
+        let long_name = "a".repeat(300);
+        assert_eq!(
+            long_path_safe(PathBuf::from(format!(r"C:\repo\{long_name}"))),
+            PathBuf::from(format!(r"\\?\C:\repo\{long_name}"))
+        );
+        assert_eq!(
+            long_path_safe(PathBuf::from(format!(r"\\server\share\{long_name}"))),
+            PathBuf::from(format!(r"\\?\UNC\server\share\{long_name}"))
+        );
+    }
+}
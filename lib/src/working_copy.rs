@@ -13,43 +13,180 @@
 // limitations under the License.
 
 use std::cell::RefCell;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ffi::OsString;
 use std::fs;
-use std::fs::{DirEntry, File, Metadata, OpenOptions};
+use std::fs::{File, Metadata, OpenOptions};
 use std::io::{Read, Write};
 use std::ops::Bound;
 #[cfg(unix)]
 use std::os::unix::fs::symlink;
 #[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+#[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::UNIX_EPOCH;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
+use itertools::chain;
 use once_cell::unsync::OnceCell;
 use protobuf::{EnumOrUnknown, Message, MessageField};
+use rayon::prelude::*;
 use tempfile::NamedTempFile;
 use thiserror::Error;
+use unicode_normalization::UnicodeNormalization as _;
 
 use crate::backend::{
     BackendError, ConflictId, FileId, MillisSinceEpoch, SymlinkId, TreeId, TreeValue,
 };
 use crate::conflicts::{materialize_conflict, update_conflict_from_content};
+use crate::content_cache::{ContentCache, ContentCacheError};
+use crate::content_filter::{ContentFilterError, ContentFilters};
 use crate::gitignore::GitIgnoreFile;
+use crate::line_endings::{self, LineEndingChange, LineEndingPolicy};
 use crate::lock::FileLock;
-use crate::matchers::{DifferenceMatcher, Matcher, PrefixMatcher};
+use crate::matchers::{DifferenceMatcher, IgnoreMatcher, Matcher, PrefixMatcher, Visit};
 use crate::op_store::{OperationId, WorkspaceId};
 use crate::repo_path::{RepoPath, RepoPathComponent, RepoPathJoin};
 use crate::store::Store;
 use crate::tree::{Diff, Tree};
 use crate::tree_builder::TreeBuilder;
+use crate::windows_paths::{self, WindowsPathIssue};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum FileType {
-    Normal { executable: bool },
+    Normal {
+        executable: bool,
+    },
     Symlink,
-    Conflict { id: ConflictId },
+    Conflict {
+        id: ConflictId,
+    },
+    /// A regular file whose contents haven't been written to disk. The file
+    /// on disk contains a short message instead (see `placeholder_contents()`).
+    Placeholder {
+        id: FileId,
+        executable: bool,
+    },
+    /// A file that wasn't written to disk because its path can't be used on
+    /// Windows (see `TreeState::set_windows_safe_paths()`). Snapshots keep the
+    /// recorded value.
+    Unmaterialized,
+}
+
+/// How far `snapshot()` trusts a file's recorded metadata (mtime, size, type)
+/// to tell whether the file has changed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SnapshotMode {
+    /// Trust matching metadata, except for files modified at or after the time
+    /// the working copy state was last saved. A later write to such a file
+    /// could have left the mtime unchanged, so it's rehashed.
+    Default,
+    /// Like `Default`, but also rehash files modified up to
+    /// `racy_window_millis` before the state was saved, for filesystems with
+    /// coarse or unreliable timestamps.
+    Paranoid { racy_window_millis: i64 },
+    /// Trust matching metadata fully. Fastest, but may miss writes that happen
+    /// within the filesystem's timestamp granularity of the last snapshot.
+    Fast,
+}
+
+impl Default for SnapshotMode {
+    fn default() -> Self {
+        SnapshotMode::Default
+    }
+}
+
+/// How `snapshot()` handles paths that are spelled differently on disk than in
+/// the tree but are the same after Unicode normalization. macOS, for example,
+/// may return names in NFD form for files that were written in NFC form.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnicodeNormalization {
+    /// Paths are compared byte by byte, and new files are recorded with the
+    /// names they have on disk.
+    None,
+    /// A file on disk whose name is a different normalization of a tracked
+    /// path is recorded under the tracked path. New files are recorded in NFC
+    /// form.
+    Nfc,
+    /// Like `Nfc`, but new files are recorded in NFD form.
+    Nfd,
+}
+
+impl Default for UnicodeNormalization {
+    fn default() -> Self {
+        if cfg!(target_os = "macos") {
+            UnicodeNormalization::Nfc
+        } else {
+            UnicodeNormalization::None
+        }
+    }
+}
+
+impl UnicodeNormalization {
+    /// `name` in the form new files are recorded in.
+    fn normalize(self, name: &str) -> String {
+        match self {
+            UnicodeNormalization::None => name.to_string(),
+            UnicodeNormalization::Nfc => name.nfc().collect(),
+            UnicodeNormalization::Nfd => name.nfd().collect(),
+        }
+    }
+}
+
+/// The tracked paths (and their parent directories) that aren't all ASCII, for
+/// finding the tracked path that a differently normalized name on disk refers
+/// to.
+struct NonAsciiPaths {
+    tracked: HashSet<RepoPath>,
+    // Keyed by the NFC form of the paths in `tracked`
+    by_nfc: HashMap<String, RepoPath>,
+}
+
+impl NonAsciiPaths {
+    fn new<'a>(paths: impl Iterator<Item = &'a RepoPath>) -> Self {
+        let mut tracked = HashSet::new();
+        let mut by_nfc = HashMap::new();
+        for path in paths {
+            let mut maybe_path = Some(path.clone());
+            while let Some(path) = maybe_path {
+                let path_string = path.to_internal_file_string();
+                if path_string.is_ascii() || tracked.contains(&path) {
+                    break;
+                }
+                by_nfc
+                    .entry(path_string.nfc().collect())
+                    .or_insert_with(|| path.clone());
+                maybe_path = path.parent();
+                tracked.insert(path);
+            }
+        }
+        NonAsciiPaths { tracked, by_nfc }
+    }
+
+    /// The path that the entry `name` in the directory `dir` is recorded as.
+    fn path_for(
+        &self,
+        dir: &RepoPath,
+        name: &str,
+        normalization: UnicodeNormalization,
+    ) -> RepoPath {
+        let path = dir.join(&RepoPathComponent::from(name));
+        if name.is_ascii() || normalization == UnicodeNormalization::None {
+            return path;
+        }
+        if self.tracked.contains(&path) {
+            return path;
+        }
+        let path_nfc: String = path.to_internal_file_string().nfc().collect();
+        if let Some(tracked_path) = self.by_nfc.get(&path_nfc) {
+            return tracked_path.clone();
+        }
+        dir.join(&RepoPathComponent::from(
+            normalization.normalize(name).as_str(),
+        ))
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -57,7 +194,13 @@ pub struct FileState {
     pub file_type: FileType,
     pub mtime: MillisSinceEpoch,
     pub size: u64,
-    /* TODO: What else do we need here? Git stores a lot of fields.
+    /// The file's inode number. 0 if unknown, e.g. on platforms without inodes
+    /// or for states recorded before this field was added.
+    pub inode: u64,
+    /// The file's status change time (ctime). 0 if unknown, like `inode`.
+    pub ctime: MillisSinceEpoch,
+    /* TODO: Record the file index on Windows once it's available on stable
+     * Rust.
      * TODO: Could possibly handle case-insensitive file systems keeping an
      *       Option<PathBuf> with the actual path here. */
 }
@@ -68,6 +211,8 @@ impl FileState {
             file_type: FileType::Normal { executable },
             mtime: mtime_from_metadata(metadata),
             size,
+            inode: inode_from_metadata(metadata),
+            ctime: ctime_from_metadata(metadata),
         }
     }
 
@@ -79,6 +224,18 @@ impl FileState {
             file_type: FileType::Symlink,
             mtime: mtime_from_metadata(metadata),
             size: metadata.len(),
+            inode: inode_from_metadata(metadata),
+            ctime: ctime_from_metadata(metadata),
+        }
+    }
+
+    fn unmaterialized() -> Self {
+        FileState {
+            file_type: FileType::Unmaterialized,
+            mtime: MillisSinceEpoch(0),
+            size: 0,
+            inode: 0,
+            ctime: MillisSinceEpoch(0),
         }
     }
 
@@ -87,34 +244,111 @@ impl FileState {
             file_type: FileType::Conflict { id },
             mtime: mtime_from_metadata(metadata),
             size,
+            inode: inode_from_metadata(metadata),
+            ctime: ctime_from_metadata(metadata),
         }
     }
 
+    /// Whether a file with this recorded state is unchanged if it now has state
+    /// `current` on disk. Unknown (zero) inodes and ctimes in the recorded
+    /// state are not compared, so states written by older versions still
+    /// match.
+    fn matches_disk_state(&self, current: &FileState, trust_ctime: bool) -> bool {
+        self.file_type == current.file_type
+            && self.mtime == current.mtime
+            && self.size == current.size
+            && (self.inode == 0 || self.inode == current.inode)
+            && (!trust_ctime || self.ctime.0 == 0 || self.ctime == current.ctime)
+    }
+
     #[cfg_attr(unix, allow(dead_code))]
     fn is_executable(&self) -> bool {
-        if let FileType::Normal { executable } = &self.file_type {
-            *executable
-        } else {
-            false
+        match &self.file_type {
+            FileType::Normal { executable } | FileType::Placeholder { executable, .. } => {
+                *executable
+            }
+            _ => false,
         }
     }
 
     fn mark_executable(&mut self, executable: bool) {
-        if let FileType::Normal { .. } = &self.file_type {
-            self.file_type = FileType::Normal { executable }
+        match &self.file_type {
+            FileType::Normal { .. } => self.file_type = FileType::Normal { executable },
+            FileType::Placeholder { id, .. } => {
+                self.file_type = FileType::Placeholder {
+                    id: id.clone(),
+                    executable,
+                }
+            }
+            _ => {}
         }
     }
 }
 
+/// How many diff entries to apply between saving the tree state during a
+/// checkout.
+const CHECKOUT_CHECKPOINT_INTERVAL: u64 = 1000;
+
+/// A checkout that was started but not completed. It's recorded in the tree
+/// state so that if the process gets interrupted, the next process can resume
+/// or roll back the checkout instead of leaving the working copy half-updated
+/// without a record of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingCheckout {
+    pub old_tree_id: TreeId,
+    pub new_tree_id: TreeId,
+    /// The number of entries of the diff from the old tree to the new tree
+    /// that are known to have been applied. Up to
+    /// `CHECKOUT_CHECKPOINT_INTERVAL` more entries may also have been applied.
+    pub completed_entries: u64,
+}
+
+/// The entries of a directory as of `mtime`, from the `dir_cache` file.
+#[derive(Debug, Clone)]
+struct DirListing {
+    mtime: MillisSinceEpoch,
+    // (name, is_dir) pairs, excluding `.jj` and `.git`
+    entries: Vec<(String, bool)>,
+}
+
 pub struct TreeState {
     store: Arc<Store>,
     working_copy_path: PathBuf,
     state_path: PathBuf,
     tree_id: TreeId,
     file_states: BTreeMap<RepoPath, FileState>,
+    // The segment file the saved state is based on, and how many entries it
+    // has. Saving only writes `changed_file_states`, the entries that were
+    // set (`Some`) or removed (`None`) since the segment was written.
+    segment_name: String,
+    segment_len: usize,
+    changed_file_states: BTreeMap<RepoPath, Option<FileState>>,
     // Currently only path prefixes
     sparse_patterns: Vec<RepoPath>,
     own_mtime: MillisSinceEpoch,
+    generation: u64,
+    pending_checkout: Option<PendingCheckout>,
+    content_cache: Option<Arc<ContentCache>>,
+    lazy_threshold: Option<u64>,
+    snapshot_mode: SnapshotMode,
+    unicode_normalization: UnicodeNormalization,
+    trust_ctime: bool,
+    content_filters: Option<Arc<ContentFilters>>,
+    line_ending_policy: LineEndingPolicy,
+    // The files whose line endings or byte-order mark the last snapshot found
+    // to have changed wholesale
+    line_ending_changes: Vec<(RepoPath, Vec<LineEndingChange>)>,
+    timings: WorkingCopyTimings,
+    // Paths that snapshots never look at, even if they're not ignored
+    never_track: Arc<GitIgnoreFile>,
+    use_dir_cache: bool,
+    max_snapshot_files: Option<usize>,
+    windows_safe_paths: bool,
+    // Loaded by the first snapshot that uses it
+    dir_cache: Option<BTreeMap<RepoPath, DirListing>>,
+    dir_cache_dirty: bool,
+    backup_dir: Option<PathBuf>,
+    discard_changes: bool,
 }
 
 fn file_state_from_proto(proto: &crate::protos::working_copy::FileState) -> FileState {
@@ -126,11 +360,22 @@ fn file_state_from_proto(proto: &crate::protos::working_copy::FileState) -> File
             let id = ConflictId::new(proto.conflict_id.to_vec());
             FileType::Conflict { id }
         }
+        crate::protos::working_copy::FileType::Placeholder => FileType::Placeholder {
+            id: FileId::new(proto.placeholder_file_id.to_vec()),
+            executable: false,
+        },
+        crate::protos::working_copy::FileType::ExecutablePlaceholder => FileType::Placeholder {
+            id: FileId::new(proto.placeholder_file_id.to_vec()),
+            executable: true,
+        },
+        crate::protos::working_copy::FileType::Unmaterialized => FileType::Unmaterialized,
     };
     FileState {
         file_type,
         mtime: MillisSinceEpoch(proto.mtime_millis_since_epoch),
         size: proto.size,
+        inode: proto.inode,
+        ctime: MillisSinceEpoch(proto.ctime_millis_since_epoch),
     }
 }
 
@@ -144,10 +389,21 @@ fn file_state_to_proto(file_state: &FileState) -> crate::protos::working_copy::F
             proto.conflict_id = id.to_bytes();
             crate::protos::working_copy::FileType::Conflict
         }
+        FileType::Placeholder { id, executable } => {
+            proto.placeholder_file_id = id.to_bytes();
+            if *executable {
+                crate::protos::working_copy::FileType::ExecutablePlaceholder
+            } else {
+                crate::protos::working_copy::FileType::Placeholder
+            }
+        }
+        FileType::Unmaterialized => crate::protos::working_copy::FileType::Unmaterialized,
     };
     proto.file_type = EnumOrUnknown::new(file_type);
     proto.mtime_millis_since_epoch = file_state.mtime.0;
     proto.size = file_state.size;
+    proto.inode = file_state.inode;
+    proto.ctime_millis_since_epoch = file_state.ctime.0;
     proto
 }
 
@@ -162,6 +418,181 @@ fn file_states_from_proto(
     file_states
 }
 
+/// Whether `disk_dir` is the root of a repo or workspace (i.e. contains a `.jj`
+/// directory or a `.git` directory or file).
+fn is_repo_root(disk_dir: &Path) -> bool {
+    disk_dir.join(".jj").is_dir() || disk_dir.join(".git").exists()
+}
+
+fn file_state_entry_to_proto(
+    path: &RepoPath,
+    file_state: &FileState,
+) -> crate::protos::working_copy::FileStateEntry {
+    let mut proto = crate::protos::working_copy::FileStateEntry::new();
+    proto.path = path.to_internal_file_string();
+    proto.state = MessageField::some(file_state_to_proto(file_state));
+    proto
+}
+
+fn file_state_entry_from_proto(
+    proto: &crate::protos::working_copy::FileStateEntry,
+) -> (RepoPath, FileState) {
+    let path = RepoPath::from_internal_string(proto.path.as_str());
+    let file_state = file_state_from_proto(proto.state.get_or_default());
+    (path, file_state)
+}
+
+/// Once the changes on top of the base segment have this many entries (or
+/// more than an eighth of the segment, whichever is larger), they are folded
+/// into a new segment.
+const MIN_SEGMENT_COMPACTION_THRESHOLD: usize = 1000;
+
+/// How many times `TreeState::load()` reads the `tree_state` file again when
+/// the segment it refers to was removed by a concurrent save.
+const LOAD_ATTEMPTS: usize = 10;
+
+fn segments_dir(state_path: &Path) -> PathBuf {
+    state_path.join("file_states")
+}
+
+/// Reads the segment called `name`, or returns `None` if it doesn't exist
+/// (anymore).
+fn read_segment(
+    state_path: &Path,
+    name: &str,
+) -> Result<Option<BTreeMap<RepoPath, FileState>>, protobuf::Error> {
+    let mut file = match File::open(segments_dir(state_path).join(name)) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        result => result?,
+    };
+    let proto: crate::protos::working_copy::FileStatesSegment =
+        Message::parse_from_reader(&mut file)?;
+    Ok(Some(
+        proto
+            .entries
+            .iter()
+            .map(file_state_entry_from_proto)
+            .collect(),
+    ))
+}
+
+/// Writes `proto` to a temporary file in `dir` and renames it to `name`, so
+/// readers never see a partially written file.
+fn write_proto_file(
+    dir: &Path,
+    name: &str,
+    proto: &impl Message,
+) -> Result<(), WorkingCopyStateError> {
+    let path = dir.join(name);
+    let write_error = |err| WorkingCopyStateError {
+        message: format!("Failed to write {}", path.display()),
+        err,
+    };
+    let mut temp_file = NamedTempFile::new_in(dir).map_err(write_error)?;
+    proto
+        .write_to_writer(temp_file.as_file_mut())
+        .map_err(|err| write_error(std::io::Error::new(std::io::ErrorKind::Other, err)))?;
+    // TODO: Retry if persisting fails (it will on Windows if the file happened to
+    // be open for read).
+    temp_file
+        .persist(&path)
+        .map_err(|err| write_error(err.error))?;
+    Ok(())
+}
+
+fn write_segment(
+    state_path: &Path,
+    name: &str,
+    file_states: &BTreeMap<RepoPath, FileState>,
+) -> Result<(), WorkingCopyStateError> {
+    let dir = segments_dir(state_path);
+    fs::create_dir_all(&dir).map_err(|err| WorkingCopyStateError {
+        message: format!("Failed to create directory {}", dir.display()),
+        err,
+    })?;
+    let mut proto = crate::protos::working_copy::FileStatesSegment::new();
+    for (path, file_state) in file_states {
+        proto
+            .entries
+            .push(file_state_entry_to_proto(path, file_state));
+    }
+    write_proto_file(&dir, name, &proto)
+}
+
+fn read_dir_cache(state_path: &Path) -> BTreeMap<RepoPath, DirListing> {
+    let proto: crate::protos::working_copy::DirCache =
+        match File::open(state_path.join("dir_cache")) {
+            Ok(mut file) => Message::parse_from_reader(&mut file).unwrap_or_default(),
+            Err(_) => return BTreeMap::new(),
+        };
+    proto
+        .listings
+        .into_iter()
+        .map(|listing| {
+            let entries = chain(
+                listing.dirs.into_iter().map(|name| (name, true)),
+                listing.files.into_iter().map(|name| (name, false)),
+            )
+            .collect();
+            (
+                RepoPath::from_internal_string(&listing.path),
+                DirListing {
+                    mtime: MillisSinceEpoch(listing.mtime_millis_since_epoch),
+                    entries,
+                },
+            )
+        })
+        .collect()
+}
+
+fn write_dir_cache(
+    state_path: &Path,
+    dir_cache: &BTreeMap<RepoPath, DirListing>,
+) -> Result<(), WorkingCopyStateError> {
+    let mut proto = crate::protos::working_copy::DirCache::new();
+    for (path, listing) in dir_cache {
+        let mut proto_listing = crate::protos::working_copy::DirListing::new();
+        proto_listing.path = path.to_internal_file_string();
+        proto_listing.mtime_millis_since_epoch = listing.mtime.0;
+        for (name, is_dir) in &listing.entries {
+            if *is_dir {
+                proto_listing.dirs.push(name.clone());
+            } else {
+                proto_listing.files.push(name.clone());
+            }
+        }
+        proto.listings.push(proto_listing);
+    }
+    write_proto_file(state_path, "dir_cache", &proto)
+}
+
+/// Deletes segment files other than the ones in `keep`. The previous segment is
+/// usually kept so a process that read the old `tree_state` file without
+/// holding the lock can still read it.
+fn remove_stale_segments(state_path: &Path, keep: &[&str]) {
+    if let Ok(entries) = fs::read_dir(segments_dir(state_path)) {
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            if !keep.iter().any(|keep_name| name == **keep_name) {
+                fs::remove_file(entry.path()).ok();
+            }
+        }
+    }
+}
+
+fn pending_checkout_from_proto(
+    proto: &crate::protos::working_copy::TreeState,
+) -> Option<PendingCheckout> {
+    proto
+        .pending_checkout
+        .as_ref()
+        .map(|proto_pending_checkout| PendingCheckout {
+            old_tree_id: TreeId::new(proto_pending_checkout.old_tree_id.clone()),
+            new_tree_id: TreeId::new(proto_pending_checkout.new_tree_id.clone()),
+            completed_entries: proto_pending_checkout.completed_entries,
+        })
+}
+
 fn sparse_patterns_from_proto(proto: &crate::protos::working_copy::TreeState) -> Vec<RepoPath> {
     let mut sparse_patterns = vec![];
     if let Some(proto_sparse_patterns) = proto.sparse_patterns.as_ref() {
@@ -193,6 +624,7 @@ fn create_parent_dirs(working_copy_path: &Path, repo_path: &RepoPath) -> Result<
     let mut dir_path = working_copy_path.to_owned();
     for c in dir_components {
         dir_path.push(c.as_str());
+        dir_path = windows_paths::long_path_safe(dir_path);
         match fs::create_dir(&dir_path) {
             Ok(()) => {}
             Err(_)
@@ -228,6 +660,38 @@ fn mtime_from_metadata(metadata: &Metadata) -> MillisSinceEpoch {
     )
 }
 
+#[cfg(unix)]
+fn inode_from_metadata(metadata: &Metadata) -> u64 {
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn inode_from_metadata(_metadata: &Metadata) -> u64 {
+    0
+}
+
+#[cfg(unix)]
+fn ctime_from_metadata(metadata: &Metadata) -> MillisSinceEpoch {
+    MillisSinceEpoch(metadata.ctime() * 1000 + metadata.ctime_nsec() / 1_000_000)
+}
+
+#[cfg(not(unix))]
+fn ctime_from_metadata(_metadata: &Metadata) -> MillisSinceEpoch {
+    // Windows has no status change time
+    MillisSinceEpoch(0)
+}
+
+/// The contents written instead of the actual contents of a file that's not
+/// materialized.
+fn placeholder_contents(path: &RepoPath) -> Vec<u8> {
+    format!(
+        "This file has not been materialized because it is large. Run `jj sparse --hydrate {}` \
+         to materialize it.\n",
+        path.to_internal_file_string()
+    )
+    .into_bytes()
+}
+
 fn file_state(metadata: &Metadata) -> Option<FileState> {
     let metadata_file_type = metadata.file_type();
     let file_type = if metadata_file_type.is_dir() {
@@ -254,15 +718,44 @@ fn file_state(metadata: &Metadata) -> Option<FileState> {
             file_type,
             mtime,
             size,
+            inode: inode_from_metadata(metadata),
+            ctime: ctime_from_metadata(metadata),
         }
     })
 }
 
+/// Where the time of the last snapshot or checkout went, for diagnosing
+/// slowness.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct WorkingCopyTimings {
+    /// Snapshot: listing directories and comparing files' metadata to the
+    /// recorded state. Checkout: looking for files that were modified since
+    /// the last snapshot.
+    pub stat: Duration,
+    /// Snapshot: reading and hashing new and modified files, and writing them
+    /// to the store.
+    pub hash: Duration,
+    /// Snapshot: writing the trees to the store. Checkout: reading files from
+    /// the store and writing them to disk.
+    pub write: Duration,
+    /// Serializing the state and writing it to disk, the last time it was
+    /// saved.
+    pub save: Duration,
+    /// Number of files that were hashed (snapshot)
+    pub hashed_files: usize,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct CheckoutStats {
     pub updated_files: u32,
     pub added_files: u32,
     pub removed_files: u32,
+    /// Where untracked files that were in the way of added files were moved
+    /// to (see `TreeState::set_backup_dir()`).
+    pub backed_up_files: Vec<PathBuf>,
+    /// Files that weren't written because their path can't be used on Windows
+    /// (see `TreeState::set_windows_safe_paths()`).
+    pub skipped_files: Vec<(RepoPath, WindowsPathIssue)>,
 }
 
 #[derive(Debug, Error)]
@@ -279,6 +772,18 @@ pub enum SnapshotError {
     InvalidUtf8SymlinkTarget { path: PathBuf, target: PathBuf },
     #[error("Internal backend error: {0}")]
     InternalBackendError(#[from] BackendError),
+    #[error("Failed to roll back interrupted checkout: {0}")]
+    InterruptedCheckout(#[from] CheckoutError),
+    #[error(transparent)]
+    ContentFilterError(#[from] ContentFilterError),
+    // The snapshot would change the line endings or byte-order mark of these
+    // files and `LineEndingPolicy::Block` is in effect.
+    #[error("{} files would have their line endings or byte-order mark changed", paths.len())]
+    LineEndingChanges { paths: Vec<RepoPath> },
+    // The snapshot would record more new or modified files than the limit set
+    // with `set_max_snapshot_files()`.
+    #[error("{count} new or modified files exceed the limit of {limit} files per snapshot")]
+    TooManyFiles { count: usize, limit: usize },
 }
 
 #[derive(Debug, Error)]
@@ -299,6 +804,14 @@ pub enum CheckoutError {
     },
     #[error("Internal error: {0}")]
     InternalBackendError(#[from] BackendError),
+    #[error(transparent)]
+    ContentFilterError(#[from] ContentFilterError),
+    #[error(transparent)]
+    StateError(#[from] WorkingCopyStateError),
+    // The checkout would overwrite or remove files that were modified since the last
+    // snapshot.
+    #[error("{} files were modified since the last snapshot", paths.len())]
+    DirtyFiles { paths: Vec<RepoPath> },
 }
 
 impl CheckoutError {
@@ -310,6 +823,29 @@ impl CheckoutError {
     }
 }
 
+/// Creates a new directory for backups, named after the current time.
+fn new_backup_subdir(backup_dir: &Path) -> Result<PathBuf, CheckoutError> {
+    let name = chrono::Local::now().format("%Y-%m-%dT%H-%M-%S").to_string();
+    for attempt in 0.. {
+        let subdir = if attempt == 0 {
+            backup_dir.join(&name)
+        } else {
+            backup_dir.join(format!("{name}-{attempt}"))
+        };
+        match fs::create_dir_all(backup_dir).and_then(|()| fs::create_dir(&subdir)) {
+            Ok(()) => return Ok(subdir),
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(err) => {
+                return Err(CheckoutError::IoError {
+                    message: format!("Failed to create backup directory {}", subdir.display()),
+                    err,
+                })
+            }
+        }
+    }
+    unreachable!()
+}
+
 fn suppress_file_exists_error(orig_err: CheckoutError) -> Result<(), CheckoutError> {
     match orig_err {
         CheckoutError::IoError { err, .. } if err.kind() == std::io::ErrorKind::AlreadyExists => {
@@ -329,6 +865,15 @@ pub enum ResetError {
     InternalBackendError(#[from] BackendError),
 }
 
+/// Failed to write one of the files the working copy's state is stored in.
+#[derive(Debug, Error)]
+#[error("{message}: {err}")]
+pub struct WorkingCopyStateError {
+    message: String,
+    #[source]
+    err: std::io::Error,
+}
+
 impl TreeState {
     pub fn current_tree_id(&self) -> &TreeId {
         &self.tree_id
@@ -342,13 +887,112 @@ impl TreeState {
         &self.sparse_patterns
     }
 
+    pub fn pending_checkout(&self) -> Option<&PendingCheckout> {
+        self.pending_checkout.as_ref()
+    }
+
+    /// Makes checkouts clone files from `content_cache` when possible instead of
+    /// writing them.
+    pub fn set_content_cache(&mut self, content_cache: Option<Arc<ContentCache>>) {
+        self.content_cache = content_cache;
+    }
+
+    /// Makes checkouts write a placeholder instead of the contents of files
+    /// larger than `lazy_threshold` bytes. Use `hydrate()` to write the actual
+    /// contents.
+    pub fn set_lazy_threshold(&mut self, lazy_threshold: Option<u64>) {
+        self.lazy_threshold = lazy_threshold;
+    }
+
+    pub fn set_snapshot_mode(&mut self, snapshot_mode: SnapshotMode) {
+        self.snapshot_mode = snapshot_mode;
+    }
+
+    pub fn set_unicode_normalization(&mut self, unicode_normalization: UnicodeNormalization) {
+        self.unicode_normalization = unicode_normalization;
+    }
+
+    /// Whether a changed ctime means that a file has changed, like Git's
+    /// `core.trustctime`.
+    pub fn set_trust_ctime(&mut self, trust_ctime: bool) {
+        self.trust_ctime = trust_ctime;
+    }
+
+    /// Sets the filters that transform file contents on snapshot ("clean") and
+    /// checkout ("smudge").
+    pub fn set_content_filters(&mut self, content_filters: Option<Arc<ContentFilters>>) {
+        self.content_filters = content_filters;
+    }
+
+    /// Sets what snapshots do about files whose line endings or byte-order
+    /// mark changed wholesale.
+    pub fn set_line_ending_policy(&mut self, line_ending_policy: LineEndingPolicy) {
+        self.line_ending_policy = line_ending_policy;
+    }
+
+    /// The files whose line endings or byte-order mark the last snapshot found
+    /// to have changed wholesale, and the changes. With
+    /// `LineEndingPolicy::Normalize`, these changes were undone in the store.
+    pub fn line_ending_changes(&self) -> &[(RepoPath, Vec<LineEndingChange>)] {
+        &self.line_ending_changes
+    }
+
+    /// Where the time of the last snapshot or checkout went.
+    pub fn timings(&self) -> &WorkingCopyTimings {
+        &self.timings
+    }
+
+    /// Makes snapshots skip the paths matching `never_track` (in gitignore
+    /// syntax), whether or not they're ignored. Such files are never added, and
+    /// files that are already tracked are left as they were recorded.
+    pub fn set_never_track(&mut self, never_track: Arc<GitIgnoreFile>) {
+        self.never_track = never_track;
+    }
+
+    /// Makes snapshots reuse the entries they found in a directory last time,
+    /// instead of reading it again, if its mtime hasn't changed since. That
+    /// replaces listing each directory with a `stat()` of it. The files in it
+    /// are still checked for changes, since modifying a file doesn't change
+    /// the mtime of its directory, so no subtree is skipped.
+    pub fn set_use_dir_cache(&mut self, use_dir_cache: bool) {
+        self.use_dir_cache = use_dir_cache;
+    }
+
+    /// Makes snapshots fail with `SnapshotError::TooManyFiles` instead of
+    /// recording more than `max_snapshot_files` new or modified files, e.g.
+    /// because a build directory isn't ignored.
+    pub fn set_max_snapshot_files(&mut self, max_snapshot_files: Option<usize>) {
+        self.max_snapshot_files = max_snapshot_files;
+    }
+
+    /// Makes checkouts skip files whose path can't be used on Windows (see
+    /// `windows_paths::check_path()`) instead of failing. They're recorded as
+    /// `FileType::Unmaterialized` and reported in `CheckoutStats`. On by
+    /// default on Windows.
+    pub fn set_windows_safe_paths(&mut self, windows_safe_paths: bool) {
+        self.windows_safe_paths = windows_safe_paths;
+    }
+
+    /// Makes checkouts move untracked (e.g. ignored) files that are in the way
+    /// of files being added into a new timestamped directory under
+    /// `backup_dir`, instead of failing.
+    pub fn set_backup_dir(&mut self, backup_dir: Option<PathBuf>) {
+        self.backup_dir = backup_dir;
+    }
+
+    /// Makes checkouts overwrite and remove files that were modified since the
+    /// last snapshot, instead of failing with `CheckoutError::DirtyFiles`.
+    pub fn set_discard_changes(&mut self, discard_changes: bool) {
+        self.discard_changes = discard_changes;
+    }
+
     fn sparse_matcher(&self) -> Box<dyn Matcher> {
         Box::new(PrefixMatcher::new(&self.sparse_patterns))
     }
 
     pub fn init(store: Arc<Store>, working_copy_path: PathBuf, state_path: PathBuf) -> TreeState {
         let mut wc = TreeState::empty(store, working_copy_path, state_path);
-        wc.save();
+        wc.save().unwrap();
         wc
     }
 
@@ -362,23 +1006,55 @@ impl TreeState {
             state_path,
             tree_id,
             file_states: BTreeMap::new(),
+            segment_name: String::new(),
+            segment_len: 0,
+            changed_file_states: BTreeMap::new(),
             sparse_patterns: vec![RepoPath::root()],
             own_mtime: MillisSinceEpoch(0),
+            generation: 0,
+            pending_checkout: None,
+            content_cache: None,
+            lazy_threshold: None,
+            snapshot_mode: SnapshotMode::Default,
+            unicode_normalization: UnicodeNormalization::default(),
+            trust_ctime: true,
+            content_filters: None,
+            line_ending_policy: LineEndingPolicy::default(),
+            line_ending_changes: vec![],
+            timings: WorkingCopyTimings::default(),
+            never_track: GitIgnoreFile::empty(),
+            use_dir_cache: false,
+            max_snapshot_files: None,
+            windows_safe_paths: cfg!(windows),
+            dir_cache: None,
+            dir_cache_dirty: false,
+            backup_dir: None,
+            discard_changes: false,
         }
     }
 
     pub fn load(store: Arc<Store>, working_copy_path: PathBuf, state_path: PathBuf) -> TreeState {
-        let maybe_file = File::open(state_path.join("tree_state"));
-        let file = match maybe_file {
-            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => {
-                return TreeState::init(store, working_copy_path, state_path);
+        for _attempt in 0..LOAD_ATTEMPTS {
+            let maybe_file = File::open(state_path.join("tree_state"));
+            let file = match maybe_file {
+                Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    return TreeState::init(store, working_copy_path, state_path);
+                }
+                result => result.unwrap(),
+            };
+
+            let mut wc =
+                TreeState::empty(store.clone(), working_copy_path.clone(), state_path.clone());
+            // If the segment is gone, a concurrent save replaced the file after we
+            // opened it, so the new file refers to a newer segment
+            if wc.read(file).unwrap() {
+                return wc;
             }
-            result => result.unwrap(),
-        };
-
-        let mut wc = TreeState::empty(store, working_copy_path, state_path);
-        wc.read(file);
-        wc
+        }
+        panic!(
+            "The working copy state in {} refers to a missing file states segment",
+            state_path.display()
+        );
     }
 
     fn update_own_mtime(&mut self) {
@@ -389,24 +1065,96 @@ impl TreeState {
         }
     }
 
-    fn read(&mut self, mut file: File) {
+    /// Reads the state from `file`, an open `tree_state` file. Returns false if
+    /// the segment it refers to has been removed since; the caller should
+    /// then open the file again.
+    fn read(&mut self, mut file: File) -> Result<bool, protobuf::Error> {
         self.update_own_mtime();
-        let proto: crate::protos::working_copy::TreeState =
-            Message::parse_from_reader(&mut file).unwrap();
+        let proto: crate::protos::working_copy::TreeState = Message::parse_from_reader(&mut file)?;
         self.tree_id = TreeId::new(proto.tree_id.clone());
-        self.file_states = file_states_from_proto(&proto);
+        self.changed_file_states.clear();
+        if proto.file_states_segment.is_empty() {
+            // Legacy format: the full map is stored inline. It's moved to a
+            // segment on the next save.
+            self.segment_name = String::new();
+            self.segment_len = 0;
+            self.file_states = file_states_from_proto(&proto);
+            for (path, file_state) in &self.file_states {
+                self.changed_file_states
+                    .insert(path.clone(), Some(file_state.clone()));
+            }
+        } else {
+            self.file_states = match read_segment(&self.state_path, &proto.file_states_segment)? {
+                Some(file_states) => file_states,
+                None => return Ok(false),
+            };
+            self.segment_name = proto.file_states_segment.clone();
+            self.segment_len = self.file_states.len();
+        }
+        for path_str in &proto.removed_file_states {
+            self.remove_file_state(&RepoPath::from_internal_string(path_str.as_str()));
+        }
+        for entry in &proto.changed_file_states {
+            let (path, file_state) = file_state_entry_from_proto(entry);
+            self.set_file_state(path, file_state);
+        }
         self.sparse_patterns = sparse_patterns_from_proto(&proto);
+        self.pending_checkout = pending_checkout_from_proto(&proto);
+        self.generation = proto.generation;
+        Ok(true)
     }
 
-    fn save(&mut self) {
+    fn set_file_state(&mut self, path: RepoPath, file_state: FileState) {
+        self.changed_file_states
+            .insert(path.clone(), Some(file_state.clone()));
+        self.file_states.insert(path, file_state);
+    }
+
+    fn remove_file_state(&mut self, path: &RepoPath) {
+        self.changed_file_states.insert(path.clone(), None);
+        self.file_states.remove(path);
+    }
+
+    /// Reads just the tree ID from the `tree_state` file in `state_path`, or
+    /// returns `None` if the file is missing or can't be parsed.
+    fn read_tree_id(state_path: &Path) -> Option<TreeId> {
+        let mut file = File::open(state_path.join("tree_state")).ok()?;
+        let proto: crate::protos::working_copy::TreeState =
+            Message::parse_from_reader(&mut file).ok()?;
+        Some(TreeId::new(proto.tree_id))
+    }
+
+    /// Writes the state to the `tree_state` file in the state directory.
+    ///
+    /// The file states are stored as an immutable segment plus the changes on
+    /// top of it, so saving usually only costs as much as what changed since
+    /// the segment was written. Loading still decodes the whole segment.
+    pub fn save(&mut self) -> Result<(), WorkingCopyStateError> {
+        let save_start = Instant::now();
+        self.generation += 1;
         let mut proto = crate::protos::working_copy::TreeState::new();
         proto.tree_id = self.tree_id.to_bytes();
-        for (file, file_state) in &self.file_states {
-            proto.file_states.insert(
-                file.to_internal_file_string(),
-                file_state_to_proto(file_state),
-            );
+        let threshold = MIN_SEGMENT_COMPACTION_THRESHOLD.max(self.segment_len / 8);
+        let mut previous_segment_name = None;
+        if self.changed_file_states.len() > threshold {
+            let name = format!("{:016x}", self.generation);
+            write_segment(&self.state_path, &name, &self.file_states)?;
+            previous_segment_name = Some(std::mem::replace(&mut self.segment_name, name));
+            self.segment_len = self.file_states.len();
+            self.changed_file_states.clear();
+        } else {
+            for (path, file_state) in &self.changed_file_states {
+                match file_state {
+                    Some(file_state) => proto
+                        .changed_file_states
+                        .push(file_state_entry_to_proto(path, file_state)),
+                    None => proto
+                        .removed_file_states
+                        .push(path.to_internal_file_string()),
+                }
+            }
         }
+        proto.file_states_segment = self.segment_name.clone();
         let mut sparse_patterns = crate::protos::working_copy::SparsePatterns::new();
         for path in &self.sparse_patterns {
             sparse_patterns
@@ -414,29 +1162,93 @@ impl TreeState {
                 .push(path.to_internal_file_string());
         }
         proto.sparse_patterns = MessageField::some(sparse_patterns);
+        if let Some(pending_checkout) = &self.pending_checkout {
+            let mut proto_pending_checkout = crate::protos::working_copy::PendingCheckout::new();
+            proto_pending_checkout.old_tree_id = pending_checkout.old_tree_id.to_bytes();
+            proto_pending_checkout.new_tree_id = pending_checkout.new_tree_id.to_bytes();
+            proto_pending_checkout.completed_entries = pending_checkout.completed_entries;
+            proto.pending_checkout = MessageField::some(proto_pending_checkout);
+        }
+        proto.generation = self.generation;
 
-        let mut temp_file = NamedTempFile::new_in(&self.state_path).unwrap();
-        proto.write_to_writer(temp_file.as_file_mut()).unwrap();
         // update own write time while we before we rename it, so we know
         // there is no unknown data in it
         self.update_own_mtime();
-        // TODO: Retry if persisting fails (it will on Windows if the file happened to
-        // be open for read).
-        temp_file
-            .persist(self.state_path.join("tree_state"))
-            .unwrap();
+        write_proto_file(&self.state_path, "tree_state", &proto)?;
+        if self.dir_cache_dirty {
+            if let Some(dir_cache) = &self.dir_cache {
+                write_dir_cache(&self.state_path, dir_cache)?;
+            }
+            self.dir_cache_dirty = false;
+        }
+        if let Some(previous_segment_name) = previous_segment_name {
+            // A process that read the previous `tree_state` file without the lock
+            // may be about to open the segment it refers to, so keep that one. Older
+            // segments can't be picked by anyone anymore: a reader that finds its
+            // segment gone reads the `tree_state` file again.
+            remove_stale_segments(
+                &self.state_path,
+                &[&self.segment_name, &previous_segment_name],
+            );
+        }
+        self.timings.save = save_start.elapsed();
+        Ok(())
     }
 
+    /// Writes the file at `disk_path` to the store. `old_id` is the file that
+    /// was at `path` before, if any, to check for line-ending changes against.
     fn write_file_to_store(
         &self,
         path: &RepoPath,
         disk_path: &Path,
-    ) -> Result<FileId, SnapshotError> {
-        let file = File::open(disk_path).map_err(|err| SnapshotError::IoError {
+        old_id: Option<&FileId>,
+    ) -> Result<(FileId, Vec<LineEndingChange>), SnapshotError> {
+        let mut file = File::open(disk_path).map_err(|err| SnapshotError::IoError {
             message: format!("Failed to open file {}", disk_path.display()),
             err,
         })?;
-        Ok(self.store.write_file(path, &mut Box::new(file))?)
+        let content_filters = self.content_filters.as_ref().filter(|f| f.cleans(path));
+        let mut old_id = old_id.filter(|_| self.line_ending_policy != LineEndingPolicy::Ignore);
+        if content_filters.is_none() && old_id.is_some() {
+            let metadata = file.metadata().map_err(|err| SnapshotError::IoError {
+                message: format!("Failed to stat file {}", disk_path.display()),
+                err,
+            })?;
+            if metadata.len() > line_endings::MAX_CHECKED_FILE_SIZE {
+                old_id = None;
+            }
+        }
+        if content_filters.is_none() && old_id.is_none() {
+            return Ok((self.store.write_file(path, &mut Box::new(file))?, vec![]));
+        }
+        let mut contents = vec![];
+        file.read_to_end(&mut contents)
+            .map_err(|err| SnapshotError::IoError {
+                message: format!("Failed to read file {}", disk_path.display()),
+                err,
+            })?;
+        if let Some(content_filters) = content_filters {
+            contents = content_filters.clean(path, contents)?;
+        }
+        let mut changes = vec![];
+        // Binary files never have line-ending changes, so don't read the old
+        // contents for them.
+        if let Some(old_id) = old_id.filter(|_| !line_endings::is_binary(&contents)) {
+            let mut old_contents = vec![];
+            self.store
+                .read_file(path, old_id)?
+                .read_to_end(&mut old_contents)
+                .map_err(|err| SnapshotError::IoError {
+                    message: format!("Failed to read file {}", path.to_internal_file_string()),
+                    err,
+                })?;
+            changes = line_endings::detect_changes(&old_contents, &contents);
+            if !changes.is_empty() && self.line_ending_policy == LineEndingPolicy::Normalize {
+                contents = line_endings::normalize(&contents, &changes);
+            }
+        }
+        let id = self.store.write_file(path, &mut contents.as_slice())?;
+        Ok((id, changes))
     }
 
     fn write_symlink_to_store(
@@ -463,63 +1275,243 @@ impl TreeState {
     /// Look for changes to the working copy. If there are any changes, create
     /// a new tree from it.
     pub fn snapshot(&mut self, base_ignores: Arc<GitIgnoreFile>) -> Result<bool, SnapshotError> {
+        // The files on disk don't match any tree while a checkout is pending, so
+        // go back to the tree we have recorded first.
+        let rolled_back = self.pending_checkout.is_some();
+        if rolled_back {
+            self.roll_back_checkout()?;
+        }
+        self.timings = WorkingCopyTimings::default();
+        let stat_start = Instant::now();
         let sparse_matcher = self.sparse_matcher();
         let mut work = vec![(
             RepoPath::root(),
             self.working_copy_path.clone(),
             base_ignores,
         )];
+        let base_tree = self.store.get_tree(&RepoPath::root(), &self.tree_id)?;
         let mut tree_builder = self.store.tree_builder(self.tree_id.clone());
-        let mut deleted_files: HashSet<_> = self.file_states.keys().cloned().collect();
+        let mut deleted_files: HashSet<_> = self
+            .file_states
+            .iter()
+            .filter(|(path, file_state)| {
+                file_state.file_type != FileType::Unmaterialized
+                    && !self
+                        .never_track
+                        .matches_file(&path.to_internal_file_string())
+            })
+            .map(|(path, _file_state)| path.clone())
+            .collect();
+        let non_ascii_paths = if self.unicode_normalization == UnicodeNormalization::None {
+            NonAsciiPaths::new(std::iter::empty())
+        } else {
+            NonAsciiPaths::new(self.file_states.keys())
+        };
+        let mut files_to_write = vec![];
+        let mut visited_dirs = HashSet::new();
         while let Some((dir, disk_dir, git_ignore)) = work.pop() {
             if sparse_matcher.visit(&dir).is_nothing() {
                 continue;
             }
-            let git_ignore = git_ignore
-                .chain_with_file(&dir.to_internal_dir_string(), disk_dir.join(".gitignore"));
-            for maybe_entry in disk_dir.read_dir().unwrap() {
-                let entry = maybe_entry.unwrap();
-                let file_type = entry.file_type().unwrap();
-                let file_name = entry.file_name();
-                let name = file_name
-                    .to_str()
-                    .ok_or_else(|| SnapshotError::InvalidUtf8Path {
-                        path: file_name.clone(),
-                    })?;
-                if name == ".jj" || name == ".git" {
+            // If the whole directory is ignored, skip it unless we're already tracking
+            // some file in it. If we are, we still don't read any .gitignore file in
+            // it, since Git doesn't look at those in ignored directories either.
+            let git_ignore = if IgnoreMatcher::new(git_ignore.clone()).visit(&dir)
+                == Visit::AllRecursively
+            {
+                if !self.has_files_under(&dir) {
                     continue;
                 }
-                let sub_path = dir.join(&RepoPathComponent::from(name));
-                if file_type.is_dir() {
-                    // If the whole directory is ignored, skip it unless we're already tracking
-                    // some file in it.
-                    if git_ignore.matches_all_files_in(&sub_path.to_internal_dir_string())
-                        && !self.has_files_under(&sub_path)
+                git_ignore
+            } else {
+                // Like Git, don't follow a symlinked .gitignore in the working copy
+                let git_ignore_path = disk_dir.join(".gitignore");
+                match git_ignore_path.symlink_metadata() {
+                    Ok(metadata) if metadata.file_type().is_symlink() => git_ignore,
+                    _ => git_ignore.chain_with_file(&dir.to_internal_dir_string(), git_ignore_path),
+                }
+            };
+            visited_dirs.insert(dir.clone());
+            for (name, is_dir) in self.dir_entries(&dir, &disk_dir)? {
+                let sub_path = non_ascii_paths.path_for(&dir, &name, self.unicode_normalization);
+                let disk_path = disk_dir.join(&name);
+                if is_dir {
+                    if self
+                        .never_track
+                        .matches_all_files_in(&sub_path.to_internal_dir_string())
                     {
                         continue;
                     }
-                    work.push((sub_path, entry.path(), git_ignore.clone()));
-                } else {
+                    // Don't start tracking the files of a nested repo (unless we already
+                    // track some of them).
+                    if is_repo_root(&disk_path) && !self.has_files_under(&sub_path) {
+                        continue;
+                    }
+                    work.push((sub_path, disk_path, git_ignore.clone()));
+                } else if !self
+                    .never_track
+                    .matches_file(&sub_path.to_internal_file_string())
+                {
                     deleted_files.remove(&sub_path);
                     if sparse_matcher.matches(&sub_path) {
                         self.update_file_state(
                             sub_path,
-                            &entry,
+                            disk_path,
                             git_ignore.as_ref(),
                             &mut tree_builder,
+                            &mut files_to_write,
                         )?;
                     }
                 }
             }
         }
 
+        if let Some(dir_cache) = &mut self.dir_cache {
+            let num_listings = dir_cache.len();
+            dir_cache.retain(|dir, _| visited_dirs.contains(dir));
+            self.dir_cache_dirty |= dir_cache.len() != num_listings;
+        }
+
+        let old_values = files_to_write
+            .iter()
+            .map(|(repo_path, _, _)| base_tree.path_value(repo_path))
+            .collect::<Vec<_>>();
+        if let Some(limit) = self.max_snapshot_files {
+            // Files that weren't in the tree are new for sure, so fail before
+            // hashing them. Whether the others changed is only known after
+            // hashing them (they may just have been touched or written right
+            // before the last snapshot).
+            let count = old_values.iter().filter(|value| value.is_none()).count();
+            if count > limit {
+                return Err(SnapshotError::TooManyFiles { count, limit });
+            }
+        }
+
+        self.timings.stat = stat_start.elapsed();
+        self.timings.hashed_files = files_to_write.len();
+
+        // Hashing and writing the contents of new and modified files is where most
+        // of the time goes, so do it in parallel. The values are added to the tree
+        // in path order afterwards.
+        let hash_start = Instant::now();
+        let this = &*self;
+        let file_values = files_to_write
+            .par_iter()
+            .zip(old_values.par_iter())
+            .map(|((repo_path, disk_path, file_type), old_value)| {
+                this.write_path_to_store(repo_path, disk_path, file_type.clone(), old_value.clone())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.timings.hash = hash_start.elapsed();
+        if let Some(limit) = self.max_snapshot_files {
+            let count = file_values
+                .iter()
+                .zip(&old_values)
+                .filter(|((file_value, _), old_value)| old_value.as_ref() != Some(file_value))
+                .count();
+            if count > limit {
+                return Err(SnapshotError::TooManyFiles { count, limit });
+            }
+        }
+        let mut line_ending_changes = vec![];
+        for ((repo_path, _, _), (file_value, changes)) in
+            files_to_write.into_iter().zip(file_values)
+        {
+            if !changes.is_empty() {
+                line_ending_changes.push((repo_path.clone(), changes));
+            }
+            tree_builder.set(repo_path, file_value);
+        }
+        if self.line_ending_policy == LineEndingPolicy::Block && !line_ending_changes.is_empty() {
+            return Err(SnapshotError::LineEndingChanges {
+                paths: line_ending_changes
+                    .into_iter()
+                    .map(|(path, _)| path)
+                    .collect(),
+            });
+        }
+        self.line_ending_changes = line_ending_changes;
+
         for file in &deleted_files {
-            self.file_states.remove(file);
+            self.remove_file_state(file);
             tree_builder.remove(file.clone());
         }
         let changed = tree_builder.has_overrides();
+        let write_start = Instant::now();
         self.tree_id = tree_builder.write_tree();
-        Ok(changed)
+        self.timings.write = write_start.elapsed();
+        Ok(changed || rolled_back)
+    }
+
+    /// The names of the entries in `disk_dir` other than `.jj` and `.git`, and
+    /// whether they're directories. With the dir cache enabled, they're taken
+    /// from the cache if the directory's mtime hasn't changed.
+    fn dir_entries(
+        &mut self,
+        dir: &RepoPath,
+        disk_dir: &Path,
+    ) -> Result<Vec<(String, bool)>, SnapshotError> {
+        let read_dir_error = |err| SnapshotError::IoError {
+            message: format!("Failed to read directory {}", disk_dir.display()),
+            err,
+        };
+        let mtime = if self.use_dir_cache {
+            let metadata = disk_dir.symlink_metadata().map_err(read_dir_error)?;
+            let mtime = mtime_from_metadata(&metadata);
+            // Like for files, a listing recorded around the time the state was
+            // saved may have missed a change that left the mtime as it was.
+            let racy_since = self.racy_since();
+            let dir_cache = self
+                .dir_cache
+                .get_or_insert_with(|| read_dir_cache(&self.state_path));
+            if let Some(listing) = dir_cache.get(dir) {
+                if listing.mtime == mtime && racy_since.map_or(true, |since| mtime.0 < since) {
+                    return Ok(listing.entries.clone());
+                }
+            }
+            Some(mtime)
+        } else {
+            None
+        };
+        let mut entries = vec![];
+        for maybe_entry in disk_dir.read_dir().map_err(read_dir_error)? {
+            let entry = maybe_entry.map_err(read_dir_error)?;
+            let file_type = entry.file_type().map_err(read_dir_error)?;
+            let file_name = entry.file_name();
+            let name = file_name
+                .to_str()
+                .ok_or_else(|| SnapshotError::InvalidUtf8Path {
+                    path: file_name.clone(),
+                })?;
+            if name == ".jj" || name == ".git" {
+                continue;
+            }
+            entries.push((name.to_string(), file_type.is_dir()));
+        }
+        if let (Some(mtime), Some(dir_cache)) = (mtime, &mut self.dir_cache) {
+            dir_cache.insert(
+                dir.clone(),
+                DirListing {
+                    mtime,
+                    entries: entries.clone(),
+                },
+            );
+            self.dir_cache_dirty = true;
+        }
+        Ok(entries)
+    }
+
+    /// Files (and directories) modified at or after this time may have changed
+    /// since the last snapshot even if their mtime is as recorded. `None` if
+    /// the recorded mtimes are trusted regardless.
+    fn racy_since(&self) -> Option<i64> {
+        match self.snapshot_mode {
+            SnapshotMode::Default => Some(self.own_mtime.0),
+            SnapshotMode::Paranoid { racy_window_millis } => {
+                Some(self.own_mtime.0.saturating_sub(racy_window_millis))
+            }
+            SnapshotMode::Fast => None,
+        }
     }
 
     fn has_files_under(&self, dir: &RepoPath) -> bool {
@@ -544,11 +1536,12 @@ impl TreeState {
     fn update_file_state(
         &mut self,
         repo_path: RepoPath,
-        dir_entry: &DirEntry,
+        disk_path: PathBuf,
         git_ignore: &GitIgnoreFile,
         tree_builder: &mut TreeBuilder,
+        files_to_write: &mut Vec<(RepoPath, PathBuf, FileType)>,
     ) -> Result<(), SnapshotError> {
-        let maybe_current_file_state = self.file_states.get_mut(&repo_path);
+        let maybe_current_file_state = self.file_states.get(&repo_path).cloned();
         if maybe_current_file_state.is_none()
             && git_ignore.matches_file(&repo_path.to_internal_file_string())
         {
@@ -556,11 +1549,12 @@ impl TreeState {
             // ignore it.
             return Ok(());
         }
-        let disk_path = dir_entry.path();
-        let metadata = dir_entry.metadata().map_err(|err| SnapshotError::IoError {
-            message: format!("Failed to stat file {}", disk_path.display()),
-            err,
-        })?;
+        let metadata = disk_path
+            .symlink_metadata()
+            .map_err(|err| SnapshotError::IoError {
+                message: format!("Failed to stat file {}", disk_path.display()),
+                err,
+            })?;
         let maybe_new_file_state = file_state(&metadata);
         match (maybe_current_file_state, maybe_new_file_state) {
             (None, None) => {
@@ -568,81 +1562,129 @@ impl TreeState {
             }
             (Some(_), None) => {
                 // Tracked file replaced by Unix socket or such
-                self.file_states.remove(&repo_path);
+                self.remove_file_state(&repo_path);
                 tree_builder.remove(repo_path);
             }
             (None, Some(new_file_state)) => {
                 // untracked
                 let file_type = new_file_state.file_type.clone();
-                self.file_states.insert(repo_path.clone(), new_file_state);
-                let file_value = self.write_path_to_store(&repo_path, &disk_path, file_type)?;
-                tree_builder.set(repo_path, file_value);
+                self.set_file_state(repo_path.clone(), new_file_state);
+                files_to_write.push((repo_path, disk_path, file_type));
             }
-            (Some(current_file_state), Some(mut new_file_state)) => {
-                #[cfg(windows)]
-                {
-                    // On Windows, we preserve the state we had recorded
-                    // when we wrote the file.
-                    new_file_state.mark_executable(current_file_state.is_executable());
+            (Some(recorded_file_state), Some(new_file_state)) => {
+                let mut current_file_state = recorded_file_state.clone();
+                self.update_tracked_file_state(
+                    &repo_path,
+                    &disk_path,
+                    &mut current_file_state,
+                    new_file_state,
+                    tree_builder,
+                    files_to_write,
+                )?;
+                // Only changed entries are recorded, so that saving the state
+                // afterwards costs as much as what changed
+                if current_file_state != recorded_file_state {
+                    self.set_file_state(repo_path, current_file_state);
                 }
-                // If the file's mtime was set at the same time as this state file's own mtime,
-                // then we don't know if the file was modified before or after this state file.
-                // We set the file's mtime to 0 to simplify later code.
-                if current_file_state.mtime >= self.own_mtime {
-                    current_file_state.mtime = MillisSinceEpoch(0);
-                }
-                let mut clean = current_file_state == &new_file_state;
-                // Because the file system doesn't have a built-in way of indicating a conflict,
-                // we look at the current state instead. If that indicates that the path has a
-                // conflict and the contents are now a file, then we take interpret that as if
-                // it is still a conflict.
-                if !clean
-                    && matches!(current_file_state.file_type, FileType::Conflict { .. })
-                    && matches!(new_file_state.file_type, FileType::Normal { .. })
+            }
+        };
+        Ok(())
+    }
+
+    /// Updates `current_file_state`, the recorded state of a tracked file, for
+    /// `new_file_state` found on disk, and schedules the file for writing to
+    /// the store if it changed.
+    fn update_tracked_file_state(
+        &self,
+        repo_path: &RepoPath,
+        disk_path: &Path,
+        current_file_state: &mut FileState,
+        mut new_file_state: FileState,
+        tree_builder: &mut TreeBuilder,
+        files_to_write: &mut Vec<(RepoPath, PathBuf, FileType)>,
+    ) -> Result<(), SnapshotError> {
+        let racy_since = self.racy_since();
+        #[cfg(windows)]
+        {
+            // On Windows, we preserve the state we had recorded
+            // when we wrote the file.
+            new_file_state.mark_executable(current_file_state.is_executable());
+        }
+        // If the file's mtime was set at the same time as this state file's own mtime,
+        // then we don't know if the file was modified before or after this state file.
+        // We set the file's mtime to 0 to simplify later code.
+        if let Some(racy_since) = racy_since {
+            if current_file_state.mtime.0 >= racy_since {
+                current_file_state.mtime = MillisSinceEpoch(0);
+            }
+        }
+        let mut clean = current_file_state.matches_disk_state(&new_file_state, self.trust_ctime);
+        if clean {
+            // Fill in fields that weren't recorded by older versions
+            current_file_state.inode = new_file_state.inode;
+            current_file_state.ctime = new_file_state.ctime.clone();
+        }
+        // A placeholder is unchanged as long as it still has the placeholder
+        // contents. We compare the contents (they're short) because recording
+        // the placeholder text as the file's contents would be bad.
+        if let (FileType::Placeholder { .. }, FileType::Normal { .. }) =
+            (&current_file_state.file_type, &new_file_state.file_type)
+        {
+            if new_file_state.size == current_file_state.size
+                && fs::read(disk_path).ok() == Some(placeholder_contents(repo_path))
+            {
+                current_file_state.mtime = new_file_state.mtime;
+                return Ok(());
+            }
+        }
+        // Because the file system doesn't have a built-in way of indicating a conflict,
+        // we look at the current state instead. If that indicates that the path has a
+        // conflict and the contents are now a file, then we take interpret that as if
+        // it is still a conflict.
+        if !clean
+            && matches!(current_file_state.file_type, FileType::Conflict { .. })
+            && matches!(new_file_state.file_type, FileType::Normal { .. })
+        {
+            // If the only change is that the type changed from conflict to regular file,
+            // then we consider it clean (the same as a regular file being clean, it's
+            // just that the file system doesn't have a conflict type).
+            if new_file_state.mtime == current_file_state.mtime
+                && new_file_state.size == current_file_state.size
+            {
+                clean = true;
+            } else {
+                // If the file contained a conflict before and is now a normal file on disk
+                // (new_file_state cannot be a Conflict at this point), we try to parse
+                // any conflict markers in the file into a conflict.
+                if let (FileType::Conflict { id }, FileType::Normal { executable: _ }) =
+                    (&current_file_state.file_type, &new_file_state.file_type)
                 {
-                    // If the only change is that the type changed from conflict to regular file,
-                    // then we consider it clean (the same as a regular file being clean, it's
-                    // just that the file system doesn't have a conflict type).
-                    if new_file_state.mtime == current_file_state.mtime
-                        && new_file_state.size == current_file_state.size
-                    {
-                        clean = true;
-                    } else {
-                        // If the file contained a conflict before and is now a normal file on disk
-                        // (new_file_state cannot be a Conflict at this point), we try to parse
-                        // any conflict markers in the file into a conflict.
-                        if let (FileType::Conflict { id }, FileType::Normal { executable: _ }) =
-                            (&current_file_state.file_type, &new_file_state.file_type)
-                        {
-                            let mut file = File::open(&disk_path).unwrap();
-                            let mut content = vec![];
-                            file.read_to_end(&mut content).unwrap();
-                            if let Some(new_conflict_id) = update_conflict_from_content(
-                                self.store.as_ref(),
-                                &repo_path,
-                                id,
-                                &content,
-                            )
+                    let read_error = |err| SnapshotError::IoError {
+                        message: format!("Failed to read file {}", disk_path.display()),
+                        err,
+                    };
+                    let mut file = File::open(disk_path).map_err(read_error)?;
+                    let mut content = vec![];
+                    file.read_to_end(&mut content).map_err(read_error)?;
+                    if let Some(new_conflict_id) =
+                        update_conflict_from_content(self.store.as_ref(), repo_path, id, &content)
                             .unwrap()
-                            {
-                                new_file_state.file_type = FileType::Conflict {
-                                    id: new_conflict_id.clone(),
-                                };
-                                *current_file_state = new_file_state;
-                                tree_builder.set(repo_path, TreeValue::Conflict(new_conflict_id));
-                                return Ok(());
-                            }
-                        }
+                    {
+                        new_file_state.file_type = FileType::Conflict {
+                            id: new_conflict_id.clone(),
+                        };
+                        *current_file_state = new_file_state;
+                        tree_builder.set(repo_path.clone(), TreeValue::Conflict(new_conflict_id));
+                        return Ok(());
                     }
                 }
-                if !clean {
-                    let file_type = new_file_state.file_type.clone();
-                    *current_file_state = new_file_state;
-                    let file_value = self.write_path_to_store(&repo_path, &disk_path, file_type)?;
-                    tree_builder.set(repo_path, file_value);
-                }
             }
-        };
+        }
+        if !clean {
+            let file_type = new_file_state.file_type.clone();
+            *current_file_state = new_file_state;
+            files_to_write.push((repo_path.clone(), disk_path.to_path_buf(), file_type));
+        }
         Ok(())
     }
 
@@ -651,17 +1693,24 @@ impl TreeState {
         repo_path: &RepoPath,
         disk_path: &Path,
         file_type: FileType,
-    ) -> Result<TreeValue, SnapshotError> {
+        old_value: Option<TreeValue>,
+    ) -> Result<(TreeValue, Vec<LineEndingChange>), SnapshotError> {
         match file_type {
             FileType::Normal { executable } => {
-                let id = self.write_file_to_store(repo_path, disk_path)?;
-                Ok(TreeValue::Normal { id, executable })
+                let old_id = match &old_value {
+                    Some(TreeValue::Normal { id, .. }) => Some(id),
+                    _ => None,
+                };
+                let (id, changes) = self.write_file_to_store(repo_path, disk_path, old_id)?;
+                Ok((TreeValue::Normal { id, executable }, changes))
             }
             FileType::Symlink => {
                 let id = self.write_symlink_to_store(repo_path, disk_path)?;
-                Ok(TreeValue::Symlink(id))
+                Ok((TreeValue::Symlink(id), vec![]))
             }
             FileType::Conflict { .. } => panic!("conflicts should be handled by the caller"),
+            FileType::Placeholder { .. } => panic!("placeholders should be handled by the caller"),
+            FileType::Unmaterialized => panic!("unmaterialized files aren't found on disk"),
         }
     }
 
@@ -673,6 +1722,62 @@ impl TreeState {
         executable: bool,
     ) -> Result<FileState, CheckoutError> {
         create_parent_dirs(&self.working_copy_path, path)?;
+        let content_filters = self.content_filters.as_ref().filter(|f| f.smudges(path));
+        if let Some(content_filters) = content_filters {
+            // Filtered files are written in full, since the cache and placeholders
+            // hold the stored contents.
+            let mut contents = vec![];
+            self.store
+                .read_file(path, id)?
+                .read_to_end(&mut contents)
+                .map_err(|err| CheckoutError::IoError {
+                    message: format!("Failed to read file {}", path.to_internal_file_string()),
+                    err,
+                })?;
+            let contents = content_filters.smudge(path, contents)?;
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true) // Don't overwrite un-ignored file. Don't follow symlink.
+                .open(disk_path)
+                .map_err(|err| CheckoutError::IoError {
+                    message: format!("Failed to open file {} for writing", disk_path.display()),
+                    err,
+                })?;
+            file.write_all(&contents)
+                .map_err(|err| CheckoutError::IoError {
+                    message: format!("Failed to write file {}", disk_path.display()),
+                    err,
+                })?;
+            self.set_executable(disk_path, executable)?;
+            let metadata = file
+                .metadata()
+                .map_err(|err| CheckoutError::for_stat_error(err, disk_path))?;
+            return Ok(FileState::for_file(
+                executable,
+                contents.len() as u64,
+                &metadata,
+            ));
+        }
+        if let Some(content_cache) = &self.content_cache {
+            let maybe_file = content_cache
+                .clone_to(&self.store, path, id, disk_path)
+                .map_err(|err| match err {
+                    ContentCacheError::BackendError(err) => {
+                        CheckoutError::InternalBackendError(err)
+                    }
+                    ContentCacheError::IoError(err) => CheckoutError::IoError {
+                        message: format!("Failed to clone file {}", disk_path.display()),
+                        err,
+                    },
+                })?;
+            if let Some(file) = maybe_file {
+                self.set_executable(disk_path, executable)?;
+                let metadata = file
+                    .metadata()
+                    .map_err(|err| CheckoutError::for_stat_error(err, disk_path))?;
+                return Ok(FileState::for_file(executable, metadata.len(), &metadata));
+            }
+        }
         let mut file = OpenOptions::new()
             .write(true)
             .create_new(true) // Don't overwrite un-ignored file. Don't follow symlink.
@@ -682,11 +1787,39 @@ impl TreeState {
                 err,
             })?;
         let mut contents = self.store.read_file(path, id)?;
-        let size =
-            std::io::copy(&mut contents, &mut file).map_err(|err| CheckoutError::IoError {
-                message: format!("Failed to write file {}", disk_path.display()),
-                err,
-            })?;
+        let write_error = |err| CheckoutError::IoError {
+            message: format!("Failed to write file {}", disk_path.display()),
+            err,
+        };
+        let mut size = 0;
+        if let Some(lazy_threshold) = self.lazy_threshold {
+            let mut head = vec![];
+            (&mut contents)
+                .take(lazy_threshold + 1)
+                .read_to_end(&mut head)
+                .map_err(write_error)?;
+            if head.len() as u64 > lazy_threshold {
+                let placeholder = placeholder_contents(path);
+                file.write_all(&placeholder).map_err(write_error)?;
+                self.set_executable(disk_path, executable)?;
+                let metadata = file
+                    .metadata()
+                    .map_err(|err| CheckoutError::for_stat_error(err, disk_path))?;
+                return Ok(FileState {
+                    file_type: FileType::Placeholder {
+                        id: id.clone(),
+                        executable,
+                    },
+                    mtime: mtime_from_metadata(&metadata),
+                    size: placeholder.len() as u64,
+                    inode: inode_from_metadata(&metadata),
+                    ctime: ctime_from_metadata(&metadata),
+                });
+            }
+            file.write_all(&head).map_err(write_error)?;
+            size += head.len() as u64;
+        }
+        size += std::io::copy(&mut contents, &mut file).map_err(write_error)?;
         self.set_executable(disk_path, executable)?;
         // Read the file state from the file descriptor. That way, know that the file
         // exists and is of the expected type, and the stat information is most likely
@@ -773,30 +1906,337 @@ impl TreeState {
         Ok(())
     }
 
-    pub fn check_out(&mut self, new_tree: &Tree) -> Result<CheckoutStats, CheckoutError> {
-        let old_tree = self
-            .store
-            .get_tree(&RepoPath::root(), &self.tree_id)
+    fn get_tree_for_checkout(&self, tree_id: &TreeId) -> Result<Tree, CheckoutError> {
+        self.store
+            .get_tree(&RepoPath::root(), tree_id)
             .map_err(|err| match err {
                 BackendError::NotFound => CheckoutError::SourceNotFound,
                 other => CheckoutError::InternalBackendError(other),
-            })?;
-        let stats = self.update(&old_tree, new_tree, self.sparse_matcher().as_ref(), Err)?;
+            })
+    }
+
+    pub fn check_out(&mut self, new_tree: &Tree) -> Result<CheckoutStats, CheckoutError> {
+        if let Some(pending_checkout) = &self.pending_checkout {
+            if pending_checkout.new_tree_id == *new_tree.id() {
+                return self.resume_checkout();
+            }
+            self.roll_back_checkout()?;
+        }
+        let old_tree = self.get_tree_for_checkout(&self.tree_id)?;
+        if old_tree.id() == new_tree.id() {
+            return Ok(CheckoutStats {
+                updated_files: 0,
+                added_files: 0,
+                removed_files: 0,
+                backed_up_files: vec![],
+                skipped_files: vec![],
+            });
+        }
+        self.timings = WorkingCopyTimings::default();
+        if !self.discard_changes {
+            let stat_start = Instant::now();
+            let paths = self.dirty_files(new_tree)?;
+            if !paths.is_empty() {
+                return Err(CheckoutError::DirtyFiles { paths });
+            }
+            self.timings.stat = stat_start.elapsed();
+        }
+        self.pending_checkout = Some(PendingCheckout {
+            old_tree_id: old_tree.id().clone(),
+            new_tree_id: new_tree.id().clone(),
+            completed_entries: 0,
+        });
+        self.save()?;
+        let write_start = Instant::now();
+        let stats = self.apply_checkout(&old_tree, new_tree, 0, false)?;
+        self.timings.write = write_start.elapsed();
         self.tree_id = new_tree.id().clone();
+        self.pending_checkout = None;
         Ok(stats)
     }
 
+    /// Applies the remainder of an interrupted checkout.
+    pub fn resume_checkout(&mut self) -> Result<CheckoutStats, CheckoutError> {
+        let pending_checkout = self
+            .pending_checkout
+            .clone()
+            .expect("no checkout to resume");
+        let old_tree = self.get_tree_for_checkout(&pending_checkout.old_tree_id)?;
+        let new_tree = self.get_tree_for_checkout(&pending_checkout.new_tree_id)?;
+        let stats = self.apply_checkout(
+            &old_tree,
+            &new_tree,
+            pending_checkout.completed_entries,
+            true,
+        )?;
+        self.tree_id = new_tree.id().clone();
+        self.pending_checkout = None;
+        Ok(stats)
+    }
+
+    /// Undoes the part of an interrupted checkout that may have been applied,
+    /// leaving the working copy at the tree it had before the checkout.
+    pub fn roll_back_checkout(&mut self) -> Result<CheckoutStats, CheckoutError> {
+        let pending_checkout = self
+            .pending_checkout
+            .clone()
+            .expect("no checkout to roll back");
+        let old_tree = self.get_tree_for_checkout(&pending_checkout.old_tree_id)?;
+        let new_tree = self.get_tree_for_checkout(&pending_checkout.new_tree_id)?;
+        let maybe_applied_entries =
+            pending_checkout.completed_entries + CHECKOUT_CHECKPOINT_INTERVAL;
+        let maybe_applied: Vec<_> = old_tree
+            .diff(&new_tree, self.sparse_matcher().as_ref())
+            .take(usize::try_from(maybe_applied_entries).unwrap_or(usize::MAX))
+            .collect();
+        let mut stats = CheckoutStats {
+            updated_files: 0,
+            added_files: 0,
+            removed_files: 0,
+            backed_up_files: vec![],
+            skipped_files: vec![],
+        };
+        // Undo the changes in reverse order so e.g. a file that replaced a directory
+        // is removed before the files in the directory are restored.
+        for (path, diff) in maybe_applied.into_iter().rev() {
+            let inverse_diff = match diff {
+                Diff::Added(after) => Diff::Removed(after),
+                Diff::Removed(before) => Diff::Added(before),
+                Diff::Modified(before, after) => Diff::Modified(after, before),
+            };
+            self.apply_diff(path, inverse_diff, true, &mut stats)?;
+        }
+        self.tree_id = old_tree.id().clone();
+        self.pending_checkout = None;
+        Ok(stats)
+    }
+
+    /// Applies the diff from `old_tree` to `new_tree`, skipping the first
+    /// `skip_entries` entries and saving the progress to
+    /// `self.pending_checkout` every `CHECKOUT_CHECKPOINT_INTERVAL` entries.
+    fn apply_checkout(
+        &mut self,
+        old_tree: &Tree,
+        new_tree: &Tree,
+        skip_entries: u64,
+        resuming: bool,
+    ) -> Result<CheckoutStats, CheckoutError> {
+        let mut stats = CheckoutStats {
+            updated_files: 0,
+            added_files: 0,
+            removed_files: 0,
+            backed_up_files: vec![],
+            skipped_files: vec![],
+        };
+        let sparse_matcher = self.sparse_matcher();
+        let mut backup_subdir = None;
+        for (index, (path, diff)) in (0_u64..).zip(old_tree.diff(new_tree, sparse_matcher.as_ref()))
+        {
+            if index < skip_entries {
+                continue;
+            }
+            // Entries after the last checkpoint of an interrupted checkout may or may not
+            // have been applied.
+            let tolerant = resuming && index < skip_entries + CHECKOUT_CHECKPOINT_INTERVAL;
+            let result = match &diff {
+                Diff::Added(_) if !tolerant => {
+                    self.back_up_untracked_file(&path, &mut backup_subdir, &mut stats)
+                }
+                _ => Ok(()),
+            };
+            let result = result.and_then(|()| self.apply_diff(path, diff, tolerant, &mut stats));
+            let completed_entries = match result {
+                Ok(()) => index + 1,
+                Err(_) => index,
+            };
+            if result.is_err() || completed_entries % CHECKOUT_CHECKPOINT_INTERVAL == 0 {
+                self.pending_checkout.as_mut().unwrap().completed_entries = completed_entries;
+                self.save()?;
+            }
+            result?;
+        }
+        Ok(stats)
+    }
+
+    /// Moves an untracked file at `path` out of the way of a file that's about
+    /// to be added there, if backups are enabled. The backups made by a single
+    /// checkout go into the same directory, which is created on the first
+    /// backup and stored in `backup_subdir`.
+    fn back_up_untracked_file(
+        &self,
+        path: &RepoPath,
+        backup_subdir: &mut Option<PathBuf>,
+        stats: &mut CheckoutStats,
+    ) -> Result<(), CheckoutError> {
+        let backup_dir = match &self.backup_dir {
+            Some(backup_dir) => backup_dir,
+            None => return Ok(()),
+        };
+        let disk_path = path.to_fs_path(&self.working_copy_path);
+        match disk_path.symlink_metadata() {
+            Ok(metadata) if !metadata.is_dir() => {}
+            _ => return Ok(()),
+        }
+        let backup_subdir = match backup_subdir {
+            Some(backup_subdir) => backup_subdir,
+            None => backup_subdir.insert(new_backup_subdir(backup_dir)?),
+        };
+        let backup_path = path.to_fs_path(backup_subdir);
+        let io_error = |message: String| move |err| CheckoutError::IoError { message, err };
+        fs::create_dir_all(backup_path.parent().unwrap()).map_err(io_error(format!(
+            "Failed to create backup directory for {}",
+            disk_path.display()
+        )))?;
+        fs::rename(&disk_path, &backup_path).map_err(io_error(format!(
+            "Failed to move {} to {}",
+            disk_path.display(),
+            backup_path.display()
+        )))?;
+        stats.backed_up_files.push(backup_path);
+        Ok(())
+    }
+
+    /// Returns the state of the file at `disk_path` if it already has the
+    /// contents of `value`.
+    fn file_state_if_matches(
+        &self,
+        disk_path: &Path,
+        path: &RepoPath,
+        value: &TreeValue,
+    ) -> Result<Option<FileState>, CheckoutError> {
+        let metadata = match disk_path.symlink_metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => return Ok(None),
+        };
+        let read_disk_file = || {
+            fs::read(disk_path).map_err(|err| CheckoutError::IoError {
+                message: format!("Failed to read file {}", disk_path.display()),
+                err,
+            })
+        };
+        match value {
+            TreeValue::Normal { id, executable } if metadata.is_file() => {
+                if file_state(&metadata).map(|state| state.file_type)
+                    != Some(FileType::Normal {
+                        executable: *executable,
+                    })
+                    && cfg!(unix)
+                {
+                    return Ok(None);
+                }
+                let mut expected = vec![];
+                self.store
+                    .read_file(path, id)?
+                    .read_to_end(&mut expected)
+                    .map_err(|err| CheckoutError::IoError {
+                        message: format!(
+                            "Failed to read file {} from store",
+                            path.to_internal_file_string()
+                        ),
+                        err,
+                    })?;
+                if read_disk_file()? != expected {
+                    return Ok(None);
+                }
+                Ok(Some(FileState::for_file(
+                    *executable,
+                    expected.len() as u64,
+                    &metadata,
+                )))
+            }
+            TreeValue::Symlink(id) if metadata.file_type().is_symlink() => {
+                let target = self.store.read_symlink(path, id)?;
+                match disk_path.read_link() {
+                    Ok(disk_target) if disk_target.as_path() == Path::new(&target) => {
+                        Ok(Some(FileState::for_symlink(&metadata)))
+                    }
+                    _ => Ok(None),
+                }
+            }
+            TreeValue::Conflict(id) if metadata.is_file() => {
+                let conflict = self.store.read_conflict(path, id)?;
+                let mut expected = vec![];
+                materialize_conflict(self.store.as_ref(), path, &conflict, &mut expected)
+                    .expect("Failed to materialize conflict to in-memory buffer");
+                if read_disk_file()? != expected {
+                    return Ok(None);
+                }
+                Ok(Some(FileState::for_conflict(
+                    id.clone(),
+                    expected.len() as u64,
+                    &metadata,
+                )))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the files that checking out `new_tree` would overwrite or
+    /// remove but that were modified on disk since the last snapshot.
+    pub fn dirty_files(&self, new_tree: &Tree) -> Result<Vec<RepoPath>, CheckoutError> {
+        let old_tree = self.get_tree_for_checkout(&self.tree_id)?;
+        let mut dirty_files = vec![];
+        for (path, diff) in old_tree.diff(new_tree, self.sparse_matcher().as_ref()) {
+            let before = match &diff {
+                Diff::Modified(before, _) | Diff::Removed(before) => before,
+                Diff::Added(_) => continue,
+            };
+            if self.is_dirty(&path, before)? {
+                dirty_files.push(path);
+            }
+        }
+        Ok(dirty_files)
+    }
+
+    /// Whether the file at `path`, which had the value `before` at the last
+    /// snapshot, has been modified since. Deleted files don't count as
+    /// modified, since checking out over them doesn't lose anything.
+    fn is_dirty(&self, path: &RepoPath, before: &TreeValue) -> Result<bool, CheckoutError> {
+        let recorded = match self.file_states.get(path) {
+            Some(recorded) => recorded,
+            None => return Ok(false),
+        };
+        let disk_path = path.to_fs_path(&self.working_copy_path);
+        let current = match disk_path.symlink_metadata() {
+            Ok(metadata) => match file_state(&metadata) {
+                Some(current) => current,
+                // Replaced by a directory
+                None => return Ok(true),
+            },
+            Err(_) => return Ok(false),
+        };
+        if let FileType::Placeholder { .. } = &recorded.file_type {
+            return Ok(current.size != recorded.size
+                || fs::read(&disk_path).ok() != Some(placeholder_contents(path)));
+        }
+        // Like when snapshotting, the metadata can only be trusted if the file
+        // wasn't modified around the time of the last snapshot. Filtered files
+        // can't be compared to the stored contents, so they have to trust it.
+        let filtered = self
+            .content_filters
+            .as_ref()
+            .map_or(false, |f| f.smudges(path));
+        if recorded.matches_disk_state(&current, self.trust_ctime)
+            && (filtered || recorded.mtime < self.own_mtime)
+        {
+            return Ok(false);
+        }
+        if filtered {
+            return Ok(true);
+        }
+        Ok(self
+            .file_state_if_matches(&disk_path, path, before)?
+            .is_none())
+    }
+
     pub fn set_sparse_patterns(
         &mut self,
         sparse_patterns: Vec<RepoPath>,
     ) -> Result<CheckoutStats, CheckoutError> {
-        let tree = self
-            .store
-            .get_tree(&RepoPath::root(), &self.tree_id)
-            .map_err(|err| match err {
-                BackendError::NotFound => CheckoutError::SourceNotFound,
-                other => CheckoutError::InternalBackendError(other),
-            })?;
+        if self.pending_checkout.is_some() {
+            self.roll_back_checkout()?;
+        }
+        let tree = self.get_tree_for_checkout(&self.tree_id)?;
         let old_matcher = PrefixMatcher::new(&self.sparse_patterns);
         let new_matcher = PrefixMatcher::new(&sparse_patterns);
         let added_matcher = DifferenceMatcher::new(&new_matcher, &old_matcher);
@@ -818,6 +2258,8 @@ impl TreeState {
             updated_files: 0,
             added_files: added_stats.added_files,
             removed_files: removed_stats.removed_files,
+            backed_up_files: vec![],
+            skipped_files: vec![],
         })
     }
 
@@ -832,91 +2274,197 @@ impl TreeState {
             updated_files: 0,
             added_files: 0,
             removed_files: 0,
+            backed_up_files: vec![],
+            skipped_files: vec![],
         };
-        let mut apply_diff = |path: RepoPath, diff: Diff<TreeValue>| -> Result<(), CheckoutError> {
-            let disk_path = path.to_fs_path(&self.working_copy_path);
+        for (path, diff) in old_tree.diff(new_tree, matcher) {
+            self.apply_diff(path, diff, false, &mut stats)
+                .or_else(&mut handle_error)?;
+        }
+        Ok(stats)
+    }
 
-            // TODO: Check that the file has not changed before overwriting/removing it.
-            match diff {
-                Diff::Removed(_before) => {
+    /// Applies a single diff entry to the working copy and the file states.
+    ///
+    /// If `tolerant` is set, the file on disk may already be in the state after
+    /// the diff (because an interrupted checkout applied it but didn't record
+    /// that). In that case, it's recorded as such rather than treated as a
+    /// conflicting untracked file.
+    fn apply_diff(
+        &mut self,
+        path: RepoPath,
+        diff: Diff<TreeValue>,
+        tolerant: bool,
+        stats: &mut CheckoutStats,
+    ) -> Result<(), CheckoutError> {
+        let disk_path = windows_paths::long_path_safe(path.to_fs_path(&self.working_copy_path));
+        if !matches!(diff, Diff::Removed(_)) && self.windows_safe_paths {
+            if let Some(issue) = windows_paths::check_path(&path) {
+                if let Diff::Modified(..) = diff {
                     fs::remove_file(&disk_path).ok();
-                    let mut parent_dir = disk_path.parent().unwrap();
-                    loop {
-                        if fs::remove_dir(&parent_dir).is_err() {
-                            break;
-                        }
-                        parent_dir = parent_dir.parent().unwrap();
+                }
+                self.set_file_state(path.clone(), FileState::unmaterialized());
+                stats.skipped_files.push((path, issue));
+                return Ok(());
+            }
+        }
+        if tolerant {
+            match &diff {
+                Diff::Removed(before) => {
+                    if disk_path.symlink_metadata().is_ok()
+                        && self
+                            .file_state_if_matches(&disk_path, &path, before)?
+                            .is_none()
+                    {
+                        // The file has something else in it, so leave it alone.
+                        self.remove_file_state(&path);
+                        stats.removed_files += 1;
+                        return Ok(());
                     }
-                    self.file_states.remove(&path);
-                    stats.removed_files += 1;
                 }
-                Diff::Added(after) => {
-                    let file_state = match after {
-                        TreeValue::Normal { id, executable } => {
-                            self.write_file(&disk_path, &path, &id, executable)?
-                        }
-                        TreeValue::Symlink(id) => self.write_symlink(&disk_path, &path, &id)?,
-                        TreeValue::Conflict(id) => self.write_conflict(&disk_path, &path, &id)?,
-                        TreeValue::GitSubmodule(_id) => {
-                            println!("ignoring git submodule at {:?}", path);
-                            return Ok(());
-                        }
-                        TreeValue::Tree(_id) => {
-                            panic!("unexpected tree entry in diff at {:?}", path);
+                Diff::Added(after) | Diff::Modified(_, after) => {
+                    if let Some(file_state) =
+                        self.file_state_if_matches(&disk_path, &path, after)?
+                    {
+                        if matches!(diff, Diff::Added(_)) {
+                            stats.added_files += 1;
+                        } else {
+                            stats.updated_files += 1;
                         }
-                    };
-                    self.file_states.insert(path, file_state);
-                    stats.added_files += 1;
-                }
-                Diff::Modified(
-                    TreeValue::Normal {
-                        id: old_id,
-                        executable: old_executable,
-                    },
-                    TreeValue::Normal { id, executable },
-                ) if id == old_id => {
-                    // Optimization for when only the executable bit changed
-                    assert_ne!(executable, old_executable);
-                    self.set_executable(&disk_path, executable)?;
-                    let file_state = self.file_states.get_mut(&path).unwrap();
-                    file_state.mark_executable(executable);
-                    stats.updated_files += 1;
+                        self.set_file_state(path, file_state);
+                        return Ok(());
+                    }
                 }
-                Diff::Modified(before, after) => {
-                    fs::remove_file(&disk_path).ok();
-                    let file_state = match (before, after) {
-                        (_, TreeValue::Normal { id, executable }) => {
-                            self.write_file(&disk_path, &path, &id, executable)?
-                        }
-                        (_, TreeValue::Symlink(id)) => {
-                            self.write_symlink(&disk_path, &path, &id)?
-                        }
-                        (_, TreeValue::Conflict(id)) => {
-                            self.write_conflict(&disk_path, &path, &id)?
-                        }
-                        (_, TreeValue::GitSubmodule(_id)) => {
-                            println!("ignoring git submodule at {:?}", path);
-                            self.file_states.remove(&path);
-                            return Ok(());
-                        }
-                        (_, TreeValue::Tree(_id)) => {
-                            panic!("unexpected tree entry in diff at {:?}", path);
-                        }
-                    };
+            }
+        }
 
-                    self.file_states.insert(path, file_state);
-                    stats.updated_files += 1;
+        match diff {
+            Diff::Removed(_before) => {
+                fs::remove_file(&disk_path).ok();
+                let mut parent_dir = disk_path.parent().unwrap();
+                loop {
+                    if fs::remove_dir(&parent_dir).is_err() {
+                        break;
+                    }
+                    parent_dir = parent_dir.parent().unwrap();
                 }
+                self.remove_file_state(&path);
+                stats.removed_files += 1;
             }
-            Ok(())
-        };
+            Diff::Added(after) => {
+                let file_state = match after {
+                    TreeValue::Normal { id, executable } => {
+                        self.write_file(&disk_path, &path, &id, executable)?
+                    }
+                    TreeValue::Symlink(id) => self.write_symlink(&disk_path, &path, &id)?,
+                    TreeValue::Conflict(id) => self.write_conflict(&disk_path, &path, &id)?,
+                    TreeValue::GitSubmodule(_id) => {
+                        println!("ignoring git submodule at {:?}", path);
+                        return Ok(());
+                    }
+                    TreeValue::Tree(_id) => {
+                        panic!("unexpected tree entry in diff at {:?}", path);
+                    }
+                };
+                self.set_file_state(path, file_state);
+                stats.added_files += 1;
+            }
+            Diff::Modified(
+                TreeValue::Normal {
+                    id: old_id,
+                    executable: old_executable,
+                },
+                TreeValue::Normal { id, executable },
+            ) if id == old_id => {
+                // Optimization for when only the executable bit changed
+                assert_ne!(executable, old_executable);
+                self.set_executable(&disk_path, executable)?;
+                let mut file_state = self.file_states[&path].clone();
+                file_state.mark_executable(executable);
+                self.set_file_state(path, file_state);
+                stats.updated_files += 1;
+            }
+            Diff::Modified(before, after) => {
+                fs::remove_file(&disk_path).ok();
+                let file_state = match (before, after) {
+                    (_, TreeValue::Normal { id, executable }) => {
+                        self.write_file(&disk_path, &path, &id, executable)?
+                    }
+                    (_, TreeValue::Symlink(id)) => self.write_symlink(&disk_path, &path, &id)?,
+                    (_, TreeValue::Conflict(id)) => self.write_conflict(&disk_path, &path, &id)?,
+                    (_, TreeValue::GitSubmodule(_id)) => {
+                        println!("ignoring git submodule at {:?}", path);
+                        self.remove_file_state(&path);
+                        return Ok(());
+                    }
+                    (_, TreeValue::Tree(_id)) => {
+                        panic!("unexpected tree entry in diff at {:?}", path);
+                    }
+                };
 
-        for (path, diff) in old_tree.diff(new_tree, matcher) {
-            apply_diff(path, diff).or_else(&mut handle_error)?;
+                self.set_file_state(path, file_state);
+                stats.updated_files += 1;
+            }
         }
+        Ok(())
+    }
+
+    /// Writes the actual contents of the placeholder files matching `matcher`.
+    /// Placeholders that have been modified are left alone.
+    pub fn hydrate(&mut self, matcher: &dyn Matcher) -> Result<CheckoutStats, CheckoutError> {
+        let placeholders = self
+            .file_states
+            .iter()
+            .filter_map(|(path, file_state)| match &file_state.file_type {
+                FileType::Placeholder { id, executable } if matcher.matches(path) => {
+                    Some((path.clone(), id.clone(), *executable))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let mut stats = CheckoutStats {
+            updated_files: 0,
+            added_files: 0,
+            removed_files: 0,
+            backed_up_files: vec![],
+            skipped_files: vec![],
+        };
+        let lazy_threshold = self.lazy_threshold.take();
+        let mut result = Ok(());
+        for (path, id, executable) in placeholders {
+            match self.hydrate_file(&path, &id, executable) {
+                Ok(true) => stats.updated_files += 1,
+                Ok(false) => {}
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        self.lazy_threshold = lazy_threshold;
+        result?;
         Ok(stats)
     }
 
+    fn hydrate_file(
+        &mut self,
+        path: &RepoPath,
+        id: &FileId,
+        executable: bool,
+    ) -> Result<bool, CheckoutError> {
+        let disk_path = path.to_fs_path(&self.working_copy_path);
+        if fs::read(&disk_path).ok() != Some(placeholder_contents(path)) {
+            return Ok(false);
+        }
+        fs::remove_file(&disk_path).map_err(|err| CheckoutError::IoError {
+            message: format!("Failed to remove placeholder {}", disk_path.display()),
+            err,
+        })?;
+        let file_state = self.write_file(&disk_path, path, id, executable)?;
+        self.set_file_state(path.clone(), file_state);
+        Ok(true)
+    }
+
     pub fn reset(&mut self, new_tree: &Tree) -> Result<(), ResetError> {
         let old_tree = self
             .store
@@ -929,11 +2477,28 @@ impl TreeState {
         for (path, diff) in old_tree.diff(new_tree, self.sparse_matcher().as_ref()) {
             match diff {
                 Diff::Removed(_before) => {
-                    self.file_states.remove(&path);
+                    self.remove_file_state(&path);
+                }
+                Diff::Added(_) | Diff::Modified(..)
+                    if self.windows_safe_paths && windows_paths::check_path(&path).is_some() =>
+                {
+                    self.set_file_state(path, FileState::unmaterialized());
                 }
                 Diff::Added(after) | Diff::Modified(_, after) => {
                     let file_type = match after {
-                        TreeValue::Normal { id: _, executable } => FileType::Normal { executable },
+                        TreeValue::Normal { id, executable } => {
+                            // A placeholder file doesn't have any particular contents, so it
+                            // can stand in for the new contents too.
+                            if let Some(file_state) = self.file_states.get(&path) {
+                                if let FileType::Placeholder { .. } = &file_state.file_type {
+                                    let mut file_state = file_state.clone();
+                                    file_state.file_type = FileType::Placeholder { id, executable };
+                                    self.set_file_state(path, file_state);
+                                    continue;
+                                }
+                            }
+                            FileType::Normal { executable }
+                        }
                         TreeValue::Symlink(_id) => FileType::Symlink,
                         TreeValue::Conflict(id) => FileType::Conflict { id },
                         TreeValue::GitSubmodule(_id) => {
@@ -948,14 +2513,75 @@ impl TreeState {
                         file_type,
                         mtime: MillisSinceEpoch(0),
                         size: 0,
+                        inode: 0,
+                        ctime: MillisSinceEpoch(0),
                     };
-                    self.file_states.insert(path.clone(), file_state);
+                    self.set_file_state(path.clone(), file_state);
                 }
             }
         }
         self.tree_id = new_tree.id().clone();
+        // Whatever state the files were left in, it will be picked up by the next
+        // snapshot.
+        self.pending_checkout = None;
         Ok(())
     }
+
+    /// Makes the state refer to `new_tree`, which has the same contents as the
+    /// current tree but is from another store (after the repo was migrated to
+    /// another backend). Unlike `reset()`, this doesn't read the current tree.
+    pub fn replace_tree(&mut self, new_tree: &Tree) {
+        let mut replaced_file_states = vec![];
+        for (path, file_state) in &self.file_states {
+            let mut file_state = file_state.clone();
+            match (&mut file_state.file_type, new_tree.path_value(path)) {
+                (FileType::Placeholder { id, .. }, Some(TreeValue::Normal { id: new_id, .. })) => {
+                    *id = new_id;
+                }
+                (FileType::Conflict { id }, Some(TreeValue::Conflict(new_id))) => {
+                    *id = new_id;
+                }
+                _ => continue,
+            }
+            replaced_file_states.push((path.clone(), file_state));
+        }
+        for (path, file_state) in replaced_file_states {
+            self.set_file_state(path, file_state);
+        }
+        self.tree_id = new_tree.id().clone();
+        self.pending_checkout = None;
+    }
+}
+
+/// What was done about a `LockedWorkingCopy::finish()` call that was
+/// interrupted before all the state files were written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FinishRecovery {
+    /// The new tree state had already been written, so the operation ID was
+    /// recorded too.
+    RolledForward(OperationId),
+    /// The new tree state had not been written, so the previous state was
+    /// kept. Any files that had been updated on disk will be picked up by the
+    /// next snapshot.
+    RolledBack,
+}
+
+/// The result of `WorkingCopy::repair()`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct WorkingCopyRepair {
+    pub finish_recovery: Option<FinishRecovery>,
+    /// The `tree_state` file could not be parsed and was moved aside to
+    /// `tree_state.corrupt`. The working copy now has the empty tree recorded,
+    /// so the caller should reset it to the tree it's expected to have.
+    pub tree_state_discarded: bool,
+}
+
+/// A snapshot of the working copy that was taken without holding the lock.
+struct Prescan {
+    /// The tree recorded in the state the snapshot started from
+    base_tree_id: TreeId,
+    changed: bool,
+    tree_state: TreeState,
 }
 
 pub struct WorkingCopy {
@@ -965,6 +2591,20 @@ pub struct WorkingCopy {
     operation_id: RefCell<Option<OperationId>>,
     workspace_id: RefCell<Option<WorkspaceId>>,
     tree_state: OnceCell<TreeState>,
+    content_cache: Option<Arc<ContentCache>>,
+    lazy_threshold: Option<u64>,
+    snapshot_mode: SnapshotMode,
+    unicode_normalization: UnicodeNormalization,
+    trust_ctime: bool,
+    content_filters: Option<Arc<ContentFilters>>,
+    line_ending_policy: LineEndingPolicy,
+    never_track: Arc<GitIgnoreFile>,
+    use_dir_cache: bool,
+    max_snapshot_files: Option<usize>,
+    windows_safe_paths: bool,
+    backup_dir: Option<PathBuf>,
+    discard_changes: bool,
+    prescan: Option<Prescan>,
 }
 
 impl WorkingCopy {
@@ -994,6 +2634,20 @@ impl WorkingCopy {
             operation_id: RefCell::new(Some(operation_id)),
             workspace_id: RefCell::new(Some(workspace_id)),
             tree_state: OnceCell::new(),
+            content_cache: None,
+            lazy_threshold: None,
+            snapshot_mode: SnapshotMode::Default,
+            unicode_normalization: UnicodeNormalization::default(),
+            trust_ctime: true,
+            content_filters: None,
+            line_ending_policy: LineEndingPolicy::default(),
+            never_track: GitIgnoreFile::empty(),
+            use_dir_cache: false,
+            max_snapshot_files: None,
+            windows_safe_paths: cfg!(windows),
+            backup_dir: None,
+            discard_changes: false,
+            prescan: None,
         }
     }
 
@@ -1005,6 +2659,20 @@ impl WorkingCopy {
             operation_id: RefCell::new(None),
             workspace_id: RefCell::new(None),
             tree_state: OnceCell::new(),
+            content_cache: None,
+            lazy_threshold: None,
+            snapshot_mode: SnapshotMode::Default,
+            unicode_normalization: UnicodeNormalization::default(),
+            trust_ctime: true,
+            content_filters: None,
+            line_ending_policy: LineEndingPolicy::default(),
+            never_track: GitIgnoreFile::empty(),
+            use_dir_cache: false,
+            max_snapshot_files: None,
+            windows_safe_paths: cfg!(windows),
+            backup_dir: None,
+            discard_changes: false,
+            prescan: None,
         }
     }
 
@@ -1024,6 +2692,78 @@ impl WorkingCopy {
         temp_file.persist(self.state_path.join("checkout")).unwrap();
     }
 
+    fn journal_path(&self) -> PathBuf {
+        self.state_path.join("finish_journal")
+    }
+
+    fn write_journal(
+        &self,
+        operation_id: &OperationId,
+        tree_id: &TreeId,
+    ) -> Result<(), WorkingCopyStateError> {
+        let mut proto = crate::protos::working_copy::FinishJournal::new();
+        proto.operation_id = operation_id.to_bytes();
+        proto.tree_id = tree_id.to_bytes();
+        write_proto_file(&self.state_path, "finish_journal", &proto)
+    }
+
+    /// Completes or rolls back a `finish()` that was interrupted. The caller
+    /// must hold the working copy lock.
+    fn recover_interrupted_finish(&self) -> Option<FinishRecovery> {
+        let journal_path = self.journal_path();
+        let mut file = match File::open(&journal_path) {
+            Err(ref err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return None;
+            }
+            result => result.unwrap(),
+        };
+        let journal: Result<crate::protos::working_copy::FinishJournal, _> =
+            Message::parse_from_reader(&mut file);
+        let recovery = match journal {
+            Ok(journal)
+                if TreeState::read_tree_id(&self.state_path)
+                    == Some(TreeId::new(journal.tree_id.clone())) =>
+            {
+                let operation_id = OperationId::new(journal.operation_id);
+                let mut proto = crate::protos::working_copy::Checkout::new();
+                proto.operation_id = operation_id.to_bytes();
+                proto.workspace_id = self.workspace_id().as_str().to_string();
+                self.write_proto(proto);
+                FinishRecovery::RolledForward(operation_id)
+            }
+            // Either the tree state wasn't written yet, or the journal itself wasn't
+            // (completely) written, in which case nothing else was either.
+            _ => FinishRecovery::RolledBack,
+        };
+        fs::remove_file(&journal_path).unwrap();
+        Some(recovery)
+    }
+
+    /// Recovers from an interrupted update of the working copy's state files
+    /// and sets aside the tree state if it can't be read.
+    pub fn repair(&mut self) -> WorkingCopyRepair {
+        let lock = FileLock::lock(self.state_path.join("working_copy.lock"));
+        let finish_recovery = self.recover_interrupted_finish();
+        let tree_state_path = self.state_path.join("tree_state");
+        let tree_state_discarded =
+            tree_state_path.exists() && TreeState::read_tree_id(&self.state_path).is_none();
+        if tree_state_discarded {
+            fs::rename(&tree_state_path, self.state_path.join("tree_state.corrupt")).unwrap();
+            TreeState::init(
+                self.store.clone(),
+                self.working_copy_path.clone(),
+                self.state_path.clone(),
+            );
+        }
+        self.load_proto();
+        self.tree_state.take();
+        drop(lock);
+        WorkingCopyRepair {
+            finish_recovery,
+            tree_state_discarded,
+        }
+    }
+
     fn load_proto(&self) {
         let mut file = File::open(self.state_path.join("checkout")).unwrap();
         let proto: crate::protos::working_copy::Checkout =
@@ -1058,14 +2798,157 @@ impl WorkingCopy {
 
     fn tree_state(&self) -> &TreeState {
         self.tree_state.get_or_init(|| {
-            TreeState::load(
+            let mut tree_state = TreeState::load(
                 self.store.clone(),
                 self.working_copy_path.clone(),
                 self.state_path.clone(),
-            )
+            );
+            tree_state.set_content_cache(self.content_cache.clone());
+            tree_state.set_lazy_threshold(self.lazy_threshold);
+            tree_state.set_snapshot_mode(self.snapshot_mode);
+            tree_state.set_unicode_normalization(self.unicode_normalization);
+            tree_state.set_trust_ctime(self.trust_ctime);
+            tree_state.set_content_filters(self.content_filters.clone());
+            tree_state.set_line_ending_policy(self.line_ending_policy);
+            tree_state.set_never_track(self.never_track.clone());
+            tree_state.set_use_dir_cache(self.use_dir_cache);
+            tree_state.set_max_snapshot_files(self.max_snapshot_files);
+            tree_state.set_windows_safe_paths(self.windows_safe_paths);
+            tree_state.set_backup_dir(self.backup_dir.clone());
+            tree_state.set_discard_changes(self.discard_changes);
+            tree_state
         })
     }
 
+    /// Makes checkouts write placeholders instead of files larger than
+    /// `lazy_threshold` bytes.
+    pub fn set_lazy_threshold(&mut self, lazy_threshold: Option<u64>) {
+        self.lazy_threshold = lazy_threshold;
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_lazy_threshold(lazy_threshold);
+        }
+    }
+
+    /// Sets how far snapshots trust file metadata. See `SnapshotMode`.
+    pub fn set_snapshot_mode(&mut self, snapshot_mode: SnapshotMode) {
+        self.snapshot_mode = snapshot_mode;
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_snapshot_mode(snapshot_mode);
+        }
+    }
+
+    /// Sets how snapshots handle differently normalized paths. See
+    /// `UnicodeNormalization`.
+    pub fn set_unicode_normalization(&mut self, unicode_normalization: UnicodeNormalization) {
+        self.unicode_normalization = unicode_normalization;
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_unicode_normalization(unicode_normalization);
+        }
+    }
+
+    /// Sets whether a changed ctime means that a file has changed.
+    pub fn set_trust_ctime(&mut self, trust_ctime: bool) {
+        self.trust_ctime = trust_ctime;
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_trust_ctime(trust_ctime);
+        }
+    }
+
+    /// Sets the filters that transform file contents on snapshot and checkout.
+    pub fn set_content_filters(&mut self, content_filters: Option<Arc<ContentFilters>>) {
+        self.content_filters = content_filters.clone();
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_content_filters(content_filters);
+        }
+    }
+
+    /// Sets what snapshots do about files whose line endings or byte-order
+    /// mark changed wholesale. See `LineEndingPolicy`.
+    pub fn set_line_ending_policy(&mut self, line_ending_policy: LineEndingPolicy) {
+        self.line_ending_policy = line_ending_policy;
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_line_ending_policy(line_ending_policy);
+        }
+    }
+
+    /// Sets the paths that snapshots never look at. See
+    /// `TreeState::set_never_track()`.
+    pub fn set_never_track(&mut self, never_track: Arc<GitIgnoreFile>) {
+        self.never_track = never_track.clone();
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_never_track(never_track);
+        }
+    }
+
+    /// Sets whether snapshots reuse the entries of directories whose mtime
+    /// hasn't changed. See `TreeState::set_use_dir_cache()`.
+    pub fn set_use_dir_cache(&mut self, use_dir_cache: bool) {
+        self.use_dir_cache = use_dir_cache;
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_use_dir_cache(use_dir_cache);
+        }
+    }
+
+    /// Sets how many new or modified files a snapshot may record. See
+    /// `TreeState::set_max_snapshot_files()`.
+    pub fn set_max_snapshot_files(&mut self, max_snapshot_files: Option<usize>) {
+        self.max_snapshot_files = max_snapshot_files;
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_max_snapshot_files(max_snapshot_files);
+        }
+    }
+
+    /// Sets whether checkouts skip files whose path can't be used on Windows.
+    /// See `TreeState::set_windows_safe_paths()`.
+    pub fn set_windows_safe_paths(&mut self, windows_safe_paths: bool) {
+        self.windows_safe_paths = windows_safe_paths;
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_windows_safe_paths(windows_safe_paths);
+        }
+    }
+
+    /// Sets where checkouts move untracked files that are in the way. See
+    /// `TreeState::set_backup_dir()`.
+    pub fn set_backup_dir(&mut self, backup_dir: Option<PathBuf>) {
+        self.backup_dir = backup_dir.clone();
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_backup_dir(backup_dir);
+        }
+    }
+
+    /// Sets whether checkouts overwrite changes that haven't been snapshotted.
+    /// See `TreeState::set_discard_changes()`.
+    pub fn set_discard_changes(&mut self, discard_changes: bool) {
+        self.discard_changes = discard_changes;
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_discard_changes(discard_changes);
+        }
+    }
+
+    /// Returns the files that checking out `new_tree` would overwrite or
+    /// remove but that were modified since the last snapshot. See
+    /// `TreeState::dirty_files()`.
+    pub fn dirty_files(&self, new_tree: &Tree) -> Result<Vec<RepoPath>, CheckoutError> {
+        self.tree_state().dirty_files(new_tree)
+    }
+
+    /// Enables or disables cloning checked-out files from a cache of file
+    /// contents in the working copy's state directory, which is pruned to
+    /// `max_size` bytes after each checkout. This only helps on filesystems
+    /// that support reflinks; on others, the cache is left unused.
+    pub fn set_use_content_cache(&mut self, enabled: bool, max_size: u64) {
+        self.content_cache = if enabled {
+            ContentCache::new(self.state_path.join("content_cache"), max_size)
+                .ok()
+                .map(Arc::new)
+        } else {
+            None
+        };
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_content_cache(self.content_cache.clone());
+        }
+    }
+
     fn tree_state_mut(&mut self) -> &mut TreeState {
         self.tree_state(); // ensure loaded
         self.tree_state.get_mut().unwrap()
@@ -1083,6 +2966,18 @@ impl WorkingCopy {
         self.tree_state().sparse_patterns()
     }
 
+    /// Where the time of the last snapshot or checkout went, including saving
+    /// the state afterwards.
+    pub fn timings(&self) -> &WorkingCopyTimings {
+        self.tree_state().timings()
+    }
+
+    /// The checkout that was interrupted, if any. It will be resumed or rolled
+    /// back by the next checkout or snapshot.
+    pub fn pending_checkout(&self) -> Option<&PendingCheckout> {
+        self.tree_state().pending_checkout()
+    }
+
     fn save(&mut self) {
         let mut proto = crate::protos::working_copy::Checkout::new();
         proto.operation_id = self.operation_id().to_bytes();
@@ -1090,10 +2985,63 @@ impl WorkingCopy {
         self.write_proto(proto);
     }
 
+    /// Snapshots the working copy without taking the lock, so other processes
+    /// don't have to wait while we scan it. The next
+    /// `LockedWorkingCopy::snapshot()` uses the result if no other process has
+    /// updated the working copy's state since we read it. If one has, that
+    /// snapshot reloads the state and scans again; only the files that changed
+    /// since the other process's update need to be hashed again then.
+    pub fn prescan(&mut self, base_ignores: Arc<GitIgnoreFile>) {
+        self.prescan = None;
+        // Finishing an interrupted update of the state files, or rolling back an
+        // interrupted checkout, needs the lock, so leave that to the locked snapshot.
+        if self.journal_path().exists() {
+            return;
+        }
+        let file = match File::open(self.state_path.join("tree_state")) {
+            Ok(file) => file,
+            Err(_) => return,
+        };
+        let mut tree_state = TreeState::empty(
+            self.store.clone(),
+            self.working_copy_path.clone(),
+            self.state_path.clone(),
+        );
+        if !tree_state.read(file).unwrap_or(false) || tree_state.pending_checkout.is_some() {
+            return;
+        }
+        tree_state.set_content_cache(self.content_cache.clone());
+        tree_state.set_lazy_threshold(self.lazy_threshold);
+        tree_state.set_snapshot_mode(self.snapshot_mode);
+        tree_state.set_unicode_normalization(self.unicode_normalization);
+        tree_state.set_trust_ctime(self.trust_ctime);
+        tree_state.set_content_filters(self.content_filters.clone());
+        tree_state.set_line_ending_policy(self.line_ending_policy);
+        tree_state.set_never_track(self.never_track.clone());
+        tree_state.set_use_dir_cache(self.use_dir_cache);
+        tree_state.set_max_snapshot_files(self.max_snapshot_files);
+        tree_state.set_windows_safe_paths(self.windows_safe_paths);
+        tree_state.set_backup_dir(self.backup_dir.clone());
+        tree_state.set_discard_changes(self.discard_changes);
+        let base_tree_id = tree_state.current_tree_id().clone();
+        // Errors (e.g. because another process removed a file while we were
+        // scanning) will be reported by the locked snapshot if they persist.
+        if let Ok(changed) = tree_state.snapshot(base_ignores) {
+            self.prescan = Some(Prescan {
+                base_tree_id,
+                changed,
+                tree_state,
+            });
+        }
+    }
+
     pub fn start_mutation(&mut self) -> LockedWorkingCopy {
         let lock_path = self.state_path.join("working_copy.lock");
         let lock = FileLock::lock(lock_path);
 
+        // A previous process may have been killed while it was writing the state
+        // files, so fix that up before reading them.
+        self.recover_interrupted_finish();
         // Re-read from disk after taking the lock
         self.load_proto();
         // TODO: It's expensive to reload the whole tree. We should first check if it
@@ -1129,7 +3077,7 @@ impl WorkingCopy {
             }
         }
         let stats = locked_wc.check_out(new_tree)?;
-        locked_wc.finish(operation_id);
+        locked_wc.finish(operation_id)?;
         Ok(stats)
     }
 }
@@ -1161,16 +3109,42 @@ impl LockedWorkingCopy<'_> {
     // because the TreeState may be long-lived if the library is used in a
     // long-lived process.
     pub fn snapshot(&mut self, base_ignores: Arc<GitIgnoreFile>) -> Result<TreeId, SnapshotError> {
+        if let Some(prescan) = self.wc.prescan.take() {
+            let current = self.wc.tree_state();
+            if !self.tree_state_dirty
+                && prescan.base_tree_id == self.old_tree_id
+                && prescan.tree_state.generation == current.generation
+                && prescan.tree_state.own_mtime == current.own_mtime
+            {
+                let tree_id = prescan.tree_state.current_tree_id().clone();
+                self.wc.tree_state = OnceCell::from(prescan.tree_state);
+                self.tree_state_dirty |= prescan.changed;
+                return Ok(tree_id);
+            }
+        }
         let tree_state = self.wc.tree_state_mut();
         self.tree_state_dirty |= tree_state.snapshot(base_ignores)?;
         Ok(tree_state.current_tree_id().clone())
     }
 
+    /// The files whose line endings or byte-order mark the last `snapshot()`
+    /// found to have changed wholesale, and the changes.
+    pub fn line_ending_changes(&self) -> &[(RepoPath, Vec<LineEndingChange>)] {
+        self.wc.tree_state().line_ending_changes()
+    }
+
+    /// Where the time of the last `snapshot()` or `check_out()` went.
+    pub fn timings(&self) -> &WorkingCopyTimings {
+        self.wc.tree_state().timings()
+    }
+
     pub fn check_out(&mut self, new_tree: &Tree) -> Result<CheckoutStats, CheckoutError> {
-        // TODO: Write a "pending_checkout" file with the new TreeId so we can
-        // continue an interrupted update if we find such a file.
         let stats = self.wc.tree_state_mut().check_out(new_tree)?;
         self.tree_state_dirty = true;
+        if let Some(content_cache) = &self.wc.content_cache {
+            // Failing to prune only leaves the cache larger than configured.
+            content_cache.prune().ok();
+        }
         Ok(stats)
     }
 
@@ -1180,10 +3154,25 @@ impl LockedWorkingCopy<'_> {
         Ok(())
     }
 
+    pub fn replace_tree(&mut self, new_tree: &Tree) {
+        self.wc.tree_state_mut().replace_tree(new_tree);
+        self.tree_state_dirty = true;
+    }
+
     pub fn sparse_patterns(&self) -> &[RepoPath] {
         self.wc.sparse_patterns()
     }
 
+    pub fn pending_checkout(&self) -> Option<&PendingCheckout> {
+        self.wc.pending_checkout()
+    }
+
+    pub fn hydrate(&mut self, matcher: &dyn Matcher) -> Result<CheckoutStats, CheckoutError> {
+        let stats = self.wc.tree_state_mut().hydrate(matcher)?;
+        self.tree_state_dirty = true;
+        Ok(stats)
+    }
+
     pub fn set_sparse_patterns(
         &mut self,
         new_sparse_patterns: Vec<RepoPath>,
@@ -1198,24 +3187,52 @@ impl LockedWorkingCopy<'_> {
         Ok(stats)
     }
 
-    pub fn finish(mut self, operation_id: OperationId) {
+    /// Writes the changes to the working copy's state and releases the lock.
+    /// If writing fails, the lock is released too, and what was written is
+    /// completed or rolled back by the next `WorkingCopy::repair()`.
+    pub fn finish(mut self, operation_id: OperationId) -> Result<(), WorkingCopyStateError> {
         assert!(self.tree_state_dirty || &self.old_tree_id == self.wc.current_tree_id());
+        let result = self.write_state(operation_id);
+        if result.is_err() {
+            // Read whatever made it to disk when the state is used next
+            self.wc.tree_state.take();
+            self.wc.operation_id.replace(None);
+        }
+        // TODO: Clear the "pending_checkout" file here.
+        self.wc.prescan = None;
+        self.tree_state_dirty = false;
+        self.closed = true;
+        result
+    }
+
+    fn write_state(&mut self, operation_id: OperationId) -> Result<(), WorkingCopyStateError> {
+        // The tree state and the operation ID are written to separate files. Record
+        // what we're about to write so a process that gets killed halfway through
+        // doesn't leave them out of sync.
         if self.tree_state_dirty {
-            self.wc.tree_state_mut().save();
+            self.wc
+                .write_journal(&operation_id, self.wc.current_tree_id())?;
+            self.wc.tree_state_mut().save()?;
         }
         if self.old_operation_id != operation_id {
             self.wc.operation_id.replace(Some(operation_id));
             self.wc.save();
         }
-        // TODO: Clear the "pending_checkout" file here.
-        self.tree_state_dirty = false;
-        self.closed = true;
+        if self.tree_state_dirty {
+            let journal_path = self.wc.journal_path();
+            fs::remove_file(&journal_path).map_err(|err| WorkingCopyStateError {
+                message: format!("Failed to remove {}", journal_path.display()),
+                err,
+            })?;
+        }
+        Ok(())
     }
 
     pub fn discard(mut self) {
         // Undo the changes in memory
         self.wc.load_proto();
         self.wc.tree_state.take();
+        self.wc.prescan = None;
         self.tree_state_dirty = false;
         self.closed = true;
     }
@@ -20,9 +20,13 @@ use std::sync::Arc;
 use thiserror::Error;
 
 use crate::backend::Backend;
+use crate::backend_migration::PendingMigration;
 use crate::git_backend::GitBackend;
+use crate::gitignore::GitIgnoreFile;
 use crate::local_backend::LocalBackend;
+use crate::object_store::object_store_from_url;
 use crate::op_store::WorkspaceId;
+use crate::remote_backend::RemoteBackend;
 use crate::repo::{BackendFactories, ReadonlyRepo, RepoLoader};
 use crate::settings::UserSettings;
 use crate::working_copy::WorkingCopy;
@@ -31,6 +35,8 @@ use crate::working_copy::WorkingCopy;
 pub enum WorkspaceInitError {
     #[error("The destination repo ({0}) already exists")]
     DestinationExists(PathBuf),
+    #[error("Invalid object store: {0}")]
+    InvalidObjectStore(String),
 }
 
 #[derive(Error, Debug, PartialEq, Eq)]
@@ -39,6 +45,8 @@ pub enum WorkspaceLoadError {
     RepoDoesNotExist(PathBuf),
     #[error("There is no Jujutsu repo in {0}")]
     NoWorkspaceHere(PathBuf),
+    #[error("Failed to finish migrating the repo to another backend: {0}")]
+    MigrationFailed(String),
 }
 
 /// Represents a workspace, i.e. what's typically the .jj/ directory and its
@@ -131,6 +139,23 @@ impl Workspace {
         })
     }
 
+    /// Initializes a workspace with a backend that stores objects in the
+    /// object store at `url` (see `object_store::object_store_from_url()`)
+    pub fn init_remote(
+        user_settings: &UserSettings,
+        workspace_root: &Path,
+        url: &str,
+    ) -> Result<(Self, Arc<ReadonlyRepo>), WorkspaceInitError> {
+        // The backend factory can't return errors, so check the URL first. The
+        // backend doesn't talk to the object store when it's created, so only
+        // local I/O can fail after this.
+        object_store_from_url(url)
+            .map_err(|err| WorkspaceInitError::InvalidObjectStore(err.to_string()))?;
+        Self::init_with_backend(user_settings, workspace_root, |store_path| {
+            Box::new(RemoteBackend::init(store_path, url).unwrap())
+        })
+    }
+
     pub fn init_with_backend(
         user_settings: &UserSettings,
         workspace_root: &Path,
@@ -193,13 +218,64 @@ impl Workspace {
                 return Err(WorkspaceLoadError::RepoDoesNotExist(repo_dir));
             }
         }
+        let migration_error =
+            |err: &dyn std::fmt::Display| WorkspaceLoadError::MigrationFailed(err.to_string());
+        let pending_migration =
+            PendingMigration::load(&repo_dir).map_err(|err| migration_error(&err))?;
+        if let Some(pending_migration) = &pending_migration {
+            pending_migration
+                .swap_store()
+                .map_err(|err| migration_error(&err))?;
+        }
         let repo_loader = RepoLoader::init(user_settings, &repo_dir, backend_factories);
         let working_copy_state_path = jj_dir.join("working_copy");
-        let working_copy = WorkingCopy::load(
+        let mut working_copy = WorkingCopy::load(
             repo_loader.store().clone(),
             workspace_root.clone(),
             working_copy_state_path,
         );
+        working_copy.set_use_content_cache(
+            user_settings.use_content_cache(),
+            user_settings.content_cache_max_size(),
+        );
+        working_copy.set_lazy_threshold(user_settings.lazy_file_threshold());
+        working_copy.set_snapshot_mode(user_settings.snapshot_mode());
+        working_copy.set_unicode_normalization(user_settings.unicode_normalization());
+        working_copy.set_trust_ctime(user_settings.trust_ctime());
+        // Invalid values are reported by the caller; see `line_ending_policy()`.
+        working_copy.set_line_ending_policy(user_settings.line_ending_policy().unwrap_or_default());
+        working_copy.set_use_dir_cache(user_settings.use_dir_cache());
+        working_copy.set_max_snapshot_files(user_settings.max_snapshot_files());
+        working_copy.set_windows_safe_paths(user_settings.windows_safe_paths());
+        let never_track_patterns = user_settings.never_track_patterns();
+        if !never_track_patterns.is_empty() {
+            working_copy.set_never_track(
+                GitIgnoreFile::empty().chain("", never_track_patterns.join("\n").as_bytes()),
+            );
+        }
+        if user_settings.backup_overwritten_files() {
+            working_copy.set_backup_dir(Some(jj_dir.join("backup")));
+        }
+        if let Some(pending_migration) = pending_migration {
+            // The working copy still refers to the trees in the old store
+            let repo = repo_loader
+                .load_at_head_readonly()
+                .map_err(|err| migration_error(&err))?;
+            if let Some(wc_commit_id) = repo.view().get_wc_commit_id(&working_copy.workspace_id()) {
+                let wc_commit = repo
+                    .store()
+                    .get_commit(wc_commit_id)
+                    .map_err(|err| migration_error(&err))?;
+                let mut locked_wc = working_copy.start_mutation();
+                locked_wc.replace_tree(&wc_commit.tree());
+                locked_wc
+                    .finish(repo.op_id().clone())
+                    .map_err(|err| migration_error(&err))?;
+            }
+            pending_migration
+                .finish()
+                .map_err(|err| migration_error(&err))?;
+        }
         Ok(Workspace::new(&workspace_root, working_copy, repo_loader))
     }
 
@@ -0,0 +1,291 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A pool of hidden working copies for commands that check out many revisions
+//! one after another, like `jj run`.
+//!
+//! Each slot in the pool is a directory with a working copy and its tree
+//! state. Checking out a tree picks the slot that already has that tree, or
+//! else the one whose tree differs from it in the fewest paths, so only the
+//! files that differ need to be written. Slots are locked while they're in
+//! use, so concurrent processes get different slots. A new slot is only created
+//! when all existing ones are in use. Slots beyond the pool's capacity are
+//! removed, least recently used first.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::backend::{MillisSinceEpoch, TreeId};
+use crate::gitignore::GitIgnoreFile;
+use crate::lock::FileLock;
+use crate::matchers::EverythingMatcher;
+use crate::repo_path::RepoPath;
+use crate::store::Store;
+use crate::tree::Tree;
+use crate::working_copy::{
+    CheckoutError, CheckoutStats, SnapshotError, TreeState, WorkingCopyStateError,
+};
+
+#[derive(Debug, Error)]
+pub enum WorkspaceCacheError {
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    SnapshotError(#[from] SnapshotError),
+    #[error(transparent)]
+    CheckoutError(#[from] CheckoutError),
+    #[error(transparent)]
+    StateError(#[from] WorkingCopyStateError),
+}
+
+/// Information about a slot in the pool, for inspecting the cache.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheSlot {
+    pub name: String,
+    pub tree_id: TreeId,
+    pub last_used: MillisSinceEpoch,
+    /// Whether another process is using the slot right now.
+    pub in_use: bool,
+}
+
+pub struct WorkspaceCache {
+    store: Arc<Store>,
+    dir: PathBuf,
+    capacity: usize,
+}
+
+/// A slot that has been checked out to a tree. The slot stays locked until
+/// this is dropped.
+pub struct CachedWorkspace {
+    _lock: FileLock,
+    name: String,
+    working_copy_path: PathBuf,
+    stats: CheckoutStats,
+}
+
+impl CachedWorkspace {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn working_copy_path(&self) -> &Path {
+        &self.working_copy_path
+    }
+
+    /// What had to be updated to check out the tree in this slot.
+    pub fn stats(&self) -> &CheckoutStats {
+        &self.stats
+    }
+}
+
+fn now() -> MillisSinceEpoch {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| MillisSinceEpoch(duration.as_millis() as i64))
+        .unwrap_or(MillisSinceEpoch(0))
+}
+
+impl WorkspaceCache {
+    pub fn new(store: Arc<Store>, dir: PathBuf, capacity: usize) -> Self {
+        WorkspaceCache {
+            store,
+            dir,
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn slot_names(&self) -> io::Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        let mut names = vec![];
+        for entry in entries {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if name.parse::<u32>().is_ok() {
+                names.push(name);
+            }
+        }
+        names.sort_by_key(|name| name.parse::<u32>().unwrap());
+        Ok(names)
+    }
+
+    fn load_tree_state(&self, name: &str) -> TreeState {
+        let slot_dir = self.dir.join(name);
+        TreeState::load(
+            self.store.clone(),
+            slot_dir.join("working_copy"),
+            slot_dir.join("state"),
+        )
+    }
+
+    fn last_used(&self, name: &str) -> MillisSinceEpoch {
+        fs::read_to_string(self.dir.join(name).join("last_used"))
+            .ok()
+            .and_then(|content| content.trim().parse().ok())
+            .map_or(MillisSinceEpoch(0), MillisSinceEpoch)
+    }
+
+    /// Returns the slots in the pool, most recently used first.
+    pub fn slots(&self) -> io::Result<Vec<CacheSlot>> {
+        let mut slots = vec![];
+        for name in self.slot_names()? {
+            let in_use = match FileLock::try_lock(self.dir.join(&name).join("lock")) {
+                Ok(lock) => lock.is_none(),
+                // Another process removed the slot
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            slots.push(CacheSlot {
+                tree_id: self.load_tree_state(&name).current_tree_id().clone(),
+                last_used: self.last_used(&name),
+                in_use,
+                name,
+            });
+        }
+        slots.sort_by_key(|slot| std::cmp::Reverse(slot.last_used.clone()));
+        Ok(slots)
+    }
+
+    /// Checks out `tree` in a slot and returns it, locked. Changes made to the
+    /// files in the slot since it was last checked out are discarded.
+    pub fn check_out(
+        &self,
+        tree: &Tree,
+        base_ignores: Arc<GitIgnoreFile>,
+    ) -> Result<CachedWorkspace, WorkspaceCacheError> {
+        fs::create_dir_all(&self.dir)?;
+        let names = self.slot_names()?;
+        let mut best: Option<(usize, String, FileLock)> = None;
+        for name in &names {
+            let lock = match FileLock::try_lock(self.dir.join(name).join("lock")) {
+                Ok(Some(lock)) => lock,
+                Ok(None) => continue,
+                // Another process removed the slot
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+            let cached_tree_id = self.load_tree_state(name).current_tree_id().clone();
+            let distance = if &cached_tree_id == tree.id() {
+                0
+            } else {
+                match self.store.get_tree(&RepoPath::root(), &cached_tree_id) {
+                    Ok(cached_tree) => cached_tree.diff(tree, &EverythingMatcher).count() + 1,
+                    Err(_) => usize::MAX,
+                }
+            };
+            if best
+                .as_ref()
+                .map_or(true, |(best_distance, _, _)| distance < *best_distance)
+            {
+                best = Some((distance, name.clone(), lock));
+            }
+        }
+        let (name, lock) = match best {
+            Some((_, name, lock)) => (name, lock),
+            None if names.len() < self.capacity => {
+                let name = (0..)
+                    .map(|i: u32| i.to_string())
+                    .find(|name| !names.contains(name))
+                    .unwrap();
+                let slot_dir = self.dir.join(&name);
+                fs::create_dir_all(slot_dir.join("working_copy"))?;
+                fs::create_dir_all(slot_dir.join("state"))?;
+                let lock = FileLock::lock(slot_dir.join("lock"));
+                (name, lock)
+            }
+            None => {
+                // Every slot is in use, so wait for the one that was used
+                // longest ago
+                let name = names
+                    .iter()
+                    .min_by_key(|name| self.last_used(name))
+                    .unwrap()
+                    .clone();
+                let lock = FileLock::lock(self.dir.join(&name).join("lock"));
+                (name, lock)
+            }
+        };
+
+        let slot_dir = self.dir.join(&name);
+        let mut tree_state = self.load_tree_state(&name);
+        // Snapshot first so checking out the tree overwrites whatever was
+        // changed in the slot since it was last checked out
+        tree_state.snapshot(base_ignores)?;
+        let stats = tree_state.check_out(tree)?;
+        tree_state.save()?;
+        fs::write(slot_dir.join("last_used"), now().0.to_string())?;
+        let workspace = CachedWorkspace {
+            _lock: lock,
+            name,
+            working_copy_path: slot_dir.join("working_copy"),
+            stats,
+        };
+        self.evict()?;
+        Ok(workspace)
+    }
+
+    /// Removes the least recently used slots that aren't in use until there
+    /// are at most as many as the capacity. Returns the number of removed
+    /// slots.
+    pub fn evict(&self) -> io::Result<usize> {
+        let slots = self.slots()?;
+        let num_excess = slots.len().saturating_sub(self.capacity);
+        self.remove_slots(slots.iter().rev().take(num_excess))
+    }
+
+    /// Removes all slots that aren't in use. Returns the number of removed
+    /// slots.
+    pub fn clear(&self) -> io::Result<usize> {
+        self.remove_slots(self.slots()?.iter())
+    }
+
+    fn remove_slots<'a>(&self, slots: impl Iterator<Item = &'a CacheSlot>) -> io::Result<usize> {
+        let mut num_removed = 0;
+        for slot in slots.filter(|slot| !slot.in_use).collect_vec() {
+            let slot_dir = self.dir.join(&slot.name);
+            // Hold the lock while deleting so nobody starts using the slot
+            let lock = match FileLock::try_lock(slot_dir.join("lock"))? {
+                Some(lock) => lock,
+                None => continue,
+            };
+            for entry in fs::read_dir(&slot_dir)? {
+                let entry = entry?;
+                if entry.file_name() == "lock" {
+                    continue;
+                }
+                if entry.file_type()?.is_dir() {
+                    fs::remove_dir_all(entry.path())?;
+                } else {
+                    fs::remove_file(entry.path())?;
+                }
+            }
+            drop(lock);
+            fs::remove_dir(&slot_dir)?;
+            num_removed += 1;
+        }
+        Ok(num_removed)
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use jujutsu_lib::backend::{Backend, TreeValue};
+use jujutsu_lib::backend_migration::{migrate_backend, PendingMigration};
+use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::git_backend::GitBackend;
+use jujutsu_lib::op_store::{RefTarget, WorkspaceId};
+use jujutsu_lib::repo::BackendFactories;
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::{TestRepo, TestWorkspace};
+use jujutsu_lib::tree;
+use jujutsu_lib::workspace::Workspace;
+
+#[test]
+fn test_migrate_backend() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let path = RepoPath::from_internal_string("dir/file");
+    let tree1 = testutils::create_tree(repo, &[(&path, "base")]);
+    let tree2 = testutils::create_tree(repo, &[(&path, "left")]);
+    let tree3 = testutils::create_tree(repo, &[(&path, "right")]);
+    let conflict_tree_id = tree::merge_trees(&tree2, &tree1, &tree3).unwrap();
+    let mut tx = repo.start_transaction("test");
+    let mut graph_builder = testutils::CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit_a = graph_builder.initial_commit();
+    let commit_b = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_c = CommitBuilder::for_rewrite_from(&settings, &commit_b)
+        .set_tree(conflict_tree_id)
+        .write_to_repo(tx.mut_repo());
+    tx.mut_repo().rebase_descendants(&settings).unwrap();
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit_c.id().clone()));
+    let repo = tx.commit();
+
+    let temp_dir = testutils::new_temp_dir();
+    let git_backend = GitBackend::init_internal(temp_dir.path());
+    let migration = migrate_backend(
+        repo.op_store(),
+        &[repo.op_id().clone()],
+        repo.store().backend(),
+        &git_backend,
+    )
+    .unwrap();
+
+    // The commits (including the rewritten one) were copied with their
+    // metadata
+    let new_c_id = &migration.commit_ids[commit_c.id()];
+    let new_c = git_backend.read_commit(new_c_id).unwrap();
+    assert_eq!(&new_c.change_id, commit_c.change_id());
+    assert_eq!(new_c.description, commit_c.description());
+    assert_eq!(
+        new_c.parents,
+        vec![migration.commit_ids[commit_a.id()].clone()]
+    );
+    assert_eq!(
+        new_c.predecessors,
+        vec![migration.commit_ids[commit_b.id()].clone()]
+    );
+    // So was the conflict in its tree
+    let dir = git_backend
+        .read_tree(&RepoPath::root(), &new_c.root_tree)
+        .unwrap();
+    let dir_id = match dir.value(&"dir".into()) {
+        Some(TreeValue::Tree(id)) => id.clone(),
+        other => panic!("unexpected value {other:?}"),
+    };
+    let dir_tree = git_backend
+        .read_tree(&path.parent().unwrap(), &dir_id)
+        .unwrap();
+    let conflict_id = match dir_tree.value(&"file".into()) {
+        Some(TreeValue::Conflict(id)) => id.clone(),
+        other => panic!("unexpected value {other:?}"),
+    };
+    let conflict = git_backend.read_conflict(&path, &conflict_id).unwrap();
+    assert_eq!(conflict.adds.len(), 2);
+    assert_eq!(conflict.removes.len(), 1);
+
+    // The operations were rewritten to refer to the new commits
+    let new_op_id = &migration.operation_ids[repo.op_id()];
+    let new_op = repo.op_store().read_operation(new_op_id).unwrap();
+    assert_eq!(new_op.metadata, repo.operation().store_operation().metadata);
+    assert_eq!(new_op.parents.len(), 1);
+    let new_view = repo.op_store().read_view(&new_op.view_id).unwrap();
+    assert_eq!(
+        new_view.branches["main"].local_target,
+        Some(RefTarget::Normal(new_c_id.clone()))
+    );
+    assert!(new_view.head_ids.contains(new_c_id));
+}
+
+#[test]
+fn test_finish_pending_migration() {
+    // Test that a migration that was committed but interrupted before the new
+    // store was swapped in is finished when the workspace is loaded
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+    let repo_path = repo.repo_path().clone();
+
+    let path = RepoPath::from_internal_string("file");
+    let tree = testutils::create_tree(repo, &[(&path, "contents")]);
+    let mut tx = repo.start_transaction("test");
+    let commit = CommitBuilder::for_new_commit(
+        &settings,
+        vec![repo.store().root_commit_id().clone()],
+        tree.id().clone(),
+    )
+    .write_to_repo(tx.mut_repo());
+    tx.mut_repo()
+        .set_wc_commit(WorkspaceId::default(), commit.id().clone());
+    let repo = tx.commit();
+    test_workspace
+        .workspace
+        .working_copy_mut()
+        .check_out(repo.op_id().clone(), None, &tree)
+        .unwrap();
+
+    let new_store_path = PendingMigration::new_store_path(&repo_path);
+    std::fs::create_dir(&new_store_path).unwrap();
+    let git_backend = GitBackend::init_internal(&new_store_path);
+    std::fs::write(new_store_path.join("backend"), "git").unwrap();
+    let migration = migrate_backend(
+        repo.op_store(),
+        &[repo.op_id().clone()],
+        repo.store().backend(),
+        &git_backend,
+    )
+    .unwrap();
+    let new_op_id = migration.operation_ids[repo.op_id()].clone();
+    PendingMigration::commit(
+        &repo_path,
+        std::slice::from_ref(repo.op_id()),
+        std::slice::from_ref(&new_op_id),
+    )
+    .unwrap();
+    drop(git_backend);
+    drop(test_workspace.workspace);
+
+    let workspace =
+        Workspace::load(&settings, &workspace_root, &BackendFactories::default()).unwrap();
+    assert_eq!(PendingMigration::load(&repo_path).unwrap(), None);
+    assert!(!new_store_path.exists());
+    assert!(!PendingMigration::old_store_path(&repo_path).exists());
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("store").join("backend")).unwrap(),
+        "git"
+    );
+    let new_repo = workspace.repo_loader().load_at_head_readonly().unwrap();
+    assert_eq!(new_repo.op_id(), &new_op_id);
+    let new_commit = new_repo
+        .store()
+        .get_commit(&migration.commit_ids[commit.id()])
+        .unwrap();
+    // The working copy refers to the tree in the new store
+    assert_eq!(
+        workspace.working_copy().current_tree_id(),
+        new_commit.tree_id()
+    );
+    assert_eq!(workspace.working_copy().operation_id(), new_op_id);
+}
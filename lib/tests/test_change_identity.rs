@@ -0,0 +1,125 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use jujutsu_lib::backend::{MillisSinceEpoch, Signature, Timestamp};
+use jujutsu_lib::change_identity::{
+    change_ids_by_identity, find_change_id_drift, CommitIdentities,
+};
+use jujutsu_lib::commit::Commit;
+use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::repo::ReadonlyRepo;
+use jujutsu_lib::settings::UserSettings;
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestRepo;
+
+fn signature(millis: i64) -> Signature {
+    Signature {
+        name: "Some One".to_string(),
+        email: "some.one@example.com".to_string(),
+        timestamp: Timestamp {
+            timestamp: MillisSinceEpoch(millis),
+            tz_offset: 60,
+        },
+    }
+}
+
+fn write_commits(settings: &UserSettings, repo: &Arc<ReadonlyRepo>) -> (Commit, Commit) {
+    let mut tx = repo.start_transaction("test");
+    let commit1 = CommitBuilder::for_new_commit(
+        settings,
+        vec![repo.store().root_commit_id().clone()],
+        repo.store().empty_tree_id().clone(),
+    )
+    .set_description("first\n".to_string())
+    .set_author(signature(1_000_000_000_123))
+    .set_committer(signature(1_000_000_000_456))
+    .write_to_repo(tx.mut_repo());
+    let commit2 = CommitBuilder::for_new_commit(
+        settings,
+        vec![commit1.id().clone()],
+        repo.store().empty_tree_id().clone(),
+    )
+    .set_description("second\n".to_string())
+    .set_author(signature(1_000_000_001_000))
+    .set_committer(signature(1_000_000_001_000))
+    .write_to_repo(tx.mut_repo());
+    tx.commit();
+    (commit1, commit2)
+}
+
+#[test]
+fn test_identity_is_backend_independent() {
+    let settings = testutils::user_settings();
+    let local_test_repo = TestRepo::init(false);
+    let git_test_repo = TestRepo::init(true);
+    let (local_commit1, local_commit2) = write_commits(&settings, &local_test_repo.repo);
+    let (git_commit1, git_commit2) = write_commits(&settings, &git_test_repo.repo);
+    assert_ne!(local_commit2.id(), git_commit2.id());
+    // The Git backend drops the milliseconds, which don't affect the identity
+    let stored_git_commit1 = git_test_repo
+        .repo
+        .store()
+        .backend()
+        .read_commit(git_commit1.id())
+        .unwrap();
+    assert_ne!(*local_commit1.committer(), stored_git_commit1.committer);
+
+    let mut local_identities = CommitIdentities::new();
+    let mut git_identities = CommitIdentities::new();
+    let local_identity = local_identities
+        .get(local_test_repo.repo.store(), local_commit2.id())
+        .unwrap();
+    let git_identity = git_identities
+        .get(git_test_repo.repo.store(), git_commit2.id())
+        .unwrap();
+    assert_eq!(local_identity, git_identity);
+    assert_ne!(
+        local_identities.identities()[local_commit1.id()],
+        local_identity
+    );
+    // So is the change id derived from it
+    assert_eq!(
+        local_identity.derived_change_id(),
+        git_identity.derived_change_id()
+    );
+}
+
+#[test]
+fn test_find_change_id_drift() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+    let (commit1, commit2) = write_commits(&settings, repo);
+    let expected = change_ids_by_identity(repo.store(), &[commit2.id().clone()]).unwrap();
+    // The root commit and the two commits
+    assert_eq!(expected.len(), 3);
+    assert_eq!(
+        find_change_id_drift(repo.store(), &[commit2.id().clone()], &expected).unwrap(),
+        vec![]
+    );
+
+    // A commit with the same metadata and parents but another change id
+    let mut tx = repo.start_transaction("test");
+    let copy1 = CommitBuilder::for_rewrite_from(&settings, &commit1)
+        .set_committer(commit1.committer().clone())
+        .generate_new_change_id()
+        .write_to_repo(tx.mut_repo());
+    let drift = find_change_id_drift(repo.store(), &[copy1.id().clone()], &expected).unwrap();
+    assert_eq!(drift.len(), 1);
+    assert_eq!(drift[0].commit_id, *copy1.id());
+    assert_eq!(drift[0].actual_change_id, *copy1.change_id());
+    assert!(drift[0].expected_change_ids.contains(commit1.change_id()));
+}
@@ -12,10 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use jujutsu_lib::backend::{CoAuthor, CopyRecord};
 use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::copies::{resolve_copies, CopiedPath, CopyOperation};
+use jujutsu_lib::git_backend::GitBackend;
+use jujutsu_lib::local_backend::LocalBackend;
 use jujutsu_lib::matchers::EverythingMatcher;
 use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::settings::UserSettings;
+use jujutsu_lib::store::Store;
 use jujutsu_lib::testutils;
 use jujutsu_lib::testutils::{assert_rebased, CommitGraphBuilder, TestRepo};
 use jujutsu_lib::tree::DiffSummary;
@@ -254,3 +259,142 @@ fn test_commit_builder_descendants(use_git: bool) {
     let mut rebaser = tx.mut_repo().create_descendant_rebaser(&settings);
     assert!(rebaser.rebase_next().unwrap().is_none());
 }
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_copy_records(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store().clone();
+
+    let file_a = RepoPath::from_internal_string("a");
+    let file_b = RepoPath::from_internal_string("b");
+    let file_c = RepoPath::from_internal_string("c");
+    let file_d = RepoPath::from_internal_string("d");
+    let tree1 = testutils::create_tree(repo, &[(&file_a, "a"), (&file_b, "b")]);
+    // "a" is copied to "c" and "b" is renamed to "d"
+    let tree2 = testutils::create_tree(repo, &[(&file_a, "a"), (&file_c, "a"), (&file_d, "b")]);
+
+    let mut tx = repo.start_transaction("test");
+    let commit1 = CommitBuilder::for_new_commit(
+        &settings,
+        vec![store.root_commit_id().clone()],
+        tree1.id().clone(),
+    )
+    .write_to_repo(tx.mut_repo());
+    let commit2 =
+        CommitBuilder::for_new_commit(&settings, vec![commit1.id().clone()], tree2.id().clone())
+            .add_copy(file_a.clone(), file_c.clone())
+            .add_copy(file_b.clone(), file_d.clone())
+            // Doesn't apply since "a" wasn't added
+            .add_copy(file_b.clone(), file_a.clone())
+            .write_to_repo(tx.mut_repo());
+    tx.commit();
+
+    let expected_records = vec![
+        CopyRecord {
+            source: file_a.clone(),
+            target: file_c.clone(),
+        },
+        CopyRecord {
+            source: file_b.clone(),
+            target: file_d.clone(),
+        },
+        CopyRecord {
+            source: file_b.clone(),
+            target: file_a.clone(),
+        },
+    ];
+    assert_eq!(commit2.copies(), expected_records);
+    // The records survive a round trip through the backend
+    let reloaded_store = Store::new(if use_git {
+        Box::new(GitBackend::load(&repo.repo_path().join("store")))
+    } else {
+        Box::new(LocalBackend::load(&repo.repo_path().join("store")))
+    });
+    let reloaded_commit = reloaded_store.get_commit(commit2.id()).unwrap();
+    assert_eq!(reloaded_commit.copies(), expected_records);
+
+    assert_eq!(
+        resolve_copies(&commit1.tree(), &commit2.tree(), commit2.copies()),
+        vec![
+            CopiedPath {
+                source: file_a.clone(),
+                target: file_c,
+                operation: CopyOperation::Copy,
+            },
+            CopiedPath {
+                source: file_b,
+                target: file_d,
+                operation: CopyOperation::Rename,
+            },
+        ]
+    );
+    // Rewriting a commit keeps its records
+    let mut tx = repo.start_transaction("test");
+    let rewritten = CommitBuilder::for_rewrite_from(&settings, &commit2)
+        .set_description("rewritten".to_string())
+        .write_to_repo(tx.mut_repo());
+    assert_eq!(rewritten.copies(), expected_records);
+    let rewritten = CommitBuilder::for_rewrite_from(&settings, &rewritten)
+        .set_description("without copies".to_string())
+        .set_copies(vec![])
+        .write_to_repo(tx.mut_repo());
+    assert_eq!(rewritten.copies(), vec![]);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_co_authors(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store().clone();
+
+    let co_authors = vec![
+        CoAuthor {
+            name: "Alice".to_string(),
+            email: "alice@example.com".to_string(),
+        },
+        CoAuthor {
+            name: "Bob".to_string(),
+            email: "bob@example.com".to_string(),
+        },
+    ];
+    let mut tx = repo.start_transaction("test");
+    let commit = CommitBuilder::for_new_commit(
+        &settings,
+        vec![store.root_commit_id().clone()],
+        store.empty_tree_id().clone(),
+    )
+    .set_description("subject\n\nbody\n".to_string())
+    .set_co_authors(co_authors.clone())
+    .write_to_repo(tx.mut_repo());
+    tx.commit();
+    assert_eq!(commit.description(), "subject\n\nbody\n");
+    assert_eq!(commit.co_authors(), co_authors);
+
+    // The co-authors survive a round trip through the backend
+    let reloaded_store = Store::new(if use_git {
+        Box::new(GitBackend::load(&repo.repo_path().join("store")))
+    } else {
+        Box::new(LocalBackend::load(&repo.repo_path().join("store")))
+    });
+    let reloaded_commit = reloaded_store.get_commit(commit.id()).unwrap();
+    assert_eq!(reloaded_commit.description(), "subject\n\nbody\n");
+    assert_eq!(reloaded_commit.co_authors(), co_authors);
+
+    // In the Git backend, they're stored as trailers
+    if use_git {
+        let git_repo = store.git_repo().unwrap();
+        let git_commit = git_repo
+            .find_commit(git2::Oid::from_bytes(commit.id().as_bytes()).unwrap())
+            .unwrap();
+        assert_eq!(
+            git_commit.message().unwrap(),
+            "subject\n\nbody\n\nCo-authored-by: Alice <alice@example.com>\nCo-authored-by: Bob \
+             <bob@example.com>\n"
+        );
+    }
+}
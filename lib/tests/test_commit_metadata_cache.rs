@@ -0,0 +1,47 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use jujutsu_lib::commit_metadata_cache::CommitMetadataCache;
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestRepo;
+use test_case::test_case;
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_commit_metadata_cache(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let commit = testutils::create_random_commit(&settings, repo)
+        .set_description("first line\n\nmore details\n".to_string())
+        .write_to_repo(tx.mut_repo());
+    let repo = tx.commit();
+    let store = repo.store();
+    let cache_dir = repo.index_store().commit_metadata_dir();
+
+    // The first lookup reads the commit from the store
+    let cache = CommitMetadataCache::load(cache_dir.clone(), store.hash_length()).unwrap();
+    let cached_commit = cache.get_commit(store, commit.id()).unwrap();
+    assert_eq!(cached_commit.store_commit(), commit.store_commit());
+    drop(cache);
+
+    // The entry was written when the cache was dropped
+    let cache = CommitMetadataCache::load(cache_dir, store.hash_length()).unwrap();
+    let cached_commit = cache.get_commit(store, commit.id()).unwrap();
+    assert_eq!(cached_commit.id(), commit.id());
+    assert_eq!(cached_commit.store_commit(), commit.store_commit());
+    assert_eq!(cached_commit.description(), "first line\n\nmore details\n");
+}
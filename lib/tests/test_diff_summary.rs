@@ -12,6 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Mutex;
+
+use itertools::Itertools;
 use jujutsu_lib::matchers::{EverythingMatcher, FilesMatcher};
 use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::testutils;
@@ -283,3 +286,61 @@ fn test_matcher_normal_cases(use_git: bool) {
         }
     );
 }
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_parallel_diff(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let path = RepoPath::from_internal_string;
+    let tree1 = testutils::create_tree(
+        repo,
+        &[
+            (&path("a"), "a"),
+            (&path("dir1/a"), "a"),
+            (&path("dir1/sub/b"), "b"),
+            (&path("dir2/c"), "c"),
+            (&path("file_then_dir"), "file"),
+            (&path("dir_then_file/d"), "d"),
+            (&path("z"), "z"),
+        ],
+    );
+    let tree2 = testutils::create_tree(
+        repo,
+        &[
+            (&path("a"), "a"),
+            (&path("dir1/a"), "a modified"),
+            (&path("dir1/sub/c"), "c"),
+            (&path("dir3/d"), "d"),
+            (&path("file_then_dir/e"), "e"),
+            (&path("dir_then_file"), "file"),
+        ],
+    );
+
+    for (before, after) in [(&tree1, &tree2), (&tree2, &tree1)] {
+        let serial = before.diff(after, &EverythingMatcher).collect_vec();
+        assert_eq!(serial.len(), 10);
+        // Same diffs in the same order
+        assert_eq!(before.diff_parallel(after, &EverythingMatcher), serial);
+
+        // Same diffs in any order
+        let unordered = Mutex::new(vec![]);
+        before.diff_unordered(after, &EverythingMatcher, |path, diff| {
+            unordered.lock().unwrap().push((path, diff));
+        });
+        let mut unordered = unordered.into_inner().unwrap();
+        unordered.sort_by(|(path1, _), (path2, _)| path1.cmp(path2));
+        let mut sorted = serial;
+        sorted.sort_by(|(path1, _), (path2, _)| path1.cmp(path2));
+        assert_eq!(unordered, sorted);
+    }
+
+    // The matcher is respected
+    let matcher = FilesMatcher::new(hashset! {path("dir1/a"), path("dir3/d")});
+    assert_eq!(
+        tree1.diff_parallel(&tree2, &matcher),
+        tree1.diff(&tree2, &matcher).collect_vec()
+    );
+    assert_eq!(tree1.diff_parallel(&tree2, &matcher).len(), 2);
+}
@@ -0,0 +1,234 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use jujutsu_lib::backend::TreeValue;
+use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::fast_import::{export_commits, import_stream, FastImportError};
+use jujutsu_lib::op_store::RefTarget;
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestRepo;
+use jujutsu_lib::tree_builder::TreeBuilder;
+use test_case::test_case;
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_export_import_round_trip(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let file_path = RepoPath::from_internal_string("dir/file");
+    let exec_path = RepoPath::from_internal_string("exec");
+    let link_path = RepoPath::from_internal_string("link");
+    let store = repo.store();
+    let mut tree_builder = TreeBuilder::new(store.clone(), store.empty_tree_id().clone());
+    testutils::write_normal_file(&mut tree_builder, &file_path, "a\n");
+    testutils::write_executable_file(&mut tree_builder, &exec_path, "#!/bin/sh\n");
+    let tree_id1 = tree_builder.write_tree();
+    let mut tree_builder = TreeBuilder::new(store.clone(), tree_id1.clone());
+    testutils::write_normal_file(&mut tree_builder, &file_path, "b\n");
+    tree_builder.remove(exec_path.clone());
+    testutils::write_symlink(&mut tree_builder, &link_path, "dir/file");
+    let tree_id2 = tree_builder.write_tree();
+
+    let mut tx = repo.start_transaction("test");
+    let commit1 =
+        CommitBuilder::for_new_commit(&settings, vec![store.root_commit_id().clone()], tree_id1)
+            .set_description("first\n".to_string())
+            .write_to_repo(tx.mut_repo());
+    let commit2 = CommitBuilder::for_new_commit(&settings, vec![commit1.id().clone()], tree_id2)
+        .set_description("second\n".to_string())
+        .write_to_repo(tx.mut_repo());
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit2.id().clone()));
+    tx.mut_repo().set_local_branch(
+        "feature".to_string(),
+        RefTarget::Normal(commit2.id().clone()),
+    );
+    let repo = tx.commit();
+
+    let mut stream = vec![];
+    export_commits(
+        repo.as_repo_ref(),
+        &[commit2.clone(), commit1.clone()],
+        &mut stream,
+    )
+    .unwrap();
+    let stream_str = String::from_utf8(stream.clone()).unwrap();
+    // Both commits are written to the first branch pointing to the descendant
+    assert_eq!(stream_str.matches("commit refs/heads/feature\n").count(), 2);
+    assert!(stream_str.contains("reset refs/heads/main\nfrom :2\n"));
+
+    let other_test_repo = TestRepo::init(use_git);
+    let other_repo = &other_test_repo.repo;
+    let mut tx = other_repo.start_transaction("test");
+    let stats = import_stream(&settings, tx.mut_repo(), &mut stream.as_slice()).unwrap();
+    let other_repo = tx.commit();
+    assert_eq!(stats.imported_commits, 2);
+    assert_eq!(stats.updated_branches, 2);
+
+    let main_id = match other_repo.view().get_local_branch("main") {
+        Some(RefTarget::Normal(id)) => id,
+        target => panic!("unexpected target: {target:?}"),
+    };
+    let imported2 = other_repo.store().get_commit(&main_id).unwrap();
+    let imported1 = imported2.parents()[0].clone();
+    assert_eq!(imported2.tree_id(), commit2.tree_id());
+    assert_eq!(imported1.tree_id(), commit1.tree_id());
+    assert_eq!(imported2.description(), "second\n");
+    assert_eq!(imported2.author().name, commit2.author().name);
+    assert_eq!(imported2.author().email, commit2.author().email);
+    assert_eq!(
+        imported1.parent_ids(),
+        &[other_repo.store().root_commit_id().clone()]
+    );
+    assert_eq!(
+        other_repo.view().get_local_branch("feature"),
+        Some(RefTarget::Normal(imported2.id().clone()))
+    );
+
+    // Importing the stream into a repo with the other backend results in the
+    // same change ids
+    let third_test_repo = TestRepo::init(!use_git);
+    let third_repo = &third_test_repo.repo;
+    let mut tx = third_repo.start_transaction("test");
+    import_stream(&settings, tx.mut_repo(), &mut stream.as_slice()).unwrap();
+    let third_repo = tx.commit();
+    let main_id = match third_repo.view().get_local_branch("main") {
+        Some(RefTarget::Normal(id)) => id,
+        target => panic!("unexpected target: {target:?}"),
+    };
+    let reimported2 = third_repo.store().get_commit(&main_id).unwrap();
+    assert_ne!(reimported2.id(), imported2.id());
+    assert_eq!(reimported2.change_id(), imported2.change_id());
+    assert_eq!(reimported2.parents()[0].change_id(), imported1.change_id());
+}
+
+#[test]
+fn test_import_stream_features() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    // Blobs referenced by mark, delimited data, commits continuing a ref's
+    // history without `from`, quoted paths, resets and tags
+    let stream = b"\
+feature done
+blob
+mark :1
+data 6
+hello
+
+commit refs/heads/main
+mark :2
+author Some One <some.one@example.com> 1000000000 +0200
+committer Other One <other.one@example.com> 1000000001 -0130
+data <<EOF
+first
+EOF
+M 100644 :1 file
+M 100755 inline \"dir/with\\nnewline\"
+data 3
+ab
+
+# A comment
+commit refs/heads/main
+committer Other One <other.one@example.com> 1000000002 +0000
+data 6
+second
+D file
+
+reset refs/heads/topic
+from :2
+
+tag v1.0
+from :2
+tagger Other One <other.one@example.com> 1000000003 +0000
+data 8
+release
+done
+";
+    let mut tx = repo.start_transaction("test");
+    let stats = import_stream(&settings, tx.mut_repo(), &mut stream.as_slice()).unwrap();
+    let repo = tx.commit();
+    assert_eq!(stats.imported_commits, 2);
+    assert_eq!(stats.updated_branches, 2);
+    assert_eq!(stats.updated_tags, 1);
+
+    let main_id = match repo.view().get_local_branch("main") {
+        Some(RefTarget::Normal(id)) => id,
+        target => panic!("unexpected target: {target:?}"),
+    };
+    let second = repo.store().get_commit(&main_id).unwrap();
+    let first = second.parents()[0].clone();
+    assert_eq!(first.description(), "first\n");
+    assert_eq!(first.author().name, "Some One");
+    assert_eq!(first.author().timestamp.timestamp.0, 1_000_000_000_000);
+    assert_eq!(first.author().timestamp.tz_offset, 120);
+    assert_eq!(first.committer().name, "Other One");
+    assert_eq!(first.committer().timestamp.tz_offset, -90);
+    // The author defaults to the committer
+    assert_eq!(second.author(), second.committer());
+
+    let file_path = RepoPath::from_internal_string("file");
+    let odd_path = RepoPath::from_internal_string("dir/with\nnewline");
+    match first.tree().path_value(&file_path) {
+        Some(TreeValue::Normal { id, executable }) => {
+            assert!(!executable);
+            assert_eq!(
+                testutils::read_file(repo.store(), &file_path, &id),
+                b"hello\n"
+            );
+        }
+        value => panic!("unexpected value: {value:?}"),
+    }
+    assert!(matches!(
+        second.tree().path_value(&odd_path),
+        Some(TreeValue::Normal {
+            executable: true,
+            ..
+        })
+    ));
+    assert_eq!(second.tree().path_value(&file_path), None);
+    assert_eq!(
+        repo.view().get_local_branch("topic"),
+        Some(RefTarget::Normal(first.id().clone()))
+    );
+    assert_eq!(
+        repo.view().get_tag("v1.0"),
+        Some(RefTarget::Normal(first.id().clone()))
+    );
+}
+
+#[test]
+fn test_import_stream_errors() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let result = import_stream(&settings, tx.mut_repo(), &mut b"ls :1 file\n".as_slice());
+    assert!(matches!(
+        result,
+        Err(FastImportError::UnsupportedCommand { line: 1, .. })
+    ));
+
+    let stream = b"commit refs/heads/main\ncommitter A <a@example.com> 0 +0000\ndata 0\nfrom :5\n";
+    let result = import_stream(&settings, tx.mut_repo(), &mut stream.as_slice());
+    assert!(matches!(
+        result,
+        Err(FastImportError::InvalidStream { line: 4, .. })
+    ));
+}
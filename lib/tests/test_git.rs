@@ -15,10 +15,11 @@
 use std::path::PathBuf;
 use std::sync::Arc;
 
+use assert_matches::assert_matches;
 use git2::Oid;
 use jujutsu_lib::backend::CommitId;
 use jujutsu_lib::commit::Commit;
-use jujutsu_lib::git::{GitFetchError, GitPushError, GitRefUpdate};
+use jujutsu_lib::git::{GitFetchError, GitPushError, GitRefUpdate, SignedPush};
 use jujutsu_lib::git_backend::GitBackend;
 use jujutsu_lib::op_store::{BranchTarget, RefTarget};
 use jujutsu_lib::repo::ReadonlyRepo;
@@ -536,7 +537,14 @@ fn test_fetch_empty_repo() {
     let test_data = GitRepoData::create();
 
     let mut tx = test_data.repo.start_transaction("test");
-    let default_branch = git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    let default_branch = git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        false,
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     // No default branch and no refs
     assert_eq!(default_branch, None);
     assert_eq!(*tx.mut_repo().view().git_refs(), btreemap! {});
@@ -549,7 +557,14 @@ fn test_fetch_initial_commit() {
     let initial_git_commit = empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
 
     let mut tx = test_data.repo.start_transaction("test");
-    let default_branch = git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    let default_branch = git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        false,
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     // No default branch because the origin repo's HEAD wasn't set
     assert_eq!(default_branch, None);
     let repo = tx.commit();
@@ -574,13 +589,93 @@ fn test_fetch_initial_commit() {
     );
 }
 
+#[test]
+fn test_fetch_progress() {
+    let test_data = GitRepoData::create();
+    empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
+
+    let mut updates = vec![];
+    let mut progress_cb = |progress: &git::Progress| updates.push(progress.clone());
+    let callbacks = git::RemoteCallbacks {
+        progress: Some(&mut progress_cb),
+        ..Default::default()
+    };
+    let mut tx = test_data.repo.start_transaction("test");
+    git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        false,
+        callbacks,
+    )
+    .unwrap();
+    let last_update = updates.last().unwrap();
+    assert_eq!(last_update.objects, last_update.total_objects);
+    assert!(last_update.total_objects > 0);
+}
+
+#[test]
+fn test_fetch_too_many_objects() {
+    let test_data = GitRepoData::create();
+    let git_commit = empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
+
+    let callbacks = git::RemoteCallbacks {
+        max_objects: Some(1),
+        ..Default::default()
+    };
+    let mut tx = test_data.repo.start_transaction("test");
+    let result = git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        false,
+        callbacks,
+    );
+    assert_matches!(
+        result,
+        Err(GitFetchError::TooManyObjects { total, limit: 1 }) if total > 1
+    );
+    // Nothing was imported
+    assert!(!tx
+        .mut_repo()
+        .view()
+        .heads()
+        .contains(&commit_id(&git_commit)));
+
+    // A limit above the number of objects doesn't get in the way
+    let callbacks = git::RemoteCallbacks {
+        max_objects: Some(100),
+        ..Default::default()
+    };
+    git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        false,
+        callbacks,
+    )
+    .unwrap();
+    assert!(tx
+        .mut_repo()
+        .view()
+        .heads()
+        .contains(&commit_id(&git_commit)));
+}
+
 #[test]
 fn test_fetch_success() {
     let mut test_data = GitRepoData::create();
     let initial_git_commit = empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
 
     let mut tx = test_data.repo.start_transaction("test");
-    git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        false,
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     test_data.repo = tx.commit();
 
     test_data.origin_repo.set_head("refs/heads/main").unwrap();
@@ -591,7 +686,14 @@ fn test_fetch_success() {
     );
 
     let mut tx = test_data.repo.start_transaction("test");
-    let default_branch = git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    let default_branch = git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        false,
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     // The default branch is "main"
     assert_eq!(default_branch, Some("main".to_string()));
     let repo = tx.commit();
@@ -622,7 +724,14 @@ fn test_fetch_prune_deleted_ref() {
     empty_git_commit(&test_data.git_repo, "refs/heads/main", &[]);
 
     let mut tx = test_data.repo.start_transaction("test");
-    git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        false,
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     // Test the setup
     assert!(tx.mut_repo().get_branch("main").is_some());
 
@@ -633,17 +742,82 @@ fn test_fetch_prune_deleted_ref() {
         .delete()
         .unwrap();
     // After re-fetching, the branch should be deleted
-    git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        false,
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     assert!(tx.mut_repo().get_branch("main").is_none());
 }
 
+#[test]
+fn test_fetch_prune_remote_branch() {
+    let test_data = GitRepoData::create();
+    let initial_git_commit = empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
+    empty_git_commit(&test_data.origin_repo, "refs/heads/feature", &[]);
+    let fetch = |tx: &mut jujutsu_lib::transaction::Transaction, prune: bool| {
+        git::fetch(
+            tx.mut_repo(),
+            &test_data.git_repo,
+            "origin",
+            prune,
+            git::RemoteCallbacks::default(),
+        )
+        .unwrap();
+    };
+
+    let mut tx = test_data.repo.start_transaction("test");
+    fetch(&mut tx, false);
+    // Move the local "feature" branch
+    let local_commit = create_random_commit(&test_data.settings, &test_data.repo)
+        .set_parents(vec![commit_id(&initial_git_commit)])
+        .write_to_repo(tx.mut_repo());
+    tx.mut_repo().set_local_branch(
+        "feature".to_string(),
+        RefTarget::Normal(local_commit.id().clone()),
+    );
+
+    for name in ["refs/heads/main", "refs/heads/feature"] {
+        test_data
+            .origin_repo
+            .find_reference(name)
+            .unwrap()
+            .delete()
+            .unwrap();
+    }
+    // Without pruning, the remote-tracking branches are kept
+    fetch(&mut tx, false);
+    assert!(tx.mut_repo().get_remote_branch("main", "origin").is_some());
+
+    fetch(&mut tx, true);
+    let view = tx.mut_repo().view();
+    assert_eq!(view.get_remote_branch("main", "origin"), None);
+    assert_eq!(view.get_remote_branch("feature", "origin"), None);
+    // The unchanged local branch was deleted, but the one that was moved is kept
+    assert_eq!(view.get_local_branch("main"), None);
+    assert_eq!(
+        view.get_local_branch("feature"),
+        Some(RefTarget::Normal(local_commit.id().clone()))
+    );
+}
+
 #[test]
 fn test_fetch_no_default_branch() {
     let test_data = GitRepoData::create();
     let initial_git_commit = empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
 
     let mut tx = test_data.repo.start_transaction("test");
-    git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        false,
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
 
     empty_git_commit(
         &test_data.origin_repo,
@@ -658,7 +832,14 @@ fn test_fetch_no_default_branch() {
         .set_head_detached(initial_git_commit.id())
         .unwrap();
 
-    let default_branch = git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    let default_branch = git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        false,
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     // There is no default branch
     assert_eq!(default_branch, None);
 }
@@ -668,7 +849,13 @@ fn test_fetch_no_such_remote() {
     let test_data = GitRepoData::create();
 
     let mut tx = test_data.repo.start_transaction("test");
-    let result = git::fetch(tx.mut_repo(), &test_data.git_repo, "invalid-remote");
+    let result = git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "invalid-remote",
+        false,
+        git::RemoteCallbacks::default(),
+    );
     assert!(matches!(result, Err(GitFetchError::NoSuchRemote(_))));
 }
 
@@ -716,6 +903,7 @@ fn test_push_updates_success() {
             force: false,
             new_target: Some(setup.new_commit.id().clone()),
         }],
+        git::RemoteCallbacks::default(),
     );
     assert_eq!(result, Ok(()));
 
@@ -757,6 +945,7 @@ fn test_push_updates_deletion() {
             force: false,
             new_target: None,
         }],
+        git::RemoteCallbacks::default(),
     );
     assert_eq!(result, Ok(()));
 
@@ -792,6 +981,7 @@ fn test_push_updates_mixed_deletion_and_addition() {
                 new_target: Some(setup.new_commit.id().clone()),
             },
         ],
+        git::RemoteCallbacks::default(),
     );
     assert_eq!(result, Ok(()));
 
@@ -825,6 +1015,7 @@ fn test_push_updates_not_fast_forward() {
             force: false,
             new_target: Some(new_commit.id().clone()),
         }],
+        git::RemoteCallbacks::default(),
     );
     assert_eq!(result, Err(GitPushError::NotFastForward));
 }
@@ -846,6 +1037,7 @@ fn test_push_updates_not_fast_forward_with_force() {
             force: true,
             new_target: Some(new_commit.id().clone()),
         }],
+        git::RemoteCallbacks::default(),
     );
     assert_eq!(result, Ok(()));
 
@@ -859,6 +1051,69 @@ fn test_push_updates_not_fast_forward_with_force() {
     assert_eq!(new_target, Some(new_oid));
 }
 
+#[test]
+fn test_push_updates_signed() {
+    let settings = testutils::user_settings();
+    let temp_dir = testutils::new_temp_dir();
+    let mut setup = set_up_push_repos(&settings, &temp_dir);
+    let git_repo = setup.jj_repo.store().git_repo().unwrap();
+    let update = |force, new_target: &Commit| GitRefUpdate {
+        qualified_name: "refs/heads/main".to_string(),
+        force,
+        new_target: Some(new_target.id().clone()),
+    };
+
+    // The remote doesn't support push certificates
+    let result = git::push_updates_signed(
+        &git_repo,
+        "origin",
+        &[update(false, &setup.new_commit)],
+        SignedPush::Yes,
+    );
+    assert_matches!(result, Err(GitPushError::GitCommandFailed(_)));
+    let result = git::push_updates_signed(
+        &git_repo,
+        "origin",
+        &[update(false, &setup.new_commit)],
+        SignedPush::IfAsked,
+    );
+    assert_eq!(result, Ok(()));
+    let source_repo = git2::Repository::open(&setup.source_repo_dir).unwrap();
+    let new_target = source_repo
+        .find_reference("refs/heads/main")
+        .unwrap()
+        .target();
+    let new_oid = Oid::from_bytes(setup.new_commit.id().as_bytes()).unwrap();
+    assert_eq!(new_target, Some(new_oid));
+
+    let mut tx = setup.jj_repo.start_transaction("test");
+    let new_commit =
+        testutils::create_random_commit(&settings, &setup.jj_repo).write_to_repo(tx.mut_repo());
+    setup.jj_repo = tx.commit();
+    let result = git::push_updates_signed(
+        &git_repo,
+        "origin",
+        &[update(false, &new_commit)],
+        SignedPush::IfAsked,
+    );
+    assert_eq!(
+        result,
+        Err(GitPushError::RefUpdateRejected(vec![
+            "refs/heads/main".to_string()
+        ]))
+    );
+    let result = git::push_updates_signed(
+        &git_repo,
+        "nonexistent",
+        &[update(true, &new_commit)],
+        SignedPush::IfAsked,
+    );
+    assert_eq!(
+        result,
+        Err(GitPushError::NoSuchRemote("nonexistent".to_string()))
+    );
+}
+
 #[test]
 fn test_push_updates_no_such_remote() {
     let settings = testutils::user_settings();
@@ -872,6 +1127,7 @@ fn test_push_updates_no_such_remote() {
             force: false,
             new_target: Some(setup.new_commit.id().clone()),
         }],
+        git::RemoteCallbacks::default(),
     );
     assert!(matches!(result, Err(GitPushError::NoSuchRemote(_))));
 }
@@ -889,6 +1145,7 @@ fn test_push_updates_invalid_remote() {
             force: false,
             new_target: Some(setup.new_commit.id().clone()),
         }],
+        git::RemoteCallbacks::default(),
     );
     assert!(matches!(result, Err(GitPushError::NoSuchRemote(_))));
 }
@@ -0,0 +1,81 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use jujutsu_lib::gitignore::GitIgnoreFile;
+use jujutsu_lib::testutils;
+use test_case::test_case;
+
+/// Checks that `GitIgnoreFile` agrees with libgit2 about which of `paths` are
+/// ignored by a `.gitignore` file at the root with `content`. The paths are all
+/// files; they don't need to exist on disk.
+fn assert_matches_git(content: &str, paths: &[&str]) {
+    let _home_dir = testutils::new_user_home();
+    let temp_dir = testutils::new_temp_dir();
+    let git_repo = git2::Repository::init(temp_dir.path()).unwrap();
+    std::fs::write(temp_dir.path().join(".gitignore"), content).unwrap();
+
+    let ignores = GitIgnoreFile::empty().chain("", content.as_bytes());
+    for path in paths {
+        assert_eq!(
+            ignores.matches_file(path),
+            git_repo.is_path_ignored(path).unwrap(),
+            "mismatch for {path:?} with .gitignore {content:?}"
+        );
+    }
+}
+
+const PATHS: &[&str] = &[
+    "foo",
+    "bar",
+    "#foo",
+    "!foo",
+    "foo ",
+    "foo.a",
+    "foo.b",
+    "foo.]",
+    "dir/foo",
+    "dir/bar",
+    "dir/sub/foo",
+    "dir/sub/bar",
+    "foo-dir/foo",
+    "foo-dir/bar",
+    "foo-dir/sub/bar",
+    "other/dir/bar",
+];
+
+#[test_case("foo" ; "literal")]
+#[test_case("/foo" ; "rooted literal")]
+#[test_case("dir/foo" ; "path")]
+#[test_case("dir/" ; "directory")]
+#[test_case("*.a" ; "glob")]
+#[test_case("foo.[!a]" ; "negated range")]
+#[test_case("foo.[]]" ; "bracket range")]
+#[test_case("\\#foo" ; "escaped hash")]
+#[test_case("#foo" ; "comment")]
+#[test_case("\\!foo" ; "escaped exclamation mark")]
+#[test_case("foo\\ " ; "escaped trailing space")]
+#[test_case("foo  " ; "trailing spaces")]
+#[test_case("**/sub" ; "leading double star")]
+#[test_case("dir/**" ; "trailing double star")]
+#[test_case("dir/**/bar" ; "internal double star")]
+#[test_case("dir\n!dir/bar" ; "re-include in excluded directory")]
+#[test_case("dir/\n!bar" ; "re-include basename in excluded directory")]
+#[test_case("dir/*\n!dir/bar" ; "re-include in included directory")]
+#[test_case("dir/*\n!dir/sub/\ndir/sub/foo" ; "re-include directory")]
+#[test_case("/*\n!/foo-dir/\n/foo-dir/*\n!/foo-dir/sub/" ; "nested re-include")]
+#[test_case("*-dir\n!foo-dir" ; "re-include directory by name")]
+#[test_case("bar\n!dir/bar\n!dir/sub/" ; "negations of unrelated paths")]
+fn test_gitignore_conformance(content: &str) {
+    assert_matches_git(content, PATHS);
+}
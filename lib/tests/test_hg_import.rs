@@ -0,0 +1,283 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+use std::process::Command;
+
+use jujutsu_lib::backend::{MillisSinceEpoch, Signature, Timestamp, TreeValue};
+use jujutsu_lib::hg::{
+    import_changesets, HgChangeset, HgFile, HgFileChange, HgImportError, HgImportState, HgRepo,
+};
+use jujutsu_lib::op_store::RefTarget;
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestRepo;
+use test_case::test_case;
+
+fn changeset(node: char, parents: &[char], files: &[(&str, Option<&str>)]) -> HgChangeset {
+    HgChangeset {
+        node: node.to_string().repeat(40),
+        parents: parents.iter().map(|p| p.to_string().repeat(40)).collect(),
+        author: Signature {
+            name: "Some One".to_string(),
+            email: "some.one@example.com".to_string(),
+            timestamp: Timestamp {
+                timestamp: MillisSinceEpoch(1_000_000_000_000),
+                tz_offset: -420,
+            },
+        },
+        description: format!("changeset {node}"),
+        branch: "default".to_string(),
+        bookmarks: vec![],
+        files: files
+            .iter()
+            .map(|(path, contents)| HgFileChange {
+                path: RepoPath::from_internal_string(path),
+                file: contents.map(|contents| HgFile::Normal {
+                    contents: contents.as_bytes().to_vec(),
+                    executable: false,
+                }),
+            })
+            .collect(),
+    }
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_import_changesets(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut changeset_a = changeset('a', &[], &[("file", Some("a\n")), ("other", Some("x\n"))]);
+    changeset_a.bookmarks = vec!["main".to_string()];
+    let mut changeset_b = changeset('b', &['a'], &[("file", Some("b\n")), ("other", None)]);
+    changeset_b.branch = "stable".to_string();
+    let changesets = vec![changeset_a.clone(), changeset_b.clone()];
+
+    let mut state = HgImportState::default();
+    let mut tx = repo.start_transaction("test");
+    let stats = import_changesets(&settings, tx.mut_repo(), &mut state, &changesets).unwrap();
+    let repo = tx.commit();
+    assert_eq!(stats.imported_changesets, 2);
+    assert_eq!(stats.updated_branches, 2);
+
+    let commit_a = repo
+        .store()
+        .get_commit(state.commit_id(&changeset_a.node).unwrap())
+        .unwrap();
+    let commit_b = repo
+        .store()
+        .get_commit(state.commit_id(&changeset_b.node).unwrap())
+        .unwrap();
+    assert_eq!(
+        commit_a.parent_ids(),
+        &[repo.store().root_commit_id().clone()]
+    );
+    assert_eq!(commit_b.parent_ids(), &[commit_a.id().clone()]);
+    assert_eq!(commit_a.author(), &changeset_a.author);
+    assert_eq!(commit_a.committer(), &changeset_a.author);
+    assert_eq!(commit_b.description(), "changeset b");
+    assert!(!commit_b.is_open());
+    // The change ID is derived from the node
+    assert_eq!(commit_a.change_id().hex(), "a".repeat(32));
+
+    let file_path = RepoPath::from_internal_string("file");
+    let other_path = RepoPath::from_internal_string("other");
+    assert!(commit_a.tree().path_value(&other_path).is_some());
+    assert!(commit_b.tree().path_value(&other_path).is_none());
+    match commit_b.tree().path_value(&file_path) {
+        Some(TreeValue::Normal { id, .. }) => {
+            assert_eq!(testutils::read_file(repo.store(), &file_path, &id), b"b\n");
+        }
+        value => panic!("unexpected value: {value:?}"),
+    }
+
+    assert_eq!(
+        repo.view().get_local_branch("main"),
+        Some(RefTarget::Normal(commit_a.id().clone()))
+    );
+    assert_eq!(
+        repo.view().get_local_branch("stable"),
+        Some(RefTarget::Normal(commit_b.id().clone()))
+    );
+    assert_eq!(repo.view().get_local_branch("default"), None);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_import_changesets_incremental(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let changeset_a = changeset('a', &[], &[("file", Some("a\n"))]);
+    let mut state = HgImportState::default();
+    let mut tx = repo.start_transaction("test");
+    import_changesets(
+        &settings,
+        tx.mut_repo(),
+        &mut state,
+        std::slice::from_ref(&changeset_a),
+    )
+    .unwrap();
+    let repo = tx.commit();
+    let commit_id_a = state.commit_id(&changeset_a.node).unwrap().clone();
+
+    // The state survives a round trip through the repo directory
+    state.save(repo.repo_path()).unwrap();
+    let mut state = HgImportState::load(repo.repo_path()).unwrap();
+    assert_eq!(state.len(), 1);
+
+    // Importing again only imports the new changeset. Its files don't need to
+    // be read again, and the bookmark moved to it.
+    let mut changeset_a = changeset('a', &[], &[]);
+    let mut changeset_b = changeset('b', &['a'], &[("file", Some("b\n"))]);
+    changeset_a.bookmarks = vec![];
+    changeset_b.bookmarks = vec!["main".to_string()];
+    let mut tx = repo.start_transaction("test");
+    let stats = import_changesets(
+        &settings,
+        tx.mut_repo(),
+        &mut state,
+        &[changeset_a.clone(), changeset_b.clone()],
+    )
+    .unwrap();
+    let repo = tx.commit();
+    assert_eq!(stats.imported_changesets, 1);
+    assert_eq!(state.commit_id(&changeset_a.node), Some(&commit_id_a));
+    let commit_b = repo
+        .store()
+        .get_commit(state.commit_id(&changeset_b.node).unwrap())
+        .unwrap();
+    assert_eq!(commit_b.parent_ids(), &[commit_id_a]);
+    assert_eq!(
+        repo.view().get_local_branch("main"),
+        Some(RefTarget::Normal(commit_b.id().clone()))
+    );
+
+    // Nothing to do the third time
+    let mut tx = repo.start_transaction("test");
+    let stats = import_changesets(
+        &settings,
+        tx.mut_repo(),
+        &mut state,
+        &[changeset_a, changeset_b],
+    )
+    .unwrap();
+    assert_eq!(stats.imported_changesets, 0);
+    assert_eq!(stats.updated_branches, 0);
+}
+
+#[test]
+fn test_import_changesets_missing_parent() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut state = HgImportState::default();
+    let mut tx = repo.start_transaction("test");
+    let result = import_changesets(
+        &settings,
+        tx.mut_repo(),
+        &mut state,
+        &[changeset('b', &['a'], &[])],
+    );
+    assert!(matches!(result, Err(HgImportError::MissingParent { .. })));
+}
+
+#[test]
+fn test_open_not_hg_repo() {
+    let temp_dir = testutils::new_temp_dir();
+    assert!(matches!(
+        HgRepo::open(temp_dir.path()),
+        Err(HgImportError::NotAnHgRepo(_))
+    ));
+}
+
+/// Runs `hg` in `repo_path`. Returns `None` if Mercurial isn't installed.
+fn run_hg(repo_path: &Path, args: &[&str]) -> Option<()> {
+    let output = Command::new("hg")
+        .args(args)
+        .current_dir(repo_path)
+        .env("HGPLAIN", "1")
+        .env("HGUSER", "Some One <some.one@example.com>")
+        .output()
+        .ok()?;
+    assert!(
+        output.status.success(),
+        "hg {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Some(())
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_import_hg_merge(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // Create a merge that takes "right" and "added" unchanged from its second
+    // parent. Mercurial doesn't list those in the merge changeset's files.
+    let temp_dir = testutils::new_temp_dir();
+    let hg_path = temp_dir.path();
+    if run_hg(hg_path, &["init"]).is_none() {
+        eprintln!("Skipping test because hg is not installed");
+        return;
+    }
+    std::fs::write(hg_path.join("left"), "base\n").unwrap();
+    std::fs::write(hg_path.join("right"), "base\n").unwrap();
+    run_hg(hg_path, &["commit", "-A", "-m", "base"]);
+    std::fs::write(hg_path.join("right"), "right\n").unwrap();
+    std::fs::write(hg_path.join("added"), "added\n").unwrap();
+    run_hg(hg_path, &["commit", "-A", "-m", "right"]);
+    run_hg(hg_path, &["update", "-r", "0"]);
+    std::fs::write(hg_path.join("left"), "left\n").unwrap();
+    run_hg(hg_path, &["commit", "-m", "left"]);
+    run_hg(hg_path, &["merge", "-r", "1"]);
+    run_hg(hg_path, &["commit", "-m", "merge"]);
+
+    let hg_repo = HgRepo::open(hg_path).unwrap();
+    let mut state = HgImportState::default();
+    let changesets = hg_repo.changesets(&state).unwrap();
+    assert_eq!(changesets.len(), 4);
+    let mut tx = repo.start_transaction("test");
+    import_changesets(&settings, tx.mut_repo(), &mut state, &changesets).unwrap();
+    let repo = tx.commit();
+
+    let merge = repo
+        .store()
+        .get_commit(state.commit_id(&changesets[3].node).unwrap())
+        .unwrap();
+    assert_eq!(merge.parent_ids().len(), 2);
+    for (path, expected) in [
+        ("left", "left\n"),
+        ("right", "right\n"),
+        ("added", "added\n"),
+    ] {
+        let path = RepoPath::from_internal_string(path);
+        match merge.tree().path_value(&path) {
+            Some(TreeValue::Normal { id, .. }) => {
+                assert_eq!(
+                    testutils::read_file(repo.store(), &path, &id),
+                    expected.as_bytes()
+                );
+            }
+            value => panic!("unexpected value for {path:?}: {value:?}"),
+        }
+    }
+}
@@ -393,6 +393,111 @@ fn test_index_commits_incremental_already_indexed(use_git: bool) {
     assert_eq!(mut_repo.index().num_commits(), 1 + 1);
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_index_commits_add_head_with_missing_ancestors(use_git: bool) {
+    // Tests that adding a head indexes all its ancestors that aren't indexed yet.
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // Create the commits in a transaction that's discarded, so they're in the
+    // store but not in the index.
+    // o   D
+    // |\
+    // o | C
+    // | o B
+    // |/
+    // o A
+    let mut tx = repo.start_transaction("test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit_a = graph_builder.initial_commit();
+    let commit_b = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_c = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_d = graph_builder.commit_with_parents(&[&commit_b, &commit_c]);
+    drop(tx);
+    assert!(!repo.index().has_id(commit_a.id()));
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+    mut_repo.add_head(&commit_d);
+    let index = mut_repo.index();
+    assert_eq!(index.num_commits(), repo.index().num_commits() + 4);
+    assert_eq!(generation_number(index, commit_a.id()), 1);
+    assert_eq!(generation_number(index, commit_b.id()), 2);
+    assert_eq!(generation_number(index, commit_c.id()), 2);
+    assert_eq!(generation_number(index, commit_d.id()), 3);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_index_commits_reindex_incremental(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // Create A in one operation, then B and C in another. Then lose the index of
+    // the second operation and check that it's indexed again.
+    // o C
+    // o B
+    // o A
+    // | o working copy
+    // |/
+    // o root
+
+    let root_commit = repo.store().root_commit();
+    let mut tx = repo.start_transaction("test");
+    let commit_a = child_commit(&settings, repo, &root_commit).write_to_repo(tx.mut_repo());
+    let repo = tx.commit();
+
+    let mut tx = repo.start_transaction("test");
+    let commit_b = child_commit(&settings, &repo, &commit_a).write_to_repo(tx.mut_repo());
+    let commit_c = child_commit(&settings, &repo, &commit_b).write_to_repo(tx.mut_repo());
+    let repo = tx.commit();
+    let op_id_file = repo
+        .repo_path()
+        .join("index")
+        .join("operations")
+        .join(repo.op_id().hex());
+    std::fs::remove_file(&op_id_file).unwrap();
+
+    let mut repo =
+        ReadonlyRepo::load_at_head(&settings, repo.repo_path(), &BackendFactories::default())
+            .unwrap();
+    let index = Arc::get_mut(&mut repo).unwrap().reindex_incremental();
+    // There should be the root commit, plus 3 more
+    assert_eq!(index.num_commits(), 1 + 3);
+    assert_eq!(generation_number(index.as_ref(), commit_c.id()), 3);
+    assert!(op_id_file.is_file());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_index_commits_corrupt_file(use_git: bool) {
+    // Tests that a damaged index file is detected and the index is rebuilt.
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let root_commit = repo.store().root_commit();
+    let mut tx = repo.start_transaction("test");
+    let commit_a = child_commit(&settings, repo, &root_commit).write_to_repo(tx.mut_repo());
+    let repo = tx.commit();
+
+    let index_file = repo.repo_path().join("index").join(repo.index().name());
+    let mut buf = std::fs::read(&index_file).unwrap();
+    buf.pop();
+    std::fs::write(&index_file, buf).unwrap();
+
+    let repo =
+        ReadonlyRepo::load_at_head(&settings, repo.repo_path(), &BackendFactories::default())
+            .unwrap();
+    let index = repo.index();
+    assert_eq!(index.num_commits(), 1 + 1);
+    assert!(index.has_id(commit_a.id()));
+    assert_eq!(index.verify(), Ok(()));
+}
+
 #[must_use]
 fn create_n_commits(
     settings: &UserSettings,
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use jujutsu_lib::repo::{BackendFactories, RepoLoader};
+use jujutsu_lib::repo::{BackendFactories, ReadonlyRepo, RepoLoader};
 use jujutsu_lib::testutils;
 use jujutsu_lib::testutils::TestRepo;
 use test_case::test_case;
@@ -44,3 +44,35 @@ fn test_load_at_operation(use_git: bool) {
     let old_repo = loader.load_at(repo.operation());
     assert!(old_repo.view().heads().contains(commit.id()));
 }
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_load_at_head_readonly(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // Create two concurrent operations
+    let mut tx1 = repo.start_transaction("add commit 1");
+    let commit1 = testutils::create_random_commit(&settings, repo).write_to_repo(tx1.mut_repo());
+    tx1.commit();
+    // Make sure the operations' timestamps differ
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    let mut tx2 = repo.start_transaction("add commit 2");
+    let commit2 = testutils::create_random_commit(&settings, repo).write_to_repo(tx2.mut_repo());
+    let repo2 = tx2.commit();
+
+    // The repo is loaded at the most recent operation, and the operations are not
+    // merged
+    let readonly_repo = ReadonlyRepo::load_at_head_readonly(
+        &settings,
+        repo.repo_path(),
+        &BackendFactories::default(),
+    )
+    .unwrap();
+    assert_eq!(readonly_repo.op_id(), repo2.op_id());
+    assert!(readonly_repo.view().heads().contains(commit2.id()));
+    assert!(!readonly_repo.view().heads().contains(commit1.id()));
+    assert_eq!(repo.op_heads_store().get_op_heads().len(), 2);
+    assert!(!repo.repo_path().join("op_heads").join("lock").exists());
+}
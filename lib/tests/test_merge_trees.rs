@@ -14,11 +14,13 @@
 
 use assert_matches::assert_matches;
 use itertools::Itertools;
-use jujutsu_lib::backend::{ConflictPart, TreeValue};
+use jujutsu_lib::backend::{Conflict, ConflictPart, TreeValue};
 use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::merge_attributes::MergeAttributes;
 use jujutsu_lib::repo_path::{RepoPath, RepoPathComponent};
 use jujutsu_lib::rewrite::rebase_commit;
-use jujutsu_lib::testutils::TestRepo;
+use jujutsu_lib::settings::UserSettings;
+use jujutsu_lib::testutils::{TestRepo, TestWorkspace};
 use jujutsu_lib::tree::Tree;
 use jujutsu_lib::{testutils, tree};
 use test_case::test_case;
@@ -340,6 +342,209 @@ fn test_subtree_one_missing(use_git: bool) {
     assert_eq!(merged_tree_id, *merged_tree.id());
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_merge_attributes_ignore_whitespace(use_git: bool) {
+    let config = config::Config::builder()
+        .add_source(testutils::user_settings().config().clone())
+        .set_override("merge-attributes.text.patterns", vec!["*.txt"])
+        .unwrap()
+        .set_override("merge-attributes.text.ignore-whitespace", true)
+        .unwrap()
+        .build()
+        .unwrap();
+    let settings = UserSettings::from_config(config);
+    let test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = &test_workspace.repo;
+    let store = repo.store();
+
+    let text_path = RepoPath::from_internal_string("dir/file.txt");
+    let other_path = RepoPath::from_internal_string("dir/file.rs");
+    let write_tree = |contents: &str| {
+        testutils::create_tree(repo, &[(&text_path, contents), (&other_path, contents)])
+    };
+    let base_tree = write_tree("a\nb\nc\n");
+    let side1_tree = write_tree("a\r\nb\r\nc\r\n");
+    let side2_tree = write_tree("a\nB\nc\n");
+
+    // The whitespace change only conflicts in the file without the attribute
+    let merged_tree_id = tree::merge_trees(&side1_tree, &base_tree, &side2_tree).unwrap();
+    let merged_tree = store.get_tree(&RepoPath::root(), &merged_tree_id).unwrap();
+    assert_eq!(
+        merged_tree.path_value(&text_path),
+        side2_tree.path_value(&text_path)
+    );
+    assert_matches!(
+        merged_tree.path_value(&other_path),
+        Some(TreeValue::Conflict(_))
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_merge_attributes_strategies(use_git: bool) {
+    let mut builder =
+        config::Config::builder().add_source(testutils::user_settings().config().clone());
+    for strategy in ["union", "ours", "theirs"] {
+        builder = builder
+            .set_override(
+                format!("merge-attributes.{strategy}.patterns"),
+                vec![format!("*.{strategy}")],
+            )
+            .unwrap()
+            .set_override(format!("merge-attributes.{strategy}.strategy"), strategy)
+            .unwrap();
+    }
+    let settings = UserSettings::from_config(builder.build().unwrap());
+    let test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = &test_workspace.repo;
+    let store = repo.store();
+
+    let paths =
+        ["file.union", "file.ours", "file.theirs", "file.txt"].map(RepoPath::from_internal_string);
+    let write_tree = |contents: &str| {
+        let path_contents = paths
+            .iter()
+            .map(|path| (path, contents))
+            .collect::<Vec<_>>();
+        testutils::create_tree(repo, &path_contents)
+    };
+    let base_tree = write_tree("a\nb\n");
+    let side1_tree = write_tree("a\nleft\n");
+    let side2_tree = write_tree("a\nright\n");
+
+    let merged_tree_id = tree::merge_trees(&side1_tree, &base_tree, &side2_tree).unwrap();
+    let merged_tree = store.get_tree(&RepoPath::root(), &merged_tree_id).unwrap();
+    let read_merged_file = |path: &RepoPath| match merged_tree.path_value(path) {
+        Some(TreeValue::Normal { id, .. }) => {
+            String::from_utf8(testutils::read_file(store, path, &id)).unwrap()
+        }
+        other => panic!("unexpected value {other:?}"),
+    };
+    assert_eq!(read_merged_file(&paths[0]), "a\nleft\nright\n");
+    assert_eq!(read_merged_file(&paths[1]), "a\nleft\n");
+    assert_eq!(read_merged_file(&paths[2]), "a\nright\n");
+    assert_matches!(
+        merged_tree.path_value(&paths[3]),
+        Some(TreeValue::Conflict(_))
+    );
+}
+
+#[test]
+fn test_merge_attributes_invalid_strategy() {
+    let config = config::Config::builder()
+        .set_override("merge-attributes.lockfiles.patterns", vec!["*.lock"])
+        .unwrap()
+        .set_override("merge-attributes.lockfiles.strategy", "mine")
+        .unwrap()
+        .build()
+        .unwrap();
+    assert_eq!(
+        MergeAttributes::from_config(&config)
+            .unwrap_err()
+            .to_string(),
+        r#"Invalid merge attributes config: merge-attributes.lockfiles: `strategy` must be "union", "ours" or "theirs", not "mine""#
+    );
+}
+
+#[cfg(unix)]
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_merge_attributes_driver(use_git: bool) {
+    let config = config::Config::builder()
+        .add_source(testutils::user_settings().config().clone())
+        .set_override("merge-attributes.concat.patterns", vec!["*.log"])
+        .unwrap()
+        .set_override(
+            "merge-attributes.concat.driver",
+            vec!["sh", "-c", "echo $path; cat $base $left $right"],
+        )
+        .unwrap()
+        .set_override("merge-attributes.fail.patterns", vec!["*.fail"])
+        .unwrap()
+        .set_override("merge-attributes.fail.driver", vec!["false"])
+        .unwrap()
+        .set_override("merge-attributes.missing.patterns", vec!["*.missing"])
+        .unwrap()
+        .set_override(
+            "merge-attributes.missing.driver",
+            vec!["/nonexistent/merge-driver"],
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+    let settings = UserSettings::from_config(config);
+    let test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = &test_workspace.repo;
+    let store = repo.store();
+
+    let modified_path = RepoPath::from_internal_string("dir/modified.log");
+    let added_path = RepoPath::from_internal_string("added.log");
+    let failed_path = RepoPath::from_internal_string("file.fail");
+    let missing_path = RepoPath::from_internal_string("file.missing");
+    let base_tree = testutils::create_tree(
+        repo,
+        &[
+            (&modified_path, "base\n"),
+            (&failed_path, "base\n"),
+            (&missing_path, "base\n"),
+        ],
+    );
+    let side1_tree = testutils::create_tree(
+        repo,
+        &[
+            (&modified_path, "left\n"),
+            (&added_path, "left\n"),
+            (&failed_path, "left\n"),
+            (&missing_path, "left\n"),
+        ],
+    );
+    let side2_tree = testutils::create_tree(
+        repo,
+        &[
+            (&modified_path, "right\n"),
+            (&added_path, "right\n"),
+            (&failed_path, "right\n"),
+            (&missing_path, "right\n"),
+        ],
+    );
+
+    let merged_tree_id = tree::merge_trees(&side1_tree, &base_tree, &side2_tree).unwrap();
+    let merged_tree = store.get_tree(&RepoPath::root(), &merged_tree_id).unwrap();
+    let read_merged_file = |path: &RepoPath| match merged_tree.path_value(path) {
+        Some(TreeValue::Normal { id, .. }) => {
+            String::from_utf8(testutils::read_file(store, path, &id)).unwrap()
+        }
+        other => panic!("unexpected value {other:?}"),
+    };
+    assert_eq!(
+        read_merged_file(&modified_path),
+        "dir/modified.log\nbase\nleft\nright\n"
+    );
+    // A missing base is passed as an empty file
+    assert_eq!(read_merged_file(&added_path), "added.log\nleft\nright\n");
+    // The file is left conflicted if the driver fails, or can't be run
+    assert_matches!(
+        merged_tree.path_value(&failed_path),
+        Some(TreeValue::Conflict(_))
+    );
+    assert_matches!(
+        merged_tree.path_value(&missing_path),
+        Some(TreeValue::Conflict(_))
+    );
+
+    // Failing to run the driver is an error when calling it directly
+    let missing_attribute = store.merge_attributes().for_path(&missing_path).unwrap();
+    let err = missing_attribute
+        .run_driver(&missing_path, b"base\n", b"left\n", b"right\n")
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("Failed to run the merge driver of merge-attributes.missing: "),
+        "{err}"
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_types(use_git: bool) {
@@ -632,5 +837,62 @@ fn test_simplify_conflict_after_resolving_parent(use_git: bool) {
     }
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_simplify_conflicts_identical_adds(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    // Record a conflict where both sides made the same change, as older versions
+    // would have done, plus a real conflict that should be left alone.
+    let same_path = RepoPath::from_internal_string("same");
+    let real_path = RepoPath::from_internal_string("real");
+    let normal = |path: &RepoPath, contents: &str| ConflictPart {
+        value: TreeValue::Normal {
+            id: testutils::write_file(store, path, contents),
+            executable: false,
+        },
+    };
+    let same_conflict = Conflict {
+        removes: vec![normal(&same_path, "base\n")],
+        adds: vec![
+            normal(&same_path, "changed\n"),
+            normal(&same_path, "changed\n"),
+        ],
+    };
+    let real_conflict = Conflict {
+        removes: vec![normal(&real_path, "base\n")],
+        adds: vec![normal(&real_path, "left\n"), normal(&real_path, "right\n")],
+    };
+    let same_conflict_id = store.write_conflict(&same_path, &same_conflict).unwrap();
+    let real_conflict_id = store.write_conflict(&real_path, &real_conflict).unwrap();
+    let mut tree_builder = store.tree_builder(store.empty_tree_id().clone());
+    tree_builder.set(same_path.clone(), TreeValue::Conflict(same_conflict_id));
+    tree_builder.set(
+        real_path.clone(),
+        TreeValue::Conflict(real_conflict_id.clone()),
+    );
+    let tree_id = tree_builder.write_tree();
+    let tree = store.get_tree(&RepoPath::root(), &tree_id).unwrap();
+
+    let simplified_id = tree::simplify_conflicts(&tree).unwrap();
+    let simplified = store.get_tree(&RepoPath::root(), &simplified_id).unwrap();
+    assert_eq!(
+        simplified.path_value(&same_path),
+        Some(same_conflict.adds[0].value.clone())
+    );
+    assert_eq!(
+        simplified.path_value(&real_path),
+        Some(TreeValue::Conflict(real_conflict_id))
+    );
+
+    // Simplifying again doesn't change anything
+    assert_eq!(
+        tree::simplify_conflicts(&simplified).unwrap(),
+        simplified_id
+    );
+}
+
 // TODO: Add tests for simplification of multi-way conflicts. Both the content
 // and the executable bit need testing.
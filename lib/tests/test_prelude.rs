@@ -0,0 +1,47 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use itertools::Itertools;
+use jujutsu_lib::prelude::*;
+use jujutsu_lib::testutils;
+
+#[test]
+fn test_prelude_workflow() {
+    // Tests that the prelude is enough for a simple tool: create a repo, commit
+    // to it, and query it with a revset.
+    let settings = testutils::user_settings();
+    let temp_dir = testutils::new_temp_dir();
+    let (workspace, repo) = Workspace::init_local(&settings, temp_dir.path()).unwrap();
+
+    let mut tx: Transaction = repo.start_transaction("test");
+    let commit: Commit = CommitBuilder::for_new_commit(
+        &settings,
+        vec![repo.store().root_commit_id().clone()],
+        repo.store().empty_tree_id().clone(),
+    )
+    .set_description("prelude".to_string())
+    .write_to_repo(tx.mut_repo());
+    let repo: std::sync::Arc<ReadonlyRepo> = tx.commit();
+
+    let expression = RevsetExpression::commit(commit.id().clone()).ancestors();
+    let revset = expression
+        .evaluate(repo.as_repo_ref(), Some(&workspace.workspace_id()))
+        .unwrap();
+    let commit_ids: Vec<CommitId> = revset.iter().commit_ids().collect_vec();
+    assert_eq!(
+        commit_ids,
+        vec![commit.id().clone(), repo.store().root_commit_id().clone()]
+    );
+    assert!(EverythingMatcher.matches(&RepoPath::from_internal_string("file")));
+}
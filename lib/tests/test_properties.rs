@@ -0,0 +1,147 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Property-based tests. Run them with `cargo test --features proptest`.
+
+#![cfg(feature = "proptest")]
+
+use jujutsu_lib::conflicts::{materialize_merge_result, parse_conflict};
+use jujutsu_lib::diff::{diff, DiffHunk};
+use jujutsu_lib::files::{merge, MergeHunk, MergeResult};
+use jujutsu_lib::gitignore::GitIgnoreFile;
+use proptest::prelude::*;
+
+/// Text made of a few short lines from a small alphabet, so that different
+/// inputs often have lines in common.
+fn text() -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec("[abc]{0,2}\n", 0..8).prop_map(|lines| lines.concat().into_bytes())
+}
+
+fn diff_side(hunks: &[DiffHunk], side: usize) -> Vec<u8> {
+    hunks
+        .iter()
+        .flat_map(|hunk| match hunk {
+            DiffHunk::Matching(content) => content.to_vec(),
+            DiffHunk::Different(contents) => contents[side].to_vec(),
+        })
+        .collect()
+}
+
+/// Joins adjacent resolved hunks and drops empty ones, and sorts the adds of
+/// conflicts (materializing may reorder them).
+fn normalize_hunks(hunks: Vec<MergeHunk>) -> Vec<MergeHunk> {
+    let mut result: Vec<MergeHunk> = vec![];
+    for hunk in hunks {
+        match hunk {
+            MergeHunk::Resolved(content) if content.is_empty() => {}
+            MergeHunk::Resolved(content) => match result.last_mut() {
+                Some(MergeHunk::Resolved(previous)) => previous.extend(content),
+                _ => result.push(MergeHunk::Resolved(content)),
+            },
+            MergeHunk::Conflict { removes, mut adds } => {
+                adds.sort();
+                result.push(MergeHunk::Conflict { removes, adds });
+            }
+        }
+    }
+    result
+}
+
+proptest! {
+    #[test]
+    fn diff_reproduces_inputs(left in any::<Vec<u8>>(), right in any::<Vec<u8>>()) {
+        let hunks = diff(&left, &right);
+        prop_assert_eq!(diff_side(&hunks, 0), left.clone());
+        prop_assert_eq!(diff_side(&hunks, 1), right.clone());
+    }
+
+    #[test]
+    fn diff_reproduces_inputs_text(left in text(), right in text()) {
+        let hunks = diff(&left, &right);
+        prop_assert_eq!(diff_side(&hunks, 0), left.clone());
+        prop_assert_eq!(diff_side(&hunks, 1), right.clone());
+    }
+
+    #[test]
+    fn merge_trivial(base in text(), side in text()) {
+        prop_assert_eq!(
+            merge(&[&base], &[&base, &side]),
+            MergeResult::Resolved(side.clone())
+        );
+        prop_assert_eq!(
+            merge(&[&base], &[&side, &base]),
+            MergeResult::Resolved(side.clone())
+        );
+        prop_assert_eq!(
+            merge(&[&base], &[&side, &side]),
+            MergeResult::Resolved(side)
+        );
+    }
+
+    #[test]
+    fn conflict_materialize_parse_round_trip(base in text(), left in text(), right in text()) {
+        let merge_result = merge(&[&base], &[&left, &right]);
+        if let MergeResult::Conflict(hunks) = merge_result.clone() {
+            let mut materialized = vec![];
+            materialize_merge_result(merge_result, &mut materialized).unwrap();
+            let parsed = parse_conflict(&materialized, 1, 2);
+            prop_assert_eq!(parsed.map(normalize_hunks), Some(normalize_hunks(hunks)));
+        }
+    }
+
+    #[test]
+    fn conflict_parse_arbitrary(input in any::<Vec<u8>>()) {
+        for (num_removes, num_adds) in [(1, 2), (2, 3)] {
+            if let Some(hunks) = parse_conflict(&input, num_removes, num_adds) {
+                for hunk in hunks {
+                    if let MergeHunk::Conflict { removes, adds } = hunk {
+                        prop_assert_eq!(removes.len(), num_removes);
+                        prop_assert_eq!(adds.len(), num_adds);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn gitignore_arbitrary(input in any::<Vec<u8>>(), path in "[a-z/.*!]{0,10}") {
+        let ignore = GitIgnoreFile::empty().chain("", &input);
+        ignore.matches_file(&path);
+        if path.is_empty() || path.ends_with('/') {
+            ignore.matches_all_files_in(&path);
+        }
+    }
+
+    #[test]
+    fn gitignore_literal_names(name in "[a-z]{1,8}", other in "[a-z]{1,8}") {
+        let in_dir = format!("dir/{name}");
+        let in_subdir = format!("{name}/file");
+        let with_suffix = format!("{name}x");
+        let ignore = GitIgnoreFile::empty().chain("", format!("{name}\n").as_bytes());
+        prop_assert!(ignore.matches_file(&name));
+        prop_assert!(ignore.matches_file(&in_dir));
+        prop_assert!(ignore.matches_file(&in_subdir));
+        prop_assert_eq!(ignore.matches_file(&other), other == name);
+        prop_assert!(!ignore.matches_file(&with_suffix));
+
+        // A later negated pattern takes precedence
+        let ignore = ignore.chain("", format!("!{name}\n").as_bytes());
+        prop_assert!(!ignore.matches_file(&name));
+
+        // Patterns in a subdirectory's file only match under it
+        let ignore = GitIgnoreFile::empty().chain("dir/", format!("{name}\n").as_bytes());
+        prop_assert!(ignore.matches_file(&in_dir));
+        prop_assert!(!ignore.matches_file(&name));
+    }
+}
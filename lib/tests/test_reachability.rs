@@ -0,0 +1,94 @@
+// Copyright 2020 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use jujutsu_lib::index::IndexRef;
+use jujutsu_lib::op_store::RefTarget;
+use jujutsu_lib::reachability::ReachabilityBitmaps;
+use jujutsu_lib::revset::{parse, RevsetExpression};
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::{CommitGraphBuilder, TestRepo};
+use test_case::test_case;
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_reachability_bitmaps(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // o C (main, later)
+    // | o B (main)
+    // |/
+    // o A
+    let mut tx = repo.start_transaction("test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit_a = graph_builder.initial_commit();
+    let commit_b = graph_builder.commit_with_parents(&[&commit_a]);
+    let commit_c = graph_builder.commit_with_parents(&[&commit_a]);
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit_b.id().clone()));
+    let repo = tx.commit();
+
+    let bitmaps = ReachabilityBitmaps::load(repo.index_store().reachability_dir()).unwrap();
+    let index = IndexRef::Readonly(repo.index());
+    assert!(bitmaps.is_ancestor(index, commit_a.id(), commit_b.id()));
+    assert!(bitmaps.is_ancestor(index, repo.store().root_commit_id(), commit_b.id()));
+    assert!(!bitmaps.is_ancestor(index, commit_c.id(), commit_b.id()));
+    assert_eq!(bitmaps.ancestors(index, commit_b.id()).len(), 3);
+
+    // The bitmaps are stored, so they can be loaded again
+    let bitmaps = ReachabilityBitmaps::load(repo.index_store().reachability_dir()).unwrap();
+    assert_eq!(bitmaps.ancestors(index, commit_b.id()).len(), 3);
+
+    // `:main` gives the same result as walking the history
+    let expression = parse(":main").unwrap();
+    let revset = expression.evaluate(repo.as_repo_ref(), None).unwrap();
+    let commit_ids = revset.iter().commit_ids().collect::<Vec<_>>();
+    assert_eq!(
+        commit_ids,
+        vec![
+            commit_b.id().clone(),
+            commit_a.id().clone(),
+            repo.store().root_commit_id().clone(),
+        ]
+    );
+    let expression =
+        RevsetExpression::none().range(&RevsetExpression::commit(commit_b.id().clone()));
+    let walked_ids = expression
+        .evaluate(repo.as_repo_ref(), None)
+        .unwrap()
+        .iter()
+        .commit_ids()
+        .collect::<Vec<_>>();
+    assert_eq!(commit_ids, walked_ids);
+
+    // After the branch moves, the new head gets its own bitmap
+    let mut tx = repo.start_transaction("test");
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit_c.id().clone()));
+    let repo = tx.commit();
+    let index = IndexRef::Readonly(repo.index());
+    assert!(bitmaps.is_ancestor(index, commit_a.id(), commit_c.id()));
+    assert!(!bitmaps.is_ancestor(index, commit_b.id(), commit_c.id()));
+    let expression = parse(":main").unwrap();
+    let revset = expression.evaluate(repo.as_repo_ref(), None).unwrap();
+    assert_eq!(
+        revset.iter().commit_ids().collect::<Vec<_>>(),
+        vec![
+            commit_c.id().clone(),
+            commit_a.id().clone(),
+            repo.store().root_commit_id().clone(),
+        ]
+    );
+}
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use jujutsu_lib::op_store::RefTarget;
-use jujutsu_lib::refs::merge_ref_targets;
+use jujutsu_lib::refs::{merge_ref_targets, ref_divergence, ref_divergence_bounded, RefDivergence};
 use jujutsu_lib::testutils;
 use jujutsu_lib::testutils::{CommitGraphBuilder, TestWorkspace};
 
@@ -357,3 +357,71 @@ fn test_merge_ref_targets() {
         })
     );
 }
+
+#[test]
+fn test_ref_divergence() {
+    let settings = testutils::user_settings();
+    let test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+
+    // 4
+    // | 3
+    // | 2
+    // |/
+    // 1
+    let mut tx = repo.start_transaction("test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    let commit3 = graph_builder.commit_with_parents(&[&commit2]);
+    let commit4 = graph_builder.commit_with_parents(&[&commit1]);
+    let repo = tx.commit();
+
+    let target1 = RefTarget::Normal(commit1.id().clone());
+    let target3 = RefTarget::Normal(commit3.id().clone());
+    let target4 = RefTarget::Normal(commit4.id().clone());
+
+    let index = repo.index();
+    let index_ref = index.as_index_ref();
+
+    assert_eq!(
+        ref_divergence(index_ref, &target1, &target1),
+        RefDivergence::default()
+    );
+    assert_eq!(
+        ref_divergence(index_ref, &target3, &target1),
+        RefDivergence {
+            ahead: 2,
+            behind: 0
+        }
+    );
+    assert_eq!(
+        ref_divergence(index_ref, &target1, &target3),
+        RefDivergence {
+            ahead: 0,
+            behind: 2
+        }
+    );
+    let divergence = ref_divergence(index_ref, &target3, &target4);
+    assert_eq!(
+        divergence,
+        RefDivergence {
+            ahead: 2,
+            behind: 1
+        }
+    );
+    assert!(divergence.is_diverged());
+
+    // The bounded walk stops counting at the limit
+    assert_eq!(
+        ref_divergence_bounded(index_ref, &target3, &target4, 1),
+        RefDivergence {
+            ahead: 1,
+            behind: 1
+        }
+    );
+    assert_eq!(
+        ref_divergence_bounded(index_ref, &target3, &target4, 5),
+        divergence
+    );
+}
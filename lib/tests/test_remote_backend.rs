@@ -0,0 +1,358 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use assert_matches::assert_matches;
+use jujutsu_lib::backend::{Backend, BackendError, Tree, TreeValue};
+use jujutsu_lib::object_store::{
+    DirectoryObjectStore, HttpObjectStore, ObjectStore, ObjectStoreError, ObjectStoreResult,
+};
+use jujutsu_lib::remote_backend::RemoteBackend;
+use jujutsu_lib::repo_path::{RepoPath, RepoPathComponent};
+use jujutsu_lib::testutils;
+
+/// Wraps an object store, failing the first few requests and counting them.
+#[derive(Debug)]
+struct FlakyObjectStore {
+    inner: DirectoryObjectStore,
+    failures_left: Mutex<usize>,
+    error: ObjectStoreError,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl FlakyObjectStore {
+    fn new(path: &Path, failures: usize, error: ObjectStoreError) -> Self {
+        FlakyObjectStore {
+            inner: DirectoryObjectStore::new(path),
+            failures_left: Mutex::new(failures),
+            error,
+            requests: Default::default(),
+        }
+    }
+
+    fn start_request(&self, request: String) -> ObjectStoreResult<()> {
+        self.requests.lock().unwrap().push(request);
+        let mut failures_left = self.failures_left.lock().unwrap();
+        if *failures_left > 0 {
+            *failures_left -= 1;
+            return Err(self.error.clone());
+        }
+        Ok(())
+    }
+}
+
+impl ObjectStore for FlakyObjectStore {
+    fn get(&self, key: &str) -> ObjectStoreResult<Option<Vec<u8>>> {
+        self.start_request(format!("get {}", key.split('/').next().unwrap()))?;
+        self.inner.get(key)
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> ObjectStoreResult<()> {
+        self.start_request(format!("put {}", key.split('/').next().unwrap()))?;
+        self.inner.put(key, data)
+    }
+
+    fn get_many(&self, keys: &[String]) -> ObjectStoreResult<Vec<Option<Vec<u8>>>> {
+        self.start_request(format!("get_many {}", keys.len()))?;
+        self.inner.get_many(keys)
+    }
+}
+
+fn new_store_dir(temp_dir: &Path, name: &str) -> std::path::PathBuf {
+    let path = temp_dir.join(name);
+    fs::create_dir(&path).unwrap();
+    path
+}
+
+#[test]
+fn test_remote_backend_shares_objects() {
+    let temp_dir = testutils::new_temp_dir();
+    let objects_dir = new_store_dir(temp_dir.path(), "objects");
+    let url = format!("file://{}", objects_dir.display());
+    let backend1 = RemoteBackend::init(&new_store_dir(temp_dir.path(), "store1"), &url).unwrap();
+    let backend2 = RemoteBackend::init(&new_store_dir(temp_dir.path(), "store2"), &url).unwrap();
+
+    let path = RepoPath::from_internal_string("dir/file");
+    let file_id = backend1
+        .write_file(&path, &mut "contents".as_bytes())
+        .unwrap();
+    let mut subtree = Tree::default();
+    subtree.set(
+        RepoPathComponent::from("file"),
+        TreeValue::Normal {
+            id: file_id.clone(),
+            executable: false,
+        },
+    );
+    let subtree_id = backend1.write_tree(&path, &subtree).unwrap();
+    let mut tree = Tree::default();
+    tree.set(RepoPathComponent::from("dir"), TreeValue::Tree(subtree_id));
+    let tree_id = backend1.write_tree(&RepoPath::root(), &tree).unwrap();
+
+    // The other backend reads the objects from the object store
+    let read_tree = backend2.read_tree(&RepoPath::root(), &tree_id).unwrap();
+    assert_eq!(read_tree.entries().count(), 1);
+    let mut contents = String::new();
+    backend2
+        .read_file(&path, &file_id)
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert_eq!(contents, "contents");
+
+    // A repo loaded from disk uses the same object store
+    let backend3 = RemoteBackend::load(&temp_dir.path().join("store2")).unwrap();
+    assert!(backend3.read_tree(&RepoPath::root(), &tree_id).is_ok());
+}
+
+#[test]
+fn test_remote_backend_init_errors() {
+    let temp_dir = testutils::new_temp_dir();
+    // An unsupported URL is an error rather than a panic
+    assert_matches!(
+        RemoteBackend::init(&new_store_dir(temp_dir.path(), "store1"), "s3://bucket"),
+        Err(BackendError::Other(_))
+    );
+    // A missing `remote_url` file too
+    assert_matches!(
+        RemoteBackend::load(&new_store_dir(temp_dir.path(), "store2")),
+        Err(BackendError::Other(_))
+    );
+
+    // The empty tree can be read without fetching it
+    let objects_dir = new_store_dir(temp_dir.path(), "objects");
+    let object_store =
+        FlakyObjectStore::new(&objects_dir, 0, ObjectStoreError::Permanent("".into()));
+    let requests = object_store.requests.clone();
+    let backend = RemoteBackend::load_with_object_store(
+        &new_store_dir(temp_dir.path(), "store3"),
+        Box::new(object_store),
+    )
+    .unwrap();
+    let empty_tree_id = backend.empty_tree_id().clone();
+    let tree = backend
+        .read_tree(&RepoPath::root(), &empty_tree_id)
+        .unwrap();
+    assert!(tree.is_empty());
+    assert!(requests.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_remote_backend_caches_and_batches_reads() {
+    let temp_dir = testutils::new_temp_dir();
+    let objects_dir = new_store_dir(temp_dir.path(), "objects");
+    let writer = RemoteBackend::init_with_object_store(
+        &new_store_dir(temp_dir.path(), "store1"),
+        Box::new(DirectoryObjectStore::new(&objects_dir)),
+    )
+    .unwrap();
+    let mut tree = Tree::default();
+    for name in ["a", "b", "c"] {
+        let mut subtree = Tree::default();
+        subtree.set(
+            RepoPathComponent::from("file"),
+            TreeValue::Symlink(
+                writer
+                    .write_symlink(&RepoPath::root(), &format!("target-{name}"))
+                    .unwrap(),
+            ),
+        );
+        let subtree_id = writer.write_tree(&RepoPath::root(), &subtree).unwrap();
+        tree.set(RepoPathComponent::from(name), TreeValue::Tree(subtree_id));
+    }
+    let tree_id = writer.write_tree(&RepoPath::root(), &tree).unwrap();
+
+    let object_store =
+        FlakyObjectStore::new(&objects_dir, 0, ObjectStoreError::Permanent("".into()));
+    let requests = object_store.requests.clone();
+    let reader = RemoteBackend::load_with_object_store(
+        &new_store_dir(temp_dir.path(), "store2"),
+        Box::new(object_store),
+    )
+    .unwrap();
+    let read_tree = reader.read_tree(&RepoPath::root(), &tree_id).unwrap();
+    // The subtrees are fetched in one batch together with the tree
+    assert_eq!(*requests.lock().unwrap(), vec!["get trees", "get_many 3"]);
+    for entry in read_tree.entries() {
+        match entry.value() {
+            TreeValue::Tree(id) => {
+                reader.read_tree(&RepoPath::root(), id).unwrap();
+            }
+            _ => panic!("unexpected entry"),
+        }
+    }
+    reader.read_tree(&RepoPath::root(), &tree_id).unwrap();
+    assert_eq!(requests.lock().unwrap().len(), 2);
+}
+
+#[test]
+fn test_remote_backend_retries() {
+    let temp_dir = testutils::new_temp_dir();
+    let objects_dir = new_store_dir(temp_dir.path(), "objects");
+
+    // Transient errors are retried
+    let object_store = FlakyObjectStore::new(
+        &objects_dir,
+        2,
+        ObjectStoreError::Transient("connection reset".to_string()),
+    );
+    let requests = object_store.requests.clone();
+    let backend = RemoteBackend::init_with_object_store(
+        &new_store_dir(temp_dir.path(), "store1"),
+        Box::new(object_store),
+    )
+    .unwrap();
+    // Creating the backend doesn't talk to the object store
+    assert!(requests.lock().unwrap().is_empty());
+    let symlink_id = backend.write_symlink(&RepoPath::root(), "target").unwrap();
+    assert_eq!(
+        *requests.lock().unwrap(),
+        vec!["put symlinks", "put symlinks", "put symlinks"]
+    );
+
+    // Permanent errors aren't
+    let object_store = FlakyObjectStore::new(
+        &objects_dir,
+        1,
+        ObjectStoreError::Permanent("access denied".to_string()),
+    );
+    let requests = object_store.requests.clone();
+    let backend = RemoteBackend::load_with_object_store(
+        &new_store_dir(temp_dir.path(), "store2"),
+        Box::new(object_store),
+    )
+    .unwrap();
+    assert_matches!(
+        backend.read_symlink(&RepoPath::root(), &symlink_id),
+        Err(BackendError::Other(message)) if message == "access denied"
+    );
+    assert_eq!(requests.lock().unwrap().len(), 1);
+    assert_eq!(
+        backend
+            .read_symlink(&RepoPath::root(), &symlink_id)
+            .unwrap(),
+        "target"
+    );
+}
+
+#[test]
+fn test_remote_backend_rejects_corrupt_objects() {
+    let temp_dir = testutils::new_temp_dir();
+    let objects_dir = new_store_dir(temp_dir.path(), "objects");
+    let url = format!("file://{}", objects_dir.display());
+    let backend1 = RemoteBackend::init(&new_store_dir(temp_dir.path(), "store1"), &url).unwrap();
+    let id1 = backend1
+        .write_symlink(&RepoPath::root(), "target1")
+        .unwrap();
+    let id2 = backend1
+        .write_symlink(&RepoPath::root(), "target2")
+        .unwrap();
+    let symlinks_dir = objects_dir.join("symlinks");
+    fs::copy(symlinks_dir.join(id2.hex()), symlinks_dir.join(id1.hex())).unwrap();
+
+    let backend2 = RemoteBackend::init(&new_store_dir(temp_dir.path(), "store2"), &url).unwrap();
+    assert_matches!(
+        backend2.read_symlink(&RepoPath::root(), &id1),
+        Err(BackendError::HashMismatch { .. })
+    );
+    assert_matches!(
+        backend2.read_symlink(&RepoPath::root(), &id2),
+        Ok(target) if target == "target2"
+    );
+}
+
+/// Serves objects from memory over HTTP until the listener is dropped.
+fn start_http_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let mut objects: HashMap<String, Vec<u8>> = HashMap::new();
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = vec![];
+            let mut buf = [0; 4096];
+            let (head, body) = loop {
+                let bytes_read = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..bytes_read]);
+                if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                    let head = String::from_utf8(request[..end].to_vec()).unwrap();
+                    let content_length: usize = head
+                        .lines()
+                        .find_map(|line| line.strip_prefix("Content-Length: "))
+                        .unwrap()
+                        .parse()
+                        .unwrap();
+                    while request.len() < end + 4 + content_length {
+                        let bytes_read = stream.read(&mut buf).unwrap();
+                        request.extend_from_slice(&buf[..bytes_read]);
+                    }
+                    break (head, request[end + 4..].to_vec());
+                }
+            };
+            let mut words = head.split(' ');
+            let method = words.next().unwrap();
+            let path = words.next().unwrap().to_string();
+            let response = match (method, objects.get(&path)) {
+                ("GET", Some(data)) => {
+                    let mut response =
+                        format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", data.len())
+                            .into_bytes();
+                    response.extend_from_slice(data);
+                    response
+                }
+                ("GET", None) => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec(),
+                _ => {
+                    objects.insert(path, body);
+                    b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n".to_vec()
+                }
+            };
+            stream.write_all(&response).unwrap();
+        }
+    });
+    format!("http://{address}/bucket")
+}
+
+#[test]
+fn test_http_object_store() {
+    let url = start_http_server();
+    let object_store = HttpObjectStore::new(&url).unwrap();
+    assert_eq!(object_store.get("trees/abc").unwrap(), None);
+    object_store.put("trees/abc", b"tree data").unwrap();
+    assert_eq!(
+        object_store.get("trees/abc").unwrap(),
+        Some(b"tree data".to_vec())
+    );
+    assert_eq!(
+        object_store
+            .get_many(&["trees/abc".to_string(), "trees/def".to_string()])
+            .unwrap(),
+        vec![Some(b"tree data".to_vec()), None]
+    );
+
+    // The remote backend works over HTTP too
+    let temp_dir = testutils::new_temp_dir();
+    let backend1 = RemoteBackend::init(&new_store_dir(temp_dir.path(), "store1"), &url).unwrap();
+    let id = backend1.write_symlink(&RepoPath::root(), "target").unwrap();
+    let backend2 = RemoteBackend::init(&new_store_dir(temp_dir.path(), "store2"), &url).unwrap();
+    assert_eq!(
+        backend2.read_symlink(&RepoPath::root(), &id).unwrap(),
+        "target"
+    );
+}
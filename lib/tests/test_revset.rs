@@ -13,6 +13,8 @@
 // limitations under the License.
 
 use jujutsu_lib::backend::{CommitId, MillisSinceEpoch, Signature, Timestamp};
+use jujutsu_lib::changed_path_index::ChangedPathIndex;
+use jujutsu_lib::commit::Commit;
 use jujutsu_lib::commit_builder::CommitBuilder;
 use jujutsu_lib::matchers::FilesMatcher;
 use jujutsu_lib::op_store::{RefTarget, WorkspaceId};
@@ -112,13 +114,19 @@ fn test_resolve_symbol_commit_id() {
     );
     assert_eq!(
         resolve_symbol(repo_ref, "040", None),
-        Err(RevsetError::NoSuchRevision("040".to_string()))
+        Err(RevsetError::NoSuchRevision {
+            name: "040".to_string(),
+            candidates: vec![],
+        })
     );
 
     // Test non-hex string
     assert_eq!(
         resolve_symbol(repo_ref, "foo", None),
-        Err(RevsetError::NoSuchRevision("foo".to_string()))
+        Err(RevsetError::NoSuchRevision {
+            name: "foo".to_string(),
+            candidates: vec![],
+        })
     );
 }
 
@@ -225,13 +233,19 @@ fn test_resolve_symbol_change_id() {
     );
     assert_eq!(
         resolve_symbol(repo_ref, "04e13", None),
-        Err(RevsetError::NoSuchRevision("04e13".to_string()))
+        Err(RevsetError::NoSuchRevision {
+            name: "04e13".to_string(),
+            candidates: vec![],
+        })
     );
 
     // Test non-hex string
     assert_eq!(
         resolve_symbol(repo_ref, "foo", None),
-        Err(RevsetError::NoSuchRevision("foo".to_string()))
+        Err(RevsetError::NoSuchRevision {
+            name: "foo".to_string(),
+            candidates: vec![],
+        })
     );
 }
 
@@ -254,15 +268,24 @@ fn test_resolve_symbol_checkout(use_git: bool) {
     // With no workspaces, no variation can be resolved
     assert_eq!(
         resolve_symbol(mut_repo.as_repo_ref(), "@", None),
-        Err(RevsetError::NoSuchRevision("@".to_string()))
+        Err(RevsetError::NoSuchRevision {
+            name: "@".to_string(),
+            candidates: vec![],
+        })
     );
     assert_eq!(
         resolve_symbol(mut_repo.as_repo_ref(), "@", Some(&ws1)),
-        Err(RevsetError::NoSuchRevision("@".to_string()))
+        Err(RevsetError::NoSuchRevision {
+            name: "@".to_string(),
+            candidates: vec![],
+        })
     );
     assert_eq!(
         resolve_symbol(mut_repo.as_repo_ref(), "ws1@", Some(&ws1)),
-        Err(RevsetError::NoSuchRevision("ws1@".to_string()))
+        Err(RevsetError::NoSuchRevision {
+            name: "ws1@".to_string(),
+            candidates: vec![],
+        })
     );
 
     // Add some workspaces
@@ -271,7 +294,10 @@ fn test_resolve_symbol_checkout(use_git: bool) {
     // @ cannot be resolved without a default workspace ID
     assert_eq!(
         resolve_symbol(mut_repo.as_repo_ref(), "@", None),
-        Err(RevsetError::NoSuchRevision("@".to_string()))
+        Err(RevsetError::NoSuchRevision {
+            name: "@".to_string(),
+            candidates: vec![],
+        })
     );
     // Can resolve "@" shorthand with a default workspace ID
     assert_eq!(
@@ -285,6 +311,62 @@ fn test_resolve_symbol_checkout(use_git: bool) {
     );
 }
 
+#[test]
+fn test_resolve_symbol_suggestions() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+    let commit1 = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    mut_repo.set_local_branch("main".to_string(), RefTarget::Normal(commit1.id().clone()));
+    mut_repo.set_local_branch(
+        "feature".to_string(),
+        RefTarget::Normal(commit1.id().clone()),
+    );
+    mut_repo.set_remote_branch(
+        "main".to_string(),
+        "origin".to_string(),
+        RefTarget::Normal(commit1.id().clone()),
+    );
+
+    // Similarly named local and remote branches are suggested, most similar first
+    assert_eq!(
+        resolve_symbol(mut_repo.as_repo_ref(), "mian", None),
+        Err(RevsetError::NoSuchRevision {
+            name: "mian".to_string(),
+            candidates: vec!["main".to_string()],
+        })
+    );
+    assert_eq!(
+        resolve_symbol(mut_repo.as_repo_ref(), "main@orgin", None),
+        Err(RevsetError::NoSuchRevision {
+            name: "main@orgin".to_string(),
+            candidates: vec!["main@origin".to_string()],
+        })
+    );
+    assert_eq!(
+        resolve_symbol(mut_repo.as_repo_ref(), "xyz", None),
+        Err(RevsetError::NoSuchRevision {
+            name: "xyz".to_string(),
+            candidates: vec![],
+        })
+    );
+
+    // The formatted error points at the symbol and includes the suggestions
+    let err = resolve_symbol(mut_repo.as_repo_ref(), "mian", None).unwrap_err();
+    insta::assert_snapshot!(err.format_with_source("root | mian"), @r###"
+     --> 1:8
+      |
+    1 | root | mian
+      |        ^--^
+      |
+      = Revision "mian" doesn't exist
+    Hint: Did you mean "main"?
+    "###);
+}
+
 #[test]
 fn test_resolve_symbol_git_refs() {
     let settings = testutils::user_settings();
@@ -327,7 +409,10 @@ fn test_resolve_symbol_git_refs() {
     // Nonexistent ref
     assert_eq!(
         resolve_symbol(mut_repo.as_repo_ref(), "nonexistent", None),
-        Err(RevsetError::NoSuchRevision("nonexistent".to_string()))
+        Err(RevsetError::NoSuchRevision {
+            name: "nonexistent".to_string(),
+            candidates: vec![],
+        })
     );
 
     // Full ref
@@ -737,6 +822,50 @@ fn test_evaluate_expression_ancestors(use_git: bool) {
             root_commit.id().clone(),
         ]
     );
+
+    // Can limit the number of generations. Commits reachable via a shorter path
+    // are included even if there's also a longer path to them.
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), &format!("{}~0", commit4.id().hex())),
+        vec![commit4.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), &format!("{}~1", commit4.id().hex())),
+        vec![
+            commit4.id().clone(),
+            commit3.id().clone(),
+            commit1.id().clone(),
+        ]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo.as_repo_ref(),
+            &format!("ancestors({}, 2)", commit4.id().hex())
+        ),
+        vec![
+            commit4.id().clone(),
+            commit3.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone(),
+            root_commit.id().clone(),
+        ]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo.as_repo_ref(),
+            &format!(
+                "ancestors({} | {}, 1)",
+                commit4.id().hex(),
+                commit2.id().hex()
+            )
+        ),
+        vec![
+            commit4.id().clone(),
+            commit3.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone(),
+        ]
+    );
 }
 
 #[test_case(false ; "local backend")]
@@ -999,6 +1128,39 @@ fn test_evaluate_expression_descendants(use_git: bool) {
             commit2.id().clone(),
         ]
     );
+
+    // Can limit the number of generations. The shortest path to a commit counts.
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo.as_repo_ref(),
+            &format!("descendants({}, 0)", commit1.id().hex())
+        ),
+        vec![commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo.as_repo_ref(),
+            &format!("descendants({}, 1)", commit1.id().hex())
+        ),
+        vec![
+            commit4.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone(),
+        ]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo.as_repo_ref(),
+            &format!("descendants({}, 2)", commit1.id().hex())
+        ),
+        vec![
+            commit5.id().clone(),
+            commit4.id().clone(),
+            commit3.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone(),
+        ]
+    );
 }
 
 #[test_case(false ; "local backend")]
@@ -1185,6 +1347,184 @@ fn test_evaluate_expression_git_head(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_hidden(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+    let mut graph_builder = CommitGraphBuilder::new(&settings, mut_repo);
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    let commit3 = graph_builder.commit_with_parents(&[&commit2]);
+    let commit4 = graph_builder.initial_commit();
+
+    // Nothing is hidden
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "hidden()"),
+        vec![]
+    );
+
+    // Commits that are no longer ancestors of a visible head are hidden
+    mut_repo.remove_head(commit3.id());
+    mut_repo.remove_head(commit4.id());
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "hidden()"),
+        vec![
+            commit4.id().clone(),
+            commit3.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone()
+        ]
+    );
+    // Hidden commits can still be referred to explicitly
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo.as_repo_ref(),
+            &format!("hidden() & {}", commit3.id().hex())
+        ),
+        vec![commit3.id().clone()]
+    );
+
+    // Making a hidden commit visible makes its ancestors visible too
+    mut_repo.add_head(&commit3);
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "hidden()"),
+        vec![commit4.id().clone()]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_added_by_op(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    let repo = tx.commit();
+    let op1_id = repo.op_id().clone();
+
+    let mut tx = repo.start_transaction("test");
+    let mut graph_builder = CommitGraphBuilder::new(&settings, tx.mut_repo());
+    let commit3 = graph_builder.commit_with_parents(&[&commit2]);
+    let commit4 = graph_builder.initial_commit();
+    tx.mut_repo().remove_head(commit4.id());
+    let repo = tx.commit();
+    let repo_ref = repo.as_repo_ref();
+
+    // commit4 isn't visible after the operation, so it wasn't added
+    assert_eq!(
+        resolve_commit_ids(repo_ref, "added_by_op(@)"),
+        vec![commit3.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(repo_ref, r#"added_by_op("@-")"#),
+        vec![commit2.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(repo_ref, &format!("added_by_op({})", &op1_id.hex()[..12])),
+        vec![commit2.id().clone(), commit1.id().clone()]
+    );
+    // The operation that initialized the repo added the root commit
+    assert_eq!(
+        resolve_commit_ids(repo_ref, r#"added_by_op("@--")"#),
+        vec![repo.store().root_commit_id().clone()]
+    );
+    assert_eq!(
+        parse(r#"added_by_op("@---")"#)
+            .unwrap()
+            .evaluate(repo_ref, None)
+            .map(|_| ()),
+        Err(RevsetError::NoSuchOperation("@---".to_string()))
+    );
+    assert_eq!(
+        parse("added_by_op(abc)")
+            .unwrap()
+            .evaluate(repo_ref, None)
+            .map(|_| ()),
+        Err(RevsetError::NoSuchOperation("abc".to_string()))
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_divergent(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+
+    let commit1 = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    let commit2 = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+
+    // No divergence
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "divergent()"),
+        vec![]
+    );
+
+    // Rewriting a commit without hiding the old one makes it divergent
+    let commit3 = CommitBuilder::for_rewrite_from(&settings, &commit1)
+        .set_description("rewritten".to_string())
+        .write_to_repo(mut_repo);
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "divergent()"),
+        vec![commit3.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo.as_repo_ref(),
+            &format!("divergent() & {}", commit2.id().hex())
+        ),
+        vec![]
+    );
+
+    // Hidden commits don't count
+    mut_repo.remove_head(commit1.id());
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "divergent()"),
+        vec![]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_working_copies(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+
+    let commit1 = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    let commit2 = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+
+    // Can get the working copies when there are none
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "working_copies()"),
+        vec![]
+    );
+    // Can get the working copies of all workspaces, and a commit checked out in
+    // several workspaces is only included once
+    mut_repo.set_wc_commit(WorkspaceId::new("ws1".to_string()), commit1.id().clone());
+    mut_repo.set_wc_commit(WorkspaceId::new("ws2".to_string()), commit2.id().clone());
+    mut_repo.set_wc_commit(WorkspaceId::new("ws3".to_string()), commit2.id().clone());
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "working_copies()"),
+        vec![commit2.id().clone(), commit1.id().clone()]
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_evaluate_expression_branches(use_git: bool) {
@@ -1334,6 +1674,73 @@ fn test_evaluate_expression_remote_branches(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_remote_branches_filtered(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+
+    let commit1 = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    let commit2 = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    let commit3 = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    mut_repo.set_remote_branch(
+        "main".to_string(),
+        "origin".to_string(),
+        RefTarget::Normal(commit1.id().clone()),
+    );
+    mut_repo.set_remote_branch(
+        "main".to_string(),
+        "upstream".to_string(),
+        RefTarget::Normal(commit2.id().clone()),
+    );
+    mut_repo.set_remote_branch(
+        "feature/a".to_string(),
+        "origin".to_string(),
+        RefTarget::Normal(commit3.id().clone()),
+    );
+
+    // Filtered by remote
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "remote_branches(remote=origin)"),
+        vec![commit3.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "remote_branches(remote=upstream)"),
+        vec![commit2.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "remote_branches(remote=other)"),
+        vec![]
+    );
+    // Filtered by branch name, which may be a glob
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "remote_branches(main)"),
+        vec![commit2.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), r#"remote_branches("feature/*")"#),
+        vec![commit3.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo.as_repo_ref(),
+            r#"remote_branches("*", remote=origin)"#
+        ),
+        vec![commit3.id().clone(), commit1.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo.as_repo_ref(),
+            "remote_branches(main, remote=upstream)"
+        ),
+        vec![commit2.id().clone()]
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_evaluate_expression_merges(use_git: bool) {
@@ -1365,6 +1772,55 @@ fn test_evaluate_expression_merges(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_parents_count(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+    let mut graph_builder = CommitGraphBuilder::new(&settings, mut_repo);
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.initial_commit();
+    let commit3 = graph_builder.commit_with_parents(&[&commit1]);
+    let commit4 = graph_builder.commit_with_parents(&[&commit1, &commit2]);
+    let commit5 = graph_builder.commit_with_parents(&[&commit1, &commit2, &commit3]);
+
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "parents_count(0)"),
+        vec![mut_repo.store().root_commit_id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "parents_count(1)"),
+        vec![
+            commit3.id().clone(),
+            commit2.id().clone(),
+            commit1.id().clone(),
+        ]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "parents_count(3)"),
+        vec![commit5.id().clone()]
+    );
+    // Searches only among candidates if specified
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo.as_repo_ref(),
+            &format!("parents_count(2, :{})", commit4.id().hex())
+        ),
+        vec![commit4.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(
+            mut_repo.as_repo_ref(),
+            &format!("parents_count(1, roots({}:))", commit1.id().hex())
+        ),
+        vec![commit1.id().clone()]
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_evaluate_expression_description(use_git: bool) {
@@ -1471,6 +1927,55 @@ fn test_evaluate_expression_author(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_mine(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+
+    let timestamp = Timestamp {
+        timestamp: MillisSinceEpoch(0),
+        tz_offset: 0,
+    };
+    let commit1 = testutils::create_random_commit(&settings, repo)
+        .set_author(Signature {
+            name: "name1".to_string(),
+            email: "email1".to_string(),
+            timestamp: timestamp.clone(),
+        })
+        .write_to_repo(mut_repo);
+    let commit2 = testutils::create_random_commit(&settings, repo)
+        .set_parents(vec![commit1.id().clone()])
+        .set_author(Signature {
+            name: "name2".to_string(),
+            email: settings.user_email(),
+            timestamp: timestamp.clone(),
+        })
+        .write_to_repo(mut_repo);
+    // The email is compared case-insensitively
+    let commit3 = testutils::create_random_commit(&settings, repo)
+        .set_parents(vec![commit2.id().clone()])
+        .set_author(Signature {
+            name: "name3".to_string(),
+            email: settings.user_email().to_uppercase(),
+            timestamp,
+        })
+        .write_to_repo(mut_repo);
+
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "mine()"),
+        vec![commit3.id().clone(), commit2.id().clone()]
+    );
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "mine() & heads()"),
+        vec![commit3.id().clone()]
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_evaluate_expression_committer(use_git: bool) {
@@ -1647,6 +2152,68 @@ fn test_evaluate_expression_intersection(use_git: bool) {
     );
 }
 
+#[test]
+fn test_evaluate_expression_set_operations_on_large_sets() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    // Two long branches forking from a long trunk, so the sets span several
+    // words of the bitsets used for large set operations
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+    let mut graph_builder = CommitGraphBuilder::new(&settings, mut_repo);
+    let mut trunk = vec![graph_builder.initial_commit()];
+    for _ in 0..70 {
+        let commit = graph_builder.commit_with_parents(&[trunk.last().unwrap()]);
+        trunk.push(commit);
+    }
+    let mut branch1 = vec![];
+    let mut branch2 = vec![];
+    for _ in 0..70 {
+        let commit1 = graph_builder.commit_with_parents(&[branch1.last().unwrap_or(&trunk[40])]);
+        let commit2 = graph_builder.commit_with_parents(&[branch2.last().unwrap_or(&trunk[70])]);
+        branch1.push(commit1);
+        branch2.push(commit2);
+    }
+    let head1 = branch1.last().unwrap().id().hex();
+    let head2 = branch2.last().unwrap().id().hex();
+    let root_commit_id = repo.store().root_commit_id().clone();
+
+    let ids = |commits: &[Commit]| -> Vec<CommitId> {
+        commits.iter().map(|commit| commit.id().clone()).collect()
+    };
+    // The result is in the usual order: children before parents, and the
+    // commits interleaved in the order they were added to the index
+    let mut expected_union = ids(&trunk);
+    for (commit1, commit2) in branch1.iter().zip(&branch2) {
+        expected_union.push(commit1.id().clone());
+        expected_union.push(commit2.id().clone());
+    }
+    expected_union.insert(0, root_commit_id.clone());
+    expected_union.reverse();
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), &format!(":{head1} | :{head2}")),
+        expected_union
+    );
+
+    let mut expected_intersection = ids(&trunk[..=40]);
+    expected_intersection.insert(0, root_commit_id);
+    expected_intersection.reverse();
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), &format!(":{head1} & :{head2}")),
+        expected_intersection
+    );
+
+    let mut expected_difference = ids(&trunk[41..]);
+    expected_difference.extend(ids(&branch2));
+    expected_difference.reverse();
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), &format!(":{head2} ~ :{head1}")),
+        expected_difference
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_evaluate_expression_difference(use_git: bool) {
@@ -1795,4 +2362,19 @@ fn test_filter_by_diff(use_git: bool) {
             commit1.id().clone()
         ]
     );
+    // The changed-path filters were computed while filtering, and the results
+    // are the same when they're used
+    let changed_path_index = ChangedPathIndex::load(
+        repo.index_store().changed_paths_dir(),
+        repo.store().hash_length(),
+    )
+    .unwrap();
+    let filter = changed_path_index.get(commit2.id()).unwrap();
+    assert!(filter.might_contain(&added_modified_clean));
+    assert!(filter.might_contain(&added_modified_removed));
+    assert!(!filter.might_contain(&added_clean_clean));
+    assert_eq!(
+        resolve(&added_modified_clean),
+        vec![commit2.id().clone(), commit1.id().clone()]
+    );
 }
@@ -12,10 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use jujutsu_lib::backend::TreeValue;
 use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::matchers::{EverythingMatcher, PrefixMatcher};
 use jujutsu_lib::op_store::{RefTarget, WorkspaceId};
 use jujutsu_lib::repo_path::RepoPath;
-use jujutsu_lib::rewrite::DescendantRebaser;
+use jujutsu_lib::rewrite::{restore_tree, DescendantRebaser, TextReplacer};
 use jujutsu_lib::testutils;
 use jujutsu_lib::testutils::{assert_rebased, create_random_commit, CommitGraphBuilder, TestRepo};
 use maplit::{hashmap, hashset};
@@ -1394,3 +1396,82 @@ fn test_rebase_descendants_update_checkout_abandoned_merge(use_git: bool) {
     assert!(checkout.is_open());
     assert_eq!(checkout.parent_ids(), vec![commit_b.id().clone()]);
 }
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_text_replacer(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    let file1_path = RepoPath::from_internal_string("file1");
+    let file2_path = RepoPath::from_internal_string("dir/file2");
+    let file3_path = RepoPath::from_internal_string("dir/file3");
+    let tree = testutils::create_tree(
+        repo,
+        &[
+            (&file1_path, "foo(1)\nbar\n"),
+            (&file2_path, "foo(2) foo(3)\n"),
+            (&file3_path, "bar\n"),
+        ],
+    );
+    let read = |tree_id, path: &RepoPath| {
+        let tree = store.get_tree(&RepoPath::root(), tree_id).unwrap();
+        match tree.path_value(path) {
+            Some(TreeValue::Normal { id, .. }) => {
+                String::from_utf8(testutils::read_file(store, path, &id)).unwrap()
+            }
+            value => panic!("unexpected value: {value:?}"),
+        }
+    };
+
+    let mut replacer =
+        TextReplacer::new(regex::bytes::Regex::new(r"foo\((\d)\)").unwrap(), "baz[$1]");
+    let new_tree_id = replacer.replace_in_tree(&tree, &EverythingMatcher).unwrap();
+    assert_eq!(read(&new_tree_id, &file1_path), "baz[1]\nbar\n");
+    assert_eq!(read(&new_tree_id, &file2_path), "baz[2] baz[3]\n");
+    assert_eq!(read(&new_tree_id, &file3_path), "bar\n");
+
+    // Only files matching the matcher are changed
+    let matcher = PrefixMatcher::new(&[RepoPath::from_internal_string("dir")]);
+    let new_tree_id = replacer.replace_in_tree(&tree, &matcher).unwrap();
+    assert_eq!(read(&new_tree_id, &file1_path), "foo(1)\nbar\n");
+    assert_eq!(read(&new_tree_id, &file2_path), "baz[2] baz[3]\n");
+
+    // The tree is unchanged if nothing matches
+    let mut replacer = TextReplacer::new(regex::bytes::Regex::new("qux").unwrap(), "quux");
+    let new_tree_id = replacer.replace_in_tree(&tree, &EverythingMatcher).unwrap();
+    assert_eq!(&new_tree_id, tree.id());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_restore_tree(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let file1_path = RepoPath::from_internal_string("file1");
+    let file2_path = RepoPath::from_internal_string("dir/file2");
+    let file3_path = RepoPath::from_internal_string("dir/file3");
+    let source = testutils::create_tree(repo, &[(&file1_path, "a\n"), (&file2_path, "a\n")]);
+    let destination = testutils::create_tree(
+        repo,
+        &[
+            (&file1_path, "b\n"),
+            (&file2_path, "b\n"),
+            (&file3_path, "b\n"),
+        ],
+    );
+
+    // Everything is restored by default
+    assert_eq!(
+        &restore_tree(&source, &destination, &EverythingMatcher),
+        source.id()
+    );
+
+    // Only the matching paths are restored, including removals
+    let matcher = PrefixMatcher::new(&[RepoPath::from_internal_string("dir")]);
+    let new_tree_id = restore_tree(&source, &destination, &matcher);
+    let expected = testutils::create_tree(repo, &[(&file1_path, "b\n"), (&file2_path, "a\n")]);
+    assert_eq!(&new_tree_id, expected.id());
+}
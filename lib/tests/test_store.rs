@@ -0,0 +1,367 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs;
+use std::sync::Arc;
+
+use assert_matches::assert_matches;
+use itertools::Itertools;
+
+use jujutsu_lib::backend::{Backend, BackendError, ObjectKind, TreeValue};
+use jujutsu_lib::local_backend::LocalBackend;
+use jujutsu_lib::repo::{BackendFactories, RepoLoader};
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::settings::UserSettings;
+use jujutsu_lib::store::{Store, StoreOptions};
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestRepo;
+
+#[test]
+fn test_verify_reads_detects_corruption() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+    let store_path = repo.repo_path().join("store");
+    let path = RepoPath::from_internal_string("file");
+    let file_id1 = testutils::write_file(repo.store(), &path, "contents 1");
+    let file_id2 = testutils::write_file(repo.store(), &path, "contents 2");
+    let tree1 = testutils::create_tree(repo, &[(&path, "contents 1")]);
+    let tree2 = testutils::create_tree(repo, &[(&path, "contents 2")]);
+
+    // Replace the stored objects by other (well-formed) objects
+    let files_dir = store_path.join("files");
+    fs::copy(
+        files_dir.join(file_id2.hex()),
+        files_dir.join(file_id1.hex()),
+    )
+    .unwrap();
+    let trees_dir = store_path.join("trees");
+    fs::copy(
+        trees_dir.join(tree2.id().hex()),
+        trees_dir.join(tree1.id().hex()),
+    )
+    .unwrap();
+
+    // Without verification, the wrong contents are returned
+    let store = Store::new(Box::new(LocalBackend::load(&store_path)));
+    assert_eq!(
+        testutils::read_file(&store, &path, &file_id1),
+        b"contents 2"
+    );
+    assert!(store.get_tree(&RepoPath::root(), tree1.id()).is_ok());
+
+    let store = Store::with_options(
+        Box::new(LocalBackend::load(&store_path)),
+        StoreOptions {
+            verify_reads: true,
+            ..Default::default()
+        },
+    );
+    assert!(store.verifies_reads());
+    assert_eq!(
+        store.read_file(&path, &file_id1).err(),
+        Some(BackendError::HashMismatch {
+            kind: ObjectKind::File,
+            hash: file_id1.hex(),
+            actual_hash: file_id2.hex(),
+        })
+    );
+    assert_eq!(
+        testutils::read_file(&store, &path, &file_id2),
+        b"contents 2"
+    );
+    assert_eq!(
+        store.get_tree(&RepoPath::root(), tree1.id()).err(),
+        Some(BackendError::HashMismatch {
+            kind: ObjectKind::Tree,
+            hash: tree1.id().hex(),
+            actual_hash: tree2.id().hex(),
+        })
+    );
+    assert!(store.get_tree(&RepoPath::root(), tree2.id()).is_ok());
+    assert!(store.get_commit(store.root_commit_id()).is_ok());
+}
+
+#[test]
+fn test_verify_reads_setting() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+    assert!(!repo.store().verifies_reads());
+
+    let config = config::Config::builder()
+        .add_source(testutils::user_settings().config().clone())
+        .set_override("store.verify-reads", true)
+        .unwrap()
+        .build()
+        .unwrap();
+    let settings = UserSettings::from_config(config);
+    let loader = RepoLoader::init(&settings, repo.repo_path(), &BackendFactories::default());
+    assert!(loader.store().verifies_reads());
+}
+
+#[test]
+fn test_store_cache_limits() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+    let store_path = repo.repo_path().join("store");
+    let path = RepoPath::from_internal_string("file");
+    let small_file_id = testutils::write_file(repo.store(), &path, "small");
+    let large_contents = "large\n".repeat(100);
+    let large_file_id = testutils::write_file(repo.store(), &path, &large_contents);
+
+    let store = Store::with_options(
+        Box::new(LocalBackend::load(&store_path)),
+        StoreOptions {
+            file_cache_size: 1000,
+            ..Default::default()
+        },
+    );
+    for _ in 0..3 {
+        assert_eq!(
+            testutils::read_file(&store, &path, &small_file_id),
+            b"small"
+        );
+        // Larger than an eighth of the cache size, so it's not cached
+        assert_eq!(
+            testutils::read_file(&store, &path, &large_file_id),
+            large_contents.as_bytes()
+        );
+    }
+    let stats = store.cache_stats().files;
+    assert_eq!(stats.hits, 2);
+    assert_eq!(stats.misses, 4);
+    assert_eq!(stats.entries, 1);
+    assert_eq!(stats.weight, 5);
+
+    // Only the most recently used trees fit in the cache
+    let tree_ids = (0..10)
+        .map(|i| {
+            testutils::create_tree(repo, &[(&path, &format!("contents {i}"))])
+                .id()
+                .clone()
+        })
+        .collect_vec();
+    let store = Store::with_options(
+        Box::new(LocalBackend::load(&store_path)),
+        StoreOptions {
+            tree_cache_size: 1000,
+            ..Default::default()
+        },
+    );
+    for tree_id in &tree_ids {
+        store.get_tree(&RepoPath::root(), tree_id).unwrap();
+    }
+    let stats = store.cache_stats().trees;
+    assert!(stats.entries < tree_ids.len());
+    assert!(stats.weight <= 1000);
+    assert!(stats.evictions > 0);
+    store
+        .get_tree(&RepoPath::root(), tree_ids.last().unwrap())
+        .unwrap();
+    assert_eq!(store.cache_stats().trees.hits, 1);
+
+    // Only cache misses are fetched from the backend
+    let stats = store.fetch_stats();
+    assert_eq!(stats.backend, "local");
+    assert_eq!(stats.objects.keys().collect_vec(), vec![&ObjectKind::Tree]);
+    let tree_stats = &stats.objects[&ObjectKind::Tree];
+    assert_eq!(tree_stats.count, tree_ids.len() as u64);
+    assert!(tree_stats.max_time <= tree_stats.total_time);
+}
+
+#[test]
+fn test_local_backend_pack() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+    let store_path = repo.repo_path().join("store");
+    let file_path = RepoPath::from_internal_string("file");
+    let symlink_path = RepoPath::from_internal_string("symlink");
+    let tree1 = testutils::create_tree(repo, &[(&file_path, "contents 1")]);
+    let mut tree_builder = repo.store().tree_builder(tree1.id().clone());
+    testutils::write_symlink(&mut tree_builder, &symlink_path, "target");
+    let tree2 = repo
+        .store()
+        .get_tree(&RepoPath::root(), &tree_builder.write_tree())
+        .unwrap();
+    let mut tx = repo.start_transaction("test");
+    let commit = testutils::create_random_commit(&settings, repo)
+        .set_tree(tree2.id().clone())
+        .write_to_repo(tx.mut_repo());
+    tx.commit();
+
+    // A backend that has loaded the (non-existent) packs before the objects
+    // were packed
+    let old_backend = LocalBackend::load(&store_path);
+    let backend = LocalBackend::load(&store_path);
+    assert_eq!(backend.pack(1000).unwrap(), 0);
+    let num_packed = backend.pack(1).unwrap();
+    assert!(num_packed >= 5, "{num_packed}");
+    assert_eq!(backend.pack(1).unwrap(), 0);
+    for dir in ["commits", "trees", "files", "symlinks"] {
+        assert_eq!(fs::read_dir(store_path.join(dir)).unwrap().count(), 0);
+    }
+
+    let check_objects = |store: &Arc<Store>| {
+        let tree = store.get_tree(&RepoPath::root(), tree2.id()).unwrap();
+        assert_eq!(tree.entries().count(), 2);
+        let file_id = match tree.path_value(&file_path) {
+            Some(TreeValue::Normal { id, .. }) => id,
+            other => panic!("unexpected value {other:?}"),
+        };
+        assert_eq!(
+            testutils::read_file(store, &file_path, &file_id),
+            b"contents 1"
+        );
+        let symlink_id = match tree.path_value(&symlink_path) {
+            Some(TreeValue::Symlink(id)) => id,
+            other => panic!("unexpected value {other:?}"),
+        };
+        assert_eq!(
+            store.read_symlink(&symlink_path, &symlink_id).unwrap(),
+            "target"
+        );
+        assert_eq!(store.get_commit(commit.id()).unwrap(), commit);
+    };
+    check_objects(&Store::new(Box::new(old_backend)));
+    let verifying_store = Store::with_options(
+        Box::new(LocalBackend::load(&store_path)),
+        StoreOptions {
+            verify_reads: true,
+            ..Default::default()
+        },
+    );
+    check_objects(&verifying_store);
+
+    // New objects are written loose and go into a second pack
+    let tree3 = testutils::create_tree(repo, &[(&file_path, "contents 3")]);
+    assert_eq!(backend.pack(1).unwrap(), 2);
+    assert_eq!(fs::read_dir(store_path.join("packs")).unwrap().count(), 4);
+    let store = Store::new(Box::new(LocalBackend::load(&store_path)));
+    check_objects(&store);
+    let tree = store.get_tree(&RepoPath::root(), tree3.id()).unwrap();
+    assert_eq!(tree.entries().count(), 1);
+
+    // Packs are merged before there are too many of them
+    let mut tree_ids = vec![];
+    for i in 0..20 {
+        let tree = testutils::create_tree(repo, &[(&file_path, &format!("contents {i}"))]);
+        assert_eq!(backend.pack(1).unwrap(), 2);
+        tree_ids.push(tree.id().clone());
+    }
+    let num_pack_files = fs::read_dir(store_path.join("packs")).unwrap().count();
+    assert!(num_pack_files <= 2 * 16, "{num_pack_files}");
+    for store in [
+        &store,
+        &Store::new(Box::new(LocalBackend::load(&store_path))),
+    ] {
+        check_objects(store);
+        for tree_id in &tree_ids {
+            let tree = store.get_tree(&RepoPath::root(), tree_id).unwrap();
+            assert_eq!(tree.entries().count(), 1);
+        }
+    }
+}
+
+#[test]
+fn test_local_backend_pack_errors() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+    let store_path = repo.repo_path().join("store");
+    let file_path = RepoPath::from_internal_string("file");
+    let tree = testutils::create_tree(repo, &[(&file_path, "contents")]);
+
+    // Automatic packing only looks for loose objects after writing some
+    let backend = LocalBackend::load(&store_path);
+    assert_eq!(backend.pack(2).unwrap(), 0);
+    assert!(backend.pack(1).unwrap() > 0);
+
+    // A corrupt pack index is reported when reading, not when loading
+    let index_path = fs::read_dir(store_path.join("packs"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().unwrap() == "idx")
+        .unwrap();
+    fs::write(&index_path, "garbage").unwrap();
+    let backend = LocalBackend::load(&store_path);
+    assert_matches!(
+        backend.read_tree(&RepoPath::root(), tree.id()),
+        Err(BackendError::Other(message)) if message.starts_with("Invalid pack index")
+    );
+}
+
+#[test]
+fn test_local_backend_chunking() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+    let store_path = repo.repo_path().join("store");
+    let path = RepoPath::from_internal_string("file");
+    // Pseudo-random contents, so chunk boundaries are found by content
+    let mut state: u32 = 1;
+    let contents1 = (0..3 << 20)
+        .map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect_vec();
+    let mut contents2 = contents1.clone();
+    contents2[1 << 20] ^= 0xff;
+    contents2.splice(2 << 20..2 << 20, b"inserted".iter().copied());
+
+    let backend = LocalBackend::load(&store_path);
+    let id1 = backend
+        .write_file(&path, &mut contents1.as_slice())
+        .unwrap();
+    let id2 = backend
+        .write_file(&path, &mut contents2.as_slice())
+        .unwrap();
+    // Small files are stored whole
+    let small_id = backend.write_file(&path, &mut &b"small"[..]).unwrap();
+    assert_eq!(
+        backend.write_file(&path, &mut &b"small"[..]).unwrap(),
+        small_id
+    );
+    assert_ne!(id1, id2);
+
+    // The second version shares most of its chunks with the first
+    let stats = backend.chunk_stats().unwrap().unwrap();
+    assert_eq!(stats.chunked_files, 2);
+    assert_eq!(
+        stats.total_bytes,
+        (contents1.len() + contents2.len()) as u64
+    );
+    assert!(
+        stats.unique_bytes < contents1.len() as u64 + (1 << 20),
+        "{stats:?}"
+    );
+
+    // Both versions can be read back and verified, also after packing
+    let check_contents = |store: &Arc<Store>| {
+        assert_eq!(testutils::read_file(store, &path, &id1), contents1);
+        assert_eq!(testutils::read_file(store, &path, &id2), contents2);
+        assert_eq!(testutils::read_file(store, &path, &small_id), b"small");
+    };
+    let verifying_store = || {
+        Store::with_options(
+            Box::new(LocalBackend::load(&store_path)),
+            StoreOptions {
+                verify_reads: true,
+                ..Default::default()
+            },
+        )
+    };
+    check_contents(&verifying_store());
+    backend.pack(1).unwrap();
+    check_contents(&verifying_store());
+    assert_eq!(backend.chunk_stats().unwrap().unwrap(), stats);
+}
@@ -0,0 +1,194 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use assert_matches::assert_matches;
+use itertools::Itertools;
+use jujutsu_lib::backend::TreeValue;
+use jujutsu_lib::matchers::{EverythingMatcher, PrefixMatcher};
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestRepo;
+use jujutsu_lib::tree::Tree;
+use test_case::test_case;
+
+fn tree_paths(tree: &Tree) -> Vec<String> {
+    tree.entries()
+        .map(|(path, _value)| path.to_internal_file_string())
+        .collect_vec()
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_remove_matching(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    let dir_file_path = RepoPath::from_internal_string("dir/file");
+    let dir_other_path = RepoPath::from_internal_string("dir/other");
+    let file_path = RepoPath::from_internal_string("file");
+    let tree = testutils::create_tree(repo, &[(&dir_file_path, "a"), (&file_path, "a")]);
+
+    let mut tree_builder = store.tree_builder(tree.id().clone());
+    // Pending overrides are removed too
+    testutils::write_normal_file(&mut tree_builder, &dir_other_path, "a");
+    tree_builder.remove_matching(&PrefixMatcher::new(&[RepoPath::from_internal_string(
+        "dir",
+    )]));
+    let new_tree = store
+        .get_tree(&RepoPath::root(), &tree_builder.write_tree())
+        .unwrap();
+    assert_eq!(tree_paths(&new_tree), vec!["file"]);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_graft(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    let dir_file_path = RepoPath::from_internal_string("dir/file");
+    let dir_removed_path = RepoPath::from_internal_string("dir/removed");
+    let dir_added_path = RepoPath::from_internal_string("dir/added");
+    let dir_conflict_path = RepoPath::from_internal_string("dir/conflict");
+    let file_path = RepoPath::from_internal_string("file");
+    let destination = testutils::create_tree(
+        repo,
+        &[
+            (&dir_file_path, "a"),
+            (&dir_removed_path, "a"),
+            (&file_path, "a"),
+        ],
+    );
+    let base = testutils::create_tree(repo, &[(&dir_conflict_path, "base")]);
+    let side1 = testutils::create_tree(repo, &[(&dir_conflict_path, "side 1")]);
+    let side2 = testutils::create_tree(repo, &[(&dir_conflict_path, "side 2")]);
+    let conflict_tree_id = jujutsu_lib::tree::merge_trees(&side1, &base, &side2).unwrap();
+    let mut source_builder = store.tree_builder(conflict_tree_id);
+    testutils::write_normal_file(&mut source_builder, &dir_file_path, "b");
+    testutils::write_normal_file(&mut source_builder, &dir_added_path, "b");
+    testutils::write_normal_file(&mut source_builder, &file_path, "b");
+    let source = store
+        .get_tree(&RepoPath::root(), &source_builder.write_tree())
+        .unwrap();
+
+    let mut tree_builder = store.tree_builder(destination.id().clone());
+    tree_builder.graft(
+        &source,
+        &PrefixMatcher::new(&[RepoPath::from_internal_string("dir")]),
+    );
+    let new_tree = store
+        .get_tree(&RepoPath::root(), &tree_builder.write_tree())
+        .unwrap();
+    assert_eq!(
+        tree_paths(&new_tree),
+        vec!["dir/added", "dir/conflict", "dir/file", "file"]
+    );
+    // The conflict is copied as is
+    assert_eq!(
+        new_tree.path_value(&dir_conflict_path),
+        source.path_value(&dir_conflict_path)
+    );
+    assert_eq!(
+        new_tree.path_value(&dir_file_path),
+        source.path_value(&dir_file_path)
+    );
+    assert_eq!(
+        new_tree.path_value(&file_path),
+        destination.path_value(&file_path)
+    );
+
+    // Grafting everything results in the source tree
+    let mut tree_builder = store.tree_builder(destination.id().clone());
+    tree_builder.graft(&source, &EverythingMatcher);
+    assert_eq!(tree_builder.write_tree(), *source.id());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_apply_diff(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    let unchanged_path = RepoPath::from_internal_string("unchanged");
+    let same_path = RepoPath::from_internal_string("same");
+    let clean_path = RepoPath::from_internal_string("clean");
+    let conflict_path = RepoPath::from_internal_string("conflict");
+    let from = testutils::create_tree(
+        repo,
+        &[
+            (&unchanged_path, "a"),
+            (&same_path, "a"),
+            (&clean_path, "a\nb\nc\n"),
+            (&conflict_path, "a"),
+        ],
+    );
+    let to = testutils::create_tree(
+        repo,
+        &[
+            (&unchanged_path, "b"),
+            (&same_path, "b"),
+            (&clean_path, "a\nb\nC\n"),
+            (&conflict_path, "b"),
+        ],
+    );
+    let destination = testutils::create_tree(
+        repo,
+        &[
+            (&unchanged_path, "a"),
+            (&same_path, "b"),
+            (&clean_path, "A\nb\nc\n"),
+            (&conflict_path, "c"),
+        ],
+    );
+
+    let mut tree_builder = store.tree_builder(destination.id().clone());
+    tree_builder
+        .apply_diff(&from, &to, &EverythingMatcher)
+        .unwrap();
+    let new_tree = store
+        .get_tree(&RepoPath::root(), &tree_builder.write_tree())
+        .unwrap();
+    assert_eq!(
+        new_tree.path_value(&unchanged_path),
+        to.path_value(&unchanged_path)
+    );
+    assert_eq!(new_tree.path_value(&same_path), to.path_value(&same_path));
+    assert_eq!(
+        new_tree.path_value(&clean_path),
+        Some(TreeValue::Normal {
+            id: testutils::write_file(store, &clean_path, "A\nb\nC\n"),
+            executable: false
+        })
+    );
+    assert_matches!(
+        new_tree.path_value(&conflict_path),
+        Some(TreeValue::Conflict(_))
+    );
+
+    // Applying the reverse diff on top resolves the conflict again
+    let mut tree_builder = store.tree_builder(new_tree.id().clone());
+    tree_builder
+        .apply_diff(&to, &from, &EverythingMatcher)
+        .unwrap();
+    let new_tree = store
+        .get_tree(&RepoPath::root(), &tree_builder.write_tree())
+        .unwrap();
+    assert_eq!(
+        new_tree.path_value(&conflict_path),
+        destination.path_value(&conflict_path)
+    );
+}
@@ -442,6 +442,174 @@ fn test_merge_views_git_refs() {
     );
 }
 
+#[test]
+fn test_merge_views_independent_branch_moves() {
+    // Concurrent operations that move different branches should keep both moves
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+    let commit_tx0 = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    mut_repo.set_local_branch(
+        "main".to_string(),
+        RefTarget::Normal(commit_tx0.id().clone()),
+    );
+    mut_repo.set_local_branch(
+        "feature".to_string(),
+        RefTarget::Normal(commit_tx0.id().clone()),
+    );
+    let repo = tx.commit();
+
+    let mut tx1 = repo.start_transaction("test");
+    let commit_tx1 =
+        testutils::create_random_commit(&settings, &repo).write_to_repo(tx1.mut_repo());
+    tx1.mut_repo().set_local_branch(
+        "main".to_string(),
+        RefTarget::Normal(commit_tx1.id().clone()),
+    );
+    tx1.commit();
+
+    let mut tx2 = repo.start_transaction("test");
+    let commit_tx2 =
+        testutils::create_random_commit(&settings, &repo).write_to_repo(tx2.mut_repo());
+    tx2.mut_repo().set_local_branch(
+        "feature".to_string(),
+        RefTarget::Normal(commit_tx2.id().clone()),
+    );
+    tx2.mut_repo().set_local_branch(
+        "new".to_string(),
+        RefTarget::Normal(commit_tx2.id().clone()),
+    );
+    tx2.commit();
+
+    let repo = repo.reload_at_head(&settings).unwrap();
+    assert_eq!(
+        repo.view().get_local_branch("main"),
+        Some(RefTarget::Normal(commit_tx1.id().clone()))
+    );
+    assert_eq!(
+        repo.view().get_local_branch("feature"),
+        Some(RefTarget::Normal(commit_tx2.id().clone()))
+    );
+    assert_eq!(
+        repo.view().get_local_branch("new"),
+        Some(RefTarget::Normal(commit_tx2.id().clone()))
+    );
+}
+
+#[test]
+fn test_merge_views_git_head() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+    let commit_tx0 = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    mut_repo.set_git_head(commit_tx0.id().clone());
+    let repo = tx.commit();
+
+    // Only one side changed the Git HEAD
+    let mut tx1 = repo.start_transaction("test");
+    let commit_tx1 =
+        testutils::create_random_commit(&settings, &repo).write_to_repo(tx1.mut_repo());
+    tx1.mut_repo().set_git_head(commit_tx1.id().clone());
+    let mut tx2 = repo.start_transaction("test");
+    testutils::create_random_commit(&settings, &repo).write_to_repo(tx2.mut_repo());
+    let merged_repo = commit_transactions(&settings, vec![tx2, tx1]);
+    assert_eq!(merged_repo.view().git_head(), Some(commit_tx1.id().clone()));
+
+    // One side cleared it
+    let repo = merged_repo;
+    let mut tx1 = repo.start_transaction("test");
+    tx1.mut_repo().clear_git_head();
+    let mut tx2 = repo.start_transaction("test");
+    testutils::create_random_commit(&settings, &repo).write_to_repo(tx2.mut_repo());
+    let merged_repo = commit_transactions(&settings, vec![tx2, tx1]);
+    assert_eq!(merged_repo.view().git_head(), None);
+}
+
+#[test]
+fn test_merge_views_change_names() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+    let commit_a = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    let commit_b = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    let commit_c = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    mut_repo.set_change_name("moved".to_string(), commit_a.change_id().clone());
+    mut_repo.set_change_name("both-moved".to_string(), commit_a.change_id().clone());
+    mut_repo.set_change_name("deleted".to_string(), commit_a.change_id().clone());
+    let repo = tx.commit();
+
+    let mut tx1 = repo.start_transaction("test");
+    tx1.mut_repo()
+        .set_change_name("moved".to_string(), commit_b.change_id().clone());
+    tx1.mut_repo()
+        .set_change_name("both-moved".to_string(), commit_b.change_id().clone());
+    tx1.mut_repo()
+        .set_change_name("added".to_string(), commit_b.change_id().clone());
+    let mut tx2 = repo.start_transaction("test");
+    tx2.mut_repo()
+        .set_change_name("both-moved".to_string(), commit_c.change_id().clone());
+    tx2.mut_repo().remove_change_name("deleted");
+    let merged_repo = commit_transactions(&settings, vec![tx2, tx1]);
+    // If both sides moved a name, the side that was committed first wins
+    assert_eq!(
+        merged_repo.view().change_names(),
+        &btreemap! {
+            "added".to_string() => commit_b.change_id().clone(),
+            "both-moved".to_string() => commit_c.change_id().clone(),
+            "moved".to_string() => commit_b.change_id().clone(),
+        }
+    );
+}
+
+#[test]
+fn test_merge_views_change_topics() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+    let commit_a = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    let commit_b = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    let commit_c = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    let commit_d = testutils::create_random_commit(&settings, repo).write_to_repo(mut_repo);
+    mut_repo.set_change_topic(commit_a.change_id().clone(), "old".to_string());
+    mut_repo.set_change_topic(commit_b.change_id().clone(), "old".to_string());
+    mut_repo.set_change_topic(commit_c.change_id().clone(), "old".to_string());
+    let repo = tx.commit();
+
+    let mut tx1 = repo.start_transaction("test");
+    tx1.mut_repo()
+        .set_change_topic(commit_a.change_id().clone(), "new1".to_string());
+    tx1.mut_repo()
+        .set_change_topic(commit_b.change_id().clone(), "new1".to_string());
+    tx1.mut_repo()
+        .set_change_topic(commit_d.change_id().clone(), "new1".to_string());
+    let mut tx2 = repo.start_transaction("test");
+    tx2.mut_repo()
+        .set_change_topic(commit_b.change_id().clone(), "new2".to_string());
+    tx2.mut_repo().remove_change_topic(commit_c.change_id());
+    let merged_repo = commit_transactions(&settings, vec![tx2, tx1]);
+    // If both sides changed a topic, the side that was committed first wins
+    assert_eq!(
+        merged_repo.view().change_topics(),
+        &btreemap! {
+            commit_a.change_id().clone() => "new1".to_string(),
+            commit_b.change_id().clone() => "new2".to_string(),
+            commit_d.change_id().clone() => "new1".to_string(),
+        }
+    );
+}
+
 fn commit_transactions(settings: &UserSettings, txs: Vec<Transaction>) -> Arc<ReadonlyRepo> {
     let repo_loader = txs[0].base_repo().loader();
     let mut op_ids = vec![];
@@ -18,21 +18,28 @@ use std::io::Write;
 use std::os::unix::fs::PermissionsExt;
 #[cfg(unix)]
 use std::os::unix::net::UnixListener;
+use std::path::Path;
 use std::sync::Arc;
 
+use assert_matches::assert_matches;
 use itertools::Itertools;
-use jujutsu_lib::backend::{Conflict, ConflictPart, TreeValue};
+use jujutsu_lib::backend::{Conflict, ConflictPart, TreeId, TreeValue};
 use jujutsu_lib::gitignore::GitIgnoreFile;
-#[cfg(unix)]
-use jujutsu_lib::op_store::OperationId;
-use jujutsu_lib::op_store::WorkspaceId;
+use jujutsu_lib::matchers::EverythingMatcher;
+use jujutsu_lib::op_store::{OperationId, WorkspaceId};
 use jujutsu_lib::repo::ReadonlyRepo;
 use jujutsu_lib::repo_path::{RepoPath, RepoPathComponent, RepoPathJoin};
 use jujutsu_lib::settings::UserSettings;
 use jujutsu_lib::testutils;
 use jujutsu_lib::testutils::TestWorkspace;
+use jujutsu_lib::tree::Tree;
 use jujutsu_lib::tree_builder::TreeBuilder;
-use jujutsu_lib::working_copy::WorkingCopy;
+use jujutsu_lib::windows_paths::WindowsPathIssue;
+use jujutsu_lib::working_copy::{
+    CheckoutError, FileType, FinishRecovery, SnapshotError, SnapshotMode, UnicodeNormalization,
+    WorkingCopy,
+};
+use protobuf::Message;
 use test_case::test_case;
 
 #[test_case(false ; "local backend")]
@@ -311,7 +318,7 @@ fn test_reset() {
     // commit the working copy (because it's ignored).
     let mut locked_wc = wc.start_mutation();
     locked_wc.reset(&tree_without_file).unwrap();
-    locked_wc.finish(repo.op_id().clone());
+    locked_wc.finish(repo.op_id().clone()).unwrap();
     assert!(ignored_path.to_fs_path(&workspace_root).is_file());
     assert!(!wc.file_states().contains_key(&ignored_path));
     let mut locked_wc = wc.start_mutation();
@@ -324,7 +331,7 @@ fn test_reset() {
     // commit the working copy (because it's ignored).
     let mut locked_wc = wc.start_mutation();
     locked_wc.reset(&tree_without_file).unwrap();
-    locked_wc.finish(repo.op_id().clone());
+    locked_wc.finish(repo.op_id().clone()).unwrap();
     assert!(ignored_path.to_fs_path(&workspace_root).is_file());
     assert!(!wc.file_states().contains_key(&ignored_path));
     let mut locked_wc = wc.start_mutation();
@@ -336,7 +343,7 @@ fn test_reset() {
     // tracked. The file should become tracked (even though it's ignored).
     let mut locked_wc = wc.start_mutation();
     locked_wc.reset(&tree_with_file).unwrap();
-    locked_wc.finish(repo.op_id().clone());
+    locked_wc.finish(repo.op_id().clone()).unwrap();
     assert!(ignored_path.to_fs_path(&workspace_root).is_file());
     assert!(wc.file_states().contains_key(&ignored_path));
     let mut locked_wc = wc.start_mutation();
@@ -345,6 +352,140 @@ fn test_reset() {
     locked_wc.discard();
 }
 
+#[test]
+fn test_checkout_with_content_cache() {
+    // Tests that files are checked out correctly when the content cache is
+    // enabled, whether or not the filesystem supports reflinks.
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = test_workspace.repo.clone();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let file1_path = RepoPath::from_internal_string("file1");
+    let file2_path = RepoPath::from_internal_string("dir/file2");
+    let tree1 = testutils::create_tree(&repo, &[(&file1_path, "contents 1")]);
+    let tree2 = testutils::create_tree(
+        &repo,
+        &[(&file1_path, "contents 2"), (&file2_path, "contents 1")],
+    );
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_use_content_cache(true, 1 << 30);
+    wc.check_out(repo.op_id().clone(), None, &tree1).unwrap();
+    wc.check_out(repo.op_id().clone(), None, &tree2).unwrap();
+    assert_eq!(
+        std::fs::read(file1_path.to_fs_path(&workspace_root)).unwrap(),
+        b"contents 2"
+    );
+    assert_eq!(
+        std::fs::read(file2_path.to_fs_path(&workspace_root)).unwrap(),
+        b"contents 1"
+    );
+
+    // The file states match the files on disk
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    assert_eq!(new_tree_id, *tree2.id());
+}
+
+#[test]
+fn test_checkout_prunes_content_cache() {
+    // Tests that the content cache is pruned to its maximum size after a
+    // checkout, whether or not the filesystem supports reflinks.
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = test_workspace.repo.clone();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+    let cache_dir = workspace_root
+        .join(".jj")
+        .join("working_copy")
+        .join("content_cache");
+
+    let file_path = RepoPath::from_internal_string("file");
+    let tree1 = testutils::create_tree(&repo, &[(&file_path, "contents 1")]);
+    let tree2 = testutils::create_tree(&repo, &[(&file_path, "contents 2")]);
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_use_content_cache(true, 0);
+    wc.check_out(repo.op_id().clone(), None, &tree1).unwrap();
+    assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 0);
+    wc.check_out(repo.op_id().clone(), None, &tree2).unwrap();
+    assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 0);
+    assert_eq!(
+        std::fs::read(file_path.to_fs_path(&workspace_root)).unwrap(),
+        b"contents 2"
+    );
+}
+
+#[test]
+fn test_checkout_lazy_placeholders() {
+    // Tests that files larger than the lazy threshold are checked out as
+    // placeholders, which are not considered changes, and can be hydrated.
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = test_workspace.repo.clone();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let small_path = RepoPath::from_internal_string("small");
+    let large_path = RepoPath::from_internal_string("large");
+    let tree = testutils::create_tree(
+        &repo,
+        &[(&small_path, "small"), (&large_path, "large contents")],
+    );
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_lazy_threshold(Some(10));
+    wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+    assert_eq!(
+        std::fs::read(small_path.to_fs_path(&workspace_root)).unwrap(),
+        b"small"
+    );
+    assert_ne!(
+        std::fs::read(large_path.to_fs_path(&workspace_root)).unwrap(),
+        b"large contents"
+    );
+
+    // The placeholder is not a change, even if it's touched
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    assert_eq!(new_tree_id, *tree.id());
+
+    let mut locked_wc = wc.start_mutation();
+    let stats = locked_wc.hydrate(&EverythingMatcher).unwrap();
+    assert_eq!(stats.updated_files, 1);
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(repo.op_id().clone()).unwrap();
+    assert_eq!(new_tree_id, *tree.id());
+    assert_eq!(
+        std::fs::read(large_path.to_fs_path(&workspace_root)).unwrap(),
+        b"large contents"
+    );
+
+    // Writing to a placeholder replaces the file's contents
+    wc.check_out(
+        repo.op_id().clone(),
+        None,
+        &testutils::create_tree(&repo, &[]),
+    )
+    .unwrap();
+    wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+    testutils::write_working_copy_file(&workspace_root, &large_path, "new contents");
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    let expected_tree = testutils::create_tree(
+        &repo,
+        &[(&small_path, "small"), (&large_path, "new contents")],
+    );
+    assert_eq!(new_tree.id(), expected_tree.id());
+}
+
 #[test]
 fn test_checkout_discard() {
     // Start a mutation, do a checkout, and then discard the mutation. The working
@@ -391,6 +532,348 @@ fn test_checkout_discard() {
     assert!(!reloaded_wc.file_states().contains_key(&file2_path));
 }
 
+#[test]
+fn test_checkout_refuses_to_overwrite_dirty_files() {
+    // Files modified since the last snapshot are not overwritten or removed by a
+    // checkout unless the changes are explicitly discarded.
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = test_workspace.repo.clone();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let modified_path = RepoPath::from_internal_string("modified");
+    let removed_path = RepoPath::from_internal_string("removed");
+    let unchanged_path = RepoPath::from_internal_string("unchanged");
+    let tree1 = testutils::create_tree(
+        &repo,
+        &[
+            (&modified_path, "1"),
+            (&removed_path, "1"),
+            (&unchanged_path, "1"),
+        ],
+    );
+    let tree2 = testutils::create_tree(&repo, &[(&modified_path, "2"), (&unchanged_path, "2")]);
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.check_out(repo.op_id().clone(), None, &tree1).unwrap();
+    assert_eq!(wc.dirty_files(&tree2).unwrap(), vec![]);
+
+    testutils::write_working_copy_file(&workspace_root, &modified_path, "dirty");
+    testutils::write_working_copy_file(&workspace_root, &removed_path, "dirty");
+    assert_eq!(
+        wc.dirty_files(&tree2).unwrap(),
+        vec![modified_path.clone(), removed_path.clone()]
+    );
+    let result = wc.check_out(repo.op_id().clone(), None, &tree2);
+    match result {
+        Err(CheckoutError::DirtyFiles { paths }) => {
+            assert_eq!(paths, vec![modified_path.clone(), removed_path.clone()]);
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+    // Nothing was touched
+    assert_eq!(wc.current_tree_id(), tree1.id());
+    let read_file = |path: &RepoPath| std::fs::read(path.to_fs_path(&workspace_root)).unwrap();
+    assert_eq!(read_file(&modified_path), b"dirty");
+    assert_eq!(read_file(&removed_path), b"dirty");
+    assert_eq!(read_file(&unchanged_path), b"1");
+
+    wc.set_discard_changes(true);
+    wc.check_out(repo.op_id().clone(), None, &tree2).unwrap();
+    assert_eq!(read_file(&modified_path), b"2");
+    assert!(!removed_path.to_fs_path(&workspace_root).exists());
+    assert_eq!(read_file(&unchanged_path), b"2");
+}
+
+fn write_finish_journal(state_path: &Path, operation_id: &OperationId, tree_id: &TreeId) {
+    let mut proto = jujutsu_lib::protos::working_copy::FinishJournal::new();
+    proto.operation_id = operation_id.to_bytes();
+    proto.tree_id = tree_id.to_bytes();
+    std::fs::write(
+        state_path.join("finish_journal"),
+        proto.write_to_bytes().unwrap(),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_interrupted_finish_rolls_forward() {
+    // Simulate a process that got killed after writing the new tree state but
+    // before writing the new operation ID.
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = test_workspace.repo.clone();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let file_path = RepoPath::from_internal_string("file");
+    let tree1 = testutils::create_tree(&repo, &[(&file_path, "contents 1")]);
+    let tree2 = testutils::create_tree(&repo, &[(&file_path, "contents 2")]);
+    let new_op_id = OperationId::from_hex("abcdef");
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    let state_path = wc.state_path().to_path_buf();
+    wc.check_out(repo.op_id().clone(), None, &tree1).unwrap();
+    let old_checkout = std::fs::read(state_path.join("checkout")).unwrap();
+    wc.check_out(new_op_id.clone(), None, &tree2).unwrap();
+    std::fs::write(state_path.join("checkout"), old_checkout).unwrap();
+    write_finish_journal(&state_path, &new_op_id, tree2.id());
+
+    let mut wc = WorkingCopy::load(repo.store().clone(), workspace_root, state_path.clone());
+    let locked_wc = wc.start_mutation();
+    assert_eq!(locked_wc.old_operation_id(), &new_op_id);
+    assert_eq!(locked_wc.old_tree_id(), tree2.id());
+    locked_wc.discard();
+    assert!(!state_path.join("finish_journal").exists());
+}
+
+#[test]
+fn test_interrupted_finish_rolls_back() {
+    // Simulate a process that got killed after writing the journal but before
+    // writing the new tree state.
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = test_workspace.repo.clone();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let file_path = RepoPath::from_internal_string("file");
+    let tree1 = testutils::create_tree(&repo, &[(&file_path, "contents 1")]);
+    let tree2 = testutils::create_tree(&repo, &[(&file_path, "contents 2")]);
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    let state_path = wc.state_path().to_path_buf();
+    wc.check_out(repo.op_id().clone(), None, &tree1).unwrap();
+    write_finish_journal(&state_path, &OperationId::from_hex("abcdef"), tree2.id());
+
+    let mut wc = WorkingCopy::load(repo.store().clone(), workspace_root, state_path.clone());
+    let repair = wc.repair();
+    assert_eq!(repair.finish_recovery, Some(FinishRecovery::RolledBack));
+    assert!(!repair.tree_state_discarded);
+    assert_eq!(&wc.operation_id(), repo.op_id());
+    assert_eq!(wc.current_tree_id(), tree1.id());
+    assert!(!state_path.join("finish_journal").exists());
+}
+
+#[test]
+fn test_repair_discards_unreadable_tree_state() {
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = test_workspace.repo.clone();
+
+    let file_path = RepoPath::from_internal_string("file");
+    let tree = testutils::create_tree(&repo, &[(&file_path, "contents")]);
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    let state_path = wc.state_path().to_path_buf();
+    wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+    std::fs::write(state_path.join("tree_state"), b"\xff\xff\xff").unwrap();
+
+    let repair = wc.repair();
+    assert_eq!(repair.finish_recovery, None);
+    assert!(repair.tree_state_discarded);
+    assert!(state_path.join("tree_state.corrupt").is_file());
+    assert_eq!(wc.current_tree_id(), repo.store().empty_tree_id());
+
+    // The caller is expected to reset to the tree it wants
+    let mut locked_wc = wc.start_mutation();
+    locked_wc.reset(&tree).unwrap();
+    locked_wc.finish(repo.op_id().clone()).unwrap();
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    assert_eq!(new_tree_id, *tree.id());
+    locked_wc.discard();
+}
+
+#[test]
+fn test_tree_state_segments() {
+    // Tests that the file states survive a reload both when they're saved as
+    // changes on top of a segment and after being compacted into a new one.
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = test_workspace.repo.clone();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+    let wc = test_workspace.workspace.working_copy_mut();
+    let state_path = wc.state_path().to_path_buf();
+    let segments = || -> Vec<_> {
+        std::fs::read_dir(state_path.join("file_states"))
+            .map(|entries| entries.map(|entry| entry.unwrap().file_name()).collect())
+            .unwrap_or_default()
+    };
+    let snapshot = |wc: &mut WorkingCopy| {
+        let mut locked_wc = wc.start_mutation();
+        let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+        locked_wc.finish(repo.op_id().clone()).unwrap();
+        tree_id
+    };
+
+    // A few files are kept inline
+    std::fs::write(workspace_root.join("a"), "a").unwrap();
+    snapshot(wc);
+    assert!(segments().is_empty());
+
+    // Many new files are written to a segment
+    for i in 0..1500 {
+        std::fs::write(workspace_root.join(format!("file{i}")), "contents").unwrap();
+    }
+    snapshot(wc);
+    let first_segments = segments();
+    assert_eq!(first_segments.len(), 1);
+
+    // Small changes don't rewrite the segment
+    std::fs::write(workspace_root.join("b"), "b").unwrap();
+    std::fs::remove_file(workspace_root.join("file7")).unwrap();
+    let tree_id = snapshot(wc);
+    assert_eq!(segments(), first_segments);
+    let expected_file_states = wc.file_states().clone();
+    assert_eq!(expected_file_states.len(), 1501);
+    let reloaded_wc = WorkingCopy::load(
+        repo.store().clone(),
+        workspace_root.clone(),
+        state_path.clone(),
+    );
+    assert_eq!(reloaded_wc.current_tree_id(), &tree_id);
+    assert_eq!(reloaded_wc.file_states(), &expected_file_states);
+
+    // Removing most files compacts into a new segment and keeps the previous one
+    for i in 100..1500 {
+        std::fs::remove_file(workspace_root.join(format!("file{i}"))).ok();
+    }
+    let tree_id = snapshot(wc);
+    let second_segments = segments();
+    assert_eq!(second_segments.len(), 2);
+    assert!(second_segments.contains(&first_segments[0]));
+    let reloaded_wc = WorkingCopy::load(
+        repo.store().clone(),
+        workspace_root.clone(),
+        state_path.clone(),
+    );
+    assert_eq!(reloaded_wc.current_tree_id(), &tree_id);
+    assert_eq!(reloaded_wc.file_states(), wc.file_states());
+    assert_eq!(wc.file_states().len(), 101);
+
+    // The next compaction removes the segment no reader can pick anymore
+    for i in 100..1500 {
+        std::fs::write(workspace_root.join(format!("file{i}")), "contents").unwrap();
+    }
+    let tree_id = snapshot(wc);
+    let third_segments = segments();
+    assert_eq!(third_segments.len(), 2);
+    assert!(!third_segments.contains(&first_segments[0]));
+    let reloaded_wc = WorkingCopy::load(repo.store().clone(), workspace_root, state_path);
+    assert_eq!(reloaded_wc.current_tree_id(), &tree_id);
+    assert_eq!(reloaded_wc.file_states(), wc.file_states());
+}
+
+/// Starts checking out `tree2` over `tree1`, which gets interrupted when it
+/// finds an untracked file "b" in the way after having written file "a".
+fn interrupt_checkout(test_workspace: &mut TestWorkspace) -> (Tree, Tree) {
+    let repo = test_workspace.repo.clone();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+    let file_a_path = RepoPath::from_internal_string("a");
+    let file_b_path = RepoPath::from_internal_string("b");
+    let file_c_path = RepoPath::from_internal_string("c");
+    let tree1 = testutils::create_tree(&repo, &[(&file_c_path, "c 1")]);
+    let tree2 = testutils::create_tree(
+        &repo,
+        &[
+            (&file_a_path, "a 2"),
+            (&file_b_path, "b 2"),
+            (&file_c_path, "c 2"),
+        ],
+    );
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.check_out(repo.op_id().clone(), None, &tree1).unwrap();
+    testutils::write_working_copy_file(&workspace_root, &file_b_path, "untracked");
+    let mut locked_wc = wc.start_mutation();
+    assert!(locked_wc.check_out(&tree2).is_err());
+    locked_wc.discard();
+    (tree1, tree2)
+}
+
+#[test]
+fn test_interrupted_checkout_resumes() {
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = test_workspace.repo.clone();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+    let (tree1, tree2) = interrupt_checkout(&mut test_workspace);
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    let mut wc = WorkingCopy::load(
+        repo.store().clone(),
+        workspace_root.clone(),
+        wc.state_path().to_path_buf(),
+    );
+    let pending_checkout = wc.pending_checkout().unwrap();
+    assert_eq!(&pending_checkout.old_tree_id, tree1.id());
+    assert_eq!(&pending_checkout.new_tree_id, tree2.id());
+    assert_eq!(pending_checkout.completed_entries, 1);
+    assert_eq!(wc.current_tree_id(), tree1.id());
+
+    // Pretend the interrupted process had also written "b" before it was killed
+    testutils::write_working_copy_file(
+        &workspace_root,
+        &RepoPath::from_internal_string("b"),
+        "b 2",
+    );
+    let stats = wc.check_out(repo.op_id().clone(), None, &tree2).unwrap();
+    assert_eq!(stats.added_files, 1);
+    assert_eq!(stats.updated_files, 1);
+    assert_eq!(wc.pending_checkout(), None);
+    assert_eq!(wc.current_tree_id(), tree2.id());
+    assert_eq!(std::fs::read(workspace_root.join("a")).unwrap(), b"a 2");
+    assert_eq!(std::fs::read(workspace_root.join("b")).unwrap(), b"b 2");
+    assert_eq!(std::fs::read(workspace_root.join("c")).unwrap(), b"c 2");
+
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    assert_eq!(new_tree_id, *tree2.id());
+}
+
+#[test]
+fn test_interrupted_checkout_rolls_back() {
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = test_workspace.repo.clone();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+    let (tree1, _tree2) = interrupt_checkout(&mut test_workspace);
+
+    // Snapshotting rolls back the checkout first. The untracked file that was in
+    // the way is left alone.
+    let wc = test_workspace.workspace.working_copy_mut();
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(repo.op_id().clone()).unwrap();
+    assert_eq!(wc.pending_checkout(), None);
+    assert!(!workspace_root.join("a").exists());
+    assert_eq!(
+        std::fs::read(workspace_root.join("b")).unwrap(),
+        b"untracked"
+    );
+    assert_eq!(std::fs::read(workspace_root.join("c")).unwrap(), b"c 1");
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    let files = new_tree
+        .entries()
+        .map(|(name, _value)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        files,
+        vec![
+            RepoPath::from_internal_string("b"),
+            RepoPath::from_internal_string("c")
+        ]
+    );
+    assert_eq!(
+        new_tree.path_value(&RepoPath::from_internal_string("c")),
+        tree1.path_value(&RepoPath::from_internal_string("c"))
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_snapshot_racy_timestamps(use_git: bool) {
@@ -423,7 +906,183 @@ fn test_snapshot_racy_timestamps(use_git: bool) {
     }
 }
 
+#[test_case(SnapshotMode::Default, false ; "default")]
+#[test_case(SnapshotMode::Paranoid { racy_window_millis: 60_000 }, true ; "paranoid")]
+#[test_case(SnapshotMode::Fast, false ; "fast")]
+fn test_snapshot_mode(snapshot_mode: SnapshotMode, expect_detected: bool) {
+    // Tests which snapshot modes detect a same-size write that leaves the mtime
+    // unchanged, and that happened shortly before the previous snapshot. The
+    // write would also change the ctime, so we don't trust that here (see
+    // `test_snapshot_ctime()`).
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let file_path = workspace_root.join("file");
+    let mtime =
+        filetime::FileTime::from_unix_time(filetime::FileTime::now().unix_seconds() - 10, 0);
+    std::fs::write(&file_path, "contents 1").unwrap();
+    filetime::set_file_mtime(&file_path, mtime).unwrap();
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_snapshot_mode(snapshot_mode);
+    wc.set_trust_ctime(false);
+    let mut locked_wc = wc.start_mutation();
+    let tree_id1 = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(repo.op_id().clone()).unwrap();
+
+    std::fs::write(&file_path, "contents 2").unwrap();
+    filetime::set_file_mtime(&file_path, mtime).unwrap();
+    let mut locked_wc = wc.start_mutation();
+    let tree_id2 = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    assert_eq!(tree_id2 != tree_id1, expect_detected);
+}
+
 #[cfg(unix)]
+#[test_case(true ; "trust ctime")]
+#[test_case(false ; "don't trust ctime")]
+fn test_snapshot_ctime(trust_ctime: bool) {
+    // Tests that a same-size write that leaves the mtime unchanged (e.g. followed
+    // by `touch -d`) is detected through the changed ctime.
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let file_path = workspace_root.join("file");
+    let mtime =
+        filetime::FileTime::from_unix_time(filetime::FileTime::now().unix_seconds() - 10, 0);
+    std::fs::write(&file_path, "contents 1").unwrap();
+    filetime::set_file_mtime(&file_path, mtime).unwrap();
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_trust_ctime(trust_ctime);
+    let mut locked_wc = wc.start_mutation();
+    let tree_id1 = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(repo.op_id().clone()).unwrap();
+    let file_state = wc.file_states()[&RepoPath::from_internal_string("file")].clone();
+    assert_ne!(file_state.inode, 0);
+    assert_ne!(file_state.ctime.0, 0);
+
+    // Wait for the ctime to be able to change
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    std::fs::write(&file_path, "contents 2").unwrap();
+    filetime::set_file_mtime(&file_path, mtime).unwrap();
+    let mut locked_wc = wc.start_mutation();
+    let tree_id2 = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    assert_eq!(tree_id2 != tree_id1, trust_ctime);
+}
+
+#[test_case(false ; "without dir cache")]
+#[test_case(true ; "with dir cache")]
+fn test_snapshot_dir_cache(use_dir_cache: bool) {
+    // Tests that with the dir cache, snapshots don't read directories whose
+    // mtime hasn't changed, but still notice changes to the files in them.
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let dir_path = workspace_root.join("dir");
+    let mtime =
+        filetime::FileTime::from_unix_time(filetime::FileTime::now().unix_seconds() - 10, 0);
+    std::fs::create_dir(&dir_path).unwrap();
+    std::fs::write(dir_path.join("tracked"), "contents 1").unwrap();
+    filetime::set_file_mtime(&dir_path, mtime).unwrap();
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_use_dir_cache(use_dir_cache);
+    let mut locked_wc = wc.start_mutation();
+    locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(repo.op_id().clone()).unwrap();
+
+    // Add a file without changing the directory's mtime, and modify the tracked
+    // file
+    std::fs::write(dir_path.join("added"), "contents").unwrap();
+    std::fs::write(dir_path.join("tracked"), "contents 2").unwrap();
+    filetime::set_file_mtime(&dir_path, mtime).unwrap();
+    let mut locked_wc = wc.start_mutation();
+    let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(repo.op_id().clone()).unwrap();
+    let tree = repo.store().get_tree(&RepoPath::root(), &tree_id).unwrap();
+    let added_path = RepoPath::from_internal_string("dir/added");
+    let tracked_path = RepoPath::from_internal_string("dir/tracked");
+    assert_eq!(tree.path_value(&added_path).is_some(), !use_dir_cache);
+    assert_eq!(
+        tree.path_value(&tracked_path),
+        Some(TreeValue::Normal {
+            id: testutils::write_file(repo.store(), &tracked_path, "contents 2"),
+            executable: false
+        })
+    );
+
+    // Once the directory's mtime changes, the added file is found
+    filetime::set_file_mtime(&dir_path, filetime::FileTime::now()).unwrap();
+    let mut locked_wc = wc.start_mutation();
+    let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let tree = repo.store().get_tree(&RepoPath::root(), &tree_id).unwrap();
+    assert!(tree.path_value(&added_path).is_some());
+}
+
+#[cfg(unix)]
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_snapshot_many_files(use_git: bool) {
+    // Tests that snapshotting, which writes files and trees in parallel,
+    // produces the same tree as writing them one by one.
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let paths = (0..500)
+        .map(|i| RepoPath::from_internal_string(&format!("dir{}/sub{}/file{i}", i % 7, i % 3)))
+        .collect_vec();
+    let contents = paths
+        .iter()
+        .map(|path| path.to_internal_file_string())
+        .collect_vec();
+    let path_contents = paths
+        .iter()
+        .zip(&contents)
+        .map(|(path, contents)| (path, contents.as_str()))
+        .collect_vec();
+    for (path, contents) in &path_contents {
+        let disk_path = path.to_fs_path(&workspace_root);
+        std::fs::create_dir_all(disk_path.parent().unwrap()).unwrap();
+        testutils::write_working_copy_file(&workspace_root, path, contents);
+    }
+    let expected_tree = testutils::create_tree(repo, &path_contents);
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    let mut locked_wc = wc.start_mutation();
+    let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(OperationId::from_hex("abc123")).unwrap();
+    assert_eq!(&tree_id, expected_tree.id());
+    assert_eq!(wc.file_states().len(), paths.len());
+
+    // Modify some of the files and remove a directory
+    let mut path_contents = path_contents
+        .into_iter()
+        .filter(|(path, _)| !path.to_internal_file_string().starts_with("dir6/"))
+        .collect_vec();
+    std::fs::remove_dir_all(workspace_root.join("dir6")).unwrap();
+    for (path, contents) in path_contents.iter_mut().step_by(10) {
+        *contents = "modified";
+        testutils::write_working_copy_file(&workspace_root, path, contents);
+    }
+    let expected_tree = testutils::create_tree(repo, &path_contents);
+    let mut locked_wc = wc.start_mutation();
+    let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    assert_eq!(&tree_id, expected_tree.id());
+}
+
 #[test]
 fn test_snapshot_special_file() {
     // Tests that we ignore when special files (such as sockets and pipes) exist on
@@ -450,7 +1109,7 @@ fn test_snapshot_special_file() {
     let wc = test_workspace.workspace.working_copy_mut();
     let mut locked_wc = wc.start_mutation();
     let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
-    locked_wc.finish(OperationId::from_hex("abc123"));
+    locked_wc.finish(OperationId::from_hex("abc123")).unwrap();
     let tree = store.get_tree(&RepoPath::root(), &tree_id).unwrap();
     // Only the regular files should be in the tree
     assert_eq!(
@@ -467,7 +1126,7 @@ fn test_snapshot_special_file() {
     UnixListener::bind(&file1_disk_path).unwrap();
     let mut locked_wc = wc.start_mutation();
     let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
-    locked_wc.finish(OperationId::from_hex("abc123"));
+    locked_wc.finish(OperationId::from_hex("abc123")).unwrap();
     let tree = store.get_tree(&RepoPath::root(), &tree_id).unwrap();
     // Only the regular file should be in the tree
     assert_eq!(
@@ -508,7 +1167,7 @@ fn test_gitignores(use_git: bool) {
     let wc = test_workspace.workspace.working_copy_mut();
     let mut locked_wc = wc.start_mutation();
     let new_tree_id1 = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
-    locked_wc.finish(repo.op_id().clone());
+    locked_wc.finish(repo.op_id().clone()).unwrap();
     let tree1 = repo
         .store()
         .get_tree(&RepoPath::root(), &new_tree_id1)
@@ -593,6 +1252,50 @@ fn test_gitignores_checkout_never_overwrites_ignored(use_git: bool) {
     assert_eq!(std::fs::read(&path).unwrap(), b"garbage");
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_gitignores_checkout_backs_up_ignored(use_git: bool) {
+    // Tests that a .gitignore'd file that's in the way of a file being checked out
+    // is moved to the backup directory if there is one.
+
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let gitignore_path = RepoPath::from_internal_string(".gitignore");
+    testutils::write_working_copy_file(&workspace_root, &gitignore_path, "ignored\n");
+    let ignored_path = RepoPath::from_internal_string("ignored");
+    testutils::write_working_copy_file(&workspace_root, &ignored_path, "garbage");
+
+    let mut tree_builder = repo
+        .store()
+        .tree_builder(repo.store().empty_tree_id().clone());
+    testutils::write_normal_file(&mut tree_builder, &ignored_path, "contents");
+    let tree_id = tree_builder.write_tree();
+    let tree = repo.store().get_tree(&RepoPath::root(), &tree_id).unwrap();
+
+    let backup_dir = workspace_root.join(".jj").join("backup");
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_backup_dir(Some(backup_dir.clone()));
+    let stats = wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+    assert_eq!(stats.added_files, 1);
+
+    // The file was checked out, and the old contents were moved to a new
+    // directory in the backup directory
+    let path = ignored_path.to_fs_path(&workspace_root);
+    assert_eq!(std::fs::read(&path).unwrap(), b"contents");
+    assert_eq!(stats.backed_up_files.len(), 1);
+    let backup_path = &stats.backed_up_files[0];
+    assert!(backup_path.ends_with("ignored"), "{backup_path:?}");
+    assert_eq!(
+        backup_path.parent().unwrap().parent(),
+        Some(backup_dir.as_path())
+    );
+    assert_eq!(std::fs::read(backup_path).unwrap(), b"garbage");
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_gitignores_ignored_directory_already_tracked(use_git: bool) {
@@ -637,6 +1340,101 @@ fn test_gitignores_ignored_directory_already_tracked(use_git: bool) {
     assert!(new_tree.path_value(&file_path).is_some());
 }
 
+#[test]
+fn test_never_track() {
+    // Tests that paths set with `set_never_track()` are neither added nor
+    // updated nor removed by snapshots, even if they're not ignored.
+
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let tracked_path = RepoPath::from_internal_string("build/tracked");
+    let mut tree_builder = repo
+        .store()
+        .tree_builder(repo.store().empty_tree_id().clone());
+    testutils::write_normal_file(&mut tree_builder, &tracked_path, "contents");
+    let tree_id = tree_builder.write_tree();
+    let tree = repo.store().get_tree(&RepoPath::root(), &tree_id).unwrap();
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+    wc.set_never_track(GitIgnoreFile::empty().chain("", b"/build/\n*.swp\n"));
+
+    let added_path = RepoPath::from_internal_string("added");
+    let swap_path = RepoPath::from_internal_string("dir/.added.swp");
+    let build_path = RepoPath::from_internal_string("build/output");
+    std::fs::create_dir(workspace_root.join("dir")).unwrap();
+    testutils::write_working_copy_file(&workspace_root, &added_path, "contents");
+    testutils::write_working_copy_file(&workspace_root, &swap_path, "contents");
+    testutils::write_working_copy_file(&workspace_root, &build_path, "contents");
+    testutils::write_working_copy_file(&workspace_root, &tracked_path, "modified");
+
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    let files = new_tree.entries().map(|(name, _value)| name).collect_vec();
+    assert_eq!(files, vec![added_path, tracked_path.clone()]);
+    assert_eq!(
+        new_tree.path_value(&tracked_path),
+        tree.path_value(&tracked_path)
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_gitignores_ignored_directory_nested_gitignore(use_git: bool) {
+    // Tests that a .gitignore file inside an ignored directory is not respected,
+    // even if the directory is walked because it has tracked files in it (Git
+    // doesn't look for .gitignore files in ignored directories either).
+
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let gitignore_path = RepoPath::from_internal_string(".gitignore");
+    testutils::write_working_copy_file(&workspace_root, &gitignore_path, "/ignored/\n");
+    let tracked_path = RepoPath::from_internal_string("ignored/tracked");
+    let mut tree_builder = repo
+        .store()
+        .tree_builder(repo.store().empty_tree_id().clone());
+    testutils::write_normal_file(&mut tree_builder, &tracked_path, "contents");
+    let tree_id = tree_builder.write_tree();
+    let tree = repo.store().get_tree(&RepoPath::root(), &tree_id).unwrap();
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+
+    // A .gitignore in the ignored directory tries to un-ignore a new file
+    let nested_gitignore_path = RepoPath::from_internal_string("ignored/.gitignore");
+    testutils::write_working_copy_file(&workspace_root, &nested_gitignore_path, "!new\n");
+    let new_path = RepoPath::from_internal_string("ignored/new");
+    testutils::write_working_copy_file(&workspace_root, &new_path, "new");
+    let subdir_file_path = RepoPath::from_internal_string("ignored/subdir/file");
+    std::fs::create_dir(workspace_root.join("ignored").join("subdir")).unwrap();
+    testutils::write_working_copy_file(&workspace_root, &subdir_file_path, "contents");
+
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    let files = new_tree
+        .entries()
+        .map(|(name, _value)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(files, vec![gitignore_path, tracked_path]);
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_dotgit_ignored(use_git: bool) {
@@ -676,6 +1474,85 @@ fn test_dotgit_ignored(use_git: bool) {
     locked_wc.discard();
 }
 
+#[test]
+fn test_nested_repo_ignored() {
+    // Tests that the files of a nested repo are not tracked, unless some of them
+    // already are.
+
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let tracked_path = RepoPath::from_internal_string("tracked-repo/file");
+    let tree = testutils::create_tree(repo, &[(&tracked_path, "contents")]);
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+    std::fs::create_dir(workspace_root.join("tracked-repo").join(".git")).unwrap();
+    let tracked_new_path = RepoPath::from_internal_string("tracked-repo/new");
+    testutils::write_working_copy_file(&workspace_root, &tracked_new_path, "contents");
+
+    for (dir, marker) in [("git-repo", ".git"), ("jj-repo", ".jj")] {
+        std::fs::create_dir_all(workspace_root.join(dir).join(marker)).unwrap();
+        testutils::write_working_copy_file(
+            &workspace_root,
+            &RepoPath::from_internal_string(&format!("{dir}/file")),
+            "contents",
+        );
+    }
+    // A worktree has a .git file
+    std::fs::create_dir(workspace_root.join("worktree")).unwrap();
+    std::fs::write(workspace_root.join("worktree").join(".git"), "gitdir: x").unwrap();
+    testutils::write_working_copy_file(
+        &workspace_root,
+        &RepoPath::from_internal_string("worktree/file"),
+        "contents",
+    );
+
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    let files = new_tree
+        .entries()
+        .map(|(name, _value)| name)
+        .collect::<Vec<_>>();
+    assert_eq!(files, vec![tracked_path, tracked_new_path]);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_gitignores_symlink_not_followed() {
+    // Tests that a .gitignore that is a symlink is not respected, like in Git
+
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    std::fs::write(workspace_root.join("ignores"), "file\n").unwrap();
+    std::os::unix::fs::symlink("ignores", workspace_root.join(".gitignore")).unwrap();
+    std::fs::write(workspace_root.join("file"), "contents").unwrap();
+
+    let mut locked_wc = test_workspace.workspace.working_copy_mut().start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    let files = new_tree
+        .entries()
+        .map(|(name, _value)| name.to_internal_file_string())
+        .collect::<Vec<_>>();
+    assert_eq!(files, vec![".gitignore", "file", "ignores"]);
+}
+
 #[cfg(unix)]
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
@@ -707,3 +1584,194 @@ fn test_existing_directory_symlink(use_git: bool) {
     // Therefore, "../escaped" shouldn't be created.
     assert!(!workspace_root.parent().unwrap().join("escaped").exists());
 }
+
+#[test]
+fn test_max_snapshot_files() {
+    // Tests that a snapshot fails without recording anything if there are more
+    // new or modified files than the limit.
+
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let file1_path = RepoPath::from_internal_string("file1");
+    let file2_path = RepoPath::from_internal_string("file2");
+    testutils::write_working_copy_file(&workspace_root, &file1_path, "contents");
+    testutils::write_working_copy_file(&workspace_root, &file2_path, "contents");
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_max_snapshot_files(Some(1));
+    let mut locked_wc = wc.start_mutation();
+    assert_matches!(
+        locked_wc.snapshot(GitIgnoreFile::empty()),
+        Err(SnapshotError::TooManyFiles { count: 2, limit: 1 })
+    );
+    locked_wc.discard();
+
+    wc.set_max_snapshot_files(Some(2));
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc
+        .finish(test_workspace.repo.op_id().clone())
+        .unwrap();
+    let new_tree = test_workspace
+        .repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    let files = new_tree.entries().map(|(name, _value)| name).collect_vec();
+    assert_eq!(files, vec![file1_path, file2_path.clone()]);
+
+    // Unchanged files don't count against the limit
+    testutils::write_working_copy_file(&workspace_root, &file2_path, "modified");
+    wc.set_max_snapshot_files(Some(1));
+    let mut locked_wc = wc.start_mutation();
+    locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+}
+
+#[test]
+fn test_windows_safe_paths() {
+    // Tests that files whose path can't be used on Windows are skipped by
+    // checkouts when `set_windows_safe_paths()` is enabled, and that snapshots
+    // keep their recorded values.
+
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let normal_path = RepoPath::from_internal_string("dir/file");
+    let reserved_path = RepoPath::from_internal_string("aux/file");
+    let trailing_dot_path = RepoPath::from_internal_string("dir/file.");
+    let tree1 = testutils::create_tree(
+        repo,
+        &[
+            (&normal_path, "contents"),
+            (&reserved_path, "contents"),
+            (&trailing_dot_path, "contents"),
+        ],
+    );
+    let tree2 = testutils::create_tree(
+        repo,
+        &[(&normal_path, "contents"), (&reserved_path, "modified")],
+    );
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_windows_safe_paths(true);
+    let stats = wc.check_out(repo.op_id().clone(), None, &tree1).unwrap();
+    assert_eq!(stats.added_files, 1);
+    assert_eq!(
+        stats.skipped_files,
+        vec![
+            (
+                reserved_path.clone(),
+                WindowsPathIssue::ReservedName("aux".to_string())
+            ),
+            (
+                trailing_dot_path.clone(),
+                WindowsPathIssue::TrailingDotOrSpace("file.".to_string())
+            ),
+        ]
+    );
+    assert!(normal_path.to_fs_path(&workspace_root).is_file());
+    assert!(!reserved_path.to_fs_path(&workspace_root).exists());
+    assert!(!trailing_dot_path.to_fs_path(&workspace_root).exists());
+    assert_eq!(
+        wc.file_states().get(&reserved_path).unwrap().file_type,
+        FileType::Unmaterialized
+    );
+
+    // The skipped files aren't considered deleted by snapshots
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    assert_eq!(new_tree_id, *tree1.id());
+
+    // Modifying and removing skipped files works
+    let stats = wc.check_out(repo.op_id().clone(), None, &tree2).unwrap();
+    assert_eq!(stats.removed_files, 1);
+    assert_eq!(stats.skipped_files.len(), 1);
+    assert!(wc.file_states().get(&trailing_dot_path).is_none());
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    assert_eq!(new_tree_id, *tree2.id());
+}
+
+#[test]
+fn test_unicode_normalization() {
+    // Tests that a file whose name on disk is a different Unicode normalization
+    // of a tracked path isn't seen as deleted and added, as happens on macOS,
+    // and that new files are recorded in the configured form.
+
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    // A tree with both forms, which can be created on Linux
+    let nfc_dir_path = RepoPath::from_internal_string("caf\u{e9}/file");
+    let nfd_dir_path = RepoPath::from_internal_string("cafe\u{301}/other");
+    let nfc_file_path = RepoPath::from_internal_string("r\u{e9}sum\u{e9}");
+    let tree = testutils::create_tree(
+        repo,
+        &[
+            (&nfc_dir_path, "contents"),
+            (&nfd_dir_path, "contents"),
+            (&nfc_file_path, "contents"),
+        ],
+    );
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_unicode_normalization(UnicodeNormalization::Nfc);
+    wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(repo.op_id().clone()).unwrap();
+    assert_eq!(new_tree_id, *tree.id());
+
+    // Rename the tracked file to its NFD form and add new files in NFD form
+    std::fs::rename(
+        nfc_file_path.to_fs_path(&workspace_root),
+        workspace_root.join("re\u{301}sume\u{301}"),
+    )
+    .unwrap();
+    std::fs::write(workspace_root.join("cafe\u{301}").join("n\u{303}"), "new").unwrap();
+    std::fs::write(workspace_root.join("nai\u{308}ve"), "new").unwrap();
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    let files = new_tree.entries().map(|(name, _value)| name).collect_vec();
+    assert_eq!(
+        files,
+        vec![
+            nfd_dir_path,
+            RepoPath::from_internal_string("cafe\u{301}/\u{f1}"),
+            nfc_dir_path,
+            RepoPath::from_internal_string("na\u{ef}ve"),
+            nfc_file_path.clone(),
+        ]
+    );
+
+    // Without normalization, the renamed file is seen as deleted and added
+    wc.set_unicode_normalization(UnicodeNormalization::None);
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    assert_eq!(new_tree.path_value(&nfc_file_path), None);
+    assert!(new_tree
+        .path_value(&RepoPath::from_internal_string("re\u{301}sume\u{301}"))
+        .is_some());
+}
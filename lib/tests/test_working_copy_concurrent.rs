@@ -145,3 +145,87 @@ fn test_checkout_parallel(use_git: bool) {
         thread.join().ok().unwrap();
     }
 }
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_prescan_used_by_locked_snapshot(use_git: bool) {
+    // Test that a snapshot taken without the lock is used by the locked snapshot
+    // if nothing else updated the working copy in between.
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let file_a_path = RepoPath::from_internal_string("a");
+    let file_b_path = RepoPath::from_internal_string("b");
+    let wc = test_workspace.workspace.working_copy_mut();
+    // Write the initial tree state
+    let mut locked_wc = wc.start_mutation();
+    locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(repo.op_id().clone()).unwrap();
+
+    testutils::write_working_copy_file(&workspace_root, &file_a_path, "a");
+    wc.prescan(GitIgnoreFile::empty());
+    // Not seen by the locked snapshot since it reuses the earlier scan
+    testutils::write_working_copy_file(&workspace_root, &file_b_path, "b");
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(repo.op_id().clone()).unwrap();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    assert!(new_tree.path_value(&file_a_path).is_some());
+    assert!(new_tree.path_value(&file_b_path).is_none());
+
+    // The next snapshot sees it
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(repo.op_id().clone()).unwrap();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    assert!(new_tree.path_value(&file_a_path).is_some());
+    assert!(new_tree.path_value(&file_b_path).is_some());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_prescan_concurrent_update(use_git: bool) {
+    // Test that a snapshot taken without the lock is redone if another process
+    // updated the working copy before we took the lock.
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = test_workspace.repo.clone();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let file_a_path = RepoPath::from_internal_string("a");
+    let file_b_path = RepoPath::from_internal_string("b");
+    let tree = testutils::create_tree(&repo, &[(&file_b_path, "b")]);
+    testutils::write_working_copy_file(&workspace_root, &file_a_path, "a");
+    let wc1 = test_workspace.workspace.working_copy_mut();
+    wc1.prescan(GitIgnoreFile::empty());
+
+    // Check out another tree from another process (simulated by another workspace
+    // instance)
+    let mut workspace2 =
+        Workspace::load(&settings, &workspace_root, &BackendFactories::default()).unwrap();
+    workspace2
+        .working_copy_mut()
+        .check_out(repo.op_id().clone(), None, &tree)
+        .unwrap();
+
+    // The snapshot includes both the file from the other process's checkout and
+    // the new file
+    let mut locked_wc = wc1.start_mutation();
+    assert_eq!(locked_wc.old_tree_id(), tree.id());
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    assert!(new_tree.path_value(&file_a_path).is_some());
+    assert!(new_tree.path_value(&file_b_path).is_some());
+}
@@ -63,7 +63,9 @@ fn test_sparse_checkout() {
         CheckoutStats {
             updated_files: 0,
             added_files: 0,
-            removed_files: 3
+            removed_files: 3,
+            backed_up_files: vec![],
+            skipped_files: vec![],
         }
     );
     assert_eq!(locked_wc.sparse_patterns(), sparse_patterns);
@@ -77,7 +79,7 @@ fn test_sparse_checkout() {
     assert!(!dir2_file1_path.to_fs_path(&working_copy_path).exists());
 
     // Write the new state to disk
-    locked_wc.finish(repo.op_id().clone());
+    locked_wc.finish(repo.op_id().clone()).unwrap();
     assert_eq!(
         wc.file_states().keys().collect_vec(),
         vec![&dir1_file1_path, &dir1_file2_path, &dir1_subdir1_file1_path]
@@ -107,7 +109,9 @@ fn test_sparse_checkout() {
         CheckoutStats {
             updated_files: 0,
             added_files: 2,
-            removed_files: 2
+            removed_files: 2,
+            backed_up_files: vec![],
+            skipped_files: vec![],
         }
     );
     assert_eq!(locked_wc.sparse_patterns(), sparse_patterns);
@@ -119,7 +123,7 @@ fn test_sparse_checkout() {
         .to_fs_path(&working_copy_path)
         .exists());
     assert!(dir2_file1_path.to_fs_path(&working_copy_path).exists());
-    locked_wc.finish(repo.op_id().clone());
+    locked_wc.finish(repo.op_id().clone()).unwrap();
     assert_eq!(
         wc.file_states().keys().collect_vec(),
         vec![&dir1_subdir1_file1_path, &dir2_file1_path, &root_file1_path]
@@ -156,7 +160,7 @@ fn test_sparse_commit() {
     let mut locked_wc = wc.start_mutation();
     let sparse_patterns = vec![dir1_path.clone()];
     locked_wc.set_sparse_patterns(sparse_patterns).unwrap();
-    locked_wc.finish(repo.op_id().clone());
+    locked_wc.finish(repo.op_id().clone()).unwrap();
 
     // Write modified version of all files, including files that are not in the
     // sparse patterns.
@@ -169,7 +173,7 @@ fn test_sparse_commit() {
     // tree.
     let mut locked_wc = wc.start_mutation();
     let modified_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
-    locked_wc.finish(repo.op_id().clone());
+    locked_wc.finish(repo.op_id().clone()).unwrap();
     let modified_tree = repo
         .store()
         .get_tree(&RepoPath::root(), &modified_tree_id)
@@ -182,13 +186,13 @@ fn test_sparse_commit() {
     let mut locked_wc = wc.start_mutation();
     let sparse_patterns = vec![dir1_path, dir2_path];
     locked_wc.set_sparse_patterns(sparse_patterns).unwrap();
-    locked_wc.finish(repo.op_id().clone());
+    locked_wc.finish(repo.op_id().clone()).unwrap();
 
     // Create a tree from the working copy. Only dir1/file1 and dir2/file1 should be
     // updated in the tree.
     let mut locked_wc = wc.start_mutation();
     let modified_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
-    locked_wc.finish(repo.op_id().clone());
+    locked_wc.finish(repo.op_id().clone()).unwrap();
     let modified_tree = repo
         .store()
         .get_tree(&RepoPath::root(), &modified_tree_id)
@@ -217,7 +221,7 @@ fn test_sparse_commit_gitignore() {
     let mut locked_wc = wc.start_mutation();
     let sparse_patterns = vec![dir1_path.clone()];
     locked_wc.set_sparse_patterns(sparse_patterns).unwrap();
-    locked_wc.finish(repo.op_id().clone());
+    locked_wc.finish(repo.op_id().clone()).unwrap();
 
     // Write dir1/file1 and dir1/file2 and a .gitignore saying to ignore dir1/file1
     std::fs::write(working_copy_path.join(".gitignore"), "dir1/file1").unwrap();
@@ -229,7 +233,7 @@ fn test_sparse_commit_gitignore() {
     // tree because dir1/file1 is ignored.
     let mut locked_wc = wc.start_mutation();
     let modified_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
-    locked_wc.finish(repo.op_id().clone());
+    locked_wc.finish(repo.op_id().clone()).unwrap();
     let modified_tree = repo
         .store()
         .get_tree(&RepoPath::root(), &modified_tree_id)
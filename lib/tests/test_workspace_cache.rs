@@ -0,0 +1,123 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use itertools::Itertools;
+use jujutsu_lib::gitignore::GitIgnoreFile;
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestRepo;
+use jujutsu_lib::working_copy::CheckoutStats;
+use jujutsu_lib::workspace_cache::WorkspaceCache;
+
+fn stats(updated_files: u32, added_files: u32, removed_files: u32) -> CheckoutStats {
+    CheckoutStats {
+        updated_files,
+        added_files,
+        removed_files,
+        backed_up_files: vec![],
+        skipped_files: vec![],
+    }
+}
+
+#[test]
+fn test_workspace_cache_reuses_nearest_slot() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+    let file1 = RepoPath::from_internal_string("file1");
+    let file2 = RepoPath::from_internal_string("file2");
+    let tree1 = testutils::create_tree(repo, &[(&file1, "a"), (&file2, "a")]);
+    let tree2 = testutils::create_tree(repo, &[(&file1, "a"), (&file2, "b")]);
+    let cache_dir = testutils::new_temp_dir();
+    let cache = WorkspaceCache::new(repo.store().clone(), cache_dir.path().to_path_buf(), 4);
+    let ignores = GitIgnoreFile::empty();
+
+    let workspace = cache.check_out(&tree1, ignores.clone()).unwrap();
+    assert_eq!(workspace.name(), "0");
+    assert_eq!(workspace.stats(), &stats(0, 2, 0));
+    let file2_path = workspace.working_copy_path().join("file2");
+    drop(workspace);
+
+    // Only the file that differs is written
+    let workspace = cache.check_out(&tree2, ignores.clone()).unwrap();
+    assert_eq!(workspace.name(), "0");
+    assert_eq!(workspace.stats(), &stats(1, 0, 0));
+    assert_eq!(std::fs::read_to_string(&file2_path).unwrap(), "b");
+
+    // Changes made in the slot are discarded
+    std::fs::write(&file2_path, "changed").unwrap();
+    std::fs::write(workspace.working_copy_path().join("new"), "new").unwrap();
+    drop(workspace);
+    let workspace = cache.check_out(&tree2, ignores.clone()).unwrap();
+    assert_eq!(workspace.stats(), &stats(1, 0, 1));
+    assert_eq!(std::fs::read_to_string(&file2_path).unwrap(), "b");
+    assert!(!workspace.working_copy_path().join("new").exists());
+
+    // A slot that's in use isn't reused
+    let workspace2 = cache.check_out(&tree2, ignores).unwrap();
+    assert_eq!(workspace2.name(), "1");
+    assert_eq!(workspace2.stats(), &stats(0, 2, 0));
+    let slots = cache.slots().unwrap();
+    assert_eq!(
+        slots.iter().map(|slot| slot.tree_id.clone()).collect_vec(),
+        vec![tree2.id().clone(), tree2.id().clone()]
+    );
+    assert!(slots.iter().all(|slot| slot.in_use));
+}
+
+#[test]
+fn test_workspace_cache_eviction() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+    let file = RepoPath::from_internal_string("file");
+    let trees = (0..3)
+        .map(|i| testutils::create_tree(repo, &[(&file, &format!("contents {i}"))]))
+        .collect_vec();
+    let cache_dir = testutils::new_temp_dir();
+    let ignores = GitIgnoreFile::empty();
+
+    let cache = WorkspaceCache::new(repo.store().clone(), cache_dir.path().to_path_buf(), 3);
+    let mut workspaces = vec![];
+    for tree in &trees {
+        workspaces.push(cache.check_out(tree, ignores.clone()).unwrap());
+        // Make sure the slots get different timestamps
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    assert_eq!(
+        workspaces
+            .iter()
+            .map(|workspace| workspace.name())
+            .collect_vec(),
+        vec!["0", "1", "2"]
+    );
+    drop(workspaces);
+    assert_eq!(cache.slots().unwrap().len(), 3);
+
+    // With a smaller capacity, the least recently used slots are removed
+    let cache = WorkspaceCache::new(repo.store().clone(), cache_dir.path().to_path_buf(), 2);
+    assert_eq!(cache.evict().unwrap(), 1);
+    let slots = cache.slots().unwrap();
+    assert_eq!(
+        slots.iter().map(|slot| slot.name.as_str()).collect_vec(),
+        vec!["2", "1"]
+    );
+    assert!(!cache_dir.path().join("0").exists());
+
+    // Slots that are in use are not removed
+    let workspace = cache.check_out(&trees[0], ignores).unwrap();
+    assert_eq!(cache.clear().unwrap(), 1);
+    assert_eq!(cache.slots().unwrap().len(), 1);
+    drop(workspace);
+    assert_eq!(cache.clear().unwrap(), 1);
+    assert!(cache.slots().unwrap().is_empty());
+}
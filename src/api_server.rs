@@ -0,0 +1,307 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A JSON-RPC 2.0 server for editor integrations and other tools that want to
+//! query a repo repeatedly without paying for loading it each time.
+//!
+//! Requests and responses are sent as one JSON object per line. The server
+//! never takes locks or writes operations, so it can run while other jj
+//! commands are modifying the repo. It reloads the repo when it notices that
+//! a new operation has been written. Note that it doesn't snapshot the working
+//! copy, so `status` reports the working-copy commit as of the last command
+//! that did.
+
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::sync::Arc;
+
+use jujutsu_lib::backend::TreeValue;
+use jujutsu_lib::commit::Commit;
+use jujutsu_lib::matchers::EverythingMatcher;
+use jujutsu_lib::repo::ReadonlyRepo;
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::revset;
+use jujutsu_lib::rewrite::merge_commit_trees;
+use jujutsu_lib::tree::Tree;
+use jujutsu_lib::workspace::Workspace;
+use serde_json::{json, Value};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const PARSE_ERROR: i64 = -32700;
+const REQUEST_FAILED: i64 = -32000;
+
+struct ApiError {
+    code: i64,
+    message: String,
+}
+
+impl ApiError {
+    fn invalid_params(message: impl Into<String>) -> Self {
+        ApiError {
+            code: INVALID_PARAMS,
+            message: message.into(),
+        }
+    }
+
+    fn failed(message: impl Into<String>) -> Self {
+        ApiError {
+            code: REQUEST_FAILED,
+            message: message.into(),
+        }
+    }
+}
+
+pub struct ApiServer {
+    workspace: Workspace,
+    repo: Arc<ReadonlyRepo>,
+}
+
+impl ApiServer {
+    pub fn new(workspace: Workspace) -> Result<Self, String> {
+        let repo = workspace
+            .repo_loader()
+            .load_at_head_readonly()
+            .map_err(|err| err.to_string())?;
+        Ok(ApiServer { workspace, repo })
+    }
+
+    /// Reloads the repo if another process has written an operation since we
+    /// last loaded it.
+    fn refresh(&mut self) -> Result<(), ApiError> {
+        let op_heads = self.repo.op_heads_store().get_op_heads();
+        if op_heads.len() != 1 || op_heads[0] != *self.repo.op_id() {
+            self.repo = self
+                .workspace
+                .repo_loader()
+                .load_at_head_readonly()
+                .map_err(|err| ApiError::failed(err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Serves requests read from `input` until it's closed or until a
+    /// `shutdown` request is received. Returns `true` in the latter case.
+    pub fn serve(&mut self, input: impl Read, mut output: impl Write) -> io::Result<bool> {
+        for line in io::BufReader::new(input).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (response, shutdown) = self.handle_line(&line);
+            writeln!(output, "{response}")?;
+            output.flush()?;
+            if shutdown {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn handle_line(&mut self, line: &str) -> (Value, bool) {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(err) => {
+                return (
+                    error_response(Value::Null, PARSE_ERROR, &err.to_string()),
+                    false,
+                );
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = request.get("method").and_then(Value::as_str).unwrap_or("");
+        let params = request.get("params").cloned().unwrap_or_else(|| json!({}));
+        if method == "shutdown" {
+            return (json!({"jsonrpc": "2.0", "id": id, "result": null}), true);
+        }
+        let result = self.refresh().and_then(|()| match method {
+            "log" => self.log(&params),
+            "status" => self.status(),
+            "diff" => self.diff(&params),
+            "file" => self.file(&params),
+            _ => Err(ApiError {
+                code: METHOD_NOT_FOUND,
+                message: format!("Unknown method \"{method}\""),
+            }),
+        });
+        let response = match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(err) => error_response(id, err.code, &err.message),
+        };
+        (response, false)
+    }
+
+    fn resolve_revset(&self, revset_str: &str) -> Result<Vec<Commit>, ApiError> {
+        let expression =
+            revset::parse(revset_str).map_err(|err| ApiError::invalid_params(err.to_string()))?;
+        let revset = expression
+            .evaluate(
+                self.repo.as_repo_ref(),
+                Some(&self.workspace.workspace_id()),
+            )
+            .map_err(|err| ApiError::invalid_params(err.to_string()))?;
+        let commits: Result<Vec<_>, _> = revset.iter().commits(self.repo.store()).collect();
+        commits.map_err(|err| ApiError::failed(err.to_string()))
+    }
+
+    fn resolve_single_rev(&self, revision_str: &str) -> Result<Commit, ApiError> {
+        let mut commits = self.resolve_revset(revision_str)?;
+        if commits.len() != 1 {
+            return Err(ApiError::invalid_params(format!(
+                "Revset \"{revision_str}\" didn't resolve to exactly one revision"
+            )));
+        }
+        Ok(commits.pop().unwrap())
+    }
+
+    fn log(&self, params: &Value) -> Result<Value, ApiError> {
+        let revset_str = string_param(params, "revisions")?.unwrap_or("all()");
+        let limit = params
+            .get("limit")
+            .and_then(Value::as_u64)
+            .unwrap_or(u64::MAX);
+        let commits = self.resolve_revset(revset_str)?;
+        Ok(Value::Array(
+            commits
+                .iter()
+                .take(limit.try_into().unwrap_or(usize::MAX))
+                .map(commit_to_json)
+                .collect(),
+        ))
+    }
+
+    fn status(&self) -> Result<Value, ApiError> {
+        let workspace_id = self.workspace.workspace_id();
+        let wc_commit = match self.repo.view().get_wc_commit_id(&workspace_id) {
+            Some(wc_commit_id) => self
+                .repo
+                .store()
+                .get_commit(wc_commit_id)
+                .map_err(|err| ApiError::failed(err.to_string()))?,
+            None => {
+                return Ok(json!({"working_copy": null, "parents": [], "changes": []}));
+            }
+        };
+        let parents = wc_commit.parents();
+        let parent_tree = merge_commit_trees(self.repo.as_repo_ref(), &parents);
+        Ok(json!({
+            "working_copy": commit_to_json(&wc_commit),
+            "parents": parents.iter().map(commit_to_json).collect::<Vec<_>>(),
+            "changes": diff_summary_to_json(&parent_tree, &wc_commit.tree()),
+        }))
+    }
+
+    fn diff(&self, params: &Value) -> Result<Value, ApiError> {
+        let (from_tree, to_tree) = match string_param(params, "revision")? {
+            Some(revision_str) => {
+                let commit = self.resolve_single_rev(revision_str)?;
+                let parent_tree = merge_commit_trees(self.repo.as_repo_ref(), &commit.parents());
+                (parent_tree, commit.tree())
+            }
+            None => {
+                let from = string_param(params, "from")?.unwrap_or("@");
+                let to = string_param(params, "to")?.unwrap_or("@");
+                (
+                    self.resolve_single_rev(from)?.tree(),
+                    self.resolve_single_rev(to)?.tree(),
+                )
+            }
+        };
+        Ok(json!({ "changes": diff_summary_to_json(&from_tree, &to_tree) }))
+    }
+
+    fn file(&self, params: &Value) -> Result<Value, ApiError> {
+        let revision_str = string_param(params, "revision")?.unwrap_or("@");
+        let path_str = string_param(params, "path")?
+            .ok_or_else(|| ApiError::invalid_params("Missing parameter \"path\""))?;
+        let commit = self.resolve_single_rev(revision_str)?;
+        let path = RepoPath::from_internal_string(path_str);
+        let store = self.repo.store();
+        match commit.tree().path_value(&path) {
+            Some(TreeValue::Normal { id, executable }) => {
+                let mut content = vec![];
+                store
+                    .read_file(&path, &id)
+                    .map_err(|err| ApiError::failed(err.to_string()))?
+                    .read_to_end(&mut content)
+                    .map_err(|err| ApiError::failed(err.to_string()))?;
+                Ok(json!({
+                    "type": "file",
+                    "executable": executable,
+                    "content": String::from_utf8_lossy(&content),
+                }))
+            }
+            Some(TreeValue::Symlink(id)) => {
+                let target = store
+                    .read_symlink(&path, &id)
+                    .map_err(|err| ApiError::failed(err.to_string()))?;
+                Ok(json!({"type": "symlink", "target": target}))
+            }
+            Some(TreeValue::Conflict(_)) => Ok(json!({"type": "conflict"})),
+            Some(_) | None => Err(ApiError::failed(format!(
+                "No such file \"{path_str}\" in revision {}",
+                commit.id().hex()
+            ))),
+        }
+    }
+}
+
+fn string_param<'a>(params: &'a Value, name: &str) -> Result<Option<&'a str>, ApiError> {
+    match params.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(value)) => Ok(Some(value)),
+        Some(_) => Err(ApiError::invalid_params(format!(
+            "Parameter \"{name}\" must be a string"
+        ))),
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": code, "message": message},
+    })
+}
+
+fn commit_to_json(commit: &Commit) -> Value {
+    json!({
+        "commit_id": commit.id().hex(),
+        "change_id": commit.change_id().hex(),
+        "parents": commit.parent_ids().iter().map(|id| id.hex()).collect::<Vec<_>>(),
+        "description": commit.description(),
+        "author": {
+            "name": commit.author().name,
+            "email": commit.author().email,
+        },
+        "empty": commit.is_empty(),
+        "conflict": commit.tree().has_conflict(),
+    })
+}
+
+fn diff_summary_to_json(from_tree: &Tree, to_tree: &Tree) -> Value {
+    let summary = from_tree.diff_summary(to_tree, &EverythingMatcher);
+    let mut changes = vec![];
+    for (kind, paths) in [
+        ("modified", &summary.modified),
+        ("added", &summary.added),
+        ("removed", &summary.removed),
+    ] {
+        for path in paths {
+            changes.push(json!({"path": path.to_internal_file_string(), "kind": kind}));
+        }
+    }
+    changes.sort_by(|a, b| a["path"].as_str().cmp(&b["path"].as_str()));
+    Value::Array(changes)
+}
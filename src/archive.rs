@@ -0,0 +1,229 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Writing the files of a tree to a tar or zip archive. The files are read
+//! from the store one at a time, so no working copy is needed.
+
+use std::fs::File;
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use chrono::{Datelike, FixedOffset, TimeZone, Timelike};
+use jujutsu_lib::backend::{BackendError, Timestamp, TreeValue};
+use jujutsu_lib::conflicts::materialize_conflict;
+use jujutsu_lib::matchers::Matcher;
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::store::Store;
+use jujutsu_lib::tree::Tree;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    #[error("I/O error: {0}")]
+    IoError(#[from] io::Error),
+    #[error("Failed to write zip archive: {0}")]
+    ZipError(#[from] zip::result::ZipError),
+    #[error("Unexpected error from store: {0}")]
+    BackendError(#[from] BackendError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ArchiveFormat {
+    Tar,
+    #[value(name = "tar.gz")]
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Guesses the format from the file name's extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        if name.ends_with(".tar") {
+            Some(ArchiveFormat::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveFormat::TarGz)
+        } else if name.ends_with(".zip") {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+enum ArchiveEntry {
+    File { contents: Vec<u8>, executable: bool },
+    Symlink(String),
+}
+
+/// Reads the entry for a value in the tree. Conflicts are written with
+/// conflict markers. Git submodules can't be archived and are skipped.
+fn read_entry(
+    store: &Store,
+    path: &RepoPath,
+    value: &TreeValue,
+) -> Result<Option<ArchiveEntry>, ArchiveError> {
+    match value {
+        TreeValue::Normal { id, executable } => {
+            let mut contents = vec![];
+            store.read_file(path, id)?.read_to_end(&mut contents)?;
+            Ok(Some(ArchiveEntry::File {
+                contents,
+                executable: *executable,
+            }))
+        }
+        TreeValue::Symlink(id) => Ok(Some(ArchiveEntry::Symlink(store.read_symlink(path, id)?))),
+        TreeValue::Conflict(id) => {
+            let conflict = store.read_conflict(path, id)?;
+            let mut contents = vec![];
+            materialize_conflict(store, path, &conflict, &mut contents)?;
+            Ok(Some(ArchiveEntry::File {
+                contents,
+                executable: false,
+            }))
+        }
+        TreeValue::GitSubmodule(_) | TreeValue::Tree(_) => Ok(None),
+    }
+}
+
+/// Writes the files in `tree` that match `matcher` to `output`. Each path is
+/// prefixed by `prefix`, and all entries get `mtime` as their modification
+/// time.
+pub fn write_archive(
+    tree: &Tree,
+    matcher: &dyn Matcher,
+    format: ArchiveFormat,
+    prefix: &str,
+    mtime: &Timestamp,
+    output: File,
+) -> Result<(), ArchiveError> {
+    let store = tree.store();
+    let entries = tree.entries_matching(matcher).map(|(path, value)| {
+        (
+            format!("{prefix}{}", path.to_internal_file_string()),
+            path,
+            value,
+        )
+    });
+    let mtime_seconds = mtime.timestamp.0.div_euclid(1000);
+    match format {
+        ArchiveFormat::Tar | ArchiveFormat::TarGz => {
+            let output: Box<dyn Write> = if format == ArchiveFormat::TarGz {
+                Box::new(flate2::write::GzEncoder::new(
+                    output,
+                    flate2::Compression::default(),
+                ))
+            } else {
+                Box::new(output)
+            };
+            let mut builder = tar::Builder::new(output);
+            for (name, path, value) in entries {
+                let mut header = tar::Header::new_gnu();
+                header.set_mtime(mtime_seconds.max(0) as u64);
+                match read_entry(store, &path, &value)? {
+                    None => {}
+                    Some(ArchiveEntry::File {
+                        contents,
+                        executable,
+                    }) => {
+                        header.set_entry_type(tar::EntryType::Regular);
+                        header.set_mode(if executable { 0o755 } else { 0o644 });
+                        header.set_size(contents.len() as u64);
+                        builder.append_data(&mut header, &name, contents.as_slice())?;
+                    }
+                    Some(ArchiveEntry::Symlink(target)) => {
+                        header.set_entry_type(tar::EntryType::Symlink);
+                        header.set_mode(0o777);
+                        header.set_size(0);
+                        builder.append_link(&mut header, &name, &target)?;
+                    }
+                }
+            }
+            builder.into_inner()?.flush()?;
+        }
+        ArchiveFormat::Zip => {
+            let mut writer = zip::ZipWriter::new(output);
+            let last_modified = zip_date_time(mtime);
+            for (name, path, value) in entries {
+                let options = zip::write::FileOptions::default().last_modified_time(last_modified);
+                match read_entry(store, &path, &value)? {
+                    None => {}
+                    Some(ArchiveEntry::File {
+                        contents,
+                        executable,
+                    }) => {
+                        let options =
+                            options.unix_permissions(if executable { 0o755 } else { 0o644 });
+                        writer.start_file(name, options)?;
+                        writer.write_all(&contents)?;
+                    }
+                    Some(ArchiveEntry::Symlink(target)) => {
+                        writer.add_symlink(name, target, options)?;
+                    }
+                }
+            }
+            writer.finish()?.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Converts the timestamp to the local time in its time zone, which is what
+/// zip files store. Falls back to the earliest time zip supports.
+fn zip_date_time(timestamp: &Timestamp) -> zip::DateTime {
+    FixedOffset::east_opt(timestamp.tz_offset * 60)
+        .and_then(|tz| {
+            tz.timestamp_opt(timestamp.timestamp.0.div_euclid(1000), 0)
+                .single()
+        })
+        .and_then(|datetime| {
+            zip::DateTime::from_date_and_time(
+                datetime.year().try_into().ok()?,
+                datetime.month() as u8,
+                datetime.day() as u8,
+                datetime.hour() as u8,
+                datetime.minute() as u8,
+                datetime.second() as u8,
+            )
+            .ok()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("out.tar")),
+            Some(ArchiveFormat::Tar)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("dir/out.tar.gz")),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("out.tgz")),
+            Some(ArchiveFormat::TarGz)
+        );
+        assert_eq!(
+            ArchiveFormat::from_path(Path::new("out.zip")),
+            Some(ArchiveFormat::Zip)
+        );
+        assert_eq!(ArchiveFormat::from_path(Path::new("out.txt")), None);
+    }
+}
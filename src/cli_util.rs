@@ -16,37 +16,55 @@ use std::collections::{HashSet, VecDeque};
 use std::env::ArgsOs;
 use std::ffi::OsString;
 use std::fmt::Debug;
+use std::io;
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use clap::{ArgMatches, FromArgMatches};
 use git2::{Oid, Repository};
 use itertools::Itertools;
-use jujutsu_lib::backend::{BackendError, CommitId, TreeId};
+use jujutsu_lib::backend::{BackendError, ChangeId, CommitId, Timestamp, TreeId};
 use jujutsu_lib::commit::Commit;
 use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::content_filter::{ContentFilterError, ContentFilters};
+use jujutsu_lib::fast_import::{FastExportError, FastImportError};
 use jujutsu_lib::git::{GitExportError, GitImportError};
 use jujutsu_lib::gitignore::GitIgnoreFile;
+use jujutsu_lib::hg::HgImportError;
+use jujutsu_lib::hooks::{Hook, HookError};
+use jujutsu_lib::line_endings::LineEndingPolicy;
 use jujutsu_lib::matchers::{EverythingMatcher, Matcher, PrefixMatcher, Visit};
-use jujutsu_lib::op_heads_store::{OpHeadResolutionError, OpHeads, OpHeadsStore};
-use jujutsu_lib::op_store::{OpStore, OpStoreError, OperationId, WorkspaceId};
+use jujutsu_lib::merge_attributes::MergeAttributesError;
+use jujutsu_lib::op_heads_store::{LockedOpHeads, OpHeadResolutionError, OpHeads, OpHeadsStore};
+use jujutsu_lib::op_store::{OpStore, OpStoreError, OperationId, RefTarget, WorkspaceId};
 use jujutsu_lib::operation::Operation;
 use jujutsu_lib::repo::{BackendFactories, MutableRepo, ReadonlyRepo};
 use jujutsu_lib::repo_path::RepoPath;
-use jujutsu_lib::revset::{RevsetError, RevsetParseError};
+use jujutsu_lib::revset::{Revset, RevsetError, RevsetParseError};
 use jujutsu_lib::settings::UserSettings;
+use jujutsu_lib::store::Store;
 use jujutsu_lib::transaction::Transaction;
 use jujutsu_lib::tree::{Tree, TreeMergeError};
 use jujutsu_lib::working_copy::{
-    CheckoutStats, LockedWorkingCopy, ResetError, SnapshotError, WorkingCopy,
+    CheckoutError, CheckoutStats, LockedWorkingCopy, ResetError, SnapshotError, WorkingCopy,
+    WorkingCopyStateError,
 };
 use jujutsu_lib::workspace::{Workspace, WorkspaceInitError, WorkspaceLoadError};
+use jujutsu_lib::workspace_cache::WorkspaceCacheError;
 use jujutsu_lib::{dag_walk, git, revset};
+use serde_json::{json, Map, Value};
 
-use crate::config::read_config;
+use crate::archive::ArchiveError;
+use crate::config::{git_config_fallback, read_config};
 use crate::diff_edit::DiffEditError;
+use crate::email::EmailError;
+use crate::events;
+use crate::events::EventNotifier;
+use crate::forge::ForgeError;
+use crate::state_summary::write_state_summary;
+use crate::templater;
 use crate::ui;
-use crate::ui::{ColorChoice, FilePathParseError, Ui};
+use crate::ui::{relative_path, ColorChoice, FilePathParseError, Ui};
 
 pub enum CommandError {
     UserError(String),
@@ -74,6 +92,30 @@ impl From<config::ConfigError> for CommandError {
     }
 }
 
+impl From<HookError> for CommandError {
+    fn from(err: HookError) -> Self {
+        match err {
+            HookError::InvalidConfig(_) => CommandError::ConfigError(err.to_string()),
+            _ => CommandError::UserError(err.to_string()),
+        }
+    }
+}
+
+impl From<ContentFilterError> for CommandError {
+    fn from(err: ContentFilterError) -> Self {
+        match err {
+            ContentFilterError::InvalidConfig(_) => CommandError::ConfigError(err.to_string()),
+            _ => CommandError::UserError(err.to_string()),
+        }
+    }
+}
+
+impl From<MergeAttributesError> for CommandError {
+    fn from(err: MergeAttributesError) -> Self {
+        CommandError::ConfigError(err.to_string())
+    }
+}
+
 impl From<BackendError> for CommandError {
     fn from(err: BackendError) -> Self {
         CommandError::UserError(format!("Unexpected error from store: {err}"))
@@ -81,8 +123,13 @@ impl From<BackendError> for CommandError {
 }
 
 impl From<WorkspaceInitError> for CommandError {
-    fn from(_: WorkspaceInitError) -> Self {
-        CommandError::UserError("The target repo already exists".to_string())
+    fn from(err: WorkspaceInitError) -> Self {
+        match err {
+            WorkspaceInitError::DestinationExists(_) => {
+                CommandError::UserError("The target repo already exists".to_string())
+            }
+            WorkspaceInitError::InvalidObjectStore(_) => CommandError::UserError(err.to_string()),
+        }
     }
 }
 
@@ -98,7 +145,14 @@ impl From<OpHeadResolutionError> for CommandError {
 
 impl From<SnapshotError> for CommandError {
     fn from(err: SnapshotError) -> Self {
-        CommandError::InternalError(format!("Failed to snapshot the working copy: {err}"))
+        match err {
+            SnapshotError::ContentFilterError(_)
+            | SnapshotError::LineEndingChanges { .. }
+            | SnapshotError::TooManyFiles { .. } => {
+                CommandError::UserError(format!("Failed to snapshot the working copy: {err}"))
+            }
+            _ => CommandError::InternalError(format!("Failed to snapshot the working copy: {err}")),
+        }
     }
 }
 
@@ -108,6 +162,25 @@ impl From<TreeMergeError> for CommandError {
     }
 }
 
+impl From<WorkspaceCacheError> for CommandError {
+    fn from(err: WorkspaceCacheError) -> Self {
+        match err {
+            WorkspaceCacheError::IoError(err) => err.into(),
+            WorkspaceCacheError::SnapshotError(err) => err.into(),
+            WorkspaceCacheError::CheckoutError(err) => {
+                CommandError::InternalError(format!("Failed to check out revision: {err}"))
+            }
+            WorkspaceCacheError::StateError(err) => err.into(),
+        }
+    }
+}
+
+impl From<WorkingCopyStateError> for CommandError {
+    fn from(err: WorkingCopyStateError) -> Self {
+        CommandError::InternalError(format!("Failed to save the working copy state: {err}"))
+    }
+}
+
 impl From<ResetError> for CommandError {
     fn from(_: ResetError) -> Self {
         CommandError::InternalError("Failed to reset the working copy".to_string())
@@ -134,6 +207,54 @@ impl From<GitImportError> for CommandError {
     }
 }
 
+impl From<ArchiveError> for CommandError {
+    fn from(err: ArchiveError) -> Self {
+        CommandError::UserError(format!("Failed to write archive: {err}"))
+    }
+}
+
+impl From<EmailError> for CommandError {
+    fn from(err: EmailError) -> Self {
+        match err {
+            EmailError::InvalidConfig(_) => CommandError::ConfigError(err.to_string()),
+            _ => CommandError::UserError(format!("Failed to send email: {err}")),
+        }
+    }
+}
+
+impl From<ForgeError> for CommandError {
+    fn from(err: ForgeError) -> Self {
+        match err {
+            ForgeError::InvalidConfig(_) => CommandError::ConfigError(err.to_string()),
+            _ => CommandError::UserError(err.to_string()),
+        }
+    }
+}
+
+impl From<FastExportError> for CommandError {
+    fn from(err: FastExportError) -> Self {
+        match err {
+            FastExportError::IoError(err) => err.into(),
+            FastExportError::BackendError(err) => err.into(),
+        }
+    }
+}
+
+impl From<FastImportError> for CommandError {
+    fn from(err: FastImportError) -> Self {
+        match err {
+            FastImportError::BackendError(err) => err.into(),
+            _ => CommandError::UserError(format!("Failed to import stream: {err}")),
+        }
+    }
+}
+
+impl From<HgImportError> for CommandError {
+    fn from(err: HgImportError) -> Self {
+        CommandError::UserError(err.to_string())
+    }
+}
+
 impl From<GitExportError> for CommandError {
     fn from(err: GitExportError) -> Self {
         match err {
@@ -159,6 +280,18 @@ impl From<RevsetError> for CommandError {
     }
 }
 
+/// Like the `From<RevsetError>` conversion, but points at the symbol in
+/// `revset_str` that failed to resolve.
+pub fn revset_resolution_error(revset_str: &str, err: RevsetError) -> CommandError {
+    match err {
+        RevsetError::StoreError(_) => CommandError::from(err),
+        _ => CommandError::UserError(format!(
+            "Failed to resolve revset: {}",
+            err.format_with_source(revset_str)
+        )),
+    }
+}
+
 impl From<FilePathParseError> for CommandError {
     fn from(err: FilePathParseError) -> Self {
         match err {
@@ -174,6 +307,7 @@ pub struct CommandHelper {
     string_args: Vec<String>,
     global_args: GlobalArgs,
     backend_factories: BackendFactories,
+    loaded_store: Mutex<Option<Arc<Store>>>,
 }
 
 impl CommandHelper {
@@ -183,6 +317,7 @@ impl CommandHelper {
             string_args,
             global_args,
             backend_factories: BackendFactories::default(),
+            loaded_store: Mutex::new(None),
         }
     }
 
@@ -203,29 +338,7 @@ impl CommandHelper {
     }
 
     pub fn workspace_helper(&self, ui: &mut Ui) -> Result<WorkspaceCommandHelper, CommandError> {
-        let wc_path_str = self.global_args.repository.as_deref().unwrap_or(".");
-        let wc_path = ui.cwd().join(wc_path_str);
-        let workspace = match Workspace::load(ui.settings(), &wc_path, &self.backend_factories) {
-            Ok(workspace) => workspace,
-            Err(WorkspaceLoadError::NoWorkspaceHere(wc_path)) => {
-                let mut message = format!("There is no jj repo in \"{}\"", wc_path_str);
-                let git_dir = wc_path.join(".git");
-                if git_dir.is_dir() {
-                    // TODO: Make this hint separate from the error, so the caller can format
-                    // it differently.
-                    message += "
-It looks like this is a git repo. You can create a jj repo backed by it by running this:
-jj init --git-repo=.";
-                }
-                return Err(CommandError::UserError(message));
-            }
-            Err(WorkspaceLoadError::RepoDoesNotExist(repo_dir)) => {
-                return Err(CommandError::UserError(format!(
-                    "The repository directory at {} is missing. Was it moved?",
-                    repo_dir.to_str().unwrap()
-                )));
-            }
-        };
+        let workspace = self.load_workspace(ui)?;
         let repo_loader = workspace.repo_loader();
         let op_heads = resolve_op_for_load(
             repo_loader.op_store(),
@@ -242,37 +355,84 @@ jj init --git-repo=.";
                     ui,
                     "Concurrent modification detected, resolving automatically.",
                 )?;
-                let base_repo = repo_loader.load_at(&op_heads[0]);
-                // TODO: It may be helpful to print each operation we're merging here
-                let mut workspace_command = self.for_loaded_repo(ui, workspace, base_repo)?;
-                let mut tx = workspace_command.start_transaction("resolve concurrent operations");
-                for other_op_head in op_heads.into_iter().skip(1) {
-                    tx.merge_operation(other_op_head);
-                    let num_rebased = tx.mut_repo().rebase_descendants(ui.settings())?;
-                    if num_rebased > 0 {
-                        writeln!(
-                            ui,
-                            "Rebased {} descendant commits onto commits rewritten by other \
-                             operation",
-                            num_rebased
-                        )?;
-                    }
-                }
-                let merged_repo = tx.write().leave_unpublished();
-                locked_op_heads.finish(merged_repo.operation());
-                workspace_command.repo = merged_repo;
-                return Ok(workspace_command);
+                return self.merge_op_heads(ui, workspace, locked_op_heads, op_heads);
             }
         };
         self.for_loaded_repo(ui, workspace, repo)
     }
 
+    /// Merges concurrent operations into a single operation and returns a
+    /// helper for the repo at the merged operation. `op_heads` is expected to be
+    /// sorted by end time; the oldest one is used as the base.
+    pub fn merge_op_heads(
+        &self,
+        ui: &mut Ui,
+        workspace: Workspace,
+        locked_op_heads: LockedOpHeads,
+        op_heads: Vec<Operation>,
+    ) -> Result<WorkspaceCommandHelper, CommandError> {
+        let repo_loader = workspace.repo_loader();
+        let base_repo = repo_loader.load_at(&op_heads[0]);
+        // TODO: It may be helpful to print each operation we're merging here
+        let mut workspace_command = self.for_loaded_repo(ui, workspace, base_repo)?;
+        let mut tx = workspace_command.start_transaction("resolve concurrent operations");
+        for other_op_head in op_heads.into_iter().skip(1) {
+            tx.merge_operation(other_op_head);
+            let num_rebased = tx.mut_repo().rebase_descendants(ui.settings())?;
+            if num_rebased > 0 {
+                writeln!(
+                    ui,
+                    "Rebased {} descendant commits onto commits rewritten by other \
+                     operation",
+                    num_rebased
+                )?;
+            }
+        }
+        let merged_repo = tx.write().leave_unpublished();
+        locked_op_heads.finish(merged_repo.operation());
+        warn_new_branch_conflicts(ui, workspace_command.repo(), &merged_repo)?;
+        workspace_command.repo = merged_repo;
+        Ok(workspace_command)
+    }
+
+    /// Loads the workspace without loading the repo or touching the working
+    /// copy.
+    pub fn load_workspace(&self, ui: &Ui) -> Result<Workspace, CommandError> {
+        let wc_path_str = self.global_args.repository.as_deref().unwrap_or(".");
+        let wc_path = ui.cwd().join(wc_path_str);
+        match Workspace::load(ui.settings(), &wc_path, &self.backend_factories) {
+            Ok(workspace) => Ok(workspace),
+            Err(WorkspaceLoadError::NoWorkspaceHere(wc_path)) => {
+                let mut message = format!("There is no jj repo in \"{}\"", wc_path_str);
+                let git_dir = wc_path.join(".git");
+                if git_dir.is_dir() {
+                    // TODO: Make this hint separate from the error, so the caller can format
+                    // it differently.
+                    message += "
+It looks like this is a git repo. You can create a jj repo backed by it by running this:
+jj init --git-repo=.";
+                }
+                Err(CommandError::UserError(message))
+            }
+            Err(WorkspaceLoadError::RepoDoesNotExist(repo_dir)) => {
+                Err(CommandError::UserError(format!(
+                    "The repository directory at {} is missing. Was it moved?",
+                    repo_dir.to_str().unwrap()
+                )))
+            }
+            Err(err @ WorkspaceLoadError::MigrationFailed(_)) => {
+                Err(CommandError::InternalError(err.to_string()))
+            }
+        }
+    }
+
     pub fn for_loaded_repo(
         &self,
         ui: &mut Ui,
         workspace: Workspace,
         repo: Arc<ReadonlyRepo>,
     ) -> Result<WorkspaceCommandHelper, CommandError> {
+        *self.loaded_store.lock().unwrap() = Some(repo.store().clone());
         WorkspaceCommandHelper::for_loaded_repo(
             ui,
             workspace,
@@ -281,8 +441,39 @@ jj init --git-repo=.";
             repo,
         )
     }
+
+    /// The store of the repo the command loaded, if any.
+    pub fn loaded_store(&self) -> Option<Arc<Store>> {
+        self.loaded_store.lock().unwrap().clone()
+    }
+}
+
+/// Makes the user's settings fall back to the settings in the Git repo's
+/// config (see `git_config_fallback()`).
+pub fn use_git_config_fallback(
+    ui: &mut Ui,
+    git_repo: &git2::Repository,
+) -> Result<(), CommandError> {
+    if let Ok(git_config) = git_repo.config() {
+        let settings = ui
+            .settings()
+            .with_fallback(git_config_fallback(&git_config))
+            .map_err(|err| CommandError::ConfigError(err.to_string()))?;
+        ui.set_settings(settings);
+    }
+    Ok(())
 }
 
+/// Operation tag shared by all transactions committed by a single command
+/// invocation. Its value is the id of the operation the command was loaded at.
+/// `jj undo` uses it to undo all of a command's operations at once.
+pub const COMMAND_GROUP_TAG: &str = "command-group";
+
+/// Operation tag of the working-copy snapshot taken by a command. It has the
+/// same value as the command's `COMMAND_GROUP_TAG`, but is kept separate so
+/// `jj undo` only undoes the snapshot when asked to.
+pub const SNAPSHOT_GROUP_TAG: &str = "snapshot-group";
+
 // Provides utilities for writing a command that works on a workspace (like most
 // commands do).
 pub struct WorkspaceCommandHelper {
@@ -294,16 +485,33 @@ pub struct WorkspaceCommandHelper {
     repo: Arc<ReadonlyRepo>,
     may_update_working_copy: bool,
     working_copy_shared_with_git: bool,
+    command_group: String,
+    working_copy_snapshotted: bool,
 }
 
 impl WorkspaceCommandHelper {
     pub fn for_loaded_repo(
         ui: &mut Ui,
-        workspace: Workspace,
+        mut workspace: Workspace,
         string_args: Vec<String>,
         global_args: &GlobalArgs,
         repo: Arc<ReadonlyRepo>,
     ) -> Result<Self, CommandError> {
+        if let Some(git_repo) = repo.store().git_repo() {
+            use_git_config_fallback(ui, &git_repo)?;
+        }
+        // The store ignores invalid merge attributes and the working copy an
+        // invalid line-ending policy, so report them here
+        repo.settings().merge_attributes()?;
+        ui.settings().line_ending_policy()?;
+        let content_filters =
+            ContentFilters::from_settings(ui.settings(), workspace.workspace_root())?;
+        workspace
+            .working_copy_mut()
+            .set_content_filters(content_filters.map(Arc::new));
+        workspace
+            .working_copy_mut()
+            .set_discard_changes(global_args.discard_changes);
         let loaded_at_head = &global_args.at_operation == "@";
         let may_update_working_copy = loaded_at_head && !global_args.no_commit_working_copy;
         let mut working_copy_shared_with_git = false;
@@ -315,6 +523,7 @@ impl WorkspaceCommandHelper {
         {
             working_copy_shared_with_git = git_workdir == workspace.workspace_root().as_path();
         }
+        let command_group = repo.op_id().hex();
         let mut helper = Self {
             cwd: ui.cwd().to_owned(),
             string_args,
@@ -324,16 +533,67 @@ impl WorkspaceCommandHelper {
             repo,
             may_update_working_copy,
             working_copy_shared_with_git,
+            command_group,
+            working_copy_snapshotted: false,
         };
-        if may_update_working_copy {
-            if working_copy_shared_with_git {
-                helper.import_git_refs_and_head(ui, maybe_git_repo.as_ref().unwrap())?;
-            }
-            helper.commit_working_copy(ui)?;
+        if may_update_working_copy && ui.settings().auto_snapshot() {
+            helper.snapshot_working_copy(ui)?;
         }
         Ok(helper)
     }
 
+    /// Imports refs from a colocated Git repo and snapshots the working copy,
+    /// unless that has already been done by this command.
+    pub fn snapshot_working_copy(&mut self, ui: &mut Ui) -> Result<(), CommandError> {
+        self.check_working_copy_writable()?;
+        if self.working_copy_snapshotted {
+            return Ok(());
+        }
+        if self.working_copy_shared_with_git {
+            let git_repo = self.repo.store().git_repo().unwrap();
+            self.import_git_refs_and_head(ui, &git_repo)?;
+        }
+        self.commit_working_copy(ui)
+    }
+
+    /// Fails if the working copy has changes that updating it would overwrite,
+    /// listing the changed files. Only needed when the working copy wasn't
+    /// snapshotted when the command started (see `snapshot.auto`).
+    fn check_no_unsnapshotted_changes(&mut self) -> Result<(), CommandError> {
+        if self.global_args.discard_changes {
+            return Ok(());
+        }
+        let base_ignores = self.base_ignores();
+        let mut locked_wc = self.workspace.working_copy_mut().start_mutation();
+        let old_tree_id = locked_wc.old_tree_id().clone();
+        let result = locked_wc.snapshot(base_ignores);
+        locked_wc.discard();
+        let new_tree_id = result?;
+        if new_tree_id != old_tree_id {
+            let store = self.repo.store();
+            let old_tree = store.get_tree(&RepoPath::root(), &old_tree_id)?;
+            let new_tree = store.get_tree(&RepoPath::root(), &new_tree_id)?;
+            let paths = old_tree
+                .diff(&new_tree, &EverythingMatcher)
+                .map(|(path, _diff)| path)
+                .collect_vec();
+            return Err(CommandError::UserError(format!(
+                "The working copy has changes that haven't been snapshotted (snapshot.auto is \
+                 disabled):\n{}Run `jj snapshot` first, or use --discard-changes to overwrite \
+                 them.",
+                self.format_file_list(&paths)
+            )));
+        }
+        Ok(())
+    }
+
+    fn format_file_list(&self, paths: &[RepoPath]) -> String {
+        paths
+            .iter()
+            .map(|path| format!("  {}\n", self.format_file_path(path)))
+            .collect()
+    }
+
     fn check_working_copy_writable(&self) -> Result<(), CommandError> {
         if self.may_update_working_copy {
             Ok(())
@@ -382,7 +642,8 @@ impl WorkspaceCommandHelper {
                 locked_working_copy.reset(&new_checkout.tree())?;
                 tx.mut_repo().rebase_descendants(&self.settings)?;
                 self.repo = tx.commit();
-                locked_working_copy.finish(self.repo.op_id().clone());
+                locked_working_copy.finish(self.repo.op_id().clone())?;
+                self.update_state_summary(ui)?;
             } else {
                 let num_rebased = tx.mut_repo().rebase_descendants(ui.settings())?;
                 if num_rebased > 0 {
@@ -492,19 +753,40 @@ impl WorkspaceCommandHelper {
         }
     }
 
-    pub fn base_ignores(&self) -> Arc<GitIgnoreFile> {
-        let mut git_ignores = GitIgnoreFile::empty();
-        if let Ok(excludes_file_str) = self
+    /// The user's global ignore file: `snapshot.excludes-file` from our
+    /// config, or else Git's `core.excludesFile`, or else Git's default of
+    /// `$XDG_CONFIG_HOME/git/ignore`.
+    fn global_excludes_file(&self) -> Option<PathBuf> {
+        if let Ok(path_str) = self.settings.config().get_string("snapshot.excludes-file") {
+            return Some(expand_git_path(path_str));
+        }
+        if let Ok(path_str) = self
             .git_config()
             .and_then(|git_config| git_config.get_string("core.excludesFile"))
         {
-            let excludes_file_path = expand_git_path(excludes_file_str);
+            return Some(expand_git_path(path_str));
+        }
+        match std::env::var("XDG_CONFIG_HOME") {
+            Ok(config_home) if !config_home.is_empty() => Some(PathBuf::from(config_home)),
+            _ => std::env::var("HOME")
+                .ok()
+                .map(|home_dir| PathBuf::from(home_dir).join(".config")),
+        }
+        .map(|config_dir| config_dir.join("git").join("ignore"))
+    }
+
+    pub fn base_ignores(&self) -> Arc<GitIgnoreFile> {
+        let mut git_ignores = GitIgnoreFile::empty();
+        if let Some(excludes_file_path) = self.global_excludes_file() {
             git_ignores = git_ignores.chain_with_file("", excludes_file_path);
         }
         if let Some(git_repo) = self.repo.store().git_repo() {
             git_ignores =
                 git_ignores.chain_with_file("", git_repo.path().join("info").join("exclude"));
         }
+        // Ignores that only apply to this workspace
+        git_ignores =
+            git_ignores.chain_with_file("", self.workspace_root().join(".jj").join("gitignore"));
         git_ignores
     }
 
@@ -520,9 +802,7 @@ impl WorkspaceCommandHelper {
     }
 
     pub fn resolve_single_rev(&self, revision_str: &str) -> Result<Commit, CommandError> {
-        let revset_expression = revset::parse(revision_str)?;
-        let revset =
-            revset_expression.evaluate(self.repo.as_repo_ref(), Some(&self.workspace_id()))?;
+        let revset = self.evaluate_revset(revision_str)?;
         let mut iter = revset.iter().commits(self.repo.store());
         match iter.next() {
             None => Err(CommandError::UserError(format!(
@@ -543,9 +823,7 @@ impl WorkspaceCommandHelper {
     }
 
     pub fn resolve_revset(&self, revision_str: &str) -> Result<Vec<Commit>, CommandError> {
-        let revset_expression = revset::parse(revision_str)?;
-        let revset =
-            revset_expression.evaluate(self.repo.as_repo_ref(), Some(&self.workspace_id()))?;
+        let revset = self.evaluate_revset(revision_str)?;
         Ok(revset
             .iter()
             .commits(self.repo.store())
@@ -553,6 +831,29 @@ impl WorkspaceCommandHelper {
             .collect())
     }
 
+    /// Parses and evaluates the revset. Errors point at the part of
+    /// `revision_str` they're about. Fails if the revset has more commits than
+    /// `limits.revset-commits`.
+    pub fn evaluate_revset(
+        &self,
+        revision_str: &str,
+    ) -> Result<Box<dyn Revset<'_> + '_>, CommandError> {
+        let revset_expression = revset::parse(revision_str)?;
+        let revset = revset_expression
+            .evaluate(self.repo.as_repo_ref(), Some(&self.workspace_id()))
+            .map_err(|err| revset_resolution_error(revision_str, err))?;
+        // Check the size before any commits are loaded
+        if let Some(limit) = self.settings.max_revset_commits() {
+            if revset.iter().nth(limit).is_some() {
+                return Err(CommandError::UserError(format!(
+                    "Revset \"{revision_str}\" resolved to more than {limit} commits \
+                     (`limits.revset-commits`). Use a narrower revset, or raise the limit."
+                )));
+            }
+        }
+        Ok(revset)
+    }
+
     pub fn check_rewriteable(&self, commit: &Commit) -> Result<(), CommandError> {
         if commit.id() == self.repo.store().root_commit_id() {
             return Err(CommandError::UserError(
@@ -580,8 +881,21 @@ impl WorkspaceCommandHelper {
                 return Ok(());
             }
         };
+        let notifier = self.event_notifier()?;
+        let mut payload = Map::new();
+        payload.insert("commit_id".to_string(), json!(wc_commit_id.hex()));
+        notifier.notify_pre(ui, "pre-snapshot", payload)?;
         let base_ignores = self.base_ignores();
+        // Scan the working copy before taking the lock, so other commands don't have
+        // to wait for that.
+        self.workspace
+            .working_copy_mut()
+            .prescan(base_ignores.clone());
+        let workspace_root = self.workspace_root().clone();
         let mut locked_wc = self.workspace.working_copy_mut().start_mutation();
+        if locked_wc.pending_checkout().is_some() {
+            ui.write_warn("Recovering from an interrupted update of the working copy\n")?;
+        }
         // Check if the working copy commit matches the repo's view. It's fine if it
         // doesn't, but we'll need to reload the repo so the new commit is
         // in the index and view, and so we don't cause unnecessary
@@ -622,13 +936,10 @@ impl WorkspaceCommandHelper {
                         short_operation_hash(wc_operation.id()),
                         short_operation_hash(repo_operation.id()),
                     )?;
-                    locked_wc.check_out(&wc_commit.tree()).map_err(|err| {
-                        CommandError::InternalError(format!(
-                            "Failed to check out commit {}: {}",
-                            wc_commit.id().hex(),
-                            err
-                        ))
-                    })?;
+                    if let Err(err) = locked_wc.check_out(&wc_commit.tree()) {
+                        locked_wc.discard();
+                        return Err(checkout_error(ui, &workspace_root, &wc_commit, err));
+                    }
                 } else {
                     return Err(CommandError::InternalError(format!(
                         "The repo was loaded at operation {}, which seems to be a sibling of the \
@@ -646,9 +957,63 @@ impl WorkspaceCommandHelper {
                 )));
             }
         }
-        let new_tree_id = locked_wc.snapshot(base_ignores)?;
-        if new_tree_id != *wc_commit.tree_id() {
+        let new_tree_id = match locked_wc.snapshot(base_ignores) {
+            Ok(new_tree_id) => new_tree_id,
+            Err(SnapshotError::TooManyFiles { count, limit }) => {
+                locked_wc.discard();
+                return Err(CommandError::UserError(format!(
+                    "Failed to snapshot the working copy because it has {count} new or modified \
+                     files, more than the limit of {limit} (`limits.snapshot-files`). Add the \
+                     files that shouldn't be tracked to `.gitignore` or `snapshot.never-track`, \
+                     or raise the limit."
+                )));
+            }
+            Err(SnapshotError::LineEndingChanges { paths }) => {
+                locked_wc.discard();
+                return Err(CommandError::UserError(format!(
+                    "Failed to snapshot the working copy because the line endings or byte-order \
+                     mark of these files changed:\n{}Restore them, or set \
+                     `snapshot.line-endings` to \"warn\" or \"normalize\".",
+                    self.format_file_list(&paths)
+                )));
+            }
+            Err(err) => {
+                locked_wc.discard();
+                return Err(err.into());
+            }
+        };
+        let line_ending_changes = locked_wc.line_ending_changes();
+        if !line_ending_changes.is_empty() {
+            let normalized =
+                ui.settings().line_ending_policy().ok() == Some(LineEndingPolicy::Normalize);
+            ui.write_warn(if normalized {
+                "Kept the previous line endings or byte-order mark of these files:\n"
+            } else {
+                "The line endings or byte-order mark of these files changed:\n"
+            })?;
+            for (path, changes) in line_ending_changes {
+                let path = relative_path(ui.cwd(), &path.to_fs_path(&workspace_root));
+                ui.write_warn(format!(
+                    "  {}: {}\n",
+                    path.display(),
+                    changes.iter().join(", ")
+                ))?;
+            }
+            if !normalized {
+                ui.write_hint(
+                    "Hint: This is often caused by a misconfigured editor. Set \
+                     `snapshot.line-endings` to \"normalize\" to keep the previous ones, or to \
+                     \"block\" to refuse such snapshots.\n",
+                )?;
+            }
+        }
+        let changed = new_tree_id != *wc_commit.tree_id();
+        let mut payload = Map::new();
+        payload.insert("tree_id".to_string(), json!(new_tree_id.hex()));
+        payload.insert("changed".to_string(), json!(changed));
+        if changed {
             let mut tx = self.repo.start_transaction("commit working copy");
+            tx.set_tag(SNAPSHOT_GROUP_TAG.to_string(), self.command_group.clone());
             let mut_repo = tx.mut_repo();
             let commit = CommitBuilder::for_rewrite_from(&self.settings, &wc_commit)
                 .set_tree(new_tree_id)
@@ -667,16 +1032,33 @@ impl WorkspaceCommandHelper {
 
             self.repo = tx.commit();
         }
-        locked_wc.finish(self.repo.op_id().clone());
+        locked_wc.finish(self.repo.op_id().clone())?;
+        self.working_copy_snapshotted = true;
+        if changed {
+            self.update_state_summary(ui)?;
+        }
+        if let Some(wc_commit_id) = self.repo.view().get_wc_commit_id(&self.workspace_id()) {
+            payload.insert("commit_id".to_string(), json!(wc_commit_id.hex()));
+        }
+        notifier.notify(ui, "post-snapshot", payload)?;
         Ok(())
     }
 
+    pub fn event_notifier(&self) -> Result<EventNotifier, CommandError> {
+        EventNotifier::new(
+            &self.settings,
+            self.workspace.workspace_root(),
+            &self.workspace_id(),
+        )
+    }
+
     pub fn edit_diff(
         &self,
         ui: &mut Ui,
         left_tree: &Tree,
         right_tree: &Tree,
         instructions: &str,
+        matcher: &dyn Matcher,
     ) -> Result<TreeId, DiffEditError> {
         crate::diff_edit::edit_diff(
             ui,
@@ -684,6 +1066,7 @@ impl WorkspaceCommandHelper {
             left_tree,
             right_tree,
             instructions,
+            matcher,
             self.base_ignores(),
         )
     }
@@ -704,6 +1087,7 @@ impl WorkspaceCommandHelper {
                 left_tree,
                 right_tree,
                 instructions,
+                matcher,
                 self.base_ignores(),
             )?)
         } else if matcher.visit(&RepoPath::root()) == Visit::AllRecursively {
@@ -711,16 +1095,7 @@ impl WorkspaceCommandHelper {
             Ok(right_tree.id().clone())
         } else {
             let mut tree_builder = self.repo().store().tree_builder(left_tree.id().clone());
-            for (repo_path, diff) in left_tree.diff(right_tree, matcher) {
-                match diff.into_options().1 {
-                    Some(value) => {
-                        tree_builder.set(repo_path, value);
-                    }
-                    None => {
-                        tree_builder.remove(repo_path);
-                    }
-                }
-            }
+            tree_builder.graft(right_tree, matcher);
             Ok(tree_builder.write_tree())
         }
     }
@@ -751,6 +1126,13 @@ impl WorkspaceCommandHelper {
         };
         let quoted_strings = self.string_args.iter().map(shell_escape).collect_vec();
         tx.set_tag("args".to_string(), quoted_strings.join(" "));
+        tx.set_tag(COMMAND_GROUP_TAG.to_string(), self.command_group.clone());
+        for (key, value) in self.settings.operation_tags() {
+            tx.set_tag(key, value);
+        }
+        for (key, value) in &self.global_args.op_tags {
+            tx.set_tag(key.clone(), value.clone());
+        }
         tx
     }
 
@@ -765,19 +1147,46 @@ impl WorkspaceCommandHelper {
             writeln!(ui, "Nothing changed.")?;
             return Ok(());
         }
+        if self.may_update_working_copy
+            && !self.working_copy_snapshotted
+            && mut_repo.view().get_wc_commit_id(&self.workspace_id())
+                != mut_repo
+                    .base_repo()
+                    .view()
+                    .get_wc_commit_id(&self.workspace_id())
+        {
+            self.check_no_unsnapshotted_changes()?;
+        }
         let num_rebased = mut_repo.rebase_descendants(ui.settings())?;
         if num_rebased > 0 {
             writeln!(ui, "Rebased {} descendant commits", num_rebased)?;
         }
-        if self.working_copy_shared_with_git {
+        // The Git HEAD follows the working copy, so it's left alone when the working copy
+        // isn't updated (e.g. when loaded at an earlier operation)
+        if self.working_copy_shared_with_git && self.may_update_working_copy {
             self.export_head_to_git(mut_repo)?;
         }
+        let hooks = Hook::all_from_settings(ui.settings())?;
+        tx.run_hooks(&hooks)?;
         let maybe_old_commit = tx
             .base_repo()
             .view()
             .get_wc_commit_id(&self.workspace_id())
             .map(|commit_id| store.get_commit(commit_id))
             .transpose()?;
+        let notifier = self.event_notifier()?;
+        // Ask before committing, so a rejected checkout leaves the repo unchanged
+        if self.may_update_working_copy {
+            if let Some(new_commit_id) = tx.mut_repo().view().get_wc_commit_id(&self.workspace_id())
+            {
+                let new_commit = store.get_commit(new_commit_id)?;
+                if Some(new_commit.tree_id()) != maybe_old_commit.as_ref().map(Commit::tree_id) {
+                    let payload = checkout_event_payload(maybe_old_commit.as_ref(), &new_commit);
+                    notifier.notify_pre(ui, "pre-checkout", payload)?;
+                }
+            }
+        }
+        let old_view = tx.base_repo().view().clone();
         self.repo = tx.commit();
         if self.may_update_working_copy {
             let stats = update_working_copy(
@@ -786,17 +1195,244 @@ impl WorkspaceCommandHelper {
                 &self.workspace_id(),
                 self.workspace.working_copy_mut(),
                 maybe_old_commit.as_ref(),
+                &notifier,
             )?;
             if let Some(stats) = stats {
                 print_checkout_stats(ui, stats)?;
             }
         }
+        notifier.notify_branches_moved(ui, &old_view, self.repo.view())?;
         if self.working_copy_shared_with_git {
             let git_repo = self.repo.store().git_repo().unwrap();
             git::export_refs(&self.repo, &git_repo)?;
         }
+        self.update_state_summary(ui)?;
+        let auto_pack_threshold = self.repo.settings().auto_pack_threshold();
+        if auto_pack_threshold > 0 {
+            self.repo.store().pack_loose_objects(auto_pack_threshold)?;
+        }
         Ok(())
     }
+
+    /// Writes `.jj/state-summary.json` if `ui.state-summary` is enabled.
+    /// Failures are only reported as warnings, since the transaction has
+    /// already been committed.
+    fn update_state_summary(&self, ui: &mut Ui) -> Result<(), CommandError> {
+        if !ui.settings().write_state_summary() {
+            return Ok(());
+        }
+        if let Err(err) =
+            write_state_summary(&self.repo, &self.workspace_id(), self.workspace_root())
+        {
+            ui.write_warn(format!("Failed to write the state summary: {err}\n"))?;
+        }
+        Ok(())
+    }
+
+    /// Calls `preview_transaction()` if `dry_run` is set and
+    /// `finish_transaction()` otherwise.
+    pub fn finish_transaction_or_preview(
+        &mut self,
+        ui: &mut Ui,
+        tx: Transaction,
+        dry_run: bool,
+    ) -> Result<(), CommandError> {
+        if dry_run {
+            self.preview_transaction(ui, tx)
+        } else {
+            self.finish_transaction(ui, tx)
+        }
+    }
+
+    /// Like `finish_transaction_or_preview()`, but when not doing a dry run in
+    /// a terminal, first prints what the transaction would change and asks
+    /// for confirmation (unless `ui.confirm-undo` is disabled). Used by the
+    /// commands that restore or undo operations.
+    pub fn finish_transaction_after_confirmation(
+        &mut self,
+        ui: &mut Ui,
+        mut tx: Transaction,
+        dry_run: bool,
+    ) -> Result<(), CommandError> {
+        if dry_run || !ui.is_interactive() || !ui.settings().confirm_undo() {
+            return self.finish_transaction_or_preview(ui, tx, dry_run);
+        }
+        if !self.print_transaction_preview(ui, &mut tx)? {
+            return Ok(());
+        }
+        if ui.prompt_yes_no("Proceed?")? {
+            self.finish_transaction(ui, tx)
+        } else {
+            writeln!(ui, "Aborted, not committing the transaction.")?;
+            Ok(())
+        }
+    }
+
+    /// Like `finish_transaction()`, but prints what the transaction would
+    /// change instead of committing it. Used by commands' `--dry-run` flag.
+    pub fn preview_transaction(
+        &mut self,
+        ui: &mut Ui,
+        mut tx: Transaction,
+    ) -> Result<(), CommandError> {
+        if self.print_transaction_preview(ui, &mut tx)? {
+            writeln!(ui, "Dry-run requested, not committing the transaction.")?;
+        }
+        Ok(())
+    }
+
+    /// Prints the commits, branches and working copies the transaction would
+    /// change. Returns false (after saying so) if it doesn't change anything.
+    fn print_transaction_preview(
+        &mut self,
+        ui: &mut Ui,
+        tx: &mut Transaction,
+    ) -> Result<bool, CommandError> {
+        let mut_repo = tx.mut_repo();
+        let store = mut_repo.store().clone();
+        if !mut_repo.has_changes() {
+            writeln!(ui, "Nothing changed.")?;
+            return Ok(false);
+        }
+        let num_rebased = mut_repo.rebase_descendants(ui.settings())?;
+        if num_rebased > 0 {
+            writeln!(ui, "Would rebase {} descendant commits", num_rebased)?;
+        }
+        let base_repo = mut_repo.base_repo().clone();
+        let old_heads = base_repo.view().heads().iter().cloned().collect_vec();
+        let new_heads = mut_repo.view().heads().iter().cloned().collect_vec();
+        let index = mut_repo.index();
+        for (label, wanted, unwanted) in [
+            ("hide", &old_heads, &new_heads),
+            ("add", &new_heads, &old_heads),
+        ] {
+            let commit_ids = index
+                .walk_revs(wanted, unwanted)
+                .map(|entry| entry.commit_id())
+                .collect_vec();
+            if !commit_ids.is_empty() {
+                writeln!(ui, "Would {} {} commits:", label, commit_ids.len())?;
+                for commit_id in commit_ids {
+                    let commit = store.get_commit(&commit_id)?;
+                    writeln!(ui, "  {}", short_commit_description(&commit))?;
+                }
+            }
+        }
+
+        let format_target = |target: &RefTarget| match target {
+            RefTarget::Normal(id) => short_commit_hash(id),
+            RefTarget::Conflict { adds, .. } => {
+                format!(
+                    "a conflict between {}",
+                    adds.iter().map(short_commit_hash).join(", ")
+                )
+            }
+        };
+        let old_branches = base_repo.view().branches();
+        let new_branches = mut_repo.view().branches();
+        for name in old_branches
+            .keys()
+            .chain(new_branches.keys())
+            .sorted()
+            .dedup()
+        {
+            let old_target = old_branches.get(name).and_then(|t| t.local_target.as_ref());
+            let new_target = new_branches.get(name).and_then(|t| t.local_target.as_ref());
+            match (old_target, new_target) {
+                (Some(old_target), Some(new_target)) if old_target != new_target => {
+                    writeln!(
+                        ui,
+                        "Would move branch {} from {} to {}",
+                        name,
+                        format_target(old_target),
+                        format_target(new_target)
+                    )?;
+                }
+                (Some(old_target), None) => {
+                    writeln!(
+                        ui,
+                        "Would delete branch {} from {}",
+                        name,
+                        format_target(old_target)
+                    )?;
+                }
+                (None, Some(new_target)) => {
+                    writeln!(
+                        ui,
+                        "Would add branch {} to {}",
+                        name,
+                        format_target(new_target)
+                    )?;
+                }
+                _ => {}
+            }
+        }
+
+        let workspace_id = self.workspace_id();
+        let old_wc_commit_ids = base_repo.view().wc_commit_ids();
+        let new_wc_commit_ids = mut_repo.view().wc_commit_ids();
+        for other_workspace_id in old_wc_commit_ids
+            .keys()
+            .chain(new_wc_commit_ids.keys())
+            .filter(|id| **id != workspace_id)
+            .sorted()
+            .dedup()
+        {
+            let name = other_workspace_id.as_str();
+            match (
+                old_wc_commit_ids.get(other_workspace_id),
+                new_wc_commit_ids.get(other_workspace_id),
+            ) {
+                (Some(old_id), Some(new_id)) if old_id != new_id => {
+                    writeln!(
+                        ui,
+                        "Would move the working copy of workspace {} from {} to {}",
+                        name,
+                        short_commit_hash(old_id),
+                        short_commit_hash(new_id)
+                    )?;
+                }
+                (Some(old_id), None) => {
+                    writeln!(
+                        ui,
+                        "Would forget workspace {} (at {})",
+                        name,
+                        short_commit_hash(old_id)
+                    )?;
+                }
+                (None, Some(new_id)) => {
+                    writeln!(
+                        ui,
+                        "Would add workspace {} at {}",
+                        name,
+                        short_commit_hash(new_id)
+                    )?;
+                }
+                _ => {}
+            }
+        }
+
+        let old_wc_commit_id = base_repo.view().get_wc_commit_id(&workspace_id);
+        let new_wc_commit_id = mut_repo.view().get_wc_commit_id(&workspace_id);
+        if let (Some(old_wc_commit_id), Some(new_wc_commit_id)) =
+            (old_wc_commit_id, new_wc_commit_id)
+        {
+            let old_tree = store.get_commit(old_wc_commit_id)?.tree();
+            let new_tree = store.get_commit(new_wc_commit_id)?.tree();
+            let summary = old_tree.diff_summary(&new_tree, &EverythingMatcher);
+            if !summary.is_empty() {
+                writeln!(
+                    ui,
+                    "Would update the working copy: added {} files, modified {} files, removed \
+                     {} files",
+                    summary.added.len(),
+                    summary.modified.len(),
+                    summary.removed.len()
+                )?;
+            }
+        }
+        Ok(true)
+    }
 }
 
 pub fn print_checkout_stats(ui: &mut Ui, stats: CheckoutStats) -> Result<(), std::io::Error> {
@@ -807,6 +1443,20 @@ pub fn print_checkout_stats(ui: &mut Ui, stats: CheckoutStats) -> Result<(), std
             stats.added_files, stats.updated_files, stats.removed_files
         )?;
     }
+    for backup_path in &stats.backed_up_files {
+        let backup_path = relative_path(ui.cwd(), backup_path);
+        writeln!(
+            ui,
+            "Moved untracked file that was in the way to {}",
+            backup_path.display()
+        )?;
+    }
+    for (path, issue) in &stats.skipped_files {
+        ui.write_warn(format!(
+            "Skipped checking out {}: {issue}\n",
+            path.to_internal_file_string()
+        ))?;
+    }
     Ok(())
 }
 
@@ -826,21 +1476,26 @@ fn resolve_op_for_load(
     op_str: &str,
 ) -> Result<OpHeads, CommandError> {
     if op_str == "@" {
-        Ok(op_heads_store.get_heads(op_store)?)
-    } else if op_str == "@-" {
+        return Ok(op_heads_store.get_heads(op_store)?);
+    }
+    let (base_str, num_parents) = split_op_parents(op_str);
+    let base_op = if base_str == "@" {
         match op_heads_store.get_heads(op_store)? {
-            OpHeads::Single(current_op) => {
-                let resolved_op = resolve_single_op(op_store, op_heads_store, &current_op, op_str)?;
-                Ok(OpHeads::Single(resolved_op))
+            OpHeads::Single(current_op) => current_op,
+            OpHeads::Unresolved { .. } => {
+                return Err(CommandError::UserError(format!(
+                    r#"The "{op_str}" expression resolved to more than one operation"#
+                )))
             }
-            OpHeads::Unresolved { .. } => Err(CommandError::UserError(format!(
-                r#"The "{op_str}" expression resolved to more than one operation"#
-            ))),
         }
     } else {
-        let operation = resolve_single_op_from_store(op_store, op_heads_store, op_str)?;
-        Ok(OpHeads::Single(operation))
-    }
+        resolve_single_op_from_store(op_store, op_heads_store, base_str)?
+    };
+    Ok(OpHeads::Single(resolve_op_parents(
+        base_op,
+        op_str,
+        num_parents,
+    )?))
 }
 
 fn resolve_single_op(
@@ -849,19 +1504,49 @@ fn resolve_single_op(
     current_op: &Operation,
     op_str: &str,
 ) -> Result<Operation, CommandError> {
-    if op_str == "@" {
-        Ok(current_op.clone())
-    } else if op_str == "@-" {
-        let parent_ops = current_op.parents();
-        if parent_ops.len() != 1 {
-            return Err(CommandError::UserError(format!(
-                r#"The "{op_str}" expression resolved to more than one operation"#
-            )));
-        }
-        Ok(parent_ops[0].clone())
+    let (base_str, num_parents) = split_op_parents(op_str);
+    let base_op = if base_str == "@" {
+        current_op.clone()
     } else {
-        resolve_single_op_from_store(op_store, op_heads_store, op_str)
+        resolve_single_op_from_store(op_store, op_heads_store, base_str)?
+    };
+    resolve_op_parents(base_op, op_str, num_parents)
+}
+
+/// Splits an operation expression like `@--` or `abc123-` into the base
+/// operation and the number of generations to go back from it.
+fn split_op_parents(op_str: &str) -> (&str, usize) {
+    let base_str = op_str.trim_end_matches('-');
+    if base_str == "@" || (!base_str.is_empty() && base_str.bytes().all(|b| b.is_ascii_hexdigit()))
+    {
+        (base_str, op_str.len() - base_str.len())
+    } else {
+        (op_str, 0)
+    }
+}
+
+fn resolve_op_parents(
+    mut operation: Operation,
+    op_str: &str,
+    num_parents: usize,
+) -> Result<Operation, CommandError> {
+    for _ in 0..num_parents {
+        let mut parent_ops = operation.parents();
+        match parent_ops.len() {
+            0 => {
+                return Err(CommandError::UserError(format!(
+                    r#"The "{op_str}" expression goes back further than the first operation"#
+                )))
+            }
+            1 => operation = parent_ops.pop().unwrap(),
+            _ => {
+                return Err(CommandError::UserError(format!(
+                    r#"The "{op_str}" expression resolved to more than one operation"#
+                )))
+            }
+        }
     }
+    Ok(operation)
 }
 
 fn find_all_operations(
@@ -882,12 +1567,35 @@ fn find_all_operations(
     operations
 }
 
+/// Finds the most recent operation that had finished at `timestamp`.
+fn resolve_op_at_time(
+    op_store: &Arc<dyn OpStore>,
+    op_heads_store: &Arc<OpHeadsStore>,
+    op_str: &str,
+    timestamp: &Timestamp,
+) -> Result<Operation, CommandError> {
+    find_all_operations(op_store, op_heads_store)
+        .into_iter()
+        .filter(|op| op.store_operation().metadata.end_time.timestamp <= timestamp.timestamp)
+        .max_by_key(|op| op.store_operation().metadata.end_time.timestamp.clone())
+        .ok_or_else(|| {
+            CommandError::UserError(format!(
+                "No operation had finished at \"{}\" ({})",
+                op_str,
+                templater::format_timestamp(timestamp)
+            ))
+        })
+}
+
 fn resolve_single_op_from_store(
     op_store: &Arc<dyn OpStore>,
     op_heads_store: &Arc<OpHeadsStore>,
     op_str: &str,
 ) -> Result<Operation, CommandError> {
     if op_str.is_empty() || !op_str.as_bytes().iter().all(|b| b.is_ascii_hexdigit()) {
+        if let Some(timestamp) = templater::parse_timestamp(op_str, &Timestamp::now()) {
+            return resolve_op_at_time(op_store, op_heads_store, op_str, &timestamp);
+        }
         return Err(CommandError::UserError(format!(
             "Operation ID \"{}\" is not a valid hexadecimal prefix",
             op_str
@@ -989,12 +1697,23 @@ pub fn matcher_from_values(
     }
 }
 
+fn checkout_event_payload(old_commit: Option<&Commit>, new_commit: &Commit) -> Map<String, Value> {
+    let mut payload = Map::new();
+    payload.insert(
+        "old_commit_id".to_string(),
+        json!(old_commit.map(|commit| commit.id().hex())),
+    );
+    payload.insert("new_commit_id".to_string(), json!(new_commit.id().hex()));
+    payload
+}
+
 fn update_working_copy(
     ui: &mut Ui,
     repo: &Arc<ReadonlyRepo>,
     workspace_id: &WorkspaceId,
     wc: &mut WorkingCopy,
     old_commit: Option<&Commit>,
+    notifier: &EventNotifier,
 ) -> Result<Option<CheckoutStats>, CommandError> {
     let new_commit_id = match repo.view().get_wc_commit_id(workspace_id) {
         Some(new_commit_id) => new_commit_id,
@@ -1006,6 +1725,8 @@ fn update_working_copy(
     let new_commit = repo.store().get_commit(new_commit_id)?;
     let old_tree_id = old_commit.map(|commit| commit.tree_id().clone());
     let stats = if Some(new_commit.tree_id()) != old_tree_id.as_ref() {
+        // The pre-checkout event was sent before the transaction was committed
+        let mut payload = checkout_event_payload(old_commit, &new_commit);
         // TODO: CheckoutError::ConcurrentCheckout should probably just result in a
         // warning for most commands (but be an error for the checkout command)
         let stats = wc
@@ -1014,18 +1735,14 @@ fn update_working_copy(
                 old_tree_id.as_ref(),
                 &new_commit.tree(),
             )
-            .map_err(|err| {
-                CommandError::InternalError(format!(
-                    "Failed to check out commit {}: {}",
-                    new_commit.id().hex(),
-                    err
-                ))
-            })?;
+            .map_err(|err| checkout_error(ui, wc.working_copy_path(), &new_commit, err))?;
+        events::checkout_stats_to_json(&stats, &mut payload);
+        notifier.notify(ui, "post-checkout", payload)?;
         Some(stats)
     } else {
         // Record new operation id which represents the latest working-copy state
         let locked_wc = wc.start_mutation();
-        locked_wc.finish(repo.op_id().clone());
+        locked_wc.finish(repo.op_id().clone())?;
         None
     };
     if Some(&new_commit) != old_commit {
@@ -1036,6 +1753,59 @@ fn update_working_copy(
     Ok(stats)
 }
 
+pub fn checkout_error(
+    ui: &Ui,
+    workspace_root: &Path,
+    commit: &Commit,
+    err: CheckoutError,
+) -> CommandError {
+    match err {
+        CheckoutError::DirtyFiles { paths } => {
+            let file_list: String = paths
+                .iter()
+                .map(|path| {
+                    let path = relative_path(ui.cwd(), &path.to_fs_path(workspace_root));
+                    format!("  {}\n", path.display())
+                })
+                .collect();
+            CommandError::UserError(format!(
+                "Failed to check out commit {} because these files were modified since the last \
+                 snapshot:\n{file_list}Use --discard-changes to overwrite them.",
+                short_commit_hash(commit.id())
+            ))
+        }
+        err => CommandError::InternalError(format!(
+            "Failed to check out commit {}: {}",
+            commit.id().hex(),
+            err
+        )),
+    }
+}
+
+/// Warns about local branches that are conflicted in `new_repo` but weren't in
+/// `old_repo`, so concurrent branch updates don't go unnoticed.
+fn warn_new_branch_conflicts(
+    ui: &mut Ui,
+    old_repo: &ReadonlyRepo,
+    new_repo: &ReadonlyRepo,
+) -> io::Result<()> {
+    for (name, target) in new_repo.view().branches() {
+        let is_conflict =
+            |target: Option<&RefTarget>| matches!(target, Some(RefTarget::Conflict { .. }));
+        if is_conflict(target.local_target.as_ref())
+            && !is_conflict(old_repo.view().get_local_branch(name).as_ref())
+        {
+            ui.write_warn(format!(
+                "warning: Concurrent operations moved branch {} to different commits (use `jj \
+                 branch list` to see the targets and `jj branch set {} -r <rev> --resolve` to \
+                 pick one)\n",
+                name, name
+            ))?;
+        }
+    }
+    Ok(())
+}
+
 pub fn short_commit_description(commit: &Commit) -> String {
     let first_line = commit.description().split('\n').next().unwrap();
     format!("{} ({})", short_commit_hash(commit.id()), first_line)
@@ -1045,6 +1815,10 @@ pub fn short_commit_hash(commit_id: &CommitId) -> String {
     commit_id.hex()[0..12].to_string()
 }
 
+pub fn short_change_hash(change_id: &ChangeId) -> String {
+    change_id.hex()[0..12].to_string()
+}
+
 pub fn short_operation_hash(operation_id: &OperationId) -> String {
     operation_id.hex()[0..12].to_string()
 }
@@ -1087,6 +1861,15 @@ pub struct GlobalArgs {
     /// another process that commits the working copy.
     #[arg(long, global = true, help_heading = "Global Options")]
     pub no_commit_working_copy: bool,
+    /// Overwrite changes in the working copy that haven't been snapshotted
+    ///
+    /// Updating the working copy fails if it would overwrite or remove files
+    /// that were modified since the last snapshot, such as when the working
+    /// copy isn't snapshotted automatically (`snapshot.auto = false`) or a
+    /// file was modified while the command was running. This flag makes the
+    /// update discard those modifications instead.
+    #[arg(long, global = true, help_heading = "Global Options")]
+    pub discard_changes: bool,
     /// Operation to load the repo at
     ///
     /// Operation to load the repo at. By default, Jujutsu loads the repo at the
@@ -1096,10 +1879,19 @@ pub struct GlobalArgs {
     /// shown you when the given operation had just finished.
     ///
     /// Use `jj op log` to find the operation ID you want. Any unambiguous
-    /// prefix of the operation ID is enough.
+    /// prefix of the operation ID is enough. Each `-` after an operation ID or
+    /// `@` goes back one operation, so `--at-op=@--` is the operation before
+    /// the previous one.
+    ///
+    /// You can also give a point in time instead of an operation ID, e.g.
+    /// `--at-op=yesterday`, `--at-op="3 hours ago"` or
+    /// `--at-op="2022-10-01 12:00"`. The repo is then loaded at the most recent
+    /// operation that had finished at that time.
     ///
     /// When loading the repo at an earlier operation, the working copy will not
-    /// be automatically committed.
+    /// be automatically committed, and it won't be updated (nor will the Git
+    /// HEAD in a colocated repo) even if the command changes the working-copy
+    /// commit.
     ///
     /// It is possible to run mutating commands when loading the repo at an
     /// earlier operation. Doing that is equivalent to having run concurrent
@@ -1121,6 +1913,27 @@ pub struct GlobalArgs {
         help_heading = "Global Options"
     )]
     pub color: Option<ColorChoice>,
+    /// Record a `KEY=VALUE` tag in the metadata of the operations the command
+    /// creates (can be repeated)
+    ///
+    /// Tags are shown by `jj op log` and can be rendered with the `tag("KEY")`
+    /// template function. Tags from the `[operation.tags]` config are also
+    /// recorded.
+    #[arg(
+        long = "op-tag",
+        value_name = "KEY=VALUE",
+        value_parser = parse_op_tag,
+        global = true,
+        help_heading = "Global Options"
+    )]
+    pub op_tags: Vec<(String, String)>,
+}
+
+fn parse_op_tag(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, got '{}'", text)),
+    }
 }
 
 pub fn create_ui() -> (Ui<'static>, Result<(), CommandError>) {
@@ -1135,7 +1948,7 @@ pub fn create_ui() -> (Ui<'static>, Result<(), CommandError>) {
     }
 }
 
-fn string_list_from_config(value: config::Value) -> Option<Vec<String>> {
+pub fn string_list_from_config(value: config::Value) -> Option<Vec<String>> {
     match value {
         config::Value {
             kind: config::ValueKind::Array(elements),
@@ -1223,6 +2036,28 @@ fn resolve_aliases(
     }
 }
 
+/// Makes `commands.<name>.editor` and `commands.<name>.diff-editor` override
+/// `ui.editor` and `ui.diff-editor` while running the command `name`.
+fn apply_command_overrides(ui: &mut Ui, command_name: &str) -> Result<(), CommandError> {
+    let config = ui.settings().config();
+    let mut builder = config::Config::builder().add_source(config.clone());
+    let mut overridden = false;
+    for (key, ui_key) in [("editor", "ui.editor"), ("diff-editor", "ui.diff-editor")] {
+        match config.get::<config::Value>(&format!("commands.{command_name}.{key}")) {
+            Ok(value) => {
+                builder = builder.set_override(ui_key, value)?;
+                overridden = true;
+            }
+            Err(config::ConfigError::NotFound(_)) => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    if overridden {
+        ui.set_settings(UserSettings::from_config(builder.build()?));
+    }
+    Ok(())
+}
+
 pub fn parse_args(
     ui: &mut Ui,
     app: clap::Command,
@@ -1244,6 +2079,9 @@ pub fn parse_args(
         // Here we assume ui was created for_terminal().
         ui.reset_color_for_terminal(choice);
     }
+    if let Some(command_name) = matches.subcommand_name() {
+        apply_command_overrides(ui, command_name)?;
+    }
     let command_helper = CommandHelper::new(app, string_args, args.global_args);
     Ok((command_helper, matches))
 }
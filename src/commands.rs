@@ -12,56 +12,89 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{HashSet, VecDeque};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
 use std::fs::OpenOptions;
-use std::io::{Read, Seek, SeekFrom, Write};
+use std::io::{BufRead, Read, Seek, SeekFrom, Write};
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 use std::{fs, io};
 
-use chrono::{FixedOffset, TimeZone, Utc};
 use clap::{ArgGroup, ArgMatches, CommandFactory, FromArgMatches, Subcommand};
 use criterion::Criterion;
-use itertools::Itertools;
-use jujutsu_lib::backend::{BackendError, CommitId, Timestamp, TreeValue};
+use itertools::{EitherOrBoth, Itertools};
+use jujutsu_lib::backend::{
+    Backend, BackendError, ChangeId, CommitId, CopyRecord, FileId, Timestamp, TreeValue,
+};
+use jujutsu_lib::backend_migration::{migrate_backend, PendingMigration};
+use jujutsu_lib::change_identity::{change_ids_by_identity, find_change_id_drift, CommitIdentity};
 use jujutsu_lib::commit::Commit;
 use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::commit_metadata_cache::CommitMetadataCache;
+use jujutsu_lib::copies::{resolve_copies, CopiedPath, CopyOperation};
 use jujutsu_lib::dag_walk::topo_order_reverse;
 use jujutsu_lib::diff::{Diff, DiffHunk};
 use jujutsu_lib::files::DiffLine;
-use jujutsu_lib::git::{GitFetchError, GitRefUpdate};
-use jujutsu_lib::index::{HexPrefix, IndexEntry};
-use jujutsu_lib::matchers::{EverythingMatcher, Matcher};
-use jujutsu_lib::op_store::{RefTarget, WorkspaceId};
+use jujutsu_lib::git::{CommitSignatureStatus, GitFetchError, GitRefUpdate, SignedPush};
+use jujutsu_lib::git_backend::GitBackend;
+use jujutsu_lib::hg::{HgImportState, HgRepo};
+use jujutsu_lib::index::{HexPrefix, IndexEntry, IndexRef};
+use jujutsu_lib::local_backend::LocalBackend;
+use jujutsu_lib::lock::LockHolder;
+use jujutsu_lib::matchers::{EverythingMatcher, FilePattern, Matcher, PrefixMatcher};
+use jujutsu_lib::object_store::object_store_from_url;
+use jujutsu_lib::op_heads_store::OpHeads;
+use jujutsu_lib::op_store::{BranchTarget, OperationId, RefTarget, WorkspaceId};
 use jujutsu_lib::operation::Operation;
-use jujutsu_lib::refs::{classify_branch_push_action, BranchPushAction, BranchPushUpdate};
+use jujutsu_lib::reachability::ReachabilityBitmaps;
+use jujutsu_lib::refs::{
+    classify_branch_push_action, ref_divergence_bounded, BranchPushAction, BranchPushUpdate,
+    RefNamePattern,
+};
 use jujutsu_lib::repo::{ReadonlyRepo, RepoRef};
-use jujutsu_lib::repo_path::RepoPath;
-use jujutsu_lib::revset::RevsetExpression;
+use jujutsu_lib::repo_path::{RepoPath, RepoPathJoin};
+use jujutsu_lib::revset::{resolve_change_name, RevsetExpression};
 use jujutsu_lib::revset_graph_iterator::{RevsetGraphEdge, RevsetGraphEdgeType};
-use jujutsu_lib::rewrite::{back_out_commit, merge_commit_trees, rebase_commit, DescendantRebaser};
+use jujutsu_lib::rewrite::{
+    back_out_commit, merge_commit_trees, rebase_commit, restore_tree, DescendantRebaser,
+    TextReplacer,
+};
 use jujutsu_lib::settings::UserSettings;
-use jujutsu_lib::store::Store;
-use jujutsu_lib::tree::{merge_trees, Tree, TreeDiffIterator};
+use jujutsu_lib::store::{Store, StoreCacheStats, StoreFetchStats};
+use jujutsu_lib::trailers::{add_co_author_trailers, parse_co_author};
+use jujutsu_lib::transaction::Transaction;
+use jujutsu_lib::tree::{merge_trees, simplify_conflicts, Tree, TreeDiffIterator};
 use jujutsu_lib::view::View;
+use jujutsu_lib::working_copy::{FinishRecovery, WorkingCopy};
 use jujutsu_lib::workspace::Workspace;
-use jujutsu_lib::{conflicts, diff, files, git, revset, tree};
+use jujutsu_lib::workspace_cache::WorkspaceCache;
+use jujutsu_lib::{diff, fast_import, files, gerrit, git, hg, lock, revset, tree};
 use maplit::{hashmap, hashset};
 use pest::Parser;
+use serde_json::json;
 
+use crate::api_server::ApiServer;
+use crate::archive::{write_archive, ArchiveFormat};
 use crate::cli_util::{
-    matcher_from_values, print_checkout_stats, repo_paths_from_values, resolve_base_revs,
-    short_commit_description, short_commit_hash, Args, CommandError, CommandHelper,
-    WorkspaceCommandHelper,
+    checkout_error, matcher_from_values, print_checkout_stats, repo_paths_from_values,
+    resolve_base_revs, short_change_hash, short_commit_description, short_commit_hash,
+    short_operation_hash, string_list_from_config, use_git_config_fallback, Args, CommandError,
+    CommandHelper, WorkspaceCommandHelper, COMMAND_GROUP_TAG, SNAPSHOT_GROUP_TAG,
 };
 use crate::commands::CommandError::UserError;
-use crate::formatter::Formatter;
+use crate::diff_edit::interpolate_paths;
+use crate::diff_util::{
+    conflict_content, count_changed_lines, diff_content, diff_stats, ConflictStyle,
+};
+use crate::email;
+use crate::forge::{Forge, PullRequestState};
+use crate::formatter::{Formatter, PlainTextFormatter};
 use crate::graphlog::{AsciiGraphDrawer, Edge};
+use crate::progress::{format_bytes, Progress};
 use crate::template_parser::TemplateParser;
-use crate::templater::Template;
+use crate::templater::{self, BranchContext, Template};
 use crate::ui;
 use crate::ui::Ui;
 
@@ -71,12 +104,17 @@ enum Commands {
     Init(InitArgs),
     Checkout(CheckoutArgs),
     Untrack(UntrackArgs),
+    Mv(MvArgs),
+    Cp(CpArgs),
+    Snapshot(SnapshotArgs),
     Files(FilesArgs),
     Print(PrintArgs),
+    Archive(ArchiveArgs),
     Diff(DiffArgs),
     Show(ShowArgs),
     Status(StatusArgs),
     Log(LogArgs),
+    Summary(SummaryArgs),
     Obslog(ObslogArgs),
     Interdiff(InterdiffArgs),
     Describe(DescribeArgs),
@@ -84,6 +122,8 @@ enum Commands {
     Open(OpenArgs),
     Duplicate(DuplicateArgs),
     Abandon(AbandonArgs),
+    Unhide(UnhideArgs),
+    ResolveDivergence(ResolveDivergenceArgs),
     Edit(EditArgs),
     New(NewArgs),
     Move(MoveArgs),
@@ -92,20 +132,15 @@ enum Commands {
     Restore(RestoreArgs),
     Touchup(TouchupArgs),
     Split(SplitArgs),
-    /// Merge work from multiple branches
-    ///
-    /// Unlike most other VCSs, `jj merge` does not implicitly include the
-    /// working copy revision's parent as one of the parents of the merge;
-    /// you need to explicitly list all revisions that should become parents
-    /// of the merge.
-    ///
-    /// This is the same as `jj new`, except that it requires at least two
-    /// arguments.
-    Merge(NewArgs),
+    Merge(MergeArgs),
     Rebase(RebaseArgs),
     Backout(BackoutArgs),
     #[command(subcommand)]
     Branch(BranchSubcommand),
+    #[command(subcommand)]
+    Name(NameSubcommand),
+    #[command(subcommand)]
+    Topic(TopicSubcommand),
     /// Undo an operation (shortcut for `jj op undo`)
     Undo(OperationUndoArgs),
     #[command(subcommand)]
@@ -114,9 +149,22 @@ enum Commands {
     #[command(subcommand)]
     Workspace(WorkspaceCommands),
     Sparse(SparseArgs),
+    Run(RunArgs),
+    Fix(FixArgs),
+    Rewrite(RewriteArgs),
+    Verify(VerifyArgs),
+    Stats(StatsArgs),
     #[command(subcommand)]
     Git(GitCommands),
     #[command(subcommand)]
+    Gerrit(GerritCommands),
+    #[command(subcommand)]
+    Pr(PrCommands),
+    Browse(BrowseArgs),
+    Send(SendArgs),
+    Import(ImportArgs),
+    Api(ApiArgs),
+    #[command(subcommand)]
     Bench(BenchCommands),
     #[command(subcommand)]
     Debug(DebugCommands),
@@ -131,7 +179,7 @@ struct VersionArgs {}
 /// If the given directory does not exist, it will be created. If no directory
 /// is given, the current directory is used.
 #[derive(clap::Args, Clone, Debug)]
-#[command(group(ArgGroup::new("backend").args(&["git", "git_repo"])))]
+#[command(group(ArgGroup::new("backend").args(&["git", "git_repo", "remote_store"])))]
 struct InitArgs {
     /// The destination directory
     #[arg(default_value = ".", value_hint = clap::ValueHint::DirPath)]
@@ -142,6 +190,16 @@ struct InitArgs {
     /// Path to a git repo the jj repo will be backed by
     #[arg(long, value_hint = clap::ValueHint::DirPath)]
     git_repo: Option<String>,
+    /// URL of an object store to keep the repo's objects in, so they can be
+    /// shared with other repos using the same store
+    ///
+    /// The URL can be a `file://` URL (a directory, e.g. on a shared
+    /// filesystem) or an `http://` URL of a server that stores the body of
+    /// `PUT` requests and returns it for `GET` requests. Requests to the
+    /// server are neither authenticated nor encrypted, so only use one on a
+    /// trusted network. Objects are cached locally.
+    #[arg(long, value_hint = clap::ValueHint::Url)]
+    remote_store: Option<String>,
 }
 
 /// Create a new, empty change and edit it in the working copy
@@ -169,6 +227,45 @@ struct UntrackArgs {
     paths: Vec<String>,
 }
 
+/// Move or rename a file or directory
+///
+/// The file is moved both on disk and in the working-copy commit, which also
+/// records the rename, so `jj diff --summary` shows it as renamed. If the
+/// destination is an existing directory, the source is moved into it.
+#[derive(clap::Args, Clone, Debug)]
+struct MvArgs {
+    /// The file or directory to move
+    #[arg(value_hint = clap::ValueHint::AnyPath)]
+    source: String,
+    /// The new path
+    #[arg(value_hint = clap::ValueHint::AnyPath)]
+    destination: String,
+}
+
+/// Copy a file or directory
+///
+/// The file is copied both on disk and in the working-copy commit, which also
+/// records the copy, so `jj diff --summary` shows it as copied. If the
+/// destination is an existing directory, the source is copied into it.
+#[derive(clap::Args, Clone, Debug)]
+struct CpArgs {
+    /// The file or directory to copy
+    #[arg(value_hint = clap::ValueHint::AnyPath)]
+    source: String,
+    /// The path of the copy
+    #[arg(value_hint = clap::ValueHint::AnyPath)]
+    destination: String,
+}
+
+/// Snapshot the working copy
+///
+/// Most commands snapshot the working copy when they start, so this is only
+/// needed if that has been disabled by setting `snapshot.auto` to `false`, or
+/// if you want to record the state of the working copy without running any
+/// other command.
+#[derive(clap::Args, Clone, Debug)]
+struct SnapshotArgs {}
+
 /// List files in a revision
 #[derive(clap::Args, Clone, Debug)]
 struct FilesArgs {
@@ -178,6 +275,33 @@ struct FilesArgs {
     /// Only list files matching these prefixes (instead of all files)
     #[arg(value_hint = clap::ValueHint::AnyPath)]
     paths: Vec<String>,
+    /// Match the paths case-insensitively
+    #[arg(long, short)]
+    ignore_case: bool,
+}
+
+/// Write the files in a revision to a tar or zip archive
+///
+/// The files are read directly from the repo, so the working copy is not
+/// affected. Conflicted files are written with conflict markers. All files get
+/// the revision's committer timestamp as modification time.
+#[derive(clap::Args, Clone, Debug)]
+struct ArchiveArgs {
+    /// The revision to archive
+    #[arg(long, short, default_value = "@")]
+    revision: String,
+    /// Only archive files matching these prefixes (instead of all files)
+    #[arg(value_hint = clap::ValueHint::AnyPath)]
+    paths: Vec<String>,
+    /// The file to write the archive to
+    #[arg(long, short, value_hint = clap::ValueHint::FilePath)]
+    output: PathBuf,
+    /// The archive format (default: guessed from the output file's extension)
+    #[arg(long, value_enum)]
+    format: Option<ArchiveFormat>,
+    /// A directory to put the files in within the archive (e.g. `project/`)
+    #[arg(long, default_value = "")]
+    prefix: String,
 }
 
 /// Print contents of a file in a revision
@@ -189,20 +313,31 @@ struct PrintArgs {
     /// The file to print
     #[arg(value_hint = clap::ValueHint::FilePath)]
     path: String,
+    /// Print each side of a conflicted file in full instead of with conflict
+    /// markers
+    #[arg(long)]
+    no_materialize: bool,
 }
 
 #[derive(clap::Args, Clone, Debug)]
-#[command(group(ArgGroup::new("format").args(&["summary", "git", "color_words"])))]
+#[command(group(ArgGroup::new("format").args(&["summary", "stat", "git", "color_words", "json"])))]
 struct DiffFormatArgs {
     /// For each path, show only whether it was modified, added, or removed
     #[arg(long, short)]
     summary: bool,
+    /// For each path, show how many lines were added and removed
+    #[arg(long)]
+    stat: bool,
     /// Show a Git-format diff
     #[arg(long)]
     git: bool,
     /// Show a word-level diff with changes indicated only by color
     #[arg(long)]
     color_words: bool,
+    /// Show the diff as JSON, with the status, old and new object ids, and
+    /// changed line ranges of each path
+    #[arg(long)]
+    json: bool,
 }
 
 /// Show changes in a revision
@@ -216,6 +351,10 @@ struct DiffFormatArgs {
 /// given revisions. If either is left out, it defaults to the current checkout.
 /// For example, `jj diff --from main` shows the changes from "main" (perhaps a
 /// branch name) to the current checkout.
+///
+/// With the `--from-file` and `--to-file` options, shows the difference between
+/// two files, or between two directories, on disk. They don't need to be in a
+/// repo.
 #[derive(clap::Args, Clone, Debug)]
 struct DiffArgs {
     /// Show changes in this revision, compared to its parent(s)
@@ -227,9 +366,24 @@ struct DiffArgs {
     /// Show changes to this revision
     #[arg(long, conflicts_with = "revision")]
     to: Option<String>,
+    /// Show changes from this file or directory on disk
+    #[arg(
+        long,
+        requires = "to_file",
+        conflicts_with_all = ["revision", "from", "to", "paths"],
+        value_hint = clap::ValueHint::AnyPath
+    )]
+    from_file: Option<PathBuf>,
+    /// Show changes to this file or directory on disk
+    #[arg(long, requires = "from_file", value_hint = clap::ValueHint::AnyPath)]
+    to_file: Option<PathBuf>,
     /// Restrict the diff to these paths
     #[arg(value_hint = clap::ValueHint::AnyPath)]
     paths: Vec<String>,
+    /// Show each side of conflicted files in full instead of with conflict
+    /// markers (this doesn't affect `--summary` and `--stat`)
+    #[arg(long)]
+    no_materialize: bool,
     #[command(flatten)]
     format: DiffFormatArgs,
 }
@@ -243,6 +397,10 @@ struct ShowArgs {
     /// Ignored (but lets you pass `-r` for consistency with other commands)
     #[arg(short = 'r', hide = true)]
     unused_revision: bool,
+    /// Render the commit's metadata using the given template instead of the
+    /// default one (the syntax is not yet documented and is likely to change)
+    #[arg(long, short = 'T')]
+    template: Option<String>,
     #[command(flatten)]
     format: DiffFormatArgs,
 }
@@ -251,19 +409,33 @@ struct ShowArgs {
 ///
 /// This includes:
 ///
-///  * The working copy commit and its (first) parent, and a summary of the
-///    changes between them
+///  * The working copy commit and its parents, and a summary of the changes
+///    between them
+///
+///  * Paths with unresolved conflicts in the working copy
+///
+///  * Other workspaces that have the same change checked out
 ///
 ///  * Conflicted branches (see https://github.com/martinvonz/jj/blob/main/docs/branches.md)
 #[derive(clap::Args, Clone, Debug)]
 #[command(visible_alias = "st")]
-struct StatusArgs {}
+struct StatusArgs {
+    /// Restrict the status display to these paths
+    #[arg(value_hint = clap::ValueHint::AnyPath)]
+    paths: Vec<String>,
+    /// Render the working-copy commit and its parents using the given template
+    /// (the syntax is not yet documented and is likely to change)
+    #[arg(long, short = 'T')]
+    template: Option<String>,
+}
 
 /// Show commit history
 #[derive(clap::Args, Clone, Debug)]
 struct LogArgs {
     /// Which revisions to show. Defaults to the `ui.default-revset` setting,
-    /// or "remote_branches().. | (remote_branches()..)-" if it is not set.
+    /// or "builtin_log()" if it is not set. `builtin_log()` is the commits
+    /// that are not on any remote branch, plus their closest commit on a
+    /// remote branch.
     #[arg(long, short)]
     revisions: Option<String>,
     /// Show commits modifying the given paths
@@ -286,6 +458,32 @@ struct LogArgs {
     diff_format: DiffFormatArgs,
 }
 
+/// Show statistics about a set of revisions
+///
+/// Shows how many commits there are, how many lines they changed, and the
+/// files that were changed in the most commits. Use `--by-author` and
+/// `--by-day` to also count the commits per author and per day. Days are in
+/// the time zone of the author date.
+#[derive(clap::Args, Clone, Debug)]
+struct SummaryArgs {
+    /// Which revisions to summarize
+    #[arg(long, short, default_value = "all()")]
+    revisions: String,
+    /// Only include commits authored at or after this date (e.g. "2022-10-01"
+    /// or "2 weeks ago")
+    #[arg(long)]
+    since: Option<String>,
+    /// Count the commits and changed lines per author
+    #[arg(long)]
+    by_author: bool,
+    /// Count the commits per day
+    #[arg(long)]
+    by_day: bool,
+    /// How many of the most changed files to show
+    #[arg(long, default_value_t = 10)]
+    files: usize,
+}
+
 /// Show how a change has evolved
 ///
 /// Show how a change has evolved as it's been updated, rebased, etc.
@@ -336,9 +534,19 @@ struct InterdiffArgs {
 ///
 /// Starts an editor to let you edit the description of a change. The editor
 /// will be $EDITOR, or `pico` if that's not defined.
+///
+/// If the revset resolves to several revisions, their descriptions are edited
+/// together in a single file, with a "JJ: describe" line before each of them.
+/// With `--message`, `--message-file` or `--stdin`, all the revisions get the
+/// same description.
+///
+/// Co-authors are set with `--co-author`, which replaces any co-authors the
+/// revisions already have. If neither a description nor `--edit` is given
+/// with it, the descriptions are left alone. In the Git backend, co-authors are
+/// stored as `Co-authored-by:` trailers in the commit message.
 #[derive(clap::Args, Clone, Debug)]
 struct DescribeArgs {
-    /// The revision whose description to edit
+    /// The revision(s) whose description to edit
     #[arg(default_value = "@")]
     revision: String,
     /// Ignored (but lets you pass `-r` for consistency with other commands)
@@ -350,6 +558,19 @@ struct DescribeArgs {
     /// Read the change description from stdin
     #[arg(long)]
     stdin: bool,
+    /// Read the change description from a file
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["message", "stdin"])]
+    message_file: Option<PathBuf>,
+    /// Set a co-author, like "Some One <some.one@example.com>" (can be
+    /// repeated)
+    #[arg(long, value_name = "NAME <EMAIL>")]
+    co_author: Vec<String>,
+    /// Remove all co-authors
+    #[arg(long, conflicts_with = "co_author")]
+    no_co_authors: bool,
+    /// Open the editor even when only setting co-authors
+    #[arg(long, conflicts_with_all = ["message", "stdin", "message_file"])]
+    edit: bool,
 }
 
 /// Mark a revision closed
@@ -406,6 +627,9 @@ struct DuplicateArgs {
 /// Abandon a revision, rebasing descendants onto its parent(s). The behavior is
 /// similar to `jj restore`; the difference is that `jj abandon` gives you a new
 /// change, while `jj restore` updates the existing change.
+///
+/// Local branches pointing to an abandoned revision are moved to its parent(s)
+/// unless `--delete-branches` or `--retain-branches` is passed.
 #[derive(clap::Args, Clone, Debug)]
 #[command(visible_alias = "hide")]
 struct AbandonArgs {
@@ -415,6 +639,55 @@ struct AbandonArgs {
     /// Ignored (but lets you pass `-r` for consistency with other commands)
     #[arg(short = 'r', hide = true)]
     unused_revision: bool,
+    /// Delete local branches pointing to the abandoned revisions instead of
+    /// moving them to the parents
+    #[arg(long, conflicts_with = "retain_branches")]
+    delete_branches: bool,
+    /// Leave local branches pointing to the abandoned (now hidden) revisions
+    #[arg(long)]
+    retain_branches: bool,
+    /// Only print what would change, without changing anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Make hidden revisions visible again
+///
+/// Abandoned and rewritten revisions are hidden, but they're kept in the repo.
+/// This makes them visible heads again, e.g. to recover from abandoning the
+/// wrong revision. Use `jj log -r 'hidden()'` to find hidden revisions. Their
+/// ancestors become visible too.
+#[derive(clap::Args, Clone, Debug)]
+struct UnhideArgs {
+    /// The revision(s) to make visible
+    #[arg(required = true)]
+    revisions: Vec<String>,
+    /// Ignored (but lets you pass `-r` for consistency with other commands)
+    #[arg(short = 'r', hide = true)]
+    unused_revision: bool,
+}
+
+/// Resolve a divergent change
+///
+/// A change is divergent when it has more than one visible commit, which
+/// happens e.g. when it was rewritten in two concurrent operations. Such
+/// commits are marked as "divergent" in `jj log` and can be found with the
+/// `divergent()` revset. With `--keep`, the given commit is kept and the
+/// change's other commits are abandoned. With `--merge`, all the change's
+/// commits are merged into a single commit. Either way, descendants of the
+/// replaced commits are rebased onto the result.
+#[derive(clap::Args, Clone, Debug)]
+#[command(group(ArgGroup::new("action").args(&["keep", "merge"]).required(true)))]
+struct ResolveDivergenceArgs {
+    /// A commit of the divergent change
+    #[arg(default_value = "@")]
+    revision: String,
+    /// Keep the given commit and abandon the change's other commits
+    #[arg(long)]
+    keep: bool,
+    /// Merge all the change's commits into a new commit
+    #[arg(long)]
+    merge: bool,
 }
 
 /// Edit a commit in the working copy
@@ -451,6 +724,33 @@ struct NewArgs {
     message: String,
 }
 
+/// Merge work from multiple branches
+///
+/// Unlike most other VCSs, `jj merge` does not implicitly include the working
+/// copy revision's parent as one of the parents of the merge; you need to
+/// explicitly list all revisions that should become parents of the merge. There
+/// can be any number of them, as long as there are at least two.
+///
+/// This is like `jj new`, except that the new commit gets a description naming
+/// the parents (by their branches if they have any), which you can edit before
+/// the commit is created. Paths with conflicts in the merge are listed
+/// afterwards.
+#[derive(clap::Args, Clone, Debug)]
+struct MergeArgs {
+    /// Parents of the merge
+    #[arg(required = true, num_args = 2..)]
+    revisions: Vec<String>,
+    /// Ignored (but lets you pass `-r` for consistency with other commands)
+    #[arg(short = 'r', hide = true)]
+    unused_revision: bool,
+    /// The description to use instead of the generated one
+    #[arg(long, short, conflicts_with = "no_edit")]
+    message: Option<String>,
+    /// Use the generated description without opening an editor
+    #[arg(long)]
+    no_edit: bool,
+}
+
 /// Move changes from one revision into another
 ///
 /// Use `--interactive` to move only part of the source revision into the
@@ -534,6 +834,11 @@ struct UnsquashArgs {
 /// When neither `--from` nor `--to` is specified, the command restores into the
 /// working copy from its parent. If one of `--from` or `--to` is specified, the
 /// other one defaults to the working copy.
+///
+/// The destination can be any revision. Its descendants are rebased onto the
+/// rewritten revision as usual, so the restored contents are merged into them
+/// and changes they make to the restored paths are kept (or result in
+/// conflicts).
 #[derive(clap::Args, Clone, Debug)]
 struct RestoreArgs {
     /// Revision to restore from (source)
@@ -546,7 +851,9 @@ struct RestoreArgs {
     #[arg(long, short)]
     interactive: bool,
     /// Restore only these paths (instead of all paths)
-    #[arg(conflicts_with = "interactive", value_hint = clap::ValueHint::AnyPath)]
+    ///
+    /// With `--interactive`, only these paths are shown in the diff editor.
+    #[arg(value_hint = clap::ValueHint::AnyPath)]
     paths: Vec<String>,
 }
 
@@ -572,11 +879,18 @@ struct TouchupArgs {
 /// first revision. Once you close the editor, your edited content will replace
 /// the previous revision. The remaining changes will be put in a new revision
 /// on top. You will be asked to enter a change description for each.
+///
+/// If paths are given, the changes to them are put in the first revision
+/// without running the diff editor, and both revisions keep the original
+/// description (unless `--message` is given), so scripts can split revisions.
 #[derive(clap::Args, Clone, Debug)]
 struct SplitArgs {
     /// The revision to split
     #[arg(long, short, default_value = "@")]
     revision: String,
+    /// The description of the first revision (don't open the editor for it)
+    #[arg(long, short)]
+    message: Option<String>,
     /// Put these paths in the first commit and don't run the diff editor
     #[arg(value_hint = clap::ValueHint::AnyPath)]
     paths: Vec<String>,
@@ -605,6 +919,13 @@ struct SplitArgs {
 /// |/         |/
 /// A          A
 ///
+/// This works like `git rebase --onto` with the fork point computed for you,
+/// even if the branch has merged from the destination's history (or the other
+/// way around): only the commits that aren't already in the destination's
+/// history are rebased, and merge parents that would become ancestors of the
+/// destination are dropped. `-b` can be repeated to rebase several branches at
+/// once. Commits that are already on top of the destination are left alone.
+///
 /// With `-s`, it rebases the specified revision and its descendants onto the
 /// destination. For example, `jj rebase -s C -d D` would transform your history
 /// like this:
@@ -642,13 +963,21 @@ struct SplitArgs {
 /// | B        | B
 /// |/         |/
 /// A          A
+///
+/// Commits that get conflicts when they're rebased are normally rebased
+/// anyway, and their descendants are rebased on top of the conflicts. With
+/// `--stop-on-conflict`, the rebase instead stops at the first such commit and
+/// starts editing it, so you can resolve the conflicts before its descendants
+/// are rebased. The commits that haven't been rebased yet are hidden until
+/// then. Run `jj rebase --continue` to continue rebasing after that, or `jj
+/// rebase --abort` to put the rebased commits back where they were.
 #[derive(clap::Args, Clone, Debug)]
 #[command(verbatim_doc_comment)]
 #[command(group(ArgGroup::new("to_rebase").args(&["branch", "source", "revision"])))]
 struct RebaseArgs {
     /// Rebase the whole branch (relative to destination's ancestors)
     #[arg(long, short)]
-    branch: Option<String>,
+    branch: Vec<String>,
     /// Rebase this revision and its descendants
     #[arg(long, short)]
     source: Option<String>,
@@ -657,8 +986,27 @@ struct RebaseArgs {
     #[arg(long, short)]
     revision: Option<String>,
     /// The revision(s) to rebase onto
-    #[arg(long, short, required = true)]
+    #[arg(long, short, required_unless_present_any = ["continue_", "abort"])]
     destination: Vec<String>,
+    /// Only print what would change, without changing anything
+    #[arg(long)]
+    dry_run: bool,
+    /// Stop at the first commit that gets conflicts, so they can be resolved
+    /// before its descendants are rebased
+    #[arg(long, conflicts_with_all = ["revision", "dry_run"])]
+    stop_on_conflict: bool,
+    /// Continue a rebase that stopped at a commit with conflicts
+    #[arg(
+        long = "continue",
+        conflicts_with_all = ["to_rebase", "destination", "dry_run", "stop_on_conflict", "abort"]
+    )]
+    continue_: bool,
+    /// Undo a rebase that stopped at a commit with conflicts
+    #[arg(
+        long,
+        conflicts_with_all = ["to_rebase", "destination", "dry_run", "stop_on_conflict"]
+    )]
+    abort: bool,
 }
 
 /// Apply the reverse of a revision on top of another revision
@@ -710,6 +1058,23 @@ enum BranchSubcommand {
         names: Vec<String>,
     },
 
+    /// Rename a branch
+    ///
+    /// The branch keeps its target. If the branch exists on a remote, the old
+    /// name is left behind as a deleted branch, so `jj git push --all` deletes
+    /// the old branch on the remote and creates the new one. If
+    /// `branch.rename-old-remote` is set to `"untrack"`, the remote branches
+    /// with the old name are forgotten instead, so they're left alone on the
+    /// remote.
+    #[command(visible_alias("r"))]
+    Rename {
+        /// The branch to rename
+        old: String,
+
+        /// The new name of the branch
+        new: String,
+    },
+
     /// List branches and their targets
     ///
     /// A remote branch will be included only if its target is different from
@@ -717,8 +1082,32 @@ enum BranchSubcommand {
     /// target revisions are preceded by a "-" and new target revisions are
     /// preceded by a "+". For information about branches, see
     /// https://github.com/martinvonz/jj/blob/main/docs/branches.md.
+    ///
+    /// With `--template`, each branch is rendered using the template instead.
+    /// The keywords are `name`, `present`, `conflict`, `target`, `remotes`,
+    /// `description`, `ahead` and `behind`. The last two count the commits
+    /// the local branch has that the tracked remote branch doesn't, and vice
+    /// versa. The tracked remote is the one given by `--remote`, or `origin`.
     #[command(visible_alias("l"))]
-    List,
+    List {
+        /// Only list branches whose names match these glob patterns (e.g.
+        /// `feature/*`)
+        names: Vec<String>,
+
+        /// Only list branches that exist on this remote, and only show the
+        /// remote's version of them
+        #[arg(long)]
+        remote: Option<String>,
+
+        /// The order to list the branches in
+        #[arg(long, value_enum, default_value = "name")]
+        sort: BranchSortKey,
+
+        /// Render each branch using the given template (the syntax is not yet
+        /// documented and is likely to change)
+        #[arg(long, short = 'T')]
+        template: Option<String>,
+    },
 
     /// Update a given branch to point to a certain commit.
     #[command(visible_alias("s"))]
@@ -731,12 +1120,93 @@ enum BranchSubcommand {
         #[arg(long)]
         allow_backwards: bool,
 
+        /// Resolve a conflicted branch by pointing it to the given revision.
+        ///
+        /// The revision doesn't have to be one of the branch's current
+        /// targets. Fails if the branch is not conflicted.
+        #[arg(long)]
+        resolve: bool,
+
         /// The branches to update.
         #[arg(required = true)]
         names: Vec<String>,
     },
 }
 
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum BranchSortKey {
+    /// Sort by branch name
+    Name,
+    /// Sort by the committer date of the local target, newest first
+    Date,
+}
+
+/// Manage names of changes
+///
+/// A name refers to a change rather than to a commit, so unlike a branch, it
+/// keeps referring to the change when the change is rewritten. Names can be
+/// used in revsets and are shown in `jj log`. They're local to the repo and are
+/// not pushed to remotes.
+#[derive(clap::Subcommand, Clone, Debug)]
+enum NameSubcommand {
+    /// Give a name to a change, or move an existing name to it
+    #[command(visible_alias("s"))]
+    Set {
+        /// The revision whose change to name
+        #[arg(long, short, default_value = "@")]
+        revision: String,
+
+        /// The names to give the change
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+
+    /// Delete names
+    #[command(visible_alias("d"))]
+    Delete {
+        /// The names to delete
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+
+    /// List names and the commits they currently refer to
+    #[command(visible_alias("l"))]
+    List,
+}
+
+/// Manage topics of changes
+///
+/// A topic is a label for grouping related changes without creating branches.
+/// Like a name, it refers to changes rather than to commits, so it stays with
+/// the changes when they're rewritten. Each change has at most one topic.
+/// Topics can be used in revsets with `topic(NAME)` and are shown in `jj log`.
+/// They're local to the repo and are not pushed to remotes.
+#[derive(clap::Subcommand, Clone, Debug)]
+enum TopicSubcommand {
+    /// Set the topic of changes, replacing their current topic
+    #[command(visible_alias("s"))]
+    Set {
+        /// The revisions whose changes to set the topic of
+        #[arg(long, short, default_value = "@")]
+        revisions: String,
+
+        /// The topic
+        name: String,
+    },
+
+    /// Remove the topic from changes
+    #[command(visible_alias("c"))]
+    Clear {
+        /// The revisions whose changes to remove the topic from
+        #[arg(long, short, default_value = "@")]
+        revisions: String,
+    },
+
+    /// List topics and the commits in them
+    #[command(visible_alias("l"))]
+    List,
+}
+
 /// Commands for working with the operation log
 ///
 /// Commands for working with the operation log. For information about the
@@ -744,27 +1214,88 @@ enum BranchSubcommand {
 #[derive(Subcommand, Clone, Debug)]
 enum OperationCommands {
     Log(OperationLogArgs),
+    Heads(OperationHeadsArgs),
+    Merge(OperationMergeArgs),
     Undo(OperationUndoArgs),
     Restore(OperationRestoreArgs),
 }
 
 /// Show the operation log
+///
+/// With `--template`, each operation is rendered using the template. The
+/// keywords are `id`, `user`, `start_time`, `end_time`, `description`, `tags`
+/// and `current_operation`. Use `tag("KEY")` to render the value of a single
+/// tag.
+///
+/// If concurrent operations have left the operation log with several heads,
+/// the log is drawn starting from all of them, without merging them. Use `jj
+/// op merge` to merge them.
+#[derive(clap::Args, Clone, Debug)]
+struct OperationLogArgs {
+    /// Render each operation using the given template (the syntax is not yet
+    /// documented and is likely to change)
+    #[arg(long, short = 'T')]
+    template: Option<String>,
+    /// Only show operations that finished at or after this time (e.g.
+    /// "yesterday", "2 hours ago" or "2022-10-01")
+    #[arg(long, value_name = "DATE")]
+    after: Option<String>,
+    /// Only show operations that finished at or before this time
+    #[arg(long, value_name = "DATE")]
+    before: Option<String>,
+}
+
+/// List the heads of the operation log
+///
+/// There's normally a single head, but concurrent operations (e.g. commands
+/// run at the same time, or on different machines sharing the repo) can leave
+/// several. They're listed with the most recent one first.
+#[derive(clap::Args, Clone, Debug)]
+struct OperationHeadsArgs {
+    /// Render each operation using the given template (see `jj op log`)
+    #[arg(long, short = 'T')]
+    template: Option<String>,
+}
+
+/// Merge divergent heads of the operation log
+///
+/// Most commands merge concurrent operations automatically when they load the
+/// repo. This command does only that, so the operation log can be inspected
+/// with `jj op log` and `jj op heads` first.
 #[derive(clap::Args, Clone, Debug)]
-struct OperationLogArgs {}
+struct OperationMergeArgs {}
 
 /// Restore to the state at an operation
 #[derive(clap::Args, Clone, Debug)]
 struct OperationRestoreArgs {
     /// The operation to restore to
     operation: String,
+    /// Only print what would change, without changing anything
+    #[arg(long)]
+    dry_run: bool,
 }
 
 /// Undo an operation
+///
+/// Some commands commit several operations. By default, all operations
+/// committed by the same command are undone together. The snapshot of the
+/// working copy the command took before running is kept unless
+/// `--include-snapshot` is passed, so uncommitted edits aren't lost.
 #[derive(clap::Args, Clone, Debug)]
 struct OperationUndoArgs {
     /// The operation to undo
     #[arg(default_value = "@")]
     operation: String,
+    /// Undo only the given operation, not the other operations committed by
+    /// the same command
+    #[arg(long)]
+    step: bool,
+    /// Also undo the working-copy snapshot taken by the same command
+    #[arg(long, conflicts_with = "step")]
+    include_snapshot: bool,
+    /// Only print what would change, without changing anything
+    #[arg(long)]
+    dry_run: bool,
 }
 
 /// Commands for working with workspaces
@@ -786,6 +1317,17 @@ struct WorkspaceAddArgs {
     /// directory.
     #[arg(long)]
     name: Option<String>,
+    /// The revision to check out in the new workspace
+    ///
+    /// Defaults to the parent of the current workspace's working-copy commit.
+    #[arg(long, short)]
+    revision: Option<String>,
+    /// Only check out these paths (relative to the repo root) in the new
+    /// workspace
+    ///
+    /// Can be repeated. See `jj sparse` for changing them later.
+    #[arg(long, value_name = "PATH")]
+    sparse_pattern: Vec<String>,
 }
 
 /// Stop tracking a workspace's checkout in the repo
@@ -811,106 +1353,458 @@ struct SparseArgs {
     /// Patterns to remove from the working copy
     #[arg(long, conflicts_with = "clear", value_hint = clap::ValueHint::AnyPath)]
     remove: Vec<String>,
-    /// Include no files in the working copy (combine with --add)
+    /// Add the patterns from a sparse profile stored in the repo
+    ///
+    /// The profile `NAME` is read from `NAME.sparse` in the directory
+    /// configured by `sparse.profiles-dir` (`profiles` by default) in the
+    /// working-copy commit. It lists one path per line; empty lines and lines
+    /// starting with `#` are ignored. Can be repeated to combine profiles, and
+    /// combined with --clear to use only the profiles' patterns.
+    #[arg(long, value_name = "NAME")]
+    profile: Vec<String>,
+    /// Replace the patterns by the ones listed in a file (`-` for stdin)
+    ///
+    /// The file uses the same format as sparse profiles. All patterns are
+    /// validated before any of them are applied.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = &["add", "remove", "profile"],
+        value_hint = clap::ValueHint::FilePath
+    )]
+    from_file: Option<String>,
+    /// Include no files in the working copy (combine with --add or --profile)
     #[arg(long)]
     clear: bool,
     /// Include all files in the working copy
-    #[arg(long, conflicts_with_all = &["add", "remove", "clear"])]
+    #[arg(long, conflicts_with_all = &["add", "remove", "clear", "profile", "from_file"])]
     reset: bool,
     /// List patterns
-    #[arg(long, conflicts_with_all = &["add", "remove", "clear", "reset"])]
+    #[arg(
+        long,
+        conflicts_with_all = &["add", "remove", "clear", "reset", "profile", "from_file"]
+    )]
     list: bool,
+    /// Write the actual contents of placeholder files in these paths
+    ///
+    /// When `working-copy.lazy-threshold` is set, files larger than that many
+    /// bytes are checked out as small placeholder files.
+    #[arg(
+        long,
+        conflicts_with_all = &["add", "remove", "clear", "reset", "list", "profile", "from_file"],
+        value_hint = clap::ValueHint::AnyPath
+    )]
+    hydrate: Vec<String>,
 }
 
-/// Commands for working with the underlying Git repo
+/// Run a command on each revision in a set
 ///
-/// For a comparison with Git, including a table of commands, see
-/// https://github.com/martinvonz/jj/blob/main/docs/git-comparison.md.
-#[derive(Subcommand, Clone, Debug)]
-enum GitCommands {
-    #[command(subcommand)]
-    Remote(GitRemoteCommands),
-    Fetch(GitFetchArgs),
-    Clone(GitCloneArgs),
-    Push(GitPushArgs),
-    Import(GitImportArgs),
-    Export(GitExportArgs),
-}
-
-/// Manage Git remotes
+/// Each revision is checked out in a hidden working copy inside `.jj/`. These
+/// are kept between runs, and the one whose contents are closest to the
+/// revision is reused, so only the files that differ need to be written.
+/// Changes the command makes to the files there are discarded before the next
+/// revision is checked out. At most `run.workspace-cache-size` (4 by default)
+/// of them are kept; concurrent runs use different ones. The command is run by the shell,
+/// with `JJ_COMMIT_ID` and `JJ_CHANGE_ID` set to the revision's IDs.
 ///
-/// The Git repo will be a bare git repo stored inside the `.jj/` directory.
-#[derive(Subcommand, Clone, Debug)]
-enum GitRemoteCommands {
-    Add(GitRemoteAddArgs),
-    Remove(GitRemoteRemoveArgs),
-    List(GitRemoteListArgs),
+/// Revisions are processed parents first. The exit status is non-zero if the
+/// command failed on any of them.
+#[derive(clap::Args, Clone, Debug)]
+struct RunArgs {
+    /// The command to run
+    command: String,
+    /// The revisions to run the command on
+    #[arg(long, short, default_value = "@")]
+    revisions: String,
 }
 
-/// Add a Git remote
+/// Format files in a set of revisions
+///
+/// Runs the tools configured in `[fix.tools.<name>]` on the files each
+/// revision changes, and rewrites the revisions with the results. Descendants
+/// are rebased onto the rewritten revisions. Each tool is given the file's
+/// contents on stdin and is expected to write the new contents to stdout. If
+/// a tool fails, the file is left as it was.
 #[derive(clap::Args, Clone, Debug)]
-struct GitRemoteAddArgs {
-    /// The remote's name
-    remote: String,
-    /// The remote's URL
-    url: String,
+struct FixArgs {
+    /// The revisions to fix
+    #[arg(long, short, default_value = "@")]
+    revisions: String,
 }
 
-/// Remove a Git remote and forget its branches
+/// Search and replace text in the files of a set of revisions
+///
+/// Replaces all matches of a regular expression in the files of each revision
+/// and rewrites the revisions with the results. All files in the revisions are
+/// searched, not only the files they change, so the replacement isn't undone
+/// by later revisions in the set. Descendants are rebased onto the rewritten
+/// revisions.
 #[derive(clap::Args, Clone, Debug)]
-struct GitRemoteRemoveArgs {
-    /// The remote's name
-    remote: String,
+struct RewriteArgs {
+    /// The revisions to rewrite
+    #[arg(long, short, default_value = "@")]
+    revisions: String,
+    /// Replace matches of REGEX by REPLACEMENT, which can refer to capture
+    /// groups as `$1` or `${name}`
+    #[arg(long, required = true, num_args = 2, value_names = ["REGEX", "REPLACEMENT"])]
+    replace: Vec<String>,
+    /// Only rewrite these paths
+    #[arg(value_hint = clap::ValueHint::AnyPath)]
+    paths: Vec<String>,
 }
 
-/// List Git remotes
+/// Show statistics about the repo
+///
+/// With the local backend, this includes how much space is saved by storing
+/// large files as chunks that are shared between versions of the files.
 #[derive(clap::Args, Clone, Debug)]
-struct GitRemoteListArgs {}
+struct StatsArgs {}
 
-/// Fetch from a Git remote
+/// Check the signatures of a set of revisions
+///
+/// Verifies the signature of each revision with `git verify-commit`, using
+/// Git's configuration for the keys to trust. Fails if any of the revisions is
+/// unsigned or has a signature that can't be verified, so it can be used as a
+/// check before pushing. Only works in Git-backed repos.
 #[derive(clap::Args, Clone, Debug)]
-struct GitFetchArgs {
-    /// The remote to fetch from (only named remotes are supported)
-    #[arg(long, default_value = "origin")]
-    remote: String,
+struct VerifyArgs {
+    /// The revisions to verify
+    #[arg(long, short, default_value = "@")]
+    revisions: String,
+    /// Print the results as JSON
+    #[arg(long)]
+    json: bool,
 }
 
-/// Create a new repo backed by a clone of a Git repo
+/// Commands for reviewing changes with Gerrit
+#[derive(Subcommand, Clone, Debug)]
+enum GerritCommands {
+    Upload(GerritUploadArgs),
+}
+
+/// Upload revisions to Gerrit for review
 ///
-/// The Git repo will be a bare git repo stored inside the `.jj/` directory.
+/// Uploads the revisions and their ancestors that aren't on any remote yet by
+/// pushing them to `refs/for/<branch>` on the Gerrit remote. Revisions without
+/// a `Change-Id:` trailer get one derived from their change id first, so later
+/// uploads of rewritten revisions become new patch sets of the same Gerrit
+/// changes. The state of the uploads is available in templates as the
+/// `gerrit_review` keyword ("uploaded" or "needs-upload").
 #[derive(clap::Args, Clone, Debug)]
-struct GitCloneArgs {
-    /// URL or path of the Git repo to clone
-    #[arg(value_hint = clap::ValueHint::DirPath)]
-    source: String,
-    /// The directory to write the Jujutsu repo to
-    #[arg(value_hint = clap::ValueHint::DirPath)]
-    destination: Option<String>,
+struct GerritUploadArgs {
+    /// The revisions to upload
+    #[arg(long, short, default_value = "@")]
+    revisions: String,
+    /// The branch the changes are for [default: `gerrit.default-branch` from
+    /// the config, or "main"]
+    #[arg(long = "for")]
+    for_branch: Option<String>,
+    /// The Gerrit remote [default: `gerrit.default-remote` from the config,
+    /// or the default Git remote]
+    #[arg(long)]
+    remote: Option<String>,
 }
 
-/// Push to a Git remote
+/// Manage pull requests for stacks of changes
 ///
-/// By default, pushes any branches pointing to `@`. Use `--branch` to push a
-/// specific branch. Use `--all` to push all branches. Use `--change` to
-/// generate a branch name based on a specific commit's change ID.
+/// Each change in the stack is pushed to its own branch, named like the
+/// branches `jj git push --change` creates, and gets its own pull request (a
+/// merge request on GitLab). The base of each pull request is the branch of the
+/// change before it, so the stack can be reviewed one change at a time.
+///
+/// The forge and repo are derived from the remote's URL unless `pr.forge`
+/// ("github" or "gitlab") and `pr.repo` are set. The API token is read from
+/// `pr.token`.
+#[derive(Subcommand, Clone, Debug)]
+enum PrCommands {
+    Create(PrCreateArgs),
+    Status(PrStatusArgs),
+}
+
+/// Push a stack of changes and create or update their pull requests
+///
+/// The stack consists of the revision and its ancestors that aren't on the
+/// base branch on the remote. Pull requests that already exist for the changes
+/// get the current descriptions as their titles and bodies.
 #[derive(clap::Args, Clone, Debug)]
-#[command(group(ArgGroup::new("what").args(&["branch", "all", "change"])))]
-struct GitPushArgs {
-    /// The remote to push to (only named remotes are supported)
-    #[arg(long, default_value = "origin")]
+struct PrCreateArgs {
+    /// The last revision in the stack
+    #[arg(long, short, default_value = "@")]
+    revision: String,
+    /// The branch to merge the stack into [default: `pr.default-base` from the
+    /// config, or "main"]
+    #[arg(long)]
+    base: Option<String>,
+    /// The remote to push to [default: `git.default-remote` from the config,
+    /// or "origin"]
+    #[arg(long)]
+    remote: Option<String>,
+}
+
+/// Show the pull requests of a stack of changes
+#[derive(clap::Args, Clone, Debug)]
+struct PrStatusArgs {
+    /// The last revision in the stack
+    #[arg(long, short, default_value = "@")]
+    revision: String,
+    /// The branch the stack is merged into [default: `pr.default-base` from
+    /// the config, or "main"]
+    #[arg(long)]
+    base: Option<String>,
+    /// The remote the stack was pushed to [default: `git.default-remote` from
+    /// the config, or "origin"]
+    #[arg(long)]
+    remote: Option<String>,
+}
+
+/// Open a revision's page on the forge in a browser
+///
+/// The URL is made from the `browse.commit-url` template in the config, e.g.
+/// `https://github.com/owner/repo/commit/{commit_id}`. The URL is opened by
+/// running the command in `browse.command` with the URL as its last argument
+/// (`xdg-open` by default, `open` on macOS).
+#[derive(clap::Args, Clone, Debug)]
+struct BrowseArgs {
+    /// The revision to open
+    #[arg(long, short, default_value = "@")]
+    revision: String,
+    /// Print the URL instead of opening it
+    #[arg(long)]
+    print: bool,
+}
+
+/// Send revisions as patches by email
+///
+/// Each revision is sent as a separate email in the format `git am` expects,
+/// oldest first. The emails are threaded: the later ones are replies to the
+/// first one (or to the cover letter). They're sent by piping them to the
+/// command in `send.sendmail-command` (`sendmail -i` by default), or to the SMTP
+/// server in `send.smtp-server` if that's set.
+#[derive(clap::Args, Clone, Debug)]
+struct SendArgs {
+    /// The revisions to send
+    #[arg(long, short, default_value = "@")]
+    revisions: String,
+    /// Recipients of the emails
+    #[arg(long, required = true)]
+    to: Vec<String>,
+    /// Recipients of copies of the emails
+    #[arg(long)]
+    cc: Vec<String>,
+    /// Write a cover letter in the editor and send it before the patches
+    #[arg(long)]
+    cover_letter: bool,
+    /// Print the emails in mbox format instead of sending them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Import history from another version control system
+///
+/// Imports all changesets of a Mercurial repository (using the `hg` command).
+/// Bookmarks become branches of the same name, as do the heads of named
+/// branches other than "default". Importing from the same repository again
+/// only imports the changesets that are new since the last import, and moves
+/// the branches.
+#[derive(clap::Args, Clone, Debug)]
+struct ImportArgs {
+    /// Path to the Mercurial repository to import
+    #[arg(long, value_hint = clap::ValueHint::DirPath, required = true)]
+    from_hg: PathBuf,
+}
+
+/// Commands for working with the underlying Git repo
+///
+/// For a comparison with Git, including a table of commands, see
+/// https://github.com/martinvonz/jj/blob/main/docs/git-comparison.md.
+#[derive(Subcommand, Clone, Debug)]
+enum GitCommands {
+    #[command(subcommand)]
+    Remote(GitRemoteCommands),
+    Fetch(GitFetchArgs),
+    Clone(GitCloneArgs),
+    Push(GitPushArgs),
+    Import(GitImportArgs),
+    Export(GitExportArgs),
+    FastExport(GitFastExportArgs),
+    FastImport(GitFastImportArgs),
+    #[command(subcommand)]
+    Notes(GitNotesCommands),
+}
+
+/// Manage Git remotes
+///
+/// The Git repo will be a bare git repo stored inside the `.jj/` directory.
+#[derive(Subcommand, Clone, Debug)]
+enum GitRemoteCommands {
+    Add(GitRemoteAddArgs),
+    Remove(GitRemoteRemoveArgs),
+    Rename(GitRemoteRenameArgs),
+    SetUrl(GitRemoteSetUrlArgs),
+    List(GitRemoteListArgs),
+}
+
+/// Add a Git remote
+#[derive(clap::Args, Clone, Debug)]
+struct GitRemoteAddArgs {
+    /// The remote's name
     remote: String,
+    /// The remote's URL
+    url: String,
+    /// A different URL to push to
+    #[arg(long)]
+    push_url: Option<String>,
+    /// Refspecs to fetch instead of all the remote's branches (e.g.
+    /// `refs/heads/main:refs/remotes/<remote>/main`)
+    #[arg(long)]
+    fetch: Vec<String>,
+}
+
+/// Remove a Git remote and forget its branches
+#[derive(clap::Args, Clone, Debug)]
+struct GitRemoteRemoveArgs {
+    /// The remote's name
+    remote: String,
+}
+
+/// Rename a Git remote and its remote-tracking branches
+#[derive(clap::Args, Clone, Debug)]
+struct GitRemoteRenameArgs {
+    /// The remote's current name
+    old: String,
+    /// The remote's new name
+    new: String,
+}
+
+/// Change the URL of a Git remote
+#[derive(clap::Args, Clone, Debug)]
+struct GitRemoteSetUrlArgs {
+    /// The remote's name
+    remote: String,
+    /// The remote's new URL
+    url: String,
+    /// Set the URL used for pushing instead of the one used for fetching
+    #[arg(long)]
+    push: bool,
+}
+
+/// List Git remotes
+#[derive(clap::Args, Clone, Debug)]
+struct GitRemoteListArgs {}
+
+/// Fetch from a Git remote
+#[derive(clap::Args, Clone, Debug)]
+struct GitFetchArgs {
+    /// The remote to fetch from (only named remotes are supported) [default:
+    /// `git.default-remote` from the config, or "origin"]
+    #[arg(long)]
+    remote: Option<String>,
+    /// Delete remote-tracking branches that no longer exist on the remote
+    /// [default: `git.fetch-prune` from the config, or false]
+    #[arg(long)]
+    prune: bool,
+    /// Don't show progress information
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+/// Create a new repo backed by a clone of a Git repo
+///
+/// The Git repo will be a bare git repo stored inside the `.jj/` directory.
+#[derive(clap::Args, Clone, Debug)]
+struct GitCloneArgs {
+    /// URL or path of the Git repo to clone
+    #[arg(value_hint = clap::ValueHint::DirPath)]
+    source: String,
+    /// The directory to write the Jujutsu repo to
+    #[arg(value_hint = clap::ValueHint::DirPath)]
+    destination: Option<String>,
+    /// Don't show progress information
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+/// Push to a Git remote
+///
+/// By default, pushes any branches pointing to `@`. Use `--branch` to push a
+/// specific branch. Use `--all` to push all branches. Use `--change` to
+/// generate a branch name based on a specific commit's change ID.
+#[derive(clap::Args, Clone, Debug)]
+#[command(group(ArgGroup::new("what").args(&["branch", "all", "change"])))]
+struct GitPushArgs {
+    /// The remote to push to (only named remotes are supported) [default:
+    /// `git.default-remote` from the config, or "origin"]
+    #[arg(long)]
+    remote: Option<String>,
     /// Push only this branch
     #[arg(long)]
     branch: Option<String>,
     /// Push all branches
     #[arg(long)]
     all: bool,
-    /// Push this commit by creating a branch based on its change ID
+    /// Push these commits by creating a branch for each of them based on its
+    /// change ID
+    ///
+    /// The branch is named after the change ID, with the prefix from
+    /// `push.branch-prefix` ("push-" by default). Like other branches, it moves
+    /// along when the change is rewritten, so pushing the same change again
+    /// updates the same branch. Can be repeated, and each value can be a
+    /// revset, e.g. `main..@` to push every change in a stack.
+    #[arg(long)]
+    change: Vec<String>,
+    /// Allow force-pushing and deleting branches matching
+    /// `git.protected-branches`
     #[arg(long)]
-    change: Option<String>,
+    force_protected: bool,
+    /// Allow pushing commits with empty descriptions or descriptions matching
+    /// `push.wip-patterns`
+    #[arg(long)]
+    allow_wip: bool,
+    /// Send a signed push certificate (requires support from the remote)
+    ///
+    /// Defaults to the `git.sign-push` setting. The certificate is signed
+    /// using Git's signing configuration (`user.signingKey` etc.).
+    #[arg(long)]
+    signed: bool,
     /// Only display what will change on the remote
     #[arg(long)]
     dry_run: bool,
+    /// Don't show progress information
+    #[arg(long, short)]
+    quiet: bool,
+}
+
+/// Manage Git notes attached to commits
+///
+/// Notes are read from and written to `refs/notes/commits` in the underlying
+/// Git repo, or the ref configured in `git.notes-ref`. They're shown by `jj
+/// show` and are available as the `notes` keyword in templates.
+#[derive(Subcommand, Clone, Debug)]
+enum GitNotesCommands {
+    Add(GitNotesAddArgs),
+    Remove(GitNotesRemoveArgs),
+}
+
+/// Attach a note to a commit
+#[derive(clap::Args, Clone, Debug)]
+struct GitNotesAddArgs {
+    /// The commit to attach the note to
+    #[arg(long, short, default_value = "@")]
+    revision: String,
+    /// The note's contents
+    #[arg(long, short)]
+    message: String,
+    /// Replace the commit's existing note, if any
+    #[arg(long, short)]
+    force: bool,
+}
+
+/// Remove the note attached to a commit
+#[derive(clap::Args, Clone, Debug)]
+struct GitNotesRemoveArgs {
+    /// The commit to remove the note from
+    #[arg(long, short, default_value = "@")]
+    revision: String,
 }
 
 /// Update repo with changes made in the underlying Git repo
@@ -921,6 +1815,53 @@ struct GitImportArgs {}
 #[derive(clap::Args, Clone, Debug)]
 struct GitExportArgs {}
 
+/// Write revisions as a `git fast-import` stream
+///
+/// The stream is written to stdout. It can be imported with `git fast-import`
+/// or by other tools that read the format. Parents of the revisions that are
+/// not themselves exported are referred to by commit ID, so the receiving
+/// repo needs to have them.
+#[derive(clap::Args, Clone, Debug)]
+struct GitFastExportArgs {
+    /// The revisions to export
+    #[arg(long, short, default_value = "all()")]
+    revisions: String,
+}
+
+/// Create commits from a `git fast-import` stream
+///
+/// The stream is read from stdin, e.g. from `git fast-export --all`. Commits
+/// written to refs under `refs/heads/` update the branches of the same name,
+/// and refs under `refs/tags/` update tags.
+#[derive(clap::Args, Clone, Debug)]
+struct GitFastImportArgs {
+    /// Read the stream from this file instead of from stdin
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    file: Option<PathBuf>,
+}
+
+/// Serve queries about the repo over JSON-RPC
+///
+/// Starts a long-running server that keeps the repo loaded, for use by editor
+/// integrations and similar tools. Requests and responses are JSON-RPC 2.0
+/// objects, one per line. The supported methods are `log` (params `revisions`
+/// and `limit`), `status`, `diff` (params `revision`, or `from` and `to`),
+/// `file` (params `revision` and `path`), and `shutdown`.
+///
+/// The server never takes locks or records operations, so it's safe to keep it
+/// running while using other jj commands. It doesn't snapshot the working copy.
+#[derive(clap::Args, Clone, Debug)]
+#[command(group(ArgGroup::new("transport").args(&["socket", "stdio"])))]
+struct ApiArgs {
+    /// Path of the Unix socket to listen on [default: .jj/api.sock]
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    socket: Option<PathBuf>,
+    /// Read requests from stdin and write responses to stdout instead of
+    /// listening on a socket
+    #[arg(long)]
+    stdio: bool,
+}
+
 /// Commands for benchmarking internal operations
 #[derive(Subcommand, Clone, Debug)]
 enum BenchCommands {
@@ -972,11 +1913,21 @@ enum DebugCommands {
     ResolveRev(DebugResolveRevArgs),
     #[command(name = "workingcopy")]
     WorkingCopy(DebugWorkingCopyArgs),
+    RepairWorkingCopy(DebugRepairWorkingCopyArgs),
+    BreakLock(DebugBreakLockArgs),
     Template(DebugTemplateArgs),
+    Graph(DebugGraphArgs),
     Index(DebugIndexArgs),
     #[command(name = "reindex")]
     ReIndex(DebugReIndexArgs),
     Operation(DebugOperationArgs),
+    Pack(DebugPackArgs),
+    WorkspaceCache(DebugWorkspaceCacheArgs),
+    MigrateBackend(DebugMigrateBackendArgs),
+    ChangeIds(DebugChangeIdsArgs),
+    SimplifyConflicts(DebugSimplifyConflictsArgs),
+    SnapshotBench(DebugSnapshotBenchArgs),
+    CheckoutBench(DebugCheckoutBenchArgs),
 }
 
 /// Print a command-line-completion script
@@ -1023,19 +1974,85 @@ struct DebugResolveRevArgs {
 #[derive(clap::Args, Clone, Debug)]
 struct DebugWorkingCopyArgs {}
 
+/// Repair the working copy state after an interrupted update
+///
+/// If jj was killed while it was writing the working copy state, this completes
+/// or rolls back that update. If the recorded state can't be read at all, it's
+/// moved aside and rebuilt from the working-copy commit.
+#[derive(clap::Args, Clone, Debug)]
+struct DebugRepairWorkingCopyArgs {}
+
+/// Remove lock files left behind by processes that are gone
+///
+/// Locks are normally taken over automatically if the process holding them is
+/// known to have exited. That's not possible if the process ran on another host
+/// (e.g. with the repo on a network file system) or if the lock was taken by an
+/// older version of jj.
+#[derive(clap::Args, Clone, Debug)]
+struct DebugBreakLockArgs {
+    /// Remove locks even if the holding process seems to be alive or if it's
+    /// unknown whether it is
+    #[arg(long)]
+    force: bool,
+}
+
 /// Parse a template
 #[derive(clap::Args, Clone, Debug)]
 struct DebugTemplateArgs {
     template: String,
 }
 
+/// Export the commit graph for use in other tools
+///
+/// Prints the graph of the selected revisions in Graphviz's DOT format, e.g.
+/// for rendering with `jj debug graph | dot -Tsvg > graph.svg`. Edges to
+/// ancestors that are not directly connected in the selected set are dashed.
+#[derive(clap::Args, Clone, Debug)]
+struct DebugGraphArgs {
+    /// Which revisions to include
+    #[arg(long, short, default_value = "all()")]
+    revisions: String,
+    /// The output format
+    #[arg(long, value_enum, default_value_t = GraphFormat::Dot)]
+    format: GraphFormat,
+    /// Render each node's label using the given template
+    #[arg(long, short = 'T')]
+    template: Option<String>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum GraphFormat {
+    /// Graphviz's DOT language
+    Dot,
+}
+
 /// Show commit index stats
 #[derive(clap::Args, Clone, Debug)]
-struct DebugIndexArgs {}
+struct DebugIndexArgs {
+    #[command(subcommand)]
+    command: Option<DebugIndexCommands>,
+}
+
+#[derive(Subcommand, Clone, Debug)]
+enum DebugIndexCommands {
+    Verify(DebugIndexVerifyArgs),
+}
+
+/// Check the commit index for corruption
+///
+/// Checks that the index files match their checksums, that the entries in
+/// them are consistent, and that all visible commits are indexed.
+#[derive(clap::Args, Clone, Debug)]
+struct DebugIndexVerifyArgs {}
 
 /// Rebuild commit index
 #[derive(clap::Args, Clone, Debug)]
-struct DebugReIndexArgs {}
+struct DebugReIndexArgs {
+    /// Keep the index of earlier operations and only index the commits that
+    /// aren't in it
+    #[arg(long)]
+    incremental: bool,
+}
 
 /// Show information about an operation and its view
 #[derive(clap::Args, Clone, Debug)]
@@ -1044,6 +2061,113 @@ struct DebugOperationArgs {
     operation: String,
 }
 
+/// Move loose objects into a pack file
+///
+/// The local backend stores each new object in a file of its own. This moves
+/// them into a single pack file (with an index), which is faster to read on
+/// some filesystems and uses fewer inodes. Commands do this automatically
+/// once there are `store.auto-pack-threshold` loose objects. Git-backed repos
+/// are not affected.
+#[derive(clap::Args, Clone, Debug)]
+struct DebugPackArgs {}
+
+/// Move the repo's objects to another backend
+///
+/// Copies every commit the operation log refers to (with its trees and files)
+/// into a new store using the given backend and rewrites the operation log to
+/// refer to the copies. Then the new store replaces the old one. Commit ids are
+/// kept where both backends hash the objects the same way, but the local and
+/// Git backends don't, so the commits usually get new ids (change ids are
+/// always kept). The old and new ids of the commits whose ids changed are
+/// written to `.jj/repo/store/migrated_commit_ids`.
+///
+/// If the migration is interrupted after the new store has been written, the
+/// next command finishes it. The old store is only deleted once the operation
+/// log refers to the new one.
+///
+/// Repos with more than one workspace can't be migrated, since the other
+/// workspaces would still refer to the old commits.
+#[derive(clap::Args, Clone, Debug)]
+struct DebugMigrateBackendArgs {
+    /// The backend to migrate to
+    #[arg(long, value_enum)]
+    to: BackendKind,
+}
+
+/// List or check the change ids of all visible commits by identity
+///
+/// A commit's identity is a hash of its author, committer, description and
+/// its parents' identities, so it's the same whichever backend stores the
+/// commit. Each line of the output has a commit's identity and its change id.
+/// Save the output before migrating the repo to another backend or
+/// re-importing its commits, then pass the file to `--check` afterwards to
+/// find commits whose change id has changed.
+#[derive(clap::Args, Clone, Debug)]
+struct DebugChangeIdsArgs {
+    /// Compare the change ids with the ones in this file (the output of an
+    /// earlier run) instead of listing them
+    #[arg(long, value_hint = clap::ValueHint::FilePath)]
+    check: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum BackendKind {
+    /// The native backend
+    Local,
+    /// A Git repo inside the `.jj` directory
+    Git,
+}
+
+/// List the hidden working copies used by `jj run`
+///
+/// Shows the tree each of them has checked out and when it was last used, most
+/// recently used first.
+#[derive(clap::Args, Clone, Debug)]
+struct DebugWorkspaceCacheArgs {
+    /// Remove the working copies that aren't in use
+    #[arg(long)]
+    clear: bool,
+}
+
+/// Simplify the conflicts in revisions
+///
+/// Conflicts recorded by older versions, or before merge attributes were
+/// configured, may be resolvable now, e.g. because all sides made the same
+/// change. This rewrites the given revisions with their conflicts simplified
+/// and resolved where possible.
+#[derive(clap::Args, Clone, Debug)]
+struct DebugSimplifyConflictsArgs {
+    /// The revisions to simplify the conflicts in
+    #[arg(long, short, default_value = "@")]
+    revisions: String,
+}
+
+/// Time snapshots of the working copy
+///
+/// Snapshots the working copy the given number of times and prints where the
+/// time went. The snapshots aren't recorded. Since the working copy is usually
+/// snapshotted before the command runs, the files will normally be unchanged;
+/// pass `--no-commit-working-copy` to time the snapshot of the changes too.
+#[derive(clap::Args, Clone, Debug)]
+struct DebugSnapshotBenchArgs {
+    /// How many snapshots to take
+    #[arg(long, default_value_t = 3)]
+    iterations: u32,
+}
+
+/// Time checkouts in the working copy
+///
+/// Copies the working-copy commit into a temporary directory, checks out the
+/// given revision there and then the working-copy commit again, and prints
+/// where the time went in each checkout and in saving the working copy state
+/// afterwards. The workspace's own files are not touched.
+#[derive(clap::Args, Clone, Debug)]
+struct DebugCheckoutBenchArgs {
+    /// The revision to check out before going back to the working-copy commit
+    #[arg(long, short, default_value = "root")]
+    revision: String,
+}
+
 fn add_to_git_exclude(ui: &mut Ui, git_repo: &git2::Repository) -> Result<(), CommandError> {
     let exclude_file_path = git_repo.path().join("info").join("exclude");
     if exclude_file_path.exists() {
@@ -1096,6 +2220,10 @@ fn cmd_init(ui: &mut Ui, command: &CommandHelper, args: &InitArgs) -> Result<(),
             "'--repository' cannot be used with 'init'".to_string(),
         ));
     }
+    if let Some(url) = &args.remote_store {
+        // Check the URL before creating anything
+        object_store_from_url(url).map_err(|err| CommandError::UserError(err.to_string()))?;
+    }
     let wc_path = ui.cwd().join(&args.destination);
     if wc_path.exists() {
         assert!(wc_path.is_dir());
@@ -1110,6 +2238,10 @@ fn cmd_init(ui: &mut Ui, command: &CommandHelper, args: &InitArgs) -> Result<(),
             git_store_path = git_store_path.join(".git");
         }
         git_store_path = git_store_path.canonicalize().unwrap();
+        if let Ok(git_repo) = git2::Repository::open(&git_store_path) {
+            // The working-copy commit is created before the repo is loaded
+            use_git_config_fallback(ui, &git_repo)?;
+        }
         // If the git repo is inside the workspace, use a relative path to it so the
         // whole workspace can be moved without breaking.
         if let Ok(relative_path) = git_store_path.strip_prefix(&wc_path) {
@@ -1141,6 +2273,8 @@ fn cmd_init(ui: &mut Ui, command: &CommandHelper, args: &InitArgs) -> Result<(),
         }
     } else if args.git {
         Workspace::init_internal_git(ui.settings(), &wc_path)?;
+    } else if let Some(url) = &args.remote_store {
+        Workspace::init_remote(ui.settings(), &wc_path, url)?;
     } else {
         Workspace::init_local(ui.settings(), &wc_path)?;
     };
@@ -1199,6 +2333,16 @@ fn cmd_checkout(
     Ok(())
 }
 
+fn cmd_snapshot(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    _args: &SnapshotArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    workspace_command.snapshot_working_copy(ui)?;
+    Ok(())
+}
+
 fn cmd_untrack(
     ui: &mut Ui,
     command: &CommandHelper,
@@ -1213,9 +2357,7 @@ fn cmd_untrack(
     let (mut locked_working_copy, wc_commit) = workspace_command.start_working_copy_mutation()?;
     // Create a new tree without the unwanted files
     let mut tree_builder = store.tree_builder(wc_commit.tree_id().clone());
-    for (path, _value) in wc_commit.tree().entries_matching(matcher.as_ref()) {
-        tree_builder.remove(path);
-    }
+    tree_builder.remove_matching(matcher.as_ref());
     let new_tree_id = tree_builder.write_tree();
     let new_tree = store.get_tree(&RepoPath::root(), &new_tree_id)?;
     // Reset the working copy to the new tree
@@ -1259,42 +2401,195 @@ fn cmd_untrack(
         writeln!(ui, "Rebased {} descendant commits", num_rebased)?;
     }
     let repo = tx.commit();
-    locked_working_copy.finish(repo.op_id().clone());
+    locked_working_copy.finish(repo.op_id().clone())?;
     Ok(())
 }
 
-fn cmd_files(ui: &mut Ui, command: &CommandHelper, args: &FilesArgs) -> Result<(), CommandError> {
-    let workspace_command = command.workspace_helper(ui)?;
-    let commit = workspace_command.resolve_single_rev(&args.revision)?;
-    let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
-    for (name, _value) in commit.tree().entries_matching(matcher.as_ref()) {
-        writeln!(ui, "{}", &workspace_command.format_file_path(&name))?;
-    }
-    Ok(())
+fn cmd_mv(ui: &mut Ui, command: &CommandHelper, args: &MvArgs) -> Result<(), CommandError> {
+    copy_or_move_path(ui, command, &args.source, &args.destination, true)
 }
 
-fn cmd_print(ui: &mut Ui, command: &CommandHelper, args: &PrintArgs) -> Result<(), CommandError> {
-    let workspace_command = command.workspace_helper(ui)?;
-    let commit = workspace_command.resolve_single_rev(&args.revision)?;
-    let path = ui.parse_file_path(workspace_command.workspace_root(), &args.path)?;
-    let repo = workspace_command.repo();
-    match commit.tree().path_value(&path) {
-        None => {
-            return Err(CommandError::UserError("No such path".to_string()));
-        }
-        Some(TreeValue::Normal { id, .. }) => {
-            let mut contents = repo.store().read_file(&path, &id)?;
-            std::io::copy(&mut contents, &mut ui.stdout_formatter().as_mut())?;
-        }
-        Some(TreeValue::Conflict(id)) => {
-            let conflict = repo.store().read_conflict(&path, &id)?;
-            let mut contents = vec![];
-            conflicts::materialize_conflict(repo.store(), &path, &conflict, &mut contents).unwrap();
-            ui.stdout_formatter().write_all(&contents)?;
-        }
-        _ => {
-            return Err(CommandError::UserError(
-                "Path exists but is not a file".to_string(),
+fn cmd_cp(ui: &mut Ui, command: &CommandHelper, args: &CpArgs) -> Result<(), CommandError> {
+    copy_or_move_path(ui, command, &args.source, &args.destination, false)
+}
+
+/// Copies or moves `source` to `destination` in the working-copy commit and
+/// records the copies. Checking out the resulting commit then updates the files
+/// on disk.
+fn copy_or_move_path(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    source: &str,
+    destination: &str,
+    is_move: bool,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let wc_commit = workspace_command.resolve_single_rev("@")?;
+    workspace_command.check_rewriteable(&wc_commit)?;
+    let workspace_root = workspace_command.workspace_root().clone();
+    let source_path = ui.parse_file_path(&workspace_root, source)?;
+    let mut destination_path = ui.parse_file_path(&workspace_root, destination)?;
+    if source_path.is_root() {
+        return Err(UserError(
+            "Cannot move or copy the workspace root".to_string(),
+        ));
+    }
+    let tree = wc_commit.tree();
+    let source_entries = tree
+        .entries_matching(&PrefixMatcher::new(&[source_path.clone()]))
+        .collect_vec();
+    if source_entries.is_empty() {
+        return Err(UserError(format!(
+            "No such path in the working copy: {}",
+            workspace_command.format_file_path(&source_path)
+        )));
+    }
+    // Like `mv` and `cp`, put the source inside the destination if it's a
+    // directory
+    let destination_is_dir = destination_path.is_root()
+        || destination_path.to_fs_path(&workspace_root).is_dir()
+        || tree
+            .entries_matching(&PrefixMatcher::new(&[destination_path.clone()]))
+            .any(|(path, _)| path != destination_path);
+    if destination_is_dir {
+        let (_, basename) = source_path.split().unwrap();
+        destination_path = destination_path.join(basename);
+    }
+    if destination_path == source_path || source_path.contains(&destination_path) {
+        return Err(UserError(format!(
+            "Cannot move or copy {} into itself",
+            workspace_command.format_file_path(&source_path)
+        )));
+    }
+
+    let mut tx = workspace_command.start_transaction(&format!(
+        "{} {} to {}",
+        if is_move { "move" } else { "copy" },
+        workspace_command.format_file_path(&source_path),
+        workspace_command.format_file_path(&destination_path)
+    ));
+    let store = tx.mut_repo().store().clone();
+    let mut tree_builder = store.tree_builder(wc_commit.tree_id().clone());
+    let mut copies = wc_commit.copies().to_vec();
+    for (path, value) in source_entries {
+        let relative_components = &path.components()[source_path.components().len()..];
+        let target = RepoPath::from_components(
+            destination_path
+                .components()
+                .iter()
+                .chain(relative_components)
+                .cloned()
+                .collect(),
+        );
+        let target_fs_path = target.to_fs_path(&workspace_root);
+        if tree.path_value(&target).is_some() || target_fs_path.symlink_metadata().is_ok() {
+            return Err(UserError(format!(
+                "Destination already exists: {}",
+                workspace_command.format_file_path(&target)
+            )));
+        }
+        // If the path was itself copied in this commit, the new path is a copy
+        // of the original source
+        let copy_source = copies
+            .iter()
+            .find(|copy| copy.target == path)
+            .map_or_else(|| path.clone(), |copy| copy.source.clone());
+        if is_move {
+            copies.retain(|copy| copy.target != path);
+            tree_builder.remove(path);
+        }
+        copies.retain(|copy| copy.target != target);
+        copies.push(CopyRecord {
+            source: copy_source,
+            target: target.clone(),
+        });
+        tree_builder.set(target, value);
+    }
+    CommitBuilder::for_rewrite_from(ui.settings(), &wc_commit)
+        .set_tree(tree_builder.write_tree())
+        .set_copies(copies)
+        .write_to_repo(tx.mut_repo());
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
+fn cmd_files(ui: &mut Ui, command: &CommandHelper, args: &FilesArgs) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let commit = workspace_command.resolve_single_rev(&args.revision)?;
+    let matcher: Box<dyn Matcher> = if args.ignore_case && !args.paths.is_empty() {
+        let paths = repo_paths_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
+        Box::new(PrefixMatcher::new_case_insensitive(&paths))
+    } else {
+        matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?
+    };
+    for (name, _value) in commit.tree().entries_matching(matcher.as_ref()) {
+        writeln!(ui, "{}", &workspace_command.format_file_path(&name))?;
+    }
+    Ok(())
+}
+
+fn cmd_archive(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &ArchiveArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let commit = workspace_command.resolve_single_rev(&args.revision)?;
+    let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
+    let output_path = ui.cwd().join(&args.output);
+    let format = match args
+        .format
+        .or_else(|| ArchiveFormat::from_path(&output_path))
+    {
+        Some(format) => format,
+        None => {
+            return Err(CommandError::UserError(format!(
+                "Cannot tell the archive format from the file name {}; use --format",
+                args.output.display()
+            )))
+        }
+    };
+    let mut prefix = args.prefix.clone();
+    if !prefix.is_empty() && !prefix.ends_with('/') {
+        prefix.push('/');
+    }
+    let output = fs::File::create(&output_path)?;
+    write_archive(
+        &commit.tree(),
+        matcher.as_ref(),
+        format,
+        &prefix,
+        &commit.committer().timestamp,
+        output,
+    )?;
+    Ok(())
+}
+
+fn cmd_print(ui: &mut Ui, command: &CommandHelper, args: &PrintArgs) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let commit = workspace_command.resolve_single_rev(&args.revision)?;
+    let path = ui.parse_file_path(workspace_command.workspace_root(), &args.path)?;
+    let repo = workspace_command.repo();
+    match commit.tree().path_value(&path) {
+        None => {
+            return Err(CommandError::UserError("No such path".to_string()));
+        }
+        Some(TreeValue::Normal { id, .. }) => {
+            let mut contents = repo.store().read_file(&path, &id)?;
+            std::io::copy(&mut contents, &mut ui.stdout_formatter().as_mut())?;
+        }
+        Some(TreeValue::Conflict(id)) => {
+            let conflict_style = if args.no_materialize {
+                ConflictStyle::Sides
+            } else {
+                ConflictStyle::Materialized
+            };
+            let contents = conflict_content(repo.store(), &path, &id, conflict_style)?;
+            ui.stdout_formatter().write_all(&contents)?;
+        }
+        _ => {
+            return Err(CommandError::UserError(
+                "Path exists but is not a file".to_string(),
             ));
         }
     }
@@ -1405,50 +2700,325 @@ fn show_color_words_diff_line(
 }
 
 fn cmd_diff(ui: &mut Ui, command: &CommandHelper, args: &DiffArgs) -> Result<(), CommandError> {
+    if let (Some(from_file), Some(to_file)) = (&args.from_file, &args.to_file) {
+        let file_diffs = collect_file_diffs(ui.cwd(), from_file, to_file)?;
+        let format = diff_format_for(ui, &args.format);
+        return show_file_diffs(ui.stdout_formatter().as_mut(), &file_diffs, format);
+    }
     let workspace_command = command.workspace_helper(ui)?;
     let from_tree;
     let to_tree;
+    let copies;
     if args.from.is_some() || args.to.is_some() {
         let from = workspace_command.resolve_single_rev(args.from.as_deref().unwrap_or("@"))?;
         from_tree = from.tree();
         let to = workspace_command.resolve_single_rev(args.to.as_deref().unwrap_or("@"))?;
         to_tree = to.tree();
+        copies = vec![];
     } else {
         let commit =
             workspace_command.resolve_single_rev(args.revision.as_deref().unwrap_or("@"))?;
         let parents = commit.parents();
         from_tree = merge_commit_trees(workspace_command.repo().as_repo_ref(), &parents);
-        to_tree = commit.tree()
+        to_tree = commit.tree();
+        copies = resolve_copies(&from_tree, &to_tree, commit.copies());
     }
     let workspace_root = workspace_command.workspace_root();
     let matcher = matcher_from_values(ui, workspace_root, &args.paths)?;
     let diff_iterator = from_tree.diff(&to_tree, matcher.as_ref());
+    let conflict_style = if args.no_materialize {
+        ConflictStyle::Sides
+    } else {
+        ConflictStyle::Materialized
+    };
     show_diff(
         ui.stdout_formatter().as_mut(),
         &workspace_command,
         diff_iterator,
+        &copies,
         diff_format_for(ui, &args.format),
+        conflict_style,
     )?;
     Ok(())
 }
 
+/// A file that differs between the two sides of `jj diff --from-file --to-file`.
+struct FileDiff {
+    left_name: String,
+    right_name: String,
+    left_content: Option<Vec<u8>>,
+    right_content: Option<Vec<u8>>,
+    /// The Git file modes of the two sides, if they exist
+    left_mode: &'static str,
+    right_mode: &'static str,
+}
+
+impl FileDiff {
+    /// The name to show for the file in formats that show a single name.
+    fn name(&self) -> &str {
+        if self.right_content.is_some() {
+            &self.right_name
+        } else {
+            &self.left_name
+        }
+    }
+}
+
+/// Collects the regular files and symlinks under `dir`, keyed by their path
+/// relative to it with "/" as separator. Symlinks to directories aren't
+/// followed.
+fn collect_dir_files(
+    dir: &Path,
+    prefix: &str,
+    files: &mut BTreeMap<String, PathBuf>,
+) -> Result<(), CommandError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy();
+        let relative = format!("{prefix}{name}");
+        let file_type = fs::symlink_metadata(&path)?.file_type();
+        if file_type.is_dir() {
+            collect_dir_files(&path, &format!("{relative}/"), files)?;
+        } else if file_type.is_file() || file_type.is_symlink() {
+            files.insert(relative, path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// Reads a file on disk, or a symlink's target, and returns its contents and
+/// Git file mode.
+fn read_disk_file(path: &Path) -> Result<(Vec<u8>, &'static str), CommandError> {
+    let read = || -> io::Result<_> {
+        let metadata = fs::symlink_metadata(path)?;
+        if metadata.file_type().is_symlink() {
+            let target = fs::read_link(path)?;
+            Ok((target.to_string_lossy().into_owned().into_bytes(), "120000"))
+        } else if is_executable(&metadata) {
+            Ok((fs::read(path)?, "100755"))
+        } else {
+            Ok((fs::read(path)?, "100644"))
+        }
+    };
+    read()
+        .map_err(|err| CommandError::UserError(format!("Failed to read {}: {err}", path.display())))
+}
+
+/// Finds the differences between two files, or between the files in two
+/// directories, on disk.
+fn collect_file_diffs(cwd: &Path, from: &Path, to: &Path) -> Result<Vec<FileDiff>, CommandError> {
+    let from_path = cwd.join(from);
+    let to_path = cwd.join(to);
+    let new_file_diff = |left_name: String,
+                         right_name: String,
+                         left: Option<(Vec<u8>, &'static str)>,
+                         right: Option<(Vec<u8>, &'static str)>| {
+        if left == right {
+            return None;
+        }
+        let (left_content, left_mode) =
+            left.map_or((None, ""), |(content, mode)| (Some(content), mode));
+        let (right_content, right_mode) =
+            right.map_or((None, ""), |(content, mode)| (Some(content), mode));
+        Some(FileDiff {
+            left_name,
+            right_name,
+            left_content,
+            right_content,
+            left_mode,
+            right_mode,
+        })
+    };
+    match (from_path.is_dir(), to_path.is_dir()) {
+        (false, false) => {
+            let left = read_disk_file(&from_path)?;
+            let right = read_disk_file(&to_path)?;
+            Ok(new_file_diff(
+                from.to_string_lossy().into_owned(),
+                to.to_string_lossy().into_owned(),
+                Some(left),
+                Some(right),
+            )
+            .into_iter()
+            .collect())
+        }
+        (true, true) => {
+            let mut left_files = BTreeMap::new();
+            collect_dir_files(&from_path, "", &mut left_files)?;
+            let mut right_files = BTreeMap::new();
+            collect_dir_files(&to_path, "", &mut right_files)?;
+            let mut file_diffs = vec![];
+            for entry in left_files
+                .into_iter()
+                .merge_join_by(right_files, |(left, _), (right, _)| left.cmp(right))
+            {
+                let (name, left, right) = match entry {
+                    EitherOrBoth::Left((name, left)) => (name, Some(read_disk_file(&left)?), None),
+                    EitherOrBoth::Right((name, right)) => {
+                        (name, None, Some(read_disk_file(&right)?))
+                    }
+                    EitherOrBoth::Both((name, left), (_, right)) => (
+                        name,
+                        Some(read_disk_file(&left)?),
+                        Some(read_disk_file(&right)?),
+                    ),
+                };
+                file_diffs.extend(new_file_diff(name.clone(), name, left, right));
+            }
+            Ok(file_diffs)
+        }
+        _ => Err(CommandError::UserError(format!(
+            "Cannot compare a file with a directory: {} and {}",
+            from.display(),
+            to.display()
+        ))),
+    }
+}
+
+/// Like `show_diff()`, but for files on disk instead of trees.
+fn show_file_diffs(
+    formatter: &mut dyn Formatter,
+    file_diffs: &[FileDiff],
+    format: DiffFormat,
+) -> Result<(), CommandError> {
+    if format == DiffFormat::Stat {
+        let stats = file_diffs
+            .iter()
+            .map(|file_diff| {
+                let (added, removed) = count_changed_lines(
+                    file_diff.left_content.as_deref().unwrap_or_default(),
+                    file_diff.right_content.as_deref().unwrap_or_default(),
+                );
+                (file_diff.name().to_string(), added, removed)
+            })
+            .collect_vec();
+        return show_diff_stat_lines(formatter, &stats);
+    }
+    if format == DiffFormat::Json {
+        let files = file_diffs
+            .iter()
+            .map(|file_diff| {
+                let status = match (&file_diff.left_content, &file_diff.right_content) {
+                    (None, _) => "added",
+                    (_, None) => "removed",
+                    _ => "modified",
+                };
+                json!({
+                    "path": file_diff.name(),
+                    "status": status,
+                    "hunks": json_diff_hunks(
+                        file_diff.left_content.as_deref().unwrap_or_default(),
+                        file_diff.right_content.as_deref().unwrap_or_default(),
+                    ),
+                })
+            })
+            .collect_vec();
+        writeln!(
+            formatter,
+            "{}",
+            serde_json::to_string_pretty(&files).unwrap()
+        )?;
+        return Ok(());
+    }
+    formatter.add_label(String::from("diff"))?;
+    for file_diff in file_diffs {
+        let left_content = file_diff.left_content.as_deref().unwrap_or_default();
+        let right_content = file_diff.right_content.as_deref().unwrap_or_default();
+        match format {
+            DiffFormat::Summary => {
+                let (label, status) = match (&file_diff.left_content, &file_diff.right_content) {
+                    (None, _) => ("added", "A"),
+                    (_, None) => ("removed", "R"),
+                    _ => ("modified", "M"),
+                };
+                formatter.add_label(String::from(label))?;
+                writeln!(formatter, "{status} {}", file_diff.name())?;
+                formatter.remove_label()?;
+            }
+            DiffFormat::Git => {
+                formatter.add_label(String::from("file_header"))?;
+                writeln!(
+                    formatter,
+                    "diff --git a/{} b/{}",
+                    file_diff.left_name, file_diff.right_name
+                )?;
+                match (&file_diff.left_content, &file_diff.right_content) {
+                    (None, _) => {
+                        writeln!(formatter, "new file mode {}", file_diff.right_mode)?;
+                        writeln!(formatter, "--- /dev/null")?;
+                        writeln!(formatter, "+++ b/{}", file_diff.right_name)?;
+                    }
+                    (_, None) => {
+                        writeln!(formatter, "deleted file mode {}", file_diff.left_mode)?;
+                        writeln!(formatter, "--- a/{}", file_diff.left_name)?;
+                        writeln!(formatter, "+++ /dev/null")?;
+                    }
+                    _ => {
+                        if file_diff.left_mode != file_diff.right_mode {
+                            writeln!(formatter, "old mode {}", file_diff.left_mode)?;
+                            writeln!(formatter, "new mode {}", file_diff.right_mode)?;
+                        }
+                        if left_content != right_content {
+                            writeln!(formatter, "--- a/{}", file_diff.left_name)?;
+                            writeln!(formatter, "+++ b/{}", file_diff.right_name)?;
+                        }
+                    }
+                }
+                formatter.remove_label()?;
+                show_unified_diff_hunks(formatter, left_content, right_content)?;
+            }
+            DiffFormat::ColorWords => {
+                let verb = match (&file_diff.left_content, &file_diff.right_content) {
+                    (None, _) => "Added",
+                    (_, None) => "Removed",
+                    _ => "Modified",
+                };
+                formatter.add_label(String::from("header"))?;
+                formatter.write_str(&format!("{verb} regular file {}:\n", file_diff.name()))?;
+                formatter.remove_label()?;
+                show_color_words_diff_hunks(left_content, right_content, formatter)?;
+            }
+            DiffFormat::Stat | DiffFormat::Json => unreachable!(),
+        }
+    }
+    formatter.remove_label()?;
+    Ok(())
+}
+
 fn cmd_show(ui: &mut Ui, command: &CommandHelper, args: &ShowArgs) -> Result<(), CommandError> {
     let workspace_command = command.workspace_helper(ui)?;
     let commit = workspace_command.resolve_single_rev(&args.revision)?;
     let parents = commit.parents();
     let from_tree = merge_commit_trees(workspace_command.repo().as_repo_ref(), &parents);
     let to_tree = commit.tree();
+    let copies = resolve_copies(&from_tree, &to_tree, commit.copies());
     let diff_iterator = from_tree.diff(&to_tree, &EverythingMatcher);
     // TODO: Add branches, tags, etc
     // TODO: Indent the description like Git does
-    let template_string = r#"
+    let author_timestamp = timestamp_template(ui.settings(), "author.timestamp()")?;
+    let committer_timestamp = timestamp_template(ui.settings(), "committer.timestamp()")?;
+    let default_template = format!(
+        r#"
             "Commit ID: " commit_id "\n"
             "Change ID: " change_id "\n"
-            "Author: " author " <" author.email() "> (" author.timestamp() ")\n"
-            "Committer: " committer " <" committer.email() "> (" committer.timestamp() ")\n"
+            "Author: " author " <" author.email() "> (" {author_timestamp} ")\n"
+            "Committer: " committer " <" committer.email() "> (" {committer_timestamp} ")\n"
             "\n"
             description
-            "\n""#;
+            "\n""#
+    );
+    let template_string = args.template.as_deref().unwrap_or(&default_template);
     let template_string = if ui.settings().enable_open_commits() {
         format!(
             r#"
@@ -1462,14 +3032,30 @@ fn cmd_show(ui: &mut Ui, command: &CommandHelper, args: &ShowArgs) -> Result<(),
         &workspace_command.workspace_id(),
         &template_string,
     );
+    let repo = workspace_command.repo();
+    let note = match repo.store().git_repo() {
+        Some(git_repo) => git::get_note(&git_repo, &repo.settings().git_notes_ref(), commit.id())?,
+        None => None,
+    };
     let mut formatter = ui.stdout_formatter();
     let formatter = formatter.as_mut();
     template.format(&commit, formatter)?;
+    if let Some(note) = note {
+        formatter.add_label(String::from("notes"))?;
+        writeln!(formatter, "Notes:")?;
+        for line in note.lines() {
+            writeln!(formatter, "    {line}")?;
+        }
+        writeln!(formatter)?;
+        formatter.remove_label()?;
+    }
     show_diff(
         formatter,
         &workspace_command,
         diff_iterator,
+        &copies,
         diff_format_for(ui, &args.format),
+        ConflictStyle::Materialized,
     )?;
     Ok(())
 }
@@ -1477,22 +3063,30 @@ fn cmd_show(ui: &mut Ui, command: &CommandHelper, args: &ShowArgs) -> Result<(),
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum DiffFormat {
     Summary,
+    Stat,
     Git,
     ColorWords,
+    Json,
 }
 
 fn diff_format_for(ui: &Ui, args: &DiffFormatArgs) -> DiffFormat {
     if args.summary {
         DiffFormat::Summary
+    } else if args.stat {
+        DiffFormat::Stat
     } else if args.git {
         DiffFormat::Git
     } else if args.color_words {
         DiffFormat::ColorWords
+    } else if args.json {
+        DiffFormat::Json
     } else {
         match ui.settings().config().get_string("diff.format") {
             Ok(value) if &value == "summary" => DiffFormat::Summary,
+            Ok(value) if &value == "stat" => DiffFormat::Stat,
             Ok(value) if &value == "git" => DiffFormat::Git,
             Ok(value) if &value == "color-words" => DiffFormat::ColorWords,
+            Ok(value) if &value == "json" => DiffFormat::Json,
             _ => DiffFormat::ColorWords,
         }
     }
@@ -1502,54 +3096,34 @@ fn show_diff(
     formatter: &mut dyn Formatter,
     workspace_command: &WorkspaceCommandHelper,
     tree_diff: TreeDiffIterator,
+    copies: &[CopiedPath],
     format: DiffFormat,
+    conflict_style: ConflictStyle,
 ) -> Result<(), CommandError> {
     match format {
         DiffFormat::Summary => {
-            show_diff_summary(formatter, workspace_command, tree_diff)?;
+            show_diff_summary(formatter, workspace_command, tree_diff, copies)?;
+        }
+        DiffFormat::Stat => {
+            show_diff_stat(formatter, workspace_command, tree_diff)?;
         }
         DiffFormat::Git => {
-            show_git_diff(formatter, workspace_command, tree_diff)?;
+            show_git_diff(formatter, workspace_command, tree_diff, conflict_style)?;
         }
         DiffFormat::ColorWords => {
-            show_color_words_diff(formatter, workspace_command, tree_diff)?;
-        }
-    }
-    Ok(())
-}
-
-fn diff_content(
-    repo: &Arc<ReadonlyRepo>,
-    path: &RepoPath,
-    value: &TreeValue,
-) -> Result<Vec<u8>, CommandError> {
-    match value {
-        TreeValue::Normal { id, .. } => {
-            let mut file_reader = repo.store().read_file(path, id).unwrap();
-            let mut content = vec![];
-            file_reader.read_to_end(&mut content)?;
-            Ok(content)
-        }
-        TreeValue::Symlink(id) => {
-            let target = repo.store().read_symlink(path, id)?;
-            Ok(target.into_bytes())
-        }
-        TreeValue::Tree(_) => {
-            panic!(
-                "Got an unexpected tree in a diff of path {}",
-                path.to_internal_file_string()
-            );
-        }
-        TreeValue::GitSubmodule(id) => {
-            Ok(format!("Git submodule checked out at {}", id.hex()).into_bytes())
+            show_color_words_diff(formatter, workspace_command, tree_diff, conflict_style)?;
         }
-        TreeValue::Conflict(id) => {
-            let conflict = repo.store().read_conflict(path, id).unwrap();
-            let mut content = vec![];
-            conflicts::materialize_conflict(repo.store(), path, &conflict, &mut content).unwrap();
-            Ok(content)
+        DiffFormat::Json => {
+            show_json_diff(
+                formatter,
+                workspace_command,
+                tree_diff,
+                copies,
+                conflict_style,
+            )?;
         }
     }
+    Ok(())
 }
 
 fn basic_diff_file_type(value: &TreeValue) -> String {
@@ -1572,6 +3146,7 @@ fn show_color_words_diff(
     formatter: &mut dyn Formatter,
     workspace_command: &WorkspaceCommandHelper,
     tree_diff: TreeDiffIterator,
+    conflict_style: ConflictStyle,
 ) -> Result<(), CommandError> {
     let repo = workspace_command.repo();
     formatter.add_label(String::from("diff"))?;
@@ -1579,7 +3154,8 @@ fn show_color_words_diff(
         let ui_path = workspace_command.format_file_path(&path);
         match diff {
             tree::Diff::Added(right_value) => {
-                let right_content = diff_content(repo, &path, &right_value)?;
+                let right_content =
+                    diff_content(repo.store(), &path, &right_value, conflict_style)?;
                 let description = basic_diff_file_type(&right_value);
                 formatter.add_label(String::from("header"))?;
                 formatter.write_str(&format!("Added {} {}:\n", description, ui_path))?;
@@ -1587,8 +3163,9 @@ fn show_color_words_diff(
                 show_color_words_diff_hunks(&[], &right_content, formatter)?;
             }
             tree::Diff::Modified(left_value, right_value) => {
-                let left_content = diff_content(repo, &path, &left_value)?;
-                let right_content = diff_content(repo, &path, &right_value)?;
+                let left_content = diff_content(repo.store(), &path, &left_value, conflict_style)?;
+                let right_content =
+                    diff_content(repo.store(), &path, &right_value, conflict_style)?;
                 let description = match (left_value, right_value) {
                     (
                         TreeValue::Normal {
@@ -1636,7 +3213,7 @@ fn show_color_words_diff(
                 show_color_words_diff_hunks(&left_content, &right_content, formatter)?;
             }
             tree::Diff::Removed(left_value) => {
-                let left_content = diff_content(repo, &path, &left_value)?;
+                let left_content = diff_content(repo.store(), &path, &left_value, conflict_style)?;
                 let description = basic_diff_file_type(&left_value);
                 formatter.add_label(String::from("header"))?;
                 formatter.write_str(&format!("Removed {} {}:\n", description, ui_path))?;
@@ -1659,6 +3236,7 @@ fn git_diff_part(
     repo: &Arc<ReadonlyRepo>,
     path: &RepoPath,
     value: &TreeValue,
+    conflict_style: ConflictStyle,
 ) -> Result<GitDiffPart, CommandError> {
     let mode;
     let hash;
@@ -1694,8 +3272,7 @@ fn git_diff_part(
         TreeValue::Conflict(id) => {
             mode = "100644".to_string();
             hash = id.hex();
-            let conflict = repo.store().read_conflict(path, id).unwrap();
-            conflicts::materialize_conflict(repo.store(), path, &conflict, &mut content).unwrap();
+            content = conflict_content(repo.store(), path, id, conflict_style)?;
         }
     }
     let hash = hash[0..10].to_string();
@@ -1848,6 +3425,7 @@ fn show_git_diff(
     formatter: &mut dyn Formatter,
     workspace_command: &WorkspaceCommandHelper,
     tree_diff: TreeDiffIterator,
+    conflict_style: ConflictStyle,
 ) -> Result<(), CommandError> {
     let repo = workspace_command.repo();
     formatter.add_label(String::from("diff"))?;
@@ -1857,7 +3435,7 @@ fn show_git_diff(
         writeln!(formatter, "diff --git a/{} b/{}", path_string, path_string)?;
         match diff {
             tree::Diff::Added(right_value) => {
-                let right_part = git_diff_part(repo, &path, &right_value)?;
+                let right_part = git_diff_part(repo, &path, &right_value, conflict_style)?;
                 writeln!(formatter, "new file mode {}", &right_part.mode)?;
                 writeln!(formatter, "index 0000000000..{}", &right_part.hash)?;
                 writeln!(formatter, "--- /dev/null")?;
@@ -1866,8 +3444,8 @@ fn show_git_diff(
                 show_unified_diff_hunks(formatter, &[], &right_part.content)?;
             }
             tree::Diff::Modified(left_value, right_value) => {
-                let left_part = git_diff_part(repo, &path, &left_value)?;
-                let right_part = git_diff_part(repo, &path, &right_value)?;
+                let left_part = git_diff_part(repo, &path, &left_value, conflict_style)?;
+                let right_part = git_diff_part(repo, &path, &right_value, conflict_style)?;
                 if left_part.mode != right_part.mode {
                     writeln!(formatter, "old mode {}", &left_part.mode)?;
                     writeln!(formatter, "new mode {}", &right_part.mode)?;
@@ -1889,7 +3467,7 @@ fn show_git_diff(
                 show_unified_diff_hunks(formatter, &left_part.content, &right_part.content)?;
             }
             tree::Diff::Removed(left_value) => {
-                let left_part = git_diff_part(repo, &path, &left_value)?;
+                let left_part = git_diff_part(repo, &path, &left_value, conflict_style)?;
                 writeln!(formatter, "deleted file mode {}", &left_part.mode)?;
                 writeln!(formatter, "index {}..0000000000", &left_part.hash)?;
                 writeln!(formatter, "--- a/{}", path_string)?;
@@ -1907,6 +3485,7 @@ fn show_diff_summary(
     formatter: &mut dyn Formatter,
     workspace_command: &WorkspaceCommandHelper,
     tree_diff: TreeDiffIterator,
+    copies: &[CopiedPath],
 ) -> io::Result<()> {
     formatter.add_label(String::from("diff"))?;
     for (repo_path, diff) in tree_diff {
@@ -1922,11 +3501,23 @@ fn show_diff_summary(
             }
             tree::Diff::Added(_) => {
                 formatter.add_label(String::from("added"))?;
-                writeln!(
+                write!(
                     formatter,
                     "A {}",
                     workspace_command.format_file_path(&repo_path)
                 )?;
+                if let Some(copy) = copies.iter().find(|copy| copy.target == repo_path) {
+                    let verb = match copy.operation {
+                        CopyOperation::Copy => "copied",
+                        CopyOperation::Rename => "renamed",
+                    };
+                    write!(
+                        formatter,
+                        " ({verb} from {})",
+                        workspace_command.format_file_path(&copy.source)
+                    )?;
+                }
+                writeln!(formatter)?;
                 formatter.remove_label()?;
             }
             tree::Diff::Removed(_) => {
@@ -1944,52 +3535,312 @@ fn show_diff_summary(
     Ok(())
 }
 
-fn cmd_status(
-    ui: &mut Ui,
-    command: &CommandHelper,
-    _args: &StatusArgs,
-) -> Result<(), CommandError> {
-    let workspace_command = command.workspace_helper(ui)?;
-    let repo = workspace_command.repo();
-    let maybe_checkout_id = repo
-        .view()
-        .get_wc_commit_id(&workspace_command.workspace_id());
-    let maybe_checkout = maybe_checkout_id
-        .map(|id| repo.store().get_commit(id))
-        .transpose()?;
-    if let Some(wc_commit) = &maybe_checkout {
-        ui.write("Parent commit: ")?;
-        let workspace_id = workspace_command.workspace_id();
-        ui.write_commit_summary(repo.as_repo_ref(), &workspace_id, &wc_commit.parents()[0])?;
-        ui.write("\n")?;
-        ui.write("Working copy : ")?;
-        ui.write_commit_summary(repo.as_repo_ref(), &workspace_id, wc_commit)?;
-        ui.write("\n")?;
-    } else {
-        ui.write("No working copy\n")?;
+fn json_diff_value(value: &TreeValue) -> serde_json::Value {
+    match value {
+        TreeValue::Normal { id, executable } => {
+            json!({"type": "file", "id": id.hex(), "executable": executable})
+        }
+        TreeValue::Symlink(id) => json!({"type": "symlink", "id": id.hex()}),
+        TreeValue::Tree(id) => json!({"type": "tree", "id": id.hex()}),
+        TreeValue::GitSubmodule(id) => json!({"type": "submodule", "id": id.hex()}),
+        TreeValue::Conflict(id) => json!({"type": "conflict", "id": id.hex()}),
     }
+}
 
-    let mut conflicted_local_branches = vec![];
-    let mut conflicted_remote_branches = vec![];
-    for (branch_name, branch_target) in repo.view().branches() {
-        if let Some(local_target) = &branch_target.local_target {
-            if local_target.is_conflict() {
-                conflicted_local_branches.push(branch_name.clone());
+/// The changed line ranges between two versions of a file, as JSON objects.
+fn json_diff_hunks(left_content: &[u8], right_content: &[u8]) -> Vec<serde_json::Value> {
+    let mut hunks = vec![];
+    let mut left_line = 1;
+    let mut right_line = 1;
+    let diff = Diff::for_tokenizer(&[left_content, right_content], &diff::find_line_ranges);
+    for hunk in diff.hunks() {
+        match hunk {
+            DiffHunk::Matching(content) => {
+                let num_lines = content.split_inclusive(|b| *b == b'\n').count();
+                left_line += num_lines;
+                right_line += num_lines;
+            }
+            DiffHunk::Different(contents) => {
+                let left_lines = contents[0].split_inclusive(|b| *b == b'\n').count();
+                let right_lines = contents[1].split_inclusive(|b| *b == b'\n').count();
+                hunks.push(json!({
+                    "old_start": left_line,
+                    "old_lines": left_lines,
+                    "new_start": right_line,
+                    "new_lines": right_lines,
+                }));
+                left_line += left_lines;
+                right_line += right_lines;
             }
         }
-        for (remote_name, remote_target) in &branch_target.remote_targets {
-            if remote_target.is_conflict() {
-                conflicted_remote_branches.push((branch_name.clone(), remote_name.clone()));
+    }
+    hunks
+}
+
+/// Prints the diff as a JSON array with an object per path. Line numbers in the
+/// hunks are 1-based, and hunks don't include any context lines.
+fn show_json_diff(
+    formatter: &mut dyn Formatter,
+    workspace_command: &WorkspaceCommandHelper,
+    tree_diff: TreeDiffIterator,
+    copies: &[CopiedPath],
+    conflict_style: ConflictStyle,
+) -> Result<(), CommandError> {
+    let store = workspace_command.repo().store();
+    let mut files = vec![];
+    for (path, diff) in tree_diff {
+        let (status, left_value, right_value) = match &diff {
+            tree::Diff::Added(right_value) => ("added", None, Some(right_value)),
+            tree::Diff::Modified(left_value, right_value) => {
+                ("modified", Some(left_value), Some(right_value))
             }
+            tree::Diff::Removed(left_value) => ("removed", Some(left_value), None),
+        };
+        let left_content = match left_value {
+            Some(value) => diff_content(store, &path, value, conflict_style)?,
+            None => vec![],
+        };
+        let right_content = match right_value {
+            Some(value) => diff_content(store, &path, value, conflict_style)?,
+            None => vec![],
+        };
+        let hunks = json_diff_hunks(&left_content, &right_content);
+        let mut file = json!({
+            "path": path.to_internal_file_string(),
+            "status": status,
+            "old": left_value.map(json_diff_value),
+            "new": right_value.map(json_diff_value),
+            "hunks": hunks,
+        });
+        if let Some(copy) = copies.iter().find(|copy| copy.target == path) {
+            let key = match copy.operation {
+                CopyOperation::Copy => "copied_from",
+                CopyOperation::Rename => "renamed_from",
+            };
+            file[key] = json!(copy.source.to_internal_file_string());
         }
+        files.push(file);
     }
-    if !conflicted_local_branches.is_empty() {
-        ui.stdout_formatter().add_label("conflict".to_string())?;
-        writeln!(ui, "These branches have conflicts:")?;
-        ui.stdout_formatter().remove_label()?;
-        for branch_name in conflicted_local_branches {
-            write!(ui, "  ")?;
-            ui.stdout_formatter().add_label("branch".to_string())?;
+    writeln!(
+        formatter,
+        "{}",
+        serde_json::to_string_pretty(&files).unwrap()
+    )?;
+    Ok(())
+}
+
+// The maximum width of the histogram in `--stat` output
+const DIFF_STAT_MAX_BAR_WIDTH: usize = 40;
+
+fn show_diff_stat(
+    formatter: &mut dyn Formatter,
+    workspace_command: &WorkspaceCommandHelper,
+    tree_diff: TreeDiffIterator,
+) -> Result<(), CommandError> {
+    let stats = diff_stats(workspace_command.repo().store(), tree_diff)?
+        .into_iter()
+        .map(|stat| {
+            (
+                workspace_command.format_file_path(&stat.path),
+                stat.added,
+                stat.removed,
+            )
+        })
+        .collect_vec();
+    show_diff_stat_lines(formatter, &stats)
+}
+
+/// Prints a `--stat` line for each (path, added, removed) entry, and a total.
+fn show_diff_stat_lines(
+    formatter: &mut dyn Formatter,
+    stats: &[(String, usize, usize)],
+) -> Result<(), CommandError> {
+    let max_path_width = stats.iter().map(|(path, ..)| path.len()).max().unwrap_or(0);
+    let max_changes = stats
+        .iter()
+        .map(|(_, added, removed)| added + removed)
+        .max()
+        .unwrap_or(0);
+    let number_width = max_changes.to_string().len();
+    // Scale the bars down if they don't fit, but keep at least one character for
+    // any change
+    let bar_width = |count: usize| {
+        if max_changes <= DIFF_STAT_MAX_BAR_WIDTH || count == 0 {
+            count
+        } else {
+            (count * DIFF_STAT_MAX_BAR_WIDTH / max_changes).max(1)
+        }
+    };
+    formatter.add_label(String::from("diff"))?;
+    let mut total_added = 0;
+    let mut total_removed = 0;
+    for (path, added, removed) in stats {
+        total_added += added;
+        total_removed += removed;
+        write!(
+            formatter,
+            "{path:max_path_width$} | {:>number_width$}",
+            added + removed
+        )?;
+        if added + removed > 0 {
+            write!(formatter, " ")?;
+        }
+        formatter.add_label(String::from("added"))?;
+        write!(formatter, "{}", "+".repeat(bar_width(*added)))?;
+        formatter.remove_label()?;
+        formatter.add_label(String::from("removed"))?;
+        write!(formatter, "{}", "-".repeat(bar_width(*removed)))?;
+        formatter.remove_label()?;
+        writeln!(formatter)?;
+    }
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    writeln!(
+        formatter,
+        "{} file{} changed, {} insertion{}(+), {} deletion{}(-)",
+        stats.len(),
+        plural(stats.len()),
+        total_added,
+        plural(total_added),
+        total_removed,
+        plural(total_removed),
+    )?;
+    formatter.remove_label()?;
+    Ok(())
+}
+
+// How many commits to walk at most when counting how far a branch is ahead of or
+// behind its remote branch
+const DIVERGENCE_LIMIT: usize = 1000;
+
+fn format_divergence_count(count: usize) -> String {
+    if count >= DIVERGENCE_LIMIT {
+        format!("{DIVERGENCE_LIMIT}+")
+    } else {
+        count.to_string()
+    }
+}
+
+fn cmd_status(ui: &mut Ui, command: &CommandHelper, args: &StatusArgs) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo();
+    let workspace_id = workspace_command.workspace_id();
+    let maybe_checkout_id = repo.view().get_wc_commit_id(&workspace_id);
+    let maybe_checkout = maybe_checkout_id
+        .map(|id| repo.store().get_commit(id))
+        .transpose()?;
+    let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
+    if let Some(wc_commit) = &maybe_checkout {
+        let summary_template = match &args.template {
+            Some(template) => template.clone(),
+            None => ui.commit_summary_template(),
+        };
+        for parent in wc_commit.parents() {
+            ui.write("Parent commit: ")?;
+            ui.write_commit_summary_with_template(
+                repo.as_repo_ref(),
+                &workspace_id,
+                &parent,
+                &summary_template,
+            )?;
+            ui.write("\n")?;
+        }
+        ui.write("Working copy : ")?;
+        ui.write_commit_summary_with_template(
+            repo.as_repo_ref(),
+            &workspace_id,
+            wc_commit,
+            &summary_template,
+        )?;
+        ui.write("\n")?;
+
+        let mut other_workspaces = vec![];
+        for (other_workspace_id, other_commit_id) in repo.view().wc_commit_ids() {
+            if *other_workspace_id == workspace_id {
+                continue;
+            }
+            let other_commit = repo.store().get_commit(other_commit_id)?;
+            if other_commit.change_id() == wc_commit.change_id() {
+                other_workspaces.push(other_workspace_id.as_str().to_string());
+            }
+        }
+        other_workspaces.sort();
+        if !other_workspaces.is_empty() {
+            writeln!(
+                ui,
+                "The working copy change is also checked out in {}: {}",
+                if other_workspaces.len() == 1 {
+                    "workspace"
+                } else {
+                    "workspaces"
+                },
+                other_workspaces.join(", ")
+            )?;
+        }
+
+        // Show how the branches on the working copy or its parents compare to
+        // their remote branches
+        let index = repo.index();
+        for (branch_name, branch_target) in repo.view().branches() {
+            let local_target = match &branch_target.local_target {
+                Some(local_target @ RefTarget::Normal(id))
+                    if id == wc_commit.id() || wc_commit.parent_ids().contains(id) =>
+                {
+                    local_target
+                }
+                _ => continue,
+            };
+            for (remote_name, remote_target) in &branch_target.remote_targets {
+                let divergence = ref_divergence_bounded(
+                    index.as_index_ref(),
+                    local_target,
+                    remote_target,
+                    DIVERGENCE_LIMIT,
+                );
+                let (ahead, behind) = (
+                    format_divergence_count(divergence.ahead),
+                    format_divergence_count(divergence.behind),
+                );
+                let comparison = match (divergence.ahead, divergence.behind) {
+                    (0, 0) => continue,
+                    (_, 0) => format!("{ahead} ahead of"),
+                    (0, _) => format!("{behind} behind"),
+                    (_, _) => format!("{ahead} ahead, {behind} behind"),
+                };
+                ui.stdout_formatter().add_label("branch".to_string())?;
+                write!(ui, "{branch_name}")?;
+                ui.stdout_formatter().remove_label()?;
+                write!(ui, " is {comparison} ")?;
+                ui.stdout_formatter().add_label("branch".to_string())?;
+                write!(ui, "{branch_name}@{remote_name}")?;
+                ui.stdout_formatter().remove_label()?;
+                writeln!(ui)?;
+            }
+        }
+    } else {
+        ui.write("No working copy\n")?;
+    }
+
+    let mut conflicted_local_branches = vec![];
+    let mut conflicted_remote_branches = vec![];
+    for (branch_name, branch_target) in repo.view().branches() {
+        if let Some(local_target) = &branch_target.local_target {
+            if local_target.is_conflict() {
+                conflicted_local_branches.push(branch_name.clone());
+            }
+        }
+        for (remote_name, remote_target) in &branch_target.remote_targets {
+            if remote_target.is_conflict() {
+                conflicted_remote_branches.push((branch_name.clone(), remote_name.clone()));
+            }
+        }
+    }
+    if !conflicted_local_branches.is_empty() {
+        ui.stdout_formatter().add_label("conflict".to_string())?;
+        writeln!(ui, "These branches have conflicts:")?;
+        ui.stdout_formatter().remove_label()?;
+        for branch_name in conflicted_local_branches {
+            write!(ui, "  ")?;
+            ui.stdout_formatter().add_label("branch".to_string())?;
             write!(ui, "{}", branch_name)?;
             ui.stdout_formatter().remove_label()?;
             writeln!(ui)?;
@@ -2018,20 +3869,25 @@ fn cmd_status(
     }
 
     if let Some(wc_commit) = &maybe_checkout {
-        let parent_tree = wc_commit.parents()[0].tree();
+        let parent_tree = merge_commit_trees(repo.as_repo_ref(), &wc_commit.parents());
         let tree = wc_commit.tree();
-        if tree.id() == parent_tree.id() {
+        if parent_tree.diff(&tree, matcher.as_ref()).next().is_none() {
             ui.write("The working copy is clean\n")?;
         } else {
             ui.write("Working copy changes:\n")?;
             show_diff_summary(
                 ui.stdout_formatter().as_mut(),
                 &workspace_command,
-                parent_tree.diff(&tree, &EverythingMatcher),
+                parent_tree.diff(&tree, matcher.as_ref()),
+                &resolve_copies(&parent_tree, &tree, wc_commit.copies()),
             )?;
         }
 
-        let conflicts = tree.conflicts();
+        let conflicts = tree
+            .conflicts()
+            .into_iter()
+            .filter(|(path, _)| matcher.matches(path))
+            .collect_vec();
         if !conflicts.is_empty() {
             ui.stdout_formatter().add_label("conflict".to_string())?;
             writeln!(ui, "There are unresolved conflicts at these paths:")?;
@@ -2042,61 +3898,137 @@ fn cmd_status(
         }
     }
 
+    if load_rebase_state(&workspace_command)?.is_some() {
+        writeln!(
+            ui,
+            "A rebase is in progress. Run `jj rebase --continue` once the conflicts are \
+             resolved, or `jj rebase --abort` to undo it."
+        )?;
+    }
+
     Ok(())
 }
 
-fn log_template(settings: &UserSettings) -> String {
+/// The template for showing the timestamp in the built-in templates, as
+/// configured by `ui.timestamp-format`. It can be "relative" (e.g. "3 hours
+/// ago") or a `strftime`-like format. Absolute timestamps are shown in the
+/// time zone chosen by `ui.timestamp-timezone`.
+fn timestamp_template(settings: &UserSettings, timestamp: &str) -> Result<String, CommandError> {
+    let timestamp = match settings.config().get_string("ui.timestamp-timezone") {
+        Ok(timezone) if timezone == "original" => timestamp.to_string(),
+        Ok(timezone) if timezone == "local" => format!("{timestamp}.local()"),
+        Ok(timezone) if timezone == "utc" => format!("{timestamp}.utc()"),
+        Ok(timezone) => {
+            return Err(CommandError::ConfigError(format!(
+                "Invalid `ui.timestamp-timezone`: {timezone} (expected \"original\", \"local\" \
+                 or \"utc\")"
+            )));
+        }
+        Err(_) => timestamp.to_string(),
+    };
+    match settings.config().get_string("ui.timestamp-format") {
+        Ok(format) if format == "relative" => Ok(format!("relative({timestamp})")),
+        Ok(format) => {
+            if !templater::is_valid_date_format(&format) {
+                return Err(CommandError::ConfigError(format!(
+                    "Invalid `ui.timestamp-format`: {format}"
+                )));
+            }
+            let format = format.replace('\\', "\\\\").replace('"', "\\\"");
+            Ok(format!(r#"format_date({timestamp}, "{format}")"#))
+        }
+        Err(_) => Ok(timestamp),
+    }
+}
+
+/// The template for showing the author in `jj log`'s built-in template, as
+/// configured by `ui.log-author`. It can be "email" (the default), "name" or
+/// "username" (the part of the email address before the "@").
+fn author_template(settings: &UserSettings) -> Result<String, CommandError> {
+    match settings.config().get_string("ui.log-author") {
+        Ok(author) if author == "email" => Ok("author.email()".to_string()),
+        Ok(author) if author == "name" => Ok("author.name()".to_string()),
+        Ok(author) if author == "username" => Ok("author.username()".to_string()),
+        Ok(author) => Err(CommandError::ConfigError(format!(
+            r#"Invalid `ui.log-author`: {author} (expected "email", "name" or "username")"#
+        ))),
+        Err(_) => Ok("author.email()".to_string()),
+    }
+}
+
+fn log_template(settings: &UserSettings) -> Result<String, CommandError> {
     // TODO: define a method on boolean values, so we can get auto-coloring
     //       with e.g. `conflict.then("conflict")`
-    let default_template = r#"
+    let timestamp = timestamp_template(settings, "author.timestamp()")?;
+    let author = author_template(settings)?;
+    let default_template = format!(
+        r#"
             commit_id.short()
             " " change_id.short()
-            " " author.email()
-            " " label("timestamp", author.timestamp())
+            " " {author}
+            " " label("timestamp", {timestamp})
             " " branches
             " " tags
             " " working_copies
+            if(change_names, label("change_names", " " change_names))
+            if(topic, label("topic", " topic:" topic))
             if(is_git_head, label("git_head", " HEAD@git"))
             if(divergent, label("divergent", " divergent"))
             if(conflict, label("conflict", " conflict"))
             "\n"
             description.first_line()
-            "\n""#;
+            "\n""#
+    );
     let default_template = if settings.enable_open_commits() {
         format!(
             r#"
             label(if(open, "open"), {default_template})"#
         )
     } else {
-        String::from(default_template)
+        default_template
     };
-    settings
+    Ok(settings
         .config()
         .get_string("template.log.graph")
-        .unwrap_or(default_template)
+        .unwrap_or(default_template))
 }
 
 fn cmd_log(ui: &mut Ui, command: &CommandHelper, args: &LogArgs) -> Result<(), CommandError> {
     let workspace_command = command.workspace_helper(ui)?;
 
     let default_revset = ui.settings().default_revset();
-    let revset_expression = revset::parse(args.revisions.as_ref().unwrap_or(&default_revset))?;
+    let revset_str = args.revisions.as_ref().unwrap_or(&default_revset);
     let repo = workspace_command.repo();
     let workspace_id = workspace_command.workspace_id();
     let checkout_id = repo.view().get_wc_commit_id(&workspace_id);
     let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
-    let mut revset = revset_expression.evaluate(repo.as_repo_ref(), Some(&workspace_id))?;
+    let mut revset = workspace_command.evaluate_revset(revset_str)?;
     if !args.paths.is_empty() {
         revset = revset::filter_by_diff(repo.as_repo_ref(), matcher.as_ref(), revset);
     }
 
     let store = repo.store();
-    let diff_format = (args.patch || args.diff_format.git || args.diff_format.summary)
+    // Reading the commits from the metadata cache is much cheaper than from some
+    // backends
+    let metadata_cache = CommitMetadataCache::load(
+        repo.index_store().commit_metadata_dir(),
+        store.hash_length(),
+    )
+    .ok();
+    let get_commit = |id: &CommitId| match &metadata_cache {
+        Some(metadata_cache) => metadata_cache.get_commit(store, id),
+        None => store.get_commit(id),
+    };
+    let diff_format = (args.patch
+        || args.diff_format.git
+        || args.diff_format.summary
+        || args.diff_format.stat
+        || args.diff_format.json)
         .then(|| diff_format_for(ui, &args.diff_format));
 
     let template_string = match &args.template {
         Some(value) => value.to_string(),
-        None => log_template(ui.settings()),
+        None => log_template(ui.settings())?,
     };
     let template = crate::template_parser::parse_commit_template(
         repo.as_repo_ref(),
@@ -2141,7 +4073,7 @@ fn cmd_log(ui: &mut Ui, command: &CommandHelper, args: &LogArgs) -> Result<(), C
             }
             let mut buffer = vec![];
             let commit_id = index_entry.commit_id();
-            let commit = store.get_commit(&commit_id)?;
+            let commit = get_commit(&commit_id)?;
             let is_checkout = Some(&commit_id) == checkout_id;
             {
                 let writer = Box::new(&mut buffer);
@@ -2183,7 +4115,7 @@ fn cmd_log(ui: &mut Ui, command: &CommandHelper, args: &LogArgs) -> Result<(), C
             Box::new(revset.iter())
         };
         for index_entry in iter {
-            let commit = store.get_commit(&index_entry.commit_id())?;
+            let commit = get_commit(&index_entry.commit_id())?;
             template.format(&commit, formatter)?;
             if let Some(diff_format) = diff_format {
                 show_patch(
@@ -2210,8 +4142,148 @@ fn show_patch(
     let parents = commit.parents();
     let from_tree = merge_commit_trees(workspace_command.repo().as_repo_ref(), &parents);
     let to_tree = commit.tree();
+    let copies = resolve_copies(&from_tree, &to_tree, commit.copies());
     let diff_iterator = from_tree.diff(&to_tree, matcher);
-    show_diff(formatter, workspace_command, diff_iterator, format)
+    show_diff(
+        formatter,
+        workspace_command,
+        diff_iterator,
+        &copies,
+        format,
+        ConflictStyle::Materialized,
+    )
+}
+
+#[derive(Default)]
+struct SummaryCounts {
+    commits: usize,
+    added: usize,
+    removed: usize,
+}
+
+fn cmd_summary(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &SummaryArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let since = match &args.since {
+        Some(date) => Some(
+            templater::parse_timestamp(date, &Timestamp::now())
+                .ok_or_else(|| UserError(format!("Invalid date: \"{date}\"")))?,
+        ),
+        None => None,
+    };
+    let repo = workspace_command.repo();
+    let revset = workspace_command.evaluate_revset(&args.revisions)?;
+    let store = repo.store();
+    let metadata_cache = CommitMetadataCache::load(
+        repo.index_store().commit_metadata_dir(),
+        store.hash_length(),
+    )
+    .ok();
+    let mailmap = ui.settings().mailmap();
+
+    let mut total = SummaryCounts::default();
+    let mut by_author: HashMap<String, SummaryCounts> = HashMap::new();
+    let mut by_day: BTreeMap<String, usize> = BTreeMap::new();
+    let mut files: HashMap<RepoPath, usize> = HashMap::new();
+    for index_entry in revset.iter() {
+        let commit_id = index_entry.commit_id();
+        if &commit_id == store.root_commit_id() {
+            continue;
+        }
+        let commit = match &metadata_cache {
+            Some(metadata_cache) => metadata_cache.get_commit(store, &commit_id),
+            None => store.get_commit(&commit_id),
+        }?;
+        let author = mailmap.resolve(commit.author());
+        if let Some(since) = &since {
+            if author.timestamp.timestamp < since.timestamp {
+                continue;
+            }
+        }
+        let parent_tree = merge_commit_trees(repo.as_repo_ref(), &commit.parents());
+        let stats = diff_stats(store, parent_tree.diff(&commit.tree(), &EverythingMatcher))?;
+        let added = stats.iter().map(|stat| stat.added).sum::<usize>();
+        let removed = stats.iter().map(|stat| stat.removed).sum::<usize>();
+        for counts in [
+            &mut total,
+            by_author
+                .entry(format!("{} <{}>", author.name, author.email))
+                .or_default(),
+        ] {
+            counts.commits += 1;
+            counts.added += added;
+            counts.removed += removed;
+        }
+        *by_day
+            .entry(templater::format_timestamp_with(
+                &author.timestamp,
+                "%Y-%m-%d",
+            ))
+            .or_default() += 1;
+        for stat in stats {
+            *files.entry(stat.path).or_default() += 1;
+        }
+    }
+
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    writeln!(
+        ui,
+        "{} commit{} by {} author{}, {} file{} changed, {} insertion{}(+), {} deletion{}(-)",
+        total.commits,
+        plural(total.commits),
+        by_author.len(),
+        plural(by_author.len()),
+        files.len(),
+        plural(files.len()),
+        total.added,
+        plural(total.added),
+        total.removed,
+        plural(total.removed),
+    )?;
+    let count_width = total.commits.to_string().len();
+    if args.by_author && !by_author.is_empty() {
+        writeln!(ui, "\nCommits per author:")?;
+        // Most commits first
+        let authors = by_author
+            .iter()
+            .sorted_by(|(name1, counts1), (name2, counts2)| {
+                counts2.commits.cmp(&counts1.commits).then(name1.cmp(name2))
+            })
+            .collect_vec();
+        for (name, counts) in authors {
+            writeln!(
+                ui,
+                "  {:>count_width$}  {name} (+{} -{})",
+                counts.commits, counts.added, counts.removed
+            )?;
+        }
+    }
+    if args.by_day && !by_day.is_empty() {
+        writeln!(ui, "\nCommits per day:")?;
+        for (day, commits) in &by_day {
+            writeln!(ui, "  {day}  {commits:>count_width$}")?;
+        }
+    }
+    if args.files > 0 && !files.is_empty() {
+        writeln!(ui, "\nMost changed files:")?;
+        // Changed in the most commits first
+        let files = files
+            .iter()
+            .sorted_by(|(path1, count1), (path2, count2)| count2.cmp(count1).then(path1.cmp(path2)))
+            .take(args.files)
+            .collect_vec();
+        for (path, commits) in files {
+            writeln!(
+                ui,
+                "  {commits:>count_width$}  {}",
+                workspace_command.format_file_path(path)
+            )?;
+        }
+    }
+    Ok(())
 }
 
 fn cmd_obslog(ui: &mut Ui, command: &CommandHelper, args: &ObslogArgs) -> Result<(), CommandError> {
@@ -2224,12 +4296,16 @@ fn cmd_obslog(ui: &mut Ui, command: &CommandHelper, args: &ObslogArgs) -> Result
         .view()
         .get_wc_commit_id(&workspace_id);
 
-    let diff_format = (args.patch || args.diff_format.git || args.diff_format.summary)
+    let diff_format = (args.patch
+        || args.diff_format.git
+        || args.diff_format.summary
+        || args.diff_format.stat
+        || args.diff_format.json)
         .then(|| diff_format_for(ui, &args.diff_format));
 
     let template_string = match &args.template {
         Some(value) => value.to_string(),
-        None => log_template(ui.settings()),
+        None => log_template(ui.settings())?,
     };
     let template = crate::template_parser::parse_commit_template(
         workspace_command.repo().as_repo_ref(),
@@ -2304,7 +4380,14 @@ fn show_predecessor_patch(
     };
     let predecessor_tree = rebase_to_dest_parent(workspace_command, predecessor, commit)?;
     let diff_iterator = predecessor_tree.diff(&commit.tree(), &EverythingMatcher);
-    show_diff(formatter, workspace_command, diff_iterator, diff_format)
+    show_diff(
+        formatter,
+        workspace_command,
+        diff_iterator,
+        &[],
+        diff_format,
+        ConflictStyle::Materialized,
+    )
 }
 
 fn cmd_interdiff(
@@ -2324,7 +4407,9 @@ fn cmd_interdiff(
         ui.stdout_formatter().as_mut(),
         &workspace_command,
         diff_iterator,
+        &[],
         diff_format_for(ui, &args.format),
+        ConflictStyle::Materialized,
     )
 }
 
@@ -2355,11 +4440,138 @@ fn rebase_to_dest_parent(
     }
 }
 
+/// The line after which everything in the description file is ignored. The
+/// diff shown below it doesn't have to be prefixed by "JJ: ".
+const DESCRIPTION_IGNORE_REST_LINE: &str = "JJ: ignore-rest\n";
+
 fn edit_description(
     ui: &Ui,
     repo: &ReadonlyRepo,
     description: &str,
 ) -> Result<String, CommandError> {
+    edit_description_with_diff(ui, repo, description, "")
+}
+
+/// Renders the changes in the commit in the format configured by
+/// `ui.describe-diff` ("none" by default), to be shown below the description
+/// while it's being edited.
+fn description_diff(
+    ui: &Ui,
+    workspace_command: &WorkspaceCommandHelper,
+    commit: &Commit,
+) -> Result<String, CommandError> {
+    let format = match ui.settings().config().get_string("ui.describe-diff") {
+        Ok(value) if &value == "summary" => DiffFormat::Summary,
+        Ok(value) if &value == "stat" => DiffFormat::Stat,
+        Ok(value) if &value == "git" => DiffFormat::Git,
+        Ok(value) if &value == "none" => return Ok(String::new()),
+        Ok(value) => {
+            return Err(CommandError::ConfigError(format!(
+                "Invalid `ui.describe-diff`: {value} (expected \"none\", \"summary\", \"stat\" \
+                 or \"git\")"
+            )))
+        }
+        Err(_) => return Ok(String::new()),
+    };
+    let parent_tree = merge_commit_trees(workspace_command.repo().as_repo_ref(), &commit.parents());
+    let tree = commit.tree();
+    let copies = resolve_copies(&parent_tree, &tree, commit.copies());
+    let tree_diff = parent_tree.diff(&tree, &EverythingMatcher);
+    let mut buffer = vec![];
+    {
+        let mut formatter = PlainTextFormatter::new(Box::new(&mut buffer));
+        show_diff(
+            &mut formatter,
+            workspace_command,
+            tree_diff,
+            &copies,
+            format,
+            ConflictStyle::Materialized,
+        )?;
+    }
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+/// Like `edit_description()`, but shows `diff` below the description. The
+/// diff is removed again when the file is read back.
+fn edit_description_with_diff(
+    ui: &Ui,
+    repo: &ReadonlyRepo,
+    description: &str,
+    diff: &str,
+) -> Result<String, CommandError> {
+    let mut content = format!(
+        "{description}\nJJ: Lines starting with \"JJ: \" (like this one) will be removed.\n"
+    );
+    if !diff.is_empty() {
+        content.push_str(DESCRIPTION_IGNORE_REST_LINE);
+        content.push_str(diff);
+    }
+    let content = edit_text(ui, repo, &content)?;
+    Ok(strip_description_comments(&content))
+}
+
+/// Lets the user edit the descriptions of all of `commits` in a single file,
+/// with a "JJ: describe <commit hash>" line before each description. Returns
+/// the new descriptions. Commits whose section was deleted are not included.
+fn edit_multiple_descriptions(
+    ui: &Ui,
+    repo: &ReadonlyRepo,
+    commits: &[Commit],
+) -> Result<HashMap<CommitId, String>, CommandError> {
+    let mut content = String::new();
+    let mut commits_by_hash = HashMap::new();
+    for commit in commits {
+        let commit_hash = short_commit_hash(commit.id());
+        content.push_str(&format!(
+            "{DESCRIPTION_SECTION_PREFIX}{commit_hash} -------\n"
+        ));
+        content.push_str(commit.description());
+        if !commit.description().is_empty() && !commit.description().ends_with('\n') {
+            content.push('\n');
+        }
+        content.push('\n');
+        commits_by_hash.insert(commit_hash, commit.id().clone());
+    }
+    content.push_str("JJ: Lines starting with \"JJ: \" (like this one) will be removed.\n");
+    let content = edit_text(ui, repo, &content)?;
+
+    let mut descriptions = HashMap::new();
+    let mut current: Option<(CommitId, String)> = None;
+    for line in content.split_inclusive('\n') {
+        if let Some(rest) = line.strip_prefix(DESCRIPTION_SECTION_PREFIX) {
+            if let Some((commit_id, text)) = current.take() {
+                descriptions.insert(commit_id, strip_description_comments(&text));
+            }
+            let commit_hash = rest.split_whitespace().next().unwrap_or("");
+            let commit_id = commits_by_hash.get(commit_hash).ok_or_else(|| {
+                CommandError::UserError(format!(
+                    "The description file refers to unknown commit '{commit_hash}'"
+                ))
+            })?;
+            if descriptions.contains_key(commit_id) {
+                return Err(CommandError::UserError(format!(
+                    "The description file contains commit '{commit_hash}' more than once"
+                )));
+            }
+            current = Some((commit_id.clone(), String::new()));
+        } else if let Some((_, text)) = &mut current {
+            text.push_str(line);
+        }
+    }
+    if let Some((commit_id, text)) = current {
+        descriptions.insert(commit_id, strip_description_comments(&text));
+    }
+    Ok(descriptions)
+}
+
+/// The prefix of the line before each description when editing several
+/// descriptions at once.
+const DESCRIPTION_SECTION_PREFIX: &str = "JJ: describe ";
+
+/// Writes `content` to a file in the repo, opens it in the user's editor, and
+/// returns the edited content.
+fn edit_text(ui: &Ui, repo: &ReadonlyRepo, content: &str) -> Result<String, CommandError> {
     let random: u32 = rand::random();
     let description_file_path = repo.repo_path().join(format!("description-{}.txt", random));
     {
@@ -2369,23 +4581,21 @@ fn edit_description(
             .truncate(true)
             .open(&description_file_path)
             .unwrap_or_else(|_| panic!("failed to open {:?} for write", &description_file_path));
-        description_file.write_all(description.as_bytes()).unwrap();
-        description_file
-            .write_all(b"\nJJ: Lines starting with \"JJ: \" (like this one) will be removed.\n")
-            .unwrap();
+        description_file.write_all(content.as_bytes()).unwrap();
     }
 
-    let editor = ui
-        .settings()
-        .config()
-        .get_string("ui.editor")
-        .unwrap_or_else(|_| "pico".to_string());
-    // Handle things like `EDITOR=emacs -nw`
-    let args = editor.split(' ').collect_vec();
-    let editor_args = if args.len() > 1 { &args[1..] } else { &[] };
-    let exit_status = std::process::Command::new(args[0])
-        .args(editor_args)
-        .arg(&description_file_path)
+    let editor_command = get_editor_command(ui.settings())?;
+    let editor = editor_command.join(" ");
+    let vars = [("$path", description_file_path.as_os_str())];
+    let mut args = editor_command
+        .iter()
+        .map(|arg| interpolate_paths(arg, &vars))
+        .collect_vec();
+    if !editor_command.iter().any(|arg| arg.contains("$path")) {
+        args.push(description_file_path.clone().into_os_string());
+    }
+    let exit_status = std::process::Command::new(&args[0])
+        .args(&args[1..])
         .status()
         .map_err(|_| CommandError::UserError(format!("Failed to run editor '{editor}'")))?;
     if !exit_status.success() {
@@ -2400,19 +4610,50 @@ fn edit_description(
         .unwrap_or_else(|_| panic!("failed to open {:?} for read", &description_file_path));
     let mut buf = vec![];
     description_file.read_to_end(&mut buf).unwrap();
-    let description = String::from_utf8(buf).unwrap();
+    let content = String::from_utf8(buf).unwrap();
     // Delete the file only if everything went well.
     // TODO: Tell the user the name of the file we left behind.
     std::fs::remove_file(description_file_path).ok();
-    let mut lines = description
+    Ok(content)
+}
+
+/// The program and arguments to run to edit a file, from `ui.editor`. It's
+/// either a list of arguments or a string that's split on spaces (to handle
+/// things like `EDITOR=emacs -nw`).
+fn get_editor_command(settings: &UserSettings) -> Result<Vec<String>, CommandError> {
+    let value = match settings.config().get::<config::Value>("ui.editor") {
+        Ok(value) => value,
+        Err(config::ConfigError::NotFound(_)) => return Ok(vec!["pico".to_string()]),
+        Err(err) => return Err(err.into()),
+    };
+    let args = if let config::ValueKind::String(editor) = &value.kind {
+        editor.split(' ').map(ToString::to_string).collect_vec()
+    } else {
+        string_list_from_config(value).ok_or_else(|| {
+            CommandError::ConfigError("ui.editor must be a string or a list of strings".to_string())
+        })?
+    };
+    if args.is_empty() || args[0].is_empty() {
+        return Err(CommandError::ConfigError(
+            "ui.editor must not be empty".to_string(),
+        ));
+    }
+    Ok(args)
+}
+
+/// Removes the "JJ: " lines, everything after the "JJ: ignore-rest" line, and
+/// trailing blank lines.
+fn strip_description_comments(content: &str) -> String {
+    let mut lines = content
         .split_inclusive('\n')
+        .take_while(|line| line.trim_end() != DESCRIPTION_IGNORE_REST_LINE.trim_end())
         .filter(|line| !line.starts_with("JJ: "))
         .collect_vec();
     // Remove trailing blank lines
     while matches!(lines.last(), Some(&"\n") | Some(&"\r\n")) {
         lines.pop().unwrap();
     }
-    Ok(lines.join(""))
+    lines.join("")
 }
 
 fn cmd_describe(
@@ -2421,28 +4662,115 @@ fn cmd_describe(
     args: &DescribeArgs,
 ) -> Result<(), CommandError> {
     let mut workspace_command = command.workspace_helper(ui)?;
-    let commit = workspace_command.resolve_single_rev(&args.revision)?;
-    workspace_command.check_rewriteable(&commit)?;
-    let description;
-    if args.stdin {
+    // Parents first, so the descriptions are shown in that order, and so they
+    // are rewritten before their children
+    let commits = workspace_command
+        .resolve_revset(&args.revision)?
+        .into_iter()
+        .rev()
+        .collect_vec();
+    if commits.is_empty() {
+        return Err(CommandError::UserError(format!(
+            "Revset \"{}\" didn't resolve to any revisions",
+            &args.revision
+        )));
+    }
+    for commit in &commits {
+        workspace_command.check_rewriteable(commit)?;
+    }
+    let co_authors = if args.no_co_authors {
+        Some(vec![])
+    } else if !args.co_author.is_empty() {
+        let co_authors: Vec<_> = args
+            .co_author
+            .iter()
+            .map(|value| {
+                parse_co_author(value).ok_or_else(|| {
+                    UserError(format!(
+                        "Invalid co-author \"{value}\": expected \"Name <email>\""
+                    ))
+                })
+            })
+            .try_collect()?;
+        Some(co_authors)
+    } else {
+        None
+    };
+    let shared_description = if args.stdin {
         let mut buffer = String::new();
         io::stdin().read_to_string(&mut buffer).unwrap();
-        description = buffer;
+        Some(buffer)
     } else if let Some(message) = &args.message {
-        description = message.to_owned()
+        Some(message.to_owned())
+    } else if let Some(path) = &args.message_file {
+        Some(std::fs::read_to_string(path).map_err(|err| {
+            CommandError::UserError(format!("Failed to read {}: {err}", path.display()))
+        })?)
     } else {
-        description = edit_description(ui, workspace_command.repo(), commit.description())?;
-    }
-    if description == *commit.description() {
+        None
+    };
+    let descriptions = match shared_description {
+        Some(description) => commits
+            .iter()
+            .map(|commit| (commit.id().clone(), description.clone()))
+            .collect(),
+        None if co_authors.is_some() && !args.edit => commits
+            .iter()
+            .map(|commit| (commit.id().clone(), commit.description().to_string()))
+            .collect(),
+        None if commits.len() == 1 => {
+            let commit = &commits[0];
+            let diff = description_diff(ui, &workspace_command, commit)?;
+            let description = edit_description_with_diff(
+                ui,
+                workspace_command.repo(),
+                commit.description(),
+                &diff,
+            )?;
+            HashMap::from([(commit.id().clone(), description)])
+        }
+        None => edit_multiple_descriptions(ui, workspace_command.repo(), &commits)?,
+    };
+    let changed_commits = commits
+        .iter()
+        .filter(|commit| match descriptions.get(commit.id()) {
+            Some(description) => {
+                description != commit.description()
+                    || co_authors
+                        .as_ref()
+                        .map_or(false, |co_authors| co_authors != commit.co_authors())
+            }
+            None => false,
+        })
+        .collect_vec();
+    if changed_commits.is_empty() {
         ui.write("Nothing changed.\n")?;
+        return Ok(());
+    }
+    let mut tx = if let [commit] = changed_commits.as_slice() {
+        workspace_command.start_transaction(&format!("describe commit {}", commit.id().hex()))
     } else {
-        let mut tx =
-            workspace_command.start_transaction(&format!("describe commit {}", commit.id().hex()));
-        CommitBuilder::for_rewrite_from(ui.settings(), &commit)
-            .set_description(description)
-            .write_to_repo(tx.mut_repo());
-        workspace_command.finish_transaction(ui, tx)?;
+        workspace_command.start_transaction(&format!("describe {} commits", changed_commits.len()))
+    };
+    // Rewrite the commits on top of their rewritten parents, so their
+    // descendants only need to be rebased once, when the transaction finishes
+    let mut rewritten_ids: HashMap<CommitId, CommitId> = HashMap::new();
+    for commit in changed_commits {
+        let new_parent_ids = commit
+            .parent_ids()
+            .iter()
+            .map(|id| rewritten_ids.get(id).unwrap_or(id).clone())
+            .collect_vec();
+        let mut commit_builder = CommitBuilder::for_rewrite_from(ui.settings(), commit)
+            .set_parents(new_parent_ids)
+            .set_description(descriptions[commit.id()].clone());
+        if let Some(co_authors) = &co_authors {
+            commit_builder = commit_builder.set_co_authors(co_authors.clone());
+        }
+        let new_commit = commit_builder.write_to_repo(tx.mut_repo());
+        rewritten_ids.insert(commit.id().clone(), new_commit.id().clone());
     }
+    workspace_command.finish_transaction(ui, tx)?;
     Ok(())
 }
 
@@ -2467,13 +4795,16 @@ fn cmd_close(ui: &mut Ui, command: &CommandHelper, args: &CloseArgs) -> Result<(
     let description = if let Some(message) = &args.message {
         message.to_string()
     } else if commit.description().is_empty() {
-        edit_description(
+        let diff = description_diff(ui, &workspace_command, &commit)?;
+        edit_description_with_diff(
             ui,
             workspace_command.repo(),
             "\n\nJJ: Enter commit description.\n",
+            &diff,
         )?
     } else if args.edit {
-        edit_description(ui, workspace_command.repo(), commit.description())?
+        let diff = description_diff(ui, &workspace_command, &commit)?;
+        edit_description_with_diff(ui, workspace_command.repo(), commit.description(), &diff)?
     } else {
         commit.description().to_string()
     };
@@ -2551,18 +4882,201 @@ fn cmd_abandon(
             to_abandon.len() - 1
         )
     };
-    let mut tx = workspace_command.start_transaction(&transaction_description);
-    for commit in to_abandon {
-        tx.mut_repo().record_abandoned_commit(commit.id().clone());
-    }
+    let abandoned_ids: HashSet<_> = to_abandon.iter().map(|commit| commit.id()).collect();
+    let abandoned_branches = workspace_command
+        .repo()
+        .view()
+        .branches()
+        .iter()
+        .filter_map(|(name, target)| {
+            let local_target = target.local_target.as_ref()?;
+            local_target
+                .adds()
+                .iter()
+                .any(|id| abandoned_ids.contains(id))
+                .then(|| (name.clone(), local_target.clone()))
+        })
+        .collect_vec();
+    let mut tx = workspace_command.start_transaction(&transaction_description);
+    for commit in &to_abandon {
+        tx.mut_repo().record_abandoned_commit(commit.id().clone());
+    }
     let num_rebased = tx.mut_repo().rebase_descendants(ui.settings())?;
-    if num_rebased > 0 {
+    if num_rebased > 0 && !args.dry_run {
         writeln!(
             ui,
             "Rebased {} descendant commits onto parents of abandoned commits",
             num_rebased
         )?;
     }
+    if !abandoned_branches.is_empty() && (args.delete_branches || args.retain_branches) {
+        for (name, target) in &abandoned_branches {
+            if args.delete_branches {
+                tx.mut_repo().remove_local_branch(name);
+            } else {
+                tx.mut_repo().set_local_branch(name.clone(), target.clone());
+            }
+        }
+        if args.delete_branches && !args.dry_run {
+            writeln!(
+                ui,
+                "Deleted branches: {}",
+                abandoned_branches.iter().map(|(name, _)| name).join(", ")
+            )?;
+        }
+    }
+    workspace_command.finish_transaction_or_preview(ui, tx, args.dry_run)?;
+    Ok(())
+}
+
+fn cmd_unhide(ui: &mut Ui, command: &CommandHelper, args: &UnhideArgs) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let mut to_unhide: Vec<Commit> = vec![];
+    for revset in &args.revisions {
+        let revisions = workspace_command.resolve_revset(revset)?;
+        workspace_command.check_non_empty(&revisions)?;
+        for commit in revisions {
+            if !to_unhide.contains(&commit) {
+                to_unhide.push(commit);
+            }
+        }
+    }
+    let hidden_ids: HashSet<_> = RevsetExpression::hidden()
+        .evaluate(workspace_command.repo().as_repo_ref(), None)?
+        .iter()
+        .commit_ids()
+        .collect();
+    let (to_unhide, visible): (Vec<_>, Vec<_>) = to_unhide
+        .into_iter()
+        .partition(|commit| hidden_ids.contains(commit.id()));
+    for commit in &visible {
+        ui.write_warn(format!(
+            "Commit {} is already visible\n",
+            short_commit_hash(commit.id())
+        ))?;
+    }
+    if to_unhide.is_empty() {
+        writeln!(ui, "Nothing changed.")?;
+        return Ok(());
+    }
+    let transaction_description = if to_unhide.len() == 1 {
+        format!("unhide commit {}", to_unhide[0].id().hex())
+    } else {
+        format!(
+            "unhide commit {} and {} more",
+            to_unhide[0].id().hex(),
+            to_unhide.len() - 1
+        )
+    };
+    let mut tx = workspace_command.start_transaction(&transaction_description);
+    for commit in &to_unhide {
+        tx.mut_repo().add_head(commit);
+    }
+    for commit in &to_unhide {
+        ui.write("Unhid ")?;
+        ui.write_commit_summary(
+            tx.repo().as_repo_ref(),
+            &workspace_command.workspace_id(),
+            commit,
+        )?;
+        ui.write("\n")?;
+    }
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
+fn cmd_resolve_divergence(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &ResolveDivergenceArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let target = workspace_command.resolve_single_rev(&args.revision)?;
+    let repo = workspace_command.repo().clone();
+    let versions: Vec<Commit> = RevsetExpression::all()
+        .evaluate(repo.as_repo_ref(), None)?
+        .iter()
+        .filter(|entry| entry.change_id() == *target.change_id())
+        .map(|entry| repo.store().get_commit(&entry.commit_id()))
+        .try_collect()?;
+    if versions.len() < 2 {
+        return Err(CommandError::UserError(format!(
+            "Change {} is not divergent",
+            short_change_hash(target.change_id())
+        )));
+    }
+    let index = repo.index();
+    for (version1, version2) in versions.iter().tuple_combinations() {
+        if index.is_ancestor(version1.id(), version2.id())
+            || index.is_ancestor(version2.id(), version1.id())
+        {
+            return Err(CommandError::UserError(format!(
+                "Commit {} is an ancestor of commit {} of the same change",
+                short_commit_hash(version2.id()),
+                short_commit_hash(version1.id())
+            )));
+        }
+    }
+    for version in &versions {
+        workspace_command.check_rewriteable(version)?;
+    }
+
+    let mut tx = workspace_command.start_transaction(&format!(
+        "resolve divergent change {}",
+        target.change_id().hex()
+    ));
+    let mut_repo = tx.mut_repo();
+    let result = if args.keep {
+        target.clone()
+    } else {
+        let mut parent_ids = vec![];
+        for version in &versions {
+            for parent_id in version.parent_ids() {
+                if !parent_ids.contains(parent_id) {
+                    parent_ids.push(parent_id.clone());
+                }
+            }
+        }
+        let parent_ids = mut_repo.index().heads(&parent_ids);
+        let merged_tree = merge_commit_trees(mut_repo.as_repo_ref(), &versions);
+        let description = versions
+            .iter()
+            .map(|version| version.description())
+            .filter(|description| !description.is_empty())
+            .unique()
+            .join("\n");
+        CommitBuilder::for_rewrite_from(ui.settings(), &target)
+            .set_parents(parent_ids)
+            .set_predecessors(
+                versions
+                    .iter()
+                    .map(|version| version.id().clone())
+                    .collect(),
+            )
+            .set_tree(merged_tree.id().clone())
+            .set_description(description)
+            .write_to_repo(mut_repo)
+    };
+    for version in &versions {
+        if version.id() != result.id() {
+            mut_repo.record_rewritten_commit(version.id().clone(), result.id().clone());
+        }
+    }
+    let num_rebased = mut_repo.rebase_descendants(ui.settings())?;
+    if args.keep {
+        write!(ui, "Kept commit ")?;
+    } else {
+        write!(ui, "Merged {} commits into ", versions.len())?;
+    }
+    ui.write_commit_summary(
+        mut_repo.as_repo_ref(),
+        &workspace_command.workspace_id(),
+        &result,
+    )?;
+    writeln!(ui)?;
+    if num_rebased > 0 {
+        writeln!(ui, "Rebased {} descendant commits", num_rebased)?;
+    }
     workspace_command.finish_transaction(ui, tx)?;
     Ok(())
 }
@@ -2825,8 +5339,13 @@ aborted.
             short_commit_description(parent),
             short_commit_description(&commit)
         );
-        new_parent_tree_id =
-            workspace_command.edit_diff(ui, &parent_base_tree, &parent.tree(), &instructions)?;
+        new_parent_tree_id = workspace_command.edit_diff(
+            ui,
+            &parent_base_tree,
+            &parent.tree(),
+            &instructions,
+            &EverythingMatcher,
+        )?;
         if &new_parent_tree_id == parent_base_tree.id() {
             return Err(CommandError::UserError(String::from("No changes selected")));
         }
@@ -2872,6 +5391,7 @@ fn cmd_restore(
     let from_commit = workspace_command.resolve_single_rev(from_str)?;
     let to_commit = workspace_command.resolve_single_rev(to_str)?;
     workspace_command.check_rewriteable(&to_commit)?;
+    let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
     let tree_id;
     if args.interactive {
         let instructions = format!(
@@ -2894,26 +5414,10 @@ side. If you don't make any changes, then the operation will be aborted.
             &from_commit.tree(),
             &to_commit.tree(),
             &instructions,
+            matcher.as_ref(),
         )?;
-    } else if !args.paths.is_empty() {
-        let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
-        let mut tree_builder = workspace_command
-            .repo()
-            .store()
-            .tree_builder(to_commit.tree_id().clone());
-        for (repo_path, diff) in from_commit.tree().diff(&to_commit.tree(), matcher.as_ref()) {
-            match diff.into_options().0 {
-                Some(value) => {
-                    tree_builder.set(repo_path, value);
-                }
-                None => {
-                    tree_builder.remove(repo_path);
-                }
-            }
-        }
-        tree_id = tree_builder.write_tree();
     } else {
-        tree_id = from_commit.tree_id().clone();
+        tree_id = restore_tree(&from_commit.tree(), &to_commit.tree(), matcher.as_ref());
     }
     if &tree_id == to_commit.tree_id() {
         ui.write("Nothing changed.\n")?;
@@ -2955,7 +5459,13 @@ Adjust the right side until it shows the contents you want. If you
 don't make any changes, then the operation will be aborted.",
         short_commit_description(&commit)
     );
-    let tree_id = workspace_command.edit_diff(ui, &base_tree, &commit.tree(), &instructions)?;
+    let tree_id = workspace_command.edit_diff(
+        ui,
+        &base_tree,
+        &commit.tree(),
+        &instructions,
+        &EverythingMatcher,
+    )?;
     if &tree_id == commit.tree_id() {
         ui.write("Nothing changed.\n")?;
     } else {
@@ -2994,36 +5504,51 @@ any changes, then the operation will be aborted.
 ",
         short_commit_description(&commit)
     );
+    let interactive = args.paths.is_empty();
     let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
     let tree_id = workspace_command.select_diff(
         ui,
         &base_tree,
         &commit.tree(),
         &instructions,
-        args.paths.is_empty(),
+        interactive,
         matcher.as_ref(),
     )?;
+    if !interactive && &tree_id == base_tree.id() {
+        return Err(UserError(format!(
+            "No changes in commit {} match the given paths",
+            short_commit_hash(commit.id())
+        )));
+    }
     if &tree_id == commit.tree_id() {
         ui.write("Nothing changed.\n")?;
     } else {
         let mut tx =
             workspace_command.start_transaction(&format!("split commit {}", commit.id().hex()));
-        let first_description = edit_description(
-            ui,
-            tx.base_repo(),
-            &("JJ: Enter commit description for the first part.\n".to_string()
-                + commit.description()),
-        )?;
+        let first_description = match &args.message {
+            Some(message) => message.clone(),
+            None if !interactive => commit.description().to_string(),
+            None => edit_description(
+                ui,
+                tx.base_repo(),
+                &("JJ: Enter commit description for the first part.\n".to_string()
+                    + commit.description()),
+            )?,
+        };
         let first_commit = CommitBuilder::for_rewrite_from(ui.settings(), &commit)
             .set_tree(tree_id)
             .set_description(first_description)
             .write_to_repo(tx.mut_repo());
-        let second_description = edit_description(
-            ui,
-            tx.base_repo(),
-            &("JJ: Enter commit description for the second part.\n".to_string()
-                + commit.description()),
-        )?;
+        let second_description = if interactive {
+            edit_description(
+                ui,
+                tx.base_repo(),
+                &("JJ: Enter commit description for the second part.\n".to_string()
+                    + commit.description()),
+            )?
+        } else {
+            commit.description().to_string()
+        };
         let second_commit = CommitBuilder::for_rewrite_from(ui.settings(), &commit)
             .set_parents(vec![first_commit.id().clone()])
             .set_tree(commit.tree_id().clone())
@@ -3059,49 +5584,188 @@ any changes, then the operation will be aborted.
     Ok(())
 }
 
-fn cmd_merge(ui: &mut Ui, command: &CommandHelper, args: &NewArgs) -> Result<(), CommandError> {
-    if args.revisions.len() < 2 {
-        return Err(CommandError::CliError(String::from(
-            "Merge requires at least two revisions",
-        )));
+/// Returns a name for `commit` for the description of a merge: the first of
+/// the local branches pointing to it, or else its short commit hash.
+fn merge_parent_label(repo: &ReadonlyRepo, commit: &Commit) -> String {
+    repo.view()
+        .branches()
+        .iter()
+        .find(|(_, target)| {
+            target
+                .local_target
+                .as_ref()
+                .map_or(false, |target| target.has_add(commit.id()))
+        })
+        .map_or_else(|| short_commit_hash(commit.id()), |(name, _)| name.clone())
+}
+
+/// Generates the description of a merge of `parents`, with a subject naming
+/// the parents and a line for each parent in the body.
+fn merge_description(repo: &ReadonlyRepo, parents: &[Commit]) -> String {
+    let labels = parents
+        .iter()
+        .map(|parent| merge_parent_label(repo, parent))
+        .collect_vec();
+    let (last_label, other_labels) = labels.split_last().unwrap();
+    let mut description = format!("Merge {} and {last_label}\n\n", other_labels.join(", "));
+    for (label, parent) in labels.iter().zip(parents) {
+        let first_line = parent.description().lines().next().unwrap_or("");
+        let hash = short_commit_hash(parent.id());
+        let line = if *label == hash {
+            format!("* {hash} {first_line}")
+        } else {
+            format!("* {label}: {hash} {first_line}")
+        };
+        description.push_str(line.trim_end());
+        description.push('\n');
+    }
+    description
+}
+
+fn cmd_merge(ui: &mut Ui, command: &CommandHelper, args: &MergeArgs) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let commits = resolve_base_revs(&workspace_command, &args.revisions)?;
+    let repo = workspace_command.repo().clone();
+    let description = match &args.message {
+        Some(message) => message.clone(),
+        None if args.no_edit => merge_description(&repo, &commits),
+        None => edit_description(ui, &repo, &merge_description(&repo, &commits))?,
+    };
+    let parent_ids = commits.iter().map(|c| c.id().clone()).collect();
+    let mut tx = workspace_command.start_transaction("merge");
+    let merged_tree = merge_commit_trees(repo.as_repo_ref(), &commits);
+    let new_commit =
+        CommitBuilder::for_new_commit(ui.settings(), parent_ids, merged_tree.id().clone())
+            .set_description(description)
+            .set_open(true)
+            .write_to_repo(tx.mut_repo());
+    let workspace_id = workspace_command.workspace_id();
+    tx.mut_repo().edit(workspace_id, &new_commit);
+    workspace_command.finish_transaction(ui, tx)?;
+    let conflicts = merged_tree.conflicts();
+    if !conflicts.is_empty() {
+        ui.stdout_formatter().add_label("conflict".to_string())?;
+        writeln!(ui, "The merge has conflicts at these paths:")?;
+        ui.stdout_formatter().remove_label()?;
+        for (path, _) in conflicts {
+            writeln!(ui, "{}", &workspace_command.format_file_path(&path))?;
+        }
     }
-    cmd_new(ui, command, args)
+    Ok(())
 }
 
 fn cmd_rebase(ui: &mut Ui, command: &CommandHelper, args: &RebaseArgs) -> Result<(), CommandError> {
     let mut workspace_command = command.workspace_helper(ui)?;
+    if args.continue_ {
+        return continue_rebase(ui, &mut workspace_command);
+    } else if args.abort {
+        return abort_rebase(ui, &mut workspace_command);
+    }
+    if load_rebase_state(&workspace_command)?.is_some() {
+        return Err(UserError(
+            "A rebase is already in progress. Run `jj rebase --continue` or `jj rebase --abort` \
+             first."
+                .to_string(),
+        ));
+    }
     let new_parents = resolve_base_revs(&workspace_command, &args.destination)?;
-    if let Some(rev_str) = &args.revision {
-        rebase_revision(ui, &mut workspace_command, &new_parents, rev_str)?;
+    let default_branch = ["@".to_string()];
+    let branch_strs = if args.branch.is_empty() {
+        &default_branch
+    } else {
+        args.branch.as_slice()
+    };
+    if args.stop_on_conflict {
+        let (roots, description) = if let Some(source_str) = &args.source {
+            let source_commit = workspace_command.resolve_single_rev(source_str)?;
+            check_rebase_destinations(&workspace_command, &new_parents, &source_commit)?;
+            let description = format!("rebase commit {} and descendants", source_commit.id().hex());
+            (vec![source_commit], description)
+        } else {
+            let branch_commits =
+                resolve_rebase_branches(&workspace_command, &new_parents, branch_strs)?;
+            (
+                branch_roots(&workspace_command, &new_parents, &branch_commits)?,
+                format!(
+                    "rebase branch at {}",
+                    branch_commits
+                        .iter()
+                        .map(|commit| commit.id().hex())
+                        .join(", ")
+                ),
+            )
+        };
+        start_rebase(
+            ui,
+            &mut workspace_command,
+            &new_parents,
+            &roots,
+            &description,
+        )?;
+    } else if let Some(rev_str) = &args.revision {
+        rebase_revision(
+            ui,
+            &mut workspace_command,
+            &new_parents,
+            rev_str,
+            args.dry_run,
+        )?;
     } else if let Some(source_str) = &args.source {
-        rebase_descendants(ui, &mut workspace_command, &new_parents, source_str)?;
+        rebase_descendants(
+            ui,
+            &mut workspace_command,
+            &new_parents,
+            source_str,
+            args.dry_run,
+        )?;
     } else {
-        let branch_str = args.branch.as_deref().unwrap_or("@");
-        rebase_branch(ui, &mut workspace_command, &new_parents, branch_str)?;
+        rebase_branch(
+            ui,
+            &mut workspace_command,
+            &new_parents,
+            branch_strs,
+            args.dry_run,
+        )?;
     }
     Ok(())
 }
 
-fn rebase_branch(
-    ui: &mut Ui,
-    workspace_command: &mut WorkspaceCommandHelper,
+fn resolve_rebase_branches(
+    workspace_command: &WorkspaceCommandHelper,
     new_parents: &[Commit],
-    branch_str: &str,
-) -> Result<(), CommandError> {
-    let branch_commit = workspace_command.resolve_single_rev(branch_str)?;
-    let mut tx = workspace_command
-        .start_transaction(&format!("rebase branch at {}", branch_commit.id().hex()));
-    check_rebase_destinations(workspace_command, new_parents, &branch_commit)?;
+    branch_strs: &[String],
+) -> Result<Vec<Commit>, CommandError> {
+    let mut branch_commits = vec![];
+    for branch_str in branch_strs {
+        let branch_commit = workspace_command.resolve_single_rev(branch_str)?;
+        check_rebase_destinations(workspace_command, new_parents, &branch_commit)?;
+        branch_commits.push(branch_commit);
+    }
+    Ok(branch_commits)
+}
 
+/// Returns the commits where the branches forked off of the destination's
+/// history, except for ones that are already on top of the destination.
+fn branch_roots(
+    workspace_command: &WorkspaceCommandHelper,
+    new_parents: &[Commit],
+    branch_commits: &[Commit],
+) -> Result<Vec<Commit>, CommandError> {
     let parent_ids = new_parents
         .iter()
         .map(|commit| commit.id().clone())
         .collect_vec();
-    let roots_expression = RevsetExpression::commits(parent_ids)
-        .range(&RevsetExpression::commit(branch_commit.id().clone()))
+    let branch_ids = branch_commits
+        .iter()
+        .map(|commit| commit.id().clone())
+        .collect_vec();
+    // The roots of the commits that are ancestors of the branch heads but not of
+    // the destination. Those are the commits where the branch forked off of the
+    // destination's history, even if the branch has been merged with it since.
+    let roots_expression = RevsetExpression::commits(parent_ids.clone())
+        .range(&RevsetExpression::commits(branch_ids))
         .roots();
-    let mut num_rebased = 0;
-    let store = workspace_command.repo().store();
+    let mut roots = vec![];
     for root_result in roots_expression
         .evaluate(
             workspace_command.repo().as_repo_ref(),
@@ -3109,96 +5773,414 @@ fn rebase_branch(
         )
         .unwrap()
         .iter()
-        .commits(store)
+        .commits(workspace_command.repo().store())
     {
         let root_commit = root_result?;
-        workspace_command.check_rewriteable(&root_commit)?;
-        rebase_commit(ui.settings(), tx.mut_repo(), &root_commit, new_parents);
-        num_rebased += 1;
+        if root_commit.parent_ids() != parent_ids {
+            workspace_command.check_rewriteable(&root_commit)?;
+            roots.push(root_commit);
+        }
     }
-    num_rebased += tx.mut_repo().rebase_descendants(ui.settings())?;
-    writeln!(ui, "Rebased {} commits", num_rebased)?;
-    workspace_command.finish_transaction(ui, tx)?;
-    Ok(())
+    Ok(roots)
 }
 
-fn rebase_descendants(
+fn rebase_branch(
     ui: &mut Ui,
     workspace_command: &mut WorkspaceCommandHelper,
     new_parents: &[Commit],
-    source_str: &str,
+    branch_strs: &[String],
+    dry_run: bool,
 ) -> Result<(), CommandError> {
-    let old_commit = workspace_command.resolve_single_rev(source_str)?;
-    workspace_command.check_rewriteable(&old_commit)?;
-    check_rebase_destinations(workspace_command, new_parents, &old_commit)?;
+    let branch_commits = resolve_rebase_branches(workspace_command, new_parents, branch_strs)?;
+    let roots = branch_roots(workspace_command, new_parents, &branch_commits)?;
     let mut tx = workspace_command.start_transaction(&format!(
-        "rebase commit {} and descendants",
-        old_commit.id().hex()
+        "rebase branch at {}",
+        branch_commits
+            .iter()
+            .map(|commit| commit.id().hex())
+            .join(", ")
     ));
-    rebase_commit(ui.settings(), tx.mut_repo(), &old_commit, new_parents);
-    let num_rebased = tx.mut_repo().rebase_descendants(ui.settings())? + 1;
-    writeln!(ui, "Rebased {} commits", num_rebased)?;
-    workspace_command.finish_transaction(ui, tx)?;
+    for root_commit in &roots {
+        rebase_commit(ui.settings(), tx.mut_repo(), root_commit, new_parents);
+    }
+    // Descendants of the roots are rebased along with them. Merge parents that
+    // are ancestors of the destination become redundant and are dropped.
+    let num_rebased = roots.len() + tx.mut_repo().rebase_descendants(ui.settings())?;
+    if !dry_run && num_rebased > 0 {
+        writeln!(ui, "Rebased {} commits", num_rebased)?;
+    }
+    workspace_command.finish_transaction_or_preview(ui, tx, dry_run)?;
     Ok(())
 }
 
-fn rebase_revision(
+/// The plan of a `jj rebase --stop-on-conflict` that stopped at a commit with
+/// conflicts. It's stored in `.jj/rebase-state` until the rebase is continued
+/// or aborted. Commit ids are in hex.
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+struct RebaseState {
+    /// The operation the rebase started at
+    operation: String,
+    /// The working-copy commit when the rebase started
+    working_copy: Option<String>,
+    destination: Vec<String>,
+    /// The commits to rebase onto the destination
+    roots: Vec<String>,
+    /// The commits left to rebase, parents first
+    remaining: Vec<String>,
+    /// The commits rebased so far, and what they were rebased to
+    rewritten: BTreeMap<String, String>,
+    /// The commit whose rebased version has conflicts
+    stopped_at: Option<String>,
+}
+
+fn rebase_state_path(workspace_command: &WorkspaceCommandHelper) -> PathBuf {
+    workspace_command
+        .workspace_root()
+        .join(".jj")
+        .join("rebase-state")
+}
+
+fn load_rebase_state(
+    workspace_command: &WorkspaceCommandHelper,
+) -> Result<Option<RebaseState>, CommandError> {
+    match fs::read(rebase_state_path(workspace_command)) {
+        Ok(content) => serde_json::from_slice(&content).map(Some).map_err(|err| {
+            CommandError::InternalError(format!("Failed to read the rebase state: {err}"))
+        }),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn start_rebase(
     ui: &mut Ui,
     workspace_command: &mut WorkspaceCommandHelper,
     new_parents: &[Commit],
-    rev_str: &str,
+    roots: &[Commit],
+    description: &str,
 ) -> Result<(), CommandError> {
-    let old_commit = workspace_command.resolve_single_rev(rev_str)?;
-    workspace_command.check_rewriteable(&old_commit)?;
-    check_rebase_destinations(workspace_command, new_parents, &old_commit)?;
-    let mut tx =
-        workspace_command.start_transaction(&format!("rebase commit {}", old_commit.id().hex()));
-    rebase_commit(ui.settings(), tx.mut_repo(), &old_commit, new_parents);
-    // Manually rebase children because we don't want to rebase them onto the
-    // rewritten commit. (But we still want to record the commit as rewritten so
-    // branches and the working copy get updated to the rewritten commit.)
-    let children_expression = RevsetExpression::commit(old_commit.id().clone()).children();
-    let mut num_rebased_descendants = 0;
-    let store = workspace_command.repo().store();
-
-    for child_commit in children_expression
+    let root_ids = roots.iter().map(|commit| commit.id().clone()).collect_vec();
+    let mut remaining = RevsetExpression::commits(root_ids)
+        .descendants()
         .evaluate(
             workspace_command.repo().as_repo_ref(),
             Some(&workspace_command.workspace_id()),
         )
         .unwrap()
         .iter()
-        .commits(store)
-    {
-        let child_commit = child_commit?;
-        let new_child_parent_ids: Vec<CommitId> = child_commit
-            .parents()
-            .iter()
-            .flat_map(|c| {
-                if c == &old_commit {
-                    old_commit
-                        .parents()
-                        .iter()
-                        .map(|c| c.id().clone())
-                        .collect()
-                } else {
-                    [c.id().clone()].to_vec()
-                }
-            })
-            .collect();
+        .commit_ids()
+        .map(|id| id.hex())
+        .collect_vec();
+    remaining.reverse();
+    let state = RebaseState {
+        operation: workspace_command.repo().op_id().hex(),
+        working_copy: workspace_command
+            .repo()
+            .view()
+            .get_wc_commit_id(&workspace_command.workspace_id())
+            .map(|id| id.hex()),
+        destination: new_parents.iter().map(|commit| commit.id().hex()).collect(),
+        roots: roots.iter().map(|commit| commit.id().hex()).collect(),
+        remaining,
+        rewritten: BTreeMap::new(),
+        stopped_at: None,
+    };
+    let tx = workspace_command.start_transaction(description);
+    run_rebase_steps(ui, workspace_command, tx, state)
+}
 
-        // Some of the new parents may be ancestors of others as in
-        // `test_rebase_single_revision`.
-        let new_child_parents: Result<Vec<Commit>, BackendError> = RevsetExpression::Difference(
-            RevsetExpression::commits(new_child_parent_ids.clone()),
-            RevsetExpression::commits(new_child_parent_ids.clone())
-                .parents()
-                .ancestors(),
-        )
-        .evaluate(
-            workspace_command.repo().as_repo_ref(),
-            Some(&workspace_command.workspace_id()),
-        )
+/// Rebases the remaining commits in `state` until one of them gets conflicts.
+/// The state is then saved so the rebase can be continued later.
+fn run_rebase_steps(
+    ui: &mut Ui,
+    workspace_command: &mut WorkspaceCommandHelper,
+    mut tx: Transaction,
+    mut state: RebaseState,
+) -> Result<(), CommandError> {
+    let store = workspace_command.repo().store().clone();
+    let workspace_id = workspace_command.workspace_id();
+    let mut num_rebased = 0;
+    while !state.remaining.is_empty() {
+        let old_commit = store.get_commit(&CommitId::from_hex(&state.remaining.remove(0)))?;
+        let mut new_parent_ids = if state.roots.contains(&old_commit.id().hex()) {
+            state
+                .destination
+                .iter()
+                .map(|hex| CommitId::from_hex(hex))
+                .collect_vec()
+        } else {
+            old_commit
+                .parent_ids()
+                .iter()
+                .map(|id| match state.rewritten.get(&id.hex()) {
+                    Some(new_hex) => CommitId::from_hex(new_hex),
+                    None => id.clone(),
+                })
+                .unique()
+                .collect_vec()
+        };
+        if new_parent_ids.len() > 1 {
+            // Drop parents that are ancestors of other parents
+            let heads: HashSet<_> = tx
+                .mut_repo()
+                .index()
+                .heads(&new_parent_ids)
+                .into_iter()
+                .collect();
+            new_parent_ids.retain(|id| heads.contains(id));
+        }
+        if new_parent_ids == old_commit.parent_ids() {
+            continue;
+        }
+        let new_parents: Vec<_> = new_parent_ids
+            .iter()
+            .map(|id| store.get_commit(id))
+            .try_collect()?;
+        let new_commit = rebase_commit(ui.settings(), tx.mut_repo(), &old_commit, &new_parents);
+        state
+            .rewritten
+            .insert(old_commit.id().hex(), new_commit.id().hex());
+        num_rebased += 1;
+        if new_commit.tree().has_conflict() && !old_commit.tree().has_conflict() {
+            // Leave the commits that haven't been rebased yet where they are
+            // until the conflicts have been resolved. Hide them and the rebased
+            // commits' originals so they don't show up as divergent.
+            tx.mut_repo().clear_rewritten_commits();
+            for hex in state.rewritten.keys().chain(&state.remaining) {
+                tx.mut_repo().remove_head(&CommitId::from_hex(hex));
+            }
+            tx.mut_repo()
+                .set_wc_commit(workspace_id, new_commit.id().clone());
+            state.stopped_at = Some(old_commit.id().hex());
+            writeln!(ui, "Rebased {} commits", num_rebased)?;
+            workspace_command.finish_transaction(ui, tx)?;
+            let content = serde_json::to_vec_pretty(&state).unwrap();
+            fs::write(rebase_state_path(workspace_command), content)?;
+            writeln!(
+                ui,
+                "Stopped at {} because it has conflicts",
+                short_commit_description(&new_commit)
+            )?;
+            ui.write_hint(
+                "Hint: Resolve the conflicts and run `jj rebase --continue`, or run `jj rebase \
+                 --abort` to undo the rebase.\n",
+            )?;
+            return Ok(());
+        }
+    }
+
+    // All commits have been rebased, so move branches and the working copy to
+    // the rebased commits and hide the old ones. The old ones were hidden if
+    // the rebase stopped, so make them visible again for the descendant rebaser
+    // to find them.
+    for (old_hex, new_hex) in &state.rewritten {
+        let old_commit = store.get_commit(&CommitId::from_hex(old_hex))?;
+        tx.mut_repo().add_head(&old_commit);
+        tx.mut_repo()
+            .record_rewritten_commit(old_commit.id().clone(), CommitId::from_hex(new_hex));
+    }
+    if let Some(wc_hex) = &state.working_copy {
+        let wc_hex = state.rewritten.get(wc_hex).unwrap_or(wc_hex);
+        tx.mut_repo()
+            .set_wc_commit(workspace_id, CommitId::from_hex(wc_hex));
+    }
+    if num_rebased > 0 {
+        writeln!(ui, "Rebased {} commits", num_rebased)?;
+    }
+    workspace_command.finish_transaction(ui, tx)?;
+    fs::remove_file(rebase_state_path(workspace_command)).or_else(|err| {
+        if err.kind() == io::ErrorKind::NotFound {
+            Ok(())
+        } else {
+            Err(err)
+        }
+    })?;
+    Ok(())
+}
+
+fn continue_rebase(
+    ui: &mut Ui,
+    workspace_command: &mut WorkspaceCommandHelper,
+) -> Result<(), CommandError> {
+    let mut state = load_rebase_state(workspace_command)?
+        .ok_or_else(|| UserError("No rebase in progress".to_string()))?;
+    let stopped_at = state.stopped_at.take().unwrap();
+    let repo = workspace_command.repo();
+    let rebased_commit = repo
+        .store()
+        .get_commit(&CommitId::from_hex(&state.rewritten[&stopped_at]))?;
+    // The commit has likely been rewritten while resolving the conflicts, so
+    // look it up by its change id. The original commit still has the same change
+    // id.
+    let candidates = RevsetExpression::all()
+        .evaluate(repo.as_repo_ref(), None)
+        .unwrap()
+        .iter()
+        .filter(|entry| {
+            entry.change_id() == *rebased_commit.change_id()
+                && !state.rewritten.contains_key(&entry.commit_id().hex())
+        })
+        .map(|entry| entry.commit_id())
+        .collect_vec();
+    let resolved_commit = match candidates.as_slice() {
+        [commit_id] => repo.store().get_commit(commit_id)?,
+        _ => {
+            return Err(UserError(format!(
+                "Cannot find the commit the rebase stopped at (change {}). It may have been \
+                 abandoned or become divergent. Run `jj rebase --abort` to undo the rebase.",
+                short_change_hash(rebased_commit.change_id())
+            )))
+        }
+    };
+    if resolved_commit.tree().has_conflict() {
+        return Err(UserError(format!(
+            "Commit {} still has conflicts. Resolve them and run `jj rebase --continue` again.",
+            short_commit_hash(resolved_commit.id())
+        )));
+    }
+    state
+        .rewritten
+        .insert(stopped_at, resolved_commit.id().hex());
+    let tx = workspace_command.start_transaction("continue rebase");
+    run_rebase_steps(ui, workspace_command, tx, state)
+}
+
+fn abort_rebase(
+    ui: &mut Ui,
+    workspace_command: &mut WorkspaceCommandHelper,
+) -> Result<(), CommandError> {
+    let state = load_rebase_state(workspace_command)?
+        .ok_or_else(|| UserError("No rebase in progress".to_string()))?;
+    let repo = workspace_command.repo().clone();
+    let workspace_id = workspace_command.workspace_id();
+    let original_ids = state
+        .rewritten
+        .keys()
+        .chain(&state.remaining)
+        .map(|hex| CommitId::from_hex(hex))
+        .collect::<HashSet<_>>();
+    // Only the commits the rebase rewrote are reverted, so other changes made
+    // while the rebase was stopped are kept. The rebased commits may have been
+    // rewritten since, so they're looked up by change id.
+    let mut original_by_change_id = HashMap::new();
+    for (old_hex, new_hex) in &state.rewritten {
+        let new_commit = repo.store().get_commit(&CommitId::from_hex(new_hex))?;
+        original_by_change_id.insert(new_commit.change_id().clone(), CommitId::from_hex(old_hex));
+    }
+    let mut tx = workspace_command.start_transaction("abort rebase");
+    let mut_repo = tx.mut_repo();
+    for entry in RevsetExpression::all()
+        .evaluate(repo.as_repo_ref(), None)
+        .unwrap()
+        .iter()
+    {
+        if let Some(original_id) = original_by_change_id.get(&entry.change_id()) {
+            if !original_ids.contains(&entry.commit_id()) {
+                mut_repo.record_rewritten_commit(entry.commit_id(), original_id.clone());
+            }
+        }
+    }
+    for original_id in &original_ids {
+        let original_commit = repo.store().get_commit(original_id)?;
+        mut_repo.add_head(&original_commit);
+    }
+    let wc_was_rebased =
+        repo.view()
+            .get_wc_commit_id(&workspace_id)
+            .map_or(false, |wc_commit_id| {
+                let wc_commit = repo.store().get_commit(wc_commit_id).unwrap();
+                original_by_change_id.contains_key(wc_commit.change_id())
+            });
+    mut_repo.rebase_descendants(ui.settings())?;
+    if let (true, Some(wc_hex)) = (wc_was_rebased, &state.working_copy) {
+        mut_repo.set_wc_commit(workspace_id, CommitId::from_hex(wc_hex));
+    }
+    workspace_command.finish_transaction(ui, tx)?;
+    fs::remove_file(rebase_state_path(workspace_command))?;
+    Ok(())
+}
+
+fn rebase_descendants(
+    ui: &mut Ui,
+    workspace_command: &mut WorkspaceCommandHelper,
+    new_parents: &[Commit],
+    source_str: &str,
+    dry_run: bool,
+) -> Result<(), CommandError> {
+    let old_commit = workspace_command.resolve_single_rev(source_str)?;
+    workspace_command.check_rewriteable(&old_commit)?;
+    check_rebase_destinations(workspace_command, new_parents, &old_commit)?;
+    let mut tx = workspace_command.start_transaction(&format!(
+        "rebase commit {} and descendants",
+        old_commit.id().hex()
+    ));
+    rebase_commit(ui.settings(), tx.mut_repo(), &old_commit, new_parents);
+    let num_rebased = tx.mut_repo().rebase_descendants(ui.settings())? + 1;
+    if !dry_run {
+        writeln!(ui, "Rebased {} commits", num_rebased)?;
+    }
+    workspace_command.finish_transaction_or_preview(ui, tx, dry_run)?;
+    Ok(())
+}
+
+fn rebase_revision(
+    ui: &mut Ui,
+    workspace_command: &mut WorkspaceCommandHelper,
+    new_parents: &[Commit],
+    rev_str: &str,
+    dry_run: bool,
+) -> Result<(), CommandError> {
+    let old_commit = workspace_command.resolve_single_rev(rev_str)?;
+    workspace_command.check_rewriteable(&old_commit)?;
+    check_rebase_destinations(workspace_command, new_parents, &old_commit)?;
+    let mut tx =
+        workspace_command.start_transaction(&format!("rebase commit {}", old_commit.id().hex()));
+    rebase_commit(ui.settings(), tx.mut_repo(), &old_commit, new_parents);
+    // Manually rebase children because we don't want to rebase them onto the
+    // rewritten commit. (But we still want to record the commit as rewritten so
+    // branches and the working copy get updated to the rewritten commit.)
+    let children_expression = RevsetExpression::commit(old_commit.id().clone()).children();
+    let mut num_rebased_descendants = 0;
+    let store = workspace_command.repo().store();
+
+    for child_commit in children_expression
+        .evaluate(
+            workspace_command.repo().as_repo_ref(),
+            Some(&workspace_command.workspace_id()),
+        )
+        .unwrap()
+        .iter()
+        .commits(store)
+    {
+        let child_commit = child_commit?;
+        let new_child_parent_ids: Vec<CommitId> = child_commit
+            .parents()
+            .iter()
+            .flat_map(|c| {
+                if c == &old_commit {
+                    old_commit
+                        .parents()
+                        .iter()
+                        .map(|c| c.id().clone())
+                        .collect()
+                } else {
+                    [c.id().clone()].to_vec()
+                }
+            })
+            .collect();
+
+        // Some of the new parents may be ancestors of others as in
+        // `test_rebase_single_revision`.
+        let new_child_parents: Result<Vec<Commit>, BackendError> = RevsetExpression::Difference(
+            RevsetExpression::commits(new_child_parent_ids.clone()),
+            RevsetExpression::commits(new_child_parent_ids.clone())
+                .parents()
+                .ancestors(),
+        )
+        .evaluate(
+            workspace_command.repo().as_repo_ref(),
+            Some(&workspace_command.workspace_id()),
+        )
         .unwrap()
         .iter()
         .commits(store)
@@ -3213,14 +6195,14 @@ fn rebase_revision(
         num_rebased_descendants += 1;
     }
     num_rebased_descendants += tx.mut_repo().rebase_descendants(ui.settings())?;
-    if num_rebased_descendants > 0 {
+    if num_rebased_descendants > 0 && !dry_run {
         writeln!(
             ui,
             "Also rebased {} descendant commits onto parent of rebased commit",
             num_rebased_descendants
         )?;
     }
-    workspace_command.finish_transaction(ui, tx)?;
+    workspace_command.finish_transaction_or_preview(ui, tx, dry_run)?;
     Ok(())
 }
 
@@ -3351,6 +6333,7 @@ fn cmd_branch(
         BranchSubcommand::Set {
             revision,
             allow_backwards,
+            resolve,
             names: branch_names,
         } => {
             if branch_names.len() > 1 {
@@ -3362,7 +6345,25 @@ fn cmd_branch(
 
             let target_commit =
                 workspace_command.resolve_single_rev(revision.as_deref().unwrap_or("@"))?;
-            if !allow_backwards
+            if *resolve {
+                for branch_name in branch_names {
+                    match view.get_local_branch(branch_name) {
+                        Some(RefTarget::Conflict { .. }) => {}
+                        Some(RefTarget::Normal(_)) => {
+                            return Err(CommandError::UserError(format!(
+                                "Branch {} is not conflicted",
+                                branch_name
+                            )));
+                        }
+                        None => {
+                            return Err(CommandError::UserError(format!(
+                                "No such branch: {}",
+                                branch_name
+                            )));
+                        }
+                    }
+                }
+            } else if !allow_backwards
                 && !branch_names.iter().all(|branch_name| {
                     is_fast_forward(
                         workspace_command.repo().as_repo_ref(),
@@ -3377,7 +6378,8 @@ fn cmd_branch(
                 ));
             }
             let mut tx = workspace_command.start_transaction(&format!(
-                "point {} to commit {}",
+                "{} {} to commit {}",
+                if *resolve { "resolve" } else { "point" },
                 make_branch_term(branch_names),
                 target_commit.id().hex()
             ));
@@ -3410,8 +6412,73 @@ fn cmd_branch(
             workspace_command.finish_transaction(ui, tx)?;
         }
 
-        BranchSubcommand::List => {
-            list_branches(ui, &workspace_command)?;
+        BranchSubcommand::Rename { old, new } => {
+            let target = match view.get_local_branch(old) {
+                Some(target) => target,
+                None => return Err(CommandError::UserError(format!("No such branch: {}", old))),
+            };
+            if view.get_local_branch(new).is_some() {
+                return Err(CommandError::UserError(format!(
+                    "Branch already exists: {}",
+                    new
+                )));
+            }
+            let untrack_old = match ui
+                .settings()
+                .config()
+                .get_string("branch.rename-old-remote")
+            {
+                Ok(value) if value == "untrack" => true,
+                Ok(value) if value == "delete" => false,
+                Ok(value) => {
+                    return Err(CommandError::ConfigError(format!(
+                        "Invalid branch.rename-old-remote: {value} (expected \"delete\" or \
+                         \"untrack\")"
+                    )))
+                }
+                Err(_) => false,
+            };
+            let remotes = view
+                .get_branch(old)
+                .map(|branch_target| branch_target.remote_targets.keys().cloned().collect_vec())
+                .unwrap_or_default();
+            let mut tx =
+                workspace_command.start_transaction(&format!("rename branch {old} to {new}"));
+            tx.mut_repo().set_local_branch(new.clone(), target);
+            if untrack_old {
+                tx.mut_repo().remove_branch(old);
+            } else {
+                // Leaves the remote branches, which pushing deletes
+                tx.mut_repo().remove_local_branch(old);
+            }
+            workspace_command.finish_transaction(ui, tx)?;
+            if !untrack_old && !remotes.is_empty() {
+                ui.write_hint(format!(
+                    "Branch {old} still exists on {}. Use `jj git push --branch {old}` or `jj git \
+                     push --all` to delete it there.\n",
+                    remotes.join(", ")
+                ))?;
+            }
+        }
+
+        BranchSubcommand::List {
+            names,
+            remote,
+            sort,
+            template,
+        } => {
+            let patterns = names
+                .iter()
+                .map(|name| RefNamePattern::glob(name))
+                .collect_vec();
+            list_branches(
+                ui,
+                &workspace_command,
+                &patterns,
+                remote.as_deref(),
+                *sort,
+                template.as_deref(),
+            )?;
         }
     }
 
@@ -3421,8 +6488,55 @@ fn cmd_branch(
 fn list_branches(
     ui: &mut Ui,
     workspace_command: &WorkspaceCommandHelper,
+    patterns: &[RefNamePattern],
+    remote: Option<&str>,
+    sort: BranchSortKey,
+    template: Option<&str>,
 ) -> Result<(), CommandError> {
     let repo = workspace_command.repo();
+    let mut branches = repo
+        .view()
+        .branches_matching(patterns)
+        .filter(|(_name, target)| {
+            remote.map_or(true, |remote| target.remote_targets.contains_key(remote))
+        })
+        .collect_vec();
+    if sort == BranchSortKey::Date {
+        // Newest first. Branches without a single local target go last.
+        let timestamp = |target: &BranchTarget| match &target.local_target {
+            Some(RefTarget::Normal(id)) => repo
+                .store()
+                .get_commit(id)
+                .map(|commit| Some(commit.committer().timestamp.timestamp.0)),
+            _ => Ok(None),
+        };
+        let mut keyed = vec![];
+        for (name, target) in branches {
+            keyed.push((timestamp(target)?, name, target));
+        }
+        keyed.sort_by(|(time1, name1, _), (time2, name2, _)| {
+            time2.cmp(time1).then_with(|| name1.cmp(name2))
+        });
+        branches = keyed
+            .into_iter()
+            .map(|(_time, name, target)| (name, target))
+            .collect();
+    }
+
+    if let Some(template_string) = template {
+        let template =
+            crate::template_parser::parse_branch_template(repo.as_repo_ref(), template_string);
+        let mut formatter = ui.stdout_formatter();
+        for (name, target) in branches {
+            let context = BranchContext {
+                name: name.clone(),
+                target: target.clone(),
+                tracked_remote: Some(remote.unwrap_or("origin").to_string()),
+            };
+            template.format(&context, formatter.as_mut())?;
+        }
+        return Ok(());
+    }
 
     let workspace_id = workspace_command.workspace_id();
     let print_branch_target =
@@ -3461,15 +6575,20 @@ fn list_branches(
         };
 
     let index = repo.index();
-    for (name, branch_target) in repo.view().branches() {
+    let mut conflicted_branches = vec![];
+    for (name, branch_target) in branches {
+        if let Some(RefTarget::Conflict { .. }) = &branch_target.local_target {
+            conflicted_branches.push(name.clone());
+        }
         ui.stdout_formatter().add_label("branch".to_string())?;
         write!(ui, "{}", name)?;
         ui.stdout_formatter().remove_label()?;
         print_branch_target(ui, branch_target.local_target.as_ref())?;
 
-        for (remote, remote_target) in branch_target
+        for (remote_name, remote_target) in branch_target
             .remote_targets
             .iter()
+            .filter(|(remote_name, _target)| remote.map_or(true, |remote| remote == *remote_name))
             .sorted_by_key(|(name, _target)| name.to_owned())
         {
             if Some(remote_target) == branch_target.local_target.as_ref() {
@@ -3477,20 +6596,25 @@ fn list_branches(
             }
             write!(ui, "  ")?;
             ui.stdout_formatter().add_label("branch".to_string())?;
-            write!(ui, "@{}", remote)?;
+            write!(ui, "@{}", remote_name)?;
             ui.stdout_formatter().remove_label()?;
             if let Some(local_target) = branch_target.local_target.as_ref() {
-                let remote_ahead_count = index
-                    .walk_revs(&remote_target.adds(), &local_target.adds())
-                    .count();
-                let local_ahead_count = index
-                    .walk_revs(&local_target.adds(), &remote_target.adds())
-                    .count();
-                if remote_ahead_count != 0 && local_ahead_count == 0 {
+                let divergence = ref_divergence_bounded(
+                    index.as_index_ref(),
+                    local_target,
+                    remote_target,
+                    DIVERGENCE_LIMIT,
+                );
+                // The counts are from the remote branch's point of view
+                let (remote_ahead_count, local_ahead_count) = (
+                    format_divergence_count(divergence.behind),
+                    format_divergence_count(divergence.ahead),
+                );
+                if divergence.behind != 0 && divergence.ahead == 0 {
                     write!(ui, " (ahead by {} commits)", remote_ahead_count)?;
-                } else if remote_ahead_count == 0 && local_ahead_count != 0 {
+                } else if divergence.behind == 0 && divergence.ahead != 0 {
                     write!(ui, " (behind by {} commits)", local_ahead_count)?;
-                } else if remote_ahead_count != 0 && local_ahead_count != 0 {
+                } else if divergence.behind != 0 && divergence.ahead != 0 {
                     write!(
                         ui,
                         " (ahead by {} commits, behind by {} commits)",
@@ -3502,61 +6626,744 @@ fn list_branches(
         }
     }
 
+    if !conflicted_branches.is_empty() {
+        ui.write_hint(format!(
+            "Some branches have conflicting targets: {}\nUse `jj branch set <name> -r <rev> \
+             --resolve` to point a branch to a single target, or `jj branch forget <name>` to \
+             drop the local branch.\n",
+            conflicted_branches.join(", ")
+        ))?;
+    }
+
     Ok(())
 }
 
-fn cmd_debug(
+fn cmd_name(
     ui: &mut Ui,
     command: &CommandHelper,
-    subcommand: &DebugCommands,
+    subcommand: &NameSubcommand,
 ) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
     match subcommand {
-        DebugCommands::Completion(completion_matches) => {
-            let mut app = command.app().clone();
-            let mut buf = vec![];
-            let shell = if completion_matches.zsh {
-                clap_complete::Shell::Zsh
-            } else if completion_matches.fish {
-                clap_complete::Shell::Fish
-            } else {
-                clap_complete::Shell::Bash
-            };
-            clap_complete::generate(shell, &mut app, "jj", &mut buf);
-            ui.stdout_formatter().write_all(&buf)?;
-        }
-        DebugCommands::Mangen(_mangen_matches) => {
-            let mut buf = vec![];
-            let man = clap_mangen::Man::new(command.app().clone());
-            man.render(&mut buf)?;
-            ui.stdout_formatter().write_all(&buf)?;
-        }
-        DebugCommands::ResolveRev(resolve_matches) => {
-            let workspace_command = command.workspace_helper(ui)?;
-            let commit = workspace_command.resolve_single_rev(&resolve_matches.revision)?;
-            writeln!(ui, "{}", commit.id().hex())?;
+        NameSubcommand::Set { revision, names } => {
+            let commit = workspace_command.resolve_single_rev(revision)?;
+            if commit.id() == workspace_command.repo().store().root_commit_id() {
+                return Err(UserError("Cannot name the root commit".to_string()));
+            }
+            for name in names {
+                if name.is_empty() || name.contains(char::is_whitespace) {
+                    return Err(UserError(format!("Invalid name: \"{name}\"")));
+                }
+            }
+            let mut tx = workspace_command.start_transaction(&format!(
+                "name change {} {}",
+                commit.change_id().hex(),
+                names.join(", ")
+            ));
+            for name in names {
+                tx.mut_repo()
+                    .set_change_name(name.clone(), commit.change_id().clone());
+            }
+            workspace_command.finish_transaction(ui, tx)?;
         }
-        DebugCommands::WorkingCopy(_wc_matches) => {
-            let workspace_command = command.workspace_helper(ui)?;
-            let wc = workspace_command.working_copy();
-            writeln!(ui, "Current operation: {:?}", wc.operation_id())?;
-            writeln!(ui, "Current tree: {:?}", wc.current_tree_id())?;
-            for (file, state) in wc.file_states() {
-                writeln!(
-                    ui,
-                    "{:?} {:13?} {:10?} {:?}",
-                    state.file_type, state.size, state.mtime.0, file
-                )?;
+        NameSubcommand::Delete { names } => {
+            let view = workspace_command.repo().view();
+            for name in names {
+                if !view.change_names().contains_key(name) {
+                    return Err(UserError(format!("No such name: {name}")));
+                }
+            }
+            let mut tx =
+                workspace_command.start_transaction(&format!("delete names {}", names.join(", ")));
+            for name in names {
+                tx.mut_repo().remove_change_name(name);
             }
+            workspace_command.finish_transaction(ui, tx)?;
         }
-        DebugCommands::Template(template_matches) => {
-            let parse = TemplateParser::parse(
-                crate::template_parser::Rule::template,
-                &template_matches.template,
-            );
-            writeln!(ui, "{:?}", parse)?;
+        NameSubcommand::List => {
+            let repo = workspace_command.repo();
+            let workspace_id = workspace_command.workspace_id();
+            for (name, change_id) in repo.view().change_names() {
+                let commits: Vec<_> = resolve_change_name(repo.as_repo_ref(), change_id)
+                    .iter()
+                    .map(|id| repo.store().get_commit(id))
+                    .try_collect()?;
+                ui.stdout_formatter()
+                    .add_label("change_names".to_string())?;
+                write!(ui, "{name}")?;
+                ui.stdout_formatter().remove_label()?;
+                match commits.as_slice() {
+                    [] => {
+                        writeln!(
+                            ui,
+                            ": {} (no visible commits)",
+                            short_change_hash(change_id)
+                        )?;
+                    }
+                    [commit] => {
+                        write!(ui, ": ")?;
+                        ui.write_commit_summary(repo.as_repo_ref(), &workspace_id, commit)?;
+                        writeln!(ui)?;
+                    }
+                    commits => {
+                        write!(ui, " ")?;
+                        ui.stdout_formatter().add_label("divergent".to_string())?;
+                        write!(ui, "(divergent)")?;
+                        ui.stdout_formatter().remove_label()?;
+                        writeln!(ui, ":")?;
+                        for commit in commits {
+                            write!(ui, "  ")?;
+                            ui.write_commit_summary(repo.as_repo_ref(), &workspace_id, commit)?;
+                            writeln!(ui)?;
+                        }
+                    }
+                }
+            }
         }
-        DebugCommands::Index(_index_matches) => {
-            let workspace_command = command.workspace_helper(ui)?;
+    }
+    Ok(())
+}
+
+fn cmd_topic(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    subcommand: &TopicSubcommand,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    match subcommand {
+        TopicSubcommand::Set { revisions, name } => {
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(UserError(format!("Invalid topic: \"{name}\"")));
+            }
+            let commits = workspace_command.resolve_revset(revisions)?;
+            workspace_command.check_non_empty(&commits)?;
+            let root_commit_id = workspace_command.repo().store().root_commit_id();
+            if commits.iter().any(|commit| commit.id() == root_commit_id) {
+                return Err(UserError(
+                    "Cannot set the topic of the root commit".to_string(),
+                ));
+            }
+            let change_ids: BTreeSet<_> = commits
+                .iter()
+                .map(|commit| commit.change_id().clone())
+                .collect();
+            let mut tx = workspace_command
+                .start_transaction(&format!("set topic {name} on {} changes", change_ids.len()));
+            for change_id in change_ids {
+                tx.mut_repo().set_change_topic(change_id, name.clone());
+            }
+            workspace_command.finish_transaction(ui, tx)?;
+        }
+        TopicSubcommand::Clear { revisions } => {
+            let commits = workspace_command.resolve_revset(revisions)?;
+            let view = workspace_command.repo().view();
+            let change_ids: BTreeSet<_> = commits
+                .iter()
+                .map(|commit| commit.change_id().clone())
+                .filter(|change_id| view.topic_for_change(change_id).is_some())
+                .collect();
+            if change_ids.is_empty() {
+                writeln!(ui, "Nothing changed.")?;
+                return Ok(());
+            }
+            let mut tx = workspace_command
+                .start_transaction(&format!("clear topic of {} changes", change_ids.len()));
+            for change_id in &change_ids {
+                tx.mut_repo().remove_change_topic(change_id);
+            }
+            workspace_command.finish_transaction(ui, tx)?;
+        }
+        TopicSubcommand::List => {
+            let repo = workspace_command.repo();
+            let workspace_id = workspace_command.workspace_id();
+            let topics: BTreeSet<_> = repo.view().change_topics().values().collect();
+            for topic in topics {
+                let commits: Vec<_> = RevsetExpression::all()
+                    .with_topic(topic.clone())
+                    .evaluate(repo.as_repo_ref(), Some(&workspace_id))?
+                    .iter()
+                    .commits(repo.store())
+                    .try_collect()?;
+                ui.stdout_formatter().add_label("topic".to_string())?;
+                write!(ui, "{topic}")?;
+                ui.stdout_formatter().remove_label()?;
+                writeln!(ui, ":")?;
+                for commit in &commits {
+                    write!(ui, "  ")?;
+                    ui.write_commit_summary(repo.as_repo_ref(), &workspace_id, commit)?;
+                    writeln!(ui)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn cmd_debug_graph(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &DebugGraphArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo();
+    let workspace_id = workspace_command.workspace_id();
+    let checkout_id = repo.view().get_wc_commit_id(&workspace_id);
+    let revset = workspace_command.evaluate_revset(&args.revisions)?;
+    let template_string = args
+        .template
+        .as_deref()
+        .unwrap_or(r#"commit_id.short() "\n" description.first_line()"#);
+    let template = crate::template_parser::parse_commit_template(
+        repo.as_repo_ref(),
+        &workspace_id,
+        template_string,
+    );
+    let index = repo.index();
+    let dot_id = |commit_id: &CommitId| format!("\"{}\"", commit_id.hex());
+
+    match args.format {
+        GraphFormat::Dot => {
+            writeln!(ui, "digraph {{")?;
+            writeln!(ui, "  node [shape=box];")?;
+            for (index_entry, edges) in revset.iter().graph() {
+                let commit_id = index_entry.commit_id();
+                let commit = repo.store().get_commit(&commit_id)?;
+                let mut label = vec![];
+                template.format(&commit, &mut PlainTextFormatter::new(Box::new(&mut label)))?;
+                let label = String::from_utf8_lossy(&label);
+                let mut attributes = format!("label={}", dot_string(label.trim_end()));
+                if Some(&commit_id) == checkout_id {
+                    attributes.push_str(", style=bold");
+                }
+                writeln!(ui, "  {} [{attributes}];", dot_id(&commit_id))?;
+                for edge in edges {
+                    // Edges to commits outside the set are left out
+                    let style = match edge.edge_type {
+                        RevsetGraphEdgeType::Missing => continue,
+                        RevsetGraphEdgeType::Direct => "",
+                        RevsetGraphEdgeType::Indirect => " [style=dashed]",
+                    };
+                    let target_id = index.entry_by_pos(edge.target).commit_id();
+                    writeln!(
+                        ui,
+                        "  {} -> {}{style};",
+                        dot_id(&commit_id),
+                        dot_id(&target_id)
+                    )?;
+                }
+            }
+            writeln!(ui, "}}")?;
+        }
+    }
+    Ok(())
+}
+
+/// Quotes `text` as a DOT string, with lines left-aligned.
+fn dot_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\l"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push_str("\\l\"");
+    quoted
+}
+
+fn cmd_debug_change_ids(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &DebugChangeIdsArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo();
+    let head_ids = repo.view().heads().iter().cloned().collect_vec();
+    let check_path = match &args.check {
+        None => {
+            let change_ids = change_ids_by_identity(repo.store(), &head_ids)?;
+            for (identity, change_ids) in change_ids {
+                for change_id in change_ids {
+                    writeln!(ui, "{} {}", identity.hex(), change_id.hex())?;
+                }
+            }
+            return Ok(());
+        }
+        Some(path) => path,
+    };
+    let contents = fs::read_to_string(check_path)
+        .map_err(|err| UserError(format!("Failed to read {}: {err}", check_path.display())))?;
+    let mut expected: BTreeMap<CommitIdentity, BTreeSet<ChangeId>> = BTreeMap::new();
+    for (line_number, line) in contents.lines().enumerate() {
+        let parsed = line.split_once(' ').and_then(|(identity, change_id)| {
+            let change_id = hex::decode(change_id).ok()?;
+            Some((
+                CommitIdentity::from_hex(identity)?,
+                ChangeId::new(change_id),
+            ))
+        });
+        match parsed {
+            Some((identity, change_id)) => {
+                expected.entry(identity).or_default().insert(change_id);
+            }
+            None => {
+                return Err(UserError(format!(
+                    "Invalid line {} in {}: {line}",
+                    line_number + 1,
+                    check_path.display()
+                )))
+            }
+        }
+    }
+    let drift = find_change_id_drift(repo.store(), &head_ids, &expected)?;
+    if drift.is_empty() {
+        writeln!(ui, "No change ids have changed.")?;
+        return Ok(());
+    }
+    for commit_drift in &drift {
+        writeln!(
+            ui,
+            "Commit {} has change id {}, expected {}",
+            short_commit_hash(&commit_drift.commit_id),
+            commit_drift.actual_change_id.hex(),
+            commit_drift
+                .expected_change_ids
+                .iter()
+                .map(|change_id| change_id.hex())
+                .join(" or ")
+        )?;
+    }
+    Err(UserError(
+        "Some change ids differ from the recorded ones".to_string(),
+    ))
+}
+
+fn cmd_debug_simplify_conflicts(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &DebugSimplifyConflictsArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let commits = workspace_command.resolve_revset(&args.revisions)?;
+    workspace_command.check_non_empty(&commits)?;
+    for commit in &commits {
+        workspace_command.check_rewriteable(commit)?;
+    }
+    let mut tx = workspace_command
+        .start_transaction(&format!("simplify conflicts in {} commits", commits.len()));
+    let mut_repo = tx.mut_repo();
+    let mut rewritten: HashMap<CommitId, CommitId> = HashMap::new();
+    let mut num_simplified = 0;
+    // The revset is in reverse topological order
+    for commit in commits.iter().rev() {
+        let new_tree_id = simplify_conflicts(&commit.tree())?;
+        let new_parent_ids = commit
+            .parent_ids()
+            .iter()
+            .map(|id| rewritten.get(id).unwrap_or(id).clone())
+            .collect_vec();
+        if new_tree_id != *commit.tree_id() || new_parent_ids != commit.parent_ids() {
+            if new_tree_id != *commit.tree_id() {
+                num_simplified += 1;
+            }
+            let new_commit = CommitBuilder::for_rewrite_from(ui.settings(), commit)
+                .set_parents(new_parent_ids)
+                .set_tree(new_tree_id)
+                .write_to_repo(mut_repo);
+            rewritten.insert(commit.id().clone(), new_commit.id().clone());
+        }
+    }
+    writeln!(ui, "Simplified conflicts in {num_simplified} commits")?;
+    if num_simplified == 0 {
+        return Ok(());
+    }
+    workspace_command.finish_transaction(ui, tx)
+}
+
+fn cmd_debug_snapshot_bench(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &DebugSnapshotBenchArgs,
+) -> Result<(), CommandError> {
+    let base_ignores = command.workspace_helper(ui)?.base_ignores();
+    // The snapshots aren't recorded, so they may run with
+    // `--no-commit-working-copy`
+    let mut workspace = command.load_workspace(ui)?;
+    for iteration in 1..=args.iterations {
+        let mut locked_wc = workspace.working_copy_mut().start_mutation();
+        let old_tree_id = locked_wc.old_tree_id().clone();
+        let start = Instant::now();
+        let result = locked_wc.snapshot(base_ignores.clone());
+        let total = start.elapsed();
+        let timings = locked_wc.timings().clone();
+        // Leave the working copy state as it was, so the changes (if any) are
+        // still snapshotted by the next command
+        locked_wc.discard();
+        let tree_id = result?;
+        writeln!(
+            ui,
+            "Snapshot {iteration}: {:.3?} in total ({}), {} files hashed",
+            total,
+            if tree_id == old_tree_id {
+                "unchanged"
+            } else {
+                "changed"
+            },
+            timings.hashed_files
+        )?;
+        writeln!(ui, "  stat:  {:.3?}", timings.stat)?;
+        writeln!(ui, "  hash:  {:.3?}", timings.hash)?;
+        writeln!(ui, "  write: {:.3?}", timings.write)?;
+    }
+    Ok(())
+}
+
+fn cmd_debug_checkout_bench(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &DebugCheckoutBenchArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let commit = workspace_command.resolve_single_rev(&args.revision)?;
+    let repo = workspace_command.repo();
+    let workspace_id = workspace_command.workspace_id();
+    let wc_commit_id = repo
+        .view()
+        .get_wc_commit_id(&workspace_id)
+        .ok_or_else(|| UserError("This command requires a working copy".to_string()))?;
+    let wc_commit = repo.store().get_commit(wc_commit_id)?;
+    // Check out into a scratch working copy so neither a failed checkout nor an
+    // interrupted one can leave the workspace's files out of sync with its
+    // working-copy commit.
+    let temp_dir = tempfile::Builder::new()
+        .prefix("jj-checkout-bench-")
+        .tempdir()?;
+    // Like in a real workspace, the state lives in `.jj/` inside the working
+    // copy, so checking out an empty tree doesn't remove the root directory.
+    let wc_path = temp_dir.path().to_path_buf();
+    let state_path = wc_path.join(".jj");
+    fs::create_dir(&state_path)?;
+    let mut working_copy = WorkingCopy::init(
+        repo.store().clone(),
+        wc_path.clone(),
+        state_path,
+        repo.op_id().clone(),
+        workspace_id,
+    );
+    let mut locked_wc = working_copy.start_mutation();
+    let old_operation_id = locked_wc.old_operation_id().clone();
+    // Populate the scratch working copy first; only the following checkouts are
+    // timed.
+    if let Err(err) = locked_wc.check_out(&wc_commit.tree()) {
+        locked_wc.discard();
+        return Err(checkout_error(ui, &wc_path, &wc_commit, err));
+    }
+    for commit in [&commit, &wc_commit] {
+        let start = Instant::now();
+        let stats = match locked_wc.check_out(&commit.tree()) {
+            Ok(stats) => stats,
+            Err(err) => {
+                locked_wc.discard();
+                return Err(checkout_error(ui, &wc_path, commit, err));
+            }
+        };
+        let total = start.elapsed();
+        let timings = locked_wc.timings();
+        writeln!(
+            ui,
+            "Checkout of {}: {:.3?} in total, {} files added, {} updated, {} removed",
+            short_commit_hash(commit.id()),
+            total,
+            stats.added_files,
+            stats.updated_files,
+            stats.removed_files
+        )?;
+        writeln!(ui, "  stat:  {:.3?}", timings.stat)?;
+        writeln!(ui, "  write: {:.3?}", timings.write)?;
+    }
+    locked_wc.finish(old_operation_id)?;
+    writeln!(
+        ui,
+        "Saving the working copy state: {:.3?}",
+        working_copy.timings().save
+    )?;
+    Ok(())
+}
+
+fn cmd_debug_migrate_backend(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &DebugMigrateBackendArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo().clone();
+    let backend_name = match args.to {
+        BackendKind::Local => "local",
+        BackendKind::Git => "git",
+    };
+    if repo.store().backend_name() == backend_name {
+        return Err(UserError(format!(
+            "The repo already uses the {backend_name} backend"
+        )));
+    }
+    if repo.view().wc_commit_ids().len() > 1 {
+        return Err(UserError(
+            "Repos with more than one workspace can't be migrated".to_string(),
+        ));
+    }
+    let locked_op_heads = repo.op_heads_store().lock();
+    let head_op_ids = repo.op_heads_store().get_op_heads();
+    if head_op_ids != [repo.op_id().clone()] {
+        return Err(UserError(
+            "Concurrent operation detected; run any command to resolve it and try again"
+                .to_string(),
+        ));
+    }
+
+    let repo_path = repo.repo_path().clone();
+    let store_path = repo_path.join("store");
+    let new_store_path = PendingMigration::new_store_path(&repo_path);
+    let old_store_path = PendingMigration::old_store_path(&repo_path);
+    if old_store_path.exists() {
+        return Err(UserError(format!(
+            "{} is left over from an earlier migration. Remove it once you've checked that the \
+             repo works.",
+            old_store_path.display()
+        )));
+    }
+    // Left behind by a migration that was interrupted before it took effect
+    if new_store_path.exists() {
+        fs::remove_dir_all(&new_store_path)?;
+    }
+    fs::create_dir(&new_store_path)?;
+    let new_backend: Box<dyn Backend> = match args.to {
+        BackendKind::Local => Box::new(LocalBackend::init(&new_store_path)),
+        BackendKind::Git => Box::new(GitBackend::init_internal(&new_store_path)),
+    };
+    fs::write(new_store_path.join("backend"), new_backend.name())?;
+    let migration = migrate_backend(
+        repo.op_store(),
+        &head_op_ids,
+        repo.store().backend(),
+        new_backend.as_ref(),
+    )
+    .map_err(|err| UserError(err.to_string()))?;
+    drop(new_backend);
+    // Ids are kept where both backends hash the objects the same way
+    let changed_ids = migration
+        .commit_ids
+        .iter()
+        .filter(|(old_id, new_id)| old_id != new_id)
+        .map(|(old_id, new_id)| format!("{} {}\n", old_id.hex(), new_id.hex()))
+        .sorted()
+        .collect_vec();
+    if !changed_ids.is_empty() {
+        fs::write(
+            new_store_path.join("migrated_commit_ids"),
+            changed_ids.concat(),
+        )?;
+    }
+
+    // Everything is written, so commit the migration. Loading the workspace
+    // then swaps the new store and op heads in and updates the working copy.
+    // If that's interrupted, the next command finishes it.
+    let new_head_op_ids = head_op_ids
+        .iter()
+        .map(|id| migration.operation_ids[id].clone())
+        .collect_vec();
+    PendingMigration::commit(&repo_path, &head_op_ids, &new_head_op_ids)?;
+    drop(locked_op_heads);
+    drop(repo);
+    drop(workspace_command);
+    command.load_workspace(ui)?;
+
+    writeln!(
+        ui,
+        "Migrated {} commits to the {backend_name} backend.",
+        // Not counting the root commit
+        migration.commit_ids.len() - 1
+    )?;
+    if !changed_ids.is_empty() {
+        let cwd = ui.cwd().canonicalize()?;
+        writeln!(
+            ui,
+            "The old and new ids of the commits whose ids changed are listed in {}.",
+            ui::relative_path(&cwd, &store_path.join("migrated_commit_ids")).display()
+        )?;
+    }
+    Ok(())
+}
+
+fn cmd_debug(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    subcommand: &DebugCommands,
+) -> Result<(), CommandError> {
+    match subcommand {
+        DebugCommands::Completion(completion_matches) => {
+            let mut app = command.app().clone();
+            let mut buf = vec![];
+            let shell = if completion_matches.zsh {
+                clap_complete::Shell::Zsh
+            } else if completion_matches.fish {
+                clap_complete::Shell::Fish
+            } else {
+                clap_complete::Shell::Bash
+            };
+            clap_complete::generate(shell, &mut app, "jj", &mut buf);
+            ui.stdout_formatter().write_all(&buf)?;
+        }
+        DebugCommands::Mangen(_mangen_matches) => {
+            let mut buf = vec![];
+            let man = clap_mangen::Man::new(command.app().clone());
+            man.render(&mut buf)?;
+            ui.stdout_formatter().write_all(&buf)?;
+        }
+        DebugCommands::ResolveRev(resolve_matches) => {
+            let workspace_command = command.workspace_helper(ui)?;
+            let commit = workspace_command.resolve_single_rev(&resolve_matches.revision)?;
+            writeln!(ui, "{}", commit.id().hex())?;
+        }
+        DebugCommands::WorkingCopy(_wc_matches) => {
+            let workspace_command = command.workspace_helper(ui)?;
+            let wc = workspace_command.working_copy();
+            writeln!(ui, "Current operation: {:?}", wc.operation_id())?;
+            writeln!(ui, "Current tree: {:?}", wc.current_tree_id())?;
+            for (file, state) in wc.file_states() {
+                writeln!(
+                    ui,
+                    "{:?} {:13?} {:10?} {:?}",
+                    state.file_type, state.size, state.mtime.0, file
+                )?;
+            }
+        }
+        DebugCommands::RepairWorkingCopy(_repair_matches) => {
+            let mut workspace = command.load_workspace(ui)?;
+            let repair = workspace.working_copy_mut().repair();
+            match &repair.finish_recovery {
+                Some(FinishRecovery::RolledForward(operation_id)) => {
+                    writeln!(
+                        ui,
+                        "Completed interrupted working copy update to operation {}",
+                        short_operation_hash(operation_id)
+                    )?;
+                }
+                Some(FinishRecovery::RolledBack) => {
+                    writeln!(ui, "Rolled back interrupted working copy update")?;
+                }
+                None => {}
+            }
+            if repair.tree_state_discarded {
+                let workspace_id = workspace.workspace_id();
+                let operation_id = workspace.working_copy().operation_id();
+                let repo_loader = workspace.repo_loader();
+                let operation = Operation::new(
+                    repo_loader.op_store().clone(),
+                    operation_id.clone(),
+                    repo_loader
+                        .op_store()
+                        .read_operation(&operation_id)
+                        .map_err(|err| {
+                            CommandError::InternalError(format!("Failed to read operation: {err}"))
+                        })?,
+                );
+                let repo = repo_loader.load_at(&operation);
+                let wc_commit_id =
+                    repo.view().get_wc_commit_id(&workspace_id).ok_or_else(|| {
+                        CommandError::UserError("Nothing checked out in this workspace".to_string())
+                    })?;
+                let wc_commit = repo.store().get_commit(wc_commit_id)?;
+                let mut locked_wc = workspace.working_copy_mut().start_mutation();
+                locked_wc.reset(&wc_commit.tree())?;
+                locked_wc.finish(operation_id)?;
+                ui.write("Unreadable tree state was moved aside and rebuilt from ")?;
+                ui.write_commit_summary(repo.as_repo_ref(), &workspace_id, &wc_commit)?;
+                ui.write("\n")?;
+            }
+            if repair.finish_recovery.is_none() && !repair.tree_state_discarded {
+                writeln!(ui, "The working copy state is consistent")?;
+            }
+        }
+        DebugCommands::BreakLock(break_lock_args) => {
+            let workspace = command.load_workspace(ui)?;
+            let lock_paths = [
+                workspace
+                    .working_copy()
+                    .state_path()
+                    .join("working_copy.lock"),
+                workspace.repo_path().join("op_heads").join("lock"),
+                workspace
+                    .repo_path()
+                    .join("store")
+                    .join("extra")
+                    .join("lock"),
+            ];
+            let mut num_locks = 0;
+            for lock_path in lock_paths.iter().filter(|path| path.exists()) {
+                num_locks += 1;
+                let holder = LockHolder::read(lock_path);
+                let holder_description = match &holder {
+                    Some(holder) => format!("process {} on {}", holder.pid, holder.hostname),
+                    None => "an unknown process".to_string(),
+                };
+                let is_dead = holder.as_ref().map_or(false, |holder| holder.is_dead());
+                if is_dead || break_lock_args.force {
+                    lock::break_lock(lock_path)?;
+                    writeln!(
+                        ui,
+                        "Removed lock {} held by {}",
+                        lock_path.display(),
+                        holder_description
+                    )?;
+                } else {
+                    ui.write_warn(format!(
+                        "Lock {} is held by {}, which may still be running. Use --force to \
+                         remove it anyway.\n",
+                        lock_path.display(),
+                        holder_description
+                    ))?;
+                }
+            }
+            if num_locks == 0 {
+                writeln!(ui, "No locks are held")?;
+            }
+        }
+        DebugCommands::Template(template_matches) => {
+            let parse = TemplateParser::parse(
+                crate::template_parser::Rule::template,
+                &template_matches.template,
+            );
+            writeln!(ui, "{:?}", parse)?;
+        }
+        DebugCommands::Graph(graph_args) => {
+            cmd_debug_graph(ui, command, graph_args)?;
+        }
+        DebugCommands::Index(DebugIndexArgs {
+            command: Some(DebugIndexCommands::Verify(_verify_args)),
+        }) => {
+            let workspace_command = command.workspace_helper(ui)?;
+            let repo = workspace_command.repo();
+            let index = repo.index();
+            index.verify().map_err(|err| {
+                CommandError::UserError(format!(
+                    "{err} (use `jj debug reindex` to rebuild the index)"
+                ))
+            })?;
+            for head_id in repo.view().heads() {
+                if !index.has_id(head_id) {
+                    return Err(CommandError::UserError(format!(
+                        "Commit {} is not indexed (use `jj debug reindex` to rebuild the \
+                         index)",
+                        head_id.hex()
+                    )));
+                }
+            }
+            writeln!(ui, "The index is OK ({} commits).", index.num_commits())?;
+        }
+        DebugCommands::Index(DebugIndexArgs { command: None }) => {
+            let workspace_command = command.workspace_helper(ui)?;
             let stats = workspace_command.repo().index().stats();
             writeln!(ui, "Number of commits: {}", stats.num_commits)?;
             writeln!(ui, "Number of merges: {}", stats.num_merges)?;
@@ -3570,22 +7377,554 @@ fn cmd_debug(
                 writeln!(ui, "    Name: {}", level.name.as_ref().unwrap())?;
             }
         }
-        DebugCommands::ReIndex(_reindex_matches) => {
-            let mut workspace_command = command.workspace_helper(ui)?;
-            let mut_repo = Arc::get_mut(workspace_command.repo_mut()).unwrap();
-            let index = mut_repo.reindex();
-            writeln!(ui, "Finished indexing {:?} commits.", index.num_commits())?;
+        DebugCommands::ReIndex(reindex_args) => {
+            let mut workspace_command = command.workspace_helper(ui)?;
+            let mut_repo = Arc::get_mut(workspace_command.repo_mut()).unwrap();
+            let index = if reindex_args.incremental {
+                mut_repo.reindex_incremental()
+            } else {
+                mut_repo.reindex()
+            };
+            writeln!(ui, "Finished indexing {:?} commits.", index.num_commits())?;
+        }
+        DebugCommands::Operation(operation_args) => {
+            let workspace_command = command.workspace_helper(ui)?;
+            let op = workspace_command.resolve_single_op(&operation_args.operation)?;
+            writeln!(ui, "{:#?}", op.store_operation())?;
+            writeln!(ui, "{:#?}", op.view().store_view())?;
+        }
+        DebugCommands::Pack(_pack_args) => {
+            let workspace_command = command.workspace_helper(ui)?;
+            let num_packed = workspace_command.repo().store().pack_loose_objects(1)?;
+            if num_packed == 0 {
+                writeln!(ui, "Nothing to pack.")?;
+            } else {
+                writeln!(ui, "Packed {num_packed} objects.")?;
+            }
+        }
+        DebugCommands::MigrateBackend(migrate_args) => {
+            cmd_debug_migrate_backend(ui, command, migrate_args)?;
+        }
+        DebugCommands::ChangeIds(change_ids_args) => {
+            cmd_debug_change_ids(ui, command, change_ids_args)?;
+        }
+        DebugCommands::SimplifyConflicts(simplify_conflicts_args) => {
+            cmd_debug_simplify_conflicts(ui, command, simplify_conflicts_args)?;
+        }
+        DebugCommands::SnapshotBench(snapshot_bench_args) => {
+            cmd_debug_snapshot_bench(ui, command, snapshot_bench_args)?;
+        }
+        DebugCommands::CheckoutBench(checkout_bench_args) => {
+            cmd_debug_checkout_bench(ui, command, checkout_bench_args)?;
+        }
+        DebugCommands::WorkspaceCache(workspace_cache_args) => {
+            let workspace_command = command.workspace_helper(ui)?;
+            let cache = workspace_cache(ui, &workspace_command)?;
+            if workspace_cache_args.clear {
+                let num_removed = cache.clear()?;
+                writeln!(ui, "Removed {num_removed} working copies.")?;
+            }
+            for slot in cache.slots()? {
+                write!(
+                    ui,
+                    "{}: tree {}, last used {}",
+                    slot.name,
+                    slot.tree_id.hex(),
+                    templater::format_timestamp(&Timestamp {
+                        timestamp: slot.last_used,
+                        tz_offset: 0,
+                    })
+                )?;
+                if slot.in_use {
+                    write!(ui, " (in use)")?;
+                }
+                writeln!(ui)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The pool of hidden working copies in the workspace's `.jj/` directory. Its
+/// capacity is `run.workspace-cache-size` (4 by default).
+fn workspace_cache(
+    ui: &Ui,
+    workspace_command: &WorkspaceCommandHelper,
+) -> Result<WorkspaceCache, CommandError> {
+    let capacity = match ui.settings().config().get_int("run.workspace-cache-size") {
+        Ok(capacity) if capacity > 0 => capacity as usize,
+        Ok(capacity) => {
+            return Err(CommandError::ConfigError(format!(
+                "run.workspace-cache-size must be positive, not {capacity}"
+            )))
+        }
+        Err(config::ConfigError::NotFound(_)) => 4,
+        Err(err) => return Err(err.into()),
+    };
+    let jj_dir = workspace_command.workspace_root().join(".jj");
+    // Older versions used a single working copy in `.jj/run/`
+    if jj_dir.join("run").is_dir() {
+        fs::remove_dir_all(jj_dir.join("run")).ok();
+    }
+    Ok(WorkspaceCache::new(
+        workspace_command.repo().store().clone(),
+        jj_dir.join("workspace-cache"),
+        capacity,
+    ))
+}
+
+fn cmd_run(ui: &mut Ui, command: &CommandHelper, args: &RunArgs) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let commits = workspace_command.resolve_revset(&args.revisions)?;
+    workspace_command.check_non_empty(&commits)?;
+    let cache = workspace_cache(ui, &workspace_command)?;
+    let base_ignores = workspace_command.base_ignores();
+    let mut num_failed = 0;
+    // The revset is in reverse topological order
+    for commit in commits.iter().rev() {
+        let workspace = cache.check_out(&commit.tree(), base_ignores.clone())?;
+        let status = shell_command(&args.command)
+            .current_dir(workspace.working_copy_path())
+            .env("JJ_COMMIT_ID", commit.id().hex())
+            .env("JJ_CHANGE_ID", commit.change_id().hex())
+            .status()
+            .map_err(|err| {
+                CommandError::UserError(format!("Failed to run '{}': {err}", args.command))
+            })?;
+        let result = if status.success() {
+            "passed"
+        } else {
+            num_failed += 1;
+            "failed"
+        };
+        writeln!(ui, "{}: {}", short_commit_description(commit), result)?;
+    }
+    if num_failed > 0 {
+        return Err(CommandError::UserError(format!(
+            "The command failed on {} of {} revisions",
+            num_failed,
+            commits.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Formatter loaded from the settings.
+#[derive(Clone, Debug, serde::Deserialize)]
+struct FixTool {
+    /// Program and arguments to execute. `$path` is replaced by the path of the
+    /// file being fixed.
+    command: Vec<String>,
+    /// Glob patterns of the files to run the tool on. Patterns without a `/`
+    /// are matched against the file name only.
+    patterns: Vec<String>,
+    /// `patterns`, compiled once when the tools are loaded.
+    #[serde(skip)]
+    file_patterns: Vec<FilePattern>,
+}
+
+impl FixTool {
+    fn matches(&self, path: &RepoPath) -> bool {
+        self.file_patterns
+            .iter()
+            .any(|pattern| pattern.matches(path))
+    }
+}
+
+/// Loads the tools from `[fix.tools.<name>]`, ordered by name.
+fn get_fix_tools(settings: &UserSettings) -> Result<Vec<(String, FixTool)>, CommandError> {
+    const TABLE_KEY: &str = "fix.tools";
+    let tools_table = match settings.config().get_table(TABLE_KEY) {
+        Ok(table) => table,
+        Err(config::ConfigError::NotFound(_)) => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+    let mut tools = vec![];
+    for (name, value) in tools_table.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+        let mut tool: FixTool = value
+            .try_deserialize()
+            .map_err(|err| CommandError::ConfigError(format!("{TABLE_KEY}.{name}: {err}")))?;
+        if tool.command.is_empty() {
+            return Err(CommandError::ConfigError(format!(
+                "{TABLE_KEY}.{name}: `command` must not be empty"
+            )));
+        }
+        tool.file_patterns = tool
+            .patterns
+            .iter()
+            .map(|glob| FilePattern::new(glob))
+            .collect();
+        tools.push((name, tool));
+    }
+    Ok(tools)
+}
+
+/// Runs `tool` on `contents`. Returns `None` if the tool failed.
+fn run_fix_tool(
+    ui: &mut Ui,
+    workspace_root: &Path,
+    name: &str,
+    tool: &FixTool,
+    path: &RepoPath,
+    contents: &[u8],
+) -> Result<Option<Vec<u8>>, CommandError> {
+    let path_str = path.to_internal_file_string();
+    let args = tool
+        .command
+        .iter()
+        .map(|arg| arg.replace("$path", &path_str))
+        .collect_vec();
+    let mut child = std::process::Command::new(&args[0])
+        .args(&args[1..])
+        .current_dir(workspace_root)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            CommandError::ConfigError(format!("Failed to run fix tool '{name}': {err}"))
+        })?;
+    let mut stdin = child.stdin.take().unwrap();
+    let input = contents.to_vec();
+    // Write on a separate thread so a tool that writes its output before having
+    // read all of its input doesn't deadlock with us.
+    let writer = std::thread::spawn(move || stdin.write_all(&input).ok());
+    let output = child.wait_with_output()?;
+    writer.join().ok();
+    if output.status.success() {
+        Ok(Some(output.stdout))
+    } else {
+        ui.write_warn(format!("Fix tool '{name}' failed on {path_str}\n"))?;
+        Ok(None)
+    }
+}
+
+fn cmd_fix(ui: &mut Ui, command: &CommandHelper, args: &FixArgs) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let commits = workspace_command.resolve_revset(&args.revisions)?;
+    workspace_command.check_non_empty(&commits)?;
+    for commit in &commits {
+        workspace_command.check_rewriteable(commit)?;
+    }
+    let tools = get_fix_tools(ui.settings())?;
+    if tools.is_empty() {
+        return Err(CommandError::ConfigError(
+            "No tools configured in fix.tools".to_string(),
+        ));
+    }
+    let workspace_root = workspace_command.workspace_root().clone();
+    let mut tx = workspace_command.start_transaction(&format!("fix {} commits", commits.len()));
+    let mut_repo = tx.mut_repo();
+    let store = mut_repo.store().clone();
+    // The paths we ran the tools on in each commit. Descendants need to fix them
+    // too even if they didn't change them, or they would undo the fixes.
+    let mut fixed_paths: HashMap<CommitId, HashSet<RepoPath>> = HashMap::new();
+    let mut rewritten: HashMap<CommitId, CommitId> = HashMap::new();
+    let mut fixed_files: HashMap<(RepoPath, FileId), FileId> = HashMap::new();
+    let mut num_fixed = 0;
+    // The revset is in reverse topological order
+    for commit in commits.iter().rev() {
+        let parent_tree = merge_commit_trees(mut_repo.as_repo_ref(), &commit.parents());
+        let tree = commit.tree();
+        let mut paths: HashSet<RepoPath> = parent_tree
+            .diff(&tree, &EverythingMatcher)
+            .filter(|(_, diff)| !matches!(diff, tree::Diff::Removed(_)))
+            .map(|(path, _)| path)
+            .collect();
+        for parent_id in commit.parent_ids() {
+            if let Some(parent_paths) = fixed_paths.get(parent_id) {
+                paths.extend(parent_paths.iter().cloned());
+            }
+        }
+        let mut tree_builder = store.tree_builder(commit.tree_id().clone());
+        for path in paths.iter().sorted() {
+            let (id, executable) = match tree.path_value(path) {
+                Some(TreeValue::Normal { id, executable }) => (id, executable),
+                _ => continue,
+            };
+            let key = (path.clone(), id.clone());
+            let new_id = match fixed_files.get(&key) {
+                Some(new_id) => new_id.clone(),
+                None => {
+                    let mut contents = vec![];
+                    store.read_file(path, &id)?.read_to_end(&mut contents)?;
+                    let mut new_contents = contents.clone();
+                    for (name, tool) in &tools {
+                        if !tool.matches(path) {
+                            continue;
+                        }
+                        if let Some(output) =
+                            run_fix_tool(ui, &workspace_root, name, tool, path, &new_contents)?
+                        {
+                            new_contents = output;
+                        }
+                    }
+                    let new_id = if new_contents == contents {
+                        id.clone()
+                    } else {
+                        store.write_file(path, &mut new_contents.as_slice())?
+                    };
+                    fixed_files.insert(key, new_id.clone());
+                    new_id
+                }
+            };
+            if new_id != id {
+                tree_builder.set(
+                    path.clone(),
+                    TreeValue::Normal {
+                        id: new_id,
+                        executable,
+                    },
+                );
+            }
         }
-        DebugCommands::Operation(operation_args) => {
-            let workspace_command = command.workspace_helper(ui)?;
-            let op = workspace_command.resolve_single_op(&operation_args.operation)?;
-            writeln!(ui, "{:#?}", op.store_operation())?;
-            writeln!(ui, "{:#?}", op.view().store_view())?;
+        let new_tree_id = tree_builder.write_tree();
+        let new_parent_ids = commit
+            .parent_ids()
+            .iter()
+            .map(|id| rewritten.get(id).unwrap_or(id).clone())
+            .collect_vec();
+        if new_tree_id != *commit.tree_id() || new_parent_ids != commit.parent_ids() {
+            if new_tree_id != *commit.tree_id() {
+                num_fixed += 1;
+            }
+            let new_commit = CommitBuilder::for_rewrite_from(ui.settings(), commit)
+                .set_parents(new_parent_ids)
+                .set_tree(new_tree_id)
+                .write_to_repo(mut_repo);
+            rewritten.insert(commit.id().clone(), new_commit.id().clone());
+        }
+        fixed_paths.insert(commit.id().clone(), paths);
+    }
+    writeln!(ui, "Fixed {} commits", num_fixed)?;
+    if num_fixed == 0 {
+        return Ok(());
+    }
+    workspace_command.finish_transaction(ui, tx)
+}
+
+fn cmd_rewrite(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &RewriteArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let commits = workspace_command.resolve_revset(&args.revisions)?;
+    workspace_command.check_non_empty(&commits)?;
+    for commit in &commits {
+        workspace_command.check_rewriteable(commit)?;
+    }
+    let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
+    let (pattern, replacement) = (&args.replace[0], &args.replace[1]);
+    let regex = regex::bytes::Regex::new(pattern)
+        .map_err(|err| UserError(format!("Invalid regex \"{pattern}\": {err}")))?;
+    let mut replacer = TextReplacer::new(regex, replacement);
+    let mut tx = workspace_command.start_transaction(&format!("rewrite {} commits", commits.len()));
+    let mut_repo = tx.mut_repo();
+    let mut rewritten: HashMap<CommitId, CommitId> = HashMap::new();
+    let mut num_rewritten = 0;
+    // The revset is in reverse topological order
+    for commit in commits.iter().rev() {
+        let new_tree_id = replacer.replace_in_tree(&commit.tree(), matcher.as_ref())?;
+        let new_parent_ids = commit
+            .parent_ids()
+            .iter()
+            .map(|id| rewritten.get(id).unwrap_or(id).clone())
+            .collect_vec();
+        if new_tree_id != *commit.tree_id() || new_parent_ids != commit.parent_ids() {
+            if new_tree_id != *commit.tree_id() {
+                num_rewritten += 1;
+            }
+            let new_commit = CommitBuilder::for_rewrite_from(ui.settings(), commit)
+                .set_parents(new_parent_ids)
+                .set_tree(new_tree_id)
+                .write_to_repo(mut_repo);
+            rewritten.insert(commit.id().clone(), new_commit.id().clone());
+        }
+    }
+    writeln!(ui, "Rewrote {} commits", num_rewritten)?;
+    if num_rewritten == 0 {
+        return Ok(());
+    }
+    workspace_command.finish_transaction(ui, tx)
+}
+
+fn cmd_stats(ui: &mut Ui, command: &CommandHelper, _args: &StatsArgs) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo();
+    writeln!(ui, "Commits: {}", repo.index().num_commits())?;
+    writeln!(ui, "Backend: {}", repo.store().backend_name())?;
+    if let Some(stats) = repo.store().chunk_stats()? {
+        writeln!(
+            ui,
+            "Chunked files: {} ({})",
+            stats.chunked_files,
+            format_bytes(stats.total_bytes as f64)
+        )?;
+        writeln!(
+            ui,
+            "Distinct chunks: {} ({}, {} stored)",
+            stats.unique_chunks,
+            format_bytes(stats.unique_bytes as f64),
+            format_bytes(stats.stored_bytes as f64)
+        )?;
+        if stats.total_bytes > 0 {
+            let saved = stats.total_bytes - stats.unique_bytes;
+            writeln!(
+                ui,
+                "Saved by deduplication: {} ({}%)",
+                format_bytes(saved as f64),
+                saved * 100 / stats.total_bytes
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn cmd_verify(ui: &mut Ui, command: &CommandHelper, args: &VerifyArgs) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo();
+    let git_repo = get_git_repo(repo.store())?;
+    let commits = workspace_command.resolve_revset(&args.revisions)?;
+    let mut results = vec![];
+    for commit in &commits {
+        if commit.id() == repo.store().root_commit_id() {
+            continue;
+        }
+        let status = git::verify_commit_signature(&git_repo, commit.id())
+            .map_err(|err| UserError(err.to_string()))?;
+        results.push((commit, status));
+    }
+    let num_good = results
+        .iter()
+        .filter(|(_, status)| *status == CommitSignatureStatus::Good)
+        .count();
+    let num_unsigned = results
+        .iter()
+        .filter(|(_, status)| *status == CommitSignatureStatus::Unsigned)
+        .count();
+    let num_bad = results.len() - num_good - num_unsigned;
+    if args.json {
+        let commits = results
+            .iter()
+            .map(|(commit, status)| {
+                let mut result = json!({
+                    "commit_id": commit.id().hex(),
+                    "change_id": commit.change_id().hex(),
+                });
+                match status {
+                    CommitSignatureStatus::Unsigned => result["status"] = json!("unsigned"),
+                    CommitSignatureStatus::Good => result["status"] = json!("good"),
+                    CommitSignatureStatus::Bad(reason) => {
+                        result["status"] = json!("bad");
+                        result["reason"] = json!(reason);
+                    }
+                }
+                result
+            })
+            .collect_vec();
+        let summary = json!({
+            "commits": commits,
+            "good": num_good,
+            "unsigned": num_unsigned,
+            "bad": num_bad,
+        });
+        writeln!(ui, "{}", serde_json::to_string_pretty(&summary).unwrap())?;
+    } else {
+        for (commit, status) in &results {
+            let (label, text) = match status {
+                CommitSignatureStatus::Unsigned => ("unsigned", "Unsigned"),
+                CommitSignatureStatus::Good => ("good_signature", "Good signature"),
+                CommitSignatureStatus::Bad(_) => ("bad_signature", "Bad signature"),
+            };
+            ui.stdout_formatter().add_label(label.to_string())?;
+            write!(ui, "{text}")?;
+            ui.stdout_formatter().remove_label()?;
+            write!(ui, ": ")?;
+            ui.write_commit_summary(
+                repo.as_repo_ref(),
+                &workspace_command.workspace_id(),
+                commit,
+            )?;
+            writeln!(ui)?;
+            if let CommitSignatureStatus::Bad(reason) = status {
+                writeln!(ui, "  {reason}")?;
+            }
         }
     }
+    if num_good < results.len() {
+        return Err(UserError(format!(
+            "{num_unsigned} unsigned and {num_bad} badly signed commits (out of {})",
+            results.len()
+        )));
+    }
     Ok(())
 }
 
+fn shell_command(command: &str) -> std::process::Command {
+    if cfg!(windows) {
+        let mut cmd = std::process::Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = std::process::Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+fn cmd_api(ui: &mut Ui, command: &CommandHelper, args: &ApiArgs) -> Result<(), CommandError> {
+    let workspace = command.load_workspace(ui)?;
+    let socket_path = args
+        .socket
+        .clone()
+        .unwrap_or_else(|| workspace.workspace_root().join(".jj").join("api.sock"));
+    let mut server = ApiServer::new(workspace).map_err(CommandError::InternalError)?;
+    if args.stdio {
+        server.serve(io::stdin(), io::stdout())?;
+        return Ok(());
+    }
+    serve_api_on_socket(ui, &mut server, &socket_path)
+}
+
+#[cfg(unix)]
+fn serve_api_on_socket(
+    ui: &mut Ui,
+    server: &mut ApiServer,
+    socket_path: &Path,
+) -> Result<(), CommandError> {
+    use std::os::unix::net::UnixListener;
+
+    let listener = UnixListener::bind(socket_path).map_err(|err| {
+        UserError(format!(
+            "Failed to listen on {}: {err}",
+            socket_path.display()
+        ))
+    })?;
+    writeln!(ui, "Listening on {}", socket_path.display())?;
+    let result = (|| -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            if server.serve(&stream, &stream)? {
+                break;
+            }
+        }
+        Ok(())
+    })();
+    fs::remove_file(socket_path).ok();
+    Ok(result?)
+}
+
+#[cfg(not(unix))]
+fn serve_api_on_socket(
+    _ui: &mut Ui,
+    _server: &mut ApiServer,
+    _socket_path: &Path,
+) -> Result<(), CommandError> {
+    Err(UserError(
+        "Sockets are not supported on this platform; use --stdio".to_string(),
+    ))
+}
+
 fn run_bench<R, O>(ui: &mut Ui, id: &str, mut routine: R) -> io::Result<()>
 where
     R: (FnMut() -> O) + Copy,
@@ -3680,72 +8019,135 @@ fn cmd_bench(
     Ok(())
 }
 
-fn format_timestamp(timestamp: &Timestamp) -> String {
-    let utc = Utc
-        .timestamp(
-            timestamp.timestamp.0.div_euclid(1000),
-            (timestamp.timestamp.0.rem_euclid(1000)) as u32 * 1000000,
-        )
-        .with_timezone(&FixedOffset::east(timestamp.tz_offset * 60));
-    utc.format("%Y-%m-%d %H:%M:%S.%3f %:z").to_string()
+struct OpTemplate;
+
+impl Template<Operation> for OpTemplate {
+    fn format(&self, op: &Operation, formatter: &mut dyn Formatter) -> io::Result<()> {
+        // TODO: Make this templated
+        formatter.add_label("id".to_string())?;
+        formatter.write_str(&op.id().hex()[0..12])?;
+        formatter.remove_label()?;
+        formatter.write_str(" ")?;
+        let metadata = &op.store_operation().metadata;
+        formatter.add_label("user".to_string())?;
+        formatter.write_str(&format!("{}@{}", metadata.username, metadata.hostname))?;
+        formatter.remove_label()?;
+        formatter.write_str(" ")?;
+        formatter.add_label("time".to_string())?;
+        formatter.write_str(&format!(
+            "{} - {}",
+            templater::format_timestamp(&metadata.start_time),
+            templater::format_timestamp(&metadata.end_time)
+        ))?;
+        formatter.remove_label()?;
+        formatter.write_str("\n")?;
+        formatter.add_label("description".to_string())?;
+        formatter.write_str(&metadata.description)?;
+        formatter.remove_label()?;
+        for (key, value) in metadata.tags.iter().sorted() {
+            formatter.add_label("tags".to_string())?;
+            formatter.write_str(&format!("\n{}: {}", key, value))?;
+            formatter.remove_label()?;
+        }
+        Ok(())
+    }
+}
+
+fn op_template(
+    ui: &Ui,
+    head_op_id: &OperationId,
+    template: &Option<String>,
+) -> Box<dyn Template<Operation>> {
+    match template {
+        Some(template_string) => crate::template_parser::parse_operation_template(
+            head_op_id,
+            ui.settings().current_timestamp(),
+            template_string,
+        ),
+        None => Box::new(OpTemplate),
+    }
+}
+
+/// Returns the heads of the operation log without merging them, sorted by end
+/// time (most recent last).
+fn unmerged_op_heads(ui: &Ui, command: &CommandHelper) -> Result<Vec<Operation>, CommandError> {
+    let workspace = command.load_workspace(ui)?;
+    let repo_loader = workspace.repo_loader();
+    let op_heads = repo_loader
+        .op_heads_store()
+        .get_heads_unlocked(repo_loader.op_store())?;
+    Ok(op_heads)
 }
 
 fn cmd_op_log(
     ui: &mut Ui,
     command: &CommandHelper,
-    _args: &OperationLogArgs,
+    args: &OperationLogArgs,
 ) -> Result<(), CommandError> {
-    let workspace_command = command.workspace_helper(ui)?;
-    let repo = workspace_command.repo();
-    let head_op = repo.operation().clone();
-    let head_op_id = head_op.id().clone();
+    // Don't merge divergent operations (as loading the workspace normally would),
+    // so they can be inspected before merging them.
+    let mut head_ops = if command.global_args().at_operation == "@" {
+        unmerged_op_heads(ui, command)?
+    } else {
+        vec![]
+    };
+    if head_ops.len() <= 1 {
+        let workspace_command = command.workspace_helper(ui)?;
+        head_ops = vec![workspace_command.repo().operation().clone()];
+    } else {
+        ui.write_hint(format!(
+            "The operation log has {} heads because of concurrent operations. Run `jj op \
+             merge` to merge them.\n",
+            head_ops.len()
+        ))?;
+    }
+    // The `current_operation` keyword refers to the most recent head
+    let head_op_id = head_ops.last().unwrap().id().clone();
+    let head_op_ids: HashSet<_> = head_ops.iter().map(|op| op.id().clone()).collect();
     let mut formatter = ui.stdout_formatter();
     let mut formatter = formatter.as_mut();
-    struct OpTemplate;
-    impl Template<Operation> for OpTemplate {
-        fn format(&self, op: &Operation, formatter: &mut dyn Formatter) -> io::Result<()> {
-            // TODO: Make this templated
-            formatter.add_label("id".to_string())?;
-            formatter.write_str(&op.id().hex()[0..12])?;
-            formatter.remove_label()?;
-            formatter.write_str(" ")?;
-            let metadata = &op.store_operation().metadata;
-            formatter.add_label("user".to_string())?;
-            formatter.write_str(&format!("{}@{}", metadata.username, metadata.hostname))?;
-            formatter.remove_label()?;
-            formatter.write_str(" ")?;
-            formatter.add_label("time".to_string())?;
-            formatter.write_str(&format!(
-                "{} - {}",
-                format_timestamp(&metadata.start_time),
-                format_timestamp(&metadata.end_time)
-            ))?;
-            formatter.remove_label()?;
-            formatter.write_str("\n")?;
-            formatter.add_label("description".to_string())?;
-            formatter.write_str(&metadata.description)?;
-            formatter.remove_label()?;
-            for (key, value) in &metadata.tags {
-                formatter.add_label("tags".to_string())?;
-                formatter.write_str(&format!("\n{}: {}", key, value))?;
-                formatter.remove_label()?;
-            }
-            Ok(())
-        }
-    }
-    let template = OpTemplate;
+    let template = op_template(ui, &head_op_id, &args.template);
+
+    let parse_date = |date: &Option<String>| -> Result<Option<Timestamp>, CommandError> {
+        date.as_ref()
+            .map(|date| {
+                templater::parse_timestamp(date, &Timestamp::now())
+                    .ok_or_else(|| UserError(format!("Invalid date: \"{date}\"")))
+            })
+            .transpose()
+    };
+    let after = parse_date(&args.after)?;
+    let before = parse_date(&args.before)?;
+    let is_in_range = |op: &Operation| {
+        let end_time = &op.store_operation().metadata.end_time.timestamp;
+        after
+            .as_ref()
+            .map_or(true, |after| end_time >= &after.timestamp)
+            && before
+                .as_ref()
+                .map_or(true, |before| end_time <= &before.timestamp)
+    };
 
     let mut graph = AsciiGraphDrawer::new(&mut formatter);
+    // Start from the most recent head so it's drawn first
+    head_ops.reverse();
     for op in topo_order_reverse(
-        vec![head_op],
+        head_ops,
         Box::new(|op: &Operation| op.id().clone()),
         Box::new(|op: &Operation| op.parents()),
     ) {
+        if !is_in_range(&op) {
+            continue;
+        }
         let mut edges = vec![];
         for parent in op.parents() {
-            edges.push(Edge::direct(parent.id().clone()));
+            if is_in_range(&parent) {
+                edges.push(Edge::direct(parent.id().clone()));
+            } else {
+                edges.push(Edge::missing());
+            }
         }
-        let is_head_op = op.id() == &head_op_id;
+        let is_head_op = head_op_ids.contains(op.id());
         let mut buffer = vec![];
         {
             let writer = Box::new(&mut buffer);
@@ -3770,6 +8172,59 @@ fn cmd_op_log(
     Ok(())
 }
 
+fn cmd_op_heads(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &OperationHeadsArgs,
+) -> Result<(), CommandError> {
+    let mut head_ops = unmerged_op_heads(ui, command)?;
+    head_ops.reverse();
+    let template = op_template(ui, head_ops[0].id(), &args.template);
+    let mut formatter = ui.stdout_formatter();
+    let formatter = formatter.as_mut();
+    for op in &head_ops {
+        formatter.add_label("op-log".to_string())?;
+        formatter.add_label("head".to_string())?;
+        template.format(op, formatter)?;
+        formatter.remove_label()?;
+        formatter.remove_label()?;
+        formatter.write_str("\n")?;
+    }
+    Ok(())
+}
+
+fn cmd_op_merge(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    _args: &OperationMergeArgs,
+) -> Result<(), CommandError> {
+    let workspace = command.load_workspace(ui)?;
+    let repo_loader = workspace.repo_loader();
+    let op_heads = repo_loader
+        .op_heads_store()
+        .get_heads(repo_loader.op_store())?;
+    match op_heads {
+        OpHeads::Single(_) => {
+            writeln!(ui, "Nothing changed: the operation log has a single head.")?;
+        }
+        OpHeads::Unresolved {
+            locked_op_heads,
+            op_heads,
+        } => {
+            let num_heads = op_heads.len();
+            let workspace_command =
+                command.merge_op_heads(ui, workspace, locked_op_heads, op_heads)?;
+            writeln!(
+                ui,
+                "Merged {} operations into operation {}",
+                num_heads,
+                short_operation_hash(workspace_command.repo().op_id())
+            )?;
+        }
+    }
+    Ok(())
+}
+
 fn cmd_op_undo(
     ui: &mut Ui,
     command: &CommandHelper,
@@ -3789,13 +8244,43 @@ fn cmd_op_undo(
         ));
     }
 
+    // Find the first operation committed by the same command as the bad one
+    let mut first_op = bad_op.clone();
+    if !args.step {
+        let group_of =
+            |op: &Operation, tag: &str| op.store_operation().metadata.tags.get(tag).cloned();
+        if let Some(group) = group_of(&bad_op, COMMAND_GROUP_TAG) {
+            loop {
+                let parent_ops = first_op.parents();
+                if parent_ops.len() != 1 {
+                    break;
+                }
+                let parent_op = &parent_ops[0];
+                let in_group = group_of(parent_op, COMMAND_GROUP_TAG).as_ref() == Some(&group)
+                    || (args.include_snapshot
+                        && group_of(parent_op, SNAPSHOT_GROUP_TAG).as_ref() == Some(&group));
+                if !in_group {
+                    break;
+                }
+                first_op = parent_op.clone();
+            }
+        }
+    }
+    let parent_ops = first_op.parents();
+    if parent_ops.len() != 1 {
+        return Err(CommandError::UserError(format!(
+            "Cannot undo past operation {}",
+            short_operation_hash(first_op.id())
+        )));
+    }
+
     let mut tx =
         workspace_command.start_transaction(&format!("undo operation {}", bad_op.id().hex()));
     let repo_loader = workspace_command.repo().loader();
     let bad_repo = repo_loader.load_at(&bad_op);
     let parent_repo = repo_loader.load_at(&parent_ops[0]);
     tx.mut_repo().merge(&bad_repo, &parent_repo);
-    workspace_command.finish_transaction(ui, tx)?;
+    workspace_command.finish_transaction_after_confirmation(ui, tx, args.dry_run)?;
 
     Ok(())
 }
@@ -3810,7 +8295,7 @@ fn cmd_op_restore(
     let mut tx = workspace_command
         .start_transaction(&format!("restore to operation {}", target_op.id().hex()));
     tx.mut_repo().set_view(target_op.view().take_store_view());
-    workspace_command.finish_transaction(ui, tx)?;
+    workspace_command.finish_transaction_after_confirmation(ui, tx, args.dry_run)?;
 
     Ok(())
 }
@@ -3822,6 +8307,8 @@ fn cmd_operation(
 ) -> Result<(), CommandError> {
     match subcommand {
         OperationCommands::Log(command_matches) => cmd_op_log(ui, command, command_matches),
+        OperationCommands::Heads(command_matches) => cmd_op_heads(ui, command, command_matches),
+        OperationCommands::Merge(command_matches) => cmd_op_merge(ui, command, command_matches),
         OperationCommands::Restore(command_matches) => cmd_op_restore(ui, command, command_matches),
         OperationCommands::Undo(command_matches) => cmd_op_undo(ui, command, command_matches),
     }
@@ -3849,6 +8336,19 @@ fn cmd_workspace_add(
     args: &WorkspaceAddArgs,
 ) -> Result<(), CommandError> {
     let old_workspace_command = command.workspace_helper(ui)?;
+    let target = args
+        .revision
+        .as_ref()
+        .map(|revision| old_workspace_command.resolve_single_rev(revision))
+        .transpose()?;
+    let sparse_patterns = args
+        .sparse_pattern
+        .iter()
+        .map(|value| {
+            parse_sparse_pattern(value)
+                .ok_or_else(|| UserError(format!("Invalid sparse pattern: \"{value}\"")))
+        })
+        .try_collect()?;
     let destination_path = ui.cwd().join(&args.destination);
     if destination_path.exists() {
         return Err(CommandError::UserError(
@@ -3894,11 +8394,26 @@ fn cmd_workspace_add(
         command.global_args(),
         repo,
     )?;
+    if !args.sparse_pattern.is_empty() {
+        // Narrow the still empty working copy first so the checkout below only
+        // writes the matching files.
+        let (mut locked_wc, _wc_commit) = new_workspace_command.start_working_copy_mutation()?;
+        locked_wc
+            .set_sparse_patterns(sparse_patterns)
+            .map_err(|err| {
+                CommandError::InternalError(format!("Failed to update working copy paths: {err}"))
+            })?;
+        let operation_id = locked_wc.old_operation_id().clone();
+        locked_wc.finish(operation_id)?;
+    }
     let mut tx = new_workspace_command
         .start_transaction(&format!("Initial checkout in workspace {}", &name));
-    // Check out a parent of the checkout of the current workspace, or the root if
-    // there is no checkout in the current workspace.
-    let new_wc_commit = if let Some(old_checkout_id) = new_workspace_command
+    // Check out the requested revision, or else a parent of the checkout of the
+    // current workspace, or the root if there is no checkout in the current
+    // workspace.
+    let new_wc_commit = if let Some(target) = target {
+        target
+    } else if let Some(old_checkout_id) = new_workspace_command
         .repo()
         .view()
         .get_wc_commit_id(&old_workspace_command.workspace_id())
@@ -3972,16 +8487,46 @@ fn cmd_sparse(ui: &mut Ui, command: &CommandHelper, args: &SparseArgs) -> Result
             let ui_path = workspace_command.format_file_path(path);
             writeln!(ui, "{}", ui_path)?;
         }
+    } else if !args.hydrate.is_empty() {
+        let mut workspace_command = command.workspace_helper(ui)?;
+        let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.hydrate)?;
+        let (mut locked_wc, _wc_commit) = workspace_command.start_working_copy_mutation()?;
+        let stats = locked_wc.hydrate(matcher.as_ref()).map_err(|err| {
+            CommandError::InternalError(format!("Failed to materialize files: {err}"))
+        })?;
+        let operation_id = locked_wc.old_operation_id().clone();
+        locked_wc.finish(operation_id)?;
+        writeln!(ui, "Materialized {} files", stats.updated_files)?;
     } else {
         let mut workspace_command = command.workspace_helper(ui)?;
         let workspace_root = workspace_command.workspace_root().clone();
-        let paths_to_add = repo_paths_from_values(ui, &workspace_root, &args.add)?;
+        let mut paths_to_add = repo_paths_from_values(ui, &workspace_root, &args.add)?;
+        if let Some(path) = &args.from_file {
+            let (content, source) = if path == "-" {
+                let mut buffer = String::new();
+                io::stdin()
+                    .read_to_string(&mut buffer)
+                    .map_err(|err| UserError(format!("Failed to read stdin: {err}")))?;
+                (buffer, "stdin".to_string())
+            } else {
+                let content = fs::read_to_string(ui.cwd().join(path))
+                    .map_err(|err| UserError(format!("Failed to read {path}: {err}")))?;
+                (content, path.clone())
+            };
+            paths_to_add.extend(parse_sparse_patterns(&content, &source)?);
+        }
+        if !args.profile.is_empty() {
+            let wc_commit = workspace_command.resolve_single_rev("@")?;
+            for name in &args.profile {
+                paths_to_add.extend(read_sparse_profile(ui, &wc_commit.tree(), name)?);
+            }
+        }
         let (mut locked_wc, _wc_commit) = workspace_command.start_working_copy_mutation()?;
         let mut new_patterns = HashSet::new();
         if args.reset {
             new_patterns.insert(RepoPath::root());
         } else {
-            if !args.clear {
+            if !args.clear && args.from_file.is_none() {
                 new_patterns.extend(locked_wc.sparse_patterns().iter().cloned());
                 let paths_to_remove = repo_paths_from_values(ui, &workspace_root, &args.remove)?;
                 for path in paths_to_remove {
@@ -3997,12 +8542,81 @@ fn cmd_sparse(ui: &mut Ui, command: &CommandHelper, args: &SparseArgs) -> Result
             CommandError::InternalError(format!("Failed to update working copy paths: {err}"))
         })?;
         let operation_id = locked_wc.old_operation_id().clone();
-        locked_wc.finish(operation_id);
+        locked_wc.finish(operation_id)?;
         print_checkout_stats(ui, stats)?;
     }
     Ok(())
 }
 
+/// Reads the paths listed in the sparse profile `name` from `tree`.
+fn read_sparse_profile(ui: &Ui, tree: &Tree, name: &str) -> Result<Vec<RepoPath>, CommandError> {
+    let profiles_dir = ui
+        .settings()
+        .config()
+        .get_string("sparse.profiles-dir")
+        .unwrap_or_else(|_| "profiles".to_string());
+    let profile_path = RepoPath::from_internal_string(
+        format!("{}/{name}.sparse", profiles_dir.trim_end_matches('/')).as_str(),
+    );
+    let id = match tree.path_value(&profile_path) {
+        Some(TreeValue::Normal { id, .. }) => id,
+        _ => {
+            return Err(UserError(format!(
+                "No sparse profile named \"{name}\" (expected a file at {})",
+                profile_path.to_internal_file_string()
+            )))
+        }
+    };
+    let mut content = String::new();
+    tree.store()
+        .read_file(&profile_path, &id)?
+        .read_to_string(&mut content)
+        .map_err(|err| {
+            UserError(format!(
+                "Failed to read sparse profile {}: {err}",
+                profile_path.to_internal_file_string()
+            ))
+        })?;
+    parse_sparse_patterns(&content, &profile_path.to_internal_file_string())
+}
+
+/// Parses a list of sparse patterns, one path relative to the repo root per
+/// line. Empty lines and lines starting with `#` are ignored. `source` is used
+/// in error messages.
+fn parse_sparse_patterns(content: &str, source: &str) -> Result<Vec<RepoPath>, CommandError> {
+    let mut patterns = vec![];
+    for (line_number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let path = parse_sparse_pattern(line).ok_or_else(|| {
+            UserError(format!(
+                "Invalid sparse pattern on line {} of {source}: \"{line}\"",
+                line_number + 1
+            ))
+        })?;
+        patterns.push(path);
+    }
+    Ok(patterns)
+}
+
+/// Parses a path relative to the repo root, or `.` for the root, rejecting
+/// empty, `.` and `..` components elsewhere.
+fn parse_sparse_pattern(value: &str) -> Option<RepoPath> {
+    let path = value.trim_end_matches('/');
+    if path == "." {
+        Some(RepoPath::root())
+    } else if path
+        .split('/')
+        .any(|component| component.is_empty() || component == "." || component == "..")
+    {
+        None
+    } else {
+        Some(RepoPath::from_internal_string(path))
+    }
+}
+
 fn get_git_repo(store: &Store) -> Result<git2::Repository, CommandError> {
     match store.git_repo() {
         None => Err(CommandError::UserError(
@@ -4023,9 +8637,22 @@ fn cmd_git_remote_add(
     if git_repo.find_remote(&args.remote).is_ok() {
         return Err(CommandError::UserError("Remote already exists".to_string()));
     }
-    git_repo
-        .remote(&args.remote, &args.url)
-        .map_err(|err| CommandError::UserError(err.to_string()))?;
+    let to_user_error = |err: git2::Error| CommandError::UserError(err.to_string());
+    match args.fetch.split_first() {
+        None => git_repo.remote(&args.remote, &args.url).map(|_| ()),
+        Some((first, rest)) => git_repo
+            .remote_with_fetch(&args.remote, &args.url, first)
+            .and_then(|_| {
+                rest.iter()
+                    .try_for_each(|refspec| git_repo.remote_add_fetch(&args.remote, refspec))
+            }),
+    }
+    .map_err(to_user_error)?;
+    if let Some(push_url) = &args.push_url {
+        git_repo
+            .remote_set_pushurl(&args.remote, Some(push_url))
+            .map_err(to_user_error)?;
+    }
     Ok(())
 }
 
@@ -4060,6 +8687,99 @@ fn cmd_git_remote_remove(
     Ok(())
 }
 
+fn cmd_git_remote_rename(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &GitRemoteRenameArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo();
+    let git_repo = get_git_repo(repo.store())?;
+    let mut tx = workspace_command
+        .start_transaction(&format!("rename git remote {} to {}", &args.old, &args.new));
+    git::rename_remote(tx.mut_repo(), &git_repo, &args.old, &args.new)
+        .map_err(|err| CommandError::UserError(err.to_string()))?;
+    if tx.mut_repo().has_changes() {
+        workspace_command.finish_transaction(ui, tx)
+    } else {
+        Ok(())
+    }
+}
+
+fn cmd_git_remote_set_url(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &GitRemoteSetUrlArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo();
+    let git_repo = get_git_repo(repo.store())?;
+    if git_repo.find_remote(&args.remote).is_err() {
+        return Err(CommandError::UserError("Remote doesn't exist".to_string()));
+    }
+    if args.push {
+        git_repo.remote_set_pushurl(&args.remote, Some(&args.url))
+    } else {
+        git_repo.remote_set_url(&args.remote, &args.url)
+    }
+    .map_err(|err| CommandError::UserError(err.to_string()))?;
+    Ok(())
+}
+
+fn cmd_git_notes_add(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &GitNotesAddArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let commit = workspace_command.resolve_single_rev(&args.revision)?;
+    let repo = workspace_command.repo();
+    let git_repo = get_git_repo(repo.store())?;
+    git::set_note(
+        &git_repo,
+        &repo.settings().git_notes_ref(),
+        commit.id(),
+        &args.message,
+        &ui.settings().signature(),
+        args.force,
+    )
+    .map_err(|err| {
+        if err.code() == git2::ErrorCode::Exists {
+            CommandError::UserError(format!(
+                "Commit {} already has a note (use --force to replace it)",
+                short_commit_hash(commit.id())
+            ))
+        } else {
+            CommandError::UserError(err.to_string())
+        }
+    })?;
+    Ok(())
+}
+
+fn cmd_git_notes_remove(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &GitNotesRemoveArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let commit = workspace_command.resolve_single_rev(&args.revision)?;
+    let repo = workspace_command.repo();
+    let git_repo = get_git_repo(repo.store())?;
+    let removed = git::remove_note(
+        &git_repo,
+        &repo.settings().git_notes_ref(),
+        commit.id(),
+        &ui.settings().signature(),
+    )?;
+    if !removed {
+        return Err(CommandError::UserError(format!(
+            "Commit {} has no note",
+            short_commit_hash(commit.id())
+        )));
+    }
+    Ok(())
+}
+
 fn cmd_git_remote_list(
     ui: &mut Ui,
     command: &CommandHelper,
@@ -4070,11 +8790,23 @@ fn cmd_git_remote_list(
     let git_repo = get_git_repo(repo.store())?;
     for remote_name in git_repo.remotes()?.iter().flatten() {
         let remote = git_repo.find_remote(remote_name)?;
-        writeln!(ui, "{} {}", remote_name, remote.url().unwrap_or("<no URL>"))?;
+        write!(ui, "{} {}", remote_name, remote.url().unwrap_or("<no URL>"))?;
+        if let Some(push_url) = remote.pushurl() {
+            write!(ui, " (push: {push_url})")?;
+        }
+        writeln!(ui)?;
     }
     Ok(())
 }
 
+/// The remote to fetch from and push to if none is specified.
+fn default_git_remote(repo: &ReadonlyRepo) -> String {
+    repo.settings()
+        .config()
+        .get_string("git.default-remote")
+        .unwrap_or_else(|_| "origin".to_string())
+}
+
 fn cmd_git_fetch(
     ui: &mut Ui,
     command: &CommandHelper,
@@ -4082,15 +8814,62 @@ fn cmd_git_fetch(
 ) -> Result<(), CommandError> {
     let mut workspace_command = command.workspace_helper(ui)?;
     let repo = workspace_command.repo();
+    let remote = args
+        .remote
+        .clone()
+        .unwrap_or_else(|| default_git_remote(repo));
     let git_repo = get_git_repo(repo.store())?;
-    let mut tx =
-        workspace_command.start_transaction(&format!("fetch from git remote {}", &args.remote));
-    git::fetch(tx.mut_repo(), &git_repo, &args.remote)
-        .map_err(|err| CommandError::UserError(err.to_string()))?;
+    let mut tx = workspace_command.start_transaction(&format!("fetch from git remote {}", &remote));
+    let prune = args.prune
+        || ui
+            .settings()
+            .config()
+            .get_bool("git.fetch-prune")
+            .unwrap_or(false);
+    with_remote_callbacks(ui, args.quiet, |callbacks| {
+        git::fetch(tx.mut_repo(), &git_repo, &remote, prune, callbacks)
+    })
+    .map_err(|err| match err {
+        GitFetchError::TooManyObjects { .. } => too_many_objects_error(err),
+        err => CommandError::UserError(err.to_string()),
+    })?;
     workspace_command.finish_transaction(ui, tx)?;
     Ok(())
 }
 
+/// Calls `f` with callbacks that show the progress of the transfer on stderr,
+/// unless `quiet` is set or stderr isn't a terminal.
+fn with_remote_callbacks<T>(
+    ui: &mut Ui,
+    quiet: bool,
+    f: impl FnOnce(git::RemoteCallbacks<'_>) -> T,
+) -> T {
+    let mut callbacks = git::RemoteCallbacks {
+        max_objects: ui.settings().max_fetch_objects(),
+        ..Default::default()
+    };
+    if quiet || !ui.use_progress_indicator() {
+        return f(callbacks);
+    }
+    let mut progress = Progress::new(Instant::now());
+    let mut progress_callback = |update: &git::Progress| {
+        // Failing to show progress isn't worth failing the transfer for
+        progress
+            .update(Instant::now(), update, &mut *ui.stderr_formatter())
+            .ok();
+    };
+    callbacks.progress = Some(&mut progress_callback);
+    let result = f(callbacks);
+    progress.finish(&mut *ui.stderr_formatter()).ok();
+    result
+}
+
+fn too_many_objects_error(err: GitFetchError) -> CommandError {
+    CommandError::UserError(format!(
+        "{err} (`limits.fetch-objects`). Nothing was fetched. Raise the limit to fetch anyway."
+    ))
+}
+
 fn clone_destination_for_source(source: &str) -> Option<&str> {
     let destination = source.strip_suffix(".git").unwrap_or(source);
     let destination = destination.strip_suffix('/').unwrap_or(destination);
@@ -4139,7 +8918,7 @@ fn cmd_git_clone(
         fs::create_dir(&wc_path).unwrap();
     }
 
-    let clone_result = do_git_clone(ui, command, source, &wc_path);
+    let clone_result = do_git_clone(ui, command, source, &wc_path, args.quiet);
     if clone_result.is_err() {
         // Canonicalize because fs::remove_dir_all() doesn't seem to like e.g.
         // `/some/path/.`
@@ -4187,6 +8966,7 @@ fn do_git_clone(
     command: &CommandHelper,
     source: &str,
     wc_path: &Path,
+    quiet: bool,
 ) -> Result<(WorkspaceCommandHelper, Option<String>), CommandError> {
     let (workspace, repo) = Workspace::init_internal_git(ui.settings(), wc_path)?;
     let git_repo = get_git_repo(repo.store())?;
@@ -4195,15 +8975,24 @@ fn do_git_clone(
     let remote_name = "origin";
     git_repo.remote(remote_name, source).unwrap();
     let mut fetch_tx = workspace_command.start_transaction("fetch from git remote into empty repo");
-    let maybe_default_branch =
-        git::fetch(fetch_tx.mut_repo(), &git_repo, remote_name).map_err(|err| match err {
-            GitFetchError::NoSuchRemote(_) => {
-                panic!("shouldn't happen as we just created the git remote")
-            }
-            GitFetchError::InternalGitError(err) => {
-                CommandError::UserError(format!("Fetch failed: {err}"))
-            }
-        })?;
+    let maybe_default_branch = with_remote_callbacks(ui, quiet, |callbacks| {
+        git::fetch(
+            fetch_tx.mut_repo(),
+            &git_repo,
+            remote_name,
+            false,
+            callbacks,
+        )
+    })
+    .map_err(|err| match err {
+        GitFetchError::NoSuchRemote(_) => {
+            panic!("shouldn't happen as we just created the git remote")
+        }
+        GitFetchError::TooManyObjects { .. } => too_many_objects_error(err),
+        GitFetchError::InternalGitError(err) => {
+            CommandError::UserError(format!("Fetch failed: {err}"))
+        }
+    })?;
     workspace_command.finish_transaction(ui, fetch_tx)?;
     Ok((workspace_command, maybe_default_branch))
 }
@@ -4214,68 +9003,88 @@ fn cmd_git_push(
     args: &GitPushArgs,
 ) -> Result<(), CommandError> {
     let mut workspace_command = command.workspace_helper(ui)?;
+    let remote = args
+        .remote
+        .clone()
+        .unwrap_or_else(|| default_git_remote(workspace_command.repo()));
 
     let mut tx;
     let mut branch_updates = vec![];
     if let Some(branch_name) = &args.branch {
-        if let Some(update) = branch_updates_for_push(
-            workspace_command.repo().as_repo_ref(),
-            &args.remote,
-            branch_name,
-        )? {
+        if let Some(update) =
+            branch_updates_for_push(workspace_command.repo().as_repo_ref(), &remote, branch_name)?
+        {
             branch_updates.push((branch_name.clone(), update));
         } else {
             writeln!(
                 ui,
                 "Branch {}@{} already matches {}",
-                branch_name, &args.remote, branch_name
+                branch_name, &remote, branch_name
             )?;
         }
         tx = workspace_command.start_transaction(&format!(
             "push branch {branch_name} to git remote {}",
-            &args.remote
+            &remote
         ));
-    } else if let Some(change_str) = &args.change {
-        let commit = workspace_command.resolve_single_rev(change_str)?;
-        let branch_name = format!(
-            "{}{}",
-            ui.settings().push_branch_prefix(),
-            commit.change_id().hex()
-        );
-        if workspace_command
-            .repo()
-            .view()
-            .get_local_branch(&branch_name)
-            .is_none()
-        {
-            writeln!(
-                ui,
-                "Creating branch {} for revision {}",
-                branch_name, change_str
-            )?;
+    } else if !args.change.is_empty() {
+        let mut commits = vec![];
+        for change_str in &args.change {
+            let revset_commits = workspace_command.resolve_revset(change_str)?;
+            workspace_command.check_non_empty(&revset_commits)?;
+            // Oldest first
+            for commit in revset_commits.into_iter().rev() {
+                if !commits.contains(&commit) {
+                    commits.push(commit);
+                }
+            }
         }
-        tx = workspace_command.start_transaction(&format!(
-            "push change {} to git remote {}",
-            commit.change_id().hex(),
-            &args.remote
-        ));
-        tx.mut_repo()
-            .set_local_branch(branch_name.clone(), RefTarget::Normal(commit.id().clone()));
-        if let Some(update) =
-            branch_updates_for_push(tx.mut_repo().as_repo_ref(), &args.remote, &branch_name)?
-        {
-            branch_updates.push((branch_name.clone(), update));
-        } else {
-            writeln!(
-                ui,
-                "Branch {}@{} already matches {}",
-                branch_name, &args.remote, branch_name
-            )?;
+        let prefix = ui.settings().push_branch_prefix();
+        tx = match commits.as_slice() {
+            [commit] => workspace_command.start_transaction(&format!(
+                "push change {} to git remote {}",
+                commit.change_id().hex(),
+                &remote
+            )),
+            _ => workspace_command.start_transaction(&format!(
+                "push {} changes to git remote {}",
+                commits.len(),
+                &remote
+            )),
+        };
+        for commit in &commits {
+            let branch_name = format!("{prefix}{}", commit.change_id().hex());
+            if tx
+                .mut_repo()
+                .view()
+                .get_local_branch(&branch_name)
+                .is_none()
+            {
+                write!(ui, "Creating branch {branch_name} for revision ")?;
+                ui.write_commit_summary(
+                    tx.mut_repo().as_repo_ref(),
+                    &workspace_command.workspace_id(),
+                    commit,
+                )?;
+                writeln!(ui)?;
+            }
+            tx.mut_repo()
+                .set_local_branch(branch_name.clone(), RefTarget::Normal(commit.id().clone()));
+            if let Some(update) =
+                branch_updates_for_push(tx.mut_repo().as_repo_ref(), &remote, &branch_name)?
+            {
+                branch_updates.push((branch_name.clone(), update));
+            } else {
+                writeln!(
+                    ui,
+                    "Branch {}@{} already matches {}",
+                    branch_name, &remote, branch_name
+                )?;
+            }
         }
     } else if args.all {
         // TODO: Is it useful to warn about conflicted branches?
         for (branch_name, branch_target) in workspace_command.repo().view().branches() {
-            let push_action = classify_branch_push_action(branch_target, &args.remote);
+            let push_action = classify_branch_push_action(branch_target, &remote);
             match push_action {
                 BranchPushAction::AlreadyMatches => {}
                 BranchPushAction::LocalConflicted => {}
@@ -4286,7 +9095,7 @@ fn cmd_git_push(
             }
         }
         tx = workspace_command
-            .start_transaction(&format!("push all branches to git remote {}", &args.remote));
+            .start_transaction(&format!("push all branches to git remote {}", &remote));
     } else {
         match workspace_command
             .repo()
@@ -4302,7 +9111,7 @@ fn cmd_git_push(
                 let desired_target = Some(RefTarget::Normal(checkout.clone()));
                 for (branch_name, branch_target) in workspace_command.repo().view().branches() {
                     if branch_target.local_target == desired_target {
-                        let push_action = classify_branch_push_action(branch_target, &args.remote);
+                        let push_action = classify_branch_push_action(branch_target, &remote);
                         match push_action {
                             BranchPushAction::AlreadyMatches => {}
                             BranchPushAction::LocalConflicted => {}
@@ -4320,7 +9129,7 @@ fn cmd_git_push(
         }
         tx = workspace_command.start_transaction(&format!(
             "push current branch(es) to git remote {}",
-            &args.remote
+            &remote
         ));
     }
 
@@ -4331,6 +9140,10 @@ fn cmd_git_push(
 
     let repo = workspace_command.repo();
 
+    // The new targets are branch heads, so their reachability bitmaps are likely
+    // to be available already
+    let reachability_bitmaps =
+        ReachabilityBitmaps::load(repo.index_store().reachability_dir()).ok();
     let mut ref_updates = vec![];
     let mut new_heads = vec![];
     let mut force_pushed_branches = hashset! {};
@@ -4340,7 +9153,14 @@ fn cmd_git_push(
             new_heads.push(new_target.clone());
             let force = match &update.old_target {
                 None => false,
-                Some(old_target) => !repo.index().is_ancestor(old_target, new_target),
+                Some(old_target) => match &reachability_bitmaps {
+                    Some(bitmaps) => !bitmaps.is_ancestor(
+                        IndexRef::Readonly(repo.index()),
+                        old_target,
+                        new_target,
+                    ),
+                    None => !repo.index().is_ancestor(old_target, new_target),
+                },
             };
             if force {
                 force_pushed_branches.insert(branch_name.to_string());
@@ -4359,142 +9179,816 @@ fn cmd_git_push(
         }
     }
 
+    if !args.force_protected {
+        let protected_patterns = repo
+            .settings()
+            .git_protected_branches()?
+            .iter()
+            .map(|pattern| RefNamePattern::glob(pattern))
+            .collect_vec();
+        for (branch_name, update) in &branch_updates {
+            let action = if update.new_target.is_none() {
+                "delete"
+            } else if force_pushed_branches.contains(branch_name) {
+                "force-push"
+            } else {
+                continue;
+            };
+            if protected_patterns
+                .iter()
+                .any(|pattern| pattern.matches(branch_name))
+            {
+                return Err(UserError(format!(
+                    "Refusing to {action} protected branch {branch_name} (use --force-protected \
+                     to {action} it anyway)"
+                )));
+            }
+        }
+    }
+
     // Check if there are conflicts in any commits we're about to push that haven't
     // already been pushed.
     let mut old_heads = vec![];
     for branch_target in repo.view().branches().values() {
-        if let Some(old_head) = branch_target.remote_targets.get(&args.remote) {
+        if let Some(old_head) = branch_target.remote_targets.get(&remote) {
             old_heads.extend(old_head.adds());
         }
     }
-    if old_heads.is_empty() {
-        old_heads.push(repo.store().root_commit_id().clone());
+    if old_heads.is_empty() {
+        old_heads.push(repo.store().root_commit_id().clone());
+    }
+    let allow_empty_description = args.allow_wip || repo.settings().push_allow_empty_description();
+    let wip_patterns = if args.allow_wip {
+        vec![]
+    } else {
+        repo.settings()
+            .push_wip_patterns()
+            .iter()
+            .map(|pattern| {
+                regex::Regex::new(pattern).map_err(|err| {
+                    UserError(format!("Invalid pattern in push.wip-patterns: {err}"))
+                })
+            })
+            .try_collect()?
+    };
+    let mut rejected_commits = vec![];
+    // Whether all the commits could be pushed with --allow-wip
+    let mut only_wip = true;
+    for index_entry in repo.index().walk_revs(&new_heads, &old_heads) {
+        let commit = repo.store().get_commit(&index_entry.commit_id())?;
+        let mut reasons = vec![];
+        let mut wip_reasons = 0;
+        if commit.description().is_empty() {
+            if !allow_empty_description {
+                reasons.push("it has no description".to_string());
+                wip_reasons += 1;
+            }
+        } else {
+            let first_line = commit.description().lines().next().unwrap_or_default();
+            if let Some(regex) = wip_patterns.iter().find(|regex| regex.is_match(first_line)) {
+                reasons.push(format!("its description matches \"{}\"", regex.as_str()));
+                wip_reasons += 1;
+            }
+        }
+        if commit.author().name == UserSettings::user_name_placeholder()
+            || commit.author().email == UserSettings::user_email_placeholder()
+            || commit.committer().name == UserSettings::user_name_placeholder()
+            || commit.committer().email == UserSettings::user_email_placeholder()
+        {
+            reasons.push("it has no author and/or committer set".to_string());
+        }
+        if commit.tree().has_conflict() {
+            reasons.push("it has conflicts".to_string());
+        }
+        if !reasons.is_empty() {
+            only_wip &= wip_reasons == reasons.len();
+            rejected_commits.push((commit, reasons));
+        }
+    }
+    if !rejected_commits.is_empty() {
+        let mut message = match rejected_commits.as_slice() {
+            [(commit, reasons)] => format!(
+                "Won't push commit {} since {}",
+                short_commit_hash(commit.id()),
+                reasons.join(" and ")
+            ),
+            _ => {
+                let mut message = "Won't push these commits:".to_string();
+                for (commit, reasons) in &rejected_commits {
+                    message.push_str(&format!(
+                        "\n  {} since {}",
+                        short_commit_hash(commit.id()),
+                        reasons.join(" and ")
+                    ));
+                }
+                message
+            }
+        };
+        if only_wip {
+            message.push_str("\nUse --allow-wip to push anyway.");
+        }
+        return Err(UserError(message));
+    }
+
+    writeln!(ui, "Branch changes to push to {}:", &remote)?;
+    for (branch_name, update) in &branch_updates {
+        match (&update.old_target, &update.new_target) {
+            (Some(old_target), Some(new_target)) => {
+                if force_pushed_branches.contains(branch_name) {
+                    writeln!(
+                        ui,
+                        "  Force branch {branch_name} from {} to {}",
+                        short_commit_hash(old_target),
+                        short_commit_hash(new_target)
+                    )?;
+                } else {
+                    writeln!(
+                        ui,
+                        "  Move branch {branch_name} from {} to {}",
+                        short_commit_hash(old_target),
+                        short_commit_hash(new_target)
+                    )?;
+                }
+            }
+            (Some(old_target), None) => {
+                writeln!(
+                    ui,
+                    "  Delete branch {branch_name} from {}",
+                    short_commit_hash(old_target)
+                )?;
+            }
+            (None, Some(new_target)) => {
+                writeln!(
+                    ui,
+                    "  Add branch {branch_name} to {}",
+                    short_commit_hash(new_target)
+                )?;
+            }
+            (None, None) => {
+                panic!("Not pushing any change to branch {branch_name}");
+            }
+        }
+    }
+
+    if args.dry_run {
+        writeln!(ui, "Dry-run requested, not pushing.")?;
+        return Ok(());
+    }
+
+    let git_repo = get_git_repo(repo.store())?;
+    let signed = if args.signed {
+        Some(SignedPush::Yes)
+    } else {
+        repo.settings().git_sign_push()
+    };
+    match signed {
+        Some(signed) => git::push_updates_signed(&git_repo, &remote, &ref_updates, signed),
+        None => with_remote_callbacks(ui, args.quiet, |callbacks| {
+            git::push_updates(&git_repo, &remote, &ref_updates, callbacks)
+        }),
+    }
+    .map_err(|err| CommandError::UserError(err.to_string()))?;
+    git::import_refs(tx.mut_repo(), &git_repo)?;
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
+fn branch_updates_for_push(
+    repo: RepoRef,
+    remote_name: &str,
+    branch_name: &str,
+) -> Result<Option<BranchPushUpdate>, CommandError> {
+    let maybe_branch_target = repo.view().get_branch(branch_name);
+    let branch_target = maybe_branch_target
+        .ok_or_else(|| CommandError::UserError(format!("Branch {} doesn't exist", branch_name)))?;
+    let push_action = classify_branch_push_action(branch_target, remote_name);
+
+    match push_action {
+        BranchPushAction::AlreadyMatches => Ok(None),
+        BranchPushAction::LocalConflicted => Err(CommandError::UserError(format!(
+            "Branch {} is conflicted",
+            branch_name
+        ))),
+        BranchPushAction::RemoteConflicted => Err(CommandError::UserError(format!(
+            "Branch {}@{} is conflicted",
+            branch_name, remote_name
+        ))),
+        BranchPushAction::Update(update) => Ok(Some(update)),
+    }
+}
+
+fn cmd_git_import(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    _args: &GitImportArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo();
+    let git_repo = get_git_repo(repo.store())?;
+    let mut tx = workspace_command.start_transaction("import git refs");
+    git::import_refs(tx.mut_repo(), &git_repo)?;
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
+fn cmd_git_export(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    _args: &GitExportArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo();
+    let git_repo = get_git_repo(repo.store())?;
+    git::export_refs(repo, &git_repo)?;
+    Ok(())
+}
+
+fn cmd_gerrit_upload(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &GerritUploadArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo().clone();
+    let git_repo = get_git_repo(repo.store())?;
+    let config = repo.settings().config();
+    let remote = match &args.remote {
+        Some(remote) => remote.clone(),
+        None => config
+            .get_string("gerrit.default-remote")
+            .unwrap_or_else(|_| default_git_remote(&repo)),
+    };
+    let branch = match &args.for_branch {
+        Some(branch) => branch.clone(),
+        None => config
+            .get_string("gerrit.default-branch")
+            .unwrap_or_else(|_| "main".to_string()),
+    };
+    let heads = workspace_command.resolve_revset(&format!("heads({})", args.revisions))?;
+    workspace_command.check_non_empty(&heads)?;
+    // In reverse topological order
+    let commits = workspace_command
+        .resolve_revset(&format!("(remote_branches()..({})) ~ root", args.revisions))?;
+    if commits.is_empty() {
+        return Err(UserError(
+            "The revisions are already on the remote".to_string(),
+        ));
+    }
+    for commit in &commits {
+        workspace_command.check_rewriteable(commit)?;
+        let mut reasons = vec![];
+        if commit.description().is_empty() {
+            reasons.push("it has no description");
+        }
+        if commit.tree().has_conflict() {
+            reasons.push("it has conflicts");
+        }
+        if !reasons.is_empty() {
+            return Err(UserError(format!(
+                "Won't upload commit {} since {}",
+                short_commit_hash(commit.id()),
+                reasons.join(" and ")
+            )));
+        }
+    }
+
+    let mut tx = workspace_command.start_transaction(&format!(
+        "upload {} commits to gerrit remote {remote}",
+        commits.len()
+    ));
+    let mut rewritten_ids: HashMap<CommitId, CommitId> = HashMap::new();
+    let mut uploaded = vec![];
+    let mut num_added = 0;
+    for commit in commits.iter().rev() {
+        let new_parent_ids = commit
+            .parent_ids()
+            .iter()
+            .map(|id| rewritten_ids.get(id).unwrap_or(id).clone())
+            .collect_vec();
+        let description = gerrit::add_change_id_trailer(commit.description(), commit.change_id());
+        let commit =
+            if description != commit.description() || &new_parent_ids != commit.parent_ids() {
+                if description != commit.description() {
+                    num_added += 1;
+                }
+                let new_commit = CommitBuilder::for_rewrite_from(ui.settings(), commit)
+                    .set_parents(new_parent_ids)
+                    .set_description(description)
+                    .write_to_repo(tx.mut_repo());
+                rewritten_ids.insert(commit.id().clone(), new_commit.id().clone());
+                new_commit
+            } else {
+                commit.clone()
+            };
+        let gerrit_change_id = gerrit::find_change_id(commit.description())
+            .unwrap()
+            .to_string();
+        uploaded.push((gerrit_change_id, commit.id().clone()));
+    }
+    if num_added > 0 {
+        writeln!(ui, "Added Change-Id to {num_added} commits")?;
+    }
+
+    let qualified_name = format!("refs/for/{branch}");
+    for head in &heads {
+        let new_target = rewritten_ids.get(head.id()).unwrap_or(head.id()).clone();
+        writeln!(
+            ui,
+            "Uploading {} to {qualified_name} on {remote}",
+            short_commit_hash(&new_target)
+        )?;
+        // Gerrit creates a new change or patch set for each push to the magic
+        // `refs/for/` ref, so there's nothing to fast-forward
+        let update = GitRefUpdate {
+            qualified_name: qualified_name.clone(),
+            force: true,
+            new_target: Some(new_target),
+        };
+        with_remote_callbacks(ui, false, |callbacks| {
+            git::push_updates(&git_repo, &remote, &[update], callbacks)
+        })
+        .map_err(|err| CommandError::UserError(err.to_string()))?;
+    }
+    for (gerrit_change_id, commit_id) in &uploaded {
+        gerrit::record_uploaded(&git_repo, gerrit_change_id, commit_id)?;
     }
-    for index_entry in repo.index().walk_revs(&new_heads, &old_heads) {
-        let commit = repo.store().get_commit(&index_entry.commit_id())?;
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
+/// The changes between `base` on `remote` and `revision`, oldest first, and
+/// the branches their pull requests are made from.
+fn pr_stack(
+    ui: &Ui,
+    workspace_command: &WorkspaceCommandHelper,
+    revision: &str,
+    base: &str,
+    remote: &str,
+) -> Result<Vec<(Commit, String)>, CommandError> {
+    let head = workspace_command.resolve_single_rev(revision)?;
+    let repo = workspace_command.repo();
+    let base_ids = repo
+        .view()
+        .get_branch(base)
+        .and_then(|branch_target| branch_target.remote_targets.get(remote))
+        .map(|target| target.adds())
+        .ok_or_else(|| UserError(format!("Branch {base}@{remote} doesn't exist")))?;
+    let mut commits: Vec<Commit> = RevsetExpression::commits(base_ids)
+        .range(&RevsetExpression::commit(head.id().clone()))
+        .evaluate(repo.as_repo_ref(), None)?
+        .iter()
+        .commits(repo.store())
+        .try_collect()?;
+    if commits.is_empty() {
+        return Err(UserError(format!(
+            "Revision {} is already on {base}@{remote}",
+            short_commit_hash(head.id())
+        )));
+    }
+    commits.reverse();
+    let prefix = ui.settings().push_branch_prefix();
+    Ok(commits
+        .into_iter()
+        .map(|commit| {
+            let branch_name = format!("{prefix}{}", commit.change_id().hex());
+            (commit, branch_name)
+        })
+        .collect())
+}
+
+fn pr_forge(
+    ui: &Ui,
+    workspace_command: &WorkspaceCommandHelper,
+    base: &Option<String>,
+    remote: &Option<String>,
+) -> Result<(Forge, git2::Repository, String, String), CommandError> {
+    let repo = workspace_command.repo();
+    let git_repo = get_git_repo(repo.store())?;
+    let base = match base {
+        Some(base) => base.clone(),
+        None => ui
+            .settings()
+            .config()
+            .get_string("pr.default-base")
+            .unwrap_or_else(|_| "main".to_string()),
+    };
+    let remote = remote.clone().unwrap_or_else(|| default_git_remote(repo));
+    let remote_url = match git_repo.find_remote(&remote) {
+        Ok(git_remote) => git_remote.url().unwrap_or_default().to_string(),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => {
+            return Err(UserError(format!("No git remote named '{remote}'")));
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let forge = Forge::from_settings(ui.settings(), &remote_url)?;
+    Ok((forge, git_repo, base, remote))
+}
+
+fn cmd_pr_create(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &PrCreateArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let (forge, git_repo, base, remote) =
+        pr_forge(ui, &workspace_command, &args.base, &args.remote)?;
+    let stack = pr_stack(ui, &workspace_command, &args.revision, &base, &remote)?;
+    for (commit, _) in &stack {
         let mut reasons = vec![];
-        if commit.description().is_empty() {
-            reasons.push("it has no description");
+        if commit.parents().len() > 1 {
+            reasons.push("it's a merge");
         }
-        if commit.author().name == UserSettings::user_name_placeholder()
-            || commit.author().email == UserSettings::user_email_placeholder()
-            || commit.committer().name == UserSettings::user_name_placeholder()
-            || commit.committer().email == UserSettings::user_email_placeholder()
-        {
-            reasons.push("it has no author and/or committer set");
+        if commit.description().trim().is_empty() {
+            reasons.push("it has no description");
         }
         if commit.tree().has_conflict() {
             reasons.push("it has conflicts");
         }
         if !reasons.is_empty() {
             return Err(UserError(format!(
-                "Won't push commit {} since {}",
+                "Won't create a pull request for commit {} since {}",
                 short_commit_hash(commit.id()),
                 reasons.join(" and ")
             )));
         }
     }
 
-    writeln!(ui, "Branch changes to push to {}:", &args.remote)?;
-    for (branch_name, update) in &branch_updates {
-        match (&update.old_target, &update.new_target) {
-            (Some(old_target), Some(new_target)) => {
-                if force_pushed_branches.contains(branch_name) {
-                    writeln!(
-                        ui,
-                        "  Force branch {branch_name} from {} to {}",
-                        short_commit_hash(old_target),
-                        short_commit_hash(new_target)
-                    )?;
-                } else {
-                    writeln!(
-                        ui,
-                        "  Move branch {branch_name} from {} to {}",
-                        short_commit_hash(old_target),
-                        short_commit_hash(new_target)
-                    )?;
-                }
-            }
-            (Some(old_target), None) => {
+    let repo = workspace_command.repo().clone();
+    let mut tx = workspace_command.start_transaction(&format!(
+        "push stack of {} commits to git remote {remote}",
+        stack.len()
+    ));
+    let mut ref_updates = vec![];
+    for (commit, branch_name) in &stack {
+        tx.mut_repo()
+            .set_local_branch(branch_name.clone(), RefTarget::Normal(commit.id().clone()));
+        if let Some(update) =
+            branch_updates_for_push(tx.mut_repo().as_repo_ref(), &remote, branch_name)?
+        {
+            let new_target = update.new_target.unwrap();
+            // Rewriting a change in the stack rewrites all of its descendants, so
+            // their branches need to be force-pushed
+            let force = update.old_target.map_or(false, |old_target| {
+                !repo.index().is_ancestor(&old_target, &new_target)
+            });
+            ref_updates.push(GitRefUpdate {
+                qualified_name: format!("refs/heads/{branch_name}"),
+                force,
+                new_target: Some(new_target),
+            });
+        }
+    }
+    if !ref_updates.is_empty() {
+        writeln!(ui, "Pushing {} branches to {remote}", ref_updates.len())?;
+        with_remote_callbacks(ui, false, |callbacks| {
+            git::push_updates(&git_repo, &remote, &ref_updates, callbacks)
+        })
+        .map_err(|err| CommandError::UserError(err.to_string()))?;
+        git::import_refs(tx.mut_repo(), &git_repo)?;
+    }
+    workspace_command.finish_transaction(ui, tx)?;
+
+    let mut pr_base = base;
+    for (commit, branch_name) in &stack {
+        let (title, body) = commit
+            .description()
+            .trim()
+            .split_once('\n')
+            .unwrap_or((commit.description().trim(), ""));
+        let body = body.trim();
+        match forge.find_pull_request(branch_name)? {
+            Some(pull_request) if pull_request.state == PullRequestState::Open => {
+                let pull_request =
+                    forge.update_pull_request(pull_request.number, &pr_base, title, body)?;
                 writeln!(
                     ui,
-                    "  Delete branch {branch_name} from {}",
-                    short_commit_hash(old_target)
+                    "Updated pull request #{} for {}: {}",
+                    pull_request.number,
+                    short_commit_description(commit),
+                    pull_request.url
                 )?;
             }
-            (None, Some(new_target)) => {
+            _ => {
+                let pull_request = forge.create_pull_request(branch_name, &pr_base, title, body)?;
                 writeln!(
                     ui,
-                    "  Add branch {branch_name} to {}",
-                    short_commit_hash(new_target)
+                    "Created pull request #{} for {}: {}",
+                    pull_request.number,
+                    short_commit_description(commit),
+                    pull_request.url
                 )?;
             }
-            (None, None) => {
-                panic!("Not pushing any change to branch {branch_name}");
+        }
+        pr_base = branch_name.clone();
+    }
+    Ok(())
+}
+
+fn cmd_pr_status(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &PrStatusArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let (forge, _git_repo, base, remote) =
+        pr_forge(ui, &workspace_command, &args.base, &args.remote)?;
+    let stack = pr_stack(ui, &workspace_command, &args.revision, &base, &remote)?;
+    let view = workspace_command.repo().view();
+    for (commit, branch_name) in &stack {
+        let pull_request = forge.find_pull_request(branch_name)?;
+        write!(ui, "{}: ", short_commit_description(commit))?;
+        match pull_request {
+            None => writeln!(ui, "no pull request")?,
+            Some(pull_request) => {
+                write!(
+                    ui,
+                    "#{} {} {}",
+                    pull_request.number, pull_request.state, pull_request.url
+                )?;
+                let pushed_target = view
+                    .get_branch(branch_name)
+                    .and_then(|branch_target| branch_target.remote_targets.get(&remote));
+                if pushed_target != Some(&RefTarget::Normal(commit.id().clone())) {
+                    write!(ui, " (needs push)")?;
+                }
+                writeln!(ui)?;
             }
         }
     }
+    Ok(())
+}
 
-    if args.dry_run {
-        writeln!(ui, "Dry-run requested, not pushing.")?;
+fn cmd_browse(ui: &mut Ui, command: &CommandHelper, args: &BrowseArgs) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let commit = workspace_command.resolve_single_rev(&args.revision)?;
+    let template = workspace_command
+        .repo()
+        .settings()
+        .commit_url_template()
+        .ok_or_else(|| {
+            CommandError::ConfigError(
+                "No commit URL template configured; set browse.commit-url".to_string(),
+            )
+        })?;
+    let url = templater::format_commit_url(&template, &commit);
+    if args.print {
+        writeln!(ui, "{url}")?;
         return Ok(());
     }
-
-    let git_repo = get_git_repo(repo.store())?;
-    git::push_updates(&git_repo, &args.remote, &ref_updates)
-        .map_err(|err| CommandError::UserError(err.to_string()))?;
-    git::import_refs(tx.mut_repo(), &git_repo)?;
-    workspace_command.finish_transaction(ui, tx)?;
+    let mut browse_command = match ui.settings().config().get::<Vec<String>>("browse.command") {
+        Ok(browse_command) if !browse_command.is_empty() => browse_command,
+        Ok(_) => {
+            return Err(CommandError::ConfigError(
+                "browse.command must not be empty".to_string(),
+            ))
+        }
+        Err(config::ConfigError::NotFound(_)) if cfg!(target_os = "macos") => {
+            vec!["open".to_string()]
+        }
+        Err(config::ConfigError::NotFound(_)) if cfg!(windows) => {
+            vec!["explorer".to_string()]
+        }
+        Err(config::ConfigError::NotFound(_)) => vec!["xdg-open".to_string()],
+        Err(err) => return Err(CommandError::ConfigError(err.to_string())),
+    };
+    let program = browse_command.remove(0);
+    let status = std::process::Command::new(&program)
+        .args(&browse_command)
+        .arg(&url)
+        .status()
+        .map_err(|err| CommandError::UserError(format!("Failed to run {program}: {err}")))?;
+    // Explorer exits with 1 even when it opened the URL
+    if !status.success() && !cfg!(windows) {
+        return Err(CommandError::UserError(format!(
+            "Failed to open {url}: {program} exited with {status}"
+        )));
+    }
     Ok(())
 }
 
-fn branch_updates_for_push(
-    repo: RepoRef,
-    remote_name: &str,
-    branch_name: &str,
-) -> Result<Option<BranchPushUpdate>, CommandError> {
-    let maybe_branch_target = repo.view().get_branch(branch_name);
-    let branch_target = maybe_branch_target
-        .ok_or_else(|| CommandError::UserError(format!("Branch {} doesn't exist", branch_name)))?;
-    let push_action = classify_branch_push_action(branch_target, remote_name);
+fn cmd_send(ui: &mut Ui, command: &CommandHelper, args: &SendArgs) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let mut commits = workspace_command.resolve_revset(&args.revisions)?;
+    workspace_command.check_non_empty(&commits)?;
+    // Send the oldest patch first
+    commits.reverse();
+    for commit in &commits {
+        if commit.parents().len() > 1 {
+            return Err(UserError(format!(
+                "Won't send commit {} since it's a merge",
+                short_commit_hash(commit.id())
+            )));
+        }
+        if commit.description().trim().is_empty() {
+            return Err(UserError(format!(
+                "Won't send commit {} since it has no description",
+                short_commit_hash(commit.id())
+            )));
+        }
+    }
 
-    match push_action {
-        BranchPushAction::AlreadyMatches => Ok(None),
-        BranchPushAction::LocalConflicted => Err(CommandError::UserError(format!(
-            "Branch {} is conflicted",
-            branch_name
-        ))),
-        BranchPushAction::RemoteConflicted => Err(CommandError::UserError(format!(
-            "Branch {}@{} is conflicted",
-            branch_name, remote_name
-        ))),
-        BranchPushAction::Update(update) => Ok(Some(update)),
+    let settings = ui.settings();
+    let from = format!("{} <{}>", settings.user_name(), settings.user_email());
+    let domain = email::bare_address(&from)
+        .rsplit_once('@')
+        .map_or("localhost", |(_, domain)| domain)
+        .to_string();
+    let now = settings.current_timestamp();
+    let date_format = "%a, %d %b %Y %H:%M:%S %z";
+    let num_patches = commits.len();
+    let numbered = num_patches > 1 || args.cover_letter;
+    let subjects = commits
+        .iter()
+        .enumerate()
+        .map(|(i, commit)| {
+            let summary = commit.description().trim_start().lines().next().unwrap();
+            if numbered {
+                format!("[PATCH {}/{num_patches}] {summary}", i + 1)
+            } else {
+                format!("[PATCH] {summary}")
+            }
+        })
+        .collect_vec();
+    // The random part keeps the IDs unique when several series are sent within
+    // the same millisecond
+    let message_id_token: u64 = rand::random();
+    let message_id = |index: usize| {
+        format!(
+            "<jj-{}.{message_id_token:016x}-{index}@{domain}>",
+            now.timestamp.0
+        )
+    };
+    let headers = |subject: String, date: &Timestamp, index: usize| {
+        let mut headers = vec![("From".to_string(), from.clone())];
+        headers.push(("To".to_string(), args.to.join(", ")));
+        if !args.cc.is_empty() {
+            headers.push(("Cc".to_string(), args.cc.join(", ")));
+        }
+        headers.push(("Subject".to_string(), subject));
+        headers.push((
+            "Date".to_string(),
+            templater::format_timestamp_with(date, date_format),
+        ));
+        headers.push(("Message-Id".to_string(), message_id(index)));
+        if index > 0 {
+            let thread_root = message_id(0);
+            headers.push(("In-Reply-To".to_string(), thread_root.clone()));
+            headers.push(("References".to_string(), thread_root));
+        }
+        headers
+    };
+
+    let mut emails = vec![];
+    if args.cover_letter {
+        let mut template = String::from("\n\n");
+        template.push_str(
+            "JJ: Enter the subject of the cover letter on the first line and its body\n\
+             JJ: below. Leave it empty to abort. The patches are:\n",
+        );
+        for subject in &subjects {
+            template.push_str(&format!("JJ:   {subject}\n"));
+        }
+        let text = edit_text(ui, workspace_command.repo(), &template)?;
+        let text = strip_description_comments(&text);
+        let text = text.trim_start();
+        if text.is_empty() {
+            return Err(UserError(
+                "Aborted because the cover letter is empty".to_string(),
+            ));
+        }
+        let (subject, body) = text.split_once('\n').unwrap_or((text, ""));
+        let mut body = body.trim_start_matches('\n').to_string();
+        if !body.is_empty() && !body.ends_with('\n') {
+            body.push('\n');
+        }
+        if !body.is_empty() {
+            body.push('\n');
+        }
+        for subject in &subjects {
+            body.push_str(&format!("{subject}\n"));
+        }
+        emails.push((
+            CommitId::new(vec![0; 20]),
+            email::Email {
+                headers: headers(format!("[PATCH 0/{num_patches}] {subject}"), &now, 0),
+                body,
+            },
+        ));
+    }
+    for (commit, subject) in commits.iter().zip_eq(subjects) {
+        let index = emails.len();
+        let mut body = String::new();
+        if commit.author().email != settings.user_email()
+            || commit.author().name != settings.user_name()
+        {
+            body.push_str(&format!(
+                "From: {} <{}>\n\n",
+                commit.author().name,
+                commit.author().email
+            ));
+        }
+        let description = add_co_author_trailers(commit.description(), commit.co_authors());
+        if let Some((_, rest)) = description.trim_start().split_once('\n') {
+            let rest = rest.trim();
+            if !rest.is_empty() {
+                body.push_str(rest);
+                body.push_str("\n\n");
+            }
+        }
+        body.push_str("---\n");
+        let parent_tree =
+            merge_commit_trees(workspace_command.repo().as_repo_ref(), &commit.parents());
+        let mut diff = vec![];
+        {
+            let mut formatter = PlainTextFormatter::new(Box::new(&mut diff));
+            show_git_diff(
+                &mut formatter,
+                &workspace_command,
+                parent_tree.diff(&commit.tree(), &EverythingMatcher),
+                ConflictStyle::Materialized,
+            )?;
+        }
+        body.push_str(&String::from_utf8_lossy(&diff));
+        emails.push((
+            commit.id().clone(),
+            email::Email {
+                headers: headers(subject, &commit.author().timestamp, index),
+                body,
+            },
+        ));
+    }
+
+    if args.dry_run {
+        for (commit_id, email) in &emails {
+            writeln!(ui, "From {} Mon Sep 17 00:00:00 2001", commit_id.hex())?;
+            ui.write(&email.to_message())?;
+            writeln!(ui)?;
+        }
+        return Ok(());
     }
+    let transport = email::MailTransport::from_settings(settings)?;
+    let recipients = args.to.iter().chain(&args.cc).cloned().collect_vec();
+    for (_, email) in &emails {
+        transport.send(&from, &recipients, email)?;
+        writeln!(ui, "Sent {}", email.header("Subject").unwrap())?;
+    }
+    Ok(())
 }
 
-fn cmd_git_import(
-    ui: &mut Ui,
-    command: &CommandHelper,
-    _args: &GitImportArgs,
-) -> Result<(), CommandError> {
+fn cmd_import(ui: &mut Ui, command: &CommandHelper, args: &ImportArgs) -> Result<(), CommandError> {
     let mut workspace_command = command.workspace_helper(ui)?;
-    let repo = workspace_command.repo();
-    let git_repo = get_git_repo(repo.store())?;
-    let mut tx = workspace_command.start_transaction("import git refs");
-    git::import_refs(tx.mut_repo(), &git_repo)?;
+    let hg_path = ui.cwd().join(&args.from_hg);
+    let hg_repo = HgRepo::open(&hg_path)?;
+    let repo_path = workspace_command.repo().repo_path().clone();
+    let mut state = HgImportState::load(&repo_path)?;
+    let changesets = hg_repo.changesets(&state)?;
+    let mut tx =
+        workspace_command.start_transaction(&format!("import hg repo at {}", hg_path.display()));
+    let stats = hg::import_changesets(ui.settings(), tx.mut_repo(), &mut state, &changesets)?;
+    if stats.imported_changesets == 0 && stats.updated_branches == 0 {
+        ui.write("Nothing changed.\n")?;
+        return Ok(());
+    }
     workspace_command.finish_transaction(ui, tx)?;
+    state.save(&repo_path)?;
+    writeln!(
+        ui,
+        "Imported {} changesets and updated {} branches",
+        stats.imported_changesets, stats.updated_branches
+    )?;
     Ok(())
 }
 
-fn cmd_git_export(
+fn cmd_git_fast_export(
     ui: &mut Ui,
     command: &CommandHelper,
-    _args: &GitExportArgs,
+    args: &GitFastExportArgs,
 ) -> Result<(), CommandError> {
     let workspace_command = command.workspace_helper(ui)?;
+    let commits = workspace_command.resolve_revset(&args.revisions)?;
     let repo = workspace_command.repo();
-    let git_repo = get_git_repo(repo.store())?;
-    git::export_refs(repo, &git_repo)?;
+    fast_import::export_commits(repo.as_repo_ref(), &commits, ui.stdout_formatter().as_mut())?;
+    Ok(())
+}
+
+fn cmd_git_fast_import(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &GitFastImportArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let mut input: Box<dyn BufRead> = match &args.file {
+        Some(path) => Box::new(io::BufReader::new(fs::File::open(ui.cwd().join(path))?)),
+        None => Box::new(io::BufReader::new(io::stdin())),
+    };
+    let mut tx = workspace_command.start_transaction("import fast-import stream");
+    let stats = fast_import::import_stream(ui.settings(), tx.mut_repo(), &mut input)?;
+    workspace_command.finish_transaction(ui, tx)?;
+    writeln!(
+        ui,
+        "Imported {} commits, updated {} branches and {} tags",
+        stats.imported_commits, stats.updated_branches, stats.updated_tags
+    )?;
     Ok(())
 }
 
@@ -4512,12 +10006,30 @@ fn cmd_git(
         GitCommands::Remote(GitRemoteCommands::Remove(command_matches)) => {
             cmd_git_remote_remove(ui, command, command_matches)
         }
+        GitCommands::Remote(GitRemoteCommands::Rename(command_matches)) => {
+            cmd_git_remote_rename(ui, command, command_matches)
+        }
+        GitCommands::Remote(GitRemoteCommands::SetUrl(command_matches)) => {
+            cmd_git_remote_set_url(ui, command, command_matches)
+        }
         GitCommands::Remote(GitRemoteCommands::List(command_matches)) => {
             cmd_git_remote_list(ui, command, command_matches)
         }
         GitCommands::Push(command_matches) => cmd_git_push(ui, command, command_matches),
         GitCommands::Import(command_matches) => cmd_git_import(ui, command, command_matches),
         GitCommands::Export(command_matches) => cmd_git_export(ui, command, command_matches),
+        GitCommands::FastExport(command_matches) => {
+            cmd_git_fast_export(ui, command, command_matches)
+        }
+        GitCommands::FastImport(command_matches) => {
+            cmd_git_fast_import(ui, command, command_matches)
+        }
+        GitCommands::Notes(GitNotesCommands::Add(command_matches)) => {
+            cmd_git_notes_add(ui, command, command_matches)
+        }
+        GitCommands::Notes(GitNotesCommands::Remove(command_matches)) => {
+            cmd_git_notes_remove(ui, command, command_matches)
+        }
     }
 }
 
@@ -4532,24 +10044,33 @@ pub fn run_command(
     matches: &ArgMatches,
 ) -> Result<(), CommandError> {
     let derived_subcommands: Commands = Commands::from_arg_matches(matches).unwrap();
-    match &derived_subcommands {
+    let result = match &derived_subcommands {
         Commands::Version(sub_args) => cmd_version(ui, command_helper, sub_args),
         Commands::Init(sub_args) => cmd_init(ui, command_helper, sub_args),
         Commands::Checkout(sub_args) => cmd_checkout(ui, command_helper, sub_args),
         Commands::Untrack(sub_args) => cmd_untrack(ui, command_helper, sub_args),
+        Commands::Mv(sub_args) => cmd_mv(ui, command_helper, sub_args),
+        Commands::Cp(sub_args) => cmd_cp(ui, command_helper, sub_args),
+        Commands::Snapshot(sub_args) => cmd_snapshot(ui, command_helper, sub_args),
         Commands::Files(sub_args) => cmd_files(ui, command_helper, sub_args),
         Commands::Print(sub_args) => cmd_print(ui, command_helper, sub_args),
+        Commands::Archive(sub_args) => cmd_archive(ui, command_helper, sub_args),
         Commands::Diff(sub_args) => cmd_diff(ui, command_helper, sub_args),
         Commands::Show(sub_args) => cmd_show(ui, command_helper, sub_args),
         Commands::Status(sub_args) => cmd_status(ui, command_helper, sub_args),
         Commands::Log(sub_args) => cmd_log(ui, command_helper, sub_args),
         Commands::Interdiff(sub_args) => cmd_interdiff(ui, command_helper, sub_args),
+        Commands::Summary(sub_args) => cmd_summary(ui, command_helper, sub_args),
         Commands::Obslog(sub_args) => cmd_obslog(ui, command_helper, sub_args),
         Commands::Describe(sub_args) => cmd_describe(ui, command_helper, sub_args),
         Commands::Close(sub_args) => cmd_close(ui, command_helper, sub_args),
         Commands::Open(sub_args) => cmd_open(ui, command_helper, sub_args),
         Commands::Duplicate(sub_args) => cmd_duplicate(ui, command_helper, sub_args),
         Commands::Abandon(sub_args) => cmd_abandon(ui, command_helper, sub_args),
+        Commands::Unhide(sub_args) => cmd_unhide(ui, command_helper, sub_args),
+        Commands::ResolveDivergence(sub_args) => {
+            cmd_resolve_divergence(ui, command_helper, sub_args)
+        }
         Commands::Edit(sub_args) => cmd_edit(ui, command_helper, sub_args),
         Commands::New(sub_args) => cmd_new(ui, command_helper, sub_args),
         Commands::Move(sub_args) => cmd_move(ui, command_helper, sub_args),
@@ -4562,14 +10083,101 @@ pub fn run_command(
         Commands::Rebase(sub_args) => cmd_rebase(ui, command_helper, sub_args),
         Commands::Backout(sub_args) => cmd_backout(ui, command_helper, sub_args),
         Commands::Branch(sub_args) => cmd_branch(ui, command_helper, sub_args),
+        Commands::Name(sub_args) => cmd_name(ui, command_helper, sub_args),
+        Commands::Topic(sub_args) => cmd_topic(ui, command_helper, sub_args),
         Commands::Undo(sub_args) => cmd_op_undo(ui, command_helper, sub_args),
         Commands::Operation(sub_args) => cmd_operation(ui, command_helper, sub_args),
         Commands::Workspace(sub_args) => cmd_workspace(ui, command_helper, sub_args),
         Commands::Sparse(sub_args) => cmd_sparse(ui, command_helper, sub_args),
+        Commands::Run(sub_args) => cmd_run(ui, command_helper, sub_args),
+        Commands::Fix(sub_args) => cmd_fix(ui, command_helper, sub_args),
+        Commands::Rewrite(sub_args) => cmd_rewrite(ui, command_helper, sub_args),
+        Commands::Verify(sub_args) => cmd_verify(ui, command_helper, sub_args),
+        Commands::Stats(sub_args) => cmd_stats(ui, command_helper, sub_args),
+        Commands::Send(sub_args) => cmd_send(ui, command_helper, sub_args),
+        Commands::Import(sub_args) => cmd_import(ui, command_helper, sub_args),
         Commands::Git(sub_args) => cmd_git(ui, command_helper, sub_args),
+        Commands::Gerrit(GerritCommands::Upload(sub_args)) => {
+            cmd_gerrit_upload(ui, command_helper, sub_args)
+        }
+        Commands::Pr(PrCommands::Create(sub_args)) => cmd_pr_create(ui, command_helper, sub_args),
+        Commands::Pr(PrCommands::Status(sub_args)) => cmd_pr_status(ui, command_helper, sub_args),
+        Commands::Browse(sub_args) => cmd_browse(ui, command_helper, sub_args),
+        Commands::Api(sub_args) => cmd_api(ui, command_helper, sub_args),
         Commands::Bench(sub_args) => cmd_bench(ui, command_helper, sub_args),
         Commands::Debug(sub_args) => cmd_debug(ui, command_helper, sub_args),
+    };
+    if ui
+        .settings()
+        .config()
+        .get_bool("debug.cache-stats")
+        .unwrap_or(false)
+    {
+        if let Some(store) = command_helper.loaded_store() {
+            // The stats are only diagnostics, so failing to write them
+            // shouldn't replace the command's result
+            if let Err(err) = write_store_cache_stats(ui, &store.cache_stats()) {
+                ui.write_warn(format!("Failed to write the store cache stats: {err}\n"))
+                    .ok();
+            }
+        }
+    }
+    if ui
+        .settings()
+        .config()
+        .get_bool("debug.fetch-stats")
+        .unwrap_or(false)
+    {
+        if let Some(store) = command_helper.loaded_store() {
+            if let Err(err) = write_store_fetch_stats(ui, &store.fetch_stats()) {
+                ui.write_warn(format!("Failed to write the store fetch stats: {err}\n"))
+                    .ok();
+            }
+        }
     }
+    result
+}
+
+fn write_store_fetch_stats(ui: &mut Ui, stats: &StoreFetchStats) -> io::Result<()> {
+    let mut formatter = ui.stderr_formatter();
+    writeln!(formatter, "Store fetch stats ({} backend):", stats.backend)?;
+    if stats.objects.is_empty() {
+        writeln!(formatter, "  no objects fetched")?;
+    }
+    for (kind, stats) in &stats.objects {
+        writeln!(
+            formatter,
+            "  {}s: {} fetches taking {:.3?} in total ({:.3?} on average, {:.3?} at most)",
+            kind.name(),
+            stats.count,
+            stats.total_time,
+            stats.average_time().unwrap_or_default(),
+            stats.max_time
+        )?;
+    }
+    Ok(())
+}
+
+fn write_store_cache_stats(ui: &mut Ui, stats: &StoreCacheStats) -> io::Result<()> {
+    let mut formatter = ui.stderr_formatter();
+    writeln!(formatter, "Store cache stats:")?;
+    for (name, stats) in [
+        ("commits", &stats.commits),
+        ("trees", &stats.trees),
+        ("files", &stats.files),
+    ] {
+        let hit_rate = match stats.hit_rate() {
+            Some(hit_rate) => format!("{:.1}%", hit_rate * 100.0),
+            None => "n/a".to_string(),
+        };
+        writeln!(
+            formatter,
+            "  {name}: {} hits, {} misses ({hit_rate} hit rate), {} evictions, {} entries using \
+             {} of {} bytes",
+            stats.hits, stats.misses, stats.evictions, stats.entries, stats.weight, stats.capacity
+        )?;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
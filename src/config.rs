@@ -84,6 +84,18 @@ fn env_overrides() -> config::Config {
     builder.build().unwrap()
 }
 
+/// Settings from the Git config of a Git-backed repo that apply unless our
+/// config sets them, so they don't have to be configured twice.
+pub fn git_config_fallback(git_config: &git2::Config) -> config::Config {
+    let mut builder = config::Config::builder();
+    for key in ["user.name", "user.email"] {
+        if let Ok(value) = git_config.get_string(key) {
+            builder = builder.set_override(key, value).unwrap();
+        }
+    }
+    builder.build().unwrap()
+}
+
 pub fn read_config() -> Result<UserSettings, ConfigError> {
     let mut config_builder = config::Config::builder().add_source(env_base());
 
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::ffi::{OsStr, OsString};
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
@@ -22,7 +23,7 @@ use config::ConfigError;
 use itertools::Itertools;
 use jujutsu_lib::backend::TreeId;
 use jujutsu_lib::gitignore::GitIgnoreFile;
-use jujutsu_lib::matchers::EverythingMatcher;
+use jujutsu_lib::matchers::Matcher;
 use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::settings::UserSettings;
 use jujutsu_lib::store::Store;
@@ -102,13 +103,18 @@ pub fn edit_diff(
     left_tree: &Tree,
     right_tree: &Tree,
     instructions: &str,
+    matcher: &dyn Matcher,
     base_ignores: Arc<GitIgnoreFile>,
 ) -> Result<TreeId, DiffEditError> {
     let store = left_tree.store();
     let changed_files = left_tree
-        .diff(right_tree, &EverythingMatcher)
+        .diff(right_tree, matcher)
         .map(|(path, _value)| path)
         .collect_vec();
+    let changed_file_strings = changed_files
+        .iter()
+        .map(|path| path.to_internal_file_string())
+        .collect_vec();
 
     // Check out the two trees in temporary directories. Only include changed files
     // in the sparse checkout patterns.
@@ -147,24 +153,31 @@ pub fn edit_diff(
 
     // TODO: Make this configuration have a table of possible editors and detect the
     // best one here.
-    let editor_name = match settings.config().get_string("ui.diff-editor") {
-        Ok(editor_binary) => editor_binary,
-        Err(_) => {
-            let default_editor = "meld".to_string();
+    let editor = match settings.config().get::<config::Value>("ui.diff-editor") {
+        Ok(value) => {
+            if let config::ValueKind::String(editor_name) = &value.kind {
+                get_tool(settings, editor_name)?
+            } else {
+                MergeTool::from_command(value.try_deserialize()?).ok_or_else(|| {
+                    ConfigError::Message("ui.diff-editor must not be empty".to_string())
+                })?
+            }
+        }
+        Err(ConfigError::NotFound(_)) => {
+            let default_editor = "meld";
             ui.write_hint(format!(
                 "Using default editor '{}'; you can change this by setting ui.diff-editor\n",
                 default_editor
             ))
             .map_err(DiffEditError::IoError)?;
-            default_editor
+            get_tool(settings, default_editor)?
         }
+        Err(err) => return Err(err.into()),
     };
-    let editor = get_tool(settings, &editor_name)?;
     // Start a diff editor on the two directories.
+    let args = editor.expand_edit_args(&left_wc_dir, &right_wc_dir, &changed_file_strings);
     let exit_status = Command::new(&editor.program)
-        .args(&editor.edit_args)
-        .arg(&left_wc_dir)
-        .arg(&right_wc_dir)
+        .args(&args)
         .status()
         .map_err(|e| DiffEditError::ExecuteEditorError {
             editor_binary: editor.program,
@@ -187,7 +200,11 @@ pub fn edit_diff(
 struct MergeTool {
     /// Program to execute.
     pub program: String,
-    /// Arguments to pass to the program when editing diffs.
+    /// Arguments to pass to the program when editing diffs. `$left` and
+    /// `$right` are replaced by the directories to diff, and an argument that's
+    /// just `$files` by the paths of the changed files relative to them. If
+    /// neither `$left` nor `$right` is used, the two directories are passed
+    /// after the arguments.
     #[serde(default)]
     pub edit_args: Vec<String>,
 }
@@ -199,6 +216,57 @@ impl MergeTool {
             edit_args: vec![],
         }
     }
+
+    /// A tool given as a program followed by its arguments
+    fn from_command(mut command: Vec<String>) -> Option<Self> {
+        if command.is_empty() {
+            return None;
+        }
+        let program = command.remove(0);
+        Some(MergeTool {
+            program,
+            edit_args: command,
+        })
+    }
+
+    fn expand_edit_args(&self, left: &Path, right: &Path, files: &[String]) -> Vec<OsString> {
+        let vars = [("$left", left.as_os_str()), ("$right", right.as_os_str())];
+        let mut args = vec![];
+        for arg in &self.edit_args {
+            if arg == "$files" {
+                args.extend(files.iter().map(OsString::from));
+            } else {
+                args.push(interpolate_paths(arg, &vars));
+            }
+        }
+        if !self
+            .edit_args
+            .iter()
+            .any(|arg| arg.contains("$left") || arg.contains("$right"))
+        {
+            args.push(left.into());
+            args.push(right.into());
+        }
+        args
+    }
+}
+
+/// Replaces each of the `vars` names in `arg` by its path. The paths don't
+/// have to be valid UTF-8.
+pub fn interpolate_paths(arg: &str, vars: &[(&str, &OsStr)]) -> OsString {
+    match vars.split_first() {
+        None => arg.into(),
+        Some(((name, path), rest)) => {
+            let mut result = OsString::new();
+            for (i, part) in arg.split(name).enumerate() {
+                if i > 0 {
+                    result.push(path);
+                }
+                result.push(interpolate_paths(part, rest));
+            }
+            result
+        }
+    }
 }
 
 /// Loads merge tool options from `[merge-tools.<name>]`. The given name is used
@@ -219,3 +287,47 @@ fn get_tool(settings: &UserSettings, name: &str) -> Result<MergeTool, ConfigErro
         Ok(MergeTool::with_program(name))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(args: &[&str]) -> MergeTool {
+        MergeTool::from_command(args.iter().map(|arg| arg.to_string()).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_expand_edit_args() {
+        let left = Path::new("/tmp/left");
+        let right = Path::new("/tmp/right");
+        let files = ["a".to_string(), "dir/b".to_string()];
+
+        // The directories are appended if they're not referenced
+        assert_eq!(
+            tool(&["meld", "--newtab"]).expand_edit_args(left, right, &files),
+            vec!["--newtab", "/tmp/left", "/tmp/right"]
+        );
+        assert_eq!(
+            tool(&["tool", "--left=$left", "$right", "--", "$files"])
+                .expand_edit_args(left, right, &files),
+            vec!["--left=/tmp/left", "/tmp/right", "--", "a", "dir/b"]
+        );
+        assert!(MergeTool::from_command(vec![]).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_expand_edit_args_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let left = Path::new(OsStr::from_bytes(b"/tmp/left\xff"));
+        let right = Path::new("/tmp/right");
+        assert_eq!(
+            tool(&["tool", "--left=$left", "$right"]).expand_edit_args(left, right, &[]),
+            vec![
+                OsStr::from_bytes(b"--left=/tmp/left\xff"),
+                OsStr::new("/tmp/right")
+            ]
+        );
+    }
+}
@@ -0,0 +1,140 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Helpers for rendering diffs that are shared by commands and templates.
+
+use std::io::Read;
+
+use jujutsu_lib::backend::{ConflictId, TreeValue};
+use jujutsu_lib::conflicts;
+use jujutsu_lib::diff::{self, Diff, DiffHunk};
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::store::Store;
+use jujutsu_lib::tree::{self, TreeDiffIterator};
+
+use crate::cli_util::CommandError;
+
+/// How the contents of conflicted files are shown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictStyle {
+    /// With conflict markers, the way they're written to the working copy.
+    Materialized,
+    /// Each side and base of the conflict in full.
+    Sides,
+}
+
+/// The content of a conflicted file, rendered in the given style.
+pub fn conflict_content(
+    store: &Store,
+    path: &RepoPath,
+    id: &ConflictId,
+    style: ConflictStyle,
+) -> Result<Vec<u8>, CommandError> {
+    let conflict = store.read_conflict(path, id)?;
+    let mut content = vec![];
+    match style {
+        ConflictStyle::Materialized => {
+            conflicts::materialize_conflict(store, path, &conflict, &mut content)?
+        }
+        ConflictStyle::Sides => {
+            conflicts::write_conflict_sides(store, path, &conflict, &mut content)?
+        }
+    }
+    Ok(content)
+}
+
+/// The content of a file as shown in diffs. Conflicts are rendered in the given
+/// style and symlinks are shown as their target.
+pub fn diff_content(
+    store: &Store,
+    path: &RepoPath,
+    value: &TreeValue,
+    conflict_style: ConflictStyle,
+) -> Result<Vec<u8>, CommandError> {
+    match value {
+        TreeValue::Normal { id, .. } => {
+            let mut file_reader = store.read_file(path, id).unwrap();
+            let mut content = vec![];
+            file_reader.read_to_end(&mut content)?;
+            Ok(content)
+        }
+        TreeValue::Symlink(id) => {
+            let target = store.read_symlink(path, id)?;
+            Ok(target.into_bytes())
+        }
+        TreeValue::Tree(_) => {
+            panic!(
+                "Got an unexpected tree in a diff of path {}",
+                path.to_internal_file_string()
+            );
+        }
+        TreeValue::GitSubmodule(id) => {
+            Ok(format!("Git submodule checked out at {}", id.hex()).into_bytes())
+        }
+        TreeValue::Conflict(id) => conflict_content(store, path, id, conflict_style),
+    }
+}
+
+/// The number of lines added and removed in a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileDiffStat {
+    pub path: RepoPath,
+    pub added: usize,
+    pub removed: usize,
+}
+
+/// Counts the lines added and removed between two versions of a file.
+pub fn count_changed_lines(left_content: &[u8], right_content: &[u8]) -> (usize, usize) {
+    let mut added = 0;
+    let mut removed = 0;
+    let diff = Diff::for_tokenizer(&[left_content, right_content], &diff::find_line_ranges);
+    for hunk in diff.hunks() {
+        if let DiffHunk::Different(contents) = hunk {
+            removed += contents[0].split_inclusive(|b| *b == b'\n').count();
+            added += contents[1].split_inclusive(|b| *b == b'\n').count();
+        }
+    }
+    (added, removed)
+}
+
+/// Counts the lines added and removed in each file in the diff.
+pub fn diff_stats(
+    store: &Store,
+    tree_diff: TreeDiffIterator,
+) -> Result<Vec<FileDiffStat>, CommandError> {
+    let mut stats = vec![];
+    for (path, diff) in tree_diff {
+        let (left_content, right_content) = match diff {
+            tree::Diff::Added(right_value) => (
+                vec![],
+                diff_content(store, &path, &right_value, ConflictStyle::Materialized)?,
+            ),
+            tree::Diff::Modified(left_value, right_value) => (
+                diff_content(store, &path, &left_value, ConflictStyle::Materialized)?,
+                diff_content(store, &path, &right_value, ConflictStyle::Materialized)?,
+            ),
+            tree::Diff::Removed(left_value) => (
+                diff_content(store, &path, &left_value, ConflictStyle::Materialized)?,
+                vec![],
+            ),
+        };
+        let (added, removed) = count_changed_lines(&left_content, &right_content);
+        stats.push(FileDiffStat {
+            path,
+            added,
+            removed,
+        });
+    }
+    Ok(stats)
+}
@@ -0,0 +1,378 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sending patches by email, like `git send-email`.
+//!
+//! Emails are either piped to a sendmail-compatible command (`sendmail -i` by
+//! default, configured by `send.sendmail-command`) or sent to the SMTP server
+//! configured in `send.smtp-server`.
+
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+
+use jujutsu_lib::settings::UserSettings;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum EmailError {
+    #[error("Invalid email config: {0}")]
+    InvalidConfig(String),
+    #[error("Failed to run {command}: {err}")]
+    SendmailIoError { command: String, err: io::Error },
+    #[error("{command} failed{}", format_stderr(.stderr))]
+    SendmailFailed { command: String, stderr: String },
+    #[error("Failed to talk to SMTP server {server}: {err}")]
+    SmtpIoError { server: String, err: io::Error },
+    #[error("SMTP server {server} replied: {reply}")]
+    SmtpRejected { server: String, reply: String },
+    #[error(
+        "Refusing to log in to SMTP server {server} over an unencrypted connection (only servers \
+         on the local machine are supported)"
+    )]
+    SmtpInsecureAuth { server: String },
+}
+
+fn format_stderr(stderr: &str) -> String {
+    let stderr = stderr.trim_end();
+    if stderr.is_empty() {
+        String::new()
+    } else {
+        format!(":\n{stderr}")
+    }
+}
+
+/// An email with headers in the order they should be written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Email {
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Email {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Formats the email as a message with `\n` line endings. Header values
+    /// with non-ASCII characters are encoded as described in RFC 2047.
+    pub fn to_message(&self) -> String {
+        let mut message = String::new();
+        for (name, value) in &self.headers {
+            message.push_str(&format!("{name}: {}\n", encode_header_value(value)));
+        }
+        message.push_str("MIME-Version: 1.0\n");
+        message.push_str("Content-Type: text/plain; charset=UTF-8\n");
+        message.push_str("Content-Transfer-Encoding: 8bit\n");
+        message.push('\n');
+        message.push_str(&self.body);
+        if !self.body.ends_with('\n') {
+            message.push('\n');
+        }
+        message
+    }
+}
+
+fn encode_header_value(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+    // Only encode the display name of addresses, since the address itself
+    // must stay readable
+    if let Some((name, address)) = value.rsplit_once(" <") {
+        if address.is_ascii() {
+            return format!("{} <{address}", encode_word(name));
+        }
+    }
+    encode_word(value)
+}
+
+fn encode_word(value: &str) -> String {
+    format!("=?UTF-8?B?{}?=", base64_encode(value.as_bytes()))
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut result = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                result.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+/// Extracts the address from e.g. "Some One <some.one@example.com>".
+pub fn bare_address(address: &str) -> &str {
+    match address.rsplit_once('<') {
+        Some((_, rest)) => rest.trim_end().trim_end_matches('>'),
+        None => address.trim(),
+    }
+}
+
+/// How emails are sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MailTransport {
+    /// A sendmail-compatible command. The recipients are passed as
+    /// arguments and the message on stdin.
+    Sendmail(Vec<String>),
+    /// An SMTP server (`host:port`), optionally with credentials for `AUTH
+    /// PLAIN`. The connection is not encrypted, so this is meant for e.g. a
+    /// relay on the local machine. The credentials are only sent to servers
+    /// on the loopback interface.
+    Smtp {
+        server: String,
+        user: Option<String>,
+        password: Option<String>,
+    },
+}
+
+impl MailTransport {
+    pub fn from_settings(settings: &UserSettings) -> Result<Self, EmailError> {
+        let config = settings.config();
+        let invalid = |err: config::ConfigError| EmailError::InvalidConfig(err.to_string());
+        match config.get_string("send.smtp-server") {
+            Ok(server) => {
+                let optional_string = |key: &str| match config.get_string(key) {
+                    Ok(value) => Ok(Some(value)),
+                    Err(config::ConfigError::NotFound(_)) => Ok(None),
+                    Err(err) => Err(invalid(err)),
+                };
+                return Ok(MailTransport::Smtp {
+                    server,
+                    user: optional_string("send.smtp-user")?,
+                    password: optional_string("send.smtp-password")?,
+                });
+            }
+            Err(config::ConfigError::NotFound(_)) => {}
+            Err(err) => return Err(invalid(err)),
+        }
+        match config.get::<Vec<String>>("send.sendmail-command") {
+            Ok(command) if command.is_empty() => Err(EmailError::InvalidConfig(
+                "send.sendmail-command must not be empty".to_string(),
+            )),
+            Ok(command) => Ok(MailTransport::Sendmail(command)),
+            Err(config::ConfigError::NotFound(_)) => Ok(MailTransport::Sendmail(vec![
+                "sendmail".to_string(),
+                "-i".to_string(),
+            ])),
+            Err(err) => Err(invalid(err)),
+        }
+    }
+
+    pub fn send(&self, from: &str, recipients: &[String], email: &Email) -> Result<(), EmailError> {
+        let message = email.to_message();
+        match self {
+            MailTransport::Sendmail(command) => send_with_command(command, recipients, &message),
+            MailTransport::Smtp {
+                server,
+                user,
+                password,
+            } => send_with_smtp(
+                server,
+                user.as_deref().zip(password.as_deref()),
+                from,
+                recipients,
+                &message,
+            ),
+        }
+    }
+}
+
+fn send_with_command(
+    command: &[String],
+    recipients: &[String],
+    message: &str,
+) -> Result<(), EmailError> {
+    let io_error = |err| EmailError::SendmailIoError {
+        command: command[0].clone(),
+        err,
+    };
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .args(recipients.iter().map(|recipient| bare_address(recipient)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(io_error)?;
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(message.as_bytes()).map_err(io_error)?;
+    drop(stdin);
+    let output = child.wait_with_output().map_err(io_error)?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(EmailError::SendmailFailed {
+            command: command[0].clone(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+}
+
+struct SmtpConnection {
+    server: String,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl SmtpConnection {
+    fn io_error(&self, err: io::Error) -> EmailError {
+        EmailError::SmtpIoError {
+            server: self.server.clone(),
+            err,
+        }
+    }
+
+    /// Reads a (possibly multi-line) reply and checks that its code starts with
+    /// `expected`.
+    fn expect_reply(&mut self, expected: char) -> Result<(), EmailError> {
+        let mut reply = String::new();
+        loop {
+            let mut line = String::new();
+            let num_read = self
+                .reader
+                .read_line(&mut line)
+                .map_err(|err| self.io_error(err))?;
+            if num_read == 0 {
+                return Err(self.io_error(io::ErrorKind::UnexpectedEof.into()));
+            }
+            reply.push_str(&line);
+            // The last line of a reply has a space after the code
+            if line.as_bytes().get(3) != Some(&b'-') {
+                break;
+            }
+        }
+        if reply.starts_with(expected) {
+            Ok(())
+        } else {
+            Err(EmailError::SmtpRejected {
+                server: self.server.clone(),
+                reply: reply.trim_end().to_string(),
+            })
+        }
+    }
+
+    fn command(&mut self, command: &str, expected: char) -> Result<(), EmailError> {
+        self.writer
+            .write_all(format!("{command}\r\n").as_bytes())
+            .map_err(|err| self.io_error(err))?;
+        self.expect_reply(expected)
+    }
+}
+
+fn send_with_smtp(
+    server: &str,
+    credentials: Option<(&str, &str)>,
+    from: &str,
+    recipients: &[String],
+    message: &str,
+) -> Result<(), EmailError> {
+    let stream = TcpStream::connect(server).map_err(|err| EmailError::SmtpIoError {
+        server: server.to_string(),
+        err,
+    })?;
+    // Don't send the password in cleartext over the network
+    if credentials.is_some()
+        && !stream
+            .peer_addr()
+            .map_or(false, |addr| addr.ip().is_loopback())
+    {
+        return Err(EmailError::SmtpInsecureAuth {
+            server: server.to_string(),
+        });
+    }
+    let mut connection = SmtpConnection {
+        server: server.to_string(),
+        reader: BufReader::new(stream.try_clone().map_err(|err| EmailError::SmtpIoError {
+            server: server.to_string(),
+            err,
+        })?),
+        writer: stream,
+    };
+    connection.expect_reply('2')?;
+    connection.command("EHLO localhost", '2')?;
+    if let Some((user, password)) = credentials {
+        let token = base64_encode(format!("\0{user}\0{password}").as_bytes());
+        connection.command(&format!("AUTH PLAIN {token}"), '2')?;
+    }
+    connection.command(&format!("MAIL FROM:<{}>", bare_address(from)), '2')?;
+    for recipient in recipients {
+        connection.command(&format!("RCPT TO:<{}>", bare_address(recipient)), '2')?;
+    }
+    connection.command("DATA", '3')?;
+    let mut data = String::new();
+    for line in message.lines() {
+        // Lines starting with a dot get an extra one, so they can't end the data
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(line);
+        data.push_str("\r\n");
+    }
+    data.push('.');
+    connection.command(&data, '2')?;
+    connection.command("QUIT", '2')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foob"), "Zm9vYg==");
+        assert_eq!(base64_encode(b"\0user\0pass"), "AHVzZXIAcGFzcw==");
+    }
+
+    #[test]
+    fn test_encode_header_value() {
+        assert_eq!(encode_header_value("[PATCH] Fix"), "[PATCH] Fix");
+        assert_eq!(
+            encode_header_value("Zoë <zoe@example.com>"),
+            "=?UTF-8?B?Wm/Dqw==?= <zoe@example.com>"
+        );
+        assert_eq!(
+            encode_header_value("[PATCH] Füx"),
+            "=?UTF-8?B?W1BBVENIXSBGw7x4?="
+        );
+    }
+
+    #[test]
+    fn test_bare_address() {
+        assert_eq!(bare_address("some.one@example.com"), "some.one@example.com");
+        assert_eq!(
+            bare_address("Some One <some.one@example.com>"),
+            "some.one@example.com"
+        );
+    }
+}
@@ -0,0 +1,220 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Notifications about changes to the working copy and to branches, so users
+//! can e.g. trigger builds or update IDE state when they happen.
+//!
+//! Each event is sent as a JSON object to the command configured in
+//! `events.command` (on its stdin) and/or to the Unix socket configured in
+//! `events.socket` (as a single line). If the command exits with an error for
+//! a "pre-" event, the action is aborted.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use jujutsu_lib::op_store::{BranchTarget, RefTarget, WorkspaceId};
+use jujutsu_lib::settings::UserSettings;
+use jujutsu_lib::view::View;
+use jujutsu_lib::working_copy::CheckoutStats;
+use serde_json::{json, Map, Value};
+
+use crate::cli_util::CommandError;
+use crate::ui::Ui;
+
+pub struct EventNotifier {
+    command: Option<Vec<String>>,
+    socket: Option<PathBuf>,
+    workspace_root: PathBuf,
+    workspace_id: WorkspaceId,
+}
+
+impl EventNotifier {
+    pub fn new(
+        settings: &UserSettings,
+        workspace_root: &Path,
+        workspace_id: &WorkspaceId,
+    ) -> Result<Self, CommandError> {
+        let command = match settings.config().get::<Vec<String>>("events.command") {
+            Ok(command) if command.is_empty() => {
+                return Err(CommandError::ConfigError(
+                    "events.command must not be empty".to_string(),
+                ));
+            }
+            Ok(command) => Some(command),
+            Err(config::ConfigError::NotFound(_)) => None,
+            Err(err) => return Err(err.into()),
+        };
+        let socket = match settings.config().get_string("events.socket") {
+            Ok(socket) => Some(PathBuf::from(socket)),
+            Err(config::ConfigError::NotFound(_)) => None,
+            Err(err) => return Err(err.into()),
+        };
+        Ok(EventNotifier {
+            command,
+            socket,
+            workspace_root: workspace_root.to_owned(),
+            workspace_id: workspace_id.clone(),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.command.is_some() || self.socket.is_some()
+    }
+
+    /// Sends an event about something that's about to happen. Returns an error
+    /// if the configured command rejected it.
+    pub fn notify_pre(
+        &self,
+        ui: &mut Ui,
+        event: &str,
+        payload: Map<String, Value>,
+    ) -> Result<(), CommandError> {
+        if !self.send(ui, event, payload)? {
+            return Err(CommandError::UserError(format!(
+                "The {event} event was rejected by the events.command hook"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Sends an event about something that has happened. Failures are only
+    /// reported as warnings, since it's too late to do anything about them.
+    pub fn notify(
+        &self,
+        ui: &mut Ui,
+        event: &str,
+        payload: Map<String, Value>,
+    ) -> Result<(), CommandError> {
+        if !self.send(ui, event, payload)? {
+            ui.write_warn(format!(
+                "The events.command hook failed for the {event} event\n"
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Sends `branch-moved` events for the local branches that differ between
+    /// the two views.
+    pub fn notify_branches_moved(
+        &self,
+        ui: &mut Ui,
+        old_view: &View,
+        new_view: &View,
+    ) -> Result<(), CommandError> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let local_target =
+            |target: Option<&BranchTarget>| target.and_then(|t| t.local_target.clone());
+        let names = old_view
+            .branches()
+            .keys()
+            .chain(new_view.branches().keys())
+            .collect::<std::collections::BTreeSet<_>>();
+        for name in names {
+            let old_target = local_target(old_view.branches().get(name));
+            let new_target = local_target(new_view.branches().get(name));
+            if old_target != new_target {
+                let mut payload = Map::new();
+                payload.insert("branch".to_string(), json!(name));
+                payload.insert("old_target".to_string(), ref_target_to_json(&old_target));
+                payload.insert("new_target".to_string(), ref_target_to_json(&new_target));
+                self.notify(ui, "branch-moved", payload)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `false` if the command exited with an error.
+    fn send(
+        &self,
+        ui: &mut Ui,
+        event: &str,
+        mut payload: Map<String, Value>,
+    ) -> Result<bool, CommandError> {
+        if !self.is_enabled() {
+            return Ok(true);
+        }
+        payload.insert("event".to_string(), json!(event));
+        payload.insert(
+            "workspace_root".to_string(),
+            json!(self.workspace_root.to_string_lossy()),
+        );
+        payload.insert(
+            "workspace_id".to_string(),
+            json!(self.workspace_id.as_str()),
+        );
+        let line = format!("{}\n", Value::Object(payload));
+        if let Some(socket) = &self.socket {
+            if let Err(err) = send_to_socket(socket, &line) {
+                ui.write_warn(format!(
+                    "Failed to send {event} event to {}: {err}\n",
+                    socket.display()
+                ))?;
+            }
+        }
+        match &self.command {
+            Some(command) => run_command(command, &line),
+            None => Ok(true),
+        }
+    }
+}
+
+fn run_command(command: &[String], line: &str) -> Result<bool, CommandError> {
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            CommandError::ConfigError(format!(
+                "Failed to run events.command '{}': {err}",
+                command.join(" ")
+            ))
+        })?;
+    // The command may exit without reading its input, so ignore errors here.
+    child.stdin.take().unwrap().write_all(line.as_bytes()).ok();
+    Ok(child.wait()?.success())
+}
+
+#[cfg(unix)]
+fn send_to_socket(socket: &Path, line: &str) -> std::io::Result<()> {
+    let mut stream = std::os::unix::net::UnixStream::connect(socket)?;
+    stream.write_all(line.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn send_to_socket(_socket: &Path, _line: &str) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "sockets are only supported on Unix",
+    ))
+}
+
+fn ref_target_to_json(target: &Option<RefTarget>) -> Value {
+    match target {
+        None => Value::Null,
+        Some(RefTarget::Normal(id)) => json!(id.hex()),
+        Some(RefTarget::Conflict { removes, adds }) => json!({
+            "removes": removes.iter().map(|id| id.hex()).collect::<Vec<_>>(),
+            "adds": adds.iter().map(|id| id.hex()).collect::<Vec<_>>(),
+        }),
+    }
+}
+
+pub fn checkout_stats_to_json(stats: &CheckoutStats, payload: &mut Map<String, Value>) {
+    payload.insert("added_files".to_string(), json!(stats.added_files));
+    payload.insert("updated_files".to_string(), json!(stats.updated_files));
+    payload.insert("removed_files".to_string(), json!(stats.removed_files));
+}
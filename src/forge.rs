@@ -0,0 +1,412 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Creating and inspecting pull requests on GitHub and merge requests on
+//! GitLab.
+//!
+//! The forge's REST API is called by running `curl`, which is available
+//! everywhere and takes care of TLS and proxies for us.
+
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use jujutsu_lib::settings::UserSettings;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ForgeError {
+    #[error("Invalid pull request config: {0}")]
+    InvalidConfig(String),
+    #[error("Failed to run curl: {0}")]
+    CurlIoError(#[from] io::Error),
+    #[error("curl failed{}", format_stderr(.0))]
+    CurlFailed(String),
+    #[error("{method} {url} failed with status {status}: {message}")]
+    ApiError {
+        method: &'static str,
+        url: String,
+        status: u32,
+        message: String,
+    },
+    #[error("Unexpected response from {url}: {message}")]
+    InvalidResponse { url: String, message: String },
+}
+
+fn format_stderr(stderr: &str) -> String {
+    let stderr = stderr.trim_end();
+    if stderr.is_empty() {
+        String::new()
+    } else {
+        format!(":\n{stderr}")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ForgeKind {
+    GitHub,
+    GitLab,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PullRequestState {
+    Open,
+    Merged,
+    Closed,
+}
+
+impl fmt::Display for PullRequestState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PullRequestState::Open => "open",
+            PullRequestState::Merged => "merged",
+            PullRequestState::Closed => "closed",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PullRequest {
+    /// The pull request number on GitHub, or the merge request IID on GitLab.
+    pub number: u64,
+    pub url: String,
+    pub state: PullRequestState,
+    pub base: String,
+}
+
+/// Splits a Git remote URL like `https://github.com/owner/name.git` or
+/// `git@github.com:owner/name.git` into the host and the repo path
+/// (`owner/name`).
+pub fn parse_remote_url(url: &str) -> Option<(String, String)> {
+    let (host, path) = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/')?,
+        // scp-like syntax
+        None => url.split_once(':')?,
+    };
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let host = host.split_once(':').map_or(host, |(host, _port)| host);
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    if host.is_empty() || !path.contains('/') {
+        return None;
+    }
+    Some((host.to_string(), path.to_string()))
+}
+
+/// A repo on a forge, and how to talk to its API.
+#[derive(Clone, Debug)]
+pub struct Forge {
+    pub kind: ForgeKind,
+    pub api_url: String,
+    /// The repo path, like `owner/name`.
+    pub repo: String,
+    pub token: Option<String>,
+}
+
+impl Forge {
+    /// Determines the forge from `pr.forge`, `pr.api-url`, `pr.repo` and
+    /// `pr.token` in the config. The settings that aren't set are derived from
+    /// the URL of the remote.
+    pub fn from_settings(settings: &UserSettings, remote_url: &str) -> Result<Self, ForgeError> {
+        let config = settings.config();
+        let optional_string = |key: &str| match config.get_string(key) {
+            Ok(value) => Ok(Some(value)),
+            Err(config::ConfigError::NotFound(_)) => Ok(None),
+            Err(err) => Err(ForgeError::InvalidConfig(err.to_string())),
+        };
+        let parsed_url = parse_remote_url(remote_url);
+        let host = parsed_url.as_ref().map(|(host, _)| host.as_str());
+        let kind = match optional_string("pr.forge")?.as_deref() {
+            Some("github") => ForgeKind::GitHub,
+            Some("gitlab") => ForgeKind::GitLab,
+            Some(other) => {
+                return Err(ForgeError::InvalidConfig(format!(
+                    "pr.forge must be \"github\" or \"gitlab\", not \"{other}\""
+                )))
+            }
+            None => match host {
+                Some(host) if host.contains("gitlab") => ForgeKind::GitLab,
+                Some(host) if host.contains("github") => ForgeKind::GitHub,
+                _ => {
+                    return Err(ForgeError::InvalidConfig(format!(
+                        "Can't tell the forge from the remote URL {remote_url}; set pr.forge"
+                    )))
+                }
+            },
+        };
+        let repo = match optional_string("pr.repo")? {
+            Some(repo) => repo,
+            None => match &parsed_url {
+                Some((_, path)) => path.clone(),
+                None => {
+                    return Err(ForgeError::InvalidConfig(format!(
+                        "Can't tell the repo from the remote URL {remote_url}; set pr.repo"
+                    )))
+                }
+            },
+        };
+        let api_url = match optional_string("pr.api-url")? {
+            Some(api_url) => api_url.trim_end_matches('/').to_string(),
+            None => match (kind, host) {
+                (ForgeKind::GitHub, Some("github.com") | None) => {
+                    "https://api.github.com".to_string()
+                }
+                (ForgeKind::GitHub, Some(host)) => format!("https://{host}/api/v3"),
+                (ForgeKind::GitLab, Some(host)) => format!("https://{host}/api/v4"),
+                (ForgeKind::GitLab, None) => "https://gitlab.com/api/v4".to_string(),
+            },
+        };
+        Ok(Forge {
+            kind,
+            api_url,
+            repo,
+            token: optional_string("pr.token")?,
+        })
+    }
+
+    /// Returns the most recent pull request from `branch`, in any state.
+    pub fn find_pull_request(&self, branch: &str) -> Result<Option<PullRequest>, ForgeError> {
+        let path = match self.kind {
+            ForgeKind::GitHub => {
+                let owner = self.repo.split('/').next().unwrap();
+                format!(
+                    "/repos/{}/pulls?state=all&head={}",
+                    self.repo,
+                    url_encode(&format!("{owner}:{branch}"))
+                )
+            }
+            ForgeKind::GitLab => format!(
+                "{}/merge_requests?source_branch={}",
+                self.project_path(),
+                url_encode(branch)
+            ),
+        };
+        let (url, response) = self.request("GET", &path, None)?;
+        match response.as_array() {
+            Some(pull_requests) => match pull_requests.first() {
+                Some(pull_request) => Ok(Some(self.parse_pull_request(&url, pull_request)?)),
+                None => Ok(None),
+            },
+            None => Err(ForgeError::InvalidResponse {
+                url,
+                message: "expected a list".to_string(),
+            }),
+        }
+    }
+
+    pub fn create_pull_request(
+        &self,
+        branch: &str,
+        base: &str,
+        title: &str,
+        body: &str,
+    ) -> Result<PullRequest, ForgeError> {
+        let (path, request) = match self.kind {
+            ForgeKind::GitHub => (
+                format!("/repos/{}/pulls", self.repo),
+                json!({"head": branch, "base": base, "title": title, "body": body}),
+            ),
+            ForgeKind::GitLab => (
+                format!("{}/merge_requests", self.project_path()),
+                json!({
+                    "source_branch": branch,
+                    "target_branch": base,
+                    "title": title,
+                    "description": body,
+                }),
+            ),
+        };
+        let (url, response) = self.request("POST", &path, Some(request))?;
+        self.parse_pull_request(&url, &response)
+    }
+
+    pub fn update_pull_request(
+        &self,
+        number: u64,
+        base: &str,
+        title: &str,
+        body: &str,
+    ) -> Result<PullRequest, ForgeError> {
+        let (method, path, request) = match self.kind {
+            ForgeKind::GitHub => (
+                "PATCH",
+                format!("/repos/{}/pulls/{number}", self.repo),
+                json!({"base": base, "title": title, "body": body}),
+            ),
+            ForgeKind::GitLab => (
+                "PUT",
+                format!("{}/merge_requests/{number}", self.project_path()),
+                json!({"target_branch": base, "title": title, "description": body}),
+            ),
+        };
+        let (url, response) = self.request(method, &path, Some(request))?;
+        self.parse_pull_request(&url, &response)
+    }
+
+    fn project_path(&self) -> String {
+        format!("/projects/{}", url_encode(&self.repo))
+    }
+
+    fn parse_pull_request(&self, url: &str, value: &Value) -> Result<PullRequest, ForgeError> {
+        let invalid = |message: &str| ForgeError::InvalidResponse {
+            url: url.to_string(),
+            message: message.to_string(),
+        };
+        let (number_key, url_key, base_key) = match self.kind {
+            ForgeKind::GitHub => ("number", "html_url", "base"),
+            ForgeKind::GitLab => ("iid", "web_url", "target_branch"),
+        };
+        let number = value[number_key]
+            .as_u64()
+            .ok_or_else(|| invalid(&format!("missing `{number_key}`")))?;
+        let web_url = value[url_key]
+            .as_str()
+            .ok_or_else(|| invalid(&format!("missing `{url_key}`")))?;
+        let base = match self.kind {
+            ForgeKind::GitHub => &value[base_key]["ref"],
+            ForgeKind::GitLab => &value[base_key],
+        }
+        .as_str()
+        .ok_or_else(|| invalid(&format!("missing `{base_key}`")))?;
+        let state = match (self.kind, value["state"].as_str()) {
+            (ForgeKind::GitHub, Some("open")) => PullRequestState::Open,
+            (ForgeKind::GitHub, Some("closed")) if value["merged_at"].is_string() => {
+                PullRequestState::Merged
+            }
+            (ForgeKind::GitHub, Some("closed")) => PullRequestState::Closed,
+            (ForgeKind::GitLab, Some("opened")) => PullRequestState::Open,
+            (ForgeKind::GitLab, Some("merged")) => PullRequestState::Merged,
+            (ForgeKind::GitLab, Some("closed" | "locked")) => PullRequestState::Closed,
+            _ => return Err(invalid("missing or unknown `state`")),
+        };
+        Ok(PullRequest {
+            number,
+            url: web_url.to_string(),
+            state,
+            base: base.to_string(),
+        })
+    }
+
+    /// Sends a request to the API and returns the full URL and the response.
+    fn request(
+        &self,
+        method: &'static str,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<(String, Value), ForgeError> {
+        let url = format!("{}{path}", self.api_url);
+        // The headers are passed on stdin so the token doesn't show up in the
+        // process list
+        let mut headers = String::new();
+        match self.kind {
+            ForgeKind::GitHub => {
+                headers.push_str("Accept: application/vnd.github+json\n");
+                if let Some(token) = &self.token {
+                    headers.push_str(&format!("Authorization: Bearer {token}\n"));
+                }
+            }
+            ForgeKind::GitLab => {
+                if let Some(token) = &self.token {
+                    headers.push_str(&format!("PRIVATE-TOKEN: {token}\n"));
+                }
+            }
+        }
+        let mut command = Command::new("curl");
+        command
+            .args(["--silent", "--show-error", "--request", method])
+            .args(["--header", "@-", "--write-out", "\n%{http_code}"]);
+        if let Some(body) = &body {
+            command.args([
+                "--header",
+                "Content-Type: application/json",
+                "--data-binary",
+                &body.to_string(),
+            ]);
+        }
+        let mut child = command
+            .arg(&url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        child.stdin.take().unwrap().write_all(headers.as_bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(ForgeError::CurlFailed(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let (response, status) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+        let status: u32 = status.trim().parse().unwrap_or(0);
+        let json: Value = serde_json::from_str(response).unwrap_or(Value::Null);
+        if !(200..300).contains(&status) {
+            let message = match &json["message"] {
+                Value::String(message) => message.clone(),
+                Value::Null => response.trim().to_string(),
+                other => other.to_string(),
+            };
+            return Err(ForgeError::ApiError {
+                method,
+                url,
+                status,
+                message,
+            });
+        }
+        Ok((url, json))
+    }
+}
+
+fn url_encode(text: &str) -> String {
+    let mut result = String::new();
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            result.push(byte as char);
+        } else {
+            result.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_remote_url() {
+        let parsed = |host: &str, path: &str| Some((host.to_string(), path.to_string()));
+        assert_eq!(
+            parse_remote_url("https://github.com/owner/name.git"),
+            parsed("github.com", "owner/name")
+        );
+        assert_eq!(
+            parse_remote_url("ssh://git@gitlab.example.com:2222/group/sub/name"),
+            parsed("gitlab.example.com", "group/sub/name")
+        );
+        assert_eq!(
+            parse_remote_url("git@github.com:owner/name.git"),
+            parsed("github.com", "owner/name")
+        );
+        assert_eq!(parse_remote_url("/home/user/repo"), None);
+    }
+
+    #[test]
+    fn test_url_encode() {
+        assert_eq!(url_encode("owner:push-abc"), "owner%3Apush-abc");
+        assert_eq!(url_encode("group/name"), "group%2Fname");
+    }
+}
@@ -93,11 +93,17 @@ fn config_colors(user_settings: &UserSettings) -> HashMap<String, String> {
     result.insert(String::from("working_copies"), String::from("magenta"));
     result.insert(String::from("branch"), String::from("magenta"));
     result.insert(String::from("branches"), String::from("magenta"));
+    result.insert(String::from("change_names"), String::from("magenta"));
+    result.insert(String::from("topic"), String::from("cyan"));
     result.insert(String::from("tags"), String::from("magenta"));
     result.insert(String::from("git_refs"), String::from("magenta"));
     result.insert(String::from("git_head"), String::from("magenta"));
     result.insert(String::from("divergent"), String::from("red"));
+    result.insert(String::from("hidden"), String::from("red"));
     result.insert(String::from("conflict"), String::from("red"));
+    result.insert(String::from("good_signature"), String::from("green"));
+    result.insert(String::from("unsigned"), String::from("yellow"));
+    result.insert(String::from("bad_signature"), String::from("red"));
 
     // TODO: This near-duplication of the lines above is unfortunate. Should we
     // allow adding and clearing the "bright" bit somehow? Or should we instead
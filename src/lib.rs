@@ -14,12 +14,20 @@
 
 #![deny(unused_must_use)]
 
+pub mod api_server;
+pub mod archive;
 pub mod cli_util;
 pub mod commands;
 pub mod config;
 pub mod diff_edit;
+pub mod diff_util;
+pub mod email;
+pub mod events;
+pub mod forge;
 pub mod formatter;
 pub mod graphlog;
+pub mod progress;
+pub mod state_summary;
 pub mod template_parser;
 pub mod templater;
 pub mod ui;
@@ -0,0 +1,170 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Progress indicator for fetching from and pushing to Git remotes.
+
+use std::io;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+use jujutsu_lib::git;
+
+/// Don't show anything for transfers that finish quickly.
+const INITIAL_DELAY: Duration = Duration::from_millis(250);
+const UPDATE_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct Progress {
+    next_print: Instant,
+    printed: bool,
+    rate: RateEstimate,
+}
+
+impl Progress {
+    pub fn new(now: Instant) -> Self {
+        Progress {
+            next_print: now + INITIAL_DELAY,
+            printed: false,
+            rate: RateEstimate::new(now),
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        now: Instant,
+        progress: &git::Progress,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        let rate = self.rate.update(now, progress.bytes as u64);
+        if now < self.next_print {
+            return Ok(());
+        }
+        self.next_print = now + UPDATE_INTERVAL;
+        self.printed = true;
+        write!(output, "\r\x1b[K{}", format_progress(progress, rate))?;
+        output.flush()
+    }
+
+    /// Clears the progress line, if anything was printed.
+    pub fn finish(&mut self, output: &mut dyn Write) -> io::Result<()> {
+        if self.printed {
+            write!(output, "\r\x1b[K")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn format_progress(progress: &git::Progress, rate: Option<f64>) -> String {
+    let mut line = String::new();
+    if let Some(percent) = (progress.objects * 100).checked_div(progress.total_objects) {
+        line.push_str(&format!(
+            "{percent:3}% ({}/{} objects)",
+            progress.objects, progress.total_objects
+        ));
+    } else {
+        line.push_str(&format!("{} objects", progress.objects));
+    }
+    line.push_str(&format!(", {}", format_bytes(progress.bytes as f64)));
+    if let Some(rate) = rate {
+        line.push_str(&format!(" at {}/s", format_bytes(rate)));
+    }
+    line
+}
+
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024.0 {
+        return format!("{bytes:.0} B");
+    }
+    let mut value = bytes / 1024.0;
+    let mut unit = UNITS[0];
+    for next_unit in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{value:.1} {unit}")
+}
+
+/// Estimates the transfer rate, smoothing it so it doesn't jump around.
+struct RateEstimate {
+    last_sample: Option<(Instant, u64)>,
+    rate: Option<f64>,
+}
+
+impl RateEstimate {
+    fn new(now: Instant) -> Self {
+        RateEstimate {
+            last_sample: Some((now, 0)),
+            rate: None,
+        }
+    }
+
+    /// Returns the estimated rate in bytes per second.
+    fn update(&mut self, now: Instant, total: u64) -> Option<f64> {
+        if let Some((last_time, last_total)) = self.last_sample {
+            let elapsed = (now - last_time).as_secs_f64();
+            // Don't update on too small intervals, since they give noisy samples
+            if elapsed < 0.1 {
+                return self.rate;
+            }
+            let sample = total.saturating_sub(last_total) as f64 / elapsed;
+            self.rate = Some(match self.rate {
+                None => sample,
+                Some(rate) => 0.7 * rate + 0.3 * sample,
+            });
+        }
+        self.last_sample = Some((now, total));
+        self.rate
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0.0), "0 B");
+        assert_eq!(format_bytes(1023.0), "1023 B");
+        assert_eq!(format_bytes(1536.0), "1.5 KiB");
+        assert_eq!(format_bytes(3.0 * 1024.0 * 1024.0 * 1024.0), "3.0 GiB");
+    }
+
+    #[test]
+    fn test_progress_output() {
+        let start = Instant::now();
+        let mut progress = Progress::new(start);
+        let mut output = vec![];
+        let update = git::Progress {
+            objects: 5,
+            total_objects: 10,
+            bytes: 2048,
+        };
+        // Nothing is printed at first
+        progress.update(start, &update, &mut output).unwrap();
+        progress.finish(&mut output).unwrap();
+        assert!(output.is_empty());
+
+        progress
+            .update(start + Duration::from_secs(1), &update, &mut output)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "\r\x1b[K 50% (5/10 objects), 2.0 KiB at 2.0 KiB/s"
+        );
+    }
+}
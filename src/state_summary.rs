@@ -0,0 +1,88 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A small JSON summary of the workspace's state, written to
+//! `.jj/state-summary.json` after each transaction when `ui.state-summary` is
+//! enabled. Shell prompts and IDE status bars can read it instead of running
+//! `jj`.
+
+use std::io::Write;
+use std::path::Path;
+
+use jujutsu_lib::backend::BackendError;
+use jujutsu_lib::op_store::WorkspaceId;
+use jujutsu_lib::repo::ReadonlyRepo;
+use serde_json::{json, Value};
+
+pub const STATE_SUMMARY_FILE: &str = "state-summary.json";
+
+/// Builds the summary of the working-copy commit of `workspace_id`. Returns
+/// `None` if the workspace has no working-copy commit.
+pub fn state_summary(
+    repo: &ReadonlyRepo,
+    workspace_id: &WorkspaceId,
+) -> Result<Option<Value>, BackendError> {
+    let wc_commit_id = match repo.view().get_wc_commit_id(workspace_id) {
+        Some(commit_id) => commit_id,
+        None => return Ok(None),
+    };
+    let wc_commit = repo.store().get_commit(wc_commit_id)?;
+    let branches = repo
+        .view()
+        .branches()
+        .iter()
+        .filter(|(_, target)| {
+            target
+                .local_target
+                .as_ref()
+                .map_or(false, |target| target.has_add(wc_commit_id))
+        })
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    Ok(Some(json!({
+        "operation_id": repo.op_id().hex(),
+        "workspace_id": workspace_id.as_str(),
+        "change_id": wc_commit.change_id().hex(),
+        "commit_id": wc_commit_id.hex(),
+        "description": wc_commit.description().lines().next().unwrap_or(""),
+        "conflicts": wc_commit.tree().conflicts().len(),
+        "branches": branches,
+    })))
+}
+
+/// Writes the summary to `.jj/state-summary.json` under the workspace root.
+/// The file is replaced atomically so readers never see a partial summary. It's
+/// removed if the workspace has no working-copy commit.
+pub fn write_state_summary(
+    repo: &ReadonlyRepo,
+    workspace_id: &WorkspaceId,
+    workspace_root: &Path,
+) -> Result<(), std::io::Error> {
+    let jj_dir = workspace_root.join(".jj");
+    let path = jj_dir.join(STATE_SUMMARY_FILE);
+    let summary = match state_summary(repo, workspace_id) {
+        Ok(Some(summary)) => summary,
+        Ok(None) => {
+            return match std::fs::remove_file(&path) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            };
+        }
+        Err(err) => return Err(std::io::Error::new(std::io::ErrorKind::Other, err)),
+    };
+    let mut temp_file = tempfile::NamedTempFile::new_in(&jj_dir)?;
+    writeln!(temp_file, "{summary}")?;
+    temp_file.persist(&path).map_err(|err| err.error)?;
+    Ok(())
+}
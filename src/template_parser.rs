@@ -12,23 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use chrono::{FixedOffset, TimeZone, Utc};
-use jujutsu_lib::backend::{CommitId, Signature};
+use itertools::Itertools;
+use jujutsu_lib::backend::{CommitId, Signature, Timestamp};
 use jujutsu_lib::commit::Commit;
-use jujutsu_lib::op_store::WorkspaceId;
+use jujutsu_lib::op_store::{OperationId, WorkspaceId};
+use jujutsu_lib::operation::Operation;
 use jujutsu_lib::repo::RepoRef;
+use jujutsu_lib::repo_path::RepoPath;
 use pest::iterators::{Pair, Pairs};
 use pest::Parser;
 use pest_derive::Parser;
 
 use crate::formatter::PlainTextFormatter;
 use crate::templater::{
-    AuthorProperty, BranchProperty, ChangeIdProperty, CommitIdKeyword, CommitterProperty,
+    format_timestamp, format_timestamp_relative, format_timestamp_with, indent_string,
+    is_valid_date_format, pad_string, timestamp_in_local_time_zone, timestamp_in_utc,
+    truncate_string, AuthorProperty, BranchConflictProperty, BranchContext,
+    BranchDescriptionProperty, BranchDivergenceProperty, BranchNameProperty, BranchPresentProperty,
+    BranchProperty, BranchRemotesProperty, BranchTargetProperty, ChangeIdProperty,
+    ChangeNamesProperty, CoAuthorsProperty, CommitIdKeyword, CommitUrlProperty, CommitterProperty,
     ConditionalTemplate, ConflictProperty, ConstantTemplateProperty, DescriptionProperty,
-    DivergentProperty, DynamicLabelTemplate, GitRefsProperty, IsGitHeadProperty,
-    IsWorkingCopyProperty, LabelTemplate, ListTemplate, LiteralTemplate, OpenProperty,
-    StringPropertyTemplate, TagProperty, Template, TemplateFunction, TemplateProperty,
-    WorkingCopiesProperty,
+    DivergentProperty, DynamicLabelTemplate, FilesProperty, GerritChangeIdProperty,
+    GerritReviewProperty, GitRefsProperty, HiddenProperty, IsCurrentOperationProperty,
+    IsGitHeadProperty, IsWorkingCopyProperty, LabelTemplate, LineChangesProperty, ListTemplate,
+    LiteralTemplate, NotesProperty, NumFilesProperty, OpenProperty, OperationDescriptionProperty,
+    OperationIdProperty, OperationTagProperty, OperationTagsProperty, OperationTimeProperty,
+    OperationUserProperty, StringFunctionTemplate, StringPropertyTemplate, TagProperty, Template,
+    TemplateFunction, TemplateProperty, TopicProperty, TouchesProperty, WorkingCopiesProperty,
 };
 
 #[derive(Parser)]
@@ -67,7 +77,7 @@ struct StringFirstLine;
 
 impl TemplateProperty<String, String> for StringFirstLine {
     fn extract(&self, context: &String) -> String {
-        context.lines().next().unwrap().to_string()
+        context.lines().next().unwrap_or_default().to_string()
     }
 }
 
@@ -95,17 +105,39 @@ impl TemplateProperty<Signature, String> for SignatureEmail {
     }
 }
 
-struct SignatureTimestamp;
+struct SignatureUsername;
 
-impl TemplateProperty<Signature, String> for SignatureTimestamp {
+impl TemplateProperty<Signature, String> for SignatureUsername {
     fn extract(&self, context: &Signature) -> String {
-        let utc = Utc
-            .timestamp(
-                context.timestamp.timestamp.0.div_euclid(1000),
-                context.timestamp.timestamp.0.rem_euclid(1000) as u32 * 1000000,
-            )
-            .with_timezone(&FixedOffset::east(context.timestamp.tz_offset * 60));
-        utc.format("%Y-%m-%d %H:%M:%S.%3f %:z").to_string()
+        let (username, _domain) = context
+            .email
+            .split_once('@')
+            .unwrap_or((&context.email, ""));
+        username.to_string()
+    }
+}
+
+struct SignatureTimestamp;
+
+impl TemplateProperty<Signature, Timestamp> for SignatureTimestamp {
+    fn extract(&self, context: &Signature) -> Timestamp {
+        context.timestamp.clone()
+    }
+}
+
+struct TimestampUtc;
+
+impl TemplateProperty<Timestamp, Timestamp> for TimestampUtc {
+    fn extract(&self, context: &Timestamp) -> Timestamp {
+        timestamp_in_utc(context)
+    }
+}
+
+struct TimestampLocal;
+
+impl TemplateProperty<Timestamp, Timestamp> for TimestampLocal {
+    fn extract(&self, context: &Timestamp) -> Timestamp {
+        timestamp_in_local_time_zone(context)
     }
 }
 
@@ -135,6 +167,14 @@ fn parse_method_chain<'a, I: 'a>(
                 let next_method = parse_signature_method(method);
                 next_method.after(property)
             }
+            Property::Integer(property) => {
+                let next_method = parse_integer_method(method);
+                next_method.after(property)
+            }
+            Property::Timestamp(property) => {
+                let next_method = parse_timestamp_method(method);
+                next_method.after(property)
+            }
         }
     }
 }
@@ -163,6 +203,30 @@ fn parse_boolean_method<'a>(method: Pair<Rule>) -> Property<'a, bool> {
     panic!("no such boolean method: {}", name.as_str());
 }
 
+fn parse_integer_method<'a>(method: Pair<Rule>) -> Property<'a, i64> {
+    assert_eq!(method.as_rule(), Rule::method);
+    let mut inner = method.into_inner();
+    let name = inner.next().unwrap();
+    // TODO: validate arguments
+
+    panic!("no such integer method: {}", name.as_str());
+}
+
+fn parse_timestamp_method<'a>(method: Pair<Rule>) -> Property<'a, Timestamp> {
+    assert_eq!(method.as_rule(), Rule::method);
+    let mut inner = method.into_inner();
+    let name = inner.next().unwrap();
+    // TODO: validate arguments
+
+    let this_function = match name.as_str() {
+        "utc" => Property::Timestamp(Box::new(TimestampUtc)),
+        "local" => Property::Timestamp(Box::new(TimestampLocal)),
+        name => panic!("no such timestamp method: {}", name),
+    };
+    let chain_method = inner.last().unwrap();
+    parse_method_chain(chain_method, this_function)
+}
+
 // TODO: pass a context to the returned function (we need the repo to find the
 //       shortest unambiguous prefix)
 fn parse_commit_id_method<'a>(method: Pair<Rule>) -> Property<'a, CommitId> {
@@ -193,7 +257,8 @@ fn parse_signature_method<'a>(method: Pair<Rule>) -> Property<'a, Signature> {
         //       `author % (name "<" email ">")`)?
         "name" => Property::String(Box::new(SignatureName)),
         "email" => Property::String(Box::new(SignatureEmail)),
-        "timestamp" => Property::String(Box::new(SignatureTimestamp)),
+        "username" => Property::String(Box::new(SignatureUsername)),
+        "timestamp" => Property::Timestamp(Box::new(SignatureTimestamp)),
         name => panic!("no such commit ID method: {}", name),
     };
     let chain_method = inner.last().unwrap();
@@ -205,6 +270,8 @@ enum Property<'a, I> {
     Boolean(Box<dyn TemplateProperty<I, bool> + 'a>),
     CommitId(Box<dyn TemplateProperty<I, CommitId> + 'a>),
     Signature(Box<dyn TemplateProperty<I, Signature> + 'a>),
+    Integer(Box<dyn TemplateProperty<I, i64> + 'a>),
+    Timestamp(Box<dyn TemplateProperty<I, Timestamp> + 'a>),
 }
 
 impl<'a, I: 'a> Property<'a, I> {
@@ -226,37 +293,201 @@ impl<'a, I: 'a> Property<'a, I> {
                 first,
                 Box::new(move |value| property.extract(&value)),
             ))),
+            Property::Integer(property) => Property::Integer(Box::new(TemplateFunction::new(
+                first,
+                Box::new(move |value| property.extract(&value)),
+            ))),
+            Property::Timestamp(property) => Property::Timestamp(Box::new(TemplateFunction::new(
+                first,
+                Box::new(move |value| property.extract(&value)),
+            ))),
         }
     }
 }
 
-fn parse_commit_keyword<'a>(
+/// The keywords and functions that are specific to the type of object a
+/// template is rendered for, such as a commit or an operation.
+trait TemplateLanguage<'a> {
+    type Context: 'a;
+
+    fn parse_keyword(&self, name: &str) -> Option<Property<'a, Self::Context>>;
+
+    /// Parses a call to a function that only exists in this language.
+    fn parse_function(&self, name: &str, args: Pairs<Rule>) -> Option<Property<'a, Self::Context>>;
+
+    /// The time `relative()` computes timestamps relative to.
+    fn current_timestamp(&self) -> Timestamp;
+}
+
+struct CommitTemplateLanguage<'a> {
     repo: RepoRef<'a>,
-    workspace_id: &WorkspaceId,
+    workspace_id: WorkspaceId,
+}
+
+impl<'a> TemplateLanguage<'a> for CommitTemplateLanguage<'a> {
+    type Context = Commit;
+
+    fn parse_keyword(&self, name: &str) -> Option<Property<'a, Commit>> {
+        let repo = self.repo;
+        let property = match name {
+            "description" => Property::String(Box::new(DescriptionProperty)),
+            "change_id" => Property::String(Box::new(ChangeIdProperty)),
+            "commit_id" => Property::CommitId(Box::new(CommitIdKeyword)),
+            "author" => Property::Signature(Box::new(AuthorProperty {
+                mailmap: repo.base_repo().settings().mailmap(),
+            })),
+            "committer" => Property::Signature(Box::new(CommitterProperty {
+                mailmap: repo.base_repo().settings().mailmap(),
+            })),
+            "co_authors" => Property::String(Box::new(CoAuthorsProperty)),
+            "open" => Property::Boolean(Box::new(OpenProperty)),
+            // `checkouts` is the old name, still accepted so existing templates work
+            "working_copies" | "checkouts" => {
+                Property::String(Box::new(WorkingCopiesProperty { repo }))
+            }
+            "current_working_copy" => Property::Boolean(Box::new(IsWorkingCopyProperty {
+                repo,
+                workspace_id: self.workspace_id.clone(),
+            })),
+            "branches" => Property::String(Box::new(BranchProperty { repo })),
+            "tags" => Property::String(Box::new(TagProperty { repo })),
+            "change_names" => Property::String(Box::new(ChangeNamesProperty { repo })),
+            "topic" => Property::String(Box::new(TopicProperty { repo })),
+            "git_refs" => Property::String(Box::new(GitRefsProperty { repo })),
+            "is_git_head" => Property::Boolean(Box::new(IsGitHeadProperty::new(repo))),
+            "divergent" => Property::Boolean(Box::new(DivergentProperty::new(repo))),
+            "hidden" => Property::Boolean(Box::new(HiddenProperty::new(repo))),
+            "notes" => Property::String(Box::new(NotesProperty::new(repo))),
+            "gerrit_change_id" => Property::String(Box::new(GerritChangeIdProperty)),
+            "gerrit_review" => Property::String(Box::new(GerritReviewProperty::new(repo))),
+            "commit_url" => Property::String(Box::new(CommitUrlProperty {
+                template: repo.base_repo().settings().commit_url_template(),
+            })),
+            "conflict" => Property::Boolean(Box::new(ConflictProperty)),
+            "files" => Property::String(Box::new(FilesProperty { repo })),
+            "num_files" => Property::Integer(Box::new(NumFilesProperty { repo })),
+            "insertions" => Property::Integer(Box::new(LineChangesProperty {
+                repo,
+                removed: false,
+            })),
+            "deletions" => Property::Integer(Box::new(LineChangesProperty {
+                repo,
+                removed: true,
+            })),
+            _ => return None,
+        };
+        Some(property)
+    }
+
+    fn parse_function(&self, name: &str, args: Pairs<Rule>) -> Option<Property<'a, Commit>> {
+        match name {
+            "touches" => Some(Property::Boolean(parse_touches_function(self.repo, args))),
+            _ => None,
+        }
+    }
+
+    fn current_timestamp(&self) -> Timestamp {
+        self.repo.base_repo().settings().current_timestamp()
+    }
+}
+
+struct OperationTemplateLanguage {
+    head_op_id: OperationId,
+    now: Timestamp,
+}
+
+impl<'a> TemplateLanguage<'a> for OperationTemplateLanguage {
+    type Context = Operation;
+
+    fn parse_keyword(&self, name: &str) -> Option<Property<'a, Operation>> {
+        let property = match name {
+            "id" => Property::String(Box::new(OperationIdProperty)),
+            "user" => Property::String(Box::new(OperationUserProperty)),
+            "start_time" => Property::Timestamp(Box::new(OperationTimeProperty { end: false })),
+            "end_time" => Property::Timestamp(Box::new(OperationTimeProperty { end: true })),
+            "description" => Property::String(Box::new(OperationDescriptionProperty)),
+            "tags" => Property::String(Box::new(OperationTagsProperty)),
+            "current_operation" => Property::Boolean(Box::new(IsCurrentOperationProperty {
+                head_op_id: self.head_op_id.clone(),
+            })),
+            _ => return None,
+        };
+        Some(property)
+    }
+
+    fn parse_function(&self, name: &str, args: Pairs<Rule>) -> Option<Property<'a, Operation>> {
+        match name {
+            "tag" => {
+                let args = args.collect_vec();
+                let key = match args.as_slice() {
+                    [arg] => parse_literal_argument(arg.clone()),
+                    _ => None,
+                }
+                .expect("tag() requires a single string literal argument");
+                Some(Property::String(Box::new(OperationTagProperty { key })))
+            }
+            _ => None,
+        }
+    }
+
+    fn current_timestamp(&self) -> Timestamp {
+        self.now.clone()
+    }
+}
+
+struct BranchTemplateLanguage<'a> {
+    repo: RepoRef<'a>,
+}
+
+impl<'a> TemplateLanguage<'a> for BranchTemplateLanguage<'a> {
+    type Context = BranchContext;
+
+    fn parse_keyword(&self, name: &str) -> Option<Property<'a, BranchContext>> {
+        let property = match name {
+            "name" => Property::String(Box::new(BranchNameProperty)),
+            "present" => Property::Boolean(Box::new(BranchPresentProperty)),
+            "conflict" => Property::Boolean(Box::new(BranchConflictProperty)),
+            "target" => Property::String(Box::new(BranchTargetProperty)),
+            "remotes" => Property::String(Box::new(BranchRemotesProperty)),
+            "ahead" => Property::Integer(Box::new(BranchDivergenceProperty {
+                repo: self.repo,
+                behind: false,
+            })),
+            "behind" => Property::Integer(Box::new(BranchDivergenceProperty {
+                repo: self.repo,
+                behind: true,
+            })),
+            "description" => {
+                Property::String(Box::new(BranchDescriptionProperty { repo: self.repo }))
+            }
+            _ => return None,
+        };
+        Some(property)
+    }
+
+    fn parse_function(
+        &self,
+        _name: &str,
+        _args: Pairs<Rule>,
+    ) -> Option<Property<'a, BranchContext>> {
+        None
+    }
+
+    fn current_timestamp(&self) -> Timestamp {
+        self.repo.base_repo().settings().current_timestamp()
+    }
+}
+
+fn parse_keyword<'a, L: TemplateLanguage<'a>>(
+    language: &L,
     pair: Pair<Rule>,
-) -> (Property<'a, Commit>, String) {
+) -> (Property<'a, L::Context>, String) {
     assert_eq!(pair.as_rule(), Rule::identifier);
-    let property = match pair.as_str() {
-        "description" => Property::String(Box::new(DescriptionProperty)),
-        "change_id" => Property::String(Box::new(ChangeIdProperty)),
-        "commit_id" => Property::CommitId(Box::new(CommitIdKeyword)),
-        "author" => Property::Signature(Box::new(AuthorProperty)),
-        "committer" => Property::Signature(Box::new(CommitterProperty)),
-        "open" => Property::Boolean(Box::new(OpenProperty)),
-        "working_copies" => Property::String(Box::new(WorkingCopiesProperty { repo })),
-        "current_working_copy" => Property::Boolean(Box::new(IsWorkingCopyProperty {
-            repo,
-            workspace_id: workspace_id.clone(),
-        })),
-        "branches" => Property::String(Box::new(BranchProperty { repo })),
-        "tags" => Property::String(Box::new(TagProperty { repo })),
-        "git_refs" => Property::String(Box::new(GitRefsProperty { repo })),
-        "is_git_head" => Property::Boolean(Box::new(IsGitHeadProperty::new(repo))),
-        "divergent" => Property::Boolean(Box::new(DivergentProperty::new(repo))),
-        "conflict" => Property::Boolean(Box::new(ConflictProperty)),
-        name => panic!("unexpected identifier: {}", name),
-    };
-    (property, pair.as_str().to_string())
+    let name = pair.as_str();
+    let property = language
+        .parse_keyword(name)
+        .unwrap_or_else(|| panic!("unexpected identifier: {}", name));
+    (property, name.to_string())
 }
 
 fn coerce_to_string<'a, I: 'a>(
@@ -276,32 +507,175 @@ fn coerce_to_string<'a, I: 'a>(
             property,
             Box::new(|signature| signature.name),
         )),
+        Property::Integer(property) => Box::new(TemplateFunction::new(
+            property,
+            Box::new(|value| value.to_string()),
+        )),
+        Property::Timestamp(property) => Box::new(TemplateFunction::new(
+            property,
+            Box::new(|timestamp| format_timestamp(&timestamp)),
+        )),
     }
 }
 
-fn parse_boolean_commit_property<'a>(
-    repo: RepoRef<'a>,
-    workspace_id: &WorkspaceId,
+/// Parses the only term in `pair` (a template), which must be a literal.
+fn parse_literal_argument(pair: Pair<Rule>) -> Option<String> {
+    // A space after the comma makes the argument a list starting with an
+    // empty term
+    let terms = pair
+        .into_inner()
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::term && !pair.as_str().is_empty())
+        .collect_vec();
+    match terms.as_slice() {
+        [term] => term
+            .clone()
+            .into_inner()
+            .next()
+            .filter(|pair| pair.as_rule() == Rule::literal)
+            .map(parse_string_literal),
+        _ => None,
+    }
+}
+
+/// Parses a template argument that must be a non-negative number. Numbers
+/// are parsed as identifiers by the grammar.
+fn parse_integer_argument(pair: Pair<Rule>) -> Option<usize> {
+    let terms = pair
+        .into_inner()
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::term && !pair.as_str().is_empty())
+        .collect_vec();
+    match terms.as_slice() {
+        [term] => term.as_str().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Parses the arguments of a function that transforms its first argument,
+/// rendered as a string.
+fn parse_string_function<'a, L: TemplateLanguage<'a>>(
+    language: &L,
+    name: &str,
+    mut args: Pairs<Rule>,
+) -> Box<dyn Template<L::Context> + 'a> {
+    // `indent(prefix, content)` takes the prefix first
+    let prefix = if name == "indent" {
+        match args.next().map(parse_literal_argument) {
+            Some(Some(prefix)) => Some(prefix),
+            _ => panic!("indent() requires a string literal as its first argument"),
+        }
+    } else {
+        None
+    };
+    let content = match args.next() {
+        None => panic!("{name}() requires an argument"),
+        Some(pair) => parse_template_rule(language, pair),
+    };
+    let function: Box<dyn Fn(String) -> String> = match name {
+        "upper" => Box::new(|text| text.to_uppercase()),
+        "lower" => Box::new(|text| text.to_lowercase()),
+        "first_line" => Box::new(|text| text.lines().next().unwrap_or_default().to_string()),
+        "truncate" | "pad_left" | "pad_right" => {
+            let width = match args.next().map(parse_integer_argument) {
+                Some(Some(width)) => width,
+                _ => panic!("{name}() requires a width"),
+            };
+            match name {
+                "truncate" => Box::new(move |text| truncate_string(&text, width)),
+                "pad_left" => Box::new(move |text| pad_string(&text, width, true)),
+                _ => Box::new(move |text| pad_string(&text, width, false)),
+            }
+        }
+        "indent" => {
+            let prefix = prefix.unwrap();
+            Box::new(move |content| indent_string(&content, &prefix))
+        }
+        name => panic!("function {} not implemented", name),
+    };
+    if args.next().is_some() {
+        panic!("too many arguments to {name}()")
+    }
+    Box::new(StringFunctionTemplate::new(content, function))
+}
+
+/// Parses a template argument that must be a keyword (with methods) that
+/// evaluates to a timestamp, such as `author.timestamp()`.
+fn parse_timestamp_property<'a, L: TemplateLanguage<'a>>(
+    language: &L,
     pair: Pair<Rule>,
+) -> Box<dyn TemplateProperty<L::Context, Timestamp> + 'a> {
+    let terms = pair
+        .clone()
+        .into_inner()
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::term && !pair.as_str().is_empty())
+        .collect_vec();
+    if let [term] = terms.as_slice() {
+        let mut inner = term.clone().into_inner();
+        let expr = inner.next().unwrap();
+        let maybe_method = inner.next().unwrap();
+        if expr.as_rule() == Rule::identifier {
+            let (property, _) = parse_keyword(language, expr);
+            if let Property::Timestamp(property) = parse_method_chain(maybe_method, property) {
+                return property;
+            }
+        }
+    }
+    panic!("expected a timestamp: {}", pair.as_str())
+}
+
+/// Parses the arguments of `touches("path", ...)`. The paths are relative to
+/// the workspace root.
+fn parse_touches_function<'a>(
+    repo: RepoRef<'a>,
+    args: Pairs<Rule>,
 ) -> Box<dyn TemplateProperty<Commit, bool> + 'a> {
+    let mut paths = vec![];
+    for arg in args {
+        let path = parse_literal_argument(arg).expect("touches() accepts only string literals");
+        paths.push(RepoPath::from_internal_string(path.trim_end_matches('/')));
+    }
+    if paths.is_empty() {
+        panic!("touches() requires at least one argument")
+    }
+    Box::new(TouchesProperty { repo, paths })
+}
+
+fn parse_boolean_property<'a, L: TemplateLanguage<'a>>(
+    language: &L,
+    pair: Pair<Rule>,
+) -> Box<dyn TemplateProperty<L::Context, bool> + 'a> {
     let mut inner = pair.into_inner();
     let pair = inner.next().unwrap();
     let _method = inner.next().unwrap();
     assert!(inner.next().is_none());
     match pair.as_rule() {
-        Rule::identifier => match parse_commit_keyword(repo, workspace_id, pair.clone()).0 {
+        Rule::identifier => match parse_keyword(language, pair.clone()).0 {
             Property::Boolean(property) => property,
+            // A string is true if it's not empty
+            Property::String(property) => Box::new(TemplateFunction::new(
+                property,
+                Box::new(|value: String| !value.is_empty()),
+            )),
             _ => panic!("cannot yet use this as boolean: {:?}", pair),
         },
+        Rule::function => {
+            let mut inner = pair.clone().into_inner();
+            let name = inner.next().unwrap().as_str();
+            match language.parse_function(name, inner) {
+                Some(Property::Boolean(property)) => property,
+                _ => panic!("cannot yet use this as boolean: {:?}", pair),
+            }
+        }
         _ => panic!("cannot yet use this as boolean: {:?}", pair),
     }
 }
 
-fn parse_commit_term<'a>(
-    repo: RepoRef<'a>,
-    workspace_id: &WorkspaceId,
+fn parse_term<'a, L: TemplateLanguage<'a>>(
+    language: &L,
     pair: Pair<Rule>,
-) -> Box<dyn Template<Commit> + 'a> {
+) -> Box<dyn Template<L::Context> + 'a> {
     assert_eq!(pair.as_rule(), Rule::term);
     if pair.as_str().is_empty() {
         Box::new(LiteralTemplate(String::new()))
@@ -326,7 +700,7 @@ fn parse_commit_term<'a>(
                 }
             }
             Rule::identifier => {
-                let (term_property, labels) = parse_commit_keyword(repo, workspace_id, expr);
+                let (term_property, labels) = parse_keyword(language, expr);
                 let property = parse_method_chain(maybe_method, term_property);
                 let string_property = coerce_to_string(property);
                 Box::new(LabelTemplate::new(
@@ -342,11 +716,8 @@ fn parse_commit_term<'a>(
                 match name {
                     "label" => {
                         let label_pair = inner.next().unwrap();
-                        let label_template = parse_commit_template_rule(
-                            repo,
-                            workspace_id,
-                            label_pair.into_inner().next().unwrap(),
-                        );
+                        let label_template =
+                            parse_template_rule(language, label_pair.into_inner().next().unwrap());
                         let arg_template = match inner.next() {
                             None => panic!("label() requires two arguments"),
                             Some(pair) => pair,
@@ -354,14 +725,14 @@ fn parse_commit_term<'a>(
                         if inner.next().is_some() {
                             panic!("label() accepts only two arguments")
                         }
-                        let content: Box<dyn Template<Commit> + 'a> =
-                            parse_commit_template_rule(repo, workspace_id, arg_template);
-                        let get_labels = move |commit: &Commit| -> String {
+                        let content: Box<dyn Template<L::Context> + 'a> =
+                            parse_template_rule(language, arg_template);
+                        let get_labels = move |context: &L::Context| -> String {
                             let mut buf: Vec<u8> = vec![];
                             {
                                 let writer = Box::new(&mut buf);
                                 let mut formatter = PlainTextFormatter::new(writer);
-                                label_template.format(commit, &mut formatter).unwrap();
+                                label_template.format(context, &mut formatter).unwrap();
                             }
                             String::from_utf8(buf).unwrap()
                         };
@@ -370,16 +741,14 @@ fn parse_commit_term<'a>(
                     "if" => {
                         let condition_pair = inner.next().unwrap();
                         let condition_template = condition_pair.into_inner().next().unwrap();
-                        let condition =
-                            parse_boolean_commit_property(repo, workspace_id, condition_template);
+                        let condition = parse_boolean_property(language, condition_template);
 
                         let true_template = match inner.next() {
                             None => panic!("if() requires at least two arguments"),
-                            Some(pair) => parse_commit_template_rule(repo, workspace_id, pair),
+                            Some(pair) => parse_template_rule(language, pair),
                         };
-                        let false_template = inner
-                            .next()
-                            .map(|pair| parse_commit_template_rule(repo, workspace_id, pair));
+                        let false_template =
+                            inner.next().map(|pair| parse_template_rule(language, pair));
                         if inner.next().is_some() {
                             panic!("if() accepts at most three arguments")
                         }
@@ -389,7 +758,56 @@ fn parse_commit_term<'a>(
                             false_template,
                         ))
                     }
-                    name => panic!("function {} not implemented", name),
+                    "format_date" => {
+                        let timestamp = match inner.next() {
+                            None => panic!("format_date() requires two arguments"),
+                            Some(pair) => parse_timestamp_property(language, pair),
+                        };
+                        let format = match inner.next().map(parse_literal_argument) {
+                            Some(Some(format)) => format,
+                            _ => panic!("format_date() requires a format string"),
+                        };
+                        if inner.next().is_some() {
+                            panic!("format_date() accepts only two arguments")
+                        }
+                        if !is_valid_date_format(&format) {
+                            panic!("invalid date format: {format}")
+                        }
+                        Box::new(StringPropertyTemplate {
+                            property: Box::new(TemplateFunction::new(
+                                timestamp,
+                                Box::new(move |timestamp| {
+                                    format_timestamp_with(&timestamp, &format)
+                                }),
+                            )),
+                        })
+                    }
+                    "relative" => {
+                        let timestamp = match inner.next() {
+                            None => panic!("relative() requires an argument"),
+                            Some(pair) => parse_timestamp_property(language, pair),
+                        };
+                        if inner.next().is_some() {
+                            panic!("relative() accepts only one argument")
+                        }
+                        let now = language.current_timestamp();
+                        Box::new(StringPropertyTemplate {
+                            property: Box::new(TemplateFunction::new(
+                                timestamp,
+                                Box::new(move |timestamp| {
+                                    format_timestamp_relative(&timestamp, &now)
+                                }),
+                            )),
+                        })
+                    }
+                    "upper" | "lower" | "first_line" | "truncate" | "pad_left" | "pad_right"
+                    | "indent" => parse_string_function(language, name, inner),
+                    name => match language.parse_function(name, inner) {
+                        Some(property) => Box::new(StringPropertyTemplate {
+                            property: coerce_to_string(property),
+                        }),
+                        None => panic!("function {} not implemented", name),
+                    },
                 }
             }
             other => panic!("unexpected term: {:?}", other),
@@ -397,23 +815,22 @@ fn parse_commit_term<'a>(
     }
 }
 
-fn parse_commit_template_rule<'a>(
-    repo: RepoRef<'a>,
-    workspace_id: &WorkspaceId,
+fn parse_template_rule<'a, L: TemplateLanguage<'a>>(
+    language: &L,
     pair: Pair<Rule>,
-) -> Box<dyn Template<Commit> + 'a> {
+) -> Box<dyn Template<L::Context> + 'a> {
     match pair.as_rule() {
         Rule::template => {
             let mut inner = pair.into_inner();
-            let formatter = parse_commit_template_rule(repo, workspace_id, inner.next().unwrap());
+            let formatter = parse_template_rule(language, inner.next().unwrap());
             assert!(inner.next().is_none());
             formatter
         }
-        Rule::term => parse_commit_term(repo, workspace_id, pair),
+        Rule::term => parse_term(language, pair),
         Rule::list => {
-            let mut formatters: Vec<Box<dyn Template<Commit>>> = vec![];
+            let mut formatters: Vec<Box<dyn Template<L::Context>>> = vec![];
             for inner_pair in pair.into_inner() {
-                formatters.push(parse_commit_template_rule(repo, workspace_id, inner_pair));
+                formatters.push(parse_template_rule(language, inner_pair));
             }
             Box::new(ListTemplate(formatters))
         }
@@ -421,11 +838,10 @@ fn parse_commit_template_rule<'a>(
     }
 }
 
-pub fn parse_commit_template<'a>(
-    repo: RepoRef<'a>,
-    workspace_id: &WorkspaceId,
+fn parse_template<'a, L: TemplateLanguage<'a>>(
+    language: &L,
     template_text: &str,
-) -> Box<dyn Template<Commit> + 'a> {
+) -> Box<dyn Template<L::Context> + 'a> {
     let mut pairs: Pairs<Rule> = TemplateParser::parse(Rule::template, template_text).unwrap();
 
     let first_pair = pairs.next().unwrap();
@@ -437,5 +853,39 @@ pub fn parse_commit_template<'a>(
         first_pair.as_span().end()
     );
 
-    parse_commit_template_rule(repo, workspace_id, first_pair)
+    parse_template_rule(language, first_pair)
+}
+
+pub fn parse_commit_template<'a>(
+    repo: RepoRef<'a>,
+    workspace_id: &WorkspaceId,
+    template_text: &str,
+) -> Box<dyn Template<Commit> + 'a> {
+    let language = CommitTemplateLanguage {
+        repo,
+        workspace_id: workspace_id.clone(),
+    };
+    parse_template(&language, template_text)
+}
+
+/// Parses a template for rendering operations. `head_op_id` is the operation
+/// the `current_operation` keyword is true for.
+pub fn parse_operation_template(
+    head_op_id: &OperationId,
+    now: Timestamp,
+    template_text: &str,
+) -> Box<dyn Template<Operation>> {
+    let language = OperationTemplateLanguage {
+        head_op_id: head_op_id.clone(),
+        now,
+    };
+    parse_template(&language, template_text)
+}
+
+pub fn parse_branch_template<'a>(
+    repo: RepoRef<'a>,
+    template_text: &str,
+) -> Box<dyn Template<BranchContext> + 'a> {
+    let language = BranchTemplateLanguage { repo };
+    parse_template(&language, template_text)
 }
@@ -13,18 +13,30 @@
 // limitations under the License.
 
 use std::borrow::BorrowMut;
-use std::collections::{HashMap, HashSet};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io;
 use std::ops::{Add, AddAssign};
 
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use itertools::Itertools;
-use jujutsu_lib::backend::{ChangeId, CommitId, Signature};
+use jujutsu_lib::backend::{ChangeId, CommitId, MillisSinceEpoch, Signature, Timestamp};
 use jujutsu_lib::commit::Commit;
-use jujutsu_lib::op_store::WorkspaceId;
+use jujutsu_lib::gerrit::ReviewState;
+use jujutsu_lib::mailmap::Mailmap;
+use jujutsu_lib::matchers::{EverythingMatcher, Matcher, PrefixMatcher};
+use jujutsu_lib::op_store::{BranchTarget, OperationId, RefTarget, WorkspaceId};
+use jujutsu_lib::operation::Operation;
+use jujutsu_lib::refs::ref_divergence;
 use jujutsu_lib::repo::RepoRef;
+use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::revset::RevsetExpression;
+use jujutsu_lib::rewrite::merge_commit_trees;
+use jujutsu_lib::{gerrit, git};
 
-use crate::formatter::Formatter;
+use crate::diff_util::diff_stats;
+use crate::formatter::{Formatter, PlainTextFormatter};
 
 pub trait Template<C> {
     fn format(&self, context: &C, formatter: &mut dyn Formatter) -> io::Result<()>;
@@ -122,6 +134,72 @@ impl<'a, C> Template<C> for DynamicLabelTemplate<'a, C> {
     }
 }
 
+/// Renders the content as plain text and writes it after transforming it with
+/// the function. Labels inside the content are lost.
+pub struct StringFunctionTemplate<'a, C> {
+    content: Box<dyn Template<C> + 'a>,
+    function: Box<dyn Fn(String) -> String + 'a>,
+}
+
+impl<'a, C> StringFunctionTemplate<'a, C> {
+    pub fn new(
+        content: Box<dyn Template<C> + 'a>,
+        function: Box<dyn Fn(String) -> String + 'a>,
+    ) -> Self {
+        StringFunctionTemplate { content, function }
+    }
+}
+
+impl<'a, C> Template<C> for StringFunctionTemplate<'a, C> {
+    fn format(&self, context: &C, formatter: &mut dyn Formatter) -> io::Result<()> {
+        let mut buf: Vec<u8> = vec![];
+        {
+            let mut plain_formatter = PlainTextFormatter::new(Box::new(&mut buf));
+            self.content.format(context, &mut plain_formatter)?;
+        }
+        let text = String::from_utf8_lossy(&buf).into_owned();
+        formatter.write_str(&(self.function)(text))
+    }
+}
+
+/// Shortens the text to at most `width` characters, replacing the end with
+/// "..." if it was too long.
+pub fn truncate_string(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        text.to_string()
+    } else if width <= 3 {
+        text.chars().take(width).collect()
+    } else {
+        let mut result: String = text.chars().take(width - 3).collect();
+        result.push_str("...");
+        result
+    }
+}
+
+/// Pads the text with spaces to at least `width` characters, on the left if
+/// `left` is set, otherwise on the right.
+pub fn pad_string(text: &str, width: usize, left: bool) -> String {
+    let padding = " ".repeat(width.saturating_sub(text.chars().count()));
+    if left {
+        format!("{padding}{text}")
+    } else {
+        format!("{text}{padding}")
+    }
+}
+
+/// Adds the prefix to the start of every non-empty line.
+pub fn indent_string(text: &str, prefix: &str) -> String {
+    text.split_inclusive('\n')
+        .map(|line| {
+            if line == "\n" {
+                line.to_string()
+            } else {
+                format!("{prefix}{line}")
+            }
+        })
+        .collect()
+}
+
 // TODO: figure out why this lifetime is needed
 pub struct ListTemplate<'a, C>(pub Vec<Box<dyn Template<C> + 'a>>);
 
@@ -183,19 +261,36 @@ impl TemplateProperty<Commit, String> for DescriptionProperty {
     }
 }
 
-pub struct AuthorProperty;
+pub struct AuthorProperty {
+    pub mailmap: Mailmap,
+}
 
 impl TemplateProperty<Commit, Signature> for AuthorProperty {
     fn extract(&self, context: &Commit) -> Signature {
-        context.author().clone()
+        self.mailmap.resolve(context.author())
     }
 }
 
-pub struct CommitterProperty;
+pub struct CommitterProperty {
+    pub mailmap: Mailmap,
+}
 
 impl TemplateProperty<Commit, Signature> for CommitterProperty {
     fn extract(&self, context: &Commit) -> Signature {
-        context.committer().clone()
+        self.mailmap.resolve(context.committer())
+    }
+}
+
+/// The co-authors as "Name <email>", separated by ", ".
+pub struct CoAuthorsProperty;
+
+impl TemplateProperty<Commit, String> for CoAuthorsProperty {
+    fn extract(&self, context: &Commit) -> String {
+        context
+            .co_authors()
+            .iter()
+            .map(|co_author| format!("{} <{}>", co_author.name, co_author.email))
+            .join(", ")
     }
 }
 
@@ -290,6 +385,33 @@ impl TemplateProperty<Commit, String> for TagProperty<'_> {
     }
 }
 
+pub struct ChangeNamesProperty<'a> {
+    pub repo: RepoRef<'a>,
+}
+
+impl TemplateProperty<Commit, String> for ChangeNamesProperty<'_> {
+    fn extract(&self, context: &Commit) -> String {
+        self.repo
+            .view()
+            .names_for_change(context.change_id())
+            .join(" ")
+    }
+}
+
+pub struct TopicProperty<'a> {
+    pub repo: RepoRef<'a>,
+}
+
+impl TemplateProperty<Commit, String> for TopicProperty<'_> {
+    fn extract(&self, context: &Commit) -> String {
+        self.repo
+            .view()
+            .topic_for_change(context.change_id())
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
 pub struct GitRefsProperty<'a> {
     pub repo: RepoRef<'a>,
 }
@@ -328,6 +450,82 @@ impl TemplateProperty<Commit, bool> for IsGitHeadProperty<'_> {
     }
 }
 
+pub struct NotesProperty {
+    notes: BTreeMap<CommitId, String>,
+}
+
+impl NotesProperty {
+    pub fn new(repo: RepoRef) -> Self {
+        let notes = match repo.store().git_repo() {
+            Some(git_repo) => {
+                let notes_ref = repo.base_repo().settings().git_notes_ref();
+                git::get_notes(&git_repo, &notes_ref).unwrap_or_default()
+            }
+            None => BTreeMap::new(),
+        };
+        Self { notes }
+    }
+}
+
+impl TemplateProperty<Commit, String> for NotesProperty {
+    fn extract(&self, context: &Commit) -> String {
+        self.notes.get(context.id()).cloned().unwrap_or_default()
+    }
+}
+
+pub struct GerritChangeIdProperty;
+
+impl TemplateProperty<Commit, String> for GerritChangeIdProperty {
+    fn extract(&self, context: &Commit) -> String {
+        gerrit::find_change_id(context.description())
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+pub struct GerritReviewProperty {
+    uploaded: BTreeMap<String, CommitId>,
+}
+
+impl GerritReviewProperty {
+    pub fn new(repo: RepoRef) -> Self {
+        let uploaded = match repo.store().git_repo() {
+            Some(git_repo) => gerrit::get_uploaded(&git_repo).unwrap_or_default(),
+            None => BTreeMap::new(),
+        };
+        Self { uploaded }
+    }
+}
+
+impl TemplateProperty<Commit, String> for GerritReviewProperty {
+    fn extract(&self, context: &Commit) -> String {
+        ReviewState::for_commit(&self.uploaded, context.id(), context.description())
+            .as_str()
+            .to_string()
+    }
+}
+
+/// Expands the `{commit_id}` and `{change_id}` placeholders in a
+/// `browse.commit-url` template.
+pub fn format_commit_url(template: &str, commit: &Commit) -> String {
+    template
+        .replace("{commit_id}", &commit.id().hex())
+        .replace("{change_id}", &commit.change_id().hex())
+}
+
+pub struct CommitUrlProperty {
+    pub template: Option<String>,
+}
+
+impl TemplateProperty<Commit, String> for CommitUrlProperty {
+    fn extract(&self, context: &Commit) -> String {
+        match &self.template {
+            Some(template) => format_commit_url(template, context),
+            None => String::new(),
+        }
+    }
+}
+
 pub struct DivergentProperty {
     divergent_changes: HashSet<ChangeId>,
 }
@@ -359,6 +557,38 @@ impl TemplateProperty<Commit, bool> for DivergentProperty {
     }
 }
 
+/// Whether the commit isn't visible, e.g. because it was abandoned or
+/// rewritten. Such commits are only shown when they're asked for explicitly.
+pub struct HiddenProperty<'a> {
+    repo: RepoRef<'a>,
+    // Computed the first time a commit is checked
+    visible_commits: RefCell<Option<HashSet<CommitId>>>,
+}
+
+impl<'a> HiddenProperty<'a> {
+    pub fn new(repo: RepoRef<'a>) -> Self {
+        Self {
+            repo,
+            visible_commits: RefCell::new(None),
+        }
+    }
+}
+
+impl TemplateProperty<Commit, bool> for HiddenProperty<'_> {
+    fn extract(&self, context: &Commit) -> bool {
+        let mut visible_commits = self.visible_commits.borrow_mut();
+        let visible_commits = visible_commits.get_or_insert_with(|| {
+            RevsetExpression::all()
+                .evaluate(self.repo, None)
+                .unwrap()
+                .iter()
+                .commit_ids()
+                .collect()
+        });
+        !visible_commits.contains(context.id())
+    }
+}
+
 pub struct ConflictProperty;
 
 impl TemplateProperty<Commit, bool> for ConflictProperty {
@@ -367,6 +597,257 @@ impl TemplateProperty<Commit, bool> for ConflictProperty {
     }
 }
 
+/// The paths the commit changed compared to its parents.
+fn changed_paths(repo: RepoRef, commit: &Commit, matcher: &dyn Matcher) -> Vec<RepoPath> {
+    let parent_tree = merge_commit_trees(repo, &commit.parents());
+    parent_tree
+        .diff(&commit.tree(), matcher)
+        .map(|(path, _)| path)
+        .collect()
+}
+
+pub struct FilesProperty<'a> {
+    pub repo: RepoRef<'a>,
+}
+
+impl TemplateProperty<Commit, String> for FilesProperty<'_> {
+    fn extract(&self, context: &Commit) -> String {
+        changed_paths(self.repo, context, &EverythingMatcher)
+            .iter()
+            .map(|path| path.to_internal_file_string())
+            .join(" ")
+    }
+}
+
+pub struct NumFilesProperty<'a> {
+    pub repo: RepoRef<'a>,
+}
+
+impl TemplateProperty<Commit, i64> for NumFilesProperty<'_> {
+    fn extract(&self, context: &Commit) -> i64 {
+        changed_paths(self.repo, context, &EverythingMatcher).len() as i64
+    }
+}
+
+/// The number of lines the commit added (or removed, if `removed` is set)
+/// compared to its parents.
+pub struct LineChangesProperty<'a> {
+    pub repo: RepoRef<'a>,
+    pub removed: bool,
+}
+
+impl TemplateProperty<Commit, i64> for LineChangesProperty<'_> {
+    fn extract(&self, context: &Commit) -> i64 {
+        let parent_tree = merge_commit_trees(self.repo, &context.parents());
+        let tree_diff = parent_tree.diff(&context.tree(), &EverythingMatcher);
+        let stats = diff_stats(self.repo.store(), tree_diff).unwrap_or_else(|_| {
+            panic!(
+                "failed to read the changes in commit {}",
+                context.id().hex()
+            )
+        });
+        stats
+            .iter()
+            .map(|stat| if self.removed { stat.removed } else { stat.added } as i64)
+            .sum()
+    }
+}
+
+/// Whether the commit changed any of the paths (or anything under them).
+pub struct TouchesProperty<'a> {
+    pub repo: RepoRef<'a>,
+    pub paths: Vec<RepoPath>,
+}
+
+impl TemplateProperty<Commit, bool> for TouchesProperty<'_> {
+    fn extract(&self, context: &Commit) -> bool {
+        let matcher = PrefixMatcher::new(&self.paths);
+        !changed_paths(self.repo, context, &matcher).is_empty()
+    }
+}
+
+pub struct OperationIdProperty;
+
+impl TemplateProperty<Operation, String> for OperationIdProperty {
+    fn extract(&self, context: &Operation) -> String {
+        context.id().hex()
+    }
+}
+
+pub struct OperationUserProperty;
+
+impl TemplateProperty<Operation, String> for OperationUserProperty {
+    fn extract(&self, context: &Operation) -> String {
+        let metadata = &context.store_operation().metadata;
+        format!("{}@{}", metadata.username, metadata.hostname)
+    }
+}
+
+pub struct OperationTimeProperty {
+    pub end: bool,
+}
+
+impl TemplateProperty<Operation, Timestamp> for OperationTimeProperty {
+    fn extract(&self, context: &Operation) -> Timestamp {
+        let metadata = &context.store_operation().metadata;
+        if self.end {
+            metadata.end_time.clone()
+        } else {
+            metadata.start_time.clone()
+        }
+    }
+}
+
+pub struct OperationDescriptionProperty;
+
+impl TemplateProperty<Operation, String> for OperationDescriptionProperty {
+    fn extract(&self, context: &Operation) -> String {
+        context.store_operation().metadata.description.clone()
+    }
+}
+
+/// The operation's tags as "key: value" lines, sorted by key.
+pub struct OperationTagsProperty;
+
+impl TemplateProperty<Operation, String> for OperationTagsProperty {
+    fn extract(&self, context: &Operation) -> String {
+        let tags = &context.store_operation().metadata.tags;
+        tags.iter()
+            .sorted()
+            .map(|(key, value)| format!("{key}: {value}"))
+            .join("\n")
+    }
+}
+
+/// The value of a single tag, or the empty string if the operation doesn't
+/// have it.
+pub struct OperationTagProperty {
+    pub key: String,
+}
+
+impl TemplateProperty<Operation, String> for OperationTagProperty {
+    fn extract(&self, context: &Operation) -> String {
+        let tags = &context.store_operation().metadata.tags;
+        tags.get(&self.key).cloned().unwrap_or_default()
+    }
+}
+
+pub struct IsCurrentOperationProperty {
+    pub head_op_id: OperationId,
+}
+
+impl TemplateProperty<Operation, bool> for IsCurrentOperationProperty {
+    fn extract(&self, context: &Operation) -> bool {
+        *context.id() == self.head_op_id
+    }
+}
+
+/// A branch, as rendered by `jj branch list -T`.
+pub struct BranchContext {
+    pub name: String,
+    pub target: BranchTarget,
+    /// The remote that `ahead` and `behind` are relative to.
+    pub tracked_remote: Option<String>,
+}
+
+pub struct BranchNameProperty;
+
+impl TemplateProperty<BranchContext, String> for BranchNameProperty {
+    fn extract(&self, context: &BranchContext) -> String {
+        context.name.clone()
+    }
+}
+
+/// Whether the branch exists locally.
+pub struct BranchPresentProperty;
+
+impl TemplateProperty<BranchContext, bool> for BranchPresentProperty {
+    fn extract(&self, context: &BranchContext) -> bool {
+        context.target.local_target.is_some()
+    }
+}
+
+pub struct BranchConflictProperty;
+
+impl TemplateProperty<BranchContext, bool> for BranchConflictProperty {
+    fn extract(&self, context: &BranchContext) -> bool {
+        context
+            .target
+            .local_target
+            .as_ref()
+            .map_or(false, |target| target.is_conflict())
+    }
+}
+
+/// The commit ids the local branch points to, separated by spaces.
+pub struct BranchTargetProperty;
+
+impl TemplateProperty<BranchContext, String> for BranchTargetProperty {
+    fn extract(&self, context: &BranchContext) -> String {
+        match &context.target.local_target {
+            Some(target) => target.adds().iter().map(|id| id.hex()).join(" "),
+            None => String::new(),
+        }
+    }
+}
+
+/// The remotes the branch exists on, separated by spaces.
+pub struct BranchRemotesProperty;
+
+impl TemplateProperty<BranchContext, String> for BranchRemotesProperty {
+    fn extract(&self, context: &BranchContext) -> String {
+        context.target.remote_targets.keys().join(" ")
+    }
+}
+
+/// The description of the commit the local branch points to, if there's
+/// exactly one.
+pub struct BranchDescriptionProperty<'a> {
+    pub repo: RepoRef<'a>,
+}
+
+impl TemplateProperty<BranchContext, String> for BranchDescriptionProperty<'_> {
+    fn extract(&self, context: &BranchContext) -> String {
+        match &context.target.local_target {
+            Some(RefTarget::Normal(id)) => self
+                .repo
+                .store()
+                .get_commit(id)
+                .unwrap()
+                .description()
+                .to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// The number of commits the local branch is ahead of (or behind) the tracked
+/// remote branch. Zero if either of them doesn't exist.
+pub struct BranchDivergenceProperty<'a> {
+    pub repo: RepoRef<'a>,
+    pub behind: bool,
+}
+
+impl TemplateProperty<BranchContext, i64> for BranchDivergenceProperty<'_> {
+    fn extract(&self, context: &BranchContext) -> i64 {
+        let remote_target = context
+            .tracked_remote
+            .as_ref()
+            .and_then(|remote| context.target.remote_targets.get(remote));
+        match (&context.target.local_target, remote_target) {
+            (Some(local_target), Some(remote_target)) => {
+                let divergence = ref_divergence(self.repo.index(), local_target, remote_target);
+                if self.behind {
+                    divergence.behind as i64
+                } else {
+                    divergence.ahead as i64
+                }
+            }
+            _ => 0,
+        }
+    }
+}
+
 pub struct ConditionalTemplate<'a, C> {
     pub condition: Box<dyn TemplateProperty<C, bool> + 'a>,
     pub true_template: Box<dyn Template<C> + 'a>,
@@ -399,6 +880,118 @@ impl<'a, C> Template<C> for ConditionalTemplate<'a, C> {
     }
 }
 
+fn timestamp_to_datetime(timestamp: &Timestamp) -> DateTime<FixedOffset> {
+    let tz = FixedOffset::east_opt(timestamp.tz_offset * 60)
+        .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+    Utc.timestamp_opt(
+        timestamp.timestamp.0.div_euclid(1000),
+        timestamp.timestamp.0.rem_euclid(1000) as u32 * 1000000,
+    )
+    .unwrap()
+    .with_timezone(&tz)
+}
+
+/// The same instant as `timestamp`, but in UTC.
+pub fn timestamp_in_utc(timestamp: &Timestamp) -> Timestamp {
+    Timestamp {
+        timestamp: timestamp.timestamp.clone(),
+        tz_offset: 0,
+    }
+}
+
+/// The same instant as `timestamp`, but in the local time zone (as of that
+/// instant, so daylight saving time is taken into account).
+pub fn timestamp_in_local_time_zone(timestamp: &Timestamp) -> Timestamp {
+    Timestamp::from_datetime(timestamp_to_datetime(timestamp).with_timezone(&Local))
+}
+
+/// Formats the timestamp in its own time zone, e.g. "2001-02-03
+/// 04:05:06.000 +07:00".
+pub fn format_timestamp(timestamp: &Timestamp) -> String {
+    format_timestamp_with(timestamp, "%Y-%m-%d %H:%M:%S.%3f %:z")
+}
+
+/// Formats the timestamp in its own time zone using a `strftime`-like format.
+/// The format should have been checked with `is_valid_date_format()`.
+pub fn format_timestamp_with(timestamp: &Timestamp, format: &str) -> String {
+    timestamp_to_datetime(timestamp).format(format).to_string()
+}
+
+pub fn is_valid_date_format(format: &str) -> bool {
+    !StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+}
+
+/// Formats the timestamp relative to `now`, e.g. "3 hours ago".
+pub fn format_timestamp_relative(timestamp: &Timestamp, now: &Timestamp) -> String {
+    let seconds = (now.timestamp.0 - timestamp.timestamp.0) / 1000;
+    let abs_seconds = seconds.abs();
+    let (unit, unit_seconds) = TIME_UNITS
+        .iter()
+        .find(|(_, unit_seconds)| abs_seconds >= *unit_seconds)
+        .unwrap_or(&TIME_UNITS[TIME_UNITS.len() - 1]);
+    let count = abs_seconds / unit_seconds;
+    let plural = if count == 1 { "" } else { "s" };
+    if seconds < 0 {
+        format!("in {count} {unit}{plural}")
+    } else {
+        format!("{count} {unit}{plural} ago")
+    }
+}
+
+const TIME_UNITS: [(&str, i64); 7] = [
+    ("year", 365 * 24 * 60 * 60),
+    ("month", 30 * 24 * 60 * 60),
+    ("week", 7 * 24 * 60 * 60),
+    ("day", 24 * 60 * 60),
+    ("hour", 60 * 60),
+    ("minute", 60),
+    ("second", 1),
+];
+
+/// Parses a point in time given by the user. Accepts "now", "yesterday",
+/// relative times like "3 hours ago", RFC 3339 timestamps, timestamps as
+/// printed by `format_timestamp()`, and "YYYY-MM-DD[ HH:MM[:SS]]" in the time
+/// zone of `now`.
+pub fn parse_timestamp(text: &str, now: &Timestamp) -> Option<Timestamp> {
+    let text = text.trim();
+    let shift = |seconds: i64| Timestamp {
+        timestamp: MillisSinceEpoch(now.timestamp.0 - seconds * 1000),
+        tz_offset: now.tz_offset,
+    };
+    if text == "now" {
+        return Some(now.clone());
+    }
+    if text == "yesterday" {
+        return Some(shift(24 * 60 * 60));
+    }
+    if let Some(relative) = text.strip_suffix(" ago") {
+        let (count, unit) = relative.trim().split_once(' ')?;
+        let count: i64 = count.parse().ok()?;
+        let unit = unit.trim();
+        let unit = unit.strip_suffix('s').unwrap_or(unit);
+        let (_, unit_seconds) = TIME_UNITS.iter().find(|(name, _)| *name == unit)?;
+        return Some(shift(count.checked_mul(*unit_seconds)?));
+    }
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(text) {
+        return Some(Timestamp::from_datetime(datetime));
+    }
+    if let Ok(datetime) = DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f %:z") {
+        return Some(Timestamp::from_datetime(datetime));
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })?;
+    let datetime = FixedOffset::east_opt(now.tz_offset * 60)?
+        .from_local_datetime(&naive)
+        .single()?;
+    Some(Timestamp::from_datetime(datetime))
+}
+
 // TODO: If needed, add a ContextualTemplateFunction where the function also
 // gets the context
 pub struct TemplateFunction<'a, C, I, O> {
@@ -143,6 +143,37 @@ impl<'stdout> Ui<'stdout> {
         &self.settings
     }
 
+    pub fn set_settings(&mut self, settings: UserSettings) {
+        self.settings = settings;
+    }
+
+    /// Whether to show progress indicators on stderr.
+    pub fn use_progress_indicator(&self) -> bool {
+        atty::is(Stream::Stderr)
+    }
+
+    /// Whether the user can answer prompts, i.e. both stdin and stdout are
+    /// terminals.
+    pub fn is_interactive(&self) -> bool {
+        atty::is(Stream::Stdin) && atty::is(Stream::Stdout)
+    }
+
+    /// Asks a yes/no question on stdout and reads the answer from stdin.
+    /// Anything but "y" or "yes" counts as no.
+    pub fn prompt_yes_no(&mut self, prompt: &str) -> io::Result<bool> {
+        {
+            let mut formatter = self.stdout_formatter();
+            write!(formatter, "{prompt} [y/N] ")?;
+            formatter.flush()?;
+        }
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        Ok(matches!(
+            answer.trim().to_ascii_lowercase().as_str(),
+            "y" | "yes"
+        ))
+    }
+
     pub fn new_formatter<'output>(
         &self,
         output: Box<dyn Write + 'output>,
@@ -190,14 +221,10 @@ impl<'stdout> Ui<'stdout> {
         Ok(())
     }
 
-    pub fn write_commit_summary(
-        &mut self,
-        repo: RepoRef,
-        workspace_id: &WorkspaceId,
-        commit: &Commit,
-    ) -> io::Result<()> {
-        let template_string = self
-            .settings
+    /// The template for one-line summaries of commits, from the
+    /// `template.commit_summary` config.
+    pub fn commit_summary_template(&self) -> String {
+        self.settings
             .config()
             .get_string("template.commit_summary")
             .unwrap_or_else(|_| {
@@ -206,9 +233,28 @@ impl<'stdout> Ui<'stdout> {
                 } else {
                     String::from(r#"commit_id.short() " " description.first_line()"#)
                 }
-            });
+            })
+    }
+
+    pub fn write_commit_summary(
+        &mut self,
+        repo: RepoRef,
+        workspace_id: &WorkspaceId,
+        commit: &Commit,
+    ) -> io::Result<()> {
+        let template_string = self.commit_summary_template();
+        self.write_commit_summary_with_template(repo, workspace_id, commit, &template_string)
+    }
+
+    pub fn write_commit_summary_with_template(
+        &mut self,
+        repo: RepoRef,
+        workspace_id: &WorkspaceId,
+        commit: &Commit,
+        template_string: &str,
+    ) -> io::Result<()> {
         let template =
-            crate::template_parser::parse_commit_template(repo, workspace_id, &template_string);
+            crate::template_parser::parse_commit_template(repo, workspace_id, template_string);
         let mut formatter = self.stdout_formatter();
         let mut template_writer = TemplateFormatter::new(template, formatter.as_mut());
         template_writer.format(commit)?;
@@ -0,0 +1,91 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+fn create_commit(test_env: &TestEnvironment, repo_path: &Path, name: &str, parent: &str) {
+    test_env.jj_cmd_success(repo_path, &["checkout", parent]);
+    std::fs::write(repo_path.join(name), name).unwrap();
+    test_env.jj_cmd_success(repo_path, &["close", "-m", name]);
+    test_env.jj_cmd_success(repo_path, &["branch", "create", name, "-r", "@-"]);
+}
+
+#[test]
+fn test_abandon_branches() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    create_commit(&test_env, &repo_path, "a", "root");
+    create_commit(&test_env, &repo_path, "b", "a");
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @  (no description set)
+    o b b
+    o a a
+    o  (no description set)
+    "###);
+
+    // By default, the branch moves to the parent
+    let stdout = test_env.jj_cmd_success(&repo_path, &["abandon", "b"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Rebased 1 descendant commits onto parents of abandoned commits
+    Working copy now at: 27c25cb42c0d (no description set)
+    Added 0 files, modified 0 files, removed 1 files
+    "###);
+    insta::assert_snapshot!(get_branch_output(&test_env, &repo_path), @r###"
+    a: 738732d0bc3f a
+    b: 738732d0bc3f a
+    "###);
+
+    // The branch can be deleted instead
+    test_env.jj_cmd_success(&repo_path, &["undo"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["abandon", "b", "--delete-branches"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Rebased 1 descendant commits onto parents of abandoned commits
+    Deleted branches: b
+    Working copy now at: 1d67590f1d19 (no description set)
+    Added 0 files, modified 0 files, removed 1 files
+    "###);
+    insta::assert_snapshot!(get_branch_output(&test_env, &repo_path), @"a: 738732d0bc3f a");
+
+    // Or left on the abandoned commit
+    test_env.jj_cmd_success(&repo_path, &["undo"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["abandon", "b", "--retain-branches"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Rebased 1 descendant commits onto parents of abandoned commits
+    Working copy now at: e38f3eb56c62 (no description set)
+    Added 0 files, modified 0 files, removed 1 files
+    "###);
+    insta::assert_snapshot!(get_branch_output(&test_env, &repo_path), @r###"
+    a: 738732d0bc3f a
+    b: 3261194e4ff2 b
+    "###);
+
+    test_env.jj_cmd_cli_error(
+        &repo_path,
+        &["abandon", "b", "--delete-branches", "--retain-branches"],
+    );
+}
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(repo_path, &["log", "-T", r#"branches " " description"#])
+}
+
+fn get_branch_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(repo_path, &["branch", "list"])
+}
@@ -0,0 +1,67 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::{get_stdout_string, TestEnvironment};
+
+pub mod common;
+
+#[test]
+fn test_api_stdio() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "b\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "c\n").unwrap();
+    // Snapshot the working copy since the server won't do that
+    test_env.jj_cmd_success(&repo_path, &["status"]);
+
+    let requests = [
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "file", "params": {"revision": "@-", "path": "file1"}}"#,
+        r#"{"jsonrpc": "2.0", "id": 2, "method": "diff", "params": {"revision": "@"}}"#,
+        r#"{"jsonrpc": "2.0", "id": 3, "method": "log", "params": {"revisions": "@-"}}"#,
+        r#"not json"#,
+        r#"{"jsonrpc": "2.0", "id": 4, "method": "nonexistent"}"#,
+        r#"{"jsonrpc": "2.0", "id": 5, "method": "shutdown"}"#,
+        r#"{"jsonrpc": "2.0", "id": 6, "method": "status"}"#,
+    ];
+    let assert = test_env
+        .jj_cmd(&repo_path, &["api", "--stdio"])
+        .write_stdin(requests.join("\n"))
+        .assert()
+        .success();
+    let stdout = get_stdout_string(&assert);
+    let responses: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    // Nothing is processed after the shutdown request
+    assert_eq!(responses.len(), 6);
+    assert_eq!(responses[0]["result"]["content"], "a\n");
+    assert_eq!(
+        responses[1]["result"]["changes"],
+        serde_json::json!([
+            {"path": "file1", "kind": "modified"},
+            {"path": "file2", "kind": "added"},
+        ])
+    );
+    assert_eq!(responses[2]["result"][0]["description"], "first");
+    assert_eq!(responses[3]["error"]["code"], -32700);
+    assert_eq!(responses[4]["id"], 4);
+    assert_eq!(responses[4]["error"]["code"], -32601);
+    assert_eq!(responses[5]["id"], 5);
+}
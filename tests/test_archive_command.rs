@@ -0,0 +1,135 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+
+use std::fs::File;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use itertools::Itertools;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+fn set_up(test_env: &TestEnvironment) -> std::path::PathBuf {
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "contents\n").unwrap();
+    std::fs::create_dir(repo_path.join("dir")).unwrap();
+    std::fs::write(repo_path.join("dir").join("script"), "#!/bin/sh\n").unwrap();
+    std::fs::set_permissions(
+        repo_path.join("dir").join("script"),
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+    std::os::unix::fs::symlink("file", repo_path.join("link")).unwrap();
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "first"]);
+    // Changes in the working copy are not included when archiving @-
+    std::fs::write(repo_path.join("file"), "modified\n").unwrap();
+    repo_path
+}
+
+/// Lists the entries of a tar archive with their modes and contents.
+fn list_tar(reader: impl Read) -> String {
+    let mut archive = tar::Archive::new(reader);
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_str().unwrap().to_string();
+            let mode = entry.header().mode().unwrap();
+            let link = entry.link_name().unwrap().map(|target| target.into_owned());
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            match link {
+                Some(target) => format!("{path} {mode:o} -> {}\n", target.display()),
+                None => format!("{path} {mode:o} {contents:?}\n"),
+            }
+        })
+        .join("")
+}
+
+#[test]
+fn test_archive_tar() {
+    let test_env = TestEnvironment::default();
+    let repo_path = set_up(&test_env);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["archive", "-r", "@-", "-o", "out.tar"]);
+    insta::assert_snapshot!(stdout, @"");
+    let listing = list_tar(File::open(repo_path.join("out.tar")).unwrap());
+    insta::assert_snapshot!(listing, @r###"
+    dir/script 755 "#!/bin/sh\n"
+    file 644 "contents\n"
+    link 777 -> file
+    "###);
+
+    // With a prefix, restricted to some paths, and compressed
+    test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "archive", "-r", "@-", "-o", "out.tgz", "--prefix", "project", "dir", "file",
+        ],
+    );
+    let listing = list_tar(flate2::read::GzDecoder::new(
+        File::open(repo_path.join("out.tgz")).unwrap(),
+    ));
+    insta::assert_snapshot!(listing, @r###"
+    project/dir/script 755 "#!/bin/sh\n"
+    project/file 644 "contents\n"
+    "###);
+}
+
+#[test]
+fn test_archive_zip() {
+    let test_env = TestEnvironment::default();
+    let repo_path = set_up(&test_env);
+
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["archive", "-r", "@-", "-o", "out", "--format", "zip"],
+    );
+    let mut archive = zip::ZipArchive::new(File::open(repo_path.join("out")).unwrap()).unwrap();
+    let mut listing = String::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).unwrap();
+        let mut contents = String::new();
+        file.read_to_string(&mut contents).unwrap();
+        listing.push_str(&format!(
+            "{} {:o} {contents:?}\n",
+            file.name(),
+            file.unix_mode().unwrap()
+        ));
+    }
+    insta::assert_snapshot!(listing, @r###"
+    dir/script 100755 "#!/bin/sh\n"
+    file 100644 "contents\n"
+    link 120777 "file"
+    "###);
+}
+
+#[test]
+fn test_archive_unknown_format() {
+    let test_env = TestEnvironment::default();
+    let repo_path = set_up(&test_env);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["archive", "-o", "out.txt"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Cannot tell the archive format from the file name out.txt; use --format
+    "###);
+    assert!(!Path::new(&repo_path.join("out.txt")).exists());
+}
@@ -55,6 +55,236 @@ fn test_branch_multiple_names() {
     "###);
 }
 
+#[test]
+fn test_branch_list_template() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first line\nsecond line"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "foo"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "-r", "root", "bar"]);
+
+    let template = r#"name ": " target.short() " " if(present, "present") if(conflict, " conflict") " " first_line(description) "\n""#;
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    bar: 000000000000 present 
+    foo: e88f0210f4e7 present first line
+    "###);
+}
+
+#[test]
+fn test_branch_list_filtering() {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    let git_repo = git2::Repository::init_bare(&git_repo_path).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let tree_id = git_repo.treebuilder(None).unwrap().write().unwrap();
+    let tree = git_repo.find_tree(tree_id).unwrap();
+    for name in ["main", "feature/a", "feature/b"] {
+        git_repo
+            .commit(
+                Some(&format!("refs/heads/{}", name)),
+                &signature,
+                &signature,
+                name,
+                &tree,
+                &[],
+            )
+            .unwrap();
+    }
+    test_env.jj_cmd_success(test_env.env_root(), &["git", "clone", "git-repo", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    // Move main forward by two commits and add a local-only branch
+    test_env.jj_cmd_success(&repo_path, &["new", "main", "-m", "one"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "two"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "set", "main"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "local"]);
+    let template = r#"name " +" ahead " -" behind " " first_line(description) "\n""#;
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    feature/a +0 -0 feature/a
+    feature/b +0 -0 feature/b
+    local +0 -0 two
+    main +2 -0 two
+    "###);
+
+    // Glob patterns
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["branch", "list", "-T", template, "feature/*", "ma?n"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    feature/a +0 -0 feature/a
+    feature/b +0 -0 feature/b
+    main +2 -0 two
+    "###);
+
+    // Only branches on the remote
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["branch", "list", "-T", template, "--remote", "origin"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    feature/a +0 -0 feature/a
+    feature/b +0 -0 feature/b
+    main +2 -0 two
+    "###);
+
+    // The default output only shows the given remote's targets
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list", "--remote", "origin"]);
+    insta::assert_snapshot!(stdout, @r###"
+    feature/a: 07f426878c10 feature/a
+    feature/b: 37aee66182db feature/b
+    main: 4d03ddcbe010 two
+      @origin (behind by 2 commits): 1630028ea7ee main
+    "###);
+
+    // Newest first
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["branch", "list", "-T", template, "--sort", "date"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    local +0 -0 two
+    main +2 -0 two
+    feature/a +0 -0 feature/a
+    feature/b +0 -0 feature/b
+    "###);
+}
+
+#[test]
+fn test_branch_conflict_resolution() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "a"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "root", "-m", "b"]);
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["branch", "create", "foo", "-r", "description(a)"],
+    );
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "foo", "--at-op", "@-"]);
+
+    // The next command warns about the conflict
+    let assert = test_env.jj_cmd(&repo_path, &["log"]).assert().success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    warning: Concurrent operations moved branch foo to different commits (use `jj branch list` to see the targets and `jj branch set foo -r <rev> --resolve` to pick one)
+    "###);
+
+    let assert = test_env
+        .jj_cmd(&repo_path, &["branch", "list"])
+        .assert()
+        .success();
+    let stdout = get_stdout_string(&assert);
+    assert!(stdout.starts_with("foo (conflicted):\n"), "{}", stdout);
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Some branches have conflicting targets: foo
+    Use `jj branch set <name> -r <rev> --resolve` to point a branch to a single target, or `jj branch forget <name>` to drop the local branch.
+    "###);
+
+    // Can only use --resolve on conflicted branches
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "bar"]);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["branch", "set", "--resolve", "bar"]);
+    insta::assert_snapshot!(stderr, @"Error: Branch bar is not conflicted");
+
+    // Resolving doesn't require the new target to be a descendant
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["branch", "set", "--resolve", "foo", "-r", "root"],
+    );
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ bar 1bb3422507dd
+    | o  bcd64d31e699
+    |/  
+    o foo 000000000000
+    "###);
+}
+
 fn get_log_output(test_env: &TestEnvironment, cwd: &Path) -> String {
     test_env.jj_cmd_success(cwd, &["log", "-T", r#"branches " " commit_id.short()"#])
 }
+
+#[test]
+fn test_branch_rename() {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    let git_repo = git2::Repository::init_bare(&git_repo_path).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let tree_id = git_repo.treebuilder(None).unwrap().write().unwrap();
+    let tree = git_repo.find_tree(tree_id).unwrap();
+    for name in ["main", "feature"] {
+        git_repo
+            .commit(
+                Some(&format!("refs/heads/{}", name)),
+                &signature,
+                &signature,
+                name,
+                &tree,
+                &[],
+            )
+            .unwrap();
+    }
+    test_env.jj_cmd_success(
+        test_env.env_root(),
+        &["git", "clone", git_repo_path.to_str().unwrap(), "repo"],
+    );
+    let repo_path = test_env.env_root().join("repo");
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["branch", "rename", "nonexistent", "x"]);
+    insta::assert_snapshot!(stderr, @"Error: No such branch: nonexistent");
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["branch", "rename", "feature", "main"]);
+    insta::assert_snapshot!(stderr, @"Error: Branch already exists: main");
+
+    // The old name is left as a deleted branch, which is deleted on the remote
+    // when pushing
+    let assert = test_env
+        .jj_cmd(&repo_path, &["branch", "rename", "feature", "topic"])
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Branch feature still exists on origin. Use `jj git push --branch feature` or `jj git push --all` to delete it there.
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    feature (deleted)
+      @origin: 422129c685f3 feature
+    main: 1630028ea7ee main
+    topic: 422129c685f3 feature
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["git", "push", "--all", "--dry-run"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Branch changes to push to origin:
+      Delete branch feature from 422129c685f3
+      Add branch topic to 422129c685f3
+    Dry-run requested, not pushing.
+    "###);
+    test_env.jj_cmd_success(&repo_path, &["git", "push", "--all"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    main: 1630028ea7ee main
+    topic: 422129c685f3 feature
+    "###);
+    assert!(git_repo.find_reference("refs/heads/feature").is_err());
+    assert!(git_repo.find_reference("refs/heads/topic").is_ok());
+
+    // Or the remote branch can be forgotten instead
+    test_env.add_config(br#"branch.rename-old-remote = "untrack""#);
+    test_env.jj_cmd_success(&repo_path, &["branch", "rename", "main", "trunk"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    topic: 422129c685f3 feature
+    trunk: 1630028ea7ee main
+    "###);
+    test_env.jj_cmd_success(&repo_path, &["git", "push", "--all"]);
+    assert!(git_repo.find_reference("refs/heads/main").is_ok());
+    assert!(git_repo.find_reference("refs/heads/trunk").is_ok());
+
+    test_env.add_config(br#"branch.rename-old-remote = "bad""#);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["branch", "rename", "trunk", "main"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: Invalid branch.rename-old-remote: bad (expected "delete" or "untrack")
+    "###);
+}
@@ -0,0 +1,79 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_browse() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+
+    // Without a URL template, the keyword is empty and there's nothing to open
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "-r", "@", "-T", r#""[" commit_url "]""#],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    @ []
+    ~
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["browse"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: No commit URL template configured; set browse.commit-url
+    "###);
+
+    test_env.add_config(
+        br#"[browse]
+        commit-url = "https://example.com/repo/commit/{commit_id}"
+        command = ["echo", "opening"]
+        "#,
+    );
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "-r", "@", "-T", r#""[" commit_url "]""#],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    @ [https://example.com/repo/commit/85a1e2839620cf0b354d1ccb970927d040c2a4a7]
+    ~
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["browse", "-r", "@", "--print"]);
+    insta::assert_snapshot!(stdout, @r###"
+    https://example.com/repo/commit/85a1e2839620cf0b354d1ccb970927d040c2a4a7
+    "###);
+
+    // The URL is passed to the browse command
+    let stdout = test_env.jj_cmd_success(&repo_path, &["browse"]);
+    insta::assert_snapshot!(stdout, @r###"
+    opening https://example.com/repo/commit/85a1e2839620cf0b354d1ccb970927d040c2a4a7
+    "###);
+
+    // The change id can be used too
+    test_env.add_config(br#"browse.commit-url = "https://example.com/c/{change_id}""#);
+    let change_id = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "change_id"],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["browse", "--print"]);
+    assert_eq!(stdout, format!("https://example.com/c/{change_id}\n"));
+
+    test_env.add_config(br#"browse.command = ["false"]"#);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["browse", "-r", "root"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to open https://example.com/c/00000000000000000000000000000000: false exited with exit status: 1
+    "###);
+}
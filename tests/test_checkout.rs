@@ -14,7 +14,7 @@
 
 use std::path::Path;
 
-use crate::common::TestEnvironment;
+use crate::common::{get_stderr_string, get_stdout_string, TestEnvironment};
 
 pub mod common;
 
@@ -88,6 +88,39 @@ fn test_checkout() {
     "###);
 }
 
+#[test]
+fn test_checkout_windows_unsafe_paths() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "contents").unwrap();
+    std::fs::write(repo_path.join("con.txt"), "contents").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "unsafe"]);
+    test_env.jj_cmd_success(&repo_path, &["checkout", "root"]);
+
+    test_env.add_config(br#"working-copy.windows-safe-paths = true"#);
+    let assert = test_env
+        .jj_cmd(&repo_path, &["checkout", "unsafe"])
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stdout_string(&assert), @r###"
+    Working copy now at: 0d398fadee51 (no description set)
+    Added 1 files, modified 0 files, removed 0 files
+    "###);
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Skipped checking out con.txt: "con.txt" is a reserved name on Windows
+    "###);
+    assert!(repo_path.join("file").exists());
+    assert!(!repo_path.join("con.txt").exists());
+
+    // The skipped file is still in the working-copy commit
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files"]);
+    insta::assert_snapshot!(stdout, @r###"
+    con.txt
+    file
+    "###);
+}
+
 fn get_log_output(test_env: &TestEnvironment, cwd: &Path) -> String {
     test_env.jj_cmd_success(cwd, &["log", "-T", r#"commit_id " " description"#])
 }
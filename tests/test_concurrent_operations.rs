@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::common::TestEnvironment;
+use crate::common::{get_stderr_string, get_stdout_string, TestEnvironment};
 
 pub mod common;
 
@@ -39,6 +39,72 @@ fn test_concurrent_operation_divergence() {
     "###);
 }
 
+#[test]
+fn test_concurrent_operations_op_heads_and_merge() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    // There's a single head normally
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "heads", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @"add workspace 'default'");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "merge"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Nothing changed: the operation log has a single head.
+    "###);
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "message 1"]);
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["describe", "-m", "message 2", "--at-op", "@-"],
+    );
+
+    // The op log shows both heads without merging them
+    let assert = test_env
+        .jj_cmd(&repo_path, &["op", "log", "-T", "description"])
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stdout_string(&assert), @r###"
+    @ describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    | @ describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    |/  
+    o add workspace 'default'
+    o initialize repo
+    "###);
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    The operation log has 2 heads because of concurrent operations. Run `jj op merge` to merge them.
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "heads", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    "###);
+
+    // Merge them explicitly
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "merge"]);
+    assert!(
+        stdout.starts_with("Merged 2 operations into operation "),
+        "{stdout}"
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @   resolve concurrent operations
+    |\  
+    o | describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    | o describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    |/  
+    o add workspace 'default'
+    o initialize repo
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    o message 2
+    | @ message 1
+    |/  
+    o (no description set)
+    "###);
+}
+
 #[test]
 fn test_concurrent_operations_auto_rebase() {
     let test_env = TestEnvironment::default();
@@ -66,3 +132,52 @@ fn test_concurrent_operations_auto_rebase() {
     o 0000000000000000000000000000000000000000 (no description set)
     "###);
 }
+
+#[test]
+fn test_concurrent_operations_branches() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "main"]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    // Move the branch and create another one in concurrent operations
+    test_env.jj_cmd_success(&repo_path, &["branch", "set", "main"]);
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["branch", "create", "feature", "--at-op", "@-"],
+    );
+    // Set the same branch to unrelated targets in concurrent operations
+    test_env.jj_cmd_success(&repo_path, &["new", "root"]);
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["branch", "create", "conflicted", "-r", "main"],
+    );
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["branch", "create", "conflicted", "--at-op", "@-"],
+    );
+
+    let assert = test_env
+        .jj_cmd(
+            &repo_path,
+            &[
+                "branch",
+                "list",
+                "-T",
+                r#"name ": " target.short() if(conflict, " (conflicted)") "\n""#,
+            ],
+        )
+        .assert()
+        .success();
+    let stdout = get_stdout_string(&assert);
+    insta::assert_snapshot!(stdout, @r###"
+    Concurrent modification detected, resolving automatically.
+    conflicted: 230dd059e1b0 (conflicted)
+    feature: 230dd059e1b0
+    main: 230dd059e1b0
+    "###);
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    warning: Concurrent operations moved branch conflicted to different commits (use `jj branch list` to see the targets and `jj branch set conflicted -r <rev> --resolve` to pick one)
+    "###);
+}
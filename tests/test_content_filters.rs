@@ -0,0 +1,89 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_content_filters() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(
+        br#"
+        [filters.rot13]
+        clean = ["tr", "a-z", "n-za-m"]
+        smudge = ["tr", "n-za-m", "a-z"]
+        patterns = ["secrets/**"]
+        "#,
+    );
+
+    std::fs::create_dir(repo_path.join("secrets")).unwrap();
+    std::fs::write(repo_path.join("secrets").join("key"), "hello\n").unwrap();
+    std::fs::write(repo_path.join("plain"), "hello\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "add files"]);
+
+    // The filtered file is stored cleaned
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "@-", "secrets/key"]);
+    insta::assert_snapshot!(stdout, @"uryyb");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "@-", "plain"]);
+    insta::assert_snapshot!(stdout, @"hello");
+
+    // And smudged when it's checked out again
+    test_env.jj_cmd_success(&repo_path, &["co", "root"]);
+    assert!(!repo_path.join("secrets").exists());
+    test_env.jj_cmd_success(&repo_path, &["co", "description(\"add files\")"]);
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("secrets").join("key")).unwrap(),
+        "hello\n"
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @"");
+}
+
+#[test]
+fn test_content_filters_failure() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(
+        br#"
+        [filters.broken]
+        clean = ["sh", "-c", "echo 'no key' >&2; exit 1"]
+        patterns = ["*.secret"]
+        "#,
+    );
+
+    std::fs::write(repo_path.join("file.secret"), "contents").unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["status"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to snapshot the working copy: Filter 'broken' failed to clean file.secret:
+    no key
+    "###);
+
+    // Invalid config is reported
+    test_env.add_config(
+        br#"
+        [filters.invalid]
+        clean = ["cat"]
+        "#,
+    );
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["status"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: Invalid filter config: filters.invalid: missing `patterns`
+    "###);
+}
@@ -0,0 +1,421 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use itertools::Itertools;
+
+use crate::common::{get_stderr_string, get_stdout_string, TestEnvironment};
+
+pub mod common;
+
+#[test]
+fn test_debug_index_verify() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["debug", "index", "verify"]);
+    insta::assert_snapshot!(stdout, @"The index is OK (3 commits).");
+
+    // A damaged index file is detected when loading the index, and the index is
+    // rebuilt
+    let index_dir = repo_path.join(".jj").join("repo").join("index");
+    for entry in std::fs::read_dir(&index_dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_file() {
+            std::fs::write(&path, b"garbage").unwrap();
+        }
+    }
+    let assert = test_env
+        .jj_cmd(&repo_path, &["debug", "index", "verify"])
+        .assert()
+        .success();
+    let stderr = get_stderr_string(&assert);
+    assert!(stderr.starts_with("Warning: The index file"), "{stderr}");
+    let stdout = get_stdout_string(&assert);
+    insta::assert_snapshot!(stdout, @"The index is OK (3 commits).");
+}
+
+#[test]
+fn test_debug_cache_stats() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(
+        br#"[debug]
+    cache-stats = true
+    "#,
+    );
+    let assert = test_env
+        .jj_cmd(&repo_path, &["log", "-T", "commit_id"])
+        .assert()
+        .success();
+    let stderr = get_stderr_string(&assert);
+    assert!(stderr.starts_with("Store cache stats:\n"), "{stderr}");
+    assert!(stderr.contains("\n  commits: "), "{stderr}");
+    assert!(stderr.contains("\n  trees: "), "{stderr}");
+    assert!(stderr.contains("\n  files: "), "{stderr}");
+}
+
+#[test]
+fn test_debug_fetch_stats() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "contents").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "add file"]);
+    test_env.add_config(
+        br#"[debug]
+    fetch-stats = true
+    "#,
+    );
+    let assert = test_env
+        .jj_cmd(&repo_path, &["print", "-r", "@-", "file"])
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stdout_string(&assert), @"contents");
+    let stderr = get_stderr_string(&assert);
+    assert!(
+        stderr.starts_with("Store fetch stats (git backend):\n"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("\n  commits: "), "{stderr}");
+    assert!(stderr.contains("\n  trees: "), "{stderr}");
+    assert!(stderr.contains("\n  files: 1 fetches taking "), "{stderr}");
+}
+
+#[test]
+fn test_debug_pack() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "contents").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "add file"]);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["debug", "pack"]);
+    insta::assert_snapshot!(stdout, @"Packed 7 objects.");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["debug", "pack"]);
+    insta::assert_snapshot!(stdout, @"Nothing to pack.");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "@-", "file"]);
+    insta::assert_snapshot!(stdout, @"contents");
+
+    // Commands pack the objects automatically once there are enough of them
+    test_env.add_config(
+        br#"[store]
+    auto-pack-threshold = 3
+    "#,
+    );
+    std::fs::write(repo_path.join("file"), "new contents").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "modify file"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["debug", "pack"]);
+    insta::assert_snapshot!(stdout, @"Nothing to pack.");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "file"]);
+    insta::assert_snapshot!(stdout, @"new contents");
+}
+
+#[test]
+fn test_debug_graph() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "a"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "b"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", r#"c "quoted""#]);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["debug", "graph"]);
+    insta::assert_snapshot!(stdout, @r###"
+    digraph {
+      node [shape=box];
+      "3313123cfab74b9583a46a0650c3581fa4d9ffd8" [label="3313123cfab7\lc \"quoted\"\l", style=bold];
+      "3313123cfab74b9583a46a0650c3581fa4d9ffd8" -> "ae426da34c533cdc2c1163f6b26f73ec5237e3cf";
+      "ae426da34c533cdc2c1163f6b26f73ec5237e3cf" [label="ae426da34c53\lb\l"];
+      "ae426da34c533cdc2c1163f6b26f73ec5237e3cf" -> "bcd64d31e69973f08f7e083d629cacc51db822e0";
+      "bcd64d31e69973f08f7e083d629cacc51db822e0" [label="bcd64d31e699\la\l"];
+      "bcd64d31e69973f08f7e083d629cacc51db822e0" -> "0000000000000000000000000000000000000000";
+      "0000000000000000000000000000000000000000" [label="000000000000\l(no description set)\l"];
+    }
+    "###);
+
+    // Commits that are only indirectly connected get a dashed edge
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "debug",
+            "graph",
+            "-r",
+            "root | description(c)",
+            "-T",
+            "description.first_line()",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    digraph {
+      node [shape=box];
+      "3313123cfab74b9583a46a0650c3581fa4d9ffd8" [label="c \"quoted\"\l", style=bold];
+      "3313123cfab74b9583a46a0650c3581fa4d9ffd8" -> "0000000000000000000000000000000000000000" [style=dashed];
+      "0000000000000000000000000000000000000000" [label="(no description set)\l"];
+    }
+    "###);
+}
+
+#[test]
+fn test_debug_migrate_backend() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "main"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    std::fs::write(repo_path.join("file"), "b\n").unwrap();
+    let template = r#"description.first_line() " " branches"#;
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ second 
+    o first main
+    o (no description set)
+    "###);
+    let change_ids_template = r#"change_id "\n""#;
+    let change_ids_before = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-T", change_ids_template],
+    );
+    let op_log_before = test_env.jj_cmd_success(&repo_path, &["op", "log", "-T", "description"]);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["debug", "migrate-backend", "--to", "git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Migrated 5 commits to the git backend.
+    The old and new ids of the commits whose ids changed are listed in .jj/repo/store/migrated_commit_ids.
+    "###);
+    let store_path = repo_path.join(".jj").join("repo").join("store");
+    assert_eq!(
+        std::fs::read_to_string(store_path.join("backend")).unwrap(),
+        "git"
+    );
+    // The commits have new ids but the same changes
+    let id_map = std::fs::read_to_string(store_path.join("migrated_commit_ids")).unwrap();
+    let commit_id = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "main", "-T", "commit_id"],
+    );
+    assert!(id_map.contains(&format!(" {commit_id}\n")), "{id_map}");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ second 
+    o first main
+    o (no description set)
+    "###);
+    assert_eq!(
+        test_env.jj_cmd_success(
+            &repo_path,
+            &["log", "--no-graph", "-T", change_ids_template]
+        ),
+        change_ids_before
+    );
+    // The working copy is up to date
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 7109d93fe220 first
+    Working copy : 2a65b3de7174 second
+    Working copy changes:
+    M file
+    "###);
+    // The operation log was kept
+    assert_eq!(
+        test_env.jj_cmd_success(&repo_path, &["op", "log", "-T", "description"]),
+        op_log_before
+    );
+    test_env.jj_cmd_success(&repo_path, &["undo"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ second 
+    o first main
+    o (no description set)
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["debug", "migrate-backend", "--to", "git"]);
+    insta::assert_snapshot!(stderr, @"Error: The repo already uses the git backend");
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["debug", "migrate-backend", "--to", "local"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Migrated 5 commits to the local backend.
+    The old and new ids of the commits whose ids changed are listed in .jj/repo/store/migrated_commit_ids.
+    "###);
+    assert_eq!(
+        std::fs::read_to_string(store_path.join("backend")).unwrap(),
+        "local"
+    );
+}
+
+#[test]
+fn test_debug_change_ids() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["debug", "change-ids"]);
+    // The root commit and the two commits
+    assert_eq!(stdout.lines().count(), 3);
+    let change_id = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "change_id"],
+    );
+    assert!(stdout.contains(&format!(" {change_id}\n")), "{stdout}");
+    let recorded_path = test_env.env_root().join("change-ids");
+    std::fs::write(&recorded_path, &stdout).unwrap();
+
+    // The change ids survive a migration to another backend
+    test_env.jj_cmd_success(&repo_path, &["debug", "migrate-backend", "--to", "git"]);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["debug", "change-ids", "--check", "../change-ids"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    No change ids have changed.
+    "###);
+
+    // A commit whose change id differs from the recorded one is reported
+    let recorded = std::fs::read_to_string(&recorded_path).unwrap();
+    std::fs::write(
+        &recorded_path,
+        recorded.replace(&change_id, "00112233445566778899aabbccddeeff"),
+    )
+    .unwrap();
+    let assert = test_env
+        .jj_cmd(
+            &repo_path,
+            &["debug", "change-ids", "--check", "../change-ids"],
+        )
+        .assert()
+        .code(1);
+    insta::assert_snapshot!(get_stdout_string(&assert).replace(&change_id, "<change id>"), @r###"
+    Commit e4c4886e135d has change id <change id>, expected 00112233445566778899aabbccddeeff
+    "###);
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Error: Some change ids differ from the recorded ones
+    "###);
+
+    std::fs::write(&recorded_path, "not an identity\n").unwrap();
+    let stderr = test_env.jj_cmd_failure(
+        &repo_path,
+        &["debug", "change-ids", "--check", "../change-ids"],
+    );
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Invalid line 1 in ../change-ids: not an identity
+    "###);
+}
+
+#[test]
+fn test_debug_simplify_conflicts() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+
+    // Nothing to simplify, so no operation is created
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["debug", "simplify-conflicts", "-r", "root.."]);
+    insta::assert_snapshot!(stdout, @r###"
+    Simplified conflicts in 0 commits
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "log", "-T", "description"]);
+    assert!(!stdout.contains("simplify conflicts"), "{stdout}");
+}
+
+#[test]
+fn test_debug_snapshot_bench() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "contents").unwrap();
+
+    // The changes are hashed in each iteration but not recorded
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "--no-commit-working-copy",
+            "debug",
+            "snapshot-bench",
+            "--iterations",
+            "2",
+        ],
+    );
+    let lines = stdout.lines().collect_vec();
+    assert_eq!(lines.len(), 8, "{stdout}");
+    for iteration in [1, 2] {
+        let summary = lines[(iteration - 1) * 4];
+        assert!(
+            summary.starts_with(&format!("Snapshot {iteration}: ")),
+            "{stdout}"
+        );
+        assert!(
+            summary.ends_with(" in total (changed), 1 files hashed"),
+            "{stdout}"
+        );
+    }
+    assert!(lines[1].starts_with("  stat:  "), "{stdout}");
+    assert!(lines[2].starts_with("  hash:  "), "{stdout}");
+    assert!(lines[3].starts_with("  write: "), "{stdout}");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 000000000000 (no description set)
+    Working copy : feb7edf79ad1 (no description set)
+    Working copy changes:
+    A file
+    "###);
+
+    // Once the changes are recorded, there's nothing to hash
+    let stdout = test_env.jj_cmd_success(&repo_path, &["debug", "snapshot-bench"]);
+    assert!(
+        stdout.starts_with("Snapshot 1: ")
+            && stdout.contains(" in total (unchanged), 0 files hashed"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn test_debug_checkout_bench() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file1"), "1").unwrap();
+    std::fs::write(repo_path.join("file2"), "2").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["debug", "checkout-bench"]);
+    let lines = stdout.lines().collect_vec();
+    assert_eq!(lines.len(), 7, "{stdout}");
+    assert!(
+        lines[0].starts_with("Checkout of 000000000000: ")
+            && lines[0].ends_with(" in total, 0 files added, 0 updated, 2 removed"),
+        "{stdout}"
+    );
+    assert!(
+        lines[3].starts_with("Checkout of ")
+            && lines[3].ends_with(" in total, 2 files added, 0 updated, 0 removed"),
+        "{stdout}"
+    );
+    assert!(lines[6].starts_with("Saving the working copy state: "));
+    // The workspace itself was never checked out
+    assert_eq!(std::fs::read(repo_path.join("file1")).unwrap(), b"1");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 000000000000 (no description set)
+    Working copy : 445e0253df05 (no description set)
+    Working copy changes:
+    A file1
+    A file2
+    "###);
+}
@@ -106,3 +106,242 @@ JJ: Lines starting with "JJ: " (like this one) will be removed.
         .failure();
     assert!(get_stderr_string(&assert).contains("bad-jj-editor-from-jj-editor-env"));
 }
+
+#[test]
+fn test_describe_editor_args() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    let edit_script = test_env.set_up_fake_editor();
+    let editor_path = assert_cmd::cargo::cargo_bin("fake-editor");
+    let escaped_editor_path = editor_path.to_str().unwrap().replace('\\', r"\\");
+
+    // `ui.editor` can be a list of arguments with the file at `$path`
+    test_env.add_config(
+        format!(
+            r#"[ui]
+    editor = ["{escaped_editor_path}", "$path"]"#
+        )
+        .as_bytes(),
+    );
+    std::fs::write(&edit_script, "write\ndescription from list").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["describe"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Working copy now at: 0b4c7bfa323a description from list
+    "###);
+
+    // `commands.describe.editor` overrides `ui.editor` for `jj describe`
+    test_env.add_config(
+        br#"[commands.describe]
+    editor = ["bad-editor-for-describe", "$path"]"#,
+    );
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["describe"]);
+    assert!(stderr.contains("bad-editor-for-describe"));
+
+    // An empty list is an error
+    test_env.add_config(
+        br#"[commands.describe]
+    editor = []"#,
+    );
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["describe"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: ui.editor must not be empty
+    "###);
+}
+
+#[test]
+fn test_describe_with_diff() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "a\n").unwrap();
+    test_env.add_config(
+        br#"[ui]
+    describe-diff = "git""#,
+    );
+
+    // The diff is shown below the description, and it's removed along with
+    // everything else after the "ignore-rest" line
+    let edit_script = test_env.set_up_fake_editor();
+    std::fs::write(
+        &edit_script,
+        "expect
+
+JJ: Lines starting with \"JJ: \" (like this one) will be removed.
+JJ: ignore-rest
+diff --git a/file b/file
+new file mode 100644
+index 0000000000..7898192261
+--- /dev/null
++++ b/file
+@@ -1,0 +1,1 @@
++a
+\0write
+description with diff
+JJ: ignore-rest
++a
+",
+    )
+    .unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-r", "@", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ description with diff
+    ~
+    "###);
+
+    // An invalid setting is an error
+    test_env.add_config(
+        br#"[ui]
+    describe-diff = "bad""#,
+    );
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["describe"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: Invalid `ui.describe-diff`: bad (expected "none", "summary", "stat" or "git")
+    "###);
+}
+
+#[test]
+fn test_describe_multiple() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "second"]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+
+    // All descriptions are edited in one file, parents first, and the
+    // descendants are rebased
+    let edit_script = test_env.set_up_fake_editor();
+    std::fs::write(
+        &edit_script,
+        "expect
+JJ: describe 85a1e2839620 -------
+first
+
+JJ: describe d103ab3e2fa0 -------
+second
+
+JJ: Lines starting with \"JJ: \" (like this one) will be removed.
+\0write
+JJ: describe 85a1e2839620 -------
+first edited
+
+JJ: describe d103ab3e2fa0 -------
+second edited
+",
+    )
+    .unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["describe", "-r", "@- | @--"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Rebased 1 descendant commits
+    Working copy now at: 315c011f6ea9 (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ (no description set)
+    o second edited
+    o first edited
+    o (no description set)
+    "###);
+
+    // Unknown commits are an error
+    std::fs::write(&edit_script, "write\nJJ: describe 000000000000\nfoo\n").unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["describe", "-r", "@- | @--"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: The description file refers to unknown commit '000000000000'
+    "###);
+
+    // All revisions get the same description with `--message`
+    let stdout = test_env.jj_cmd_success(&repo_path, &["describe", "-r", "@- | @--", "-m", "same"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Rebased 1 descendant commits
+    Working copy now at: d4366f4c3898 (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ (no description set)
+    o same
+    o same
+    o (no description set)
+    "###);
+
+    // Or from a file with `--message-file`
+    std::fs::write(test_env.env_root().join("message"), "from file\n").unwrap();
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["describe", "@", "--message-file", "../message"],
+    );
+    insta::assert_snapshot!(stdout, @"Working copy now at: e90e54df90b5 from file");
+}
+
+#[test]
+fn test_describe_co_authors() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "describe",
+            "-m",
+            "subject",
+            "--co-author",
+            "Alice <alice@example.com>",
+            "--co-author",
+            "Bob <bob@example.com>",
+        ],
+    );
+    let template = r#"description.first_line() " [" co_authors "]""#;
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-r", "@", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ subject [Alice <alice@example.com>, Bob <bob@example.com>]
+    ~
+    "###);
+
+    // The co-authors are stored as trailers in the Git commit, but they're not
+    // part of the description
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "description"],
+    );
+    insta::assert_snapshot!(stdout, @"subject");
+    let commit_id = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "commit_id"],
+    );
+    let git_repo = git2::Repository::open(repo_path.join(".jj/repo/store/git")).unwrap();
+    let git_commit = git_repo
+        .find_commit(git2::Oid::from_str(&commit_id).unwrap())
+        .unwrap();
+    insta::assert_snapshot!(git_commit.message().unwrap(), @r###"
+    subject
+
+    Co-authored-by: Alice <alice@example.com>
+    Co-authored-by: Bob <bob@example.com>
+    "###);
+
+    // Setting only co-authors leaves the description alone
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["describe", "--co-author", "Carol <carol@example.com>"],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-r", "@", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ subject [Carol <carol@example.com>]
+    ~
+    "###);
+    test_env.jj_cmd_success(&repo_path, &["describe", "--no-co-authors"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-r", "@", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ subject []
+    ~
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["describe", "--co-author", "alice"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Invalid co-author "alice": expected "Name <email>"
+    "###);
+}
@@ -71,6 +71,99 @@ fn test_diff_basic() {
     @@ -1,0 +1,1 @@
     +foo
     "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    file1 | 1 -
+    file2 | 1 +
+    file3 | 1 +
+    3 files changed, 2 insertions(+), 1 deletion(-)
+    "###);
+
+    // Only one format can be requested
+    test_env.jj_cmd_cli_error(&repo_path, &["diff", "--stat", "--git"]);
+}
+
+#[test]
+fn test_diff_json() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "a\nb\nc\nd\ne\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("file1")).unwrap();
+    std::fs::write(repo_path.join("file2"), "a\nB\nc\nd\ne\nf\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--json"]);
+    insta::assert_snapshot!(stdout, @r###"
+    [
+      {
+        "hunks": [
+          {
+            "new_lines": 0,
+            "new_start": 1,
+            "old_lines": 1,
+            "old_start": 1
+          }
+        ],
+        "new": null,
+        "old": {
+          "executable": false,
+          "id": "257cc5642cb1a054f08cc83f2d943e56fd3ebe99",
+          "type": "file"
+        },
+        "path": "file1",
+        "status": "removed"
+      },
+      {
+        "hunks": [
+          {
+            "new_lines": 1,
+            "new_start": 2,
+            "old_lines": 1,
+            "old_start": 2
+          },
+          {
+            "new_lines": 1,
+            "new_start": 6,
+            "old_lines": 0,
+            "old_start": 6
+          }
+        ],
+        "new": {
+          "executable": false,
+          "id": "91ac79b071ce3fb54356b268bef565ad6c7e63f5",
+          "type": "file"
+        },
+        "old": {
+          "executable": false,
+          "id": "940532533944dd159bfd11136fac2ee35872de38",
+          "type": "file"
+        },
+        "path": "file2",
+        "status": "modified"
+      }
+    ]
+    "###);
+}
+
+#[test]
+fn test_diff_stat_scaled() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    // The bars are scaled down to fit, but small changes still get one character
+    std::fs::write(repo_path.join("large"), "line\n".repeat(100)).unwrap();
+    std::fs::write(repo_path.join("small"), "line\n").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    large | 100 ++++++++++++++++++++++++++++++++++++++++
+    small |   1 +
+    2 files changed, 101 insertions(+), 0 deletions(-)
+    "###);
 }
 
 #[test]
@@ -313,3 +406,159 @@ fn test_color_words_diff_missing_newline() {
        9     : I
     "###);
 }
+
+#[test]
+fn test_diff_files_on_disk() {
+    let test_env = TestEnvironment::default();
+    let root = test_env.env_root();
+    std::fs::create_dir_all(root.join("left").join("dir")).unwrap();
+    std::fs::create_dir_all(root.join("right").join("dir")).unwrap();
+    std::fs::write(root.join("left").join("file1"), "foo\n").unwrap();
+    std::fs::write(root.join("left").join("dir").join("file2"), "foo\nbar\n").unwrap();
+    std::fs::write(root.join("left").join("same"), "same\n").unwrap();
+    std::fs::write(root.join("right").join("dir").join("file2"), "foo\nbaz\n").unwrap();
+    std::fs::write(root.join("right").join("file3"), "new\n").unwrap();
+    std::fs::write(root.join("right").join("same"), "same\n").unwrap();
+
+    // A pair of files, outside any repo
+    let stdout = test_env.jj_cmd_success(
+        root,
+        &[
+            "diff",
+            "--from-file",
+            "left/dir/file2",
+            "--to-file",
+            "right/dir/file2",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Modified regular file right/dir/file2:
+       1    1: foo
+       2    2: barbaz
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        root,
+        &[
+            "diff",
+            "--git",
+            "--from-file",
+            "left/dir/file2",
+            "--to-file",
+            "right/dir/file2",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/left/dir/file2 b/right/dir/file2
+    --- a/left/dir/file2
+    +++ b/right/dir/file2
+    @@ -1,2 +1,2 @@
+     foo
+    -bar
+    +baz
+    "###);
+
+    // Identical files
+    let stdout = test_env.jj_cmd_success(
+        root,
+        &[
+            "diff",
+            "--from-file",
+            "left/same",
+            "--to-file",
+            "right/same",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @"");
+
+    // Directories
+    let stdout = test_env.jj_cmd_success(
+        root,
+        &["diff", "-s", "--from-file", "left", "--to-file", "right"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    M dir/file2
+    R file1
+    A file3
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        root,
+        &[
+            "diff",
+            "--stat",
+            "--from-file",
+            "left",
+            "--to-file",
+            "right",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    dir/file2 | 2 +-
+    file1     | 1 -
+    file3     | 1 +
+    3 files changed, 2 insertions(+), 2 deletions(-)
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(
+        root,
+        &["diff", "--from-file", "left", "--to-file", "right/same"],
+    );
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Cannot compare a file with a directory: left and right/same
+    "###);
+    let stderr = test_env.jj_cmd_failure(
+        root,
+        &[
+            "diff",
+            "--from-file",
+            "left/missing",
+            "--to-file",
+            "right/same",
+        ],
+    );
+    assert!(stderr.starts_with("Error: Failed to read "), "{stderr}");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_diff_files_on_disk_symlinks_and_modes() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    let test_env = TestEnvironment::default();
+    let root = test_env.env_root();
+    std::fs::create_dir(root.join("left")).unwrap();
+    std::fs::create_dir(root.join("right")).unwrap();
+    std::fs::write(root.join("left").join("script"), "echo\n").unwrap();
+    std::fs::write(root.join("right").join("script"), "echo\n").unwrap();
+    let mut permissions = std::fs::metadata(root.join("right").join("script"))
+        .unwrap()
+        .permissions();
+    permissions.set_mode(0o755);
+    std::fs::set_permissions(root.join("right").join("script"), permissions).unwrap();
+    symlink("script", root.join("right").join("link")).unwrap();
+    // A symlink loop isn't followed
+    symlink("..", root.join("right").join("parent")).unwrap();
+
+    let stdout = test_env.jj_cmd_success(
+        root,
+        &["diff", "--git", "--from-file", "left", "--to-file", "right"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/link b/link
+    new file mode 120000
+    --- /dev/null
+    +++ b/link
+    @@ -1,0 +1,1 @@
+    +script
+    \ No newline at end of file
+    diff --git a/parent b/parent
+    new file mode 120000
+    --- /dev/null
+    +++ b/parent
+    @@ -1,0 +1,1 @@
+    +..
+    \ No newline at end of file
+    diff --git a/script b/script
+    old mode 100644
+    new mode 100755
+    "###);
+}
@@ -0,0 +1,120 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+
+use std::path::Path;
+
+use itertools::Itertools;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_events_command() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    let log_path = test_env.env_root().join("events.log");
+    test_env.add_config(
+        format!(
+            r#"events.command = ["sh", "-c", "cat >> '{}'"]"#,
+            log_path.to_str().unwrap()
+        )
+        .as_bytes(),
+    );
+
+    std::fs::write(repo_path.join("file"), "contents").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "main"]);
+    insta::assert_snapshot!(take_events(&log_path), @r###"
+    pre-snapshot
+    post-snapshot changed=true
+    branch-moved branch="main"
+    "###);
+
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    insta::assert_snapshot!(take_events(&log_path), @r###"
+    pre-snapshot
+    post-snapshot changed=false
+    "###);
+
+    test_env.jj_cmd_success(&repo_path, &["checkout", "root"]);
+    insta::assert_snapshot!(take_events(&log_path), @r###"
+    pre-snapshot
+    post-snapshot changed=false
+    pre-checkout
+    post-checkout removed_files=1
+    "###);
+}
+
+#[test]
+fn test_events_command_rejects() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(
+        br#"events.command = ["sh", "-c", "if grep -q pre-snapshot; then exit 1; fi"]"#,
+    );
+
+    std::fs::write(repo_path.join("file"), "contents").unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["status"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: The pre-snapshot event was rejected by the events.command hook
+    "###);
+}
+
+#[test]
+fn test_events_command_rejects_checkout() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "contents").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    test_env.add_config(
+        br#"events.command = ["sh", "-c", "if grep -q pre-checkout; then exit 1; fi"]"#,
+    );
+
+    let op_log_before = test_env.jj_cmd_success(&repo_path, &["op", "log"]);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["checkout", "root"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: The pre-checkout event was rejected by the events.command hook
+    "###);
+    // The repo and the working copy are left unchanged
+    assert_eq!(
+        test_env.jj_cmd_success(&repo_path, &["op", "log"]),
+        op_log_before
+    );
+    assert!(repo_path.join("file").exists());
+}
+
+/// Summarizes the events logged so far and clears the log.
+fn take_events(log_path: &Path) -> String {
+    let log = std::fs::read_to_string(log_path).unwrap_or_default();
+    std::fs::remove_file(log_path).ok();
+    log.lines()
+        .map(|line| {
+            let event: serde_json::Value = serde_json::from_str(line).unwrap();
+            let mut summary = event["event"].as_str().unwrap().to_string();
+            for key in ["changed", "branch", "added_files", "removed_files"] {
+                match &event[key] {
+                    serde_json::Value::Null => {}
+                    serde_json::Value::Number(n) if n.as_u64() == Some(0) => {}
+                    value => summary.push_str(&format!(" {key}={value}")),
+                }
+            }
+            summary + "\n"
+        })
+        .join("")
+}
@@ -0,0 +1,42 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_files_ignore_case() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::create_dir(repo_path.join("Docs")).unwrap();
+    std::fs::write(repo_path.join("Docs").join("README.md"), "contents").unwrap();
+    std::fs::write(repo_path.join("docs.txt"), "contents").unwrap();
+    std::fs::write(repo_path.join("other"), "contents").unwrap();
+
+    // Paths are matched case-sensitively by default
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files", "docs"]);
+    insta::assert_snapshot!(stdout, @"");
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files", "-i", "docs"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Docs/README.md
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files", "--ignore-case", "DOCS/readme.MD"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Docs/README.md
+    "###);
+}
@@ -0,0 +1,118 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_fix() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(
+        br#"
+        [fix.tools.upper]
+        command = ["tr", "a-z", "A-Z"]
+        patterns = ["*.txt"]
+        "#,
+    );
+
+    std::fs::write(repo_path.join("file.txt"), "a\n").unwrap();
+    std::fs::write(repo_path.join("other"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "a"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "b"]);
+    std::fs::write(repo_path.join("file2.txt"), "b\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["fix", "-r", "@-"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Fixed 1 commits
+    Rebased 1 descendant commits
+    Working copy now at: 81a5a2fa7f1d b
+    Added 0 files, modified 1 files, removed 0 files
+    "###);
+    // The fixed commit and the working-copy commit on top of it
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "@-", "file.txt"]);
+    insta::assert_snapshot!(stdout, @"A");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "@-", "other"]);
+    insta::assert_snapshot!(stdout, @"a");
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("file.txt")).unwrap(),
+        "A\n"
+    );
+    // The file added in the working-copy commit wasn't fixed
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("file2.txt")).unwrap(),
+        "b\n"
+    );
+
+    // Fixing the working-copy commit too doesn't undo the fixes in its parent
+    let stdout = test_env.jj_cmd_success(&repo_path, &["fix", "-r", "root..@"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Fixed 1 commits
+    Working copy now at: 49d727e0dfc9 b
+    Added 0 files, modified 1 files, removed 0 files
+    "###);
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("file2.txt")).unwrap(),
+        "B\n"
+    );
+}
+
+#[test]
+fn test_fix_tool_fails() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(
+        br#"
+        [fix.tools.fail]
+        command = ["sh", "-c", "grep -q ok && echo fixed"]
+        patterns = ["**"]
+        "#,
+    );
+
+    std::fs::write(repo_path.join("good"), "ok\n").unwrap();
+    std::fs::write(repo_path.join("bad"), "not\n").unwrap();
+    let assert = test_env.jj_cmd(&repo_path, &["fix"]).assert().success();
+    insta::assert_snapshot!(common::get_stdout_string(&assert), @r###"
+    Fixed 1 commits
+    Working copy now at: d11758d7a883 (no description set)
+    Added 0 files, modified 1 files, removed 0 files
+    "###);
+    insta::assert_snapshot!(common::get_stderr_string(&assert), @r###"
+    Fix tool 'fail' failed on bad
+    "###);
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("good")).unwrap(),
+        "fixed\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("bad")).unwrap(),
+        "not\n"
+    );
+}
+
+#[test]
+fn test_fix_no_tools() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["fix"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: No tools configured in fix.tools
+    "###);
+}
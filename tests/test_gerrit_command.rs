@@ -0,0 +1,148 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::{Path, PathBuf};
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+fn set_up() -> (TestEnvironment, PathBuf) {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    let git_repo = git2::Repository::init_bare(&git_repo_path).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let empty_tree_oid = git_repo.treebuilder(None).unwrap().write().unwrap();
+    let empty_tree = git_repo.find_tree(empty_tree_oid).unwrap();
+    git_repo
+        .commit(
+            Some("refs/heads/main"),
+            &signature,
+            &signature,
+            "initial",
+            &empty_tree,
+            &[],
+        )
+        .unwrap();
+    test_env.jj_cmd_success(
+        test_env.env_root(),
+        &["git", "clone", git_repo_path.to_str().unwrap(), "repo"],
+    );
+    let repo_path = test_env.env_root().join("repo");
+    (test_env, repo_path)
+}
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(
+        repo_path,
+        &[
+            "log",
+            "-T",
+            r#"description.first_line() " [" gerrit_review "]""#,
+        ],
+    )
+}
+
+/// Checks that the Change-Id of each of the revisions is derived from its
+/// change id, and returns the number of revisions.
+fn check_change_ids(test_env: &TestEnvironment, repo_path: &Path, revisions: &str) -> usize {
+    let stdout = test_env.jj_cmd_success(
+        repo_path,
+        &[
+            "log",
+            "--no-graph",
+            "-r",
+            revisions,
+            "-T",
+            r#"change_id " " gerrit_change_id "\n""#,
+        ],
+    );
+    for line in stdout.lines() {
+        let (change_id, gerrit_change_id) = line.split_once(' ').unwrap();
+        assert_eq!(gerrit_change_id, format!("I6a6a6964{change_id}"));
+    }
+    stdout.lines().count()
+}
+
+fn get_uploaded_description(test_env: &TestEnvironment) -> String {
+    let git_repo = git2::Repository::open(test_env.env_root().join("git-repo")).unwrap();
+    let commit = git_repo
+        .find_reference("refs/for/main")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    let message = commit.message().unwrap().to_string();
+    let parent_message = commit.parent(0).unwrap().message().unwrap().to_string();
+    format!("{parent_message}---\n{message}")
+}
+
+#[test]
+fn test_gerrit_upload() {
+    let (test_env, repo_path) = set_up();
+    test_env.jj_cmd_success(&repo_path, &["checkout", "main"]);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "second\n\nBug: 123"]);
+
+    // Uploading adds Change-Id trailers to the whole stack and pushes the head
+    let stdout = test_env.jj_cmd_success(&repo_path, &["gerrit", "upload"]);
+    assert!(stdout.starts_with("Added Change-Id to 2 commits\nUploading "));
+    assert!(stdout.contains(" to refs/for/main on origin\n"));
+    assert_eq!(check_change_ids(&test_env, &repo_path, "main..@"), 2);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ second [uploaded]
+    o first [uploaded]
+    o initial []
+    ~
+    "###);
+    let uploaded = get_uploaded_description(&test_env);
+    assert!(uploaded.starts_with("first\n\nChange-Id: I6a6a6964"));
+    assert!(uploaded.contains("---\nsecond\n\nBug: 123\nChange-Id: I6a6a6964"));
+
+    // Rewriting a commit keeps its Change-Id, but it needs to be uploaded again
+    let description = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@-", "-T", "description"],
+    );
+    let description = description.replace("first", "first, amended");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-r", "@-", "-m", &description]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ second [needs-upload]
+    o first, amended [needs-upload]
+    o initial []
+    ~
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["gerrit", "upload"]);
+    assert!(!stdout.contains("Added Change-Id"));
+    assert_eq!(check_change_ids(&test_env, &repo_path, "main..@"), 2);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ second [uploaded]
+    o first, amended [uploaded]
+    o initial []
+    ~
+    "###);
+}
+
+#[test]
+fn test_gerrit_upload_checks() {
+    let (test_env, repo_path) = set_up();
+    test_env.jj_cmd_success(&repo_path, &["checkout", "main"]);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["gerrit", "upload"]);
+    assert!(stderr.ends_with("since it has no description\n"));
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["gerrit", "upload", "-r", "main"]);
+    insta::assert_snapshot!(stderr, @"Error: The revisions are already on the remote");
+}
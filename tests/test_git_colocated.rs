@@ -40,6 +40,28 @@ fn test_git_colocated() {
     );
 }
 
+#[test]
+fn test_git_colocated_at_op() {
+    let test_env = TestEnvironment::default();
+    let workspace_root = test_env.env_root().join("repo");
+    let git_repo = git2::Repository::init(&workspace_root).unwrap();
+    test_env.jj_cmd_success(&workspace_root, &["init", "--git-repo", "."]);
+    std::fs::write(workspace_root.join("file"), "contents").unwrap();
+    test_env.jj_cmd_success(&workspace_root, &["close", "-m", "add a file"]);
+    std::fs::write(workspace_root.join("file"), "modified").unwrap();
+    test_env.jj_cmd_success(&workspace_root, &["close", "-m", "modify a file"]);
+    let git_head = git_repo.head().unwrap().target().unwrap();
+
+    // Checking out another commit at an earlier operation doesn't touch the working
+    // copy or the Git HEAD
+    test_env.jj_cmd_success(&workspace_root, &["co", "@-", "--at-op", "@-"]);
+    assert_eq!(git_repo.head().unwrap().target().unwrap(), git_head);
+    assert_eq!(
+        std::fs::read_to_string(workspace_root.join("file")).unwrap(),
+        "modified"
+    );
+}
+
 #[test]
 fn test_git_colocated_rebase_on_import() {
     let test_env = TestEnvironment::default();
@@ -79,3 +101,44 @@ fn test_git_colocated_rebase_on_import() {
     o 0000000000000000000000000000000000000000 
     "###);
 }
+
+#[test]
+fn test_git_colocated_git_config_fallback() {
+    let test_env = TestEnvironment::default();
+    let workspace_root = test_env.env_root().join("repo");
+    let git_repo = git2::Repository::init(&workspace_root).unwrap();
+    let mut git_config = git_repo.config().unwrap();
+    git_config.set_str("user.name", "Git User").unwrap();
+    git_config
+        .set_str("user.email", "git.user@example.com")
+        .unwrap();
+    std::fs::write(workspace_root.join(".git/info/exclude"), "excluded\n").unwrap();
+    let jj_cmd_without_user = |args: &[&str]| {
+        let mut cmd = test_env.jj_cmd(&workspace_root, args);
+        cmd.env_remove("JJ_USER").env_remove("JJ_EMAIL");
+        let assert = cmd.assert().success().stderr("");
+        common::get_stdout_string(&assert)
+    };
+    jj_cmd_without_user(&["init", "--git-repo", "."]);
+
+    // The user from the Git config is used if our config doesn't set one, and
+    // files excluded in .git/info/exclude aren't added
+    std::fs::write(workspace_root.join("file"), "contents").unwrap();
+    std::fs::write(workspace_root.join("excluded"), "contents").unwrap();
+    jj_cmd_without_user(&["close", "-m", "add a file"]);
+    let template = r#"author.name() " <" author.email() "> " committer.name() " " files"#;
+    let stdout = jj_cmd_without_user(&["log", "-r", "@-", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    o Git User <git.user@example.com> Git User file
+    ~
+    "###);
+
+    // Our config takes precedence
+    test_env.add_config(br#"user.name = "Jj User""#);
+    jj_cmd_without_user(&["describe", "-r", "@-", "-m", "add a file again"]);
+    let stdout = jj_cmd_without_user(&["log", "-r", "@-", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    o Git User <git.user@example.com> Jj User file
+    ~
+    "###);
+}
@@ -0,0 +1,78 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::{get_stdout_string, TestEnvironment};
+
+pub mod common;
+
+#[test]
+fn test_git_fast_export_import() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "contents\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "main"]);
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "first"]);
+
+    let stream = test_env.jj_cmd_success(&repo_path, &["git", "fast-export", "-r", "main"]);
+    insta::assert_snapshot!(stream, @r###"
+    commit refs/heads/main
+    mark :1
+    author Test User <test.user@example.com> 981147907 +0700
+    committer Test User <test.user@example.com> 981147910 +0700
+    data 5
+    first
+    M 100644 inline file
+    data 9
+    contents
+    "###);
+
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "other", "--git"]);
+    let other_path = test_env.env_root().join("other");
+    let assert = test_env
+        .jj_cmd(&other_path, &["git", "fast-import"])
+        .write_stdin(stream)
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stdout_string(&assert), @"Imported 1 commits, updated 1 branches and 0 tags");
+    let stdout = test_env.jj_cmd_success(
+        &other_path,
+        &["log", "-r", "main", "-T", r#"branches " " description"#],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    o main first
+    ~
+    "###);
+    let stdout = test_env.jj_cmd_success(&other_path, &["print", "-r", "main", "file"]);
+    insta::assert_snapshot!(stdout, @"contents");
+}
+
+#[test]
+fn test_git_fast_import_invalid() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(
+        test_env.env_root().join("stream"),
+        "commit refs/heads/main\n",
+    )
+    .unwrap();
+
+    let stderr =
+        test_env.jj_cmd_failure(&repo_path, &["git", "fast-import", "--file", "../stream"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to import stream: Line 1: Expected committer
+    "###);
+}
@@ -0,0 +1,85 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+fn create_commit(git_repo: &git2::Repository, ref_name: &str, message: &str) {
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let tree_id = git_repo.treebuilder(None).unwrap().write().unwrap();
+    let tree = git_repo.find_tree(tree_id).unwrap();
+    git_repo
+        .commit(Some(ref_name), &signature, &signature, message, &tree, &[])
+        .unwrap();
+}
+
+#[test]
+fn test_git_fetch_prune() {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    let git_repo = git2::Repository::init_bare(&git_repo_path).unwrap();
+    create_commit(&git_repo, "refs/heads/main", "main");
+    create_commit(&git_repo, "refs/heads/feature", "feature");
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "--git", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "git",
+            "remote",
+            "add",
+            "origin",
+            git_repo_path.to_str().unwrap(),
+        ],
+    );
+    test_env.jj_cmd_success(&repo_path, &["git", "fetch"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    feature: 422129c685f3 feature
+    main: 1630028ea7ee main
+    "###);
+
+    git_repo
+        .find_reference("refs/heads/feature")
+        .unwrap()
+        .delete()
+        .unwrap();
+    // Without --prune, the branch is kept
+    test_env.jj_cmd_success(&repo_path, &["git", "fetch"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    feature: 422129c685f3 feature
+    main: 1630028ea7ee main
+    "###);
+
+    test_env.jj_cmd_success(&repo_path, &["git", "fetch", "--prune"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @"main: 1630028ea7ee main");
+
+    // Pruning can be enabled in the config. A branch that was moved locally is
+    // kept.
+    test_env.add_config(b"git.fetch-prune = true");
+    test_env.jj_cmd_success(&repo_path, &["new", "main"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "set", "main", "-r", "@"]);
+    git_repo
+        .find_reference("refs/heads/main")
+        .unwrap()
+        .delete()
+        .unwrap();
+    test_env.jj_cmd_success(&repo_path, &["git", "fetch"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @"main: 62144a9de31f (no description set)");
+}
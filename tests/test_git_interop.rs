@@ -0,0 +1,386 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests that round-trip repos between jj and the `git` binary, to catch
+//! interop problems that tests using only libgit2 would miss. Run them with
+//! `cargo test --features git-interop-tests --test test_git_interop`. They
+//! require `git` to be on the `PATH`.
+
+#![cfg(feature = "git-interop-tests")]
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+/// Runs `git` with a hermetic config and fixed author/committer, checks that it
+/// succeeded, and returns its stdout.
+fn git(test_env: &TestEnvironment, dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .env("HOME", test_env.home_dir())
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", test_env.home_dir().join(".gitconfig"))
+        .env("GIT_AUTHOR_NAME", "Git User")
+        .env("GIT_AUTHOR_EMAIL", "git.user@example.com")
+        .env("GIT_AUTHOR_DATE", "2001-02-03T04:05:06+07:00")
+        .env("GIT_COMMITTER_NAME", "Git User")
+        .env("GIT_COMMITTER_EMAIL", "git.user@example.com")
+        .env("GIT_COMMITTER_DATE", "2001-02-03T04:05:06+07:00")
+        .output()
+        .expect("failed to run git");
+    assert!(
+        output.status.success(),
+        "git {args:?} failed:\n{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn git_init(test_env: &TestEnvironment, path: &Path, bare: bool) {
+    let mut args = vec!["init", "--quiet", "--initial-branch=main"];
+    if bare {
+        args.push("--bare");
+    }
+    args.push(path.to_str().unwrap());
+    git(test_env, test_env.env_root(), &args);
+}
+
+fn git_commit_file(test_env: &TestEnvironment, dir: &Path, name: &str, content: &str) {
+    std::fs::write(dir.join(name), content).unwrap();
+    git(test_env, dir, &["add", name]);
+    git(
+        test_env,
+        dir,
+        &["commit", "--quiet", "-m", &format!("write {name}")],
+    );
+}
+
+/// The Git repo backing a non-colocated jj repo
+fn backing_git_dir(jj_repo_path: &Path) -> PathBuf {
+    jj_repo_path
+        .join(".jj")
+        .join("repo")
+        .join("store")
+        .join("git")
+}
+
+/// The refs under `prefix` (e.g. "refs/heads/") in a Git repo, with the prefix
+/// stripped, and their targets
+fn git_refs(test_env: &TestEnvironment, git_dir: &Path, prefix: &str) -> BTreeMap<String, String> {
+    git(
+        test_env,
+        git_dir,
+        &["for-each-ref", "--format=%(refname) %(objectname)", prefix],
+    )
+    .lines()
+    .map(|line| {
+        let (name, target) = line.split_once(' ').unwrap();
+        (
+            name.strip_prefix(prefix).unwrap().to_string(),
+            target.to_string(),
+        )
+    })
+    .filter(|(name, _)| name != "HEAD")
+    .collect()
+}
+
+fn git_branches(test_env: &TestEnvironment, git_dir: &Path) -> BTreeMap<String, String> {
+    git_refs(test_env, git_dir, "refs/heads/")
+}
+
+/// The local branches jj knows about, with their targets
+fn jj_branches(test_env: &TestEnvironment, repo_path: &Path) -> BTreeMap<String, String> {
+    let stdout = test_env.jj_cmd_success(
+        repo_path,
+        &[
+            "log",
+            "--no-graph",
+            "-r",
+            "branches()",
+            "-T",
+            r#"branches " " commit_id "\n""#,
+        ],
+    );
+    let mut branches = BTreeMap::new();
+    for line in stdout.lines() {
+        let (names, commit_id) = line.rsplit_once(' ').unwrap();
+        for name in names.split_whitespace() {
+            // Remote branches are listed as e.g. "main@origin"
+            if !name.contains('@') {
+                branches.insert(
+                    name.trim_end_matches('*').to_string(),
+                    commit_id.to_string(),
+                );
+            }
+        }
+    }
+    branches
+}
+
+/// All objects reachable from the given commit
+fn reachable_objects(test_env: &TestEnvironment, git_dir: &Path, rev: &str) -> BTreeSet<String> {
+    git(test_env, git_dir, &["rev-list", "--objects", rev])
+        .lines()
+        .map(|line| line.split(' ').next().unwrap().to_string())
+        .collect()
+}
+
+/// Checks that the same objects are reachable from the given commits in both
+/// repos, and that `git fsck` is happy with `actual_git_dir`.
+fn assert_objects_match<'a>(
+    test_env: &TestEnvironment,
+    expected_git_dir: &Path,
+    actual_git_dir: &Path,
+    commit_ids: impl IntoIterator<Item = &'a String>,
+) {
+    for commit_id in commit_ids {
+        assert_eq!(
+            reachable_objects(test_env, actual_git_dir, commit_id),
+            reachable_objects(test_env, expected_git_dir, commit_id)
+        );
+    }
+    git(
+        test_env,
+        actual_git_dir,
+        &["fsck", "--strict", "--no-dangling"],
+    );
+}
+
+#[test]
+fn test_git_interop_clone() {
+    let test_env = TestEnvironment::default();
+    let source_path = test_env.env_root().join("source");
+    git_init(&test_env, &source_path, false);
+    git_commit_file(&test_env, &source_path, "file", "contents\n");
+    std::fs::create_dir(source_path.join("dir")).unwrap();
+    git_commit_file(&test_env, &source_path, "dir/nested", "nested\n");
+    git(&test_env, &source_path, &["branch", "feature", "HEAD~"]);
+
+    test_env.jj_cmd_success(test_env.env_root(), &["git", "clone", "source", "clone"]);
+    let clone_path = test_env.env_root().join("clone");
+    let source_branches = git_branches(&test_env, &source_path);
+    let backing_git_dir = backing_git_dir(&clone_path);
+    assert_eq!(
+        git_refs(&test_env, &backing_git_dir, "refs/remotes/origin/"),
+        source_branches
+    );
+    assert_objects_match(
+        &test_env,
+        &source_path,
+        &backing_git_dir,
+        source_branches.values(),
+    );
+    assert_eq!(
+        jj_branches(&test_env, &clone_path),
+        git_branches(&test_env, &source_path)
+    );
+    // The working copy matches what `git` checks out
+    assert_eq!(
+        std::fs::read_to_string(clone_path.join("dir").join("nested")).unwrap(),
+        "nested\n"
+    );
+}
+
+#[test]
+fn test_git_interop_fetch() {
+    let test_env = TestEnvironment::default();
+    let source_path = test_env.env_root().join("source");
+    git_init(&test_env, &source_path, false);
+    git_commit_file(&test_env, &source_path, "file", "1\n");
+    test_env.jj_cmd_success(
+        test_env.env_root(),
+        &["git", "clone", source_path.to_str().unwrap(), "clone"],
+    );
+    let clone_path = test_env.env_root().join("clone");
+
+    // New commits, a new branch, and a rewritten branch all come through
+    git_commit_file(&test_env, &source_path, "file", "2\n");
+    git(
+        &test_env,
+        &source_path,
+        &["checkout", "--quiet", "-b", "other"],
+    );
+    git_commit_file(&test_env, &source_path, "other", "other\n");
+    git(
+        &test_env,
+        &source_path,
+        &["commit", "--quiet", "--amend", "-m", "amended"],
+    );
+    test_env.jj_cmd_success(&clone_path, &["git", "fetch"]);
+    let source_branches = git_branches(&test_env, &source_path);
+    assert_objects_match(
+        &test_env,
+        &source_path,
+        &backing_git_dir(&clone_path),
+        source_branches.values(),
+    );
+    assert_eq!(
+        jj_branches(&test_env, &clone_path),
+        git_branches(&test_env, &source_path)
+    );
+
+    // So does a deleted branch when pruning
+    git(&test_env, &source_path, &["checkout", "--quiet", "main"]);
+    git(
+        &test_env,
+        &source_path,
+        &["branch", "--quiet", "-D", "other"],
+    );
+    test_env.jj_cmd_success(&clone_path, &["git", "fetch", "--prune"]);
+    assert_eq!(
+        jj_branches(&test_env, &clone_path),
+        git_branches(&test_env, &source_path)
+    );
+}
+
+#[test]
+fn test_git_interop_push() {
+    let test_env = TestEnvironment::default();
+    let remote_path = test_env.env_root().join("remote.git");
+    git_init(&test_env, &remote_path, true);
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "git",
+            "remote",
+            "add",
+            "origin",
+            remote_path.to_str().unwrap(),
+        ],
+    );
+
+    std::fs::write(repo_path.join("file"), "contents\n").unwrap();
+    std::fs::create_dir(repo_path.join("dir")).unwrap();
+    std::fs::write(repo_path.join("dir").join("nested"), "nested\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "main"]);
+    test_env.jj_cmd_success(&repo_path, &["git", "push", "--branch", "main"]);
+    let branches = jj_branches(&test_env, &repo_path);
+    assert_eq!(git_branches(&test_env, &remote_path), branches);
+    assert_objects_match(
+        &test_env,
+        &backing_git_dir(&repo_path),
+        &remote_path,
+        branches.values(),
+    );
+    assert_eq!(
+        git(
+            &test_env,
+            &remote_path,
+            &["log", "--format=%an <%ae>%n%B", "main"]
+        ),
+        "Test User <test.user@example.com>\nfirst\n"
+    );
+    assert_eq!(
+        git(&test_env, &remote_path, &["show", "main:dir/nested"]),
+        "nested\n"
+    );
+
+    // Moving the branch forward pushes only the new commit
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    std::fs::write(repo_path.join("file"), "modified\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["branch", "set", "main"]);
+    test_env.jj_cmd_success(&repo_path, &["git", "push", "--branch", "main"]);
+    let branches = jj_branches(&test_env, &repo_path);
+    assert_eq!(git_branches(&test_env, &remote_path), branches);
+    assert_objects_match(
+        &test_env,
+        &backing_git_dir(&repo_path),
+        &remote_path,
+        branches.values(),
+    );
+    assert_eq!(
+        git(&test_env, &remote_path, &["rev-list", "--count", "main"]),
+        "2\n"
+    );
+
+    // A clone made by `git` gets the same content
+    git(
+        &test_env,
+        test_env.env_root(),
+        &["clone", "--quiet", "remote.git", "git-clone"],
+    );
+    let git_clone_path = test_env.env_root().join("git-clone");
+    assert_eq!(
+        std::fs::read_to_string(git_clone_path.join("file")).unwrap(),
+        "modified\n"
+    );
+    git(&test_env, &git_clone_path, &["fsck", "--strict"]);
+}
+
+#[test]
+fn test_git_interop_colocated() {
+    let test_env = TestEnvironment::default();
+    let repo_path = test_env.env_root().join("repo");
+    git_init(&test_env, &repo_path, false);
+    git_commit_file(&test_env, &repo_path, "file", "from git\n");
+    test_env.jj_cmd_success(&repo_path, &["init", "--git-repo", "."]);
+    assert_eq!(
+        jj_branches(&test_env, &repo_path),
+        git_branches(&test_env, &repo_path)
+    );
+
+    // Commits made by jj are visible to `git`, and `git status` is clean
+    std::fs::write(repo_path.join("file"), "from jj\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "from jj"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "set", "main"]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    assert_eq!(
+        jj_branches(&test_env, &repo_path),
+        git_branches(&test_env, &repo_path)
+    );
+    assert_eq!(
+        git(&test_env, &repo_path, &["log", "--format=%s", "main"]),
+        "from jj\nwrite file\n"
+    );
+    assert_eq!(
+        git(&test_env, &repo_path, &["rev-parse", "HEAD"]),
+        git(&test_env, &repo_path, &["rev-parse", "main"])
+    );
+    assert_eq!(git(&test_env, &repo_path, &["status", "--porcelain"]), "");
+
+    // Commits made by `git` on top are imported by jj
+    git(&test_env, &repo_path, &["checkout", "--quiet", "main"]);
+    git_commit_file(&test_env, &repo_path, "file", "from git again\n");
+    test_env.jj_cmd_success(&repo_path, &["git", "import"]);
+    assert_eq!(
+        jj_branches(&test_env, &repo_path),
+        git_branches(&test_env, &repo_path)
+    );
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "log",
+            "--no-graph",
+            "-r",
+            "root..main",
+            "-T",
+            r#"description"#,
+        ],
+    );
+    assert_eq!(stdout, "write file\nfrom jj\nwrite file\n");
+    git(
+        &test_env,
+        &repo_path,
+        &["fsck", "--strict", "--no-dangling"],
+    );
+}
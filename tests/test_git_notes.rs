@@ -0,0 +1,71 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_git_notes() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+
+    test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "git",
+            "notes",
+            "add",
+            "-m",
+            "Reviewed-by: someone\nTested: yes",
+        ],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["show"]);
+    // The change ID isn't deterministic, so only look at the end
+    insta::assert_snapshot!(stdout.split_once("first\n\n").unwrap().1, @r###"
+    Notes:
+        Reviewed-by: someone
+        Tested: yes
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "log",
+            "-T",
+            r#"description.first_line() " " notes.first_line()"#,
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    @ first Reviewed-by: someone
+    o (no description set)
+    "###);
+
+    // Existing notes are only replaced with --force
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["git", "notes", "add", "-m", "new"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Commit 85a1e2839620 already has a note (use --force to replace it)
+    "###);
+    test_env.jj_cmd_success(&repo_path, &["git", "notes", "add", "-m", "new", "--force"]);
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["log", "--no-graph", "-r", "@", "-T", "notes"]);
+    insta::assert_snapshot!(stdout, @"new");
+
+    test_env.jj_cmd_success(&repo_path, &["git", "notes", "remove"]);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["git", "notes", "remove"]);
+    insta::assert_snapshot!(stderr, @"Error: Commit 85a1e2839620 has no note");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["show", "-s"]);
+    insta::assert_snapshot!(stdout.split_once("first\n").unwrap().1, @"");
+}
@@ -14,7 +14,7 @@
 
 use std::path::PathBuf;
 
-use crate::common::TestEnvironment;
+use crate::common::{get_stderr_string, TestEnvironment};
 
 pub mod common;
 
@@ -167,6 +167,95 @@ fn test_git_push_all() {
     "###);
 }
 
+#[test]
+fn test_git_push_protected_branches() {
+    let (test_env, workspace_root) = set_up();
+    test_env.add_config(br#"git.protected-branches = ["branch?", "main"]"#);
+    test_env.jj_cmd_success(&workspace_root, &["branch", "delete", "branch1"]);
+    test_env.jj_cmd_success(
+        &workspace_root,
+        &["branch", "set", "--allow-backwards", "branch2"],
+    );
+    test_env.jj_cmd_success(&workspace_root, &["describe", "-m", "foo"]);
+    let stderr = test_env.jj_cmd_failure(&workspace_root, &["git", "push", "--all"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Refusing to delete protected branch branch1 (use --force-protected to delete it anyway)
+    "###);
+    let stderr = test_env.jj_cmd_failure(&workspace_root, &["git", "push", "--branch", "branch2"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Refusing to force-push protected branch branch2 (use --force-protected to force-push it anyway)
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &workspace_root,
+        &["git", "push", "--all", "--force-protected", "--dry-run"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Branch changes to push to origin:
+      Delete branch branch1 from a3ccc578ea7b
+      Force branch branch2 from 7fd4b07286b3 to 28de1283afe3
+    Dry-run requested, not pushing.
+    "###);
+
+    // A single pattern can be given as a string
+    test_env.add_config(br#"git.protected-branches = "branch1""#);
+    let stderr = test_env.jj_cmd_failure(&workspace_root, &["git", "push", "--all"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Refusing to delete protected branch branch1 (use --force-protected to delete it anyway)
+    "###);
+    test_env.add_config(br#"git.protected-branches = 1"#);
+    let stderr = test_env.jj_cmd_failure(&workspace_root, &["git", "push", "--all"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: git.protected-branches: expected a pattern or a list of patterns, got 1
+    "###);
+    test_env.add_config(br#"git.protected-branches = ["branch?", "main"]"#);
+
+    // Moving a protected branch forward is allowed
+    test_env.jj_cmd_success(&workspace_root, &["new", "branch2@origin", "-m", "bar"]);
+    test_env.jj_cmd_success(
+        &workspace_root,
+        &["branch", "set", "--allow-backwards", "branch2"],
+    );
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["git", "push", "--branch", "branch2"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Branch changes to push to origin:
+      Move branch branch2 from 7fd4b07286b3 to 6085e33c02e0
+    "###);
+}
+
+#[test]
+fn test_git_push_signed() {
+    let (test_env, workspace_root) = set_up();
+    test_env.jj_cmd_success(&workspace_root, &["new", "branch1", "-m", "foo"]);
+    test_env.jj_cmd_success(
+        &workspace_root,
+        &["branch", "set", "--allow-backwards", "branch1"],
+    );
+    // The remote doesn't accept push certificates
+    let assert = test_env
+        .jj_cmd(&workspace_root, &["git", "push", "--signed"])
+        .assert()
+        .failure();
+    let stderr = get_stderr_string(&assert);
+    assert!(
+        stderr.starts_with(
+            "Error: Failed to run `git push`: fatal: the receiving end does not support --signed \
+             push"
+        ),
+        "{stderr}"
+    );
+    test_env.add_config(br#"git.sign-push = "if-asked""#);
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["git", "push"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Branch changes to push to origin:
+      Move branch branch1 from a3ccc578ea7b to a6c7a6631486
+    "###);
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    branch1: a6c7a6631486 foo
+    branch2: 7fd4b07286b3 description 2
+    "###);
+}
+
 #[test]
 fn test_git_push_unsnapshotted_change() {
     let (test_env, workspace_root) = set_up();
@@ -177,6 +266,42 @@ fn test_git_push_unsnapshotted_change() {
     test_env.jj_cmd_success(&workspace_root, &["git", "push", "--change", "@"]);
 }
 
+#[test]
+fn test_git_push_changes() {
+    let (test_env, workspace_root) = set_up();
+    test_env.jj_cmd_success(&workspace_root, &["describe", "-m", "foo"]);
+    std::fs::write(workspace_root.join("file"), "foo").unwrap();
+    test_env.jj_cmd_success(&workspace_root, &["new", "-m", "bar"]);
+    std::fs::write(workspace_root.join("file"), "bar").unwrap();
+    let get_push_branches = || {
+        let stdout = test_env.jj_cmd_success(&workspace_root, &["branch", "list"]);
+        stdout
+            .lines()
+            .filter(|line| line.starts_with("push-"))
+            .map(|line| line.split_once(": ").unwrap().1.to_string())
+            .collect::<Vec<_>>()
+    };
+
+    // Each change in the stack gets a branch of its own
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["git", "push", "--change", "@--..@"]);
+    assert_eq!(
+        stdout.matches("Creating branch push-").count(),
+        2,
+        "{stdout}"
+    );
+    let branches = get_push_branches();
+    assert_eq!(branches.len(), 2, "{branches:?}");
+    assert!(branches.iter().any(|target| target.ends_with(" foo")));
+    assert!(branches.iter().any(|target| target.ends_with(" bar")));
+
+    // Pushing a rewritten change again updates its branch
+    std::fs::write(workspace_root.join("file"), "baz").unwrap();
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["git", "push", "--change", "@"]);
+    assert!(!stdout.contains("Creating branch"), "{stdout}");
+    assert!(stdout.contains("Force branch push-"), "{stdout}");
+    assert_eq!(get_push_branches().len(), 2);
+}
+
 #[test]
 fn test_git_push_conflict() {
     let (test_env, workspace_root) = set_up();
@@ -203,6 +328,7 @@ fn test_git_push_no_description() {
         test_env.jj_cmd_failure(&workspace_root, &["git", "push", "--branch", "my-branch"]);
     insta::assert_snapshot!(stderr, @r###"
     Error: Won't push commit 4e5f01c842af since it has no description
+    Use --allow-wip to push anyway.
     "###);
 }
 
@@ -279,3 +405,77 @@ fn test_git_push_missing_committer() {
     Error: Won't push commit 9e1aae45b6a3 since it has no description and it has no author and/or committer set
     "###);
 }
+
+#[test]
+fn test_git_push_wip_patterns() {
+    let (test_env, workspace_root) = set_up();
+    test_env.add_config(
+        br#"[push]
+    wip-patterns = ["(?i)^wip\\b", "^fixup!"]
+    "#,
+    );
+    test_env.jj_cmd_success(&workspace_root, &["branch", "create", "my-branch"]);
+    test_env.jj_cmd_success(&workspace_root, &["close", "-m", "WIP: first"]);
+    test_env.jj_cmd_success(&workspace_root, &["close", "-m", "second"]);
+    test_env.jj_cmd_success(&workspace_root, &["close", "-m", "fixup! second"]);
+    test_env.jj_cmd_success(&workspace_root, &["branch", "set", "my-branch", "-r", "@-"]);
+    // All the offending commits are listed
+    let stderr =
+        test_env.jj_cmd_failure(&workspace_root, &["git", "push", "--branch", "my-branch"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Won't push these commits:
+      2c8bfb71541f since its description matches "^fixup!"
+      86ceca38fdfa since its description matches "(?i)^wip\b"
+    Use --allow-wip to push anyway.
+    "###);
+
+    // Only the first line is checked
+    test_env.jj_cmd_success(
+        &workspace_root,
+        &["describe", "-r", "@-", "-m", "third\n\nfixup! second"],
+    );
+    let stderr =
+        test_env.jj_cmd_failure(&workspace_root, &["git", "push", "--branch", "my-branch"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Won't push commit 86ceca38fdfa since its description matches "(?i)^wip\b"
+    Use --allow-wip to push anyway.
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &workspace_root,
+        &[
+            "git",
+            "push",
+            "--branch",
+            "my-branch",
+            "--allow-wip",
+            "--dry-run",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Branch changes to push to origin:
+      Add branch my-branch to 59becbddf93b
+    Dry-run requested, not pushing.
+    "###);
+}
+
+#[test]
+fn test_git_push_allow_empty_description() {
+    let (test_env, workspace_root) = set_up();
+    test_env.jj_cmd_success(&workspace_root, &["branch", "create", "my-branch"]);
+    test_env.jj_cmd_success(&workspace_root, &["close", "-m", ""]);
+    test_env.add_config(
+        br#"[push]
+    allow-empty-description = true
+    "#,
+    );
+    let stdout = test_env.jj_cmd_success(
+        &workspace_root,
+        &["git", "push", "--branch", "my-branch", "--dry-run"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Branch changes to push to origin:
+      Add branch my-branch to 4e5f01c842af
+    Dry-run requested, not pushing.
+    "###);
+}
@@ -49,3 +49,129 @@ fn test_git_remotes() {
     insta::assert_snapshot!(stderr, @"Error: Remote doesn't exist
 ");
 }
+
+#[test]
+fn test_git_remote_urls() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "--git", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "git",
+            "remote",
+            "add",
+            "foo",
+            "http://example.com/repo/foo",
+            "--push-url",
+            "ssh://example.com/repo/foo",
+        ],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["git", "remote", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    foo http://example.com/repo/foo (push: ssh://example.com/repo/foo)
+    "###);
+
+    test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "git",
+            "remote",
+            "set-url",
+            "foo",
+            "http://example.com/repo/new",
+        ],
+    );
+    test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "git",
+            "remote",
+            "set-url",
+            "--push",
+            "foo",
+            "ssh://example.com/repo/new",
+        ],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["git", "remote", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    foo http://example.com/repo/new (push: ssh://example.com/repo/new)
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(
+        &repo_path,
+        &[
+            "git",
+            "remote",
+            "set-url",
+            "nonexistent",
+            "http://example.com",
+        ],
+    );
+    insta::assert_snapshot!(stderr, @"Error: Remote doesn't exist");
+}
+
+#[test]
+fn test_git_remote_rename() {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    let git_repo = git2::Repository::init(&git_repo_path).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let tree_id = git_repo.treebuilder(None).unwrap().write().unwrap();
+    let tree = git_repo.find_tree(tree_id).unwrap();
+    git_repo
+        .commit(
+            Some("refs/heads/main"),
+            &signature,
+            &signature,
+            "message",
+            &tree,
+            &[],
+        )
+        .unwrap();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "--git", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "git",
+            "remote",
+            "add",
+            "foo",
+            git_repo_path.to_str().unwrap(),
+        ],
+    );
+    // The remote to fetch from can be configured
+    test_env.add_config(br#"git.default-remote = "foo""#);
+    test_env.jj_cmd_success(&repo_path, &["git", "fetch"]);
+    // Delete the local branch so the remote-tracking branch is listed
+    test_env.jj_cmd_success(&repo_path, &["branch", "delete", "main"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    main (deleted)
+      @foo: d62cea7e0f1a message
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["git", "remote", "rename", "foo", "bar"]);
+    insta::assert_snapshot!(stdout, @"");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    main (deleted)
+      @bar: d62cea7e0f1a message
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["git", "remote", "list"]);
+    insta::assert_snapshot!(stdout.replace(git_repo_path.to_str().unwrap(), "$GIT_REPO"), @"bar $GIT_REPO");
+    // The remote-tracking branch was renamed in Git too, so fetching doesn't
+    // change anything
+    test_env.jj_cmd_success(&repo_path, &["git", "fetch", "--remote", "bar"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    main (deleted)
+      @bar: d62cea7e0f1a message
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["git", "remote", "rename", "foo", "baz"]);
+    insta::assert_snapshot!(stderr, @"Error: No git remote named 'foo'");
+}
@@ -65,3 +65,75 @@ fn test_gitignores() {
     A file3
     "###);
 }
+
+#[test]
+fn test_gitignores_workspace_local() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "--git", "main"]);
+    let main_path = test_env.env_root().join("main");
+    let secondary_path = test_env.env_root().join("secondary");
+    test_env.jj_cmd_success(&main_path, &["close", "-m", "initial"]);
+    test_env.jj_cmd_success(&main_path, &["workspace", "add", "../secondary"]);
+
+    // Ignore "scratch" only in the main workspace
+    std::fs::write(main_path.join(".jj").join("gitignore"), "scratch\n").unwrap();
+    std::fs::write(main_path.join("scratch"), "contents").unwrap();
+    std::fs::write(main_path.join("file"), "contents").unwrap();
+    std::fs::write(secondary_path.join("scratch"), "contents").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&main_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @"A file");
+    let stdout = test_env.jj_cmd_success(&secondary_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @"A scratch");
+}
+
+#[test]
+fn test_gitignores_global_excludes_file() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "--git", "repo"]);
+    let workspace_root = test_env.env_root().join("repo");
+
+    // Git's default global ignore file is used when core.excludesFile isn't set
+    let git_config_dir = test_env.home_dir().join(".config").join("git");
+    std::fs::create_dir_all(&git_config_dir).unwrap();
+    std::fs::write(git_config_dir.join("ignore"), "*~\n").unwrap();
+    std::fs::write(workspace_root.join("file"), "contents").unwrap();
+    std::fs::write(workspace_root.join("file~"), "contents").unwrap();
+    std::fs::write(workspace_root.join("file.bak"), "contents").unwrap();
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file
+    A file.bak
+    "###);
+
+    // Our own setting takes precedence
+    std::fs::write(test_env.home_dir().join("my-ignores"), "*.bak\n").unwrap();
+    test_env.add_config(br#"snapshot.excludes-file = "~/my-ignores""#);
+    std::fs::remove_file(workspace_root.join("file.bak")).unwrap();
+    std::fs::write(workspace_root.join("other.bak"), "contents").unwrap();
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file
+    A file~
+    "###);
+}
+
+#[test]
+fn test_never_track() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "--git", "repo"]);
+    let workspace_root = test_env.env_root().join("repo");
+    test_env.add_config(br#"snapshot.never-track = ["*.swp", "/target/"]"#);
+
+    // The patterns apply even when .gitignore says to track the files
+    std::fs::write(workspace_root.join(".gitignore"), "!*.swp\n").unwrap();
+    std::fs::create_dir(workspace_root.join("target")).unwrap();
+    std::fs::write(workspace_root.join("target").join("output"), "contents").unwrap();
+    std::fs::write(workspace_root.join("file"), "contents").unwrap();
+    std::fs::write(workspace_root.join(".file.swp"), "contents").unwrap();
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A .gitignore
+    A file
+    "###);
+}
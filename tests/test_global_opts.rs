@@ -160,9 +160,17 @@ fn test_help() {
       -h, --help                 Print help information (use `--help` for more detail)
 
     Global Options:
-      -R, --repository <REPOSITORY>      Path to repository to operate on
-          --no-commit-working-copy       Don't commit the working copy
-          --at-operation <AT_OPERATION>  Operation to load the repo at [default: @] [aliases: at-op]
-          --color <WHEN>                 When to colorize output (always, never, auto)
+      -R, --repository <REPOSITORY>
+              Path to repository to operate on
+          --no-commit-working-copy
+              Don't commit the working copy
+          --discard-changes
+              Overwrite changes in the working copy that haven't been snapshotted
+          --at-operation <AT_OPERATION>
+              Operation to load the repo at [default: @] [aliases: at-op]
+          --color <WHEN>
+              When to colorize output (always, never, auto)
+          --op-tag <KEY=VALUE>
+              Record a `KEY=VALUE` tag in the metadata of the operations the command creates (can be repeated)
     "###);
 }
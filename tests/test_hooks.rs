@@ -0,0 +1,103 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_pre_commit_hook_rejects() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(
+        br#"
+        [[hooks.pre-commit]]
+        command = ["sh", "-c", "if grep -q WIP; then echo 'WIP commits are not allowed' >&2; exit 1; fi"]
+        "#,
+    );
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["describe", "-m", "WIP: first"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: The pre-commit hook `sh -c if grep -q WIP; then echo 'WIP commits are not allowed' >&2; exit 1; fi` rejected the change:
+    WIP commits are not allowed
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ (no description set)
+    o (no description set)
+    "###);
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ first
+    o (no description set)
+    "###);
+}
+
+#[test]
+fn test_pre_rewrite_hook_input() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    let log_path = test_env.env_root().join("hook.log");
+    test_env.add_config(
+        format!(
+            r#"
+            [[hooks.pre-rewrite]]
+            command = ["sh", "-c", "cat >> '{}'"]
+            "#,
+            log_path.to_str().unwrap()
+        )
+        .as_bytes(),
+    );
+
+    std::fs::write(repo_path.join("file"), "contents").unwrap();
+    // Creating a new commit doesn't rewrite anything
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    assert!(!log_path.exists());
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-r", "@-", "-m", "first"]);
+    let log = std::fs::read_to_string(&log_path).unwrap();
+    let input: serde_json::Value = serde_json::from_str(&log).unwrap();
+    assert_eq!(input["hook"], "pre-rewrite");
+    // The described commit and its rebased child
+    let commits = input["commits"].as_array().unwrap();
+    assert_eq!(commits.len(), 2);
+    assert_eq!(commits[0]["description"], "first");
+    assert_eq!(commits[0]["predecessors"].as_array().unwrap().len(), 1);
+    insta::assert_snapshot!(commits[0]["files"].to_string(), @r###"[{"path":"file","size":8,"status":"added"}]"###);
+}
+
+#[test]
+fn test_hook_timeout() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(
+        br#"
+        [[hooks.pre-commit]]
+        command = ["sleep", "10"]
+        timeout = 1
+        "#,
+    );
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["describe", "-m", "first"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: The pre-commit hook `sleep 10` did not finish within 1 seconds
+    "###);
+}
@@ -166,3 +166,40 @@ fn test_init_local() {
     assert!(store_path.join("symlinks").is_dir());
     assert!(store_path.join("conflicts").is_dir());
 }
+
+#[test]
+fn test_init_remote_store() {
+    let test_env = TestEnvironment::default();
+    let objects_path = test_env.env_root().join("objects");
+    std::fs::create_dir(&objects_path).unwrap();
+    let url = format!("file://{}", objects_path.display());
+    let stdout = test_env.jj_cmd_success(
+        test_env.env_root(),
+        &["init", "repo", "--remote-store", &url],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Initialized repo in "repo"
+    "###);
+
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "contents").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "shared"]);
+    let commit_id = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "commit_id"],
+    );
+    // The objects are written to the object store
+    assert!(objects_path.join("commits").join(&commit_id).is_file());
+    assert!(objects_path.join("files").is_dir());
+    let stdout = test_env.jj_cmd_success(&repo_path, &["stats"]);
+    assert!(stdout.contains("Backend: remote"), "{stdout}");
+
+    let stderr = test_env.jj_cmd_failure(
+        test_env.env_root(),
+        &["init", "repo2", "--remote-store", "s3://bucket"],
+    );
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Unsupported object store URL: s3://bucket (expected a file:// or http:// URL)
+    "###);
+    assert!(!test_env.env_root().join("repo2").exists());
+}
@@ -0,0 +1,105 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::{get_stderr_string, TestEnvironment};
+
+pub mod common;
+
+#[test]
+fn test_snapshot_files_limit() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"limits.snapshot-files = 2"#);
+
+    std::fs::write(repo_path.join("file1"), "").unwrap();
+    std::fs::write(repo_path.join("file2"), "").unwrap();
+    std::fs::write(repo_path.join("file3"), "").unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["status"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to snapshot the working copy because it has 3 new or modified files, more than the limit of 2 (`limits.snapshot-files`). Add the files that shouldn't be tracked to `.gitignore` or `snapshot.never-track`, or raise the limit.
+    "###);
+
+    // Nothing was recorded, so ignoring some of the files is enough
+    std::fs::write(repo_path.join(".gitignore"), "file2\nfile3\n").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 000000000000 (no description set)
+    Working copy : 24bfea09c778 (no description set)
+    Working copy changes:
+    A .gitignore
+    A file1
+    "###);
+}
+
+#[test]
+fn test_revset_commits_limit() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    test_env.add_config(br#"limits.revset-commits = 2"#);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["abandon", "root..@"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Revset "root..@" resolved to more than 2 commits (`limits.revset-commits`). Use a narrower revset, or raise the limit.
+    "###);
+    test_env.jj_cmd_success(&repo_path, &["abandon", "@-:@"]);
+
+    // Commands that stream the revset are limited too
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-T", "commit_id"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Revset "builtin_log()" resolved to more than 2 commits (`limits.revset-commits`). Use a narrower revset, or raise the limit.
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "commit_id", "-r", "@-:@"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ 167f90e7600a50f85c4f909b53eaf546faa82879
+    o 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    ~ 
+    "###);
+}
+
+#[test]
+fn test_fetch_objects_limit() {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    let git_repo = git2::Repository::init_bare(&git_repo_path).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let tree_id = git_repo.treebuilder(None).unwrap().write().unwrap();
+    let tree = git_repo.find_tree(tree_id).unwrap();
+    git_repo
+        .commit(
+            Some("refs/heads/main"),
+            &signature,
+            &signature,
+            "main",
+            &tree,
+            &[],
+        )
+        .unwrap();
+    test_env.add_config(br#"limits.fetch-objects = 1"#);
+
+    let assert = test_env
+        .jj_cmd(
+            test_env.env_root(),
+            &["git", "clone", git_repo_path.to_str().unwrap(), "clone"],
+        )
+        .assert()
+        .code(1);
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Error: The fetch would download 2 objects, more than the limit of 1 (`limits.fetch-objects`). Nothing was fetched. Raise the limit to fetch anyway.
+    "###);
+}
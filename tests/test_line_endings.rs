@@ -0,0 +1,112 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::{get_stderr_string, TestEnvironment};
+
+pub mod common;
+
+#[test]
+fn test_line_endings_warn() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "a\nb\n").unwrap();
+    std::fs::write(repo_path.join("other"), "a\nb\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+
+    // Changing the contents doesn't warn
+    std::fs::write(repo_path.join("other"), "a\nc\n").unwrap();
+    let assert = test_env.jj_cmd(&repo_path, &["status"]).assert().success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @"");
+
+    // Changing the line endings does, and the change is recorded
+    std::fs::write(repo_path.join("file"), "\u{feff}a\r\nb\r\n").unwrap();
+    let assert = test_env.jj_cmd(&repo_path, &["status"]).assert().success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    The line endings or byte-order mark of these files changed:
+      file: line endings changed from LF to CRLF, byte-order mark added
+    Hint: This is often caused by a misconfigured editor. Set `snapshot.line-endings` to "normalize" to keep the previous ones, or to "block" to refuse such snapshots.
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M file
+    M other
+    "###);
+
+    // The warning is only printed by the snapshot that records the change
+    let assert = test_env.jj_cmd(&repo_path, &["status"]).assert().success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @"");
+}
+
+#[test]
+fn test_line_endings_normalize() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"snapshot.line-endings = "normalize""#);
+    std::fs::write(repo_path.join("file"), "a\r\nb\r\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+
+    std::fs::write(repo_path.join("file"), "a\nc\n").unwrap();
+    let assert = test_env.jj_cmd(&repo_path, &["status"]).assert().success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Kept the previous line endings or byte-order mark of these files:
+      file: line endings changed from CRLF to LF
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "file"]);
+    assert_eq!(stdout, "a\r\nc\r\n");
+    // The file in the working copy is left alone
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("file")).unwrap(),
+        "a\nc\n"
+    );
+}
+
+#[test]
+fn test_line_endings_block() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"snapshot.line-endings = "block""#);
+    std::fs::write(repo_path.join("file"), "a\nb\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+
+    std::fs::write(repo_path.join("file"), "a\r\nb\r\n").unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["status"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to snapshot the working copy because the line endings or byte-order mark of these files changed:
+      file
+    Restore them, or set `snapshot.line-endings` to "warn" or "normalize".
+    "###);
+
+    // Nothing was recorded, and restoring the line endings lets the snapshot
+    // through
+    std::fs::write(repo_path.join("file"), "a\nc\n").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M file
+    "###);
+}
+
+#[test]
+fn test_line_endings_invalid_policy() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"snapshot.line-endings = "block-all""#);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["status"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: snapshot.line-endings: expected "warn", "normalize", "block" or "ignore", got "block-all"
+    "###);
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use common::TestEnvironment;
+use common::{get_stdout_string, TestEnvironment};
 
 pub mod common;
 
@@ -224,4 +224,242 @@ fn test_default_revset() {
             .lines()
             .count()
     );
+
+    // The built-in default can be extended
+    test_env.add_config(
+        br#"[ui]
+        default-revset = "builtin_log() ~ @"
+        "#,
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @"o (no description set)");
+}
+
+#[test]
+fn test_log_diff_stat_keywords() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::create_dir(repo_path.join("dir")).unwrap();
+    std::fs::write(repo_path.join("dir/file1"), "a\nb\nc\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "add files"]);
+    std::fs::write(repo_path.join("dir/file1"), "a\nB\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "modify file1"]);
+
+    let template = r#"description.first_line() " (" num_files " files, +" insertions "/-" deletions ") " files " " if(touches("dir"), "touches dir", "doesn't touch dir") "\n""#;
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ modify file1 (1 files, +1/-2) dir/file1 touches dir
+    o add files (2 files, +4/-0) dir/file1 file2 touches dir
+    o (no description set) (0 files, +0/-0)  doesn't touch dir
+    "###);
+
+    // `touches()` can be given several paths, and a file
+    let template = r#"touches("file2", "other") "\n""#;
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "--no-graph", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    false
+    true
+    false
+    "###);
+}
+
+#[test]
+fn test_log_timestamp_format() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+
+    let template =
+        r#"format_date(author.timestamp(), "%Y-%m-%d") " " relative(author.timestamp()) "\n""#;
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "--no-graph", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    2001-02-03 2 seconds ago
+    1970-01-01 31 years ago
+    "###);
+
+    // The built-in templates use `ui.timestamp-format` (the change id is random,
+    // so we don't snapshot the whole output)
+    test_env.add_config(br#"ui.timestamp-format = "relative""#);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-r", "@"]);
+    assert!(
+        stdout.contains(" test.user@example.com 3 seconds ago "),
+        "{stdout}"
+    );
+    test_env.add_config(br#"ui.timestamp-format = "%a %b %e \"%H:%M\"""#);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["show", "-r", "@"]);
+    assert!(
+        stdout.contains("Author: Test User <test.user@example.com> (Sat Feb  3 \"04:05\")"),
+        "{stdout}"
+    );
+
+    test_env.add_config(br#"ui.timestamp-format = "%Q""#);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-r", "@"]);
+    insta::assert_snapshot!(stderr, @"Config error: Invalid `ui.timestamp-format`: %Q");
+}
+
+#[test]
+fn test_log_timestamp_timezone() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+
+    let template =
+        r#"author.timestamp() "\n" author.timestamp().utc() "\n" author.timestamp().local() "\n""#;
+    let assert = test_env
+        .jj_cmd(
+            &repo_path,
+            &["log", "--no-graph", "-r", "@", "-T", template],
+        )
+        .env("TZ", "UTC-03")
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stdout_string(&assert), @r###"
+    2001-02-03 04:05:07.000 +07:00
+    2001-02-02 21:05:07.000 +00:00
+    2001-02-03 00:05:07.000 +03:00
+    "###);
+
+    // The built-in templates use `ui.timestamp-timezone`
+    test_env.add_config(br#"ui.timestamp-timezone = "utc""#);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["show", "-r", "@"]);
+    assert!(
+        stdout
+            .contains("Author: Test User <test.user@example.com> (2001-02-02 21:05:07.000 +00:00)"),
+        "{stdout}"
+    );
+    test_env.add_config(br#"ui.timestamp-format = "%Y-%m-%d %H:%M %z""#);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-r", "@"]);
+    assert!(
+        stdout.contains(" test.user@example.com 2001-02-02 21:05 +0000 "),
+        "{stdout}"
+    );
+
+    test_env.add_config(br#"ui.timestamp-timezone = "mars""#);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-r", "@"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: Invalid `ui.timestamp-timezone`: mars (expected "original", "local" or "utc")
+    "###);
+}
+
+#[test]
+fn test_log_author_format() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+
+    let template = r#"author.name() "|" author.email() "|" author.username() "\n""#;
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", template],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Test User|test.user@example.com|test.user
+    "###);
+
+    // The built-in log template uses `ui.log-author` (the change id is random,
+    // so we don't snapshot the whole output)
+    for (setting, expected) in [
+        ("name", " Test User "),
+        ("username", " test.user "),
+        ("email", " test.user@example.com "),
+    ] {
+        test_env.add_config(format!(r#"ui.log-author = "{setting}""#).as_bytes());
+        let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-r", "@"]);
+        assert!(stdout.contains(expected), "{setting}: {stdout}");
+    }
+
+    test_env.add_config(br#"ui.log-author = "initials""#);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-r", "@"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: Invalid `ui.log-author`: initials (expected "email", "name" or "username")
+    "###);
+}
+
+#[test]
+fn test_log_string_functions() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["describe", "-m", "A long description\n\nwith a body"],
+    );
+
+    let template =
+        r#""[" pad_right(truncate(description, 10), 12) "|" pad_left(commit_id.short(), 14) "]\n""#;
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", template],
+    );
+    insta::assert_snapshot!(stdout, @"[A long ...  |  e12773fcc0a9]");
+
+    let template = r#"upper(first_line(description)) " " lower("ABC" author.email()) "\n" indent("> ", description)"#;
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", template],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    A LONG DESCRIPTION abctest.user@example.com
+    > A long description
+
+    > with a body
+    "###);
+}
+
+#[test]
+fn test_log_mine() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "mine"]);
+    for (description, email) in [
+        ("old address", "old.address@example.com"),
+        ("someone else", "someone.else@example.com"),
+    ] {
+        test_env
+            .jj_cmd(&repo_path, &["new", "-m", description])
+            .env("JJ_EMAIL", email)
+            .assert()
+            .success();
+    }
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "log",
+            "-r",
+            "mine()",
+            "--no-graph",
+            "-T",
+            r#"description.first_line() "\n""#,
+        ],
+    );
+    insta::assert_snapshot!(stdout, @"mine");
+
+    test_env.add_config(
+        br#"[user]
+        other-emails = ["old.address@example.com"]
+        "#,
+    );
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "log",
+            "-r",
+            "mine()",
+            "--no-graph",
+            "-T",
+            r#"description.first_line() "\n""#,
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    old address
+    mine
+    "###);
 }
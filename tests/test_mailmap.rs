@@ -0,0 +1,82 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_mailmap() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    let template = r#"description.first_line() " " author.name() " <" author.email() ">\n""#;
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", template],
+    );
+    insta::assert_snapshot!(stdout, @"first Test User <test.user@example.com>");
+
+    test_env.add_config(
+        br#"
+        [[mailmap]]
+        old-email = "test.user@example.com"
+        name = "Canonical User"
+        email = "canonical@example.com"
+        "#,
+    );
+    // Existing commits are shown with the canonical identity
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", template],
+    );
+    insta::assert_snapshot!(stdout, @"first Canonical User <canonical@example.com>");
+
+    // Rewriting a commit also stores the canonical identity in Git
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "second"]);
+    let commit_id = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "commit_id"],
+    );
+    let git_repo = git2::Repository::open(repo_path.join(".jj/repo/store/git")).unwrap();
+    let git_commit = git_repo
+        .find_commit(git2::Oid::from_str(&commit_id).unwrap())
+        .unwrap();
+    assert_eq!(git_commit.author().name(), Some("Canonical User"));
+    assert_eq!(
+        git_commit.committer().email(),
+        Some("canonical@example.com")
+    );
+
+    // The repo's config isn't used, so templates show what commits are written
+    // with
+    std::fs::write(
+        repo_path.join(".jj/repo/config"),
+        br#"
+        [[mailmap]]
+        old-email = "canonical@example.com"
+        name = "Repo User"
+        email = "repo@example.com"
+        "#,
+    )
+    .unwrap();
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", template],
+    );
+    insta::assert_snapshot!(stdout, @"second Canonical User <canonical@example.com>");
+}
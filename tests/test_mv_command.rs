@@ -0,0 +1,114 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_mv() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "contents 1").unwrap();
+    std::fs::create_dir(repo_path.join("dir")).unwrap();
+    std::fs::write(repo_path.join("dir").join("file2"), "contents 2").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "initial"]);
+
+    // Errors out when the source doesn't exist or the destination exists
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["mv", "nonexistent", "file3"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: No such path in the working copy: nonexistent
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["mv", "file1", "dir/file2"]);
+    insta::assert_snapshot!(stderr, @"Error: Destination already exists: dir/file2");
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["mv", "dir", "dir/sub"]);
+    insta::assert_snapshot!(stderr, @"Error: Cannot move or copy dir into itself");
+
+    // Can rename a file
+    let stdout = test_env.jj_cmd_success(&repo_path, &["mv", "file1", "file3"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Working copy now at: aa8783e07223 (no description set)
+    Added 1 files, modified 0 files, removed 1 files
+    "###);
+    assert!(!repo_path.join("file1").exists());
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("file3")).unwrap(),
+        "contents 1"
+    );
+    // Can move a file into a directory
+    test_env.jj_cmd_success(&repo_path, &["mv", "file3", "dir"]);
+    assert!(repo_path.join("dir").join("file3").exists());
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A dir/file3 (renamed from file1)
+    R file1
+    "###);
+
+    // Can move a directory
+    test_env.jj_cmd_success(&repo_path, &["mv", "dir", "moved"]);
+    assert!(!repo_path.join("dir").exists());
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary"]);
+    insta::assert_snapshot!(stdout, @r###"
+    R dir/file2
+    R file1
+    A moved/file2 (renamed from dir/file2)
+    A moved/file3 (renamed from file1)
+    "###);
+}
+
+#[test]
+fn test_cp() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "contents 1").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "initial"]);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["cp", "file1", "file2"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Working copy now at: 620d9b2fa976 (no description set)
+    Added 1 files, modified 0 files, removed 0 files
+    "###);
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("file1")).unwrap(),
+        "contents 1"
+    );
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("file2")).unwrap(),
+        "contents 1"
+    );
+    // A copy of a copy is recorded as a copy of the original file
+    test_env.jj_cmd_success(&repo_path, &["cp", "file2", "file3"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 984b7cfcf98d initial
+    Working copy : 695f10e81073 (no description set)
+    Working copy changes:
+    A file2 (copied from file1)
+    A file3 (copied from file1)
+    "###);
+
+    // An untracked file in the way is not overwritten
+    std::fs::write(repo_path.join(".gitignore"), "ignored\n").unwrap();
+    std::fs::write(repo_path.join("ignored"), "untracked").unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["cp", "file1", "ignored"]);
+    insta::assert_snapshot!(stderr, @"Error: Destination already exists: ignored");
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("ignored")).unwrap(),
+        "untracked"
+    );
+}
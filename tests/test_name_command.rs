@@ -0,0 +1,125 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_name_follows_rewrites() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["name", "set", "fix-login-bug"]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "second"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ second
+    o first [fix-login-bug]
+    o (no description set)
+    "###);
+
+    // The name keeps referring to the change when it's rewritten
+    test_env.jj_cmd_success(&repo_path, &["describe", "fix-login-bug", "-m", "first v2"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ second
+    o first v2 [fix-login-bug]
+    o (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "log",
+            "--no-graph",
+            "-r",
+            "fix-login-bug",
+            "-T",
+            "description",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @"first v2");
+
+    // Names are shown in the default log template
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-r", "fix-login-bug"]);
+    assert!(stdout.contains(" fix-login-bug\n"), "{stdout}");
+
+    // A name can be moved to another change
+    test_env.jj_cmd_success(&repo_path, &["name", "set", "fix-login-bug", "-r", "@"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ second [fix-login-bug]
+    o first v2
+    o (no description set)
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["name", "delete", "fix-login-bug"]);
+    insta::assert_snapshot!(stdout, @"");
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["name", "delete", "fix-login-bug"]);
+    insta::assert_snapshot!(stderr, @"Error: No such name: fix-login-bug");
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-r", "fix-login-bug"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to resolve revset:  --> 1:1
+      |
+    1 | fix-login-bug
+      | ^-----------^
+      |
+      = Revision "fix-login-bug" doesn't exist
+    "###);
+}
+
+#[test]
+fn test_name_list() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["name", "set", "one", "uno"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    test_env.jj_cmd_success(&repo_path, &["name", "set", "abandoned"]);
+    // Change ids are random, so replace the abandoned one in the output
+    let change_id = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "change_id.short()"],
+    );
+    test_env.jj_cmd_success(&repo_path, &["abandon"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["name", "list"]);
+    insta::assert_snapshot!(stdout.replace(&change_id, "<change id>"), @r###"
+    abandoned: <change id> (no visible commits)
+    one: 85a1e2839620 first
+    uno: 85a1e2839620 first
+    "###);
+
+    // Invalid names are rejected
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["name", "set", "two words"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Invalid name: "two words"
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["name", "set", "root", "-r", "root"]);
+    insta::assert_snapshot!(stderr, @"Error: Cannot name the root commit");
+}
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(
+        repo_path,
+        &[
+            "log",
+            "-T",
+            r#"description.first_line() if(change_names, " [" change_names "]")"#,
+        ],
+    )
+}
@@ -73,7 +73,7 @@ fn test_new_merge() {
 
     // Same test with `jj merge`
     test_env.jj_cmd_success(&repo_path, &["undo"]);
-    test_env.jj_cmd_success(&repo_path, &["merge", "main", "@"]);
+    test_env.jj_cmd_success(&repo_path, &["merge", "main", "@", "-m", ""]);
     insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
     @   c34d60aa33225c2080da52faa39980efe944bddd (no description set)
     |\  
@@ -97,6 +97,109 @@ fn test_new_merge() {
     test_env.jj_cmd_failure(&repo_path, &["new", "@", "root"]);
 }
 
+#[test]
+fn test_merge_description() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "main"]);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "add file1"]);
+    std::fs::write(repo_path.join("file1"), "a").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "root", "-m", "add file2"]);
+    std::fs::write(repo_path.join("file2"), "b").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "root", "-m", "change file1"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "feature"]);
+    std::fs::write(repo_path.join("file1"), "c").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "root"]);
+
+    // The parents are named by their branches, or else by their hashes
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["merge", "main", "description(file2)", "--no-edit"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Working copy now at: 6bc28658aea9 Merge main and 99814c62bec5
+    Added 2 files, modified 0 files, removed 0 files
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "description"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Merge main and 99814c62bec5
+
+    * main: fe37af248a06 add file1
+    * 99814c62bec5 add file2
+    "###);
+
+    // More than two parents, with conflicts
+    test_env.jj_cmd_success(&repo_path, &["undo"]);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "merge",
+            "main",
+            "description(file2)",
+            "feature",
+            "--no-edit",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Working copy now at: 673284f3d7ba Merge main, 99814c62bec5 and feature
+    Added 2 files, modified 0 files, removed 0 files
+    The merge has conflicts at these paths:
+    file1
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "description"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Merge main, 99814c62bec5 and feature
+
+    * main: fe37af248a06 add file1
+    * 99814c62bec5 add file2
+    * feature: 60b2705b3601 change file1
+    "###);
+
+    // The generated description can be edited
+    test_env.jj_cmd_success(&repo_path, &["undo"]);
+    let edit_script = test_env.set_up_fake_editor();
+    std::fs::write(
+        &edit_script,
+        "expect
+Merge main and feature
+
+* main: fe37af248a06 add file1
+* feature: 60b2705b3601 change file1
+
+JJ: Lines starting with \"JJ: \" (like this one) will be removed.
+\0write
+Merge feature into main",
+    )
+    .unwrap();
+    test_env.jj_cmd_success(&repo_path, &["merge", "main", "feature"]);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "description"],
+    );
+    insta::assert_snapshot!(stdout, @"Merge feature into main");
+
+    // -m replaces the generated description
+    test_env.jj_cmd_success(&repo_path, &["undo"]);
+    test_env.jj_cmd_success(&repo_path, &["merge", "main", "feature", "-m", "merge"]);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "description"],
+    );
+    insta::assert_snapshot!(stdout, @"merge");
+    test_env.jj_cmd_cli_error(
+        &repo_path,
+        &["merge", "main", "feature", "-m", "merge", "--no-edit"],
+    );
+}
+
 fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
     test_env.jj_cmd_success(repo_path, &["log", "-T", "commit_id \" \" description"])
 }
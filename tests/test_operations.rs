@@ -14,6 +14,8 @@
 
 use std::path::Path;
 
+use itertools::Itertools;
+
 use crate::common::TestEnvironment;
 
 pub mod common;
@@ -89,6 +91,198 @@ fn test_op_log() {
     "###);
 }
 
+#[test]
+fn test_op_log_template() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "description 1"]);
+
+    // The operation's times and user vary, so we don't show them
+    let template = r#"id.short() " " if(current_operation, "(current) ") upper(description)"#;
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "log", "-T", template]);
+    let redacted = stdout
+        .lines()
+        .map(|line| match line.find(|c: char| c.is_ascii_hexdigit()) {
+            Some(pos) if line.len() > pos + 12 => {
+                format!("{}<id>{}", &line[..pos], &line[pos + 12..])
+            }
+            _ => line.to_string(),
+        })
+        .join("\n");
+    insta::assert_snapshot!(redacted, @r###"
+    @ <id> (current) DESCRIBE COMMIT 230DD059E1B059AEFC0DA06A2E5A7DBF22362F22
+    o <id> ADD WORKSPACE 'DEFAULT'
+    o <id> INITIALIZE REPO
+    "###);
+}
+
+#[test]
+fn test_op_log_tags() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(
+        br#"[operation.tags]
+    tool = "my-tool"
+    "#,
+    );
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["describe", "-m", "first", "--op-tag", "ticket=ABC-123"],
+    );
+
+    let template = r#"description " [" tag("tool") "] [" tag("ticket") "]\n""#;
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["op", "log", "--no-commit-working-copy", "-T", template],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    @ describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22 [my-tool] [ABC-123]
+    o add workspace 'default' [] []
+    o initialize repo [] []
+    "###);
+
+    // The `tags` keyword renders all of them, sorted by key
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "op",
+            "log",
+            "--no-commit-working-copy",
+            "-T",
+            r#"tags "\n""#,
+        ],
+    );
+    assert!(
+        stdout.contains("| ticket: ABC-123\n| tool: my-tool\n"),
+        "{}",
+        stdout
+    );
+
+    // Tags must have a key
+    test_env.jj_cmd_cli_error(&repo_path, &["log", "--op-tag", "=value"]);
+}
+
+#[test]
+fn test_op_log_time_travel() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "log", "-T", "end_time"]);
+    let first_end_time = stdout.lines().next().unwrap()[2..].to_string();
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "second"]);
+
+    // A timestamp as printed by `jj op log` resolves to the operation that had
+    // just finished at that time
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "-T", "description", "--at-op", &first_end_time],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    @ first
+    o (no description set)
+    "###);
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["log", "-T", "description", "--at-op", "now"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ second
+    o (no description set)
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "--at-op", "1 hour ago"]);
+    assert!(
+        stderr.starts_with(r#"Error: No operation had finished at "1 hour ago""#),
+        "{}",
+        stderr
+    );
+
+    // Operations can be filtered by time
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["op", "log", "-T", "description", "--after", &first_end_time],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    @ describe commit 85a1e2839620cf0b354d1ccb970927d040c2a4a7
+    o describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    ~
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "op",
+            "log",
+            "-T",
+            "description",
+            "--before",
+            &first_end_time,
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    o describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    o add workspace 'default'
+    o initialize repo
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["op", "log", "-T", "description", "--before", "1 week ago"],
+    );
+    insta::assert_snapshot!(stdout, @"");
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["op", "log", "--after", "foo"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Invalid date: "foo"
+    "###);
+}
+
+#[test]
+fn test_op_parent_expressions() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "description 1"]);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "description 2"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "log", "-T", "id"]);
+    let op_ids = stdout
+        .lines()
+        .map(|line| line[2..].to_string())
+        .collect_vec();
+
+    // Each "-" goes back one operation, after "@" or after an operation ID
+    let get_description = |op_str: &str| {
+        test_env.jj_cmd_success(
+            &repo_path,
+            &["log", "-r", "@", "-T", "description", "--at-op", op_str],
+        )
+    };
+    insta::assert_snapshot!(get_description("@-"), @r###"
+    @ description 1
+    ~
+    "###);
+    insta::assert_snapshot!(get_description("@--"), @r###"
+    @ (no description set)
+    ~
+    "###);
+    assert_eq!(
+        get_description(&format!("{}-", &op_ids[0][..12])),
+        get_description("@-")
+    );
+    assert_eq!(
+        get_description(&format!("{}-", op_ids[1])),
+        get_description("@--")
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "restore", "@--"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Working copy now at: 230dd059e1b0 (no description set)
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "--at-op", "@-----"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: The "@-----" expression goes back further than the first operation
+    "###);
+}
+
 fn get_log_output(test_env: &TestEnvironment, repo_path: &Path, op_id: &str) -> String {
     test_env.jj_cmd_success(repo_path, &["log", "-T", "commit_id", "--at-op", op_id])
 }
@@ -0,0 +1,280 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+fn set_up() -> (TestEnvironment, PathBuf) {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    let git_repo = git2::Repository::init_bare(&git_repo_path).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let empty_tree_oid = git_repo.treebuilder(None).unwrap().write().unwrap();
+    let empty_tree = git_repo.find_tree(empty_tree_oid).unwrap();
+    git_repo
+        .commit(
+            Some("refs/heads/main"),
+            &signature,
+            &signature,
+            "initial",
+            &empty_tree,
+            &[],
+        )
+        .unwrap();
+    test_env.jj_cmd_success(
+        test_env.env_root(),
+        &["git", "clone", git_repo_path.to_str().unwrap(), "repo"],
+    );
+    let repo_path = test_env.env_root().join("repo");
+    (test_env, repo_path)
+}
+
+/// Starts a fake GitHub API server that keeps track of pull requests. Returns
+/// its address and a log of the requests it received, with change ids
+/// replaced by "<id>".
+fn start_fake_github() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let log = Arc::new(Mutex::new(vec![]));
+    let server_log = log.clone();
+    thread::spawn(move || {
+        // Pull requests by head branch: (number, base)
+        let mut pull_requests: BTreeMap<String, (u64, String)> = BTreeMap::new();
+        let change_id_regex = regex::Regex::new("[0-9a-f]{32}").unwrap();
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            let mut authorized = false;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end();
+                if line.is_empty() {
+                    break;
+                }
+                let (name, value) = line.split_once(": ").unwrap();
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.parse().unwrap();
+                }
+                if name.eq_ignore_ascii_case("authorization") {
+                    authorized = value == "Bearer secret";
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let body: serde_json::Value =
+                serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+            let mut parts = request_line.split_whitespace();
+            let method = parts.next().unwrap().to_string();
+            let path = parts.next().unwrap().to_string();
+            let mut entry = format!("{method} {path}");
+            if !body.is_null() {
+                entry.push_str(&format!(" {body}"));
+            }
+            server_log
+                .lock()
+                .unwrap()
+                .push(change_id_regex.replace_all(&entry, "<id>").to_string());
+
+            let pull_request_json = |head: &str, number: u64, base: &str| {
+                serde_json::json!({
+                    "number": number,
+                    "html_url": format!("https://github.com/owner/repo/pull/{number}"),
+                    "state": "open",
+                    "head": {"ref": head},
+                    "base": {"ref": base},
+                })
+            };
+            let response = if !authorized {
+                serde_json::json!({"message": "Bad credentials"})
+            } else if method == "GET" {
+                let head = path.split("head=owner%3A").nth(1).unwrap();
+                match pull_requests.get(head) {
+                    Some((number, base)) => {
+                        serde_json::json!([pull_request_json(head, *number, base)])
+                    }
+                    None => serde_json::json!([]),
+                }
+            } else if method == "POST" {
+                let head = body["head"].as_str().unwrap().to_string();
+                let base = body["base"].as_str().unwrap().to_string();
+                let number = pull_requests.len() as u64 + 1;
+                pull_requests.insert(head.clone(), (number, base.clone()));
+                pull_request_json(&head, number, &base)
+            } else {
+                let number: u64 = path.rsplit('/').next().unwrap().parse().unwrap();
+                let (head, (_, base)) = pull_requests
+                    .iter_mut()
+                    .find(|(_, (n, _))| *n == number)
+                    .unwrap();
+                *base = body["base"].as_str().unwrap().to_string();
+                pull_request_json(head, number, base)
+            };
+            let status = if authorized {
+                "200 OK"
+            } else {
+                "401 Unauthorized"
+            };
+            let response = response.to_string();
+            let mut writer = stream;
+            write!(
+                writer,
+                "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                 Connection: close\r\n\r\n{response}",
+                response.len()
+            )
+            .unwrap();
+        }
+    });
+    (address, log)
+}
+
+fn take_log(log: &Arc<Mutex<Vec<String>>>) -> String {
+    log.lock().unwrap().drain(..).collect::<Vec<_>>().join("\n")
+}
+
+#[test]
+fn test_pr_create_and_status() {
+    let (test_env, repo_path) = set_up();
+    let (address, log) = start_fake_github();
+    test_env.add_config(
+        format!(
+            r#"
+            pr.forge = "github"
+            pr.repo = "owner/repo"
+            pr.api-url = "http://{address}"
+            pr.token = "secret"
+            "#
+        )
+        .as_bytes(),
+    );
+    test_env.jj_cmd_success(&repo_path, &["checkout", "main"]);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first\n\nbody of first"]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "second"]);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["pr", "status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    a5129eb6f1cd (first): no pull request
+    ffbc90b424d2 (second): no pull request
+    "###);
+
+    take_log(&log);
+
+    // Each change gets a branch and a pull request based on the previous one
+    let stdout = test_env.jj_cmd_success(&repo_path, &["pr", "create"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Pushing 2 branches to origin
+    Created pull request #1 for a5129eb6f1cd (first): https://github.com/owner/repo/pull/1
+    Created pull request #2 for ffbc90b424d2 (second): https://github.com/owner/repo/pull/2
+    "###);
+    let git_repo = git2::Repository::open(test_env.env_root().join("git-repo")).unwrap();
+    assert_eq!(git_repo.branches(None).unwrap().count(), 3);
+    insta::assert_snapshot!(take_log(&log), @r###"
+    GET /repos/owner/repo/pulls?state=all&head=owner%3Apush-<id>
+    POST /repos/owner/repo/pulls {"base":"main","body":"body of first","head":"push-<id>","title":"first"}
+    GET /repos/owner/repo/pulls?state=all&head=owner%3Apush-<id>
+    POST /repos/owner/repo/pulls {"base":"push-<id>","body":"","head":"push-<id>","title":"second"}
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["pr", "status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    a5129eb6f1cd (first): #1 open https://github.com/owner/repo/pull/1
+    ffbc90b424d2 (second): #2 open https://github.com/owner/repo/pull/2
+    "###);
+
+    // After rewriting the first change, both branches need to be pushed again
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["describe", "-r", "@-", "-m", "first, amended"],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["pr", "status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    ea0e86517ec3 (first, amended): #1 open https://github.com/owner/repo/pull/1 (needs push)
+    455dcec441ef (second): #2 open https://github.com/owner/repo/pull/2 (needs push)
+    "###);
+    take_log(&log);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["pr", "create"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Pushing 2 branches to origin
+    Updated pull request #1 for ea0e86517ec3 (first, amended): https://github.com/owner/repo/pull/1
+    Updated pull request #2 for 455dcec441ef (second): https://github.com/owner/repo/pull/2
+    "###);
+    insta::assert_snapshot!(take_log(&log), @r###"
+    GET /repos/owner/repo/pulls?state=all&head=owner%3Apush-<id>
+    PATCH /repos/owner/repo/pulls/1 {"base":"main","body":"","title":"first, amended"}
+    GET /repos/owner/repo/pulls?state=all&head=owner%3Apush-<id>
+    PATCH /repos/owner/repo/pulls/2 {"base":"push-<id>","body":"","title":"second"}
+    "###);
+}
+
+#[test]
+fn test_pr_create_errors() {
+    let (test_env, repo_path) = set_up();
+    let (address, _log) = start_fake_github();
+    test_env.jj_cmd_success(&repo_path, &["checkout", "main"]);
+
+    // The forge can't be derived from a local path
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["pr", "create"]);
+    insta::assert_snapshot!(
+        stderr.replace(test_env.env_root().to_str().unwrap(), "$TEST_ENV"),
+        @r###"
+    Config error: Invalid pull request config: Can't tell the forge from the remote URL $TEST_ENV/git-repo; set pr.forge
+    "###
+    );
+
+    test_env.add_config(
+        format!(
+            r#"
+            pr.forge = "github"
+            pr.repo = "owner/repo"
+            pr.api-url = "http://{address}"
+            "#
+        )
+        .as_bytes(),
+    );
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["pr", "create"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Won't create a pull request for commit f7325da11f1f since it has no description
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["pr", "create", "-r", "main"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Revision e1d60cf57c9f is already on main@origin
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["pr", "create", "--base", "other"]);
+    insta::assert_snapshot!(stderr, @"Error: Branch other@origin doesn't exist");
+
+    // Without a token, the API calls fail
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["pr", "status"]);
+    let stderr = regex::Regex::new("[0-9a-f]{32}")
+        .unwrap()
+        .replace(&stderr, "<id>")
+        .replace(&address, "<address>");
+    insta::assert_snapshot!(stderr, @r###"
+    Error: GET http://<address>/repos/owner/repo/pulls?state=all&head=owner%3Apush-<id> failed with status 401: Bad credentials
+    "###);
+}
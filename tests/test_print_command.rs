@@ -68,4 +68,31 @@ fn test_print() {
     c
     >>>>>>>
     "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "file1", "--no-materialize"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Conflict with 2 sides and 1 base:
+    +++++++ side #1: file with id 78981922613b2afb6025042ff6bd878ac1994e85
+    a
+    ------- base #1: file with id 61780798228d17af2d34fce4cfbdf35556832472
+    b
+    +++++++ side #2: file with id f2ad6c76f0115a6ba5b00456a849810e7ec0af20
+    c
+    "###);
+
+    // The diff shows the conflict the same way
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "--no-materialize"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 7898192261...158eb56d85 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,1 +1,7 @@
+    +Conflict with 2 sides and 1 base:
+    ++++++++ side #1: file with id 78981922613b2afb6025042ff6bd878ac1994e85
+     a
+    +------- base #1: file with id 61780798228d17af2d34fce4cfbdf35556832472
+    +b
+    ++++++++ side #2: file with id f2ad6c76f0115a6ba5b00456a849810e7ec0af20
+    +c
+    "###);
 }
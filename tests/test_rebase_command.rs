@@ -14,7 +14,7 @@
 
 use std::path::Path;
 
-use crate::common::TestEnvironment;
+use crate::common::{get_stderr_string, get_stdout_string, TestEnvironment};
 
 pub mod common;
 
@@ -164,6 +164,218 @@ fn test_rebase_branch_with_merge() {
     "###);
 }
 
+#[test]
+fn test_rebase_branch_merged_with_destination() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    create_commit(&test_env, &repo_path, "a", &[]);
+    create_commit(&test_env, &repo_path, "b", &["a"]);
+    create_commit(&test_env, &repo_path, "x", &["a"]);
+    create_commit(&test_env, &repo_path, "y", &["x", "b"]);
+    create_commit(&test_env, &repo_path, "c", &["b"]);
+    // Test the setup
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 
+    o c
+    | o   y
+    | |\  
+    | |/  
+    |/|   
+    | o x
+    o | b
+    |/  
+    o a
+    o
+    "###);
+
+    // Only the commits that aren't in the destination's history are rebased, and
+    // the merge with the destination's history becomes redundant
+    let stdout = test_env.jj_cmd_success(&repo_path, &["rebase", "-b", "y", "-d", "c"]);
+    insta::assert_snapshot!(stdout, @"Rebased 2 commits");
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    o y
+    o x
+    | @ 
+    |/  
+    o c
+    o b
+    o a
+    o
+    "###);
+}
+
+#[test]
+fn test_rebase_multiple_branches() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    create_commit(&test_env, &repo_path, "a", &[]);
+    create_commit(&test_env, &repo_path, "b", &["a"]);
+    create_commit(&test_env, &repo_path, "c", &["a"]);
+    create_commit(&test_env, &repo_path, "d", &["c"]);
+    create_commit(&test_env, &repo_path, "e", &["a"]);
+    // Test the setup
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 
+    o e
+    | o d
+    | o c
+    |/  
+    | o b
+    |/  
+    o a
+    o
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["rebase", "-b", "d", "-b", "e", "-d", "b"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Rebased 4 commits
+    Working copy now at: 38562ef696ed (no description set)
+    Added 1 files, modified 0 files, removed 0 files
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 
+    | o d
+    | o c
+    o | e
+    |/  
+    o b
+    o a
+    o
+    "###);
+
+    // Branches that are already on top of the destination are left alone
+    let stdout = test_env.jj_cmd_success(&repo_path, &["rebase", "-b", "d", "-b", "e", "-d", "b"]);
+    insta::assert_snapshot!(stdout, @"Nothing changed.");
+}
+
+#[test]
+fn test_rebase_stop_on_conflict() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    let create_commit = |name: &str, parent: &str, file: &str| {
+        test_env.jj_cmd_success(&repo_path, &["co", parent]);
+        std::fs::write(repo_path.join(file), format!("{name}\n")).unwrap();
+        test_env.jj_cmd_success(&repo_path, &["branch", "create", name]);
+        test_env.jj_cmd_success(&repo_path, &["close", "-m", name]);
+    };
+    create_commit("a", "root", "file");
+    create_commit("b", "a", "file");
+    create_commit("c", "a", "file");
+    create_commit("d", "c", "other");
+    let log_template = r#"branches if(conflict, " conflict")"#;
+    // Test the setup
+    let setup_log = test_env.jj_cmd_success(&repo_path, &["log", "-T", log_template]);
+    insta::assert_snapshot!(setup_log, @r###"
+    @ 
+    o d
+    o c
+    | o b
+    |/  
+    o a
+    o
+    "###);
+
+    // Nothing to continue or abort
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["rebase", "--continue"]);
+    insta::assert_snapshot!(stderr, @"Error: No rebase in progress");
+
+    // The rebase stops at the commit with conflicts and edits it
+    let assert = test_env
+        .jj_cmd(
+            &repo_path,
+            &["rebase", "-s", "c", "-d", "b", "--stop-on-conflict"],
+        )
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stdout_string(&assert), @r###"
+    Rebased 1 commits
+    Working copy now at: 35c164050f6b c
+    Added 0 files, modified 1 files, removed 1 files
+    Stopped at 35c164050f6b (c) because it has conflicts
+    "###);
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Hint: Resolve the conflicts and run `jj rebase --continue`, or run `jj rebase --abort` to undo the rebase.
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", log_template]);
+    insta::assert_snapshot!(stdout, @r###"
+    @  conflict
+    o b
+    o a
+    o
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 75a5d847713b b
+    Working copy : 35c164050f6b c
+    Working copy changes:
+    M file
+    There are unresolved conflicts at these paths:
+    file
+    A rebase is in progress. Run `jj rebase --continue` once the conflicts are resolved, or `jj rebase --abort` to undo it.
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["rebase", "-b", "d", "-d", "b"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: A rebase is already in progress. Run `jj rebase --continue` or `jj rebase --abort` first.
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["rebase", "--continue"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Commit 35c164050f6b still has conflicts. Resolve them and run `jj rebase --continue` again.
+    "###);
+
+    // Aborting undoes the rebase but keeps other changes made in the meantime
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "-r", "a", "e"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["rebase", "--abort"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Working copy now at: e39692561067 (no description set)
+    Added 1 files, modified 1 files, removed 0 files
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", log_template]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ 
+    o d
+    o c
+    | o b
+    |/  
+    o a e
+    o
+    "###);
+    test_env.jj_cmd_success(&repo_path, &["branch", "delete", "e"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", log_template]);
+    assert_eq!(stdout, setup_log);
+
+    // Once the conflicts are resolved, the descendants are rebased on top
+    test_env
+        .jj_cmd(
+            &repo_path,
+            &["rebase", "-s", "c", "-d", "b", "--stop-on-conflict"],
+        )
+        .assert()
+        .success();
+    std::fs::write(repo_path.join("file"), "resolved\n").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["rebase", "--continue"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Rebased 2 commits
+    Working copy now at: a32b620acbe5 (no description set)
+    Added 1 files, modified 0 files, removed 0 files
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", log_template]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ 
+    o d
+    o c
+    o b
+    o a
+    o
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "file", "-r", "d"]);
+    insta::assert_snapshot!(stdout, @"resolved");
+}
+
 #[test]
 fn test_rebase_single_revision() {
     let test_env = TestEnvironment::default();
@@ -348,6 +560,39 @@ fn test_rebase_with_descendants() {
     "###);
 }
 
+#[test]
+fn test_rebase_dry_run() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    create_commit(&test_env, &repo_path, "a", &[]);
+    create_commit(&test_env, &repo_path, "b", &[]);
+    create_commit(&test_env, &repo_path, "c", &["b"]);
+    let log_before = get_log_output(&test_env, &repo_path);
+
+    // The rebase is previewed but nothing is changed
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["rebase", "-s", "b", "-d", "a", "--dry-run"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Would hide 3 commits:
+      590f92e01151 ()
+      b91fe843c837 (c)
+      016e25419725 (b)
+    Would add 3 commits:
+      7af91e64f16e ()
+      6f58b9275698 (c)
+      ab78ad748e01 (b)
+    Would move branch b from 016e25419725 to ab78ad748e01
+    Would move branch c from b91fe843c837 to 6f58b9275698
+    Would update the working copy: added 1 files, modified 0 files, removed 0 files
+    Dry-run requested, not committing the transaction.
+    "###);
+    assert_eq!(get_log_output(&test_env, &repo_path), log_before);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "log", "-T", "description"]);
+    assert!(stdout.starts_with("@ close commit"), "{}", stdout);
+}
+
 fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
     test_env.jj_cmd_success(repo_path, &["log", "-T", "branches"])
 }
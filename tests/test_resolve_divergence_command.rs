@@ -0,0 +1,114 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+fn create_divergence(test_env: &TestEnvironment, repo_path: &Path) {
+    std::fs::write(repo_path.join("file1"), "a\n").unwrap();
+    test_env.jj_cmd_success(repo_path, &["describe", "-m", "message 1"]);
+    test_env.jj_cmd_success(repo_path, &["describe", "-m", "message 2", "--at-op", "@-"]);
+    // Add a child to one of the versions
+    test_env.jj_cmd_success(repo_path, &["new", "description(\"message 2\")"]);
+    std::fs::write(repo_path.join("file2"), "b\n").unwrap();
+    test_env.jj_cmd_success(repo_path, &["describe", "-m", "child"]);
+}
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(
+        repo_path,
+        &[
+            "log",
+            "-T",
+            r#"commit_id.short() " " description.first_line() if(divergent, " (divergent)")"#,
+        ],
+    )
+}
+
+#[test]
+fn test_resolve_divergence_keep() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    create_divergence(&test_env, &repo_path);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 89257b264a19 child
+    o 3c322d3ace7a message 2 (divergent)
+    | o f1aee3dd18a9 message 1 (divergent)
+    |/  
+    o 000000000000 (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "-r", "divergent()", "-T", "description.first_line()"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    o message 2
+    ~ 
+    o message 1
+    ~
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["resolve-divergence", "--keep", "description(\"message 1\")"],
+    );
+    assert!(
+        stdout.contains("Rebased 1 descendant commits"),
+        "{}",
+        stdout
+    );
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 5db23b2b59e1 child
+    o f1aee3dd18a9 message 1
+    o 000000000000 (no description set)
+    "###);
+
+    // The change is no longer divergent
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["resolve-divergence", "--keep", "@-"]);
+    assert!(stderr.ends_with("is not divergent\n"), "{}", stderr);
+}
+
+#[test]
+fn test_resolve_divergence_merge() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    create_divergence(&test_env, &repo_path);
+
+    test_env.jj_cmd_success(&repo_path, &["resolve-divergence", "--merge", "@-"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 11a14c7da429 child
+    o 7031fb0af61d message 2
+    o 000000000000 (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files", "-r", "@-"]);
+    insta::assert_snapshot!(stdout, @"file1");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-r", "@-", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    o message 2
+    ~ message 1
+    "###);
+}
+
+#[test]
+fn test_resolve_divergence_requires_action() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_cli_error(&repo_path, &["resolve-divergence"]);
+}
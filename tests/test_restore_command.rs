@@ -101,6 +101,50 @@ fn test_restore() {
     "###);
 }
 
+#[test]
+fn test_restore_into_ancestor() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "b\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "b\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file2"), "c\n").unwrap();
+
+    // Restoring paths into an ancestor merges the restored contents into its
+    // descendants. The working copy's own change to file2 conflicts with it.
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["restore", "--from", "@--", "--to", "@-", "file1", "file2"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Created 785d259a2aa2 (no description set)
+    Rebased 1 descendant commits
+    Working copy now at: 30295995a307 (no description set)
+    Added 0 files, modified 2 files, removed 0 files
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s", "-r", "@-"]);
+    insta::assert_snapshot!(stdout, @"");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @"M file2");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "file1"]);
+    insta::assert_snapshot!(stdout, @"a");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "file2"]);
+    insta::assert_snapshot!(stdout, @r###"
+    <<<<<<<
+    %%%%%%%
+    -b
+    +a
+    +++++++
+    c
+    >>>>>>>
+    "###);
+}
+
 #[test]
 fn test_restore_interactive() {
     let mut test_env = TestEnvironment::default();
@@ -189,7 +233,63 @@ fn test_restore_interactive() {
     +unrelated
     "###);
 
-    // Combining paths with -i is not yet supported
-    std::fs::write(&edit_script, "").unwrap();
-    test_env.jj_cmd_cli_error(&repo_path, &["restore", "-i", "file2"]);
+    // Only the specified paths are shown in the diff editor
+    test_env.jj_cmd_success(&repo_path, &["undo"]);
+    std::fs::write(
+        &edit_script,
+        "files-before file2\0files-after JJ-INSTRUCTIONS file2\0reset file2",
+    )
+    .unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["restore", "-i", "file2"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Created edb8faad587c (no description set)
+    Working copy now at: edb8faad587c (no description set)
+    Added 0 files, modified 1 files, removed 0 files
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    R file1
+    A file3
+    "###);
+}
+
+#[test]
+fn test_restore_interactive_editor_args() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "b\n").unwrap();
+
+    let edit_script = test_env.set_up_fake_diff_editor();
+    let diff_editor_path = assert_cmd::cargo::cargo_bin("fake-diff-editor");
+    let escaped_diff_editor_path = diff_editor_path.to_str().unwrap().replace('\\', r"\\");
+
+    // `ui.diff-editor` can be a program followed by templated arguments
+    test_env.add_config(
+        format!(
+            r#"[ui]
+    diff-editor = ["{escaped_diff_editor_path}", "$left", "$right"]"#
+        )
+        .as_bytes(),
+    );
+    std::fs::write(&edit_script, "reset file1").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["restore", "-i"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Created 5990704bd486 (no description set)
+    Working copy now at: 5990704bd486 (no description set)
+    Added 0 files, modified 1 files, removed 0 files
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @"");
+
+    // `commands.restore.diff-editor` overrides `ui.diff-editor`
+    test_env.add_config(
+        br#"[commands.restore]
+    diff-editor = ["bad-diff-editor-for-restore"]"#,
+    );
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["restore", "-i"]);
+    assert!(stderr.contains("bad-diff-editor-for-restore"));
 }
@@ -0,0 +1,147 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_syntax_error() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-r", ":x()"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to parse revset:  --> 1:2
+      |
+    1 | :x()
+      |  ^
+      |
+      = Revset function "x" doesn't exist
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-r", "x &"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to parse revset:  --> 1:3
+      |
+    1 | x &
+      |   ^---
+      |
+      = Incomplete parse
+    "###);
+}
+
+#[test]
+fn test_bad_function_call() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-r", "all(or)"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to parse revset:  --> 1:5
+      |
+    1 | all(or)
+      |     ^^
+      |
+      = Invalid arguments to revset function "all": Expected 0 arguments
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-r", "parents()"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to parse revset:  --> 1:9
+      |
+    1 | parents()
+      |         ^
+      |
+      = Invalid arguments to revset function "parents": Expected 1 argument
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-r", "description(heads(), x)"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to parse revset:  --> 1:13
+      |
+    1 | description(heads(), x)
+      |             ^-----^
+      |
+      = Invalid arguments to revset function "description": Expected function argument of type string, found: heads()
+    "###);
+
+    // Misspelled function names get suggestions
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-r", "root | ancestor(@)"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to parse revset:  --> 1:8
+      |
+    1 | root | ancestor(@)
+      |        ^------^
+      |
+      = Revset function "ancestor" doesn't exist
+    Hint: Did you mean "ancestors"?
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-r", "xyzzy()"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to parse revset:  --> 1:1
+      |
+    1 | xyzzy()
+      | ^---^
+      |
+      = Revset function "xyzzy" doesn't exist
+    "###);
+}
+
+#[test]
+fn test_bad_symbol() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "main"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "maint"]);
+
+    // The error points at the symbol that doesn't exist
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-r", "root | nonexistent"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to resolve revset:  --> 1:8
+      |
+    1 | root | nonexistent
+      |        ^---------^
+      |
+      = Revision "nonexistent" doesn't exist
+    "###);
+
+    // Misspelled branch names get suggestions
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "-r", "root | mian"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to resolve revset:  --> 1:8
+      |
+    1 | root | mian
+      |        ^--^
+      |
+      = Revision "mian" doesn't exist
+    Hint: Did you mean "main", "maint"?
+    "###);
+
+    // Also when resolving a single revision
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["describe", "\"mani\"", "-m", "x"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to resolve revset:  --> 1:1
+      |
+    1 | "mani"
+      | ^----^
+      |
+      = Revision "mani" doesn't exist
+    Hint: Did you mean "main", "maint"?
+    "###);
+}
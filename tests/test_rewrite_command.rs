@@ -0,0 +1,76 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_rewrite_replace() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::create_dir(repo_path.join("dir")).unwrap();
+    std::fs::write(repo_path.join("file"), "old_name(1)\n").unwrap();
+    std::fs::write(repo_path.join("dir").join("file"), "old_name(2)\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "a"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "b"]);
+    std::fs::write(repo_path.join("file"), "old_name(1)\nold_name(3)\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "c"]);
+    std::fs::write(repo_path.join("other"), "old_name(4)\n").unwrap();
+
+    // Only the given paths in the given revisions are rewritten, and the
+    // descendants are rebased
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "rewrite",
+            "-r",
+            "description(a) | description(b)",
+            "--replace",
+            r"old_name\((\d)\)",
+            "new_name[$1]",
+            "file",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Rewrote 2 commits
+    Rebased 1 descendant commits
+    Working copy now at: 78946118bdf3 c
+    Added 0 files, modified 1 files, removed 0 files
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "description(a)", "file"]);
+    insta::assert_snapshot!(stdout, @"new_name[1]");
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["print", "-r", "description(a)", "dir/file"]);
+    insta::assert_snapshot!(stdout, @"old_name(2)");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "file"]);
+    insta::assert_snapshot!(stdout, @r###"
+    new_name[1]
+    new_name[3]
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "other"]);
+    insta::assert_snapshot!(stdout, @"old_name(4)");
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["rewrite", "--replace", "nothing", "x"]);
+    insta::assert_snapshot!(stdout, @"Rewrote 0 commits");
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["rewrite", "--replace", "(", "x"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Invalid regex "(": regex parse error:
+        (
+        ^
+    error: unclosed group
+    "###);
+}
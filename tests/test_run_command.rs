@@ -0,0 +1,116 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+
+use crate::common::{get_stderr_string, get_stdout_string, TestEnvironment};
+
+pub mod common;
+
+#[test]
+fn test_run() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "a"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "b"]);
+    std::fs::write(repo_path.join("file"), "b\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "c"]);
+    std::fs::write(repo_path.join("file"), "c\n").unwrap();
+
+    // The command runs on each revision, parents first
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["run", "-r", "root..@", "cat file; echo changed >file"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    a
+    13e1d039cf4b (a): passed
+    b
+    54b87f88d2b3 (b): passed
+    c
+    59808ba44b57 (c): passed
+    "###);
+
+    // Failures are reported and make the command fail
+    let assert = test_env
+        .jj_cmd(&repo_path, &["run", "-r", "root..@", "! grep -q b file"])
+        .assert()
+        .failure();
+    insta::assert_snapshot!(get_stdout_string(&assert), @r###"
+    13e1d039cf4b (a): passed
+    54b87f88d2b3 (b): failed
+    59808ba44b57 (c): passed
+    "###);
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Error: The command failed on 1 of 3 revisions
+    "###);
+
+    // The working copy is left alone
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("file")).unwrap(),
+        "c\n"
+    );
+}
+
+#[test]
+fn test_run_env() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let commit_id = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "commit_id"],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["run", "echo $JJ_COMMIT_ID"]);
+    assert!(stdout.starts_with(&format!("{commit_id}\n")), "{stdout}");
+}
+
+#[test]
+fn test_run_workspace_cache() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "a"]);
+
+    // Files left behind by a previous run are removed
+    test_env.jj_cmd_success(&repo_path, &["run", "echo changed >file; touch new"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["run", "cat file; ls"]);
+    insta::assert_snapshot!(stdout, @r###"
+    a
+    file
+    13e1d039cf4b (a): passed
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["debug", "workspace-cache"]);
+    let stdout = regex::Regex::new(r"last used .*")
+        .unwrap()
+        .replace_all(&stdout, "last used <timestamp>");
+    insta::assert_snapshot!(stdout, @r###"
+    0: tree 848740929e99bda0e1a9783e7daa314c5a9732d5, last used <timestamp>
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["debug", "workspace-cache", "--clear"]);
+    insta::assert_snapshot!(stdout, @"Removed 1 working copies.");
+    assert!(!repo_path.join(".jj/workspace-cache/0").exists());
+
+    test_env.add_config(br#"run.workspace-cache-size = 0"#);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["run", "true"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: run.workspace-cache-size must be positive, not 0
+    "###);
+}
@@ -0,0 +1,304 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::thread;
+
+use regex::Regex;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+/// Replaces the random part of the message IDs.
+fn redact_message_ids(text: &str) -> String {
+    let re = Regex::new(r"<jj-(\d+)\.[0-9a-f]{16}-").unwrap();
+    re.replace_all(text, "<jj-$1.<random>-").into_owned()
+}
+
+fn set_up() -> (TestEnvironment, PathBuf) {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "add file\n\nWith a body."]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file"), "b\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "modify file"]);
+    (test_env, repo_path)
+}
+
+#[test]
+fn test_send_dry_run() {
+    let (mut test_env, repo_path) = set_up();
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "send",
+            "--to",
+            "Some One <some.one@example.com>",
+            "--dry-run",
+        ],
+    );
+    insta::assert_snapshot!(redact_message_ids(&stdout), @r###"
+    From 65221e4bb0eb2154bf33d24db10acbe53be5fd9c Mon Sep 17 00:00:00 2001
+    From: Test User <test.user@example.com>
+    To: Some One <some.one@example.com>
+    Subject: [PATCH] modify file
+    Date: Sat, 03 Feb 2001 04:05:09 +0700
+    Message-Id: <jj-981147911000.<random>-0@example.com>
+    MIME-Version: 1.0
+    Content-Type: text/plain; charset=UTF-8
+    Content-Transfer-Encoding: 8bit
+
+    ---
+    diff --git a/file b/file
+    index 7898192261...6178079822 100644
+    --- a/file
+    +++ b/file
+    @@ -1,1 +1,1 @@
+    -a
+    +b
+    "###);
+
+    // A series of patches with a cover letter
+    let edit_script = test_env.set_up_fake_editor();
+    std::fs::write(
+        &edit_script,
+        "expect
+\n
+JJ: Enter the subject of the cover letter on the first line and its body
+JJ: below. Leave it empty to abort. The patches are:
+JJ:   [PATCH 1/2] add file
+JJ:   [PATCH 2/2] modify file
+\0write
+Improve file
+
+This series improves the file.
+",
+    )
+    .unwrap();
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "send",
+            "-r",
+            "root..@",
+            "--to",
+            "some.one@example.com",
+            "--cc",
+            "other@example.com",
+            "--cover-letter",
+            "--dry-run",
+        ],
+    );
+    insta::assert_snapshot!(redact_message_ids(&stdout), @r###"
+    From 0000000000000000000000000000000000000000 Mon Sep 17 00:00:00 2001
+    From: Test User <test.user@example.com>
+    To: some.one@example.com
+    Cc: other@example.com
+    Subject: [PATCH 0/2] Improve file
+    Date: Sat, 03 Feb 2001 04:05:12 +0700
+    Message-Id: <jj-981147912000.<random>-0@example.com>
+    MIME-Version: 1.0
+    Content-Type: text/plain; charset=UTF-8
+    Content-Transfer-Encoding: 8bit
+
+    This series improves the file.
+
+    [PATCH 1/2] add file
+    [PATCH 2/2] modify file
+
+    From 37b2974df3aa07e422525540e9d7db3e45044e70 Mon Sep 17 00:00:00 2001
+    From: Test User <test.user@example.com>
+    To: some.one@example.com
+    Cc: other@example.com
+    Subject: [PATCH 1/2] add file
+    Date: Sat, 03 Feb 2001 04:05:07 +0700
+    Message-Id: <jj-981147912000.<random>-1@example.com>
+    In-Reply-To: <jj-981147912000.<random>-0@example.com>
+    References: <jj-981147912000.<random>-0@example.com>
+    MIME-Version: 1.0
+    Content-Type: text/plain; charset=UTF-8
+    Content-Transfer-Encoding: 8bit
+
+    With a body.
+
+    ---
+    diff --git a/file b/file
+    new file mode 100644
+    index 0000000000..7898192261
+    --- /dev/null
+    +++ b/file
+    @@ -1,0 +1,1 @@
+    +a
+
+    From 65221e4bb0eb2154bf33d24db10acbe53be5fd9c Mon Sep 17 00:00:00 2001
+    From: Test User <test.user@example.com>
+    To: some.one@example.com
+    Cc: other@example.com
+    Subject: [PATCH 2/2] modify file
+    Date: Sat, 03 Feb 2001 04:05:09 +0700
+    Message-Id: <jj-981147912000.<random>-2@example.com>
+    In-Reply-To: <jj-981147912000.<random>-0@example.com>
+    References: <jj-981147912000.<random>-0@example.com>
+    MIME-Version: 1.0
+    Content-Type: text/plain; charset=UTF-8
+    Content-Transfer-Encoding: 8bit
+
+    ---
+    diff --git a/file b/file
+    index 7898192261...6178079822 100644
+    --- a/file
+    +++ b/file
+    @@ -1,1 +1,1 @@
+    -a
+    +b
+    "###);
+
+    // An empty cover letter aborts
+    std::fs::write(&edit_script, "write\n").unwrap();
+    let stderr = test_env.jj_cmd_failure(
+        &repo_path,
+        &["send", "--to", "a@example.com", "--cover-letter"],
+    );
+    insta::assert_snapshot!(stderr, @"Error: Aborted because the cover letter is empty");
+}
+
+#[test]
+fn test_send_checks() {
+    let (test_env, repo_path) = set_up();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["send", "--to", "a@example.com"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Won't send commit d9f7323f4267 since it has no description
+    "###);
+    test_env.jj_cmd_success(&repo_path, &["merge", "@-", "@--", "-m", "merge"]);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["send", "--to", "a@example.com"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Won't send commit 04eac609bc39 since it's a merge
+    "###);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_send_sendmail_command() {
+    let (test_env, repo_path) = set_up();
+    test_env.add_config(
+        br#"send.sendmail-command = ["sh", "-c", "echo \"$@\" >> sent; grep ^Subject >> sent", "sh"]"#,
+    );
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "send",
+            "-r",
+            "root..@",
+            "--to",
+            "Some One <some.one@example.com>",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Sent [PATCH 1/2] add file
+    Sent [PATCH 2/2] modify file
+    "###);
+    let sent = std::fs::read_to_string(repo_path.join("sent")).unwrap();
+    insta::assert_snapshot!(sent, @r###"
+    some.one@example.com
+    Subject: [PATCH 1/2] add file
+    some.one@example.com
+    Subject: [PATCH 2/2] modify file
+    "###);
+}
+
+#[test]
+fn test_send_smtp() {
+    let (test_env, repo_path) = set_up();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    test_env.add_config(
+        format!(
+            r#"
+            send.smtp-server = "{address}"
+            send.smtp-user = "user"
+            send.smtp-password = "pass"
+            "#
+        )
+        .as_bytes(),
+    );
+    // A fake SMTP server that records what it's sent
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut transcript = vec![];
+        writer.write_all(b"220 localhost\r\n").unwrap();
+        let mut in_data = false;
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap() == 0 {
+                break;
+            }
+            let line = line.trim_end().to_string();
+            let reply: &[u8] = if in_data {
+                if line == "." {
+                    in_data = false;
+                    b"250 OK\r\n"
+                } else {
+                    if line.starts_with("Subject:") || line.starts_with("..") {
+                        transcript.push(line);
+                    }
+                    continue;
+                }
+            } else if line == "EHLO localhost" {
+                b"250-localhost\r\n250 AUTH PLAIN\r\n"
+            } else if line == "DATA" {
+                in_data = true;
+                b"354 Go ahead\r\n"
+            } else if line == "QUIT" {
+                b"221 Bye\r\n"
+            } else {
+                b"250 OK\r\n"
+            };
+            if !in_data || line == "DATA" {
+                transcript.push(line.clone());
+            }
+            writer.write_all(reply).unwrap();
+            if line == "QUIT" {
+                break;
+            }
+        }
+        transcript
+    });
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["describe", "-m", "modify file\n\n.starts with a dot"],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["send", "--to", "some.one@example.com"]);
+    insta::assert_snapshot!(stdout, @"Sent [PATCH] modify file");
+    let transcript = server.join().unwrap();
+    insta::assert_snapshot!(transcript.join("\n"), @r###"
+    EHLO localhost
+    AUTH PLAIN AHVzZXIAcGFzcw==
+    MAIL FROM:<test.user@example.com>
+    RCPT TO:<some.one@example.com>
+    DATA
+    Subject: [PATCH] modify file
+    ..starts with a dot
+    .
+    QUIT
+    "###);
+}
@@ -0,0 +1,90 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use itertools::Itertools;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_show() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "add file1"]);
+    std::fs::write(repo_path.join("file2"), "bar\n").unwrap();
+
+    // The change id is random, so only check the rest of the default output
+    let stdout = test_env.jj_cmd_success(&repo_path, &["show"]);
+    let stdout = stdout
+        .lines()
+        .filter(|line| !line.starts_with("Change ID: "))
+        .join("\n");
+    insta::assert_snapshot!(stdout, @r###"
+    Commit ID: 1dec5ee2f3deefaeb90a833899b3b28f30c3afff
+    Author: Test User <test.user@example.com> (2001-02-03 04:05:07.000 +07:00)
+    Committer: Test User <test.user@example.com> (2001-02-03 04:05:09.000 +07:00)
+
+    add file1
+
+    Added regular file file1:
+            1: foo
+    Added regular file file2:
+            1: bar
+    "###);
+
+    // With a custom template
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "show",
+            "-T",
+            r#"description.first_line() "\n""#,
+            "--summary",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    add file1
+    A file1
+    A file2
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["show", "-T", r#""""#, "--stat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    file1 | 1 +
+    file2 | 1 +
+    2 files changed, 2 insertions(+), 0 deletions(-)
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["show", "-T", r#""""#, "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    new file mode 100644
+    index 0000000000..257cc5642c
+    --- /dev/null
+    +++ b/file1
+    @@ -1,0 +1,1 @@
+    +foo
+    diff --git a/file2 b/file2
+    new file mode 100644
+    index 0000000000..5716ca5987
+    --- /dev/null
+    +++ b/file2
+    @@ -1,0 +1,1 @@
+    +bar
+    "###);
+}
@@ -0,0 +1,113 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_snapshot_auto_disabled() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"snapshot.auto = false"#);
+
+    // Read-only commands don't snapshot the working copy or create operations
+    std::fs::write(repo_path.join("file"), "contents\n").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 000000000000 (no description set)
+    Working copy : 230dd059e1b0 (no description set)
+    The working copy is clean
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ add workspace 'default'
+    o initialize repo
+    "###);
+
+    // Commands that would update the working copy refuse to overwrite changes that
+    // haven't been snapshotted
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["new"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: The working copy has changes that haven't been snapshotted (snapshot.auto is disabled):
+      file
+    Run `jj snapshot` first, or use --discard-changes to overwrite them.
+    "###);
+    assert!(repo_path.join("file").exists());
+
+    // `jj snapshot` records the changes explicitly
+    let stdout = test_env.jj_cmd_success(&repo_path, &["snapshot"]);
+    insta::assert_snapshot!(stdout, @"");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 000000000000 (no description set)
+    Working copy : 29f1fdd8d78b (no description set)
+    Working copy changes:
+    A file
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["new"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Working copy now at: 0294c4fd4ba5 (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ new empty commit
+    o commit working copy
+    o add workspace 'default'
+    o initialize repo
+    "###);
+}
+
+#[test]
+fn test_snapshot_auto_disabled_discard_changes() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"snapshot.auto = false"#);
+
+    std::fs::write(repo_path.join("file"), "1\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["snapshot"]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file"), "2\n").unwrap();
+
+    // The error lists the files with unsnapshotted changes
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["new", "root"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: The working copy has changes that haven't been snapshotted (snapshot.auto is disabled):
+      file
+    Run `jj snapshot` first, or use --discard-changes to overwrite them.
+    "###);
+    assert_eq!(std::fs::read(repo_path.join("file")).unwrap(), b"2\n");
+
+    // The changes can be discarded explicitly
+    let stdout = test_env.jj_cmd_success(&repo_path, &["new", "root", "--discard-changes"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Working copy now at: 7c376b2ea453 (no description set)
+    Added 0 files, modified 0 files, removed 1 files
+    "###);
+    assert!(!repo_path.join("file").exists());
+}
+
+#[test]
+fn test_snapshot_at_op() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["snapshot", "--at-op", "@-"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: This command must be able to update the working copy (don't use --at-op).
+    "###);
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::common::TestEnvironment;
+use crate::common::{get_stderr_string, get_stdout_string, TestEnvironment};
 
 pub mod common;
 
@@ -112,3 +112,161 @@ fn test_sparse_manage_patterns() {
     assert!(repo_path.join("file2").exists());
     assert!(repo_path.join("file3").exists());
 }
+
+#[test]
+fn test_sparse_profiles() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::create_dir(repo_path.join("frontend")).unwrap();
+    std::fs::create_dir(repo_path.join("backend")).unwrap();
+    std::fs::create_dir(repo_path.join("docs")).unwrap();
+    std::fs::create_dir(repo_path.join("profiles")).unwrap();
+    std::fs::write(repo_path.join("frontend").join("file"), "contents").unwrap();
+    std::fs::write(repo_path.join("backend").join("file"), "contents").unwrap();
+    std::fs::write(repo_path.join("docs").join("file"), "contents").unwrap();
+    std::fs::write(
+        repo_path.join("profiles").join("frontend.sparse"),
+        "# The web app\nfrontend/\n\nprofiles\n",
+    )
+    .unwrap();
+    std::fs::write(repo_path.join("profiles").join("docs.sparse"), "docs\n").unwrap();
+
+    // Can use only a profile's patterns
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["sparse", "--clear", "--profile", "frontend"]);
+    insta::assert_snapshot!(stdout, @"Added 0 files, modified 0 files, removed 2 files");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["sparse", "--list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    frontend
+    profiles
+    "###);
+    assert!(repo_path.join("frontend").join("file").exists());
+    assert!(!repo_path.join("backend").join("file").exists());
+    assert!(!repo_path.join("docs").join("file").exists());
+
+    // Can combine profiles and paths
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["sparse", "--profile", "docs", "--add", "backend"],
+    );
+    insta::assert_snapshot!(stdout, @"Added 2 files, modified 0 files, removed 0 files");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["sparse", "--list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    backend
+    docs
+    frontend
+    profiles
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["sparse", "--profile", "missing"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: No sparse profile named "missing" (expected a file at profiles/missing.sparse)
+    "###);
+
+    // Profiles are read from the configured directory
+    test_env.add_config(br#"sparse.profiles-dir = "sparse""#);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["sparse", "--profile", "docs"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: No sparse profile named "docs" (expected a file at sparse/docs.sparse)
+    "###);
+}
+
+#[test]
+fn test_sparse_from_file() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::create_dir(repo_path.join("dir1")).unwrap();
+    std::fs::create_dir(repo_path.join("dir2")).unwrap();
+    std::fs::write(repo_path.join("dir1").join("file"), "contents").unwrap();
+    std::fs::write(repo_path.join("dir2").join("file"), "contents").unwrap();
+    std::fs::write(repo_path.join("file3"), "contents").unwrap();
+    std::fs::write(
+        test_env.env_root().join("patterns"),
+        "# generated\ndir1/\nfile3\n",
+    )
+    .unwrap();
+
+    // The file replaces the existing patterns
+    let stdout = test_env.jj_cmd_success(&repo_path, &["sparse", "--from-file", "../patterns"]);
+    insta::assert_snapshot!(stdout, @"Added 0 files, modified 0 files, removed 1 files");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["sparse", "--list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    dir1
+    file3
+    "###);
+
+    // Can read from stdin
+    let assert = test_env
+        .jj_cmd(&repo_path, &["sparse", "--from-file", "-"])
+        .write_stdin("dir2\n")
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stdout_string(&assert), @"Added 1 files, modified 0 files, removed 2 files");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["sparse", "--list"]);
+    insta::assert_snapshot!(stdout, @"dir2");
+
+    // Invalid patterns are rejected without changing anything
+    let assert = test_env
+        .jj_cmd(&repo_path, &["sparse", "--from-file", "-"])
+        .write_stdin("dir1\n../outside\n")
+        .assert()
+        .failure();
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Error: Invalid sparse pattern on line 2 of stdin: "../outside"
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["sparse", "--list"]);
+    insta::assert_snapshot!(stdout, @"dir2");
+
+    // So is input that isn't UTF-8
+    let assert = test_env
+        .jj_cmd(&repo_path, &["sparse", "--from-file", "-"])
+        .write_stdin(b"dir1\n\xff\n".as_slice())
+        .assert()
+        .failure();
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Error: Failed to read stdin: stream did not contain valid UTF-8
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["sparse", "--list"]);
+    insta::assert_snapshot!(stdout, @"dir2");
+}
+
+#[test]
+fn test_sparse_hydrate_placeholders() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("small"), "small").unwrap();
+    std::fs::write(repo_path.join("large"), "large contents").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["sparse", "--clear"]);
+
+    // Files larger than the threshold get checked out as placeholders
+    test_env.add_config(b"working-copy.lazy-threshold = 10");
+    test_env.jj_cmd_success(&repo_path, &["sparse", "--reset"]);
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("small")).unwrap(),
+        "small"
+    );
+    let placeholder = std::fs::read_to_string(repo_path.join("large")).unwrap();
+    insta::assert_snapshot!(placeholder, @r###"
+    This file has not been materialized because it is large. Run `jj sparse --hydrate large` to materialize it.
+    "###);
+    // The placeholder's contents are not recorded
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "large"]);
+    insta::assert_snapshot!(stdout, @"large contents");
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["sparse", "--hydrate", "large"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Materialized 1 files
+    "###);
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("large")).unwrap(),
+        "large contents"
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "large"]);
+    insta::assert_snapshot!(stdout, @"large contents");
+}
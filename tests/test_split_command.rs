@@ -58,3 +58,41 @@ fn test_split() {
     A file3
     "###);
 }
+
+#[test]
+fn test_split_by_paths_without_editor() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "both files"]);
+
+    // Both parts keep the description, and no editor is run
+    let stdout = test_env.jj_cmd_success(&repo_path, &["split", "file2"]);
+    insta::assert_snapshot!(stdout, @r###"
+    First part: 44fbcce73800 both files
+    Second part: fb6398d5e80a both files
+    Working copy now at: fb6398d5e80a both files
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s", "-r", "@-"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file2
+    "###);
+
+    // The first part's description can be given
+    test_env.jj_cmd_success(&repo_path, &["undo"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["split", "-m", "file2 only", "file2"]);
+    insta::assert_snapshot!(stdout, @r###"
+    First part: 3cb4e563ff3b file2 only
+    Second part: 0006f371e493 both files
+    Working copy now at: 0006f371e493 both files
+    "###);
+
+    // Paths that don't match any changes are an error
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["split", "file2"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: No changes in commit 0006f371e493 match the given paths
+    "###);
+}
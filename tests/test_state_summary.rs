@@ -0,0 +1,105 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+fn read_summary(repo_path: &Path) -> String {
+    let content =
+        std::fs::read_to_string(repo_path.join(".jj").join("state-summary.json")).unwrap();
+    let summary: serde_json::Value = serde_json::from_str(&content).unwrap();
+    // The IDs are left out since they're checked against `jj log` separately
+    format!(
+        "description={} conflicts={} branches={}\n",
+        summary["description"], summary["conflicts"], summary["branches"]
+    )
+}
+
+#[test]
+fn test_state_summary() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    let summary_path = repo_path.join(".jj").join("state-summary.json");
+
+    // Not written by default
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    assert!(!summary_path.exists());
+
+    test_env.add_config(br#"ui.state-summary = true"#);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "second\n\nmore details"]);
+    insta::assert_snapshot!(read_summary(&repo_path), @r###"
+    description="second" conflicts=0 branches=[]
+    "###);
+    let content = std::fs::read_to_string(&summary_path).unwrap();
+    let summary: serde_json::Value = serde_json::from_str(&content).unwrap();
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "log",
+            "--no-graph",
+            "-r",
+            "@",
+            "-T",
+            r#"change_id " " commit_id"#,
+        ],
+    );
+    assert_eq!(
+        stdout,
+        format!(
+            "{} {}",
+            summary["change_id"].as_str().unwrap(),
+            summary["commit_id"].as_str().unwrap()
+        )
+    );
+
+    // Updated when branches move
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "main"]);
+    insta::assert_snapshot!(read_summary(&repo_path), @r###"
+    description="second" conflicts=0 branches=["main"]
+    "###);
+
+    // Updated when the working copy is snapshotted, even by a read-only command
+    std::fs::write(repo_path.join("file"), "contents").unwrap();
+    let before = std::fs::read_to_string(&summary_path).unwrap();
+    test_env.jj_cmd_success(&repo_path, &["status"]);
+    assert_ne!(std::fs::read_to_string(&summary_path).unwrap(), before);
+    insta::assert_snapshot!(read_summary(&repo_path), @r###"
+    description="second" conflicts=0 branches=["main"]
+    "###);
+}
+
+#[test]
+fn test_state_summary_conflicts() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"ui.state-summary = true"#);
+
+    std::fs::write(repo_path.join("file"), "base\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "@-"]);
+    std::fs::write(repo_path.join("file"), "b\n").unwrap();
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["merge", "@", "heads(all()) ~ @", "-m", "merge"],
+    );
+    insta::assert_snapshot!(read_summary(&repo_path), @r###"
+    description="merge" conflicts=1 branches=[]
+    "###);
+}
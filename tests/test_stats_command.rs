@@ -0,0 +1,62 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_stats() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["stats"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Commits: 2
+    Backend: local
+    Chunked files: 0 (0 B)
+    Distinct chunks: 0 (0 B, 0 B stored)
+    "###);
+
+    // Two versions of a large file that only differ in a few bytes share most
+    // of their chunks
+    let mut state: u32 = 1;
+    let mut contents: Vec<u8> = (0..2 << 20)
+        .map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect();
+    std::fs::write(repo_path.join("file"), &contents).unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    contents[1 << 20] ^= 0xff;
+    std::fs::write(repo_path.join("file"), &contents).unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["stats"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Commits: 5
+    Backend: local
+    Chunked files: 2 (4.0 MiB)
+    Distinct chunks: 32 (2.1 MiB, 2.1 MiB stored)
+    Saved by deduplication: 1.9 MiB (48%)
+    "###);
+
+    // The git backend doesn't chunk files
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "git-repo", "--git"]);
+    let stdout = test_env.jj_cmd_success(&test_env.env_root().join("git-repo"), &["stats"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Commits: 2
+    Backend: git
+    "###);
+}
@@ -0,0 +1,184 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_status_paths() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::create_dir(repo_path.join("dir")).unwrap();
+    std::fs::write(repo_path.join("dir").join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status", "dir"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 000000000000 (no description set)
+    Working copy : 258269438fcf (no description set)
+    Working copy changes:
+    A dir/file1
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status", "file3"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 000000000000 (no description set)
+    Working copy : 258269438fcf (no description set)
+    The working copy is clean
+    "###);
+}
+
+#[test]
+fn test_status_merge_and_conflicts() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file"), "a\n").unwrap();
+    std::fs::write(repo_path.join("other"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "left"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "root", "-m", "right"]);
+    std::fs::write(repo_path.join("file"), "b\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "description(left)", "@"]);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 29f45765817a left
+    Parent commit: e755180d17ac right
+    Working copy : f9640da16577 (no description set)
+    The working copy is clean
+    There are unresolved conflicts at these paths:
+    file
+    "###);
+
+    // Conflicts outside the given paths aren't shown
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status", "other"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 29f45765817a left
+    Parent commit: e755180d17ac right
+    Working copy : f9640da16577 (no description set)
+    The working copy is clean
+    "###);
+}
+
+#[test]
+fn test_status_other_workspaces() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "main", "--git"]);
+    let main_path = test_env.env_root().join("main");
+    let secondary_path = test_env.env_root().join("secondary");
+
+    std::fs::write(main_path.join("file"), "contents\n").unwrap();
+    test_env.jj_cmd_success(&main_path, &["close", "-m", "shared"]);
+    test_env.jj_cmd_success(
+        &main_path,
+        &["workspace", "add", "--name", "second", "../secondary"],
+    );
+    test_env.jj_cmd_success(&main_path, &["edit", "description(shared)"]);
+    test_env.jj_cmd_success(&secondary_path, &["edit", "description(shared)"]);
+
+    let stdout = test_env.jj_cmd_success(&main_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 000000000000 (no description set)
+    Working copy : 9f32cabbd650 shared
+    The working copy change is also checked out in workspace: second
+    Working copy changes:
+    A file
+    "###);
+}
+
+#[test]
+fn test_status_template() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "my change"]);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "status",
+            "-T",
+            r#"commit_id.short() " " upper(first_line(description))"#,
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 000000000000 (NO DESCRIPTION SET)
+    Working copy : 9effb3f78ce6 MY CHANGE
+    The working copy is clean
+    "###);
+}
+
+#[test]
+fn test_status_remote_branches() {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    let git_repo = git2::Repository::init_bare(&git_repo_path).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let empty_tree_oid = git_repo.treebuilder(None).unwrap().write().unwrap();
+    let empty_tree = git_repo.find_tree(empty_tree_oid).unwrap();
+    let base_oid = git_repo
+        .commit(None, &signature, &signature, "base", &empty_tree, &[])
+        .unwrap();
+    let base = git_repo.find_commit(base_oid).unwrap();
+    git_repo
+        .commit(
+            Some("refs/heads/main"),
+            &signature,
+            &signature,
+            "remote change",
+            &empty_tree,
+            &[&base],
+        )
+        .unwrap();
+    test_env.jj_cmd_success(
+        test_env.env_root(),
+        &["git", "clone", git_repo_path.to_str().unwrap(), "repo"],
+    );
+    let repo_path = test_env.env_root().join("repo");
+
+    // Nothing is shown while the branch matches the remote
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    assert!(!stdout.contains("main@origin"), "{stdout}");
+
+    // The branch on the parent commit is compared to its remote branch
+    test_env.jj_cmd_success(&repo_path, &["co", "main-"]);
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "local 1"]);
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "local 2"]);
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["branch", "set", "main", "-r", "@-", "--allow-backwards"],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: caba0630eb45 local 2
+    Working copy : 0e6ce83222b8 (no description set)
+    main is 2 ahead, 1 behind main@origin
+    The working copy is clean
+    "###);
+
+    test_env.jj_cmd_success(&repo_path, &["branch", "set", "main", "-r", "@"]);
+    test_env.jj_cmd_success(&repo_path, &["rebase", "-d", "main@origin"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 48b49ee96303 local 2
+    Working copy : 8c3fe0292854 (no description set)
+    main is 3 ahead of main@origin
+    The working copy is clean
+    "###);
+}
@@ -0,0 +1,89 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_summary() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\nb\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "a\nc\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "second"]);
+    test_env
+        .jj_cmd(&repo_path, &["new", "-m", "third"])
+        .env("JJ_USER", "Other User")
+        .env("JJ_EMAIL", "other.user@example.com")
+        .assert()
+        .success();
+    std::fs::write(repo_path.join("file3"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["status"]);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["summary"]);
+    insta::assert_snapshot!(stdout, @r###"
+    3 commits by 2 authors, 3 files changed, 5 insertions(+), 1 deletion(-)
+
+    Most changed files:
+      2  file1
+      1  file2
+      1  file3
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["summary", "--by-author", "--by-day", "--files", "1"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    3 commits by 2 authors, 3 files changed, 5 insertions(+), 1 deletion(-)
+
+    Commits per author:
+      2  Test User <test.user@example.com> (+4 -1)
+      1  Other User <other.user@example.com> (+1 -0)
+
+    Commits per day:
+      2001-02-03  3
+
+    Most changed files:
+      2  file1
+    "###);
+
+    // Only some revisions
+    let stdout = test_env.jj_cmd_success(&repo_path, &["summary", "-r", "@", "--by-author"]);
+    insta::assert_snapshot!(stdout, @r###"
+    1 commit by 1 author, 1 file changed, 1 insertion(+), 0 deletions(-)
+
+    Commits per author:
+      1  Other User <other.user@example.com> (+1 -0)
+
+    Most changed files:
+      1  file3
+    "###);
+
+    // Only recent commits
+    let stdout = test_env.jj_cmd_success(&repo_path, &["summary", "--since", "2001-02-04"]);
+    insta::assert_snapshot!(stdout, @r###"
+    0 commits by 0 authors, 0 files changed, 0 insertions(+), 0 deletions(-)
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["summary", "--since", "soon"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Invalid date: "soon"
+    "###);
+}
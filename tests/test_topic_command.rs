@@ -0,0 +1,111 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_topic_follows_rewrites() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "third"]);
+    test_env.jj_cmd_success(&repo_path, &["topic", "set", "login", "-r", "@--:@-"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ third
+    o second [login]
+    o first [login]
+    o (no description set)
+    "###);
+
+    // The topic stays with the changes when they're rewritten
+    test_env.jj_cmd_success(&repo_path, &["describe", "@--", "-m", "first v2"]);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "log",
+            "--no-graph",
+            "-r",
+            "topic(login)",
+            "-T",
+            r#"description"#,
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    second
+    first v2
+    "###);
+
+    // Topics are shown in the default log template
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-r", "@-"]);
+    assert!(stdout.contains(" topic:login\n"), "{stdout}");
+
+    // Setting a topic replaces the previous one
+    test_env.jj_cmd_success(&repo_path, &["topic", "set", "signup", "-r", "@-"]);
+    test_env.jj_cmd_success(&repo_path, &["topic", "set", "signup"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ third [signup]
+    o second [signup]
+    o first v2 [login]
+    o (no description set)
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["topic", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    login:
+      abd5da35bc4b first v2
+    signup:
+      15eddad9be10 third
+      7733e19693fc second
+    "###);
+
+    test_env.jj_cmd_success(&repo_path, &["topic", "clear", "-r", "@-:@"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ third
+    o second
+    o first v2 [login]
+    o (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["topic", "clear"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Nothing changed.
+    "###);
+
+    // Invalid topics are rejected
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["topic", "set", "two words"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Invalid topic: "two words"
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["topic", "set", "root", "-r", "root"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Cannot set the topic of the root commit
+    "###);
+}
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(
+        repo_path,
+        &[
+            "log",
+            "-T",
+            r#"description.first_line() if(topic, " [" topic "]")"#,
+        ],
+    )
+}
@@ -46,3 +46,50 @@ fn test_undo_rewrite_with_child() {
     o (no description set)
     "###);
 }
+
+#[test]
+fn test_undo_command_group() {
+    // Test that undoing a command that committed several operations undoes all
+    // of them, but keeps the working-copy snapshot it took unless
+    // `--include-snapshot` is passed.
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file"), "contents\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "modified"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ describe commit cf911c223d3e24e001fc8264d6dbf0610804fc40
+    o commit working copy
+    o add workspace 'default'
+    o initialize repo
+    "###);
+
+    // The uncommitted edits are kept by default
+    test_env.jj_cmd_success(&repo_path, &["undo"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ (no description set)
+    o (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file
+    "###);
+    assert!(repo_path.join("file").exists());
+
+    std::fs::write(repo_path.join("file2"), "contents\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "modified"]);
+    test_env.jj_cmd_success(&repo_path, &["undo", "--include-snapshot"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ (no description set)
+    o (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file
+    "###);
+    assert!(!repo_path.join("file2").exists());
+}
@@ -0,0 +1,102 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::{get_stderr_string, get_stdout_string, TestEnvironment};
+
+pub mod common;
+
+fn create_commit(test_env: &TestEnvironment, repo_path: &Path, name: &str, parent: &str) {
+    test_env.jj_cmd_success(repo_path, &["checkout", parent]);
+    std::fs::write(repo_path.join(name), name).unwrap();
+    test_env.jj_cmd_success(repo_path, &["close", "-m", name]);
+    test_env.jj_cmd_success(repo_path, &["branch", "create", name, "-r", "@-"]);
+}
+
+#[test]
+fn test_unhide() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    create_commit(&test_env, &repo_path, "a", "root");
+    create_commit(&test_env, &repo_path, "b", "a");
+    create_commit(&test_env, &repo_path, "c", "b");
+    test_env.jj_cmd_success(&repo_path, &["abandon", "-r", "b"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path, "all()"), @r###"
+    @ (no description set)
+    o c
+    o a
+    o (no description set)
+    "###);
+    insta::assert_snapshot!(
+        get_log_output(&test_env, &repo_path, "description(b, hidden()) | description(c, hidden())"),
+        @r###"
+    o c (hidden)
+    o b (hidden)
+    ~
+    "###
+    );
+
+    // Hidden commits can be addressed by id, and are marked as hidden
+    let hidden_b = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "log",
+            "--no-graph",
+            "-r",
+            "description(b, hidden())",
+            "-T",
+            "commit_id",
+        ],
+    );
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path, &hidden_b), @r###"
+    o b (hidden)
+    ~
+    "###);
+
+    // Unhiding makes the commit a visible head again
+    let stdout = test_env.jj_cmd_success(&repo_path, &["unhide", &hidden_b]);
+    insta::assert_snapshot!(stdout, @"Unhid 3261194e4ff2 b");
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path, "all()"), @r###"
+    @ (no description set)
+    o c
+    | o b
+    |/  
+    o a
+    o (no description set)
+    "###);
+
+    // Visible commits are left alone
+    let assert = test_env
+        .jj_cmd(&repo_path, &["unhide", "a"])
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stdout_string(&assert), @"Nothing changed.");
+    insta::assert_snapshot!(get_stderr_string(&assert), @"Commit 738732d0bc3f is already visible");
+}
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path, revisions: &str) -> String {
+    test_env.jj_cmd_success(
+        repo_path,
+        &[
+            "log",
+            "-r",
+            revisions,
+            "-T",
+            r#"description.first_line() if(hidden, " (hidden)")"#,
+        ],
+    )
+}
@@ -0,0 +1,79 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::{get_stderr_string, get_stdout_string, TestEnvironment};
+
+pub mod common;
+
+#[test]
+fn test_verify() {
+    let test_env = TestEnvironment::default();
+    let repo_path = test_env.env_root().join("repo");
+    let git_repo = git2::Repository::init(&repo_path).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let empty_tree_oid = git_repo.treebuilder(None).unwrap().write().unwrap();
+    let empty_tree = git_repo.find_tree(empty_tree_oid).unwrap();
+    let buffer = git_repo
+        .commit_create_buffer(&signature, &signature, "signed", &empty_tree, &[])
+        .unwrap();
+    let commit_oid = git_repo
+        .commit_signed(
+            std::str::from_utf8(&buffer).unwrap(),
+            "-----BEGIN PGP SIGNATURE-----\n\nbm90IGEgc2lnbmF0dXJl\n-----END PGP SIGNATURE-----",
+            None,
+        )
+        .unwrap();
+    git_repo
+        .reference("refs/heads/signed", commit_oid, false, "")
+        .unwrap();
+    test_env.jj_cmd_success(&repo_path, &["init", "--git-repo", "."]);
+    test_env.jj_cmd_success(&repo_path, &["new", "signed", "-m", "unsigned"]);
+
+    // The root commit is skipped
+    let stdout = test_env.jj_cmd_success(&repo_path, &["verify", "-r", "root"]);
+    insta::assert_snapshot!(stdout, @"");
+
+    let assert = test_env
+        .jj_cmd(&repo_path, &["verify", "-r", "signed | @"])
+        .assert()
+        .failure();
+    let stdout = get_stdout_string(&assert);
+    assert!(stdout.starts_with("Unsigned: "), "{stdout}");
+    assert!(stdout.contains("\nBad signature: "), "{stdout}");
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Error: 1 unsigned and 1 badly signed commits (out of 2)
+    "###);
+
+    let assert = test_env
+        .jj_cmd(&repo_path, &["verify", "-r", "signed | @", "--json"])
+        .assert()
+        .failure();
+    let summary: serde_json::Value = serde_json::from_str(&get_stdout_string(&assert)).unwrap();
+    assert_eq!(summary["good"], 0);
+    assert_eq!(summary["unsigned"], 1);
+    assert_eq!(summary["bad"], 1);
+    assert_eq!(summary["commits"][0]["status"], "unsigned");
+    assert_eq!(summary["commits"][1]["status"], "bad");
+    assert_eq!(summary["commits"][1]["commit_id"], commit_oid.to_string());
+}
+
+#[test]
+fn test_verify_not_git_backed() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["verify"]);
+    insta::assert_snapshot!(stderr, @"Error: The repo is not backed by a git repo");
+}
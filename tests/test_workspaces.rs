@@ -16,7 +16,7 @@ use std::path::Path;
 
 use itertools::Itertools;
 
-use crate::common::TestEnvironment;
+use crate::common::{get_stderr_string, TestEnvironment};
 
 pub mod common;
 
@@ -69,6 +69,99 @@ fn test_workspaces_add_second_workspace() {
     default: 988d8c1dca7e (no description set)
     second: 8ac248e0c8d2 (no description set)
     "###);
+
+    // The working copies can be selected in revsets from either workspace
+    let stdout = test_env.jj_cmd_success(
+        &secondary_path,
+        &[
+            "log",
+            "--no-graph",
+            "-r",
+            "working_copies()",
+            "-T",
+            r#"working_copies "\n""#,
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    second@
+    default@
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &main_path,
+        &[
+            "log",
+            "--no-graph",
+            "-r",
+            "second@",
+            "-T",
+            r#"working_copies "\n""#,
+        ],
+    );
+    insta::assert_snapshot!(stdout, @"second@");
+    // The old name of the keyword still works
+    let stdout = test_env.jj_cmd_success(
+        &main_path,
+        &[
+            "log",
+            "--no-graph",
+            "-r",
+            "working_copies()",
+            "-T",
+            r#"checkouts "\n""#,
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    second@
+    default@
+    "###);
+}
+
+/// Test adding a workspace narrowed to some paths and at a given revision
+#[test]
+fn test_workspaces_add_sparse_at_revision() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "--git", "main"]);
+    let main_path = test_env.env_root().join("main");
+    let secondary_path = test_env.env_root().join("secondary");
+
+    std::fs::create_dir(main_path.join("dir")).unwrap();
+    std::fs::write(main_path.join("dir").join("file"), "contents").unwrap();
+    std::fs::write(main_path.join("file"), "contents").unwrap();
+    test_env.jj_cmd_success(&main_path, &["close", "-m", "first"]);
+    std::fs::write(main_path.join("dir").join("file2"), "contents").unwrap();
+    test_env.jj_cmd_success(&main_path, &["close", "-m", "second"]);
+
+    let stdout = test_env.jj_cmd_success(
+        &main_path,
+        &[
+            "workspace",
+            "add",
+            "-r",
+            "@--",
+            "--sparse-pattern",
+            "dir",
+            "../secondary",
+        ],
+    );
+    insta::assert_snapshot!(stdout.replace('\\', "/"), @r###"
+    Created workspace in "../secondary"
+    Working copy now at: c1c2f07409c0 (no description set)
+    Added 1 files, modified 0 files, removed 0 files
+    "###);
+    assert!(secondary_path.join("dir").join("file").exists());
+    assert!(!secondary_path.join("dir").join("file2").exists());
+    assert!(!secondary_path.join("file").exists());
+    let stdout = test_env.jj_cmd_success(&secondary_path, &["sparse", "--list"]);
+    insta::assert_snapshot!(stdout, @"dir");
+
+    let stderr = test_env.jj_cmd_failure(
+        &main_path,
+        &["workspace", "add", "--sparse-pattern", "../x", "../third"],
+    );
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Invalid sparse pattern: "../x"
+    "###);
+    assert!(!test_env.env_root().join("third").exists());
 }
 
 /// Test making changes to the working copy in a workspace as it gets rewritten
@@ -112,10 +205,35 @@ fn test_workspaces_conflicting_edits() {
     o 52601f748bf6cb00ad5389922f530f20a7ecffaa 
     o 0000000000000000000000000000000000000000 
     "###);
-    let stdout = get_log_output(&test_env, &secondary_path);
-    // It was detected that the working copy is now stale
+    // It was detected that the working copy is now stale, but updating it would
+    // overwrite the uncommitted change
     // TODO: Since there was an uncommitted change in the working copy, it should
     // have been committed first (causing divergence)
+    let assert = test_env
+        .jj_cmd(
+            &secondary_path,
+            &["log", "-T", r#"commit_id " " working_copies"#],
+        )
+        .assert()
+        .code(1);
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Error: Failed to check out commit 8d8269a323a0 because these files were modified since the last snapshot:
+      file
+    Use --discard-changes to overwrite them.
+    "###);
+    assert_eq!(
+        std::fs::read_to_string(secondary_path.join("file")).unwrap(),
+        "changed in second\n"
+    );
+    let stdout = test_env.jj_cmd_success(
+        &secondary_path,
+        &[
+            "log",
+            "-T",
+            r#"commit_id " " working_copies"#,
+            "--discard-changes",
+        ],
+    );
     assert!(stdout.starts_with("The working copy is stale"));
     insta::assert_snapshot!(stdout.lines().skip(1).join("\n"), @r###"
     o 86bef7fee095bb5626d853c222764fc7c9fb88ac default@
@@ -179,7 +297,12 @@ fn test_workspaces_forget() {
     // Revision "@" cannot be used
     let stderr = test_env.jj_cmd_failure(&main_path, &["log", "-r", "@"]);
     insta::assert_snapshot!(stderr, @r###"
-    Error: Revision "@" doesn't exist
+    Error: Failed to resolve revset:  --> 1:1
+      |
+    1 | @
+      | ^
+      |
+      = Revision "@" doesn't exist
     "###);
 
     // Try to add back the workspace